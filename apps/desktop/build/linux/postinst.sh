@@ -62,18 +62,29 @@ if command -v update-mime-database >/dev/null 2>&1; then
     update-mime-database /usr/share/mime 2>/dev/null || true
 fi
 
-# 8. Check if user needs to be added to input group for global hotkeys (Wayland)
+# 8. Install the polkit policy for granting keyboard access
+# `nvidia-cc-rs install-permissions` asks for authentication under this action
+POLICY_FILE="$INSTALL_DIR/resources/polkit/io.github.aj47.NvidiaCC.policy"
+if [ -f "$POLICY_FILE" ] && [ -d /usr/share/polkit-1/actions ]; then
+    install -m 644 "$POLICY_FILE" /usr/share/polkit-1/actions/
+    echo "✓ Installed polkit policy"
+fi
+
+# 9. Check if the user can read keyboards for global hotkeys (Wayland)
 # This is required for evdev-based keyboard listening on Wayland
 CURRENT_USER="${SUDO_USER:-$USER}"
+UDEV_RULE="/etc/udev/rules.d/70-nvidia-cc.rules"
 if [ -n "$CURRENT_USER" ] && [ "$CURRENT_USER" != "root" ]; then
-    if ! groups "$CURRENT_USER" 2>/dev/null | grep -qw 'input'; then
+    if [ -f "$UDEV_RULE" ]; then
+        echo "✓ Keyboard access rule installed (hotkeys will work)"
+    elif ! groups "$CURRENT_USER" 2>/dev/null | grep -qw 'input'; then
         echo ""
         echo "⚠️  IMPORTANT: For global hotkeys to work (especially on Wayland),"
-        echo "   you need to add your user to the 'input' group:"
+        echo "   the app needs access to keyboards. Grant it from the app, or run:"
         echo ""
-        echo "   sudo usermod -aG input $CURRENT_USER"
+        echo "   $INSTALL_DIR/resources/bin/nvidia-cc-rs install-permissions"
         echo ""
-        echo "   Then log out and log back in for the change to take effect."
+        echo "   No log out is needed."
         echo ""
     else
         echo "✓ User is in 'input' group (hotkeys will work)"
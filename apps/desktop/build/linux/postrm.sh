@@ -32,6 +32,19 @@ if command -v update-mime-database >/dev/null 2>&1; then
     update-mime-database /usr/share/mime 2>/dev/null || true
 fi
 
+# 5. Remove the polkit policy and the keyboard access rule (not on upgrade)
+case "$1" in
+    remove|purge)
+        rm -f /usr/share/polkit-1/actions/io.github.aj47.NvidiaCC.policy
+        if [ -f /etc/udev/rules.d/70-nvidia-cc.rules ]; then
+            rm -f /etc/udev/rules.d/70-nvidia-cc.rules
+            udevadm control --reload 2>/dev/null || true
+            udevadm trigger --action=change --subsystem-match=input 2>/dev/null || true
+            echo "✓ Removed keyboard access rule"
+        fi
+        ;;
+esac
+
 echo "NVIDIA Control Center has been removed."
 
 exit 0
@@ -322,6 +322,11 @@ module.exports = {
         to: "bin/nvidia-cc-rs",
         filter: ["**/*"]
       },
+      // Installed by postinst.sh, for `nvidia-cc-rs install-permissions`
+      {
+        from: "build/linux/io.github.aj47.NvidiaCC.policy",
+        to: "polkit/io.github.aj47.NvidiaCC.policy",
+      },
       {
         from: "../../packages/mcp-whatsapp/dist",
         to: "mcp-whatsapp/dist",
@@ -0,0 +1,16 @@
+fn main() {
+    // The header only changes with the C interface
+    #[cfg(feature = "cdylib")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).unwrap();
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src(format!("{}/src/ffi.rs", crate_dir))
+            .generate()
+            .expect("generate the C header")
+            .write_to_file(format!("{}/include/nvidia_cc.h", crate_dir));
+    }
+}
@@ -0,0 +1,32 @@
+#ifndef NVIDIA_CC_H
+#define NVIDIA_CC_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdint.h>
+
+/**
+ * Receives each event as a NUL-terminated UTF-8 JSON object, valid for the call
+ */
+typedef void (*NccEventCallback)(const char *event_json);
+
+/**
+ * Start capturing, delivering every event to `callback`
+ */
+int32_t ncc_listen(NccEventCallback callback);
+
+/**
+ * Type `text`, a NUL-terminated UTF-8 string
+ *
+ * # Safety
+ *
+ * `text` must be null or point to a NUL-terminated string that stays valid for the call.
+ */
+int32_t ncc_write(const char *text);
+
+/**
+ * Stop delivering events and matching hotkeys and hotstrings
+ */
+void ncc_shutdown(void);
+
+#endif  /* NVIDIA_CC_H */
@@ -0,0 +1,873 @@
+//! Focused window tracking for `ActiveWindowChanged` events.
+//!
+//! Lets the app react to what has focus, e.g. disable dictation hotkeys while
+//! a password manager or terminal is in front. The window focused when
+//! listening starts is reported first, then every change of focus.
+//!
+//! Sources: the compositor's IPC on sway (`swaymsg` window events) and
+//! Hyprland (its event socket), `_NET_ACTIVE_WINDOW` on X11 (`xprop -spy`),
+//! a foreground-window WinEvent hook on Windows, and the Accessibility API on
+//! macOS, which is polled because focus changes have no notification outside
+//! of AppKit. Other Wayland compositors don't expose the focused window, so
+//! there nothing is emitted.
+//!
+//! The same sources list top-level windows and move focus for `windows
+//! list|focus|raise` and `write --window`: [`with_focus`] focuses the window
+//! matched, runs the
+//! injection and gives focus back to the window that had it. Key events go
+//! to the focused window on every platform this supports, so the target is
+//! focused rather than sent events directly. Focusing uses `swaymsg`,
+//! `hyprctl dispatch`, xdotool (or wmctrl) on X11, `SetForegroundWindow` on
+//! Windows (which Windows may refuse while another app owns the foreground)
+//! and System Events on macOS. Raising brings a window to the front without
+//! giving it the keyboard: `xdotool windowraise`, Hyprland's z-order,
+//! `SetWindowPos` and `AXRaise`; sway tiles windows, so there it focuses.
+
+use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+#[derive(Clone, PartialEq)]
+pub struct Window {
+    pub title: String,
+    /// Wayland app_id or X11 WM_CLASS class; executable name on Windows and macOS
+    pub app_id: String,
+    pub pid: Option<u32>,
+}
+
+/// A top-level window that can be focused
+#[derive(Clone)]
+pub struct Target {
+    /// Platform window id: sway container id, Hyprland address, X11 window
+    /// id, `HWND` on Windows, `<pid>/<index>` on macOS
+    pub id: String,
+    pub window: Window,
+}
+
+impl Target {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "title": self.window.title,
+            "app_id": self.window.app_id,
+            "pid": self.window.pid,
+        })
+    }
+}
+
+/// The focused window gets a moment to take keyboard focus before keys are sent to it
+const FOCUS_SETTLE_TIME: Duration = Duration::from_millis(150);
+
+/// Last window reported, so only actual changes are emitted
+static LAST: Mutex<Option<Window>> = Mutex::new(None);
+
+/// The window focused last, as far as the watcher knows
+pub fn current() -> Option<Window> {
+    LAST.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// Start emitting `ActiveWindowChanged` on a background thread
+pub fn watch() {
+    platform::watch();
+}
+
+/// Top-level windows, in the order the platform lists them
+pub fn list() -> Result<Vec<Target>, String> {
+    platform::list()
+}
+
+/// Id of the focused window, None when nothing has focus
+pub fn focused() -> Result<Option<String>, String> {
+    platform::focused()
+}
+
+/// Focus the window with id `id`
+pub fn focus(id: &str) -> Result<(), String> {
+    platform::focus(id)
+}
+
+/// Bring the window with id `id` to the front, leaving keyboard focus where it is
+pub fn raise(id: &str) -> Result<(), String> {
+    platform::raise(id)
+}
+
+/// The window `spec` names: a JSON object with any of `id`, `title`
+/// (contained, ignoring case), `app_id` and `pid`, all of which must match,
+/// or else a string as for [`find`]
+pub fn find_spec(spec: &str) -> Result<Target, String> {
+    let Ok(Value::Object(fields)) = serde_json::from_str::<Value>(spec) else {
+        return find(spec);
+    };
+    for key in fields.keys() {
+        if !["id", "title", "app_id", "pid"].contains(&key.as_str()) {
+            return Err(format!("Unknown window field {:?}; expected id, title, app_id or pid", key));
+        }
+    }
+    let text = |key: &str| fields.get(key).and_then(Value::as_str);
+    let title = text("title").map(str::to_lowercase);
+    let pid = fields.get("pid").and_then(Value::as_u64);
+    list()?
+        .into_iter()
+        .find(|target| {
+            text("id").is_none_or(|id| target.id == id)
+                && title.as_ref().is_none_or(|title| target.window.title.to_lowercase().contains(title))
+                && text("app_id").is_none_or(|app_id| target.window.app_id.eq_ignore_ascii_case(app_id))
+                && pid.is_none_or(|pid| target.window.pid.map(u64::from) == Some(pid))
+        })
+        .ok_or_else(|| format!("No window matches {}", spec))
+}
+
+/// The window `query` names: its id, else the first whose title is `query`,
+/// else the first whose title contains it (ignoring case)
+pub fn find(query: &str) -> Result<Target, String> {
+    let windows = list()?;
+    let lowercase = query.to_lowercase();
+    let matched = windows
+        .iter()
+        .find(|target| target.id == query)
+        .or_else(|| windows.iter().find(|target| target.window.title == query))
+        .or_else(|| windows.iter().find(|target| target.window.title.to_lowercase().contains(&lowercase)));
+    matched.cloned().ok_or_else(|| format!("No window matches {:?}", query))
+}
+
+/// Run `f` with the window `spec` names (see [`find_spec`]) focused, then
+/// give focus back to the window that had it
+pub fn with_focus<R>(spec: &str, f: impl FnOnce() -> R) -> Result<R, String> {
+    let target = find_spec(spec)?;
+    let previous = focused().unwrap_or_else(|e| {
+        eprintln!("Focus won't be given back: {}", e);
+        None
+    });
+    if previous.as_deref() != Some(target.id.as_str()) {
+        focus(&target.id).map_err(|e| format!("Cannot focus {:?}: {}", target.window.title, e))?;
+        thread::sleep(FOCUS_SETTLE_TIME);
+    }
+    let result = f();
+    if let Some(previous) = previous.filter(|previous| *previous != target.id) {
+        if let Err(e) = focus(&previous) {
+            eprintln!("Failed to give focus back to window {}: {}", previous, e);
+        }
+    }
+    Ok(result)
+}
+
+fn update(window: Window) {
+    let mut last = LAST.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if last.as_ref() == Some(&window) {
+        return;
+    }
+    *last = Some(window.clone());
+    drop(last);
+    stream::emit(&Event::now(EventKind::ActiveWindowChanged {
+        title: window.title,
+        app_id: window.app_id,
+        pid: window.pid,
+    }));
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use serde_json::Value;
+    use std::env;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    use super::{update, Target, Window};
+
+    enum Desktop {
+        Hyprland,
+        Sway,
+        X11,
+    }
+
+    fn desktop() -> Result<Desktop, String> {
+        if env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Ok(Desktop::Hyprland)
+        } else if env::var_os("SWAYSOCK").is_some() {
+            Ok(Desktop::Sway)
+        } else if env::var_os("DISPLAY").is_some() && env::var_os("WAYLAND_DISPLAY").is_none() {
+            Ok(Desktop::X11)
+        } else {
+            Err("the compositor doesn't expose its windows".to_string())
+        }
+    }
+
+    pub fn watch() {
+        thread::spawn(|| {
+            let result = desktop().and_then(|desktop| match desktop {
+                Desktop::Hyprland => hyprland(),
+                Desktop::Sway => sway(),
+                Desktop::X11 => x11(),
+            });
+            if let Err(e) = result {
+                eprintln!("Active window tracking unavailable: {}", e);
+            }
+        });
+    }
+
+    pub fn list() -> Result<Vec<Target>, String> {
+        match desktop()? {
+            Desktop::Hyprland => hyprland_windows(),
+            Desktop::Sway => {
+                let mut windows = Vec::new();
+                sway_windows(&sway_tree()?, &mut windows);
+                Ok(windows)
+            }
+            Desktop::X11 => x11_windows(),
+        }
+    }
+
+    pub fn focused() -> Result<Option<String>, String> {
+        match desktop()? {
+            Desktop::Hyprland => {
+                let window: Value = serde_json::from_str(&command_output("hyprctl", &["activewindow", "-j"])?)
+                    .map_err(|e| e.to_string())?;
+                Ok(window["address"].as_str().map(str::to_string))
+            }
+            Desktop::Sway => Ok(find_focused(&sway_tree()?).map(|focused| focused["id"].to_string())),
+            Desktop::X11 => {
+                let output = command_output("xprop", &["-root", "_NET_ACTIVE_WINDOW"])?;
+                let id = window_ids(&output).next().map(str::to_string);
+                Ok(id)
+            }
+        }
+    }
+
+    pub fn focus(id: &str) -> Result<(), String> {
+        match desktop()? {
+            Desktop::Hyprland => {
+                let output = command_output("hyprctl", &["dispatch", "focuswindow", &format!("address:{}", id)])?;
+                match output.trim() {
+                    "ok" => Ok(()),
+                    error => Err(error.to_string()),
+                }
+            }
+            Desktop::Sway => {
+                let command = format!("[con_id={}] focus", id);
+                let replies: Value =
+                    serde_json::from_str(&command_output("swaymsg", &[&command])?).map_err(|e| e.to_string())?;
+                match replies[0]["success"].as_bool() {
+                    Some(true) => Ok(()),
+                    _ => Err(replies[0]["error"].as_str().unwrap_or("swaymsg refused").to_string()),
+                }
+            }
+            Desktop::X11 => command_output("xdotool", &["windowactivate", "--sync", id])
+                .or_else(|_| command_output("wmctrl", &["-i", "-a", id]))
+                .map(|_| ()),
+        }
+    }
+
+    pub fn raise(id: &str) -> Result<(), String> {
+        match desktop()? {
+            Desktop::Hyprland => {
+                let window = format!("top,address:{}", id);
+                let output = command_output("hyprctl", &["dispatch", "alterzorder", &window])?;
+                match output.trim() {
+                    "ok" => Ok(()),
+                    error => Err(error.to_string()),
+                }
+            }
+            Desktop::Sway => focus(id),
+            Desktop::X11 => command_output("xdotool", &["windowraise", id]).map(|_| ()),
+        }
+    }
+
+    fn command_output(program: &str, args: &[&str]) -> Result<String, String> {
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .map_err(|e| format!("{} unavailable: {}", program, e))?;
+        if !output.status.success() {
+            return Err(format!("{} exited with {}", program, output.status));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Lines `program args...` prints until it exits
+    fn output_lines(program: &str, args: &[&str]) -> Result<impl Iterator<Item = String>, String> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("{} unavailable: {}", program, e))?;
+        let stdout = child.stdout.take().ok_or_else(|| format!("{} has no stdout", program))?;
+        Ok(BufReader::new(stdout).lines().map_while(Result::ok))
+    }
+
+    fn json_str(value: &Value, key: &str) -> String {
+        value[key].as_str().unwrap_or_default().to_string()
+    }
+
+    fn json_pid(value: &Value) -> Option<u32> {
+        value["pid"].as_u64().and_then(|pid| u32::try_from(pid).ok()).filter(|&pid| pid > 0)
+    }
+
+    // ---- sway ----
+
+    fn sway_window(container: &Value) -> Window {
+        // XWayland windows have no app_id, only their X11 class
+        let app_id = container["app_id"]
+            .as_str()
+            .or_else(|| container["window_properties"]["class"].as_str())
+            .unwrap_or_default();
+        Window {
+            title: json_str(container, "name"),
+            app_id: app_id.to_string(),
+            pid: json_pid(container),
+        }
+    }
+
+    fn find_focused(node: &Value) -> Option<&Value> {
+        if node["focused"].as_bool() == Some(true) && node["type"] != "workspace" {
+            return Some(node);
+        }
+        ["nodes", "floating_nodes"]
+            .iter()
+            .filter_map(|key| node[key].as_array())
+            .flatten()
+            .find_map(find_focused)
+    }
+
+    fn sway_tree() -> Result<Value, String> {
+        serde_json::from_str(&command_output("swaymsg", &["-t", "get_tree"])?).map_err(|e| e.to_string())
+    }
+
+    /// The windows in `node`: its leaf containers
+    fn sway_windows(node: &Value, windows: &mut Vec<Target>) {
+        let children: Vec<&Value> = ["nodes", "floating_nodes"]
+            .iter()
+            .filter_map(|key| node[key].as_array())
+            .flatten()
+            .collect();
+        if children.is_empty() && (node["type"] == "con" || node["type"] == "floating_con") {
+            windows.push(Target {
+                id: node["id"].to_string(),
+                window: sway_window(node),
+            });
+        }
+        for child in children {
+            sway_windows(child, windows);
+        }
+    }
+
+    fn sway() -> Result<(), String> {
+        let tree = sway_tree()?;
+        if let Some(focused) = find_focused(&tree) {
+            update(sway_window(focused));
+        }
+        for line in output_lines("swaymsg", &["-m", "-t", "subscribe", r#"["window"]"#])? {
+            let Ok(event) = serde_json::from_str::<Value>(&line) else { continue };
+            let container = &event["container"];
+            let focused = match event["change"].as_str() {
+                Some("focus") => true,
+                Some("title") => container["focused"].as_bool() == Some(true),
+                _ => false,
+            };
+            if focused {
+                update(sway_window(container));
+            }
+        }
+        Err("swaymsg subscription ended".to_string())
+    }
+
+    // ---- Hyprland ----
+
+    fn hyprland_active_window() -> Option<Window> {
+        let window: Value = serde_json::from_str(&command_output("hyprctl", &["activewindow", "-j"]).ok()?).ok()?;
+        window.is_object().then(|| Window {
+            title: json_str(&window, "title"),
+            app_id: json_str(&window, "class"),
+            pid: json_pid(&window),
+        })
+    }
+
+    fn hyprland_windows() -> Result<Vec<Target>, String> {
+        let clients: Value =
+            serde_json::from_str(&command_output("hyprctl", &["clients", "-j"])?).map_err(|e| e.to_string())?;
+        Ok(clients
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|client| client["mapped"].as_bool() != Some(false))
+            .map(|client| Target {
+                id: json_str(client, "address"),
+                window: Window {
+                    title: json_str(client, "title"),
+                    app_id: json_str(client, "class"),
+                    pid: json_pid(client),
+                },
+            })
+            .collect())
+    }
+
+    fn hyprland() -> Result<(), String> {
+        let signature = env::var("HYPRLAND_INSTANCE_SIGNATURE").map_err(|e| e.to_string())?;
+        // Hyprland moved its sockets from /tmp/hypr to the runtime directory in 0.40
+        let socket = env::var_os("XDG_RUNTIME_DIR")
+            .map(|dir| PathBuf::from(dir).join("hypr"))
+            .into_iter()
+            .chain([PathBuf::from("/tmp/hypr")])
+            .map(|dir| dir.join(&signature).join(".socket2.sock"))
+            .find(|path| path.exists())
+            .ok_or("Hyprland event socket not found")?;
+        let stream = UnixStream::connect(&socket).map_err(|e| format!("{}: {}", socket.display(), e))?;
+
+        if let Some(window) = hyprland_active_window() {
+            update(window);
+        }
+        // `activewindow>>class,title` carries no pid, so ask for the full window on every change
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            if line.starts_with("activewindow>>") {
+                if let Some(window) = hyprland_active_window() {
+                    update(window);
+                }
+            }
+        }
+        Err("Hyprland event socket closed".to_string())
+    }
+
+    // ---- X11 ----
+
+    /// `"text"` with xprop's backslash escapes removed
+    fn unquote(value: &str) -> String {
+        let inner = value.trim().trim_start_matches('"').trim_end_matches('"');
+        inner.replace("\\\"", "\"").replace("\\\\", "\\")
+    }
+
+    fn x11_window(id: &str) -> Option<Window> {
+        let output = command_output("xprop", &["-id", id, "_NET_WM_NAME", "WM_NAME", "WM_CLASS", "_NET_WM_PID"]).ok()?;
+        let (mut net_wm_name, mut wm_name, mut app_id, mut pid) = (None, None, String::new(), None);
+        for line in output.lines() {
+            let Some((property, value)) = line.split_once(" = ") else { continue };
+            match property.split('(').next() {
+                Some("_NET_WM_NAME") => net_wm_name = Some(unquote(value)),
+                Some("WM_NAME") => wm_name = Some(unquote(value)),
+                // "instance", "Class"
+                Some("WM_CLASS") => app_id = value.rsplit(", ").next().map(unquote).unwrap_or_default(),
+                Some("_NET_WM_PID") => pid = value.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        Some(Window {
+            title: net_wm_name.or(wm_name).unwrap_or_default(),
+            app_id,
+            pid,
+        })
+    }
+
+    /// The nonzero ids in a root window property: `_NET_CLIENT_LIST(WINDOW): window id # 0x3a00007, 0x3c00004`
+    fn window_ids(line: &str) -> impl Iterator<Item = &str> {
+        let ids = line.split_once("# ").map_or("", |(_, ids)| ids);
+        ids.split(',')
+            .map(str::trim)
+            .filter(|id| u64::from_str_radix(id.trim_start_matches("0x"), 16).unwrap_or(0) != 0)
+    }
+
+    fn x11_windows() -> Result<Vec<Target>, String> {
+        let output = command_output("xprop", &["-root", "_NET_CLIENT_LIST"])?;
+        Ok(window_ids(&output)
+            .filter_map(|id| {
+                x11_window(id).map(|window| Target {
+                    id: id.to_string(),
+                    window,
+                })
+            })
+            .collect())
+    }
+
+    fn x11() -> Result<(), String> {
+        // Prints the current value, then a line per change: `_NET_ACTIVE_WINDOW(WINDOW): window id # 0x3a00007`
+        for line in output_lines("xprop", &["-root", "-spy", "_NET_ACTIVE_WINDOW"])? {
+            let Some(id) = window_ids(&line).next() else { continue };
+            if let Some(window) = x11_window(id) {
+                update(window);
+            }
+        }
+        Err("xprop exited".to_string())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::path::Path;
+    use std::ptr;
+    use std::thread;
+
+    use windows_sys::core::BOOL;
+    use windows_sys::Win32::Foundation::{CloseHandle, HWND, LPARAM};
+    use windows_sys::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows_sys::Win32::UI::Accessibility::{SetWinEventHook, HWINEVENTHOOK};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        DispatchMessageW, EnumWindows, GetForegroundWindow, GetMessageW, GetWindow, GetWindowTextLengthW,
+        GetWindowTextW, GetWindowThreadProcessId, IsIconic, IsWindow, IsWindowVisible, SetForegroundWindow,
+        SetWindowPos, ShowWindow, EVENT_SYSTEM_FOREGROUND, GW_OWNER, HWND_TOP, MSG, SWP_NOACTIVATE, SWP_NOMOVE,
+        SWP_NOSIZE, SW_RESTORE, WINEVENT_OUTOFCONTEXT,
+    };
+
+    use super::{update, Target, Window};
+
+    fn executable_name(pid: u32) -> String {
+        let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+        if process.is_null() {
+            return String::new();
+        }
+        let mut buffer = [0u16; 1024];
+        let mut len = buffer.len() as u32;
+        let queried = unsafe { QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, buffer.as_mut_ptr(), &mut len) };
+        unsafe { CloseHandle(process) };
+        if queried == 0 {
+            return String::new();
+        }
+        let path = String::from_utf16_lossy(&buffer[..len as usize]);
+        Path::new(&path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or(path)
+    }
+
+    fn describe(window: HWND) -> Option<Window> {
+        if window.is_null() {
+            return None;
+        }
+        let mut title = [0u16; 512];
+        let len = unsafe { GetWindowTextW(window, title.as_mut_ptr(), title.len() as i32) };
+        let mut pid = 0u32;
+        unsafe { GetWindowThreadProcessId(window, &mut pid) };
+        Some(Window {
+            title: String::from_utf16_lossy(&title[..len.max(0) as usize]),
+            app_id: executable_name(pid),
+            pid: (pid != 0).then_some(pid),
+        })
+    }
+
+    fn id(window: HWND) -> String {
+        format!("{:#x}", window as usize)
+    }
+
+    fn parse_id(id: &str) -> Result<HWND, String> {
+        let window = usize::from_str_radix(id.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("Window ids are hexadecimal HWNDs, not {:?}", id))? as HWND;
+        if unsafe { IsWindow(window) } == 0 {
+            return Err(format!("No window has id {}", id));
+        }
+        Ok(window)
+    }
+
+    /// Adds visible, titled, unowned windows (the ones on the taskbar) to the `Vec<HWND>` behind `windows`
+    unsafe extern "system" fn collect(window: HWND, windows: LPARAM) -> BOOL {
+        let windows = &mut *(windows as *mut Vec<HWND>);
+        if IsWindowVisible(window) != 0 && GetWindow(window, GW_OWNER).is_null() && GetWindowTextLengthW(window) > 0 {
+            windows.push(window);
+        }
+        1
+    }
+
+    pub fn list() -> Result<Vec<Target>, String> {
+        let mut windows: Vec<HWND> = Vec::new();
+        unsafe { EnumWindows(Some(collect), &mut windows as *mut Vec<HWND> as LPARAM) };
+        Ok(windows
+            .into_iter()
+            .filter_map(|window| describe(window).map(|described| Target { id: id(window), window: described }))
+            .collect())
+    }
+
+    pub fn focused() -> Result<Option<String>, String> {
+        let window = unsafe { GetForegroundWindow() };
+        Ok((!window.is_null()).then(|| id(window)))
+    }
+
+    pub fn focus(id: &str) -> Result<(), String> {
+        let window = parse_id(id)?;
+        unsafe {
+            if IsIconic(window) != 0 {
+                ShowWindow(window, SW_RESTORE);
+            }
+            if SetForegroundWindow(window) == 0 {
+                return Err("Windows refused to bring it to the foreground".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn raise(id: &str) -> Result<(), String> {
+        let window = parse_id(id)?;
+        let flags = SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE;
+        if unsafe { SetWindowPos(window, HWND_TOP, 0, 0, 0, 0, flags) } == 0 {
+            return Err("SetWindowPos failed".to_string());
+        }
+        Ok(())
+    }
+
+    unsafe extern "system" fn foreground_changed(
+        _hook: HWINEVENTHOOK,
+        _event: u32,
+        window: HWND,
+        _object: i32,
+        _child: i32,
+        _thread: u32,
+        _time: u32,
+    ) {
+        if let Some(window) = describe(window) {
+            update(window);
+        }
+    }
+
+    pub fn watch() {
+        thread::spawn(|| {
+            if let Some(window) = describe(unsafe { GetForegroundWindow() }) {
+                update(window);
+            }
+            // Out-of-context hooks are delivered through this thread's message loop
+            let hook = unsafe {
+                SetWinEventHook(
+                    EVENT_SYSTEM_FOREGROUND,
+                    EVENT_SYSTEM_FOREGROUND,
+                    ptr::null_mut(),
+                    Some(foreground_changed),
+                    0,
+                    0,
+                    WINEVENT_OUTOFCONTEXT,
+                )
+            };
+            if hook.is_null() {
+                eprintln!("Active window tracking unavailable: SetWinEventHook failed");
+                return;
+            }
+            let mut message: MSG = unsafe { std::mem::zeroed() };
+            while unsafe { GetMessageW(&mut message, ptr::null_mut(), 0, 0) } > 0 {
+                unsafe { DispatchMessageW(&message) };
+            }
+        });
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use serde_json::Value;
+    use std::ffi::{c_char, c_void, CStr, CString};
+    use std::path::Path;
+    use std::process::Command;
+    use std::ptr;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{update, Target, Window};
+
+    /// `[{pid, index, title}]` for every window of every app with a user interface
+    const LIST_SCRIPT: &str = r#"
+        function run() {
+            const windows = [];
+            for (const process of Application('System Events').processes.whose({ backgroundOnly: false })()) {
+                const pid = process.unixId();
+                process.windows().forEach((window, i) => {
+                    windows.push({ pid, index: i + 1, title: window.name() || '' });
+                });
+            }
+            return JSON.stringify(windows);
+        }"#;
+
+    /// `<pid>/1` of the frontmost app: its frontmost window
+    const FOCUSED_SCRIPT: &str = r#"
+        function run() {
+            const front = Application('System Events').processes.whose({ frontmost: true })();
+            return front.length ? front[0].unixId() + '/1' : '';
+        }"#;
+
+    /// Raise window `index` of app `pid`, then, with `focus`, bring the app to the front
+    const FOCUS_SCRIPT: &str = r#"
+        function run([pid, index, focus]) {
+            const process = Application('System Events').processes.whose({ unixId: Number(pid) })()[0];
+            process.windows[Number(index) - 1].actions['AXRaise'].perform();
+            if (focus === 'focus') process.frontmost = true;
+        }"#;
+
+    fn javascript(script: &str, args: &[&str]) -> Result<String, String> {
+        let output = Command::new("osascript")
+            .args(["-l", "JavaScript", "-e", script])
+            .args(args)
+            .output()
+            .map_err(|e| format!("osascript unavailable: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    pub fn list() -> Result<Vec<Target>, String> {
+        let windows: Value = serde_json::from_str(&javascript(LIST_SCRIPT, &[])?).map_err(|e| e.to_string())?;
+        Ok(windows
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|window| {
+                let pid = window["pid"].as_i64()? as i32;
+                Some(Target {
+                    id: format!("{}/{}", pid, window["index"].as_u64()?),
+                    window: Window {
+                        title: window["title"].as_str().unwrap_or_default().to_string(),
+                        app_id: executable_name(pid),
+                        pid: Some(pid as u32),
+                    },
+                })
+            })
+            .collect())
+    }
+
+    pub fn focused() -> Result<Option<String>, String> {
+        let id = javascript(FOCUSED_SCRIPT, &[])?;
+        Ok((!id.is_empty()).then_some(id))
+    }
+
+    fn raise_window(id: &str, focus: bool) -> Result<(), String> {
+        let (pid, index) = id
+            .split_once('/')
+            .filter(|(pid, index)| pid.parse::<u32>().is_ok() && index.parse::<u32>().is_ok())
+            .ok_or_else(|| format!("Window ids are <pid>/<index>, not {:?}", id))?;
+        javascript(FOCUS_SCRIPT, &[pid, index, if focus { "focus" } else { "raise" }]).map(|_| ())
+    }
+
+    pub fn focus(id: &str) -> Result<(), String> {
+        raise_window(id, true)
+    }
+
+    pub fn raise(id: &str) -> Result<(), String> {
+        raise_window(id, false)
+    }
+
+    type CFTypeRef = *const c_void;
+    type CFStringRef = *const c_void;
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const AX_ERROR_SUCCESS: i32 = 0;
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXUIElementCreateSystemWide() -> CFTypeRef;
+        fn AXUIElementCopyAttributeValue(element: CFTypeRef, attribute: CFStringRef, value: *mut CFTypeRef) -> i32;
+        fn AXUIElementGetPid(element: CFTypeRef, pid: *mut i32) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: CFTypeRef);
+        fn CFGetTypeID(cf: CFTypeRef) -> usize;
+        fn CFStringGetTypeID() -> usize;
+        fn CFStringCreateWithCString(allocator: CFTypeRef, string: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFStringGetLength(string: CFStringRef) -> isize;
+        fn CFStringGetMaximumSizeForEncoding(length: isize, encoding: u32) -> isize;
+        fn CFStringGetCString(string: CFStringRef, buffer: *mut c_char, size: isize, encoding: u32) -> u8;
+    }
+
+    /// Owned attribute value, or null
+    unsafe fn copy_attribute(element: CFTypeRef, name: &str) -> CFTypeRef {
+        let name = CString::new(name).expect("attribute names have no NUL");
+        let attribute = CFStringCreateWithCString(ptr::null(), name.as_ptr(), CF_STRING_ENCODING_UTF8);
+        let mut value: CFTypeRef = ptr::null();
+        let error = AXUIElementCopyAttributeValue(element, attribute, &mut value);
+        CFRelease(attribute);
+        if error == AX_ERROR_SUCCESS {
+            value
+        } else {
+            ptr::null()
+        }
+    }
+
+    unsafe fn string(value: CFTypeRef) -> Option<String> {
+        if value.is_null() || CFGetTypeID(value) != CFStringGetTypeID() {
+            return None;
+        }
+        let size = CFStringGetMaximumSizeForEncoding(CFStringGetLength(value), CF_STRING_ENCODING_UTF8) + 1;
+        let mut buffer = vec![0 as c_char; size as usize];
+        (CFStringGetCString(value, buffer.as_mut_ptr(), size, CF_STRING_ENCODING_UTF8) != 0)
+            .then(|| CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+    }
+
+    fn executable_name(pid: i32) -> String {
+        let mut buffer = [0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+        let len = unsafe { libc::proc_pidpath(pid, buffer.as_mut_ptr() as *mut c_void, buffer.len() as u32) };
+        if len <= 0 {
+            return String::new();
+        }
+        let path = String::from_utf8_lossy(&buffer[..len as usize]).into_owned();
+        Path::new(&path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or(path)
+    }
+
+    /// Needs the Accessibility permission the helper already uses for injection
+    fn current() -> Option<Window> {
+        unsafe {
+            let system = AXUIElementCreateSystemWide();
+            let app = copy_attribute(system, "AXFocusedApplication");
+            CFRelease(system);
+            if app.is_null() {
+                return None;
+            }
+            let mut pid = 0;
+            let has_pid = AXUIElementGetPid(app, &mut pid) == AX_ERROR_SUCCESS;
+            let window = copy_attribute(app, "AXFocusedWindow");
+            CFRelease(app);
+
+            let mut title = None;
+            if !window.is_null() {
+                let value = copy_attribute(window, "AXTitle");
+                title = string(value);
+                if !value.is_null() {
+                    CFRelease(value);
+                }
+                CFRelease(window);
+            }
+            Some(Window {
+                title: title.unwrap_or_default(),
+                app_id: if has_pid { executable_name(pid) } else { String::new() },
+                pid: has_pid.then_some(pid as u32),
+            })
+        }
+    }
+
+    pub fn watch() {
+        thread::spawn(|| loop {
+            if let Some(window) = current() {
+                update(window);
+            }
+            thread::sleep(POLL_INTERVAL);
+        });
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    use super::Target;
+
+    const UNSUPPORTED: &str = "Window management isn't supported on this platform";
+
+    pub fn watch() {}
+
+    pub fn list() -> Result<Vec<Target>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn focused() -> Result<Option<String>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn focus(_id: &str) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn raise(_id: &str) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
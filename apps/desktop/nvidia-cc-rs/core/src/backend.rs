@@ -0,0 +1,1411 @@
+//! Injection backends and the fallback chain between them.
+//!
+//! enigo's default backend fails in some environments (Wayland sessions, X11
+//! forwarding), so injection walks a chain and moves on when a backend can't
+//! be created or an operation fails:
+//!
+//!   enigo -> uinput (Linux) -> wayland (Linux) -> sendinput (Windows) -> clipboard paste -> xdotool (Linux)
+//!
+//! The sendinput and clipboard backends can only insert text, so key presses
+//! skip them. sendinput types characters as `KEYEVENTF_UNICODE` key events,
+//! surrogate pairs kept together, which gets emoji into apps where enigo's
+//! come out mangled. The clipboard backend puts back what the clipboard held
+//! once the paste is read (see [`crate::clipboard`]). The first time a later backend succeeds after an
+//! earlier one failed, a `BackendSwitched` event names both and says why.
+//! Mouse operations use the same chain; uinput can only move the pointer
+//! relatively, so absolute moves fall through to xdotool there.
+//!
+//! The `wayland` backend drives the virtual keyboard and pointer of wlroots
+//! compositors such as Sway and Hyprland (see [`crate::wayland`]), so `write`
+//! works there without uinput access. It types through a keymap of its own
+//! rather than the user's layout.
+//!
+//! The `portal` backend (Linux) injects through the RemoteDesktop portal (see
+//! [`crate::portal`]). It is left out of the chain unless the config names it,
+//! except inside a Flatpak or Snap sandbox without devices, where it goes
+//! first.
+//!
+//! The `ax` backend inserts text through the accessibility APIs instead (see
+//! [`crate::context`]). It isn't part of the chain unless the config names
+//! it; `write --backend ax` uses it alone for text.
+//!
+//! On Linux, text is typed by key code where the keyboard layout is known
+//! (see [`crate::layout::plan_text`]), so AltGr and dead-key characters come
+//! out right on layouts like de, fr and es.
+
+use enigo::{Button, Direction, Key};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use crate::clipboard::Selection;
+use crate::event::{Event, EventKind};
+#[cfg(target_os = "linux")]
+use crate::layout;
+use crate::stats;
+use crate::stream;
+
+pub trait Backend {
+    /// Type `text`. Backends check up front that they can type all of it, so a
+    /// failure normally means nothing was typed and the next backend can retry.
+    fn text(&mut self, text: &str) -> Result<(), String>;
+
+    fn key(&mut self, key: Key, direction: Direction) -> Result<(), String>;
+
+    /// Move the pointer to desktop coordinates `(x, y)`, or by `(x, y)` pixels when `relative`
+    fn mouse_move(&mut self, x: i32, y: i32, relative: bool) -> Result<(), String>;
+
+    fn mouse_button(&mut self, button: Button, direction: Direction) -> Result<(), String>;
+
+    /// Scroll by wheel notches; positive `dx` scrolls right, positive `dy` down
+    fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), String>;
+}
+
+type Constructor = fn(&Options) -> Result<Box<dyn Backend>, String>;
+
+struct Link {
+    name: &'static str,
+    create: Constructor,
+    /// Can't send key presses or mouse input, only insert text
+    text_only: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct Options {
+    /// Let the backend release keys still held when it is dropped (enigo's default)
+    pub release_keys_when_dropped: bool,
+    /// Insert text by clipboard paste before trying to type it (input methods mangle typed keys)
+    pub paste_text: bool,
+    /// What the clipboard backend pastes through; the primary selection pastes with a middle click
+    pub paste_selection: Selection,
+    /// Insert text through this backend only; key presses still go through the chain
+    pub only: Option<&'static str>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            release_keys_when_dropped: true,
+            paste_text: false,
+            paste_selection: Selection::Clipboard,
+            only: None,
+        }
+    }
+}
+
+/// Every backend name, including those of other platforms, in the default order
+pub const NAMES: [&str; 8] = ["enigo", "uinput", "portal", "wayland", "sendinput", "clipboard", "xdotool", "ax"];
+
+/// Left out of the chain unless the config names them
+const OPT_IN: [&str; 2] = ["portal", "ax"];
+
+/// Backends to try, in order, from the config file; empty for the whole chain
+static ORDER: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Backends to try, in order, from `--inject-backend`; wins over the config file's
+static OVERRIDE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Restrict and reorder the chain to `names`; names of other platforms' backends are skipped
+pub fn set_order(names: Vec<String>) {
+    *ORDER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = names;
+}
+
+/// Like [`set_order`], for the command line, so reloading the config file keeps it
+pub fn set_override(names: Vec<String>) {
+    *OVERRIDE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = names;
+}
+
+/// The names [`set_override`] was given
+pub(crate) fn overridden() -> Vec<String> {
+    OVERRIDE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// Each backend name with, if it is built for this platform, whether it can run here and why not
+pub(crate) fn availability() -> Vec<(&'static str, Option<Result<(), String>>)> {
+    let links = links();
+    NAMES
+        .iter()
+        .map(|&name| (name, links.iter().find(|link| link.name == name).map(Link::usable)))
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) use uinput_backend::virtual_device;
+
+/// Every backend of this platform, in the default order
+fn links() -> Vec<Link> {
+    let link = |name, create, text_only| Link { name, create, text_only };
+    vec![
+        link("enigo", enigo_backend::create as Constructor, false),
+        #[cfg(target_os = "linux")]
+        link("uinput", uinput_backend::create, false),
+        #[cfg(target_os = "linux")]
+        link("portal", portal_backend::create, false),
+        #[cfg(target_os = "linux")]
+        link("wayland", wayland_backend::create, false),
+        #[cfg(windows)]
+        link("sendinput", sendinput_backend::create, true),
+        link("clipboard", clipboard_backend::create, true),
+        #[cfg(target_os = "linux")]
+        link("xdotool", xdotool_backend::create, false),
+        link("ax", ax_backend::create, true),
+    ]
+}
+
+impl Link {
+    fn usable(&self) -> Result<(), String> {
+        // Creating it checks nothing: it looks the field up on each insert
+        if self.name == "ax" {
+            return crate::context::available();
+        }
+        // Creating it starts a session, which the desktop asks the user about
+        #[cfg(target_os = "linux")]
+        if self.name == "portal" {
+            return crate::portal::available();
+        }
+        (self.create)(&Options::default()).map(|_| ())
+    }
+}
+
+/// Backends in the order they are tried
+fn chain() -> Vec<Link> {
+    let mut links = links();
+    let overridden = overridden();
+    let order = if overridden.is_empty() {
+        ORDER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    } else {
+        overridden
+    };
+    if order.is_empty() {
+        #[cfg(target_os = "linux")]
+        if crate::portal::confined().is_some() {
+            links.retain(|link| link.name != "ax");
+            links.sort_by_key(|link| link.name != "portal");
+            return links;
+        }
+        links.retain(|link| !OPT_IN.contains(&link.name));
+    } else {
+        links.retain(|link| order.iter().any(|name| name == link.name));
+        links.sort_by_key(|link| order.iter().position(|name| name == link.name));
+    }
+    links
+}
+
+enum Slot {
+    Untried,
+    Ready(Box<dyn Backend>),
+    Failed,
+}
+
+/// Runs every injection operation on the first backend in the chain that works
+pub struct Injector {
+    options: Options,
+    slots: Vec<(Link, Slot)>,
+}
+
+impl Injector {
+    pub fn new(options: Options) -> Self {
+        let mut links = chain();
+        if let Some(only) = options.only {
+            links.retain(|link| link.name != only && !link.text_only);
+            links.splice(0..0, self::links().into_iter().filter(|link| link.name == only));
+        } else if options.paste_text {
+            // Stable, so the typing backends stay in their usual order behind the clipboard
+            links.sort_by_key(|link| link.name != "clipboard");
+        }
+        Injector {
+            options,
+            slots: links.into_iter().map(|link| (link, Slot::Untried)).collect(),
+        }
+    }
+
+    pub fn text(&mut self, text: &str) -> Result<(), String> {
+        self.run(false, |backend| backend.text(text))
+    }
+
+    pub fn key(&mut self, key: Key, direction: Direction) -> Result<(), String> {
+        self.run(true, |backend| backend.key(key, direction))
+    }
+
+    pub fn mouse_move(&mut self, x: i32, y: i32, relative: bool) -> Result<(), String> {
+        self.run(true, |backend| backend.mouse_move(x, y, relative))
+    }
+
+    pub fn mouse_button(&mut self, button: Button, direction: Direction) -> Result<(), String> {
+        self.run(true, |backend| backend.mouse_button(button, direction))
+    }
+
+    pub fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+        self.run(true, |backend| backend.scroll(dx, dy))
+    }
+
+    fn run(&mut self, needs_keys: bool, mut op: impl FnMut(&mut dyn Backend) -> Result<(), String>) -> Result<(), String> {
+        let options = self.options;
+        let mut failures: Vec<(&'static str, String)> = Vec::new();
+
+        for (link, slot) in self.slots.iter_mut() {
+            let name = link.name;
+            if needs_keys && link.text_only {
+                continue;
+            }
+            if !needs_keys && options.only.is_some_and(|only| only != name) {
+                continue;
+            }
+            if let Slot::Untried = slot {
+                *slot = match (link.create)(&options) {
+                    Ok(backend) => Slot::Ready(backend),
+                    Err(e) => {
+                        failures.push((name, e));
+                        Slot::Failed
+                    }
+                };
+            }
+            let Slot::Ready(backend) = slot else { continue };
+
+            match op(backend.as_mut()) {
+                Ok(()) => {
+                    stats::note_backend(name);
+                    if let Some((from, _)) = failures.first() {
+                        let reason = describe(&failures);
+                        eprintln!("Injection backend {} failed, switched to {}: {}", from, name, reason);
+                        stream::emit(&Event::now(EventKind::BackendSwitched {
+                            from: from.to_string(),
+                            to: name.to_string(),
+                            reason,
+                        }));
+                    }
+                    return Ok(());
+                }
+                Err(e) => {
+                    failures.push((name, e));
+                    *slot = Slot::Failed;
+                }
+            }
+        }
+
+        if failures.is_empty() {
+            return Err("No injection backend is available".to_string());
+        }
+        Err(format!("No injection backend worked ({})", describe(&failures)))
+    }
+}
+
+fn describe(failures: &[(&'static str, String)]) -> String {
+    failures
+        .iter()
+        .map(|(name, error)| format!("{}: {}", name, error))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Tap each stroke through `send(code, pressed)`, holding its modifiers around the key
+#[cfg(target_os = "linux")]
+fn send_strokes(strokes: &[layout::Stroke], mut send: impl FnMut(u16, bool) -> Result<(), String>) -> Result<(), String> {
+    for stroke in strokes {
+        for &modifier in &stroke.modifiers {
+            send(modifier, true)?;
+        }
+        send(stroke.code, true)?;
+        send(stroke.code, false)?;
+        for &modifier in stroke.modifiers.iter().rev() {
+            send(modifier, false)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run `program args...`, feeding `input` on stdin
+pub(crate) fn run_with_input(program: &str, args: &[&str], input: impl AsRef<[u8]>) -> Result<(), String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("{} unavailable: {}", program, e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_ref())
+            .map_err(|e| format!("{} stdin: {}", program, e))?;
+    }
+    let status = child.wait().map_err(|e| format!("{}: {}", program, e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", program, status))
+    }
+}
+
+/// Absolute move across the whole virtual desktop
+///
+/// enigo's absolute moves are scaled to the primary monitor only, so they
+/// can't reach monitors left of or above it.
+#[cfg(windows)]
+fn move_on_virtual_desktop(x: i32, y: i32) -> Result<(), String> {
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+        SendInput, INPUT, INPUT_0, INPUT_MOUSE, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_MOVE, MOUSEEVENTF_VIRTUALDESK,
+        MOUSEINPUT,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetSystemMetrics, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN,
+    };
+
+    let (left, top, width, height) = unsafe {
+        (
+            GetSystemMetrics(SM_XVIRTUALSCREEN),
+            GetSystemMetrics(SM_YVIRTUALSCREEN),
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+        )
+    };
+    // Absolute input is normalized to 0..=65535 across the virtual desktop
+    let normalize = |value: i32, origin: i32, size: i32| {
+        let span = i64::from(size.max(2) - 1);
+        (i64::from(value - origin) * 65535 / span).clamp(0, 65535) as i32
+    };
+    let input = INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                dx: normalize(x, left, width),
+                dy: normalize(y, top, height),
+                mouseData: 0,
+                dwFlags: MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    };
+    let sent = unsafe { SendInput(1, &input, std::mem::size_of::<INPUT>() as i32) };
+    if sent == 1 {
+        Ok(())
+    } else {
+        Err("SendInput was blocked".to_string())
+    }
+}
+
+/// X key codes are evdev codes plus 8
+#[cfg(target_os = "linux")]
+const X_KEYCODE_OFFSET: u16 = 8;
+
+mod enigo_backend {
+    use enigo::{Axis, Button, Coordinate, Direction, Enigo, Key, Keyboard, Mouse, Settings};
+
+    use super::{Backend, Options};
+
+    struct EnigoBackend(Enigo);
+
+    pub fn create(options: &Options) -> Result<Box<dyn Backend>, String> {
+        let settings = Settings {
+            release_keys_when_dropped: options.release_keys_when_dropped,
+            ..Settings::default()
+        };
+        let enigo = Enigo::new(&settings).map_err(|e| e.to_string())?;
+        Ok(Box::new(EnigoBackend(enigo)))
+    }
+
+    impl Backend for EnigoBackend {
+        fn text(&mut self, text: &str) -> Result<(), String> {
+            // enigo's keysym remapping mistypes AltGr and dead-key characters on some layouts;
+            // X key codes are only safe when we know the layout the X server itself uses
+            #[cfg(target_os = "linux")]
+            if crate::layout::matches_x_server() {
+                if let Some(Ok(strokes)) = crate::layout::plan_text(text) {
+                    return super::send_strokes(&strokes, |code, pressed| {
+                        let direction = if pressed { Direction::Press } else { Direction::Release };
+                        self.0
+                            .raw(code + super::X_KEYCODE_OFFSET, direction)
+                            .map_err(|e| e.to_string())
+                    });
+                }
+            }
+            self.0.text(text).map_err(|e| e.to_string())
+        }
+
+        fn key(&mut self, key: Key, direction: Direction) -> Result<(), String> {
+            self.0.key(key, direction).map_err(|e| e.to_string())
+        }
+
+        fn mouse_move(&mut self, x: i32, y: i32, relative: bool) -> Result<(), String> {
+            #[cfg(windows)]
+            if !relative {
+                return super::move_on_virtual_desktop(x, y);
+            }
+            let coordinate = if relative { Coordinate::Rel } else { Coordinate::Abs };
+            self.0.move_mouse(x, y, coordinate).map_err(|e| e.to_string())
+        }
+
+        fn mouse_button(&mut self, button: Button, direction: Direction) -> Result<(), String> {
+            self.0.button(button, direction).map_err(|e| e.to_string())
+        }
+
+        fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+            if dx != 0 {
+                self.0.scroll(dx, Axis::Horizontal).map_err(|e| e.to_string())?;
+            }
+            if dy != 0 {
+                self.0.scroll(dy, Axis::Vertical).map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod uinput_backend {
+    use enigo::{Button, Direction, Key};
+    use evdev::uinput::VirtualDevice;
+    use evdev::{AttributeSet, EventType, InputEvent, KeyCode as EvKey, RelativeAxisCode};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{Backend, Options};
+    use crate::layout;
+    use crate::synthetic;
+
+    /// Time for the compositor or X server to pick up the new device before it is used
+    const DEVICE_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+    struct UinputBackend {
+        device: VirtualDevice,
+    }
+
+    pub fn create(_options: &Options) -> Result<Box<dyn Backend>, String> {
+        let device = virtual_device()?;
+        thread::sleep(DEVICE_SETTLE_TIME);
+        Ok(Box::new(UinputBackend { device }))
+    }
+
+    /// A keyboard with every key code below 249, the usual mouse buttons, and relative motion and wheels
+    pub fn virtual_device() -> Result<VirtualDevice, String> {
+        let mut keys = AttributeSet::<EvKey>::new();
+        for code in 1..=248 {
+            keys.insert(EvKey::new(code));
+        }
+        for button in [EvKey::BTN_LEFT, EvKey::BTN_RIGHT, EvKey::BTN_MIDDLE, EvKey::BTN_SIDE, EvKey::BTN_EXTRA] {
+            keys.insert(button);
+        }
+        let mut axes = AttributeSet::<RelativeAxisCode>::new();
+        for axis in [
+            RelativeAxisCode::REL_X,
+            RelativeAxisCode::REL_Y,
+            RelativeAxisCode::REL_WHEEL,
+            RelativeAxisCode::REL_HWHEEL,
+        ] {
+            axes.insert(axis);
+        }
+        VirtualDevice::builder()
+            .and_then(|builder| builder.name(synthetic::VIRTUAL_DEVICE_NAME).with_keys(&keys))
+            .and_then(|builder| builder.with_relative_axes(&axes))
+            .and_then(|builder| builder.build())
+            .map_err(|e| format!("cannot create virtual keyboard: {}", e))
+    }
+
+    impl UinputBackend {
+        fn send(&mut self, key: EvKey, pressed: bool) -> Result<(), String> {
+            let event = InputEvent::new(EventType::KEY.0, key.code(), i32::from(pressed));
+            self.device.emit(&[event]).map_err(|e| e.to_string())
+        }
+
+        fn send_relative(&mut self, motion: &[(RelativeAxisCode, i32)]) -> Result<(), String> {
+            let events: Vec<InputEvent> = motion
+                .iter()
+                .filter(|(_, value)| *value != 0)
+                .map(|&(axis, value)| InputEvent::new(EventType::RELATIVE.0, axis.0, value))
+                .collect();
+            // emit() appends the SYN_REPORT, so a diagonal move arrives as one motion
+            self.device.emit(&events).map_err(|e| e.to_string())
+        }
+
+        fn tap(&mut self, key: EvKey, shift: bool) -> Result<(), String> {
+            if shift {
+                self.send(EvKey::KEY_LEFTSHIFT, true)?;
+            }
+            self.send(key, true)?;
+            self.send(key, false)?;
+            if shift {
+                self.send(EvKey::KEY_LEFTSHIFT, false)?;
+            }
+            Ok(())
+        }
+    }
+
+    impl Backend for UinputBackend {
+        fn text(&mut self, text: &str) -> Result<(), String> {
+            if let Some(plan) = layout::plan_text(text) {
+                let strokes = plan?;
+                return super::send_strokes(&strokes, |code, pressed| self.send(EvKey::new(code), pressed));
+            }
+
+            // Without a layout, raw key codes only cover what a US layout can type
+            let keys = text
+                .chars()
+                .map(|c| char_key(c).ok_or_else(|| format!("cannot type {:?} with raw key codes", c)))
+                .collect::<Result<Vec<_>, _>>()?;
+            for (key, shift) in keys {
+                self.tap(key, shift)?;
+            }
+            Ok(())
+        }
+
+        fn key(&mut self, key: Key, direction: Direction) -> Result<(), String> {
+            let (code, shift) = match key {
+                Key::Unicode(c) => char_key(c),
+                key => named_key(key).map(|code| (code, false)),
+            }
+            .ok_or_else(|| format!("no key code for {:?}", key))?;
+
+            match direction {
+                Direction::Press => {
+                    if shift {
+                        self.send(EvKey::KEY_LEFTSHIFT, true)?;
+                    }
+                    self.send(code, true)
+                }
+                Direction::Release => {
+                    self.send(code, false)?;
+                    if shift {
+                        self.send(EvKey::KEY_LEFTSHIFT, false)?;
+                    }
+                    Ok(())
+                }
+                Direction::Click => self.tap(code, shift),
+            }
+        }
+
+        fn mouse_move(&mut self, x: i32, y: i32, relative: bool) -> Result<(), String> {
+            if !relative {
+                return Err("a virtual pointer only moves relatively".to_string());
+            }
+            self.send_relative(&[(RelativeAxisCode::REL_X, x), (RelativeAxisCode::REL_Y, y)])
+        }
+
+        fn mouse_button(&mut self, button: Button, direction: Direction) -> Result<(), String> {
+            let code = match button {
+                Button::Left => EvKey::BTN_LEFT,
+                Button::Right => EvKey::BTN_RIGHT,
+                Button::Middle => EvKey::BTN_MIDDLE,
+                Button::Back => EvKey::BTN_SIDE,
+                Button::Forward => EvKey::BTN_EXTRA,
+                _ => return Err(format!("no button code for {:?}", button)),
+            };
+            match direction {
+                Direction::Press => self.send(code, true),
+                Direction::Release => self.send(code, false),
+                Direction::Click => self.tap(code, false),
+            }
+        }
+
+        fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+            // The wheel axis counts up for scrolling up, the opposite of our convention
+            self.send_relative(&[(RelativeAxisCode::REL_HWHEEL, dx), (RelativeAxisCode::REL_WHEEL, -dy)])
+        }
+    }
+
+    /// Key code and whether Shift is needed to type `c` on a US layout
+    pub(super) fn char_key(c: char) -> Option<(EvKey, bool)> {
+        const LETTERS: [EvKey; 26] = [
+            EvKey::KEY_A, EvKey::KEY_B, EvKey::KEY_C, EvKey::KEY_D, EvKey::KEY_E, EvKey::KEY_F, EvKey::KEY_G,
+            EvKey::KEY_H, EvKey::KEY_I, EvKey::KEY_J, EvKey::KEY_K, EvKey::KEY_L, EvKey::KEY_M, EvKey::KEY_N,
+            EvKey::KEY_O, EvKey::KEY_P, EvKey::KEY_Q, EvKey::KEY_R, EvKey::KEY_S, EvKey::KEY_T, EvKey::KEY_U,
+            EvKey::KEY_V, EvKey::KEY_W, EvKey::KEY_X, EvKey::KEY_Y, EvKey::KEY_Z,
+        ];
+        const DIGITS: [EvKey; 10] = [
+            EvKey::KEY_0, EvKey::KEY_1, EvKey::KEY_2, EvKey::KEY_3, EvKey::KEY_4,
+            EvKey::KEY_5, EvKey::KEY_6, EvKey::KEY_7, EvKey::KEY_8, EvKey::KEY_9,
+        ];
+        const SHIFTED_DIGITS: &str = ")!@#$%^&*(";
+
+        if c.is_ascii_lowercase() {
+            return Some((LETTERS[(c as u8 - b'a') as usize], false));
+        }
+        if c.is_ascii_uppercase() {
+            return Some((LETTERS[(c as u8 - b'A') as usize], true));
+        }
+        if c.is_ascii_digit() {
+            return Some((DIGITS[(c as u8 - b'0') as usize], false));
+        }
+        if let Some(index) = SHIFTED_DIGITS.find(c) {
+            return Some((DIGITS[index], true));
+        }
+
+        let key = match c {
+            ' ' => (EvKey::KEY_SPACE, false),
+            '\n' => (EvKey::KEY_ENTER, false),
+            '\t' => (EvKey::KEY_TAB, false),
+            '-' => (EvKey::KEY_MINUS, false),
+            '_' => (EvKey::KEY_MINUS, true),
+            '=' => (EvKey::KEY_EQUAL, false),
+            '+' => (EvKey::KEY_EQUAL, true),
+            '[' => (EvKey::KEY_LEFTBRACE, false),
+            '{' => (EvKey::KEY_LEFTBRACE, true),
+            ']' => (EvKey::KEY_RIGHTBRACE, false),
+            '}' => (EvKey::KEY_RIGHTBRACE, true),
+            '\\' => (EvKey::KEY_BACKSLASH, false),
+            '|' => (EvKey::KEY_BACKSLASH, true),
+            ';' => (EvKey::KEY_SEMICOLON, false),
+            ':' => (EvKey::KEY_SEMICOLON, true),
+            '\'' => (EvKey::KEY_APOSTROPHE, false),
+            '"' => (EvKey::KEY_APOSTROPHE, true),
+            '`' => (EvKey::KEY_GRAVE, false),
+            '~' => (EvKey::KEY_GRAVE, true),
+            ',' => (EvKey::KEY_COMMA, false),
+            '<' => (EvKey::KEY_COMMA, true),
+            '.' => (EvKey::KEY_DOT, false),
+            '>' => (EvKey::KEY_DOT, true),
+            '/' => (EvKey::KEY_SLASH, false),
+            '?' => (EvKey::KEY_SLASH, true),
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    pub(super) fn named_key(key: Key) -> Option<EvKey> {
+        let code = match key {
+            Key::Control | Key::LControl => EvKey::KEY_LEFTCTRL,
+            Key::RControl => EvKey::KEY_RIGHTCTRL,
+            Key::Shift | Key::LShift => EvKey::KEY_LEFTSHIFT,
+            Key::RShift => EvKey::KEY_RIGHTSHIFT,
+            Key::Alt => EvKey::KEY_LEFTALT,
+            Key::Meta => EvKey::KEY_LEFTMETA,
+            Key::Return => EvKey::KEY_ENTER,
+            Key::Tab => EvKey::KEY_TAB,
+            Key::Space => EvKey::KEY_SPACE,
+            Key::Backspace => EvKey::KEY_BACKSPACE,
+            Key::Delete => EvKey::KEY_DELETE,
+            Key::Escape => EvKey::KEY_ESC,
+            Key::UpArrow => EvKey::KEY_UP,
+            Key::DownArrow => EvKey::KEY_DOWN,
+            Key::LeftArrow => EvKey::KEY_LEFT,
+            Key::RightArrow => EvKey::KEY_RIGHT,
+            Key::Home => EvKey::KEY_HOME,
+            Key::End => EvKey::KEY_END,
+            Key::PageUp => EvKey::KEY_PAGEUP,
+            Key::PageDown => EvKey::KEY_PAGEDOWN,
+            Key::CapsLock => EvKey::KEY_CAPSLOCK,
+            Key::Insert => EvKey::KEY_INSERT,
+            Key::PrintScr => EvKey::KEY_SYSRQ,
+            Key::Pause => EvKey::KEY_PAUSE,
+            Key::Numlock => EvKey::KEY_NUMLOCK,
+            Key::F1 => EvKey::KEY_F1,
+            Key::F2 => EvKey::KEY_F2,
+            Key::F3 => EvKey::KEY_F3,
+            Key::F4 => EvKey::KEY_F4,
+            Key::F5 => EvKey::KEY_F5,
+            Key::F6 => EvKey::KEY_F6,
+            Key::F7 => EvKey::KEY_F7,
+            Key::F8 => EvKey::KEY_F8,
+            Key::F9 => EvKey::KEY_F9,
+            Key::F10 => EvKey::KEY_F10,
+            Key::F11 => EvKey::KEY_F11,
+            Key::F12 => EvKey::KEY_F12,
+            Key::F13 => EvKey::KEY_F13,
+            Key::F14 => EvKey::KEY_F14,
+            Key::F15 => EvKey::KEY_F15,
+            Key::F16 => EvKey::KEY_F16,
+            Key::F17 => EvKey::KEY_F17,
+            Key::F18 => EvKey::KEY_F18,
+            Key::F19 => EvKey::KEY_F19,
+            Key::F20 => EvKey::KEY_F20,
+            Key::Numpad0 => EvKey::KEY_KP0,
+            Key::Numpad1 => EvKey::KEY_KP1,
+            Key::Numpad2 => EvKey::KEY_KP2,
+            Key::Numpad3 => EvKey::KEY_KP3,
+            Key::Numpad4 => EvKey::KEY_KP4,
+            Key::Numpad5 => EvKey::KEY_KP5,
+            Key::Numpad6 => EvKey::KEY_KP6,
+            Key::Numpad7 => EvKey::KEY_KP7,
+            Key::Numpad8 => EvKey::KEY_KP8,
+            Key::Numpad9 => EvKey::KEY_KP9,
+            Key::Add => EvKey::KEY_KPPLUS,
+            Key::Subtract => EvKey::KEY_KPMINUS,
+            Key::Multiply => EvKey::KEY_KPASTERISK,
+            Key::Divide => EvKey::KEY_KPSLASH,
+            Key::Decimal => EvKey::KEY_KPDOT,
+            Key::VolumeUp => EvKey::KEY_VOLUMEUP,
+            Key::VolumeDown => EvKey::KEY_VOLUMEDOWN,
+            Key::VolumeMute => EvKey::KEY_MUTE,
+            Key::MediaPlayPause => EvKey::KEY_PLAYPAUSE,
+            Key::MediaNextTrack => EvKey::KEY_NEXTSONG,
+            Key::MediaPrevTrack => EvKey::KEY_PREVIOUSSONG,
+            _ => return None,
+        };
+        Some(code)
+    }
+}
+
+/// Sends key codes through the RemoteDesktop portal, the way uinput would send them to a virtual device
+#[cfg(target_os = "linux")]
+mod portal_backend {
+    use enigo::{Button, Direction, Key};
+    use evdev::KeyCode as EvKey;
+
+    use super::uinput_backend::{char_key, named_key};
+    use super::{Backend, Options};
+    use crate::layout;
+    use crate::portal;
+
+    struct PortalBackend;
+
+    pub fn create(_options: &Options) -> Result<Box<dyn Backend>, String> {
+        portal::start_session()?;
+        Ok(Box::new(PortalBackend))
+    }
+
+    fn tap(key: EvKey, shift: bool) -> Result<(), String> {
+        let shift_key = EvKey::KEY_LEFTSHIFT.code();
+        if shift {
+            portal::key(shift_key, true)?;
+        }
+        portal::key(key.code(), true)?;
+        portal::key(key.code(), false)?;
+        if shift {
+            portal::key(shift_key, false)?;
+        }
+        Ok(())
+    }
+
+    impl Backend for PortalBackend {
+        fn text(&mut self, text: &str) -> Result<(), String> {
+            if let Some(plan) = layout::plan_text(text) {
+                return super::send_strokes(&plan?, portal::key);
+            }
+            let keys = text
+                .chars()
+                .map(|c| char_key(c).ok_or_else(|| format!("cannot type {:?} with raw key codes", c)))
+                .collect::<Result<Vec<_>, _>>()?;
+            keys.into_iter().try_for_each(|(key, shift)| tap(key, shift))
+        }
+
+        fn key(&mut self, key: Key, direction: Direction) -> Result<(), String> {
+            let (code, shift) = match key {
+                Key::Unicode(c) => char_key(c),
+                key => named_key(key).map(|code| (code, false)),
+            }
+            .ok_or_else(|| format!("no key code for {:?}", key))?;
+            let shift_key = EvKey::KEY_LEFTSHIFT.code();
+            match direction {
+                Direction::Press => {
+                    if shift {
+                        portal::key(shift_key, true)?;
+                    }
+                    portal::key(code.code(), true)
+                }
+                Direction::Release => {
+                    portal::key(code.code(), false)?;
+                    if shift {
+                        portal::key(shift_key, false)?;
+                    }
+                    Ok(())
+                }
+                Direction::Click => tap(code, shift),
+            }
+        }
+
+        fn mouse_move(&mut self, x: i32, y: i32, relative: bool) -> Result<(), String> {
+            if !relative {
+                return Err("the portal only moves the pointer relatively".to_string());
+            }
+            portal::pointer_motion(x, y)
+        }
+
+        fn mouse_button(&mut self, button: Button, direction: Direction) -> Result<(), String> {
+            let code = match button {
+                Button::Left => EvKey::BTN_LEFT,
+                Button::Right => EvKey::BTN_RIGHT,
+                Button::Middle => EvKey::BTN_MIDDLE,
+                Button::Back => EvKey::BTN_SIDE,
+                Button::Forward => EvKey::BTN_EXTRA,
+                _ => return Err(format!("no button code for {:?}", button)),
+            };
+            match direction {
+                Direction::Press => portal::button(code.code(), true),
+                Direction::Release => portal::button(code.code(), false),
+                Direction::Click => portal::button(code.code(), true).and_then(|()| portal::button(code.code(), false)),
+            }
+        }
+
+        fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+            portal::scroll(dx, dy)
+        }
+    }
+}
+
+/// Types through a virtual keyboard of the Wayland compositor, with a keymap holding the keysyms typed
+#[cfg(target_os = "linux")]
+mod wayland_backend {
+    use enigo::{Button, Direction, Key};
+    use evdev::KeyCode as EvKey;
+
+    use super::{Backend, Options};
+    use crate::wayland::{self, VirtualInput};
+
+    struct WaylandBackend {
+        input: VirtualInput,
+    }
+
+    pub fn create(_options: &Options) -> Result<Box<dyn Backend>, String> {
+        Ok(Box::new(WaylandBackend {
+            input: VirtualInput::connect()?,
+        }))
+    }
+
+    impl Backend for WaylandBackend {
+        fn text(&mut self, text: &str) -> Result<(), String> {
+            let keysyms = text
+                .chars()
+                .map(|c| wayland::char_keysym(c).ok_or_else(|| format!("cannot type {:?}", c)))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.input.type_keysyms(&keysyms)
+        }
+
+        fn key(&mut self, key: Key, direction: Direction) -> Result<(), String> {
+            if let Some(mask) = modifier(key) {
+                return match direction {
+                    Direction::Press => self.input.modifiers(mask, true),
+                    Direction::Release => self.input.modifiers(mask, false),
+                    Direction::Click => Ok(()),
+                };
+            }
+            let keysym = match key {
+                Key::Unicode(c) => wayland::char_keysym(c),
+                key => keysym(key),
+            }
+            .ok_or_else(|| format!("no keysym for {:?}", key))?;
+            match direction {
+                Direction::Press => self.input.key(keysym, true),
+                Direction::Release => self.input.key(keysym, false),
+                Direction::Click => self.input.type_keysyms(&[keysym]),
+            }
+        }
+
+        fn mouse_move(&mut self, x: i32, y: i32, relative: bool) -> Result<(), String> {
+            if !relative {
+                return Err("a virtual pointer only moves relatively".to_string());
+            }
+            self.input.pointer_motion(x, y)
+        }
+
+        fn mouse_button(&mut self, button: Button, direction: Direction) -> Result<(), String> {
+            let code = match button {
+                Button::Left => EvKey::BTN_LEFT,
+                Button::Right => EvKey::BTN_RIGHT,
+                Button::Middle => EvKey::BTN_MIDDLE,
+                Button::Back => EvKey::BTN_SIDE,
+                Button::Forward => EvKey::BTN_EXTRA,
+                _ => return Err(format!("no button code for {:?}", button)),
+            };
+            match direction {
+                Direction::Press => self.input.button(code.code(), true),
+                Direction::Release => self.input.button(code.code(), false),
+                Direction::Click => {
+                    self.input.button(code.code(), true)?;
+                    self.input.button(code.code(), false)
+                }
+            }
+        }
+
+        fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+            self.input.scroll(dx, dy)
+        }
+    }
+
+    /// The modifier mask `key` holds, for modifier keys
+    fn modifier(key: Key) -> Option<u32> {
+        match key {
+            Key::Control | Key::LControl | Key::RControl => Some(wayland::CONTROL),
+            Key::Shift | Key::LShift | Key::RShift => Some(wayland::SHIFT),
+            Key::Alt => Some(wayland::ALT),
+            Key::Meta => Some(wayland::LOGO),
+            _ => None,
+        }
+    }
+
+    fn keysym(key: Key) -> Option<u32> {
+        let keysym = match key {
+            Key::Return => 0xff0d,
+            Key::Tab => 0xff09,
+            Key::Space => 0x20,
+            Key::Backspace => 0xff08,
+            Key::Delete => 0xffff,
+            Key::Escape => 0xff1b,
+            Key::UpArrow => 0xff52,
+            Key::DownArrow => 0xff54,
+            Key::LeftArrow => 0xff51,
+            Key::RightArrow => 0xff53,
+            Key::Home => 0xff50,
+            Key::End => 0xff57,
+            Key::PageUp => 0xff55,
+            Key::PageDown => 0xff56,
+            Key::CapsLock => 0xffe5,
+            Key::Insert => 0xff63,
+            Key::PrintScr => 0xff61,
+            Key::Pause => 0xff13,
+            Key::Numlock => 0xff7f,
+            Key::F1 => 0xffbe,
+            Key::F2 => 0xffbf,
+            Key::F3 => 0xffc0,
+            Key::F4 => 0xffc1,
+            Key::F5 => 0xffc2,
+            Key::F6 => 0xffc3,
+            Key::F7 => 0xffc4,
+            Key::F8 => 0xffc5,
+            Key::F9 => 0xffc6,
+            Key::F10 => 0xffc7,
+            Key::F11 => 0xffc8,
+            Key::F12 => 0xffc9,
+            Key::F13 => 0xffca,
+            Key::F14 => 0xffcb,
+            Key::F15 => 0xffcc,
+            Key::F16 => 0xffcd,
+            Key::F17 => 0xffce,
+            Key::F18 => 0xffcf,
+            Key::F19 => 0xffd0,
+            Key::F20 => 0xffd1,
+            Key::Numpad0 => 0xffb0,
+            Key::Numpad1 => 0xffb1,
+            Key::Numpad2 => 0xffb2,
+            Key::Numpad3 => 0xffb3,
+            Key::Numpad4 => 0xffb4,
+            Key::Numpad5 => 0xffb5,
+            Key::Numpad6 => 0xffb6,
+            Key::Numpad7 => 0xffb7,
+            Key::Numpad8 => 0xffb8,
+            Key::Numpad9 => 0xffb9,
+            Key::Add => 0xffab,
+            Key::Subtract => 0xffad,
+            Key::Multiply => 0xffaa,
+            Key::Divide => 0xffaf,
+            Key::Decimal => 0xffae,
+            // XF86 media keysyms
+            Key::VolumeUp => 0x1008_ff13,
+            Key::VolumeDown => 0x1008_ff11,
+            Key::VolumeMute => 0x1008_ff12,
+            Key::MediaPlayPause => 0x1008_ff14,
+            Key::MediaNextTrack => 0x1008_ff17,
+            Key::MediaPrevTrack => 0x1008_ff16,
+            _ => return None,
+        };
+        Some(keysym)
+    }
+}
+
+/// Types text as `KEYEVENTF_UNICODE` key events, which need no key in the keyboard layout
+#[cfg(windows)]
+mod sendinput_backend {
+    use enigo::{Button, Direction, Key};
+    use std::thread;
+    use std::time::Duration;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+        SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, VIRTUAL_KEY,
+        VK_RETURN,
+    };
+
+    use super::{Backend, Options};
+
+    /// Inputs per `SendInput` call; the target app's input queue drains between calls
+    const BATCH_INPUTS: usize = 128;
+
+    /// Lets the target app handle a batch before the next one
+    const BATCH_PAUSE: Duration = Duration::from_millis(5);
+
+    struct SendInputBackend;
+
+    pub fn create(_options: &Options) -> Result<Box<dyn Backend>, String> {
+        Ok(Box::new(SendInputBackend))
+    }
+
+    fn key_input(vk: VIRTUAL_KEY, scan: u16, flags: u32) -> INPUT {
+        INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: vk,
+                    wScan: scan,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                },
+            },
+        }
+    }
+
+    /// The key events typing `c`
+    ///
+    /// Both halves of a surrogate pair go down before either comes up, so the
+    /// app's message loop sees them as one character. Newlines are Enter,
+    /// which text controls handle where a U+000A character does nothing.
+    fn char_inputs(c: char) -> Vec<INPUT> {
+        if c == '\n' {
+            return vec![key_input(VK_RETURN, 0, 0), key_input(VK_RETURN, 0, KEYEVENTF_KEYUP)];
+        }
+        let mut units = [0; 2];
+        let units = c.encode_utf16(&mut units);
+        let downs = units.iter().map(|&unit| key_input(0, unit, KEYEVENTF_UNICODE));
+        let ups = units.iter().map(|&unit| key_input(0, unit, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP));
+        downs.chain(ups).collect()
+    }
+
+    fn send(inputs: &[INPUT]) -> Result<(), String> {
+        let sent = unsafe { SendInput(inputs.len() as u32, inputs.as_ptr(), std::mem::size_of::<INPUT>() as i32) };
+        if sent as usize == inputs.len() {
+            Ok(())
+        } else {
+            // UIPI: the foreground window belongs to a more privileged process
+            Err("SendInput was blocked".to_string())
+        }
+    }
+
+    impl Backend for SendInputBackend {
+        fn text(&mut self, text: &str) -> Result<(), String> {
+            // Windows line endings would type two newlines
+            let text = text.replace("\r\n", "\n");
+            let mut batch = Vec::with_capacity(BATCH_INPUTS);
+            for c in text.chars() {
+                let inputs = char_inputs(c);
+                // A character's events stay in one batch
+                if batch.len() + inputs.len() > BATCH_INPUTS {
+                    send(&batch)?;
+                    batch.clear();
+                    thread::sleep(BATCH_PAUSE);
+                }
+                batch.extend(inputs);
+            }
+            if batch.is_empty() {
+                return Ok(());
+            }
+            send(&batch)
+        }
+
+        fn key(&mut self, key: Key, _direction: Direction) -> Result<(), String> {
+            Err(format!("cannot send {:?}: the sendinput backend only types text", key))
+        }
+
+        fn mouse_move(&mut self, _x: i32, _y: i32, _relative: bool) -> Result<(), String> {
+            Err("cannot move the pointer: the sendinput backend only types text".to_string())
+        }
+
+        fn mouse_button(&mut self, button: Button, _direction: Direction) -> Result<(), String> {
+            Err(format!("cannot press {:?}: the sendinput backend only types text", button))
+        }
+
+        fn scroll(&mut self, _dx: i32, _dy: i32) -> Result<(), String> {
+            Err("cannot scroll: the sendinput backend only types text".to_string())
+        }
+    }
+}
+
+mod clipboard_backend {
+    use enigo::{Button, Direction, Key};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{chain, Backend, Options};
+    use crate::clipboard::{self, Selection, Snapshot};
+    use crate::event::{Event, EventKind};
+    use crate::stream;
+
+    /// The clipboard owner needs a moment before the target app can request the contents
+    const CLIPBOARD_SETTLE_TIME: Duration = Duration::from_millis(50);
+
+    /// The target app reads the clipboard only once it handles the paste shortcut
+    const PASTE_READ_TIME: Duration = Duration::from_millis(200);
+
+    /// Puts the text on the clipboard, sends the paste shortcut through a
+    /// key-capable backend, then puts back what the clipboard held before
+    struct ClipboardBackend {
+        selection: Selection,
+        paster: Box<dyn Backend>,
+    }
+
+    pub fn create(options: &Options) -> Result<Box<dyn Backend>, String> {
+        let mut errors = Vec::new();
+        for link in chain().into_iter().filter(|link| !link.text_only) {
+            match (link.create)(options) {
+                Ok(paster) => {
+                    return Ok(Box::new(ClipboardBackend {
+                        selection: options.paste_selection,
+                        paster,
+                    }))
+                }
+                Err(e) => errors.push(format!("{}: {}", link.name, e)),
+            }
+        }
+        Err(format!("no backend can send the paste shortcut ({})", errors.join("; ")))
+    }
+
+    /// Put `saved` back, unless the clipboard changed since `pasted` was put on it
+    fn restore(saved: Snapshot, selection: Selection, pasted: &str) {
+        match clipboard::text(selection) {
+            Ok(Some(current)) if current == pasted => {
+                if let Err(e) = saved.restore() {
+                    eprintln!("Failed to restore the clipboard: {}", e);
+                }
+            }
+            Ok(_) => {
+                eprintln!("Clipboard changed during the paste; leaving the new contents");
+                stream::emit(&Event::now(EventKind::ClipboardConflict {
+                    formats: saved.format_names(),
+                }));
+            }
+            Err(e) => eprintln!("Clipboard left as pasted: cannot read it back: {}", e),
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    const PASTE_MODIFIER: Key = Key::Meta;
+    #[cfg(not(target_os = "macos"))]
+    const PASTE_MODIFIER: Key = Key::Control;
+
+    impl ClipboardBackend {
+        fn paste(&mut self) -> Result<(), String> {
+            if self.selection == Selection::Primary {
+                return self.paster.mouse_button(Button::Middle, Direction::Click);
+            }
+            self.paster.key(PASTE_MODIFIER, Direction::Press)?;
+            let pasted = self.paster.key(Key::Unicode('v'), Direction::Click);
+            self.paster.key(PASTE_MODIFIER, Direction::Release)?;
+            pasted
+        }
+    }
+
+    impl Backend for ClipboardBackend {
+        fn text(&mut self, text: &str) -> Result<(), String> {
+            let saved = Snapshot::take(self.selection)
+                .map_err(|e| eprintln!("Clipboard won't be restored: cannot save it: {}", e))
+                .ok();
+            clipboard::set_text(self.selection, text)?;
+            thread::sleep(CLIPBOARD_SETTLE_TIME);
+            let pasted = self.paste();
+            if let Some(saved) = saved {
+                thread::sleep(PASTE_READ_TIME);
+                restore(saved, self.selection, text);
+            }
+            pasted
+        }
+
+        fn key(&mut self, key: Key, _direction: Direction) -> Result<(), String> {
+            Err(format!("cannot send {:?}: the clipboard backend only inserts text", key))
+        }
+
+        fn mouse_move(&mut self, _x: i32, _y: i32, _relative: bool) -> Result<(), String> {
+            Err("cannot move the pointer: the clipboard backend only inserts text".to_string())
+        }
+
+        fn mouse_button(&mut self, button: Button, _direction: Direction) -> Result<(), String> {
+            Err(format!("cannot press {:?}: the clipboard backend only inserts text", button))
+        }
+
+        fn scroll(&mut self, _dx: i32, _dy: i32) -> Result<(), String> {
+            Err("cannot scroll: the clipboard backend only inserts text".to_string())
+        }
+    }
+}
+
+/// Inserts text into the focused field through the accessibility APIs, without key events
+mod ax_backend {
+    use enigo::{Button, Direction, Key};
+
+    use super::{Backend, Options};
+    use crate::context;
+
+    struct AxBackend;
+
+    pub fn create(_options: &Options) -> Result<Box<dyn Backend>, String> {
+        Ok(Box::new(AxBackend))
+    }
+
+    impl Backend for AxBackend {
+        fn text(&mut self, text: &str) -> Result<(), String> {
+            context::insert(text)
+        }
+
+        fn key(&mut self, key: Key, _direction: Direction) -> Result<(), String> {
+            Err(format!("cannot send {:?}: the ax backend only inserts text", key))
+        }
+
+        fn mouse_move(&mut self, _x: i32, _y: i32, _relative: bool) -> Result<(), String> {
+            Err("cannot move the pointer: the ax backend only inserts text".to_string())
+        }
+
+        fn mouse_button(&mut self, button: Button, _direction: Direction) -> Result<(), String> {
+            Err(format!("cannot press {:?}: the ax backend only inserts text", button))
+        }
+
+        fn scroll(&mut self, _dx: i32, _dy: i32) -> Result<(), String> {
+            Err("cannot scroll: the ax backend only inserts text".to_string())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod xdotool_backend {
+    use enigo::{Button, Direction, Key};
+    use std::process::{Command, Stdio};
+
+    use super::{run_with_input, Backend, Options};
+
+    struct XdotoolBackend;
+
+    pub fn create(_options: &Options) -> Result<Box<dyn Backend>, String> {
+        if std::env::var_os("DISPLAY").is_none() {
+            return Err("DISPLAY is not set".to_string());
+        }
+        let status = Command::new("xdotool")
+            .arg("version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| format!("xdotool unavailable: {}", e))?;
+        if !status.success() {
+            return Err(format!("xdotool exited with {}", status));
+        }
+        Ok(Box::new(XdotoolBackend))
+    }
+
+    fn xdotool(args: &[&str]) -> Result<(), String> {
+        let status = Command::new("xdotool")
+            .args(args)
+            .status()
+            .map_err(|e| format!("xdotool: {}", e))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("xdotool exited with {}", status))
+        }
+    }
+
+    impl Backend for XdotoolBackend {
+        fn text(&mut self, text: &str) -> Result<(), String> {
+            // Read from stdin so the text stays out of the process list
+            run_with_input("xdotool", &["type", "--clearmodifiers", "--file", "-"], text)
+        }
+
+        fn key(&mut self, key: Key, direction: Direction) -> Result<(), String> {
+            let keysym = keysym(key).ok_or_else(|| format!("no keysym for {:?}", key))?;
+            let command = match direction {
+                Direction::Press => "keydown",
+                Direction::Release => "keyup",
+                Direction::Click => "key",
+            };
+            xdotool(&[command, &keysym])
+        }
+
+        fn mouse_move(&mut self, x: i32, y: i32, relative: bool) -> Result<(), String> {
+            let (x, y) = (x.to_string(), y.to_string());
+            if relative {
+                // `--` so negative offsets aren't taken for options
+                xdotool(&["mousemove_relative", "--", &x, &y])
+            } else {
+                xdotool(&["mousemove", "--", &x, &y])
+            }
+        }
+
+        fn mouse_button(&mut self, button: Button, direction: Direction) -> Result<(), String> {
+            let number = match button {
+                Button::Left => "1",
+                Button::Middle => "2",
+                Button::Right => "3",
+                Button::ScrollUp => "4",
+                Button::ScrollDown => "5",
+                Button::ScrollLeft => "6",
+                Button::ScrollRight => "7",
+                Button::Back => "8",
+                Button::Forward => "9",
+            };
+            let command = match direction {
+                Direction::Press => "mousedown",
+                Direction::Release => "mouseup",
+                Direction::Click => "click",
+            };
+            xdotool(&[command, number])
+        }
+
+        fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+            // X reports wheel notches as clicks of buttons 4-7
+            for (amount, negative, positive) in [(dx, "6", "7"), (dy, "4", "5")] {
+                if amount != 0 {
+                    let button = if amount < 0 { negative } else { positive };
+                    xdotool(&["click", "--repeat", &amount.unsigned_abs().to_string(), button])?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn keysym(key: Key) -> Option<String> {
+        let name = match key {
+            Key::Unicode(c) if c.is_ascii_alphanumeric() => return Some(c.to_string()),
+            Key::Unicode(c) => return Some(format!("U{:04X}", c as u32)),
+            Key::Control | Key::LControl => "Control_L",
+            Key::RControl => "Control_R",
+            Key::Shift | Key::LShift => "Shift_L",
+            Key::RShift => "Shift_R",
+            Key::Alt => "Alt_L",
+            Key::Meta => "Super_L",
+            Key::Return => "Return",
+            Key::Tab => "Tab",
+            Key::Space => "space",
+            Key::Backspace => "BackSpace",
+            Key::Delete => "Delete",
+            Key::Escape => "Escape",
+            Key::UpArrow => "Up",
+            Key::DownArrow => "Down",
+            Key::LeftArrow => "Left",
+            Key::RightArrow => "Right",
+            Key::Home => "Home",
+            Key::End => "End",
+            Key::PageUp => "Prior",
+            Key::PageDown => "Next",
+            Key::CapsLock => "Caps_Lock",
+            Key::Insert => "Insert",
+            Key::PrintScr => "Print",
+            Key::Pause => "Pause",
+            Key::Numlock => "Num_Lock",
+            Key::F1 => "F1",
+            Key::F2 => "F2",
+            Key::F3 => "F3",
+            Key::F4 => "F4",
+            Key::F5 => "F5",
+            Key::F6 => "F6",
+            Key::F7 => "F7",
+            Key::F8 => "F8",
+            Key::F9 => "F9",
+            Key::F10 => "F10",
+            Key::F11 => "F11",
+            Key::F12 => "F12",
+            Key::F13 => "F13",
+            Key::F14 => "F14",
+            Key::F15 => "F15",
+            Key::F16 => "F16",
+            Key::F17 => "F17",
+            Key::F18 => "F18",
+            Key::F19 => "F19",
+            Key::F20 => "F20",
+            Key::Numpad0 => "KP_0",
+            Key::Numpad1 => "KP_1",
+            Key::Numpad2 => "KP_2",
+            Key::Numpad3 => "KP_3",
+            Key::Numpad4 => "KP_4",
+            Key::Numpad5 => "KP_5",
+            Key::Numpad6 => "KP_6",
+            Key::Numpad7 => "KP_7",
+            Key::Numpad8 => "KP_8",
+            Key::Numpad9 => "KP_9",
+            Key::Add => "KP_Add",
+            Key::Subtract => "KP_Subtract",
+            Key::Multiply => "KP_Multiply",
+            Key::Divide => "KP_Divide",
+            Key::Decimal => "KP_Decimal",
+            Key::VolumeUp => "XF86AudioRaiseVolume",
+            Key::VolumeDown => "XF86AudioLowerVolume",
+            Key::VolumeMute => "XF86AudioMute",
+            Key::MediaPlayPause => "XF86AudioPlay",
+            Key::MediaNextTrack => "XF86AudioNext",
+            Key::MediaPrevTrack => "XF86AudioPrev",
+            _ => return None,
+        };
+        Some(name.to_string())
+    }
+}
+
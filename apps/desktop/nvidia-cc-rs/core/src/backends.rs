@@ -0,0 +1,128 @@
+//! `backends`: which capture and injection backends this build has, and
+//! whether each can run on this system.
+//!
+//! Capture backends are what `listen --capture-backend` takes: `devices`
+//! (evdev on Linux, rdev's global hook elsewhere) and `replay`. Injection
+//! backends are the links of the fallback chain `--inject-backend` and
+//! `[injection] backends` pick from; a backend is usable when it can be
+//! created here, which for xdotool and uinput means its tool or device is
+//! there. Backends of other platforms are listed as not compiled in.
+
+use serde::Serialize;
+
+use crate::backend;
+
+#[derive(Serialize)]
+pub struct Availability {
+    pub name: &'static str,
+    pub compiled: bool,
+    pub usable: bool,
+    /// Why it isn't usable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Availability {
+    fn new(name: &'static str, usable: Option<Result<(), String>>) -> Self {
+        match usable {
+            None => Availability {
+                name,
+                compiled: false,
+                usable: false,
+                reason: Some(format!("not built for {}", std::env::consts::OS)),
+            },
+            Some(result) => Availability {
+                name,
+                compiled: true,
+                usable: result.is_ok(),
+                reason: result.err(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub capture: Vec<Availability>,
+    pub inject: Vec<Availability>,
+}
+
+/// Capture backend names `listen --capture-backend` takes
+pub const CAPTURE_NAMES: [&str; 2] = ["devices", "replay"];
+
+pub fn report() -> Report {
+    Report {
+        capture: vec![
+            Availability::new("devices", Some(platform::devices_usable())),
+            // Reads a file of recorded events, so it runs anywhere
+            Availability::new("replay", Some(Ok(()))),
+        ],
+        inject: backend::availability()
+            .into_iter()
+            .map(|(name, usable)| Availability::new(name, usable))
+            .collect(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use evdev::KeyCode;
+
+    use crate::input_access;
+
+    /// Whether a keyboard in /dev/input can be opened
+    pub fn devices_usable() -> Result<(), String> {
+        let input_dir = "/dev/input";
+        let entries = std::fs::read_dir(input_dir).map_err(|e| format!("Cannot access {}: {}", input_dir, e))?;
+        let mut denied = None;
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if !path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("event")) {
+                continue;
+            }
+            match input_access::open(&path) {
+                Ok(device) => {
+                    if device
+                        .supported_keys()
+                        .is_some_and(|keys| keys.contains(KeyCode::KEY_A) || keys.contains(KeyCode::KEY_SPACE))
+                    {
+                        return Ok(());
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => denied = Some(path),
+                Err(_) => {}
+            }
+        }
+        Err(match denied {
+            Some(path) => format!(
+                "Permission denied for {}: run install-permissions, or add the user to the 'input' group",
+                path.display()
+            ),
+            None => format!("No keyboard device found in {}", input_dir),
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXIsProcessTrusted() -> u8;
+    }
+
+    /// rdev's event tap only sees keys with the Accessibility permission
+    pub fn devices_usable() -> Result<(), String> {
+        if unsafe { AXIsProcessTrusted() } != 0 {
+            Ok(())
+        } else {
+            Err("the Accessibility permission isn't granted (System Settings > Privacy & Security)".to_string())
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod platform {
+    /// rdev's low-level keyboard hook needs no permission
+    pub fn devices_usable() -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! Keyboard backlights: `backlight list` and `backlight set`.
+//!
+//! Linux exposes laptop keyboard backlights as LED class devices named
+//! `*::kbd_backlight` under `/sys/class/leds`. `backlight set 100 --color
+//! #ff0000` turns every one of them (or the one `--device` names) fully on,
+//! red where the backlight is a multicolor one; `backlight list` prints each
+//! one's current state as JSON so it can be put back afterwards. Brightness is
+//! a percentage of the device's maximum.
+//!
+//! Writing to sysfs needs root or a udev rule; without them the brightness is
+//! set through systemd-logind instead, which lets the user at the seat do it.
+//! Colors have no logind equivalent. Keyboards that only take vendor HID
+//! commands aren't supported, and neither are macOS and Windows.
+
+use serde::Serialize;
+
+/// A keyboard backlight's state as `backlight list` reports it
+#[derive(Serialize)]
+pub struct Backlight {
+    pub name: String,
+    pub brightness: u32,
+    pub max_brightness: u32,
+    /// `#rrggbb` for a multicolor backlight
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+/// What `backlight set` applies
+pub struct Setting {
+    pub percent: u32,
+    pub color: Option<[u8; 3]>,
+    /// Only the backlight with this name; all of them when `None`
+    pub device: Option<String>,
+}
+
+/// `<percent|on|off> [--color #rrggbb] [--device <name>]`
+pub fn parse_set(args: &[&str]) -> Result<Setting, String> {
+    let Some((&level, mut rest)) = args.split_first() else {
+        return Err("Expected: backlight set <percent|on|off> [--color #rrggbb] [--device <name>]".to_string());
+    };
+    let percent = match level {
+        "on" => 100,
+        "off" => 0,
+        level => match level.trim_end_matches('%').parse::<u32>() {
+            Ok(percent) if percent <= 100 => percent,
+            _ => return Err(format!("Invalid brightness {:?} (expected 0-100, on or off)", level)),
+        },
+    };
+    let mut setting = Setting {
+        percent,
+        color: None,
+        device: None,
+    };
+    loop {
+        match rest {
+            [] => break,
+            ["--color", color, tail @ ..] => {
+                setting.color = Some(parse_color(color)?);
+                rest = tail;
+            }
+            ["--device", device, tail @ ..] => {
+                setting.device = Some(device.to_string());
+                rest = tail;
+            }
+            [other, ..] => return Err(format!("Unknown backlight option {:?}", other)),
+        }
+    }
+    Ok(setting)
+}
+
+/// `#rrggbb` or `rrggbb`
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let channel = |index: usize| hex.get(index..index + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok());
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(red), Some(green), Some(blue)) => Ok([red, green, blue]),
+        _ => Err(format!("Invalid color {:?} (expected #rrggbb)", color)),
+    }
+}
+
+/// Every keyboard backlight found
+pub fn list() -> Result<Vec<Backlight>, String> {
+    platform::list()
+}
+
+/// Apply `setting`, returning how many backlights it changed
+pub fn set(setting: &Setting) -> Result<usize, String> {
+    platform::set(setting)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    use super::{Backlight, Setting};
+
+    const LEDS_DIR: &str = "/sys/class/leds";
+
+    fn read_number(path: &Path) -> Option<u32> {
+        fs::read_to_string(path).ok()?.trim().parse().ok()
+    }
+
+    /// Backlight LED directories, sorted by name
+    fn devices() -> Vec<(String, PathBuf)> {
+        let Ok(entries) = fs::read_dir(LEDS_DIR) else {
+            return Vec::new();
+        };
+        let mut devices: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Some((entry.file_name().into_string().ok()?, entry.path())))
+            .filter(|(name, _)| name.ends_with("kbd_backlight"))
+            .collect();
+        devices.sort();
+        devices
+    }
+
+    /// Positions of red, green and blue in a multicolor LED's `multi_intensity`
+    fn color_indices(dir: &Path) -> Option<[usize; 3]> {
+        let index = fs::read_to_string(dir.join("multi_index")).ok()?;
+        let channels: Vec<&str> = index.split_whitespace().collect();
+        let position = |color| channels.iter().position(|&channel| channel == color);
+        Some([position("red")?, position("green")?, position("blue")?])
+    }
+
+    /// Intensities run up to `max_brightness`, colors up to 255
+    fn color(dir: &Path) -> Option<String> {
+        let indices = color_indices(dir)?;
+        let max = read_number(&dir.join("max_brightness"))?.max(1);
+        let intensity = fs::read_to_string(dir.join("multi_intensity")).ok()?;
+        let values: Vec<u32> = intensity.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+        let [red, green, blue] = indices.map(|index| (values.get(index).copied().unwrap_or(0) * 255 / max).min(255));
+        Some(format!("#{:02x}{:02x}{:02x}", red, green, blue))
+    }
+
+    pub fn list() -> Result<Vec<Backlight>, String> {
+        Ok(devices()
+            .into_iter()
+            .filter_map(|(name, dir)| {
+                Some(Backlight {
+                    brightness: read_number(&dir.join("brightness"))?,
+                    max_brightness: read_number(&dir.join("max_brightness"))?,
+                    color: color(&dir),
+                    name,
+                })
+            })
+            .collect())
+    }
+
+    /// Write `brightness`, through logind when sysfs is read-only to us
+    fn set_brightness(name: &str, dir: &Path, brightness: u32) -> Result<(), String> {
+        match fs::write(dir.join("brightness"), brightness.to_string()) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                let output = Command::new("busctl")
+                    .args(["call", "org.freedesktop.login1", "/org/freedesktop/login1/session/auto"])
+                    .args(["org.freedesktop.login1.Session", "SetBrightness", "ssu", "leds", name])
+                    .arg(brightness.to_string())
+                    .output()
+                    .map_err(|e| format!("Cannot write {} and busctl failed to run: {}", name, e))?;
+                if output.status.success() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Cannot write {} and logind refused: {}",
+                        name,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ))
+                }
+            }
+            Err(e) => Err(format!("Cannot write {}: {}", name, e)),
+        }
+    }
+
+    fn set_color(name: &str, dir: &Path, color: [u8; 3]) -> Result<(), String> {
+        let indices = color_indices(dir).ok_or_else(|| format!("{} is not a color backlight", name))?;
+        let channels = fs::read_to_string(dir.join("multi_index")).unwrap_or_default().split_whitespace().count();
+        let max = read_number(&dir.join("max_brightness")).ok_or_else(|| format!("Cannot read {}", name))?;
+        let mut intensity = vec![0; channels];
+        for (index, value) in indices.into_iter().zip(color) {
+            intensity[index] = u32::from(value) * max / 255;
+        }
+        let intensity: Vec<String> = intensity.iter().map(u32::to_string).collect();
+        fs::write(dir.join("multi_intensity"), intensity.join(" "))
+            .map_err(|e| format!("Cannot set the color of {}: {}", name, e))
+    }
+
+    pub fn set(setting: &Setting) -> Result<usize, String> {
+        let devices: Vec<_> = devices()
+            .into_iter()
+            .filter(|(name, _)| setting.device.as_ref().is_none_or(|device| device == name))
+            .collect();
+        if devices.is_empty() {
+            return Err(match &setting.device {
+                Some(device) => format!("No keyboard backlight named {} in {}", device, LEDS_DIR),
+                None => format!("No keyboard backlight found in {}", LEDS_DIR),
+            });
+        }
+        for (name, dir) in &devices {
+            if let Some(color) = setting.color {
+                set_color(name, dir, color)?;
+            }
+            let max = read_number(&dir.join("max_brightness")).ok_or_else(|| format!("Cannot read {}", name))?;
+            // Round up, so a low percentage still lights a backlight with few levels
+            set_brightness(name, dir, (max * setting.percent).div_ceil(100))?;
+        }
+        Ok(devices.len())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::{Backlight, Setting};
+
+    pub fn list() -> Result<Vec<Backlight>, String> {
+        Err("Keyboard backlight control is only available on Linux".to_string())
+    }
+
+    pub fn set(_setting: &Setting) -> Result<usize, String> {
+        Err("Keyboard backlight control is only available on Linux".to_string())
+    }
+}
@@ -0,0 +1,141 @@
+//! `bench throughput` and `bench latency`: measurements of the event path.
+//!
+//! `bench throughput` emits synthetic key events through the same
+//! serialization, replay ring and stdout queue as `listen` does, waiting for
+//! the queue to drain every half queue so none are dropped, and reports the
+//! sustained rate. Events go to stdout, so run it with stdout piped into the
+//! consumer being measured (or `/dev/null`); the report goes to stderr.
+//!
+//! `bench latency` (Linux) captures while pressing F20 on the uinput virtual
+//! keyboard `write` uses, and reports how long each press takes from the
+//! write to the device until its `KeyPress` line is serialized. It needs
+//! `/dev/uinput` and `/dev/input` access, and the focused app receives the
+//! F20 presses.
+
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+use crate::event::{Event, EventKind, Key};
+use crate::output::{self, OUTPUT_QUEUE_CAPACITY};
+use crate::stream;
+
+/// Events `bench throughput` emits when not told otherwise
+pub const DEFAULT_EVENTS: u64 = 100_000;
+
+/// Presses `bench latency` times when not told otherwise
+pub const DEFAULT_PRESSES: u32 = 200;
+
+/// Longest the stdout consumer may take to catch up with half a queue
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize)]
+pub struct Throughput {
+    pub events: u64,
+    pub elapsed_ms: f64,
+    pub events_per_sec: f64,
+    /// Flush strategy the events were written with, as in the capabilities handshake
+    pub flush: &'static str,
+}
+
+#[derive(Serialize)]
+pub struct Latency {
+    /// Presses that came back
+    pub samples: u32,
+    /// Presses that never came back as a `KeyPress`
+    pub lost: u32,
+    pub min_us: u64,
+    pub median_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+/// Emit `events` key events to stdout as fast as the consumer takes them
+pub fn throughput(events: u64) -> Throughput {
+    let key = || Key {
+        key: "KeyA",
+        name: Some("a"),
+        keysym: Some("a"),
+        scancode: None,
+        text: Some("a"),
+        num_lock: None,
+        synthetic: false,
+        hold_ms: None,
+    };
+    let batch = (OUTPUT_QUEUE_CAPACITY / 2) as u64;
+    let start = Instant::now();
+    for sent in 0..events {
+        let kind = if sent % 2 == 0 { EventKind::KeyPress(key()) } else { EventKind::KeyRelease(key()) };
+        stream::emit(&Event::now(kind));
+        if sent % batch == batch - 1 {
+            output::flush(DRAIN_TIMEOUT);
+        }
+    }
+    output::flush(DRAIN_TIMEOUT);
+    let elapsed = start.elapsed().as_secs_f64();
+    Throughput {
+        events,
+        elapsed_ms: elapsed * 1000.0,
+        events_per_sec: events as f64 / elapsed.max(f64::EPSILON),
+        flush: output::flush_strategy().name(),
+    }
+}
+
+/// Time `presses` injected key presses from the virtual keyboard to the event stream
+pub fn latency(presses: u32) -> Result<Latency, String> {
+    let mut samples = platform::latency(presses)?;
+    if samples.is_empty() {
+        return Err("No injected key press came back; is capture from /dev/input allowed?".to_string());
+    }
+    samples.sort_unstable();
+    let percentile = |percent: usize| samples[(samples.len() - 1) * percent / 100];
+    Ok(Latency {
+        samples: samples.len() as u32,
+        lost: presses - samples.len() as u32,
+        min_us: samples[0],
+        median_us: percentile(50),
+        p99_us: percentile(99),
+        max_us: samples[samples.len() - 1],
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use enigo::Key;
+
+    use crate::loopback::Loopback;
+
+    /// A press not back after this long is counted as lost
+    const PRESS_TIMEOUT: Duration = Duration::from_secs(1);
+    /// Between presses, so each one is timed on a quiet listener
+    const PAUSE: Duration = Duration::from_millis(5);
+
+    pub fn latency(presses: u32) -> Result<Vec<u64>, String> {
+        let mut loopback = Loopback::start()?;
+        let mut samples = Vec::with_capacity(presses as usize);
+        for _ in 0..presses {
+            let sent = Instant::now();
+            loopback.tap(Key::F20)?;
+            let deadline = sent + PRESS_TIMEOUT;
+            while let Some((arrived, event)) = loopback.next(deadline.saturating_duration_since(Instant::now())) {
+                if event["event_type"] == "KeyPress" && event["key"] == "F20" {
+                    samples.push(arrived.saturating_duration_since(sent).as_micros() as u64);
+                    break;
+                }
+            }
+            thread::sleep(PAUSE);
+            // The release, or a press that came back too late
+            loopback.discard();
+        }
+        Ok(samples)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    pub fn latency(_presses: u32) -> Result<Vec<u64>, String> {
+        Err("bench latency injects through uinput and is only available on Linux".to_string())
+    }
+}
@@ -0,0 +1,428 @@
+//! The system clipboard, as the clipboard backend uses it to paste text, and
+//! `clipboard set [--primary]`.
+//!
+//! On Linux the primary selection (whatever was last selected, pasted with a
+//! middle click) is available alongside the clipboard, and is the only paste
+//! path some terminal emulators offer; `write --paste --primary` puts text
+//! there and middle-clicks, so it lands at the pointer rather than the caret.
+//!
+//! Pasting replaces whatever the user had copied, so the backend takes a
+//! [`Snapshot`] first and restores it once the target app has read the
+//! pasted text. If the clipboard no longer holds that text by then, another
+//! app (or the user) copied something in between; restoring would clobber
+//! it, so the backend leaves it and emits `ClipboardConflict{formats}` naming
+//! the formats of the snapshot it dropped.
+//!
+//! Snapshots keep more than text: every format on macOS and Windows (images,
+//! HTML, rich text, file lists), read and written through NSPasteboard and
+//! the Win32 clipboard. On Linux they go through wl-clipboard, xclip or xsel,
+//! which offer a single format when restoring, so the richest one held is
+//! kept: PNG images, then HTML, then plain text.
+
+/// Which of the selections to use
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Selection {
+    #[default]
+    Clipboard,
+    /// The X11/Wayland primary selection (Linux)
+    Primary,
+}
+
+impl Selection {
+    /// `self`, or an error where it doesn't exist
+    fn check(self) -> Result<Selection, String> {
+        if self == Selection::Primary && !cfg!(target_os = "linux") {
+            return Err("The primary selection exists only on Linux".to_string());
+        }
+        Ok(self)
+    }
+}
+
+/// Put `text` on `selection`
+pub fn set_text(selection: Selection, text: &str) -> Result<(), String> {
+    platform::set_text(selection.check()?, text)
+}
+
+/// The text on `selection`, None when it holds none
+pub fn text(selection: Selection) -> Result<Option<String>, String> {
+    platform::text(selection.check()?)
+}
+
+/// Contents of a selection saved to be put back later
+pub struct Snapshot {
+    selection: Selection,
+    formats: Vec<platform::Format>,
+}
+
+impl Snapshot {
+    pub fn take(selection: Selection) -> Result<Snapshot, String> {
+        Ok(Snapshot {
+            selection,
+            formats: platform::save(selection.check()?)?,
+        })
+    }
+
+    /// Put the saved contents back, emptying the selection if it was empty
+    pub fn restore(&self) -> Result<(), String> {
+        platform::restore(self.selection, &self.formats)
+    }
+
+    /// Names of the saved formats, as the platform calls them
+    pub fn format_names(&self) -> Vec<String> {
+        self.formats.iter().map(|format| format.name.clone()).collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::{Command, Stdio};
+
+    use super::Selection;
+    use crate::backend::run_with_input;
+
+    /// Formats worth keeping, richest first
+    const KEPT: [&str; 5] = ["image/png", "text/html", "text/plain;charset=utf-8", "UTF8_STRING", "text/plain"];
+
+    pub struct Format {
+        pub name: String,
+        data: Vec<u8>,
+    }
+
+    fn wayland() -> bool {
+        std::env::var_os("WAYLAND_DISPLAY").is_some()
+    }
+
+    /// stdout of `program`; None when it fails, as it does for an empty
+    /// clipboard or a format the clipboard lacks
+    fn output(program: &str, args: &[&str]) -> Result<Option<Vec<u8>>, String> {
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| format!("{} unavailable: {}", program, e))?;
+        Ok(output.status.success().then_some(output.stdout))
+    }
+
+    /// `selection` as wl-clipboard, xclip and xsel name it
+    fn args(selection: Selection) -> (&'static [&'static str], [&'static str; 2], &'static str) {
+        match selection {
+            Selection::Clipboard => (&[], ["-selection", "clipboard"], "--clipboard"),
+            Selection::Primary => (&["--primary"], ["-selection", "primary"], "--primary"),
+        }
+    }
+
+    /// Read with wl-paste under Wayland, else (or when it is missing) with xclip
+    fn paste(selection: Selection, wayland_args: &[&str], xclip_args: &[&str]) -> Result<Option<Vec<u8>>, String> {
+        let (wayland_selection, xclip_selection, _) = args(selection);
+        if wayland() {
+            if let Ok(data) = output("wl-paste", &[wayland_selection, wayland_args].concat()) {
+                return Ok(data);
+            }
+        }
+        output("xclip", &[&xclip_selection, xclip_args].concat())
+    }
+
+    /// Write with wl-copy under Wayland, else (or when it is missing) with xclip
+    fn copy(selection: Selection, wayland_args: &[&str], xclip_args: &[&str], data: &[u8]) -> Result<(), String> {
+        let (wayland_selection, xclip_selection, _) = args(selection);
+        if wayland() && run_with_input("wl-copy", &[wayland_selection, wayland_args].concat(), data).is_ok() {
+            return Ok(());
+        }
+        run_with_input("xclip", &[&xclip_selection, xclip_args].concat(), data)
+    }
+
+    pub fn set_text(selection: Selection, text: &str) -> Result<(), String> {
+        let (_, _, xsel_selection) = args(selection);
+        copy(selection, &[], &[], text.as_bytes())
+            .or_else(|_| run_with_input("xsel", &[xsel_selection, "--input"], text))
+    }
+
+    pub fn text(selection: Selection) -> Result<Option<String>, String> {
+        let (_, _, xsel_selection) = args(selection);
+        let data = paste(selection, &["--no-newline", "--type", "text/plain"], &["-o"])
+            .or_else(|_| output("xsel", &[xsel_selection, "--output"]))?;
+        Ok(data.map(|data| String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    pub fn save(selection: Selection) -> Result<Vec<Format>, String> {
+        let (_, _, xsel_selection) = args(selection);
+        let targets = match paste(selection, &["--list-types"], &["-t", "TARGETS", "-o"]) {
+            Ok(targets) => targets.unwrap_or_default(),
+            // xsel reads text only
+            Err(_) => {
+                let text = output("xsel", &[xsel_selection, "--output"])?;
+                return Ok(text
+                    .map(|data| Format {
+                        name: "text/plain".to_string(),
+                        data,
+                    })
+                    .into_iter()
+                    .collect());
+            }
+        };
+        let targets = String::from_utf8_lossy(&targets);
+        let Some(name) = KEPT.into_iter().find(|kept| targets.lines().any(|target| target.trim() == *kept)) else {
+            return Ok(Vec::new());
+        };
+        let data = paste(selection, &["--no-newline", "--type", name], &["-t", name, "-o"])?;
+        Ok(data
+            .map(|data| Format {
+                name: name.to_string(),
+                data,
+            })
+            .into_iter()
+            .collect())
+    }
+
+    pub fn restore(selection: Selection, formats: &[Format]) -> Result<(), String> {
+        let (wayland_selection, xclip_selection, xsel_selection) = args(selection);
+        let Some(format) = formats.first() else {
+            if wayland() && run_with_input("wl-copy", &[wayland_selection, &["--clear"]].concat(), "").is_ok() {
+                return Ok(());
+            }
+            return run_with_input("xsel", &[xsel_selection, "--clear"], "")
+                .or_else(|_| run_with_input("xclip", &xclip_selection, ""));
+        };
+        let name = format.name.as_str();
+        copy(selection, &["--type", name], &["-t", name, "-i"], &format.data).or_else(|e| {
+            if name.starts_with("image/") {
+                return Err(e);
+            }
+            run_with_input("xsel", &[xsel_selection, "--input"], &format.data)
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::{Command, Stdio};
+
+    use super::Selection;
+    use crate::backend::run_with_input;
+
+    /// Every pasteboard type with its data, one `type<TAB>base64` line each
+    const SAVE_SCRIPT: &str = r#"
+        ObjC.import('AppKit');
+        function run() {
+            const types = $.NSPasteboard.generalPasteboard.types;
+            const lines = [];
+            for (let i = 0; !types.isNil() && i < types.count; i++) {
+                const type = types.objectAtIndex(i);
+                const data = $.NSPasteboard.generalPasteboard.dataForType(type);
+                if (!data.isNil()) lines.push(type.js + '\t' + data.base64EncodedStringWithOptions(0).js);
+            }
+            return lines.join('\n');
+        }"#;
+
+    /// Replace the pasteboard with the `type<TAB>base64` lines on stdin
+    const RESTORE_SCRIPT: &str = r#"
+        ObjC.import('AppKit');
+        function run() {
+            const input = $.NSString.alloc.initWithDataEncoding(
+                $.NSFileHandle.fileHandleWithStandardInput.readDataToEndOfFile, $.NSUTF8StringEncoding).js;
+            const board = $.NSPasteboard.generalPasteboard;
+            board.clearContents;
+            for (const line of input.split('\n').filter(line => line)) {
+                const [type, base64] = line.split('\t');
+                board.setDataForType($.NSData.alloc.initWithBase64EncodedStringOptions(base64, 0), type);
+            }
+        }"#;
+
+    pub struct Format {
+        pub name: String,
+        /// Base64, as the scripts pass it
+        data: String,
+    }
+
+    pub fn set_text(_selection: Selection, text: &str) -> Result<(), String> {
+        run_with_input("pbcopy", &[], text)
+    }
+
+    pub fn text(_selection: Selection) -> Result<Option<String>, String> {
+        let output = Command::new("pbpaste")
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("pbpaste unavailable: {}", e))?;
+        Ok(output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned()))
+    }
+
+    pub fn save(_selection: Selection) -> Result<Vec<Format>, String> {
+        let output = Command::new("osascript")
+            .args(["-l", "JavaScript", "-e", SAVE_SCRIPT])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| format!("osascript unavailable: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("cannot read the pasteboard: osascript exited with {}", output.status));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(name, data)| Format {
+                name: name.to_string(),
+                data: data.to_string(),
+            })
+            .collect())
+    }
+
+    pub fn restore(_selection: Selection, formats: &[Format]) -> Result<(), String> {
+        let lines: String = formats.iter().map(|format| format!("{}\t{}\n", format.name, format.data)).collect();
+        run_with_input("osascript", &["-l", "JavaScript", "-e", RESTORE_SCRIPT], lines)
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ptr;
+    use std::thread;
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::GlobalFree;
+    use windows_sys::Win32::System::DataExchange::{
+        CloseClipboard, EmptyClipboard, EnumClipboardFormats, GetClipboardData, GetClipboardFormatNameW,
+        OpenClipboard, SetClipboardData,
+    };
+    use windows_sys::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE};
+    use windows_sys::Win32::System::Ole::{
+        CF_BITMAP, CF_DIB, CF_ENHMETAFILE, CF_HDROP, CF_METAFILEPICT, CF_OWNERDISPLAY, CF_PALETTE, CF_UNICODETEXT,
+    };
+
+    use super::Selection;
+    use crate::backend::run_with_input;
+
+    /// Formats whose data is a GDI handle rather than memory; Windows
+    /// synthesizes bitmaps from `CF_DIB`, which is kept
+    const HANDLE_FORMATS: [u16; 5] = [CF_BITMAP, CF_ENHMETAFILE, CF_METAFILEPICT, CF_PALETTE, CF_OWNERDISPLAY];
+
+    /// Another app may hold the clipboard open for a moment
+    const OPEN_ATTEMPTS: u32 = 10;
+    const OPEN_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+    pub struct Format {
+        pub name: String,
+        id: u32,
+        data: Vec<u8>,
+    }
+
+    /// The clipboard, held open until dropped
+    struct Open;
+
+    impl Open {
+        fn clipboard() -> Result<Open, String> {
+            for _ in 0..OPEN_ATTEMPTS {
+                if unsafe { OpenClipboard(ptr::null_mut()) } != 0 {
+                    return Ok(Open);
+                }
+                thread::sleep(OPEN_RETRY_DELAY);
+            }
+            Err("the clipboard is held open by another app".to_string())
+        }
+
+        /// A copy of the data in format `id`
+        fn data(&self, id: u32) -> Option<Vec<u8>> {
+            unsafe {
+                let handle = GetClipboardData(id);
+                if handle.is_null() {
+                    return None;
+                }
+                let bytes = GlobalLock(handle).cast::<u8>();
+                if bytes.is_null() {
+                    return None;
+                }
+                let data = std::slice::from_raw_parts(bytes, GlobalSize(handle)).to_vec();
+                GlobalUnlock(handle);
+                Some(data)
+            }
+        }
+    }
+
+    impl Drop for Open {
+        fn drop(&mut self) {
+            unsafe { CloseClipboard() };
+        }
+    }
+
+    fn format_name(id: u32) -> String {
+        match id as u16 {
+            CF_UNICODETEXT => return "CF_UNICODETEXT".to_string(),
+            CF_DIB => return "CF_DIB".to_string(),
+            CF_HDROP => return "CF_HDROP".to_string(),
+            _ => {}
+        }
+        let mut name = [0u16; 256];
+        let len = unsafe { GetClipboardFormatNameW(id, name.as_mut_ptr(), name.len() as i32) };
+        if len > 0 {
+            String::from_utf16_lossy(&name[..len as usize])
+        } else {
+            format!("format {}", id)
+        }
+    }
+
+    pub fn set_text(_selection: Selection, text: &str) -> Result<(), String> {
+        run_with_input(
+            "powershell",
+            &[
+                "-NoProfile",
+                "-Command",
+                "[Console]::InputEncoding = [Text.Encoding]::UTF8; Set-Clipboard -Value ([Console]::In.ReadToEnd())",
+            ],
+            text,
+        )
+    }
+
+    pub fn text(_selection: Selection) -> Result<Option<String>, String> {
+        let open = Open::clipboard()?;
+        Ok(open.data(u32::from(CF_UNICODETEXT)).map(|data| {
+            let wide: Vec<u16> = data.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+            let len = wide.iter().position(|&unit| unit == 0).unwrap_or(wide.len());
+            String::from_utf16_lossy(&wide[..len])
+        }))
+    }
+
+    pub fn save(_selection: Selection) -> Result<Vec<Format>, String> {
+        let open = Open::clipboard()?;
+        let mut formats = Vec::new();
+        let mut id = 0;
+        loop {
+            id = unsafe { EnumClipboardFormats(id) };
+            if id == 0 {
+                return Ok(formats);
+            }
+            if HANDLE_FORMATS.contains(&(id as u16)) {
+                continue;
+            }
+            if let Some(data) = open.data(id) {
+                formats.push(Format {
+                    name: format_name(id),
+                    id,
+                    data,
+                });
+            }
+        }
+    }
+
+    pub fn restore(_selection: Selection, formats: &[Format]) -> Result<(), String> {
+        let _open = Open::clipboard()?;
+        if unsafe { EmptyClipboard() } == 0 {
+            return Err("cannot empty the clipboard".to_string());
+        }
+        for format in formats {
+            unsafe {
+                let handle = GlobalAlloc(GMEM_MOVEABLE, format.data.len());
+                if handle.is_null() {
+                    return Err(format!("out of memory restoring {}", format.name));
+                }
+                let bytes = GlobalLock(handle).cast::<u8>();
+                ptr::copy_nonoverlapping(format.data.as_ptr(), bytes, format.data.len());
+                GlobalUnlock(handle);
+                // On success the clipboard owns the memory
+                if SetClipboardData(format.id, handle).is_null() {
+                    GlobalFree(handle);
+                    return Err(format!("cannot restore {}", format.name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
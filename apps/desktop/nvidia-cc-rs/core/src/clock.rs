@@ -0,0 +1,105 @@
+//! Clock metadata for correlating helper timestamps with other processes.
+//!
+//! Every event carries `mono_us`, microseconds on a monotonic clock since the
+//! helper started. Wall time can jump (NTP, manual changes), so a `ClockSync`
+//! event is emitted at startup and periodically, mapping the current monotonic
+//! reading to wall time. The desktop app uses these pairs to line up helper
+//! events with its own logs and audio recording timestamps.
+
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+/// How often a `ClockSync` event is emitted while listening
+pub const CLOCK_SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+struct Boot {
+    instant: Instant,
+    wall: SystemTime,
+}
+
+fn boot() -> &'static Boot {
+    static BOOT: OnceLock<Boot> = OnceLock::new();
+    BOOT.get_or_init(|| Boot {
+        instant: Instant::now(),
+        wall: SystemTime::now(),
+    })
+}
+
+/// Pin the helper's boot reference; call as early as possible in `main`
+pub fn init() {
+    boot();
+}
+
+/// Microseconds elapsed on the monotonic clock since the helper started
+pub fn monotonic_us() -> u64 {
+    boot().instant.elapsed().as_micros() as u64
+}
+
+/// Milliseconds since the Unix epoch
+pub fn epoch_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// Where `time` falls on the clock `mono_us` counts, taking the wall clock as steady meanwhile
+pub fn monotonic_us_at(time: SystemTime) -> u64 {
+    let ago = SystemTime::now().duration_since(time).unwrap_or_default();
+    monotonic_us().saturating_sub(ago.as_micros() as u64)
+}
+
+/// `2026-10-14T09:30:00.125Z`: UTC, with milliseconds
+pub fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Days since the epoch to a proleptic Gregorian date (Howard Hinnant's civil_from_days)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+fn emit_clock_sync() {
+    // Sample both clocks back to back so the mapping is as tight as possible
+    let mono_us = monotonic_us();
+    let wall = SystemTime::now();
+
+    stream::emit(&Event {
+        time: wall,
+        kind: EventKind::ClockSync {
+            boot_epoch_ms: epoch_ms(boot().wall),
+            sample_mono_us: mono_us,
+            wall_epoch_ms: epoch_ms(wall),
+            pid: std::process::id(),
+        },
+    });
+}
+
+/// Emit a `ClockSync` now and then every [`CLOCK_SYNC_INTERVAL`] on a background thread
+pub fn start_clock_sync() {
+    emit_clock_sync();
+    thread::spawn(|| loop {
+        thread::sleep(CLOCK_SYNC_INTERVAL);
+        emit_clock_sync();
+    });
+}
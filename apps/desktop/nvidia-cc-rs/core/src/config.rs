@@ -0,0 +1,497 @@
+//! Configuration file read by every command.
+//!
+//! `nvidia-cc/config.toml` in the platform's config directory
+//! (`$XDG_CONFIG_HOME` or `~/.config` on Linux, `~/Library/Application
+//! Support` on macOS, `%APPDATA%` on Windows), or the file `NVIDIA_CC_CONFIG`
+//! names. A missing file means defaults.
+//!
+//!   [listen]                  # defaults for listen's flags, which still win
+//!   media_keys = true
+//!   suppress_self = true
+//!   pause_when_locked = true
+//!   all_sessions = false      # see --all-sessions
+//!   any_keys = false          # see --any-keys
+//!   gamepads = true           # see --gamepads
+//!   g_keys = true             # see --g-keys
+//!   dbus = true               # see --dbus (Linux)
+//!   privacy = "allowlist"     # off, allowlist or hash (see --privacy)
+//!   time_format = "epoch-ms"  # system-time, epoch-ms, rfc3339 or monotonic
+//!   raw_scancodes = true      # add each key's numeric code
+//!   flush_interval_ms = 5
+//!   output = "pretty"         # json, csv, pretty or msgpack (see --output)
+//!   max_event_rate = 500      # key events emitted per second at most
+//!   idle_after_secs = 300     # see --idle-after
+//!   record_to = "/tmp/nvidia-cc-events.ndjson"  # see --record-to
+//!   record_last = 1000        # see --record-last
+//!   metrics_addr = "127.0.0.1:9836"  # see --metrics-addr
+//!   ydotool_socket = "/tmp/.ydotool_socket"  # see --ydotool-socket (Linux)
+//!   input_broker = "/run/nvidia-cc/input-broker.sock"  # see --input-broker (Linux)
+//!   sandbox = true            # see --sandbox (Linux)
+//!   driver_guidance = true    # see --driver-guidance (Windows)
+//!   socket = "/run/user/1000/nvidia-cc.sock"  # also hotkeys, hotstrings, http, http_token
+//!   tcp = "0.0.0.0:9837"      # see --tcp; also tcp_token, tls_cert, tls_key
+//!
+//!   [[hotkey]]                # as in a --hotkeys file, used unless --hotkeys is given
+//!   id = "dictate"
+//!   keys = "ControlLeft+Space"
+//!
+//!   [[hotstring]]             # as in a --hotstrings file, used unless --hotstrings is given
+//!   id = "signature"
+//!   trigger = ";sig"
+//!   expansion = "Best regards"
+//!
+//!   [devices]
+//!   ignore = ["(?i)yubikey"]  # name or path patterns of devices not to open (Linux)
+//!   paths = ["/dev/input/by-id/usb-pedal-event-kbd"]  # open only these, as with --device
+//!
+//!   [remap]                   # name a key is emitted and matched as
+//!   CapsLock = "Hyper"
+//!   KEY_PROG1 = "Assistant"   # evdev names work too (Linux)
+//!
+//!   [[device_remap]]          # only for devices whose name or path matches (Linux)
+//!   device = "(?i)magic keyboard"
+//!   keys = { MetaLeft = "Alt", Alt = "MetaLeft" }
+//!
+//!   [injection]
+//!   backends = ["uinput", "xdotool"]  # backends to try, in order (default: all but ax; --inject-backend overrides)
+//!   ime_safe = true           # write behaves as with --ime-safe
+//!
+//!   [log]
+//!   file = "/tmp/nvidia-cc.log"  # append stderr here
+//!
+//! Every key can also be set with an environment variable, for packagers and
+//! wrappers that can't change the spawn arguments. The precedence is command
+//! line flag, then environment variable, then config file, then default.
+//!
+//!   NVIDIA_CC_REALTIME, NVIDIA_CC_SUPPRESS_SELF,     [listen] flags: 1/true/yes/on
+//!   NVIDIA_CC_MEDIA_KEYS, NVIDIA_CC_LEGACY_FORMAT,   or 0/false/no/off
+//!   NVIDIA_CC_PAUSE_WHEN_LOCKED, NVIDIA_CC_ALL_SESSIONS,
+//!   NVIDIA_CC_RAW_SCANCODES, NVIDIA_CC_ANY_KEYS,
+//!   NVIDIA_CC_GAMEPADS, NVIDIA_CC_G_KEYS,
+//!   NVIDIA_CC_DBUS, NVIDIA_CC_SANDBOX
+//!   NVIDIA_CC_FLUSH_INTERVAL_MS, NVIDIA_CC_SOCKET,   [listen] values
+//!   NVIDIA_CC_MAX_EVENT_RATE, NVIDIA_CC_RECORD_TO,
+//!   NVIDIA_CC_RECORD_LAST, NVIDIA_CC_METRICS_ADDR,
+//!   NVIDIA_CC_HOTKEYS, NVIDIA_CC_HOTSTRINGS,
+//!   NVIDIA_CC_HTTP, NVIDIA_CC_HTTP_TOKEN,
+//!   NVIDIA_CC_TCP, NVIDIA_CC_TCP_TOKEN,
+//!   NVIDIA_CC_TLS_CERT, NVIDIA_CC_TLS_KEY,
+//!   NVIDIA_CC_PRIVACY, NVIDIA_CC_TIME_FORMAT,
+//!   NVIDIA_CC_OUTPUT, NVIDIA_CC_IDLE_AFTER_SECS,
+//!   NVIDIA_CC_YDOTOOL_SOCKET, NVIDIA_CC_INPUT_BROKER
+//!   NVIDIA_CC_IGNORE_DEVICES                         [devices] ignore, comma-separated
+//!   NVIDIA_CC_DEVICES                                [devices] paths, comma-separated
+//!   NVIDIA_CC_BACKENDS                               [injection] backends, comma-separated
+//!   NVIDIA_CC_IME_SAFE                               [injection] ime_safe
+//!   NVIDIA_CC_LOG_FILE                               [log] file
+//!
+//! `--parent-pid` has no variable: the app's children would inherit it. An
+//! empty variable counts as unset.
+//!
+//! A running listener re-reads the file on SIGHUP or the stdin command
+//! `config reload`, along with the `--hotkeys`/`--hotstrings` files it was
+//! started with. Hotkeys, hotstrings, remaps and injection options change in place and
+//! `ConfigReloaded` is emitted; the other sections only apply at startup. A
+//! file that fails to load leaves everything as it was and emits an
+//! `Error{error: "ConfigInvalid"}` instead.
+
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::backend;
+use crate::control;
+use crate::event::{self, Event, EventKind};
+use crate::hotkeys;
+use crate::hotstrings;
+use crate::inject;
+use crate::output;
+use crate::privacy;
+use crate::remap;
+use crate::stream;
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Config {
+    pub listen: Listen,
+    hotkey: Vec<hotkeys::Definition>,
+    hotstring: Vec<hotstrings::Definition>,
+    devices: Devices,
+    remap: BTreeMap<String, String>,
+    device_remap: Vec<remap::DeviceRemap>,
+    injection: Injection,
+    log: Log,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+pub struct Listen {
+    pub realtime: bool,
+    pub suppress_self: bool,
+    pub media_keys: bool,
+    pub legacy_format: bool,
+    pub pause_when_locked: bool,
+    pub all_sessions: bool,
+    pub any_keys: bool,
+    pub gamepads: bool,
+    pub g_keys: bool,
+    pub dbus: bool,
+    pub privacy: privacy::Mode,
+    pub time_format: event::TimeFormat,
+    pub raw_scancodes: bool,
+    pub flush_interval_ms: Option<u64>,
+    pub output: output::Format,
+    pub max_event_rate: Option<u32>,
+    pub idle_after_secs: Option<u64>,
+    pub record_to: Option<PathBuf>,
+    pub record_last: Option<usize>,
+    pub socket: Option<PathBuf>,
+    pub ydotool_socket: Option<PathBuf>,
+    pub input_broker: Option<PathBuf>,
+    pub sandbox: bool,
+    pub driver_guidance: bool,
+    pub hotkeys: Option<PathBuf>,
+    pub hotstrings: Option<PathBuf>,
+    pub http: Option<String>,
+    pub http_token: Option<String>,
+    pub metrics_addr: Option<String>,
+    pub tcp: Option<String>,
+    pub tcp_token: Option<String>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct Devices {
+    ignore: Vec<String>,
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct Injection {
+    backends: Vec<String>,
+    ime_safe: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields, default)]
+struct Log {
+    file: Option<PathBuf>,
+}
+
+impl Config {
+    /// Patterns of devices the listener leaves alone
+    pub fn ignored_devices(&self) -> Vec<Regex> {
+        // Checked by `load`
+        self.devices.ignore.iter().filter_map(|pattern| Regex::new(pattern).ok()).collect()
+    }
+
+    /// Devices the listener opens instead of looking for keyboards; empty to autodetect
+    pub fn device_paths(&self) -> Vec<PathBuf> {
+        self.devices.paths.clone()
+    }
+}
+
+/// Where the config file is looked for
+pub fn path() -> Option<PathBuf> {
+    if let Some(path) = var("NVIDIA_CC_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    dirs::config_dir().map(|dir| dir.join("nvidia-cc").join("config.toml"))
+}
+
+/// Read the config file, or the defaults when there is none, with the environment on top
+pub fn load() -> Result<Config, String> {
+    let mut config = read_file()?;
+    apply_env(&mut config)?;
+    Ok(config)
+}
+
+fn read_file() -> Result<Config, String> {
+    let Some(path) = path() else {
+        return Ok(Config::default());
+    };
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e)),
+    };
+    let config: Config = toml::from_str(&source).map_err(|e| format!("{}: {}", path.display(), e))?;
+    for pattern in &config.devices.ignore {
+        check_device_pattern(pattern).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    for name in &config.injection.backends {
+        check_backend(name).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    remap::compile(&config.remap, &config.device_remap).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(config)
+}
+
+/// Override the file's settings with the `NVIDIA_CC_*` variables that are set
+fn apply_env(config: &mut Config) -> Result<(), String> {
+    let listen = &mut config.listen;
+    for (name, flag) in [
+        ("NVIDIA_CC_REALTIME", &mut listen.realtime),
+        ("NVIDIA_CC_SUPPRESS_SELF", &mut listen.suppress_self),
+        ("NVIDIA_CC_MEDIA_KEYS", &mut listen.media_keys),
+        ("NVIDIA_CC_LEGACY_FORMAT", &mut listen.legacy_format),
+        ("NVIDIA_CC_PAUSE_WHEN_LOCKED", &mut listen.pause_when_locked),
+        ("NVIDIA_CC_ALL_SESSIONS", &mut listen.all_sessions),
+        ("NVIDIA_CC_RAW_SCANCODES", &mut listen.raw_scancodes),
+        ("NVIDIA_CC_ANY_KEYS", &mut listen.any_keys),
+        ("NVIDIA_CC_GAMEPADS", &mut listen.gamepads),
+        ("NVIDIA_CC_G_KEYS", &mut listen.g_keys),
+        ("NVIDIA_CC_DBUS", &mut listen.dbus),
+        ("NVIDIA_CC_SANDBOX", &mut listen.sandbox),
+        ("NVIDIA_CC_DRIVER_GUIDANCE", &mut listen.driver_guidance),
+        ("NVIDIA_CC_IME_SAFE", &mut config.injection.ime_safe),
+    ] {
+        if let Some(value) = var(name) {
+            *flag = parse_flag(&value).ok_or_else(|| format!("{}: expected 1 or 0, got {:?}", name, value))?;
+        }
+    }
+    if let Some(value) = var("NVIDIA_CC_FLUSH_INTERVAL_MS") {
+        let interval_ms = value
+            .parse()
+            .map_err(|_| format!("NVIDIA_CC_FLUSH_INTERVAL_MS: invalid value {:?}", value))?;
+        listen.flush_interval_ms = Some(interval_ms);
+    }
+    if let Some(value) = var("NVIDIA_CC_MAX_EVENT_RATE") {
+        let rate = value
+            .parse()
+            .map_err(|_| format!("NVIDIA_CC_MAX_EVENT_RATE: invalid value {:?}", value))?;
+        listen.max_event_rate = Some(rate);
+    }
+    if let Some(value) = var("NVIDIA_CC_IDLE_AFTER_SECS") {
+        let secs = value
+            .parse()
+            .map_err(|_| format!("NVIDIA_CC_IDLE_AFTER_SECS: invalid value {:?}", value))?;
+        listen.idle_after_secs = Some(secs);
+    }
+    if let Some(value) = var("NVIDIA_CC_RECORD_LAST") {
+        let count = value
+            .parse()
+            .map_err(|_| format!("NVIDIA_CC_RECORD_LAST: invalid value {:?}", value))?;
+        listen.record_last = Some(count);
+    }
+    for (name, path) in [
+        ("NVIDIA_CC_SOCKET", &mut listen.socket),
+        ("NVIDIA_CC_YDOTOOL_SOCKET", &mut listen.ydotool_socket),
+        ("NVIDIA_CC_INPUT_BROKER", &mut listen.input_broker),
+        ("NVIDIA_CC_HOTKEYS", &mut listen.hotkeys),
+        ("NVIDIA_CC_HOTSTRINGS", &mut listen.hotstrings),
+        ("NVIDIA_CC_RECORD_TO", &mut listen.record_to),
+        ("NVIDIA_CC_TLS_CERT", &mut listen.tls_cert),
+        ("NVIDIA_CC_TLS_KEY", &mut listen.tls_key),
+        ("NVIDIA_CC_LOG_FILE", &mut config.log.file),
+    ] {
+        if let Some(value) = var(name) {
+            *path = Some(PathBuf::from(value));
+        }
+    }
+    if let Some(value) = var("NVIDIA_CC_PRIVACY") {
+        listen.privacy = privacy::Mode::parse(&value).map_err(|e| format!("NVIDIA_CC_PRIVACY: {}", e))?;
+    }
+    if let Some(value) = var("NVIDIA_CC_OUTPUT") {
+        listen.output = output::Format::parse(&value).map_err(|e| format!("NVIDIA_CC_OUTPUT: {}", e))?;
+    }
+    if let Some(value) = var("NVIDIA_CC_TIME_FORMAT") {
+        let format = event::TimeFormat::parse(&value).map_err(|e| format!("NVIDIA_CC_TIME_FORMAT: {}", e))?;
+        listen.time_format = format;
+    }
+    if let Some(value) = var("NVIDIA_CC_HTTP") {
+        listen.http = Some(value);
+    }
+    if let Some(value) = var("NVIDIA_CC_HTTP_TOKEN") {
+        listen.http_token = Some(value);
+    }
+    if let Some(value) = var("NVIDIA_CC_METRICS_ADDR") {
+        listen.metrics_addr = Some(value);
+    }
+    if let Some(value) = var("NVIDIA_CC_TCP") {
+        listen.tcp = Some(value);
+    }
+    if let Some(value) = var("NVIDIA_CC_TCP_TOKEN") {
+        listen.tcp_token = Some(value);
+    }
+    if let Some(value) = var("NVIDIA_CC_IGNORE_DEVICES") {
+        let patterns = list(&value);
+        for pattern in &patterns {
+            check_device_pattern(pattern).map_err(|e| format!("NVIDIA_CC_IGNORE_DEVICES: {}", e))?;
+        }
+        config.devices.ignore = patterns;
+    }
+    if let Some(value) = var("NVIDIA_CC_DEVICES") {
+        config.devices.paths = list(&value).into_iter().map(PathBuf::from).collect();
+    }
+    if let Some(value) = var("NVIDIA_CC_BACKENDS") {
+        let names = list(&value);
+        for name in &names {
+            check_backend(name).map_err(|e| format!("NVIDIA_CC_BACKENDS: {}", e))?;
+        }
+        config.injection.backends = names;
+    }
+    Ok(())
+}
+
+/// The variable's value, unless it is unset or empty
+fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Comma-separated items, trimmed, without empty ones
+fn list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect()
+}
+
+fn check_device_pattern(pattern: &str) -> Result<(), String> {
+    Regex::new(pattern)
+        .map(|_| ())
+        .map_err(|e| format!("invalid device pattern {:?}: {}", pattern, e))
+}
+
+fn check_backend(name: &str) -> Result<(), String> {
+    if backend::NAMES.contains(&name) {
+        return Ok(());
+    }
+    Err(format!("unknown backend {:?} (expected {})", name, backend::NAMES.join(", ")))
+}
+
+/// Settings every command uses: the log file, key remaps and injection options
+pub fn apply_global(config: &Config) -> Result<(), String> {
+    if let Some(file) = &config.log.file {
+        redirect_stderr(file)?;
+    }
+    apply_remaps(config);
+    apply_injection(config);
+    Ok(())
+}
+
+/// Install the config's key remaps
+pub fn apply_remaps(config: &Config) {
+    // Checked by `load`
+    if let Ok(table) = remap::compile(&config.remap, &config.device_remap) {
+        remap::install(table);
+    }
+}
+
+fn apply_injection(config: &Config) {
+    backend::set_order(config.injection.backends.clone());
+    inject::set_ime_safe_by_default(config.injection.ime_safe);
+}
+
+/// Definition files named on listen's command line, used instead of the config's own
+#[derive(Clone, Default)]
+pub struct Sources {
+    pub hotkeys_path: Option<PathBuf>,
+    pub hotstrings_path: Option<PathBuf>,
+}
+
+/// Files a running listener re-reads on reload
+static SOURCES: Mutex<Option<Sources>> = Mutex::new(None);
+
+/// Install the config's hotkeys and hotstrings (or those in `sources`), returning how many of each
+///
+/// Everything is compiled before anything is installed, so a bad definition changes nothing.
+pub fn apply_definitions(config: &mut Config, sources: &Sources) -> Result<(usize, usize), String> {
+    let hotkeys = match &sources.hotkeys_path {
+        Some(path) => hotkeys::read(path)?,
+        None => hotkeys::compile(std::mem::take(&mut config.hotkey)).map_err(|e| format!("Config [[hotkey]]: {}", e))?,
+    };
+    let hotstrings = match &sources.hotstrings_path {
+        Some(path) => hotstrings::read(path)?,
+        None => hotstrings::compile(std::mem::take(&mut config.hotstring))
+            .map_err(|e| format!("Config [[hotstring]]: {}", e))?,
+    };
+    Ok((hotkeys::install(hotkeys), hotstrings::install(hotstrings)))
+}
+
+/// Remember `sources` for later reloads
+pub fn enable_reload(sources: Sources) {
+    *SOURCES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(sources);
+}
+
+/// Re-read the config file and the definition files, as asked by `source` (e.g. `SIGHUP`)
+pub fn reload(source: &str) {
+    let Some(sources) = SOURCES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone() else {
+        return;
+    };
+    let result = load().and_then(|mut config| {
+        let counts = apply_definitions(&mut config, &sources)?;
+        apply_remaps(&config);
+        apply_injection(&config);
+        Ok(counts)
+    });
+    match result {
+        Ok((hotkeys, hotstrings)) => {
+            eprintln!("Config reloaded via {}: {} hotkey(s), {} hotstring(s)", source, hotkeys, hotstrings);
+            stream::emit(&Event::now(EventKind::ConfigReloaded {
+                source: source.to_string(),
+                hotkeys,
+                hotstrings,
+            }));
+        }
+        Err(message) => control::emit_error("ConfigInvalid", format!("Config not reloaded: {}", message)),
+    }
+}
+
+fn redirect_stderr(file: &Path) -> Result<(), String> {
+    let log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file)
+        .map_err(|e| format!("Cannot open log file {}: {}", file.display(), e))?;
+    platform::redirect_stderr(log)
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+
+    pub fn redirect_stderr(log: File) -> Result<(), String> {
+        // dup2 keeps its own reference, so `log` can close
+        if unsafe { libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO) } < 0 {
+            return Err(format!("Cannot redirect stderr: {}", std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::fs::File;
+    use std::os::windows::io::IntoRawHandle;
+
+    use windows_sys::Win32::System::Console::{SetStdHandle, STD_ERROR_HANDLE};
+
+    pub fn redirect_stderr(log: File) -> Result<(), String> {
+        // The standard handle owns the file from here on; std looks it up on every write
+        if unsafe { SetStdHandle(STD_ERROR_HANDLE, log.into_raw_handle()) } == 0 {
+            return Err(format!("Cannot redirect stderr: {}", std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod platform {
+    use std::fs::File;
+
+    pub fn redirect_stderr(_log: File) -> Result<(), String> {
+        Err("[log] file is not supported on this platform".to_string())
+    }
+}
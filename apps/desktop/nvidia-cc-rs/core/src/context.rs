@@ -0,0 +1,514 @@
+//! The focused text field's contents around the caret, for `context` and the
+//! `context` stdin command.
+//!
+//! The speech pipeline formats a transcript by what it lands next to:
+//! whether it starts a sentence, continues a word, or replaces a selection.
+//! The text before and after the caret is cut to `chars` characters on
+//! either side; `caret` and `length` still count the whole field. Password
+//! fields are refused rather than read.
+//!
+//! Sources are the accessibility APIs: AT-SPI on Linux (through python3's
+//! `gi` bindings, so GTK, Qt, Firefox and Chromium with accessibility on),
+//! UI Automation's text and value patterns on Windows (through PowerShell),
+//! and the focused element's AX attributes on macOS, which needs the
+//! Accessibility permission injection already does.
+//!
+//! The same APIs back the `ax` write backend ([`insert`]), which puts text
+//! into the field directly instead of typing it: AT-SPI's EditableText,
+//! UI Automation's value pattern (the field's whole value is set, so the
+//! caret ends up wherever the control puts it) and AX's selected text.
+
+use serde::{Deserialize, Serialize};
+
+/// Characters kept on either side of the caret by default
+pub const DEFAULT_CHARS: usize = 2000;
+
+/// Focused field as a platform reports it: all of its text, split at the selection
+#[derive(Deserialize)]
+struct Field {
+    /// Name of the app the field belongs to
+    app: String,
+    role: String,
+    /// Password and other secure fields, whose text is left unread
+    #[serde(default)]
+    secure: bool,
+    before: String,
+    #[serde(default)]
+    selection: String,
+    #[serde(default)]
+    after: String,
+    source: String,
+}
+
+#[derive(Serialize)]
+pub struct Context {
+    pub app: String,
+    /// The toolkit's role name, such as `entry`, `text` or `document`
+    pub role: String,
+    pub before: String,
+    /// Selected text; empty when only the caret is there
+    pub selection: String,
+    pub after: String,
+    /// Characters in the field before the caret, or before the selection
+    pub caret: usize,
+    /// Characters in the field
+    pub length: usize,
+    /// `atspi`, `uia` or `ax`
+    pub source: String,
+}
+
+/// The focused text field's context, with up to `chars` characters either side
+pub fn read(chars: usize) -> Result<Context, String> {
+    let field = platform::focused_field()?.ok_or("No text field has focus")?;
+    if field.secure {
+        return Err("The focused field is a password field".to_string());
+    }
+    let before_chars = field.before.chars().count();
+    let length = before_chars + field.selection.chars().count() + field.after.chars().count();
+    Ok(Context {
+        app: field.app,
+        role: field.role,
+        before: field.before.chars().skip(before_chars.saturating_sub(chars)).collect(),
+        selection: field.selection,
+        after: field.after.chars().take(chars).collect(),
+        caret: before_chars,
+        length,
+        source: field.source,
+    })
+}
+
+/// Whether the accessibility API answers, focused field or not
+pub(crate) fn available() -> Result<(), String> {
+    platform::focused_field().map(|_| ())
+}
+
+/// Replace the focused field's selection, or insert at its caret, with `text`
+pub(crate) fn insert(text: &str) -> Result<(), String> {
+    platform::insert(text)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use super::Field;
+
+    /// Exit status of the scripts for errors about the field rather than AT-SPI
+    const FIELD_ERROR: i32 = 3;
+
+    /// Finds the focused node with a text interface in the active window of
+    /// each app, and where its selection (or caret) is
+    macro_rules! find_field {
+        () => {
+            r#"
+import json, sys
+import gi
+gi.require_version("Atspi", "2.0")
+from gi.repository import Atspi
+
+def fail(message):
+    sys.stderr.write(message)
+    sys.exit(3)
+
+def focused(node, depth=0):
+    if node is None or depth > 60:
+        return None
+    try:
+        states = node.get_state_set()
+        if states.contains(Atspi.StateType.FOCUSED) and "Text" in node.get_interfaces():
+            return node
+        if depth > 0 and not states.contains(Atspi.StateType.SHOWING):
+            return None
+        for i in range(node.get_child_count()):
+            found = focused(node.get_child_at_index(i), depth + 1)
+            if found is not None:
+                return found
+    except Exception:
+        pass
+    return None
+
+def active_field():
+    desktop = Atspi.get_desktop(0)
+    for a in range(desktop.get_child_count()):
+        app = desktop.get_child_at_index(a)
+        for w in range(app.get_child_count() if app else 0):
+            window = app.get_child_at_index(w)
+            if window is not None and window.get_state_set().contains(Atspi.StateType.ACTIVE):
+                field = focused(window)
+                if field is not None:
+                    return app, field
+    return None, None
+
+def selection_bounds(field):
+    count = Atspi.Text.get_character_count(field)
+    start = end = Atspi.Text.get_caret_offset(field)
+    if Atspi.Text.get_n_selections(field) > 0:
+        selection = Atspi.Text.get_selection(field, 0)
+        start, end = sorted((selection.start_offset, selection.end_offset))
+    return max(0, min(start, count)), max(0, min(end, count)), count
+
+app, field = active_field()
+"#
+        };
+    }
+
+    const READ_SCRIPT: &str = concat!(
+        find_field!(),
+        r#"
+if field is None:
+    print("null")
+    sys.exit()
+reply = {"app": app.get_name() or "", "role": field.get_role_name() or "", "source": "atspi", "before": ""}
+if field.get_role() == Atspi.Role.PASSWORD_TEXT:
+    print(json.dumps(dict(reply, secure=True)))
+    sys.exit()
+start, end, count = selection_bounds(field)
+print(json.dumps(dict(
+    reply,
+    before=Atspi.Text.get_text(field, 0, start),
+    selection=Atspi.Text.get_text(field, start, end),
+    after=Atspi.Text.get_text(field, end, count),
+)))
+"#
+    );
+
+    /// Replaces the selection, or inserts at the caret, with stdin, leaving the caret after it
+    const INSERT_SCRIPT: &str = concat!(
+        find_field!(),
+        r#"
+if field is None:
+    fail("No text field has focus")
+if "EditableText" not in field.get_interfaces():
+    fail("The focused field isn't editable")
+text = sys.stdin.buffer.read().decode()
+start, end, count = selection_bounds(field)
+if end > start and not Atspi.EditableText.delete_text(field, start, end):
+    fail("The focused field refused to delete the selection")
+# The length is in bytes
+if not Atspi.EditableText.insert_text(field, start, text, len(text.encode())):
+    fail("The focused field refused the text")
+Atspi.Text.set_caret_offset(field, start + len(text))
+"#
+    );
+
+    /// Run `script` with `input` on stdin, returning its output
+    fn python(script: &str, input: &str) -> Result<Vec<u8>, String> {
+        let mut child = Command::new("python3")
+            .args(["-c", script])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("python3 unavailable: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // A script that fails before reading its input says why in its exit status
+            match stdin.write_all(input.as_bytes()) {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(format!("python3 stdin: {}", e)),
+                _ => {}
+            }
+        }
+        let output = child.wait_with_output().map_err(|e| format!("python3: {}", e))?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match output.status.code() {
+            Some(0) => Ok(output.stdout),
+            Some(FIELD_ERROR) => Err(stderr.trim().to_string()),
+            // Usually the bindings missing: python3-gi and gir1.2-atspi-2.0 on Debian
+            _ => Err(format!("AT-SPI unavailable: {}", stderr.trim().lines().last().unwrap_or_default())),
+        }
+    }
+
+    pub fn focused_field() -> Result<Option<Field>, String> {
+        let reply = python(READ_SCRIPT, "")?;
+        serde_json::from_slice(&reply).map_err(|e| format!("unexpected AT-SPI reply: {}", e))
+    }
+
+    pub fn insert(text: &str) -> Result<(), String> {
+        python(INSERT_SCRIPT, text).map(|_| ())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use super::Field;
+
+    /// Exit status of the insert script for errors about the field rather than UI Automation
+    const FIELD_ERROR: i32 = 3;
+
+    /// Text pattern when the control has one, else the value pattern, which has no caret
+    const SCRIPT: &str = r#"
+        [Console]::OutputEncoding = [System.Text.Encoding]::UTF8
+        Add-Type -AssemblyName UIAutomationClient, UIAutomationTypes
+        $element = [System.Windows.Automation.AutomationElement]::FocusedElement
+        if (-not $element) { 'null'; exit }
+        $current = $element.Current
+        $field = @{
+            app = (Get-Process -Id $current.ProcessId -ErrorAction SilentlyContinue).ProcessName + ''
+            role = $current.ControlType.ProgrammaticName -replace '^ControlType\.', ''
+            secure = $current.IsPassword
+            source = 'uia'
+            before = ''
+        }
+        $pattern = $null
+        if ($current.IsPassword) {
+            # Left unread
+        } elseif ($element.TryGetCurrentPattern([System.Windows.Automation.TextPattern]::Pattern, [ref]$pattern)) {
+            $endpoint = [System.Windows.Automation.Text.TextPatternRangeEndpoint]
+            $document = $pattern.DocumentRange
+            $selections = $pattern.GetSelection()
+            if ($selections.Length -gt 0) {
+                $selected = $selections[0]
+            } else {
+                $selected = $document.Clone()
+                $selected.MoveEndpointByRange($endpoint::Start, $document, $endpoint::End)
+            }
+            $head = $document.Clone()
+            $head.MoveEndpointByRange($endpoint::End, $selected, $endpoint::Start)
+            $tail = $document.Clone()
+            $tail.MoveEndpointByRange($endpoint::Start, $selected, $endpoint::End)
+            $field.before = $head.GetText(-1)
+            $field.selection = $selected.GetText(-1)
+            $field.after = $tail.GetText(-1)
+        } elseif ($element.TryGetCurrentPattern([System.Windows.Automation.ValuePattern]::Pattern, [ref]$pattern)) {
+            $field.before = $pattern.Current.Value
+        } else {
+            'null'; exit
+        }
+        $field | ConvertTo-Json -Compress
+    "#;
+
+    pub fn focused_field() -> Result<Option<Field>, String> {
+        let output = Command::new("powershell")
+            .args(["-NoProfile", "-Command", SCRIPT])
+            .output()
+            .map_err(|e| format!("powershell unavailable: {}", e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("UI Automation unavailable: {}", stderr.trim()));
+        }
+        let reply = String::from_utf8_lossy(&output.stdout);
+        serde_json::from_str(reply.trim_start_matches('\u{feff}'))
+            .map_err(|e| format!("unexpected UI Automation reply: {}", e))
+    }
+
+    /// Stdin in place of the selection, or at the caret, through the value pattern
+    const INSERT_SCRIPT: &str = r#"
+        [Console]::InputEncoding = [System.Text.Encoding]::UTF8
+        function Fail($message) { [Console]::Error.Write($message); exit 3 }
+        Add-Type -AssemblyName UIAutomationClient, UIAutomationTypes
+        $text = [Console]::In.ReadToEnd()
+        $element = [System.Windows.Automation.AutomationElement]::FocusedElement
+        if (-not $element) { Fail 'No text field has focus' }
+        $value = $null
+        if (-not $element.TryGetCurrentPattern([System.Windows.Automation.ValuePattern]::Pattern, [ref]$value)) {
+            Fail 'No text field has focus'
+        }
+        if ($value.Current.IsReadOnly) { Fail "The focused field isn't editable" }
+        $before = $value.Current.Value
+        $after = ''
+        $pattern = $null
+        if ($element.TryGetCurrentPattern([System.Windows.Automation.TextPattern]::Pattern, [ref]$pattern)) {
+            $endpoint = [System.Windows.Automation.Text.TextPatternRangeEndpoint]
+            $document = $pattern.DocumentRange
+            $selections = $pattern.GetSelection()
+            if ($selections.Length -gt 0) {
+                $head = $document.Clone()
+                $head.MoveEndpointByRange($endpoint::End, $selections[0], $endpoint::Start)
+                $tail = $document.Clone()
+                $tail.MoveEndpointByRange($endpoint::Start, $selections[0], $endpoint::End)
+                $before = $head.GetText(-1)
+                $after = $tail.GetText(-1)
+            }
+        }
+        $value.SetValue($before + $text + $after)
+    "#;
+
+    pub fn insert(text: &str) -> Result<(), String> {
+        let mut child = Command::new("powershell")
+            .args(["-NoProfile", "-Command", INSERT_SCRIPT])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("powershell unavailable: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            match stdin.write_all(text.as_bytes()) {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(format!("powershell stdin: {}", e)),
+                _ => {}
+            }
+        }
+        let output = child.wait_with_output().map_err(|e| format!("powershell: {}", e))?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match output.status.code() {
+            Some(0) => Ok(()),
+            Some(FIELD_ERROR) => Err(stderr.trim().to_string()),
+            _ => Err(format!("UI Automation unavailable: {}", stderr.trim())),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void, CStr, CString};
+    use std::ptr;
+
+    use super::Field;
+
+    type CFTypeRef = *const c_void;
+    type CFStringRef = *const c_void;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CFRange {
+        location: isize,
+        length: isize,
+    }
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const AX_ERROR_SUCCESS: i32 = 0;
+    const AX_VALUE_CF_RANGE_TYPE: u32 = 4;
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXUIElementCreateSystemWide() -> CFTypeRef;
+        fn AXUIElementCopyAttributeValue(element: CFTypeRef, attribute: CFStringRef, value: *mut CFTypeRef) -> i32;
+        fn AXValueGetValue(value: CFTypeRef, kind: u32, out: *mut c_void) -> u8;
+        fn AXUIElementSetAttributeValue(element: CFTypeRef, attribute: CFStringRef, value: CFTypeRef) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: CFTypeRef);
+        fn CFGetTypeID(cf: CFTypeRef) -> usize;
+        fn CFStringGetTypeID() -> usize;
+        fn CFStringCreateWithCString(allocator: CFTypeRef, string: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFStringCreateWithBytes(
+            allocator: CFTypeRef,
+            bytes: *const u8,
+            length: isize,
+            encoding: u32,
+            external: u8,
+        ) -> CFStringRef;
+        fn CFStringGetLength(string: CFStringRef) -> isize;
+        fn CFStringGetMaximumSizeForEncoding(length: isize, encoding: u32) -> isize;
+        fn CFStringGetCString(string: CFStringRef, buffer: *mut c_char, size: isize, encoding: u32) -> u8;
+    }
+
+    /// An attribute value, released on drop
+    struct Owned(CFTypeRef);
+
+    impl Drop for Owned {
+        fn drop(&mut self) {
+            if !self.0.is_null() {
+                unsafe { CFRelease(self.0) };
+            }
+        }
+    }
+
+    unsafe fn copy_attribute(element: CFTypeRef, name: &str) -> Owned {
+        let name = CString::new(name).expect("attribute names have no NUL");
+        let attribute = CFStringCreateWithCString(ptr::null(), name.as_ptr(), CF_STRING_ENCODING_UTF8);
+        let mut value: CFTypeRef = ptr::null();
+        let error = AXUIElementCopyAttributeValue(element, attribute, &mut value);
+        CFRelease(attribute);
+        Owned(if error == AX_ERROR_SUCCESS { value } else { ptr::null() })
+    }
+
+    unsafe fn string(value: &Owned) -> Option<String> {
+        let value = value.0;
+        if value.is_null() || CFGetTypeID(value) != CFStringGetTypeID() {
+            return None;
+        }
+        let size = CFStringGetMaximumSizeForEncoding(CFStringGetLength(value), CF_STRING_ENCODING_UTF8) + 1;
+        let mut buffer = vec![0 as c_char; size as usize];
+        (CFStringGetCString(value, buffer.as_mut_ptr(), size, CF_STRING_ENCODING_UTF8) != 0)
+            .then(|| CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+    }
+
+    pub fn focused_field() -> Result<Option<Field>, String> {
+        unsafe {
+            let system = Owned(AXUIElementCreateSystemWide());
+            let app = copy_attribute(system.0, "AXFocusedApplication");
+            let element = copy_attribute(system.0, "AXFocusedUIElement");
+            if element.0.is_null() {
+                return Ok(None);
+            }
+            let app_name = if app.0.is_null() { None } else { string(&copy_attribute(app.0, "AXTitle")) };
+            let role = string(&copy_attribute(element.0, "AXRole")).unwrap_or_default();
+            if string(&copy_attribute(element.0, "AXSubrole")).as_deref() == Some("AXSecureTextField") {
+                return Ok(Some(Field {
+                    app: app_name.unwrap_or_default(),
+                    role,
+                    secure: true,
+                    before: String::new(),
+                    selection: String::new(),
+                    after: String::new(),
+                    source: "ax".to_string(),
+                }));
+            }
+            let Some(text) = string(&copy_attribute(element.0, "AXValue")) else {
+                return Ok(None);
+            };
+            // AX ranges count UTF-16 code units
+            let units: Vec<u16> = text.encode_utf16().collect();
+            let mut range = CFRange::default();
+            let selected = copy_attribute(element.0, "AXSelectedTextRange");
+            let has_range = !selected.0.is_null()
+                && AXValueGetValue(selected.0, AX_VALUE_CF_RANGE_TYPE, &mut range as *mut CFRange as *mut c_void) != 0;
+            let start = if has_range { (range.location.max(0) as usize).min(units.len()) } else { units.len() };
+            let end = if has_range { (start + range.length.max(0) as usize).min(units.len()) } else { start };
+            Ok(Some(Field {
+                app: app_name.unwrap_or_default(),
+                role,
+                secure: false,
+                before: String::from_utf16_lossy(&units[..start]),
+                selection: String::from_utf16_lossy(&units[start..end]),
+                after: String::from_utf16_lossy(&units[end..]),
+                source: "ax".to_string(),
+            }))
+        }
+    }
+
+    pub fn insert(text: &str) -> Result<(), String> {
+        unsafe {
+            let system = Owned(AXUIElementCreateSystemWide());
+            let element = copy_attribute(system.0, "AXFocusedUIElement");
+            if element.0.is_null() {
+                return Err("No text field has focus".to_string());
+            }
+            // Setting the selected text replaces the selection, or inserts at the caret
+            let name = CString::new("AXSelectedText").expect("attribute names have no NUL");
+            let attribute = Owned(CFStringCreateWithCString(ptr::null(), name.as_ptr(), CF_STRING_ENCODING_UTF8));
+            let value = Owned(CFStringCreateWithBytes(
+                ptr::null(),
+                text.as_ptr(),
+                text.len() as isize,
+                CF_STRING_ENCODING_UTF8,
+                0,
+            ));
+            if value.0.is_null() {
+                return Err("CFStringCreateWithBytes failed".to_string());
+            }
+            match AXUIElementSetAttributeValue(element.0, attribute.0, value.0) {
+                AX_ERROR_SUCCESS => Ok(()),
+                error => Err(format!("The focused field refused the text (AX error {})", error)),
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    use super::Field;
+
+    pub fn focused_field() -> Result<Option<Field>, String> {
+        Err("Text context isn't available on this platform".to_string())
+    }
+
+    pub fn insert(_text: &str) -> Result<(), String> {
+        Err("Accessibility insertion isn't available on this platform".to_string())
+    }
+}
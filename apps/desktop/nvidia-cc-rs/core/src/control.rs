@@ -0,0 +1,572 @@
+//! Runtime control of a running listener.
+//!
+//! While `listen` runs, the app can send line-based commands on stdin:
+//!   pause   - stop emitting key events (device handles stay open)
+//!   resume  - start emitting key events again; refused while a
+//!             `--pause-when-locked` session is locked
+//!   write [--keys] [--ime-safe | --paste [--primary] | --backend <name>]
+//!             [--window <json string>] <json string> - queue text for injection (the flags are those
+//!             of the `write` command),
+//!             emitting `WriteQueued{position}` (the writes ahead of it), then
+//!             `WriteProgress` and `WriteComplete` (or `WriteCancelled`) on
+//!             the event stream; writes run one at a time, in order
+//!   cancel [<id>] - stop the write in progress after its current chunk, or
+//!             with an id, the write sent with that `--id`, dropping it from
+//!             the queue if it hasn't started. Key combos are never cut
+//!             short, so no modifier is left held
+//!   config reload - re-read the config file (see [`crate::config`])
+//!   hotkey add <json definition> - register a hotkey, as written in a
+//!             `--hotkeys` file, and emit `HotkeyAdded{id}`
+//!   hotkey remove <id> - unregister a hotkey and emit `HotkeyRemoved{id}`
+//!   hotkey list - emit `HotkeyList{hotkeys}` with the active hotkeys
+//!   leds get - emit `LedState{leds}` (see [`crate::leds`])
+//!   leds set <led> on|off - light or darken a keyboard lock LED
+//!   backlight set <percent|on|off> [--color #rrggbb] [--device <name>] -
+//!             set keyboard backlights (see [`crate::backlight`])
+//!   notify {"title", "body", "icon"} - show a desktop notification (see
+//!             [`crate::notify`])
+//!   context [<chars>] - emit `TextContext{context}`: the focused text field's
+//!             text around the caret, `chars` characters either side (see
+//!             [`crate::context`])
+//!   inhibit-sleep start [<reason>] - keep the system from suspending for
+//!             idleness until `inhibit-sleep stop` or the helper exits, and
+//!             emit `SleepInhibited{reason}` (see [`crate::inhibit`])
+//!   inhibit-sleep stop - release it and emit `SleepAllowed`
+//!   idle    - emit `IdleTime{idle_secs, source}`, the time since the last
+//!             input (see [`crate::idle`])
+//!   stats   - emit `Stats{stats}` with the internal counters (see
+//!             [`crate::stats`])
+//!   dump [<path>] - write the events kept with `--record-last` to a file
+//!             and emit `EventsDumped{path, events}` (see [`crate::journal`])
+//!
+//! Under `--sandbox` (Linux), `write`, `notify`, `context`, `inhibit-sleep`,
+//! `backlight set`, `leds set` and `dump` need what the sandbox took away,
+//! and are answered with a `Sandboxed` error instead.
+//!
+//! Any command can be led by `--id <id>`, a token of the app's choosing.
+//! Every event the command results in, acknowledgment, progress, completion
+//! or error alike, then carries it as `request_id`, so the app can tell which
+//! of several queued `write`s failed. Events the command didn't cause, key
+//! events included, never carry one.
+//!
+//! On Unix the same is available through signals: SIGUSR1 pauses, SIGUSR2
+//! resumes and SIGHUP reloads the config. Signals are handled on a dedicated `sigwait` thread rather than in
+//! an async signal handler, so they can emit events like any other code.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::active_window;
+use crate::backlight;
+use crate::clipboard::Selection;
+use crate::config;
+use crate::context;
+use crate::event::{Event, EventKind};
+use crate::hotkeys;
+use crate::hotstrings;
+use crate::idle;
+use crate::inhibit;
+use crate::inject::{self, TextMethod};
+use crate::journal;
+use crate::keys;
+use crate::leds;
+use crate::notify;
+#[cfg(target_os = "linux")]
+use crate::sandbox;
+use crate::session;
+use crate::stats;
+use crate::stream;
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// stdin `write`s, injected one at a time by the thread [`start_write`] spawns
+static WRITES: Mutex<WriteQueue> = Mutex::new(WriteQueue {
+    pending: VecDeque::new(),
+    current: None,
+});
+static WRITE_QUEUED: Condvar = Condvar::new();
+static CANCEL_WRITE: AtomicBool = AtomicBool::new(false);
+
+/// Commands `--sandbox` leaves no way to carry out, with the subcommand when only that one is
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const SANDBOXED: [(&str, Option<&str>); 7] = [
+    ("write", None),
+    ("notify", None),
+    ("context", None),
+    ("inhibit-sleep", None),
+    ("backlight", None),
+    ("leds", Some("set")),
+    ("dump", None),
+];
+
+/// `WriteProgress` is emitted at most this often
+const WRITE_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+thread_local! {
+    /// `--id` of the command this thread is carrying out
+    static REQUEST_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+struct WriteQueue {
+    pending: VecDeque<QueuedWrite>,
+    /// `--id` of the write being injected; `Some(None)` when it was sent without one
+    current: Option<Option<String>>,
+}
+
+struct QueuedWrite {
+    request_id: Option<String>,
+    segments: Vec<keys::Segment>,
+    method: TextMethod,
+    /// Window to focus for the write, as `windows focus` matches it
+    window: Option<String>,
+}
+
+fn writes() -> MutexGuard<'static, WriteQueue> {
+    WRITES.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Run `f` with the `--id` of the command the calling thread is carrying out, if any
+pub fn with_request_id<R>(f: impl FnOnce(Option<&str>) -> R) -> R {
+    REQUEST_ID.with_borrow(|id| f(id.as_deref()))
+}
+
+/// Handle a command line, `--id <id>` included; also those of [`crate::tcp`] clients
+pub(crate) fn handle_line(line: &str) {
+    let line = line.trim_start();
+    let (id, command) = match line.strip_prefix("--id") {
+        Some(rest) if rest.starts_with(char::is_whitespace) => {
+            let rest = rest.trim_start();
+            let (id, command) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            (Some(id.to_string()), command)
+        }
+        _ => (None, line),
+    };
+    REQUEST_ID.set(id);
+    handle_command(command);
+    REQUEST_ID.set(None);
+}
+
+/// Carry out `f` as a command sent with `--id <id>`, for commands that come from elsewhere than stdin
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn as_request<R>(id: String, f: impl FnOnce() -> R) -> R {
+    let outer = REQUEST_ID.replace(Some(id));
+    let result = f();
+    REQUEST_ID.set(outer);
+    result
+}
+
+/// Whether key events should currently be dropped instead of emitted
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Pause or resume capture, emitting `Paused`/`Resumed` only when the state actually changes
+pub fn set_paused(paused: bool, source: &str) {
+    if source != session::PAUSE_SOURCE {
+        if !paused && session::holds_pause() {
+            emit_error("SessionLocked", "Capture stays paused while the session is locked".to_string());
+            return;
+        }
+        if paused {
+            // Asked for explicitly, so unlocking must not undo it
+            session::release_pause();
+        }
+    }
+    if PAUSED.swap(paused, Ordering::SeqCst) != paused {
+        eprintln!("Capture {} via {}", if paused { "paused" } else { "resumed" }, source);
+        if !paused {
+            // Presses and releases during the pause were never seen
+            hotkeys::reset();
+            hotstrings::reset();
+        }
+        let source = source.to_string();
+        stream::emit(&Event::now(if paused {
+            EventKind::Paused { source }
+        } else {
+            EventKind::Resumed { source }
+        }));
+    }
+}
+
+fn handle_command(line: &str) {
+    let mut parts = line.split_whitespace();
+    let Some(command) = parts.next() else {
+        return;
+    };
+    #[cfg(target_os = "linux")]
+    if sandbox::applied() {
+        let subcommand = parts.clone().next();
+        let blocked = SANDBOXED
+            .iter()
+            .find(|(name, sub)| *name == command && sub.is_none_or(|sub| Some(sub) == subcommand));
+        if let Some((_, sub)) = blocked {
+            let command = sub.map_or(command.to_string(), |sub| format!("{} {}", command, sub));
+            let message = "isn't available with --sandbox, which only leaves reading devices and writing to stdout";
+            return emit_error("Sandboxed", format!("{} {}", command, message));
+        }
+    }
+
+    match command {
+        "pause" => set_paused(true, "stdin"),
+        "resume" => set_paused(false, "stdin"),
+        "write" => {
+            let args = line.trim_start()[command.len()..].trim();
+            if let Err(message) = start_write(args) {
+                emit_error("InvalidCommand", message);
+            }
+        }
+        "cancel" => cancel_write(parts.next()),
+        "config" => match parts.next() {
+            Some("reload") => config::reload("stdin"),
+            _ => emit_error("InvalidCommand", "Expected: config reload".to_string()),
+        },
+        "hotkey" => hotkey_command(line.trim_start()[command.len()..].trim()),
+        "leds" => leds_command(&parts.collect::<Vec<_>>()),
+        "backlight" => match parts.collect::<Vec<_>>().as_slice() {
+            ["set", args @ ..] => {
+                if let Err(message) = backlight::parse_set(args).and_then(|setting| backlight::set(&setting)) {
+                    emit_error("BacklightFailed", message);
+                }
+            }
+            _ => emit_error("InvalidCommand", "Expected: backlight set <percent|on|off> [options]".to_string()),
+        },
+        "context" => match parts.next().map(str::parse).unwrap_or(Ok(context::DEFAULT_CHARS)) {
+            Ok(chars) => match context::read(chars) {
+                Ok(context) => stream::emit(&Event::now(EventKind::TextContext {
+                    context: serde_json::json!(context),
+                })),
+                Err(message) => emit_error("ContextUnavailable", message),
+            },
+            Err(_) => emit_error("InvalidCommand", "Expected: context [<chars>]".to_string()),
+        },
+        "inhibit-sleep" => inhibit_sleep_command(line.trim_start()[command.len()..].trim()),
+        "idle" => match idle::query() {
+            Ok(idle) => stream::emit(&Event::now(EventKind::IdleTime {
+                idle_secs: idle.idle_secs,
+                source: idle.source,
+            })),
+            Err(message) => emit_error("IdleUnavailable", message),
+        },
+        "stats" => stream::emit(&Event::now(EventKind::Stats { stats: stats::snapshot() })),
+        "notify" => notify_command(line.trim_start()[command.len()..].trim()),
+        "dump" => {
+            let rest = line.trim_start()[command.len()..].trim();
+            let path = if rest.is_empty() { journal::default_dump_path() } else { PathBuf::from(rest) };
+            match journal::dump(&path) {
+                Ok(events) => stream::emit(&Event::now(EventKind::EventsDumped {
+                    path: path.display().to_string(),
+                    events,
+                })),
+                Err(message) => emit_error("DumpFailed", message),
+            }
+        }
+        _ => emit_error("InvalidCommand", format!("Unknown command: {}", command)),
+    }
+}
+
+/// `add <json definition>`, `remove <id>` or `list`
+fn hotkey_command(args: &str) {
+    let (action, rest) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    let rest = rest.trim();
+    match action {
+        "add" => match serde_json::from_str::<hotkeys::Definition>(rest) {
+            Ok(definition) => match hotkeys::add(definition) {
+                Ok(id) => stream::emit(&Event::now(EventKind::HotkeyAdded { id })),
+                Err(message) => emit_error("InvalidHotkey", message),
+            },
+            Err(e) => emit_error("InvalidCommand", format!("hotkey add expects a JSON hotkey definition: {}", e)),
+        },
+        "remove" if !rest.is_empty() => match hotkeys::remove(rest) {
+            Ok(()) => stream::emit(&Event::now(EventKind::HotkeyRemoved { id: rest.to_string() })),
+            Err(message) => emit_error("UnknownHotkey", message),
+        },
+        "list" => stream::emit(&Event::now(EventKind::HotkeyList { hotkeys: hotkeys::list() })),
+        _ => emit_error(
+            "InvalidCommand",
+            "Expected: hotkey add <json>, hotkey remove <id> or hotkey list".to_string(),
+        ),
+    }
+}
+
+/// `inhibit-sleep start [<reason>]` or `inhibit-sleep stop`
+fn inhibit_sleep_command(args: &str) {
+    let (action, reason) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+    match action {
+        "start" => {
+            let reason = match reason.trim() {
+                "" => inhibit::DEFAULT_REASON,
+                reason => reason,
+            };
+            match inhibit::start(reason) {
+                Ok(()) => stream::emit(&Event::now(EventKind::SleepInhibited {
+                    reason: reason.to_string(),
+                })),
+                Err(message) => emit_error("InhibitFailed", message),
+            }
+        }
+        "stop" if reason.is_empty() => {
+            if inhibit::stop() {
+                stream::emit(&Event::now(EventKind::SleepAllowed {}));
+            } else {
+                emit_error("InvalidCommand", "Sleep isn't being inhibited".to_string());
+            }
+        }
+        _ => emit_error("InvalidCommand", "Expected: inhibit-sleep start [<reason>] or inhibit-sleep stop".to_string()),
+    }
+}
+
+/// `notify <json object>`, shown on a background thread as the platform tools take a moment
+fn notify_command(args: &str) {
+    let notification: notify::Notification = match serde_json::from_str(args) {
+        Ok(notification) => notification,
+        Err(e) => {
+            return emit_error("InvalidCommand", format!("notify expects {{\"title\", \"body\", \"icon\"}}: {}", e))
+        }
+    };
+    let request_id = REQUEST_ID.with_borrow(Clone::clone);
+    thread::spawn(move || {
+        REQUEST_ID.set(request_id);
+        if let Err(message) = notify::show(&notification) {
+            emit_error("NotifyFailed", message);
+        }
+    });
+}
+
+/// `get` or `set <led> on|off`
+fn leds_command(args: &[&str]) {
+    let result = match args {
+        ["get"] => leds::get().map(|leds| stream::emit(&Event::now(EventKind::LedState { leds }))),
+        ["set", rest @ ..] => match leds::parse_set(rest) {
+            Ok((led, on)) => leds::set(led, on),
+            Err(message) => return emit_error("InvalidCommand", message),
+        },
+        _ => return emit_error("InvalidCommand", "Expected: leds get or leds set <led> <on|off>".to_string()),
+    };
+    if let Err(message) = result {
+        emit_error("LedsFailed", message);
+    }
+}
+
+pub fn emit_error(error: &str, message: String) {
+    eprintln!("!error: {}", message);
+    stream::emit(&Event::now(EventKind::Error {
+        error: error.to_string(),
+        message,
+    }));
+}
+
+/// Parse `[--keys] [--ime-safe | --paste [--primary] | --backend <name>] [--window <json string>] <json string>`
+/// and queue it for the write thread
+fn start_write(args: &str) -> Result<(), String> {
+    let mut text = args;
+    let (mut escapes, mut method) = (false, TextMethod::default_for_write());
+    let (mut paste, mut primary, mut window, mut backend) = (false, false, None, None);
+    loop {
+        if let Some(rest) = text.strip_prefix("--keys") {
+            escapes = true;
+            text = rest.trim_start();
+        } else if let Some(rest) = text.strip_prefix("--ime-safe") {
+            method = TextMethod::ImeSafe;
+            text = rest.trim_start();
+        } else if let Some(rest) = text.strip_prefix("--paste") {
+            paste = true;
+            text = rest.trim_start();
+        } else if let Some(rest) = text.strip_prefix("--primary") {
+            primary = true;
+            text = rest.trim_start();
+        } else if let Some(rest) = text.strip_prefix("--backend") {
+            let rest = rest.trim_start();
+            let (name, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if name.is_empty() {
+                return Err("--backend expects a backend name".to_string());
+            }
+            backend = Some(TextMethod::backend(name)?);
+            text = rest.trim_start();
+        } else if let Some(rest) = text.strip_prefix("--window") {
+            // JSON like the text, as titles have spaces
+            let rest = rest.trim_start();
+            let mut strings = serde_json::Deserializer::from_str(rest).into_iter::<String>();
+            match strings.next() {
+                Some(Ok(query)) => window = Some(query),
+                _ => return Err("--window expects a JSON string with a window id or title".to_string()),
+            }
+            text = rest[strings.byte_offset()..].trim_start();
+        } else {
+            break;
+        }
+    }
+    if paste {
+        method = TextMethod::Paste(if primary { Selection::Primary } else { Selection::Clipboard });
+    } else if primary {
+        return Err("--primary goes with --paste".to_string());
+    }
+    if let Some(backend) = backend {
+        if paste || method == TextMethod::ImeSafe {
+            return Err("--backend doesn't go with --paste or --ime-safe".to_string());
+        }
+        method = backend;
+    }
+    // JSON so the text can carry newlines without ending the command line
+    let text: String = serde_json::from_str(text)
+        .map_err(|e| format!("write expects a JSON string argument: {}", e))?;
+    let segments = if escapes {
+        keys::parse_key_escapes(&text)?
+    } else {
+        vec![keys::Segment::Text(text)]
+    };
+    queue_write(segments, method, window);
+    Ok(())
+}
+
+/// Queue a write for the write thread under the current `--id`, emitting `WriteQueued`
+pub(crate) fn queue_write(segments: Vec<keys::Segment>, method: TextMethod, window: Option<String>) {
+    static WRITE_THREAD: Once = Once::new();
+    WRITE_THREAD.call_once(|| {
+        thread::spawn(run_writes);
+    });
+
+    let mut queue = writes();
+    // Emitted under the lock so it always precedes the write's progress
+    let position = queue.pending.len() + usize::from(queue.current.is_some());
+    stream::emit(&Event::now(EventKind::WriteQueued { position }));
+    queue.pending.push_back(QueuedWrite {
+        request_id: REQUEST_ID.with_borrow(Clone::clone),
+        segments,
+        method,
+        window,
+    });
+    WRITE_QUEUED.notify_one();
+}
+
+/// Inject queued writes in order, forever
+fn run_writes() {
+    loop {
+        let write = {
+            let mut queue = WRITE_QUEUED
+                .wait_while(writes(), |queue| queue.pending.is_empty())
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let write = queue.pending.pop_front().expect("woken with a write queued");
+            queue.current = Some(write.request_id.clone());
+            CANCEL_WRITE.store(false, Ordering::SeqCst);
+            write
+        };
+        REQUEST_ID.set(write.request_id);
+        match &write.window {
+            Some(query) => {
+                if let Err(e) = active_window::with_focus(query, || inject_write(&write.segments, write.method)) {
+                    emit_error("WriteFailed", format!("Write failed: {}", e));
+                }
+            }
+            None => inject_write(&write.segments, write.method),
+        }
+        REQUEST_ID.set(None);
+        writes().current = None;
+    }
+}
+
+fn inject_write(segments: &[keys::Segment], method: TextMethod) {
+    let started = Instant::now();
+    let mut last_progress: Option<Instant> = None;
+    let mut progress = (0, 0);
+    let result = inject::write_segments_with_progress(segments, method, |chars_done, total| {
+        progress = (chars_done, total);
+        if last_progress.is_none_or(|at| at.elapsed() >= WRITE_PROGRESS_INTERVAL) {
+            last_progress = Some(Instant::now());
+            stream::emit(&Event::now(EventKind::WriteProgress { chars_done, total }));
+        }
+        !CANCEL_WRITE.load(Ordering::SeqCst)
+    });
+
+    match result {
+        Ok(true) => stream::emit(&Event::now(EventKind::WriteComplete {
+            duration_ms: started.elapsed().as_millis() as u64,
+            chars: progress.1,
+        })),
+        Ok(false) => stream::emit(&Event::now(EventKind::WriteCancelled {
+            chars_done: progress.0,
+            total: progress.1,
+        })),
+        Err(e) => emit_error("WriteFailed", format!("Write failed: {}", e)),
+    }
+}
+
+/// `cancel [<id>]`: the write in progress, or the one sent with `--id <id>`
+fn cancel_write(id: Option<&str>) {
+    let mut queue = writes();
+    let Some(id) = id else {
+        if queue.current.is_some() {
+            CANCEL_WRITE.store(true, Ordering::SeqCst);
+        }
+        return;
+    };
+    if queue.current.as_ref().is_some_and(|current| current.as_deref() == Some(id)) {
+        CANCEL_WRITE.store(true, Ordering::SeqCst);
+    } else if let Some(index) = queue.pending.iter().position(|write| write.request_id.as_deref() == Some(id)) {
+        let write = queue.pending.remove(index).expect("index of a pending write");
+        drop(queue);
+        // Reported as the cancelled write's own outcome, under its id
+        let cancel_id = REQUEST_ID.replace(write.request_id);
+        stream::emit(&Event::now(EventKind::WriteCancelled {
+            chars_done: 0,
+            total: write.segments.iter().map(keys::Segment::char_count).sum(),
+        }));
+        REQUEST_ID.set(cancel_id);
+    } else {
+        emit_error("UnknownWrite", format!("No queued or running write has id {}", id));
+    }
+}
+
+/// Read commands from stdin on a background thread. EOF just ends the reader;
+/// the listener keeps running for apps that never write to stdin.
+pub fn start_command_reader() {
+    thread::spawn(|| {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => handle_line(&line),
+                Err(e) => {
+                    eprintln!("Failed to read command from stdin: {}", e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/// Route control signals to a dedicated thread
+///
+/// Must run before any other thread is spawned so every thread inherits the
+/// blocked mask; otherwise the kernel could deliver the signal to a thread that
+/// still has the default (terminating) disposition.
+#[cfg(unix)]
+pub fn start_signal_handler() {
+    let mut signals: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGUSR1);
+        libc::sigaddset(&mut signals, libc::SIGUSR2);
+        libc::sigaddset(&mut signals, libc::SIGHUP);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+    }
+
+    thread::spawn(move || loop {
+        let mut signal: libc::c_int = 0;
+        if unsafe { libc::sigwait(&signals, &mut signal) } != 0 {
+            continue;
+        }
+        match signal {
+            libc::SIGUSR1 => set_paused(true, "SIGUSR1"),
+            libc::SIGUSR2 => set_paused(false, "SIGUSR2"),
+            libc::SIGHUP => config::reload("SIGHUP"),
+            _ => {}
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn start_signal_handler() {}
@@ -0,0 +1,456 @@
+//! D-Bus service on the session bus (`listen --dbus`, Linux), for desktop
+//! integrations such as GNOME Shell extensions and KDE widgets that would
+//! rather not attach to the helper's stdout.
+//!
+//! The helper owns `io.github.aj47.NvidiaCC` and serves the interface of the
+//! same name at `/io/github/aj47/NvidiaCC`:
+//!   WriteText(s text) -> s request_id  - queue text, as stdin's `write` does
+//!   Press(s keys) -> s request_id      - queue a combo such as `ctrl+shift+v`
+//!   RegisterHotkey(s definition) -> s id - register a hotkey given as the JSON
+//!             `hotkey add` takes, emitting `HotkeyAdded{id}`
+//!   UnregisterHotkey(s id)             - emitting `HotkeyRemoved{id}`
+//!   signal Listen(s event)             - every event, as the JSON line
+//!             socket clients get
+//!
+//! Writes and presses share the stdin write queue; the `request_id` they
+//! return is carried by their `WriteQueued`, `WriteProgress` and
+//! `WriteComplete` events, so callers can follow them in `Listen`. Hotkeys
+//! fire as `HotkeyTriggered` there like any other event.
+//!
+//! libdbus is loaded when `--dbus` is given, as libzstd is, so the helper
+//! runs without it. Anyone on the session bus is the session's user, who can
+//! already read the socket and type into the session.
+
+use libloading::Library;
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::OnceLock;
+use std::thread;
+
+use crate::control;
+use crate::event::{Event, EventKind};
+use crate::hotkeys;
+use crate::inject::TextMethod;
+use crate::keys::{self, Segment};
+use crate::stream;
+
+/// The well-known name the helper takes on the session bus
+pub const BUS_NAME: &str = "io.github.aj47.NvidiaCC";
+
+const OBJECT_PATH: &CStr = c"/io/github/aj47/NvidiaCC";
+const INTERFACE: &CStr = c"io.github.aj47.NvidiaCC";
+
+/// `Listen` signals waiting for the service thread; more are dropped rather than stalling capture
+const SIGNAL_QUEUE: usize = 1024;
+
+const LIBRARY_NAMES: &[&str] = &["libdbus-1.so.3", "libdbus-1.so"];
+
+/// `DBusBusType`
+const BUS_SESSION: c_int = 0;
+/// `DBUS_NAME_FLAG_DO_NOT_QUEUE`
+const NAME_FLAG_DO_NOT_QUEUE: c_uint = 4;
+/// `DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER`
+const PRIMARY_OWNER: c_int = 1;
+/// `DBUS_MESSAGE_TYPE_METHOD_CALL`
+const METHOD_CALL: c_int = 1;
+const TYPE_INVALID: c_int = 0;
+const TYPE_STRING: c_int = b's' as c_int;
+
+const ERROR_INVALID_ARGS: &CStr = c"org.freedesktop.DBus.Error.InvalidArgs";
+const ERROR_UNKNOWN_METHOD: &CStr = c"org.freedesktop.DBus.Error.UnknownMethod";
+const ERROR_FAILED: &CStr = c"org.freedesktop.DBus.Error.Failed";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.github.aj47.NvidiaCC">
+    <method name="WriteText">
+      <arg name="text" type="s" direction="in"/>
+      <arg name="request_id" type="s" direction="out"/>
+    </method>
+    <method name="Press">
+      <arg name="keys" type="s" direction="in"/>
+      <arg name="request_id" type="s" direction="out"/>
+    </method>
+    <method name="RegisterHotkey">
+      <arg name="definition" type="s" direction="in"/>
+      <arg name="id" type="s" direction="out"/>
+    </method>
+    <method name="UnregisterHotkey">
+      <arg name="id" type="s" direction="in"/>
+    </method>
+    <signal name="Listen">
+      <arg name="event" type="s"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+</node>
+"#;
+
+/// Request ids handed out by WriteText and Press
+static NEXT_REQUEST: AtomicU64 = AtomicU64::new(1);
+
+/// `DBusError`
+#[repr(C)]
+struct Error {
+    name: *const c_char,
+    message: *const c_char,
+    dummy: c_uint,
+    padding: *mut c_void,
+}
+
+type Connection = c_void;
+type Message = c_void;
+
+struct Api {
+    error_init: unsafe extern "C" fn(*mut Error),
+    error_free: unsafe extern "C" fn(*mut Error),
+    bus_get_private: unsafe extern "C" fn(c_int, *mut Error) -> *mut Connection,
+    bus_request_name: unsafe extern "C" fn(*mut Connection, *const c_char, c_uint, *mut Error) -> c_int,
+    set_exit_on_disconnect: unsafe extern "C" fn(*mut Connection, u32),
+    get_unix_fd: unsafe extern "C" fn(*mut Connection, *mut c_int) -> u32,
+    read_write: unsafe extern "C" fn(*mut Connection, c_int) -> u32,
+    pop_message: unsafe extern "C" fn(*mut Connection) -> *mut Message,
+    send: unsafe extern "C" fn(*mut Connection, *mut Message, *mut u32) -> u32,
+    flush: unsafe extern "C" fn(*mut Connection),
+    message_get_type: unsafe extern "C" fn(*mut Message) -> c_int,
+    message_get_interface: unsafe extern "C" fn(*mut Message) -> *const c_char,
+    message_get_member: unsafe extern "C" fn(*mut Message) -> *const c_char,
+    message_get_args: unsafe extern "C" fn(*mut Message, *mut Error, c_int, ...) -> u32,
+    message_new_method_return: unsafe extern "C" fn(*mut Message) -> *mut Message,
+    message_new_error: unsafe extern "C" fn(*mut Message, *const c_char, *const c_char) -> *mut Message,
+    message_new_signal: unsafe extern "C" fn(*const c_char, *const c_char, *const c_char) -> *mut Message,
+    message_append_args: unsafe extern "C" fn(*mut Message, c_int, ...) -> u32,
+    message_unref: unsafe extern "C" fn(*mut Message),
+    /// Keeps the functions above loaded
+    _library: Library,
+}
+
+impl Api {
+    unsafe fn load() -> Result<Api, String> {
+        let library = LIBRARY_NAMES
+            .iter()
+            .find_map(|name| Library::new(name).ok())
+            .ok_or_else(|| format!("--dbus needs libdbus ({})", LIBRARY_NAMES[0]))?;
+        macro_rules! symbol {
+            ($name:literal) => {
+                *library
+                    .get(concat!($name, "\0").as_bytes())
+                    .map_err(|e| format!("libdbus lacks {}: {}", $name, e))?
+            };
+        }
+        Ok(Api {
+            error_init: symbol!("dbus_error_init"),
+            error_free: symbol!("dbus_error_free"),
+            bus_get_private: symbol!("dbus_bus_get_private"),
+            bus_request_name: symbol!("dbus_bus_request_name"),
+            set_exit_on_disconnect: symbol!("dbus_connection_set_exit_on_disconnect"),
+            get_unix_fd: symbol!("dbus_connection_get_unix_fd"),
+            read_write: symbol!("dbus_connection_read_write"),
+            pop_message: symbol!("dbus_connection_pop_message"),
+            send: symbol!("dbus_connection_send"),
+            flush: symbol!("dbus_connection_flush"),
+            message_get_type: symbol!("dbus_message_get_type"),
+            message_get_interface: symbol!("dbus_message_get_interface"),
+            message_get_member: symbol!("dbus_message_get_member"),
+            message_get_args: symbol!("dbus_message_get_args"),
+            message_new_method_return: symbol!("dbus_message_new_method_return"),
+            message_new_error: symbol!("dbus_message_new_error"),
+            message_new_signal: symbol!("dbus_message_new_signal"),
+            message_append_args: symbol!("dbus_message_append_args"),
+            message_unref: symbol!("dbus_message_unref"),
+            _library: library,
+        })
+    }
+
+    /// A fresh `DBusError`
+    fn error(&self) -> Error {
+        let mut error = Error {
+            name: ptr::null(),
+            message: ptr::null(),
+            dummy: 0,
+            padding: ptr::null_mut(),
+        };
+        unsafe { (self.error_init)(&mut error) };
+        error
+    }
+
+    /// The message of `error` if set, freeing it
+    fn take_error(&self, error: &mut Error) -> Option<String> {
+        if error.name.is_null() {
+            return None;
+        }
+        let message = unsafe { text(error.message) }.unwrap_or_default();
+        unsafe { (self.error_free)(error) };
+        Some(message)
+    }
+}
+
+fn api() -> Result<&'static Api, String> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(|| unsafe { Api::load() }).as_ref().map_err(String::clone)
+}
+
+unsafe fn text(pointer: *const c_char) -> Option<String> {
+    (!pointer.is_null()).then(|| unsafe { CStr::from_ptr(pointer) }.to_string_lossy().into_owned())
+}
+
+/// A method call that can't be carried out: the D-Bus error name and its message
+struct Failure {
+    name: &'static CStr,
+    message: String,
+}
+
+impl Failure {
+    fn invalid_args(message: impl Into<String>) -> Self {
+        Failure {
+            name: ERROR_INVALID_ARGS,
+            message: message.into(),
+        }
+    }
+
+    fn failed(message: impl Into<String>) -> Self {
+        Failure {
+            name: ERROR_FAILED,
+            message: message.into(),
+        }
+    }
+}
+
+struct Service {
+    api: &'static Api,
+    connection: *mut Connection,
+}
+
+// Only the service thread touches the connection once it is set up
+unsafe impl Send for Service {}
+
+/// Take [`BUS_NAME`] on the session bus and serve it on a background thread
+pub fn serve() -> Result<(), String> {
+    let api = api()?;
+    let mut error = api.error();
+    let connection = unsafe { (api.bus_get_private)(BUS_SESSION, &mut error) };
+    if let Some(message) = api.take_error(&mut error).or_else(|| connection.is_null().then(String::new)) {
+        return Err(format!("Cannot connect to the session bus: {}", message));
+    }
+    // libdbus would otherwise exit the whole process when the bus goes away
+    unsafe { (api.set_exit_on_disconnect)(connection, 0) };
+
+    let name = CString::new(BUS_NAME).expect("bus name without NUL");
+    let reply = unsafe { (api.bus_request_name)(connection, name.as_ptr(), NAME_FLAG_DO_NOT_QUEUE, &mut error) };
+    if let Some(message) = api.take_error(&mut error) {
+        return Err(format!("Cannot take {} on the session bus: {}", BUS_NAME, message));
+    }
+    if reply != PRIMARY_OWNER {
+        return Err(format!("{} is already owned on the session bus, by another listener?", BUS_NAME));
+    }
+
+    let (queue, lines) = mpsc::sync_channel(SIGNAL_QUEUE);
+    let (wake, woken) = UnixStream::pair().map_err(|e| format!("Cannot create a wakeup socket: {}", e))?;
+    for socket in [&wake, &woken] {
+        socket.set_nonblocking(true).map_err(|e| format!("Cannot create a wakeup socket: {}", e))?;
+    }
+    stream::subscribe(Box::new(Signals { line: Vec::new(), queue, wake }), None)
+        .map_err(|e| format!("Cannot subscribe to events: {}", e))?;
+
+    eprintln!("Serving {} on the session bus", BUS_NAME);
+    let service = Service { api, connection };
+    thread::spawn(move || service.run(lines, woken));
+    Ok(())
+}
+
+/// Hands each event line to the service thread, waking it through a socket pair
+struct Signals {
+    line: Vec<u8>,
+    queue: SyncSender<String>,
+    wake: UnixStream,
+}
+
+impl Write for Signals {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while let Some(end) = self.line.iter().position(|&byte| byte == b'\n') {
+            let line = String::from_utf8_lossy(&self.line[..end]).into_owned();
+            self.line.drain(..=end);
+            match self.queue.try_send(line) {
+                Ok(()) | Err(TrySendError::Full(_)) => {}
+                // The service thread is gone, so this subscriber is dropped
+                Err(TrySendError::Disconnected(_)) => return Err(io::ErrorKind::BrokenPipe.into()),
+            }
+        }
+        // Only fails when a wakeup is already pending
+        let _ = self.wake.write(&[1]);
+        Ok(())
+    }
+}
+
+impl Service {
+    fn run(self, lines: Receiver<String>, mut woken: UnixStream) {
+        let mut bus_fd = -1;
+        if unsafe { (self.api.get_unix_fd)(self.connection, &mut bus_fd) } == 0 {
+            eprintln!("!error: The session bus connection has no socket");
+            return;
+        }
+        let mut fds = [
+            libc::pollfd {
+                fd: bus_fd,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: woken.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        loop {
+            if unsafe { (self.api.read_write)(self.connection, 0) } == 0 {
+                eprintln!("!error: Disconnected from the session bus, {} is no longer served", BUS_NAME);
+                return;
+            }
+            loop {
+                let message = unsafe { (self.api.pop_message)(self.connection) };
+                if message.is_null() {
+                    break;
+                }
+                self.handle(message);
+                unsafe { (self.api.message_unref)(message) };
+            }
+
+            let mut drained = [0; 64];
+            while woken.read(&mut drained).is_ok_and(|read| read > 0) {}
+            while let Ok(line) = lines.try_recv() {
+                self.signal(&line);
+            }
+            unsafe { (self.api.flush)(self.connection) };
+
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0
+                && io::Error::last_os_error().kind() != io::ErrorKind::Interrupted
+            {
+                eprintln!("!error: Waiting on the session bus failed: {}", io::Error::last_os_error());
+                return;
+            }
+        }
+    }
+
+    /// Emit `Listen` with an event line
+    fn signal(&self, line: &str) {
+        let Ok(line) = CString::new(line) else { return };
+        let (path, interface) = (OBJECT_PATH.as_ptr(), INTERFACE.as_ptr());
+        let signal = unsafe { (self.api.message_new_signal)(path, interface, c"Listen".as_ptr()) };
+        if signal.is_null() {
+            return;
+        }
+        self.send_with_string(signal, Some(&line));
+    }
+
+    fn handle(&self, message: *mut Message) {
+        if unsafe { (self.api.message_get_type)(message) } != METHOD_CALL {
+            return;
+        }
+        let interface = unsafe { text((self.api.message_get_interface)(message)) };
+        let member = unsafe { text((self.api.message_get_member)(message)) }.unwrap_or_default();
+        let result = match interface.as_deref() {
+            Some("org.freedesktop.DBus.Introspectable") if member == "Introspect" => {
+                Ok(Some(INTROSPECTION.to_string()))
+            }
+            // The interface is named after the service, and may be left out of a call
+            None => self.call(&member, message),
+            Some(interface) if interface == BUS_NAME => self.call(&member, message),
+            _ => Err(self.unknown(&member)),
+        };
+        let reply = match &result {
+            Ok(_) => unsafe { (self.api.message_new_method_return)(message) },
+            Err(failure) => {
+                let description = CString::new(failure.message.replace('\0', "")).unwrap_or_default();
+                unsafe { (self.api.message_new_error)(message, failure.name.as_ptr(), description.as_ptr()) }
+            }
+        };
+        if reply.is_null() {
+            return;
+        }
+        let value = match result {
+            Ok(Some(value)) => CString::new(value).ok(),
+            _ => None,
+        };
+        self.send_with_string(reply, value.as_deref());
+    }
+
+    /// Send `message`, first appending `value` as its string argument, and unref it
+    fn send_with_string(&self, message: *mut Message, value: Option<&CStr>) {
+        if let Some(value) = value {
+            let pointer = value.as_ptr();
+            unsafe { (self.api.message_append_args)(message, TYPE_STRING, &pointer, TYPE_INVALID) };
+        }
+        unsafe {
+            (self.api.send)(self.connection, message, ptr::null_mut());
+            (self.api.message_unref)(message);
+        }
+    }
+
+    fn unknown(&self, member: &str) -> Failure {
+        Failure {
+            name: ERROR_UNKNOWN_METHOD,
+            message: format!("{} has no method {}", BUS_NAME, member),
+        }
+    }
+
+    /// The single string argument of `message`
+    fn string_arg(&self, message: *mut Message) -> Result<String, Failure> {
+        let mut error = self.api.error();
+        let mut value: *const c_char = ptr::null();
+        unsafe { (self.api.message_get_args)(message, &mut error, TYPE_STRING, &mut value, TYPE_INVALID) };
+        if let Some(description) = self.api.take_error(&mut error) {
+            return Err(Failure::invalid_args(description));
+        }
+        Ok(unsafe { text(value) }.unwrap_or_default())
+    }
+
+    /// One of our methods; `Some` value for those returning a string
+    fn call(&self, member: &str, message: *mut Message) -> Result<Option<String>, Failure> {
+        match member {
+            "WriteText" => {
+                let text = self.string_arg(message)?;
+                Ok(Some(queue_write(vec![Segment::Text(text)], TextMethod::default_for_write())))
+            }
+            "Press" => {
+                let keys = keys::parse_combo(&self.string_arg(message)?).map_err(Failure::invalid_args)?;
+                Ok(Some(queue_write(vec![Segment::Keys(keys)], TextMethod::Type)))
+            }
+            "RegisterHotkey" => {
+                let definition = serde_json::from_str::<hotkeys::Definition>(&self.string_arg(message)?)
+                    .map_err(|e| Failure::invalid_args(format!("Expected a JSON hotkey definition: {}", e)))?;
+                let id = hotkeys::add(definition).map_err(Failure::failed)?;
+                stream::emit(&Event::now(EventKind::HotkeyAdded { id: id.clone() }));
+                Ok(Some(id))
+            }
+            "UnregisterHotkey" => {
+                let id = self.string_arg(message)?;
+                hotkeys::remove(&id).map_err(Failure::failed)?;
+                stream::emit(&Event::now(EventKind::HotkeyRemoved { id }));
+                Ok(None)
+            }
+            _ => Err(self.unknown(member)),
+        }
+    }
+}
+
+/// Queue `segments` on the stdin write queue under a new request id, returning it
+fn queue_write(segments: Vec<Segment>, method: TextMethod) -> String {
+    let id = format!("dbus-{}", NEXT_REQUEST.fetch_add(1, Ordering::Relaxed));
+    control::as_request(id.clone(), || control::queue_write(segments, method, None));
+    id
+}
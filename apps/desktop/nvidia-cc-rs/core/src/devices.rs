@@ -0,0 +1,99 @@
+//! Registry of the input devices the listener is attached to, for status reporting,
+//! and which devices count as triggers besides keyboards.
+
+use serde::Serialize;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Clone, Serialize)]
+pub struct DeviceInfo {
+    /// Event node on Linux; `None` for the platform-wide rdev hook
+    pub path: Option<String>,
+    pub name: String,
+    pub active: bool,
+    /// Why the device stopped, once it has
+    pub error: Option<String>,
+    /// Key events read from it
+    pub events: u64,
+}
+
+static DEVICES: Mutex<Vec<DeviceInfo>> = Mutex::new(Vec::new());
+
+fn devices() -> MutexGuard<'static, Vec<DeviceInfo>> {
+    DEVICES.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Foot pedals, macro pads and presenter remotes: no typing keys, but buttons worth binding
+///
+/// That is F13–F24, the programmable and macro keys, a presenter's page up/down
+/// pair, or numbered buttons (`BTN_0`–`BTN_9`) on a device without pointer axes,
+/// which would make it a mouse, tablet or joystick.
+#[cfg(target_os = "linux")]
+pub fn is_trigger_device(device: &evdev::Device) -> bool {
+    use evdev::{AbsoluteAxisCode, KeyCode, RelativeAxisCode};
+
+    let Some(keys) = device.supported_keys() else {
+        return false;
+    };
+    let in_range =
+        |first: KeyCode, last: KeyCode| keys.iter().any(|key| (first.code()..=last.code()).contains(&key.code()));
+    if in_range(KeyCode::KEY_F13, KeyCode::KEY_F24)
+        || in_range(KeyCode::KEY_PROG1, KeyCode::KEY_PROG4)
+        // KEY_MACRO1 to KEY_MACRO30, which evdev has no names for
+        || in_range(KeyCode::new(0x290), KeyCode::new(0x2ad))
+        || (keys.contains(KeyCode::KEY_PAGEUP) && keys.contains(KeyCode::KEY_PAGEDOWN))
+    {
+        return true;
+    }
+    let pointer = device.supported_relative_axes().is_some_and(|axes| axes.contains(RelativeAxisCode::REL_X))
+        || device.supported_absolute_axes().is_some_and(|axes| axes.contains(AbsoluteAxisCode::ABS_X));
+    !pointer && in_range(KeyCode::BTN_0, KeyCode::BTN_9)
+}
+
+/// With `--any-keys`: every device with a key or button, short of a mouse
+#[cfg(target_os = "linux")]
+pub fn has_any_keys(device: &evdev::Device) -> bool {
+    device.supported_keys().is_some_and(|keys| keys.iter().next().is_some()) && !crate::macros::is_pointer(device)
+}
+
+pub fn register(path: Option<String>, name: &str) {
+    devices().push(DeviceInfo {
+        path,
+        name: name.to_string(),
+        active: true,
+        error: None,
+        events: 0,
+    });
+}
+
+/// Count a key event read from the device at `path` (`None` for the rdev hook)
+pub fn count_event(path: Option<&str>) {
+    for device in devices().iter_mut() {
+        if device.path.as_deref() == path {
+            device.events += 1;
+        }
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn mark_active(path: &str) {
+    for device in devices().iter_mut() {
+        if device.path.as_deref() == Some(path) {
+            device.active = true;
+            device.error = None;
+        }
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn mark_stopped(path: &str, error: &str) {
+    for device in devices().iter_mut() {
+        if device.path.as_deref() == Some(path) {
+            device.active = false;
+            device.error = Some(error.to_string());
+        }
+    }
+}
+
+pub fn snapshot() -> Vec<DeviceInfo> {
+    devices().clone()
+}
@@ -0,0 +1,140 @@
+//! The listener running inside another process: the C interface and the Node
+//! binding.
+//!
+//! [`start`] sets capture up as `listen` does from the config file and its
+//! environment (hotkeys, hotstrings, device filters, `[listen]` flags), but
+//! hands events to a function instead of stdout, which is left to the host.
+//! There are no stdin commands, sockets or signals. Capture threads can't be
+//! interrupted, so [`stop`] only stops delivery and drops every hotkey and
+//! hotstring; the threads keep reading key events, emitting nothing, and a
+//! later [`start`] reuses them.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::active_window;
+use crate::config;
+use crate::control;
+use crate::event;
+use crate::gamepad;
+use crate::gkeys;
+use crate::hotkeys;
+use crate::hotstrings;
+use crate::journal;
+#[cfg(target_os = "linux")]
+use crate::layout;
+use crate::leds;
+use crate::output;
+use crate::power;
+use crate::privacy;
+use crate::scancode;
+use crate::secure_input;
+use crate::session;
+use crate::stream;
+use crate::synthetic;
+use crate::throttle;
+use crate::KeyboardListener;
+
+type Deliver = Box<dyn FnMut(&str) + Send>;
+
+/// Where events go; `None` while stopped
+static DELIVER: Mutex<Option<Deliver>> = Mutex::new(None);
+
+/// Set once the capture threads are running
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Stream subscriber handing complete lines to [`DELIVER`]
+#[derive(Default)]
+struct Sink {
+    line: Vec<u8>,
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let line = String::from_utf8_lossy(&self.line).into_owned();
+            self.line.clear();
+            if let Some(deliver) = DELIVER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).as_mut() {
+                deliver(&line);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Start capturing, or pick up a new config after [`stop`], sending each event's JSON to `deliver`
+///
+/// `deliver` runs with the event stream locked, so it must only queue the event
+/// (a channel, a Node threadsafe function); anything that emits events from
+/// inside it deadlocks.
+pub fn start(deliver: impl FnMut(&str) + Send + 'static) -> Result<(), String> {
+    let mut config = config::load()?;
+    synthetic::set_suppress_self(config.listen.suppress_self);
+    let sources = config::Sources {
+        hotkeys_path: config.listen.hotkeys.clone(),
+        hotstrings_path: config.listen.hotstrings.clone(),
+    };
+    config::apply_definitions(&mut config, &sources)?;
+    config::apply_remaps(&config);
+    hotstrings::set_replace(true);
+    *DELIVER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Box::new(deliver));
+
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    output::set_stdout_enabled(false);
+    privacy::set_mode(config.listen.privacy);
+    event::set_time_format(config.listen.time_format);
+    scancode::set_enabled(config.listen.raw_scancodes);
+    throttle::set_max_rate(config.listen.max_event_rate);
+    stream::subscribe(Box::new(Sink::default()), None).map_err(|e| e.to_string())?;
+    if let Some(path) = &config.listen.record_to {
+        journal::record_to(path)?;
+    }
+    hotkeys::start_timer();
+    #[cfg(target_os = "linux")]
+    layout::watch_config();
+    active_window::watch();
+    power::watch();
+    session::watch(config.listen.pause_when_locked);
+    secure_input::watch();
+    leds::watch();
+    if config.listen.gamepads {
+        gamepad::watch();
+    }
+    if config.listen.g_keys {
+        gkeys::watch();
+    }
+    let listener = KeyboardListener::new()
+        .realtime(config.listen.realtime)
+        .media_keys(config.listen.media_keys)
+        .ignore_devices(config.ignored_devices())
+        .devices(config.device_paths())
+        .any_keys(config.listen.any_keys)
+        .all_sessions(config.listen.all_sessions);
+    thread::spawn(move || {
+        if let Err(e) = listener.run() {
+            control::emit_error("ListenerStopped", format!("Keyboard capture stopped: {}", e));
+        }
+    });
+    Ok(())
+}
+
+/// Stop delivering events and matching hotkeys and hotstrings
+///
+/// Returns once no `deliver` call is running, after which it is never called again.
+pub fn stop() {
+    *DELIVER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+    hotkeys::install(Vec::new());
+    hotstrings::install(Vec::new());
+}
@@ -0,0 +1,306 @@
+//! The desktop the helper runs in, for triaging capture and injection
+//! failures that differ from one Linux desktop to the next.
+//!
+//! The report says whether the session is X11 or Wayland (`XDG_SESSION_TYPE`,
+//! else which of `WAYLAND_DISPLAY` and `DISPLAY` is set), which compositor
+//! serves it, the Flatpak or Snap sandbox, whether the GlobalShortcuts and
+//! RemoteDesktop portals answer, and for each display socket its owner and
+//! permissions, whether it can be connected to and which process serves it.
+//! The compositor is the process at the other end of the Wayland socket, or
+//! the one its own environment variables name (`SWAYSOCK`,
+//! `HYPRLAND_INSTANCE_SIGNATURE`, `NIRI_SOCKET`) when that process can't be
+//! seen, as from inside a sandbox.
+//!
+//! Inside a container (Docker, Podman, Kubernetes, or whatever the `container`
+//! variable names) it also says whether the GPUs were passed in: the
+//! `/dev/nvidia*` nodes present, `NVIDIA_VISIBLE_DEVICES` as the NVIDIA
+//! Container Toolkit set it, and how many GPUs its injected nvidia-smi sees, or
+//! why none are seen, rather than leaving a bare driver error to decode.
+//!
+//! `listen` emits it as the `Environment` event after `Capabilities`, and
+//! `doctor` prints it with the `backends` report. Other platforms only name
+//! their display server.
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Environment {
+    pub platform: &'static str,
+    /// `x11` or `wayland` on Linux, none without a display; `quartz` on macOS and `win32` on Windows
+    pub display_server: Option<&'static str>,
+    /// `XDG_SESSION_TYPE`
+    pub session_type: Option<String>,
+    /// `XDG_CURRENT_DESKTOP`
+    pub desktop: Option<String>,
+    pub compositor: Option<String>,
+    /// `Flatpak` or `Snap`
+    pub sandbox: Option<&'static str>,
+    pub portals: Vec<Portal>,
+    pub sockets: Vec<Socket>,
+    pub container: Option<Container>,
+}
+
+#[derive(Serialize)]
+pub struct Portal {
+    pub interface: &'static str,
+    pub available: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    /// Why it isn't available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Socket {
+    /// `wayland` or `x11`
+    pub kind: &'static str,
+    pub path: String,
+    /// Permission bits, in octal
+    pub mode: Option<String>,
+    /// Owner uid
+    pub owner: Option<u32>,
+    pub connectable: bool,
+    /// The process serving it, from the connection's peer credentials
+    pub server: Option<String>,
+    /// Why it couldn't be looked at or connected to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Container {
+    /// `docker`, `podman`, `kubernetes` or the `container` variable
+    pub runtime: String,
+    /// `NVIDIA_VISIBLE_DEVICES`
+    pub nvidia_visible_devices: Option<String>,
+    /// `/dev/nvidia*` device nodes
+    pub devices: Vec<String>,
+    /// GPUs nvidia-smi sees
+    pub gpus: Option<usize>,
+    /// Why nvidia-smi sees none, and what the container is likely missing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub fn detect() -> Environment {
+    platform::detect()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::os::unix::net::UnixStream;
+    use std::path::{Path, PathBuf};
+
+    use super::{Container, Environment, Portal, Socket};
+    use crate::{gpu, portal, wayland};
+
+    /// Environment variables compositors set for their own clients
+    const COMPOSITOR_VARIABLES: [(&str, &str); 3] =
+        [("SWAYSOCK", "sway"), ("HYPRLAND_INSTANCE_SIGNATURE", "Hyprland"), ("NIRI_SOCKET", "niri")];
+
+    /// Files container runtimes leave in the root filesystem
+    const RUNTIME_FILES: [(&str, &str); 2] = [("/.dockerenv", "docker"), ("/run/.containerenv", "podman")];
+
+    /// Words in init's cgroup path that give the runtime away
+    const RUNTIME_CGROUPS: [(&str, &str); 4] =
+        [("kubepods", "kubernetes"), ("docker", "docker"), ("libpod", "podman"), ("lxc", "lxc")];
+
+    fn var(name: &str) -> Option<String> {
+        std::env::var(name).ok().filter(|value| !value.is_empty())
+    }
+
+    pub fn detect() -> Environment {
+        let session_type = var("XDG_SESSION_TYPE");
+        let display_server = match session_type.as_deref() {
+            Some("wayland") => Some("wayland"),
+            Some("x11") => Some("x11"),
+            _ if var("WAYLAND_DISPLAY").is_some() => Some("wayland"),
+            _ if var("DISPLAY").is_some() => Some("x11"),
+            _ => None,
+        };
+        let mut sockets = Vec::new();
+        if var("WAYLAND_DISPLAY").is_some() {
+            sockets.push(match wayland::socket_path() {
+                Ok(path) => socket("wayland", &path),
+                Err(e) => unreachable_socket("wayland", String::new(), e),
+            });
+        }
+        if let Some(display) = var("DISPLAY") {
+            sockets.push(match x11_socket_path(&display) {
+                Some(path) => socket("x11", &path),
+                None => unreachable_socket("x11", display, "not a local display, so not checked".to_string()),
+            });
+        }
+        let compositor = sockets
+            .iter()
+            .find(|socket| socket.kind == "wayland")
+            .and_then(|socket| socket.server.clone())
+            .or_else(|| {
+                COMPOSITOR_VARIABLES
+                    .iter()
+                    .find(|(name, _)| var(name).is_some())
+                    .map(|(_, compositor)| compositor.to_string())
+            });
+        let portals = portal::versions()
+            .into_iter()
+            .map(|(interface, version)| Portal {
+                interface,
+                available: version.is_ok(),
+                version: version.as_ref().ok().copied(),
+                reason: version.err(),
+            })
+            .collect();
+        Environment {
+            platform: std::env::consts::OS,
+            display_server,
+            session_type,
+            desktop: var("XDG_CURRENT_DESKTOP"),
+            compositor,
+            sandbox: portal::sandbox(),
+            portals,
+            sockets,
+            container: container(),
+        }
+    }
+
+    fn container() -> Option<Container> {
+        let runtime = var("container")
+            .or_else(|| {
+                RUNTIME_FILES
+                    .iter()
+                    .find(|(path, _)| Path::new(path).exists())
+                    .map(|(_, runtime)| runtime.to_string())
+            })
+            .or_else(|| var("KUBERNETES_SERVICE_HOST").map(|_| "kubernetes".to_string()))
+            .or_else(|| {
+                let cgroup = fs::read_to_string("/proc/1/cgroup").ok()?;
+                RUNTIME_CGROUPS
+                    .iter()
+                    .find(|(word, _)| cgroup.contains(word))
+                    .map(|(_, runtime)| runtime.to_string())
+            })?;
+        let mut devices: Vec<String> = fs::read_dir("/dev")
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("nvidia"))
+            .map(|entry| entry.path().display().to_string())
+            .collect();
+        devices.sort();
+        let nvidia_visible_devices = var("NVIDIA_VISIBLE_DEVICES");
+        let (gpus, error) = match gpu::query() {
+            Ok(gpus) if !gpus.is_empty() => (Some(gpus.len()), None),
+            Ok(_) => (Some(0), Some(missing_gpus("nvidia-smi lists no GPU", &devices, &nvidia_visible_devices))),
+            Err(e) => (None, Some(missing_gpus(&e, &devices, &nvidia_visible_devices))),
+        };
+        Some(Container { runtime, nvidia_visible_devices, devices, gpus, error })
+    }
+
+    /// `error`, followed by the likeliest reason the container has no GPU
+    fn missing_gpus(error: &str, devices: &[String], visible_devices: &Option<String>) -> String {
+        let reason = match visible_devices.as_deref() {
+            Some("void" | "none") => "NVIDIA_VISIBLE_DEVICES hides every GPU".to_string(),
+            _ if devices.is_empty() => {
+                "no /dev/nvidia* devices were passed in; run the container with the NVIDIA Container Toolkit \
+                 (--gpus all, or --device nvidia.com/gpu=all with CDI)"
+                    .to_string()
+            }
+            _ => match fs::File::open("/dev/nvidiactl") {
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    "the device cgroup doesn't allow opening /dev/nvidiactl".to_string()
+                }
+                Err(e) => format!("cannot open /dev/nvidiactl: {}", e),
+                // The nodes are there, so it's the driver libraries that weren't injected
+                Ok(_) => "the driver's user-space libraries may not be mounted in the container".to_string(),
+            },
+        };
+        format!("{}; {}", error, reason)
+    }
+
+    /// The socket of a local X display such as `:0`, `:1.0` or `unix:0`
+    fn x11_socket_path(display: &str) -> Option<PathBuf> {
+        let (host, number) = display.rsplit_once(':')?;
+        if !host.is_empty() && host != "unix" {
+            return None;
+        }
+        let number = number.split('.').next()?;
+        number.parse::<u32>().ok()?;
+        Some(PathBuf::from(format!("/tmp/.X11-unix/X{}", number)))
+    }
+
+    fn unreachable_socket(kind: &'static str, path: String, error: String) -> Socket {
+        Socket {
+            kind,
+            path,
+            mode: None,
+            owner: None,
+            connectable: false,
+            server: None,
+            error: Some(error),
+        }
+    }
+
+    fn socket(kind: &'static str, path: &Path) -> Socket {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => return unreachable_socket(kind, path.display().to_string(), format!("cannot stat it: {}", e)),
+        };
+        let mut socket = Socket {
+            mode: Some(format!("{:04o}", metadata.permissions().mode() & 0o7777)),
+            owner: Some(metadata.uid()),
+            ..unreachable_socket(kind, path.display().to_string(), String::new())
+        };
+        match UnixStream::connect(path) {
+            Ok(stream) => {
+                socket.connectable = true;
+                socket.server = peer_process(&stream);
+                socket.error = None;
+            }
+            Err(e) => socket.error = Some(format!("cannot connect to it: {}", e)),
+        }
+        socket
+    }
+
+    /// The command name of the process at the other end of `stream`, if it can be seen
+    fn peer_process(stream: &UnixStream) -> Option<String> {
+        let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                (&mut credentials as *mut libc::ucred).cast(),
+                &mut len,
+            )
+        };
+        if result != 0 || credentials.pid <= 0 {
+            return None;
+        }
+        let comm = fs::read_to_string(format!("/proc/{}/comm", credentials.pid)).ok()?;
+        Some(comm.trim_end().to_string()).filter(|comm| !comm.is_empty())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::Environment;
+
+    pub fn detect() -> Environment {
+        Environment {
+            platform: std::env::consts::OS,
+            display_server: Some(if cfg!(target_os = "macos") { "quartz" } else { "win32" }),
+            session_type: None,
+            desktop: None,
+            compositor: None,
+            sandbox: None,
+            portals: Vec::new(),
+            sockets: Vec::new(),
+            container: None,
+        }
+    }
+}
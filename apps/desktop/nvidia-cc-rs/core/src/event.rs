@@ -0,0 +1,628 @@
+//! Wire model of everything the helper emits.
+//!
+//! Events are serialized as a flat object tagged by `event_type`, with the
+//! payload fields inline:
+//!
+//!   {"event_type":"KeyPress","key":"KeyA","name":"a","time":{...}}
+//!
+//! `--legacy-format` keeps the original shape for consumers that have not
+//! migrated yet, where the payload is a JSON-encoded string in `data`:
+//!
+//!   {"event_type":"KeyPress","name":"a","time":{...},"data":"{\"key\":\"KeyA\"}"}
+//!
+//! `time` is serde's `{"secs_since_epoch":...,"nanos_since_epoch":...}` unless
+//! `--time-format` asks for milliseconds since the epoch (`epoch-ms`), an
+//! RFC 3339 string in UTC (`rfc3339`) or microseconds on the clock the
+//! envelope's `mono_us` counts (`monotonic`).
+
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::time::SystemTime;
+
+use crate::clock;
+
+static LEGACY_FORMAT: AtomicBool = AtomicBool::new(false);
+
+/// How `time` is serialized
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeFormat {
+    /// serde's `SystemTime` struct, as before the option existed
+    #[default]
+    SystemTime,
+    EpochMs,
+    Rfc3339,
+    Monotonic,
+}
+
+impl TimeFormat {
+    pub const NAMES: [&'static str; 4] = ["system-time", "epoch-ms", "rfc3339", "monotonic"];
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "system-time" => Ok(TimeFormat::SystemTime),
+            "epoch-ms" => Ok(TimeFormat::EpochMs),
+            "rfc3339" => Ok(TimeFormat::Rfc3339),
+            "monotonic" => Ok(TimeFormat::Monotonic),
+            _ => Err(format!("unknown time format {:?} (expected {})", name, TimeFormat::NAMES.join(", "))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        TimeFormat::NAMES[self as usize]
+    }
+}
+
+static TIME_FORMAT: AtomicU8 = AtomicU8::new(TimeFormat::SystemTime as u8);
+
+pub fn set_time_format(format: TimeFormat) {
+    TIME_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn time_format() -> TimeFormat {
+    match TIME_FORMAT.load(Ordering::Relaxed) {
+        1 => TimeFormat::EpochMs,
+        2 => TimeFormat::Rfc3339,
+        3 => TimeFormat::Monotonic,
+        _ => TimeFormat::SystemTime,
+    }
+}
+
+/// An event's `time`, in the [`time_format`]
+struct Stamp(SystemTime);
+
+impl Serialize for Stamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match time_format() {
+            TimeFormat::SystemTime => self.0.serialize(serializer),
+            TimeFormat::EpochMs => serializer.serialize_u64(clock::epoch_ms(self.0)),
+            TimeFormat::Rfc3339 => serializer.serialize_str(&clock::rfc3339(self.0)),
+            TimeFormat::Monotonic => serializer.serialize_u64(clock::monotonic_us_at(self.0)),
+        }
+    }
+}
+
+/// Serialize every subsequent event in the legacy `data`-string shape
+pub fn set_legacy_format(legacy: bool) {
+    LEGACY_FORMAT.store(legacy, Ordering::Relaxed);
+}
+
+pub fn legacy_format() -> bool {
+    LEGACY_FORMAT.load(Ordering::Relaxed)
+}
+
+pub struct Event<'a> {
+    pub time: SystemTime,
+    pub kind: EventKind<'a>,
+}
+
+impl<'a> Event<'a> {
+    /// Event stamped with the current wall-clock time
+    pub fn now(kind: EventKind<'a>) -> Self {
+        Event {
+            time: SystemTime::now(),
+            kind,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event_type")]
+pub enum EventKind<'a> {
+    KeyPress(Key<'a>),
+    KeyRelease(Key<'a>),
+    Error {
+        error: String,
+        message: String,
+    },
+    Capabilities(Value),
+    Environment(Value),
+    HttpListening {
+        addr: String,
+        token: String,
+    },
+    DroppedEvents {
+        count: u64,
+    },
+    Paused {
+        source: String,
+    },
+    Resumed {
+        source: String,
+    },
+    ClockSync {
+        boot_epoch_ms: u64,
+        /// Monotonic sample paired with `wall_epoch_ms` (`mono_us` in the legacy payload;
+        /// renamed so it doesn't collide with the envelope's own `mono_us`)
+        sample_mono_us: u64,
+        wall_epoch_ms: u64,
+        pid: u32,
+    },
+    ParentExited {
+        parent_pid: u32,
+    },
+    RealtimeStatus {
+        source: String,
+        elevated: bool,
+        method: Option<&'static str>,
+        memory_locked: bool,
+        error: Option<String>,
+    },
+    ReplayGap {
+        since_seq: u64,
+        oldest_seq: u64,
+    },
+    /// A stdin `write` was accepted; `position` writes run before it
+    WriteQueued {
+        position: usize,
+    },
+    WriteProgress {
+        chars_done: usize,
+        total: usize,
+    },
+    WriteComplete {
+        duration_ms: u64,
+        chars: usize,
+    },
+    WriteCancelled {
+        chars_done: usize,
+        total: usize,
+    },
+    /// The clipboard changed while text was being pasted through it, so what
+    /// it held before (in `formats`) was not put back
+    ClipboardConflict {
+        formats: Vec<String>,
+    },
+    BackendSwitched {
+        from: String,
+        to: String,
+        reason: String,
+    },
+    /// The active keyboard layout or input source changed; names are platform-specific
+    /// (`fr(oss)` from xkb, `com.apple.keylayout.French`, `fr-FR` on Windows)
+    LayoutChanged {
+        layout: String,
+        previous: String,
+    },
+    /// Focus moved to another window, or the focused window's title changed (sway)
+    ActiveWindowChanged {
+        title: String,
+        /// Wayland app_id or X11 WM_CLASS class; executable name on Windows and macOS
+        app_id: String,
+        pid: Option<u32>,
+    },
+    /// A `--hotkeys` definition's keys are all down
+    HotkeyTriggered {
+        id: String,
+    },
+    /// The first key of a triggered hotkey came up
+    HotkeyReleased {
+        id: String,
+    },
+    /// A `"mode": "hold"` hotkey has been held past its threshold
+    HoldStart {
+        id: String,
+    },
+    /// A started hold was released; `duration_ms` counts from the press
+    HoldEnd {
+        id: String,
+        duration_ms: u64,
+    },
+    /// A `"mode": "double_tap"` hotkey was tapped twice; `interval_ms` is the gap between the presses
+    DoubleTap {
+        id: String,
+        key: String,
+        interval_ms: u64,
+    },
+    /// The config file was re-read; `hotkeys` and `hotstrings` count the definitions now active
+    ConfigReloaded {
+        source: String,
+        hotkeys: usize,
+        hotstrings: usize,
+    },
+    /// The typed text ended with a hotstring's trigger; `expanded` when the helper is replacing it
+    HotstringTriggered {
+        id: String,
+        trigger: String,
+        expanded: bool,
+    },
+    /// A `"mode": "long_press"` hotkey has been held for `threshold_ms`
+    LongPress {
+        id: String,
+        key: String,
+        threshold_ms: u64,
+    },
+    /// A `"mode": "modifier_tap"` hotkey's keys were pressed and released with no other key in between
+    ModifierTap {
+        id: String,
+        key: String,
+    },
+    /// Every step of a `"sequence"` hotkey was pressed in order, each within its timeout
+    SequenceTriggered {
+        id: String,
+    },
+    /// A stdin `hotkey add` registered this hotkey
+    HotkeyAdded {
+        id: String,
+    },
+    /// A stdin `hotkey remove` unregistered this hotkey
+    HotkeyRemoved {
+        id: String,
+    },
+    /// Reply to a stdin `hotkey list`: the active hotkeys, in matching order
+    HotkeyList {
+        hotkeys: Vec<Value>,
+    },
+    /// A device's listener failed and its event node was reopened; `attempts` counts the failures it took
+    DeviceRecovered {
+        path: String,
+        name: String,
+        attempts: u32,
+    },
+    /// A device failed `attempts` times in a row and is no longer listened on
+    DeviceLost {
+        path: String,
+        name: String,
+        attempts: u32,
+        error: String,
+    },
+    /// The system is about to suspend
+    SystemSuspend {},
+    /// The system woke from suspend; evdev devices are reopened
+    SystemResume {},
+    /// The session was locked; with `--pause-when-locked` a `Paused` follows
+    SessionLocked {},
+    SessionUnlocked {},
+    /// A secure text field took focus; no key events are emitted until `SecureInputEnded`
+    SecureInputActive {},
+    SecureInputEnded {},
+    /// A key went down or up; stands in for `KeyPress`/`KeyRelease` of keys `--privacy allowlist` withholds
+    KeyActivity {
+        pressed: bool,
+    },
+    /// `dropped` key events went unemitted over `--max-event-rate` since the last one let through
+    RateLimited {
+        dropped: u64,
+    },
+    /// A gamepad button went down or up, with `--gamepads`
+    GamepadButton {
+        button: String,
+        pressed: bool,
+    },
+    /// Caps Lock, Num Lock or Scroll Lock (`capslock`, `numlock`, `scrolllock`) turned on or off
+    LockChanged {
+        lock: String,
+        on: bool,
+    },
+    /// Answer to `leds get` on stdin: each LED's state
+    LedState {
+        leds: Value,
+    },
+    /// No input for `--idle-after` seconds; `idle_secs` so far
+    UserIdle {
+        idle_secs: u64,
+    },
+    /// Input after a `UserIdle`; `idle_secs` is about how long it was idle
+    UserActive {
+        idle_secs: u64,
+    },
+    /// Answer to `idle` on stdin: seconds since the last input, and what said so (see [`crate::idle`])
+    IdleTime {
+        idle_secs: u64,
+        source: &'static str,
+    },
+    /// `inhibit-sleep start` took effect: the system won't suspend for idleness
+    SleepInhibited {
+        reason: String,
+    },
+    /// `inhibit-sleep stop` released the inhibitor
+    SleepAllowed {},
+    /// Answer to `context` on stdin: the focused text field's text around the caret (see [`crate::context`])
+    TextContext {
+        context: Value,
+    },
+    /// Answer to `stats` on stdin: the internal counters (see [`crate::stats`])
+    Stats {
+        stats: Value,
+    },
+    /// Answer to `dump` on stdin: the `--record-last` events were written to `path`
+    EventsDumped {
+        path: String,
+        events: usize,
+    },
+    /// `--metrics-addr` is serving Prometheus metrics on `addr`
+    MetricsListening {
+        addr: String,
+    },
+    /// `--tcp` is serving clients presenting `token` on `addr` (see [`crate::tcp`])
+    TcpListening {
+        addr: String,
+        token: String,
+        tls: bool,
+    },
+    /// `--driver-guidance`: nvidia-smi can't be asked, so the driver is likely missing or broken
+    DriverProblem {
+        error: String,
+        download_url: &'a str,
+    },
+}
+
+/// Payload of `KeyPress`/`KeyRelease`, borrowed so the capture hot path doesn't allocate
+#[derive(Serialize)]
+pub struct Key<'a> {
+    /// rdev-style name of the physical key (`KeyA`, `ControlLeft`), independent of layout
+    pub key: &'a str,
+    /// Character or label reported by the platform, when there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'a str>,
+    /// Keysym the active layout maps the key to (`q` for `KeyA` on AZERTY); evdev backend only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keysym: Option<&'a str>,
+    /// evdev key code, Windows scan code or macOS key code; only with `--raw-scancodes`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scancode: Option<u32>,
+    /// Character the key types under the active layout and modifiers; evdev backend only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<&'a str>,
+    /// NumLock state the key was typed under; keypad keys on Linux and Windows only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_lock: Option<bool>,
+    /// Injected by this helper rather than typed by the user; only serialized when true
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub synthetic: bool,
+    /// Milliseconds since the matching press, by the input timestamps; `KeyRelease` only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hold_ms: Option<u64>,
+}
+
+impl EventKind<'_> {
+    fn event_type(&self) -> &'static str {
+        match self {
+            EventKind::KeyPress(_) => "KeyPress",
+            EventKind::KeyRelease(_) => "KeyRelease",
+            EventKind::Error { .. } => "Error",
+            EventKind::Capabilities(_) => "Capabilities",
+            EventKind::Environment(_) => "Environment",
+            EventKind::HttpListening { .. } => "HttpListening",
+            EventKind::DroppedEvents { .. } => "DroppedEvents",
+            EventKind::Paused { .. } => "Paused",
+            EventKind::Resumed { .. } => "Resumed",
+            EventKind::ClockSync { .. } => "ClockSync",
+            EventKind::ParentExited { .. } => "ParentExited",
+            EventKind::RealtimeStatus { .. } => "RealtimeStatus",
+            EventKind::ReplayGap { .. } => "ReplayGap",
+            EventKind::WriteQueued { .. } => "WriteQueued",
+            EventKind::WriteProgress { .. } => "WriteProgress",
+            EventKind::WriteComplete { .. } => "WriteComplete",
+            EventKind::WriteCancelled { .. } => "WriteCancelled",
+            EventKind::ClipboardConflict { .. } => "ClipboardConflict",
+            EventKind::BackendSwitched { .. } => "BackendSwitched",
+            EventKind::LayoutChanged { .. } => "LayoutChanged",
+            EventKind::ActiveWindowChanged { .. } => "ActiveWindowChanged",
+            EventKind::HotkeyTriggered { .. } => "HotkeyTriggered",
+            EventKind::HotkeyReleased { .. } => "HotkeyReleased",
+            EventKind::HoldStart { .. } => "HoldStart",
+            EventKind::HoldEnd { .. } => "HoldEnd",
+            EventKind::DoubleTap { .. } => "DoubleTap",
+            EventKind::ConfigReloaded { .. } => "ConfigReloaded",
+            EventKind::HotstringTriggered { .. } => "HotstringTriggered",
+            EventKind::LongPress { .. } => "LongPress",
+            EventKind::ModifierTap { .. } => "ModifierTap",
+            EventKind::SequenceTriggered { .. } => "SequenceTriggered",
+            EventKind::HotkeyAdded { .. } => "HotkeyAdded",
+            EventKind::HotkeyRemoved { .. } => "HotkeyRemoved",
+            EventKind::HotkeyList { .. } => "HotkeyList",
+            EventKind::DeviceRecovered { .. } => "DeviceRecovered",
+            EventKind::DeviceLost { .. } => "DeviceLost",
+            EventKind::SystemSuspend {} => "SystemSuspend",
+            EventKind::SystemResume {} => "SystemResume",
+            EventKind::SessionLocked {} => "SessionLocked",
+            EventKind::SessionUnlocked {} => "SessionUnlocked",
+            EventKind::SecureInputActive {} => "SecureInputActive",
+            EventKind::SecureInputEnded {} => "SecureInputEnded",
+            EventKind::KeyActivity { .. } => "KeyActivity",
+            EventKind::RateLimited { .. } => "RateLimited",
+            EventKind::GamepadButton { .. } => "GamepadButton",
+            EventKind::LockChanged { .. } => "LockChanged",
+            EventKind::LedState { .. } => "LedState",
+            EventKind::UserIdle { .. } => "UserIdle",
+            EventKind::UserActive { .. } => "UserActive",
+            EventKind::IdleTime { .. } => "IdleTime",
+            EventKind::SleepInhibited { .. } => "SleepInhibited",
+            EventKind::SleepAllowed {} => "SleepAllowed",
+            EventKind::TextContext { .. } => "TextContext",
+            EventKind::Stats { .. } => "Stats",
+            EventKind::EventsDumped { .. } => "EventsDumped",
+            EventKind::MetricsListening { .. } => "MetricsListening",
+            EventKind::TcpListening { .. } => "TcpListening",
+            EventKind::DriverProblem { .. } => "DriverProblem",
+        }
+    }
+
+    /// `name` and `data` as the legacy format carried them
+    fn legacy_name_and_data(&self) -> (Option<&str>, Value) {
+        match self {
+            EventKind::KeyPress(key) | EventKind::KeyRelease(key) => (key.name, json!({"key": key.key})),
+            EventKind::Error { error, message } => (Some(error), json!({"error": error, "message": message})),
+            EventKind::Capabilities(capabilities) => (None, capabilities.clone()),
+            EventKind::Environment(environment) => (None, environment.clone()),
+            EventKind::HttpListening { addr, token } => (None, json!({"addr": addr, "token": token})),
+            EventKind::DroppedEvents { count } => (None, json!({"count": count})),
+            EventKind::Paused { source } => (Some(source), json!({"paused": true})),
+            EventKind::Resumed { source } => (Some(source), json!({"paused": false})),
+            EventKind::ClockSync {
+                boot_epoch_ms,
+                sample_mono_us,
+                wall_epoch_ms,
+                pid,
+            } => (
+                None,
+                json!({
+                    "boot_epoch_ms": boot_epoch_ms,
+                    "mono_us": sample_mono_us,
+                    "wall_epoch_ms": wall_epoch_ms,
+                    "pid": pid,
+                }),
+            ),
+            EventKind::ParentExited { parent_pid } => (None, json!({"parent_pid": parent_pid})),
+            EventKind::RealtimeStatus {
+                source,
+                elevated,
+                method,
+                memory_locked,
+                error,
+            } => (
+                Some(source),
+                json!({
+                    "elevated": elevated,
+                    "method": method,
+                    "memory_locked": memory_locked,
+                    "error": error,
+                }),
+            ),
+            EventKind::ReplayGap { since_seq, oldest_seq } => {
+                (None, json!({"since_seq": since_seq, "oldest_seq": oldest_seq}))
+            }
+            EventKind::WriteQueued { position } => (None, json!({"position": position})),
+            EventKind::WriteProgress { chars_done, total } => {
+                (None, json!({"chars_done": chars_done, "total": total}))
+            }
+            EventKind::WriteComplete { duration_ms, chars } => {
+                (None, json!({"duration_ms": duration_ms, "chars": chars}))
+            }
+            EventKind::WriteCancelled { chars_done, total } => {
+                (None, json!({"chars_done": chars_done, "total": total}))
+            }
+            EventKind::ClipboardConflict { formats } => (None, json!({"formats": formats})),
+            EventKind::BackendSwitched { from, to, reason } => {
+                (None, json!({"from": from, "to": to, "reason": reason}))
+            }
+            EventKind::LayoutChanged { layout, previous } => {
+                (Some(layout), json!({"layout": layout, "previous": previous}))
+            }
+            EventKind::ActiveWindowChanged { title, app_id, pid } => {
+                (Some(app_id), json!({"title": title, "app_id": app_id, "pid": pid}))
+            }
+            EventKind::HotkeyTriggered { id }
+            | EventKind::HotkeyReleased { id }
+            | EventKind::HoldStart { id }
+            | EventKind::SequenceTriggered { id }
+            | EventKind::HotkeyAdded { id }
+            | EventKind::HotkeyRemoved { id } => (Some(id), json!({"id": id})),
+            EventKind::HotkeyList { hotkeys } => (None, json!({"hotkeys": hotkeys})),
+            EventKind::ConfigReloaded { source, hotkeys, hotstrings } => {
+                (Some(source), json!({"source": source, "hotkeys": hotkeys, "hotstrings": hotstrings}))
+            }
+            EventKind::HotstringTriggered { id, trigger, expanded } => {
+                (Some(id), json!({"id": id, "trigger": trigger, "expanded": expanded}))
+            }
+            EventKind::LongPress { id, key, threshold_ms } => {
+                (Some(id), json!({"id": id, "key": key, "threshold_ms": threshold_ms}))
+            }
+            EventKind::ModifierTap { id, key } => (Some(id), json!({"id": id, "key": key})),
+            EventKind::HoldEnd { id, duration_ms } => (Some(id), json!({"id": id, "duration_ms": duration_ms})),
+            EventKind::DoubleTap { id, key, interval_ms } => {
+                (Some(id), json!({"id": id, "key": key, "interval_ms": interval_ms}))
+            }
+            EventKind::DeviceRecovered { path, name, attempts } => {
+                (Some(name), json!({"path": path, "attempts": attempts}))
+            }
+            EventKind::DeviceLost { path, name, attempts, error } => {
+                (Some(name), json!({"path": path, "attempts": attempts, "error": error}))
+            }
+            EventKind::SystemSuspend {}
+            | EventKind::SystemResume {}
+            | EventKind::SessionLocked {}
+            | EventKind::SessionUnlocked {}
+            | EventKind::SecureInputActive {}
+            | EventKind::SecureInputEnded {}
+            | EventKind::SleepAllowed {} => (None, json!({})),
+            EventKind::KeyActivity { pressed } => (None, json!({"pressed": pressed})),
+            EventKind::RateLimited { dropped } => (None, json!({"dropped": dropped})),
+            EventKind::GamepadButton { button, pressed } => {
+                (Some(button), json!({"button": button, "pressed": pressed}))
+            }
+            EventKind::LockChanged { lock, on } => (Some(lock), json!({"lock": lock, "on": on})),
+            EventKind::LedState { leds } => (None, json!({"leds": leds})),
+            EventKind::UserIdle { idle_secs } | EventKind::UserActive { idle_secs } => {
+                (None, json!({"idle_secs": idle_secs}))
+            }
+            EventKind::IdleTime { idle_secs, source } => (None, json!({"idle_secs": idle_secs, "source": source})),
+            EventKind::SleepInhibited { reason } => (None, json!({"reason": reason})),
+            EventKind::TextContext { context } => (None, json!({"context": context})),
+            EventKind::Stats { stats } => (None, json!({"stats": stats})),
+            EventKind::EventsDumped { path, events } => (None, json!({"path": path, "events": events})),
+            EventKind::MetricsListening { addr } => (None, json!({"addr": addr})),
+            EventKind::TcpListening { addr, token, tls } => (None, json!({"addr": addr, "token": token, "tls": tls})),
+            EventKind::DriverProblem { error, download_url } => {
+                (None, json!({"error": error, "download_url": download_url}))
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Typed<'e, 'a> {
+    #[serde(flatten)]
+    kind: &'e EventKind<'a>,
+    time: Stamp,
+}
+
+impl Serialize for Event<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !legacy_format() {
+            return Typed {
+                kind: &self.kind,
+                time: Stamp(self.time),
+            }
+            .serialize(serializer);
+        }
+
+        let mut state = serializer.serialize_struct("KeyboardEvent", 8)?;
+        state.serialize_field("event_type", self.kind.event_type())?;
+        match &self.kind {
+            // Streamed straight into the output buffer: key events are the hot path
+            EventKind::KeyPress(key) | EventKind::KeyRelease(key) => {
+                state.serialize_field("name", &key.name)?;
+                state.serialize_field("time", &Stamp(self.time))?;
+                state.serialize_field("data", &LegacyKeyData(key.key))?;
+                match key.scancode {
+                    Some(scancode) => state.serialize_field("scancode", &scancode)?,
+                    None => state.skip_field("scancode")?,
+                }
+                match key.num_lock {
+                    Some(num_lock) => state.serialize_field("num_lock", &num_lock)?,
+                    None => state.skip_field("num_lock")?,
+                }
+                if key.synthetic {
+                    state.serialize_field("synthetic", &true)?;
+                } else {
+                    state.skip_field("synthetic")?;
+                }
+                match key.hold_ms {
+                    Some(hold_ms) => state.serialize_field("hold_ms", &hold_ms)?,
+                    None => state.skip_field("hold_ms")?,
+                }
+            }
+            kind => {
+                let (name, data) = kind.legacy_name_and_data();
+                state.serialize_field("name", &name)?;
+                state.serialize_field("time", &Stamp(self.time))?;
+                state.serialize_field("data", &data.to_string())?;
+            }
+        }
+        state.end()
+    }
+}
+
+/// The JSON-encoded `{"key": ...}` string legacy consumers expect in `data`
+struct LegacyKeyData<'a>(&'a str);
+
+impl Serialize for LegacyKeyData<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Key names are plain identifiers, so they never need escaping inside the inner JSON
+        serializer.collect_str(&format_args!("{{\"key\":\"{}\"}}", self.0))
+    }
+}
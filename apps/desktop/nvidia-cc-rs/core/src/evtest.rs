@@ -0,0 +1,85 @@
+//! Replay of recorded evdev dumps (`decode <dump>`).
+//!
+//! Dumps use the line format printed by `evtest`, so a capture from a user's
+//! keyboard can be attached to a bug report and checked into the golden corpus
+//! under `tests/corpus/` unchanged:
+//!
+//!   Event: time 1700000000.000100, type 1 (EV_KEY), code 30 (KEY_A), value 1
+//!
+//! Every other line (device header, SYN_REPORT separators) is ignored.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::hotkeys;
+use crate::hotstrings;
+use crate::layout;
+use crate::event::EventKind;
+use crate::numpad;
+use crate::output;
+use crate::privacy;
+use crate::remap;
+
+/// evdev event type for key and button events
+const EV_KEY: u16 = 1;
+
+/// One `Event:` line from an evtest dump
+struct DumpedEvent {
+    /// Kernel timestamp, seconds since the epoch
+    time: Duration,
+    event_type: u16,
+    code: u16,
+    value: i32,
+}
+
+/// Parse the numeric type/code/value out of an evtest `Event:` line
+fn parse_line(line: &str) -> Option<DumpedEvent> {
+    let line = line.trim().strip_prefix("Event: time ")?;
+    let field = |label: &str| -> Option<&str> {
+        let rest = &line[line.find(label)? + label.len()..];
+        Some(rest.split([' ', ',']).next().unwrap_or(rest))
+    };
+
+    let time = line.split(',').next()?;
+    let (seconds, micros) = time.split_once('.')?;
+    Some(DumpedEvent {
+        time: Duration::from_secs(seconds.parse().ok()?) + Duration::from_micros(micros.parse().ok()?),
+        event_type: field(", type ")?.parse().ok()?,
+        code: field(", code ")?.parse().ok()?,
+        value: field(", value ")?.parse().ok()?,
+    })
+}
+
+/// Feed every key event in `path` through the live mapping and hotkey engine and emit the results
+pub fn decode(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let dump = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+
+    for event in dump.lines().filter_map(parse_line) {
+        if event.event_type != EV_KEY {
+            continue;
+        }
+        let key = evdev::KeyCode::new(event.code);
+        // A dump has no LED state, so NumLock follows the NumLock presses in it
+        let num_lock = numpad::is_keypad(crate::listener::evdev_key_to_rdev_name(key)).then(numpad::state).flatten();
+        let resolved = layout::resolve(event.code, event.value);
+        let name = remap::apply(&[], crate::listener::evdev_key_to_rdev_name(key));
+        if let Some(key_event) = crate::listener::key_event_from_evdev(
+            key,
+            &name,
+            event.value,
+            resolved.as_ref(),
+            num_lock,
+            SystemTime::UNIX_EPOCH + event.time,
+        ) {
+            privacy::emit_key(&key_event);
+            if let EventKind::KeyPress(key) | EventKind::KeyRelease(key) = &key_event.kind {
+                hotkeys::observe(event.value == 1, numpad::effective(key.key, num_lock), event.time);
+                hotstrings::observe(event.value == 1, key.key, key.text);
+            }
+        }
+    }
+
+    output::flush(Duration::from_secs(5));
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! C interface for loading the helper in-process (`--features cdylib`).
+//!
+//! Builds `libnvidia_cc_core` as a shared library; `include/nvidia_cc.h` is
+//! generated alongside by cbindgen. Events are delivered as the same JSON
+//! objects the binary prints, one per callback call.
+//!
+//!   ncc_listen(callback) - load the config file's hotkeys and hotstrings and
+//!                          start capturing on a background thread
+//!   ncc_write(text)      - type UTF-8 text, blocking until done
+//!   ncc_shutdown()       - stop calling the callback and drop every hotkey
+//!                          and hotstring
+//!
+//! Functions return 0 on success and -1 on failure, with the reason on stderr.
+//! The callback runs on a delivery thread of its own, so it may call
+//! `ncc_write`; it must not call `ncc_shutdown`, which waits for it to return.
+//! See [`crate::embed`] for what keeps running after a shutdown.
+
+use std::ffi::{c_char, CStr, CString};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+use crate::embed;
+use crate::TextInjector;
+
+/// Receives each event as a NUL-terminated UTF-8 JSON object, valid for the call
+pub type NccEventCallback = Option<extern "C" fn(event_json: *const c_char)>;
+
+/// The callback events go to; `None` once shut down
+static CALLBACK: Mutex<NccEventCallback> = Mutex::new(None);
+
+/// Queues events for the delivery thread, which calls [`CALLBACK`]
+fn delivery() -> Sender<CString> {
+    static DELIVERY: OnceLock<Sender<CString>> = OnceLock::new();
+    DELIVERY
+        .get_or_init(|| {
+            let (delivery, events) = mpsc::channel::<CString>();
+            thread::spawn(move || {
+                for event in events {
+                    // Held during the call, so ncc_shutdown returns only once no callback is running
+                    if let Some(callback) = *CALLBACK.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) {
+                        callback(event.as_ptr());
+                    }
+                }
+            });
+            delivery
+        })
+        .clone()
+}
+
+/// Start capturing, delivering every event to `callback`
+#[no_mangle]
+pub extern "C" fn ncc_listen(callback: NccEventCallback) -> i32 {
+    if callback.is_none() {
+        eprintln!("!error: ncc_listen needs a callback");
+        return -1;
+    }
+    *CALLBACK.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = callback;
+    let delivery = delivery();
+    let result = embed::start(move |event| {
+        // Serialized JSON never contains NUL
+        if let Ok(event) = CString::new(event) {
+            delivery.send(event).ok();
+        }
+    });
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("!error: {}", e);
+            -1
+        }
+    }
+}
+
+/// Type `text`, a NUL-terminated UTF-8 string
+///
+/// # Safety
+///
+/// `text` must be null or point to a NUL-terminated string that stays valid for the call.
+#[no_mangle]
+pub unsafe extern "C" fn ncc_write(text: *const c_char) -> i32 {
+    if text.is_null() {
+        eprintln!("!error: ncc_write needs text");
+        return -1;
+    }
+    let Ok(text) = unsafe { CStr::from_ptr(text) }.to_str() else {
+        eprintln!("!error: ncc_write text is not UTF-8");
+        return -1;
+    };
+    match TextInjector::new().write(text) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("!error: Write failed: {}", e);
+            -1
+        }
+    }
+}
+
+/// Stop delivering events and matching hotkeys and hotstrings
+#[no_mangle]
+pub extern "C" fn ncc_shutdown() {
+    embed::stop();
+    // Events already queued for the delivery thread are dropped from here on
+    *CALLBACK.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
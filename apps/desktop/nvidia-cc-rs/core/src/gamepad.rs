@@ -0,0 +1,242 @@
+//! Gamepad buttons, with `--gamepads`.
+//!
+//! Every button press and release is emitted as `GamepadButton{button,
+//! pressed}` and fed to the hotkey engine as `Gamepad<button>`, so
+//! `keys = "GamepadRightBumper"` binds push-to-talk to a controller. Face
+//! buttons are named by position (`South` is A on an Xbox pad, Cross on a
+//! PlayStation one); the others are `LeftBumper`, `RightBumper`,
+//! `LeftTrigger`, `RightTrigger`, `Select`, `Start`, `Guide`, `LeftStick`,
+//! `RightStick` and `DPadUp`/`Down`/`Left`/`Right`.
+//!
+//! Linux reads every evdev device with gamepad buttons, d-pads reported as a
+//! hat included; Windows polls the four XInput slots, which have no `Guide`.
+//! Analog triggers count as pressed past the XInput threshold. macOS is not
+//! supported.
+
+use crate::control;
+use crate::event::{Event, EventKind};
+use crate::hotkeys;
+use crate::session;
+use crate::stream;
+
+/// Start reading gamepads on background threads
+pub fn watch() {
+    platform::watch();
+}
+
+#[cfg_attr(not(any(windows, target_os = "linux")), allow(dead_code))]
+fn report(button: &str, pressed: bool) {
+    if control::is_paused() || !session::owns_input() {
+        return;
+    }
+    stream::emit(&Event::now(EventKind::GamepadButton {
+        button: button.to_string(),
+        pressed,
+    }));
+    hotkeys::observe(pressed, &format!("Gamepad{}", button), hotkeys::now());
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use evdev::{AbsoluteAxisCode, Device, EventSummary, KeyCode};
+    use std::path::PathBuf;
+    use std::thread;
+
+    use super::report;
+    use crate::input_access;
+
+    fn button_name(key: KeyCode) -> Option<&'static str> {
+        let name = match key {
+            KeyCode::BTN_SOUTH => "South",
+            KeyCode::BTN_EAST => "East",
+            KeyCode::BTN_NORTH => "North",
+            KeyCode::BTN_WEST => "West",
+            KeyCode::BTN_TL => "LeftBumper",
+            KeyCode::BTN_TR => "RightBumper",
+            KeyCode::BTN_TL2 => "LeftTrigger",
+            KeyCode::BTN_TR2 => "RightTrigger",
+            KeyCode::BTN_SELECT => "Select",
+            KeyCode::BTN_START => "Start",
+            KeyCode::BTN_MODE => "Guide",
+            KeyCode::BTN_THUMBL => "LeftStick",
+            KeyCode::BTN_THUMBR => "RightStick",
+            KeyCode::BTN_DPAD_UP => "DPadUp",
+            KeyCode::BTN_DPAD_DOWN => "DPadDown",
+            KeyCode::BTN_DPAD_LEFT => "DPadLeft",
+            KeyCode::BTN_DPAD_RIGHT => "DPadRight",
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    /// Negative and positive ends of a d-pad hat axis
+    fn hat_names(axis: AbsoluteAxisCode) -> Option<(&'static str, &'static str)> {
+        match axis {
+            AbsoluteAxisCode::ABS_HAT0X => Some(("DPadLeft", "DPadRight")),
+            AbsoluteAxisCode::ABS_HAT0Y => Some(("DPadUp", "DPadDown")),
+            _ => None,
+        }
+    }
+
+    fn is_gamepad(device: &Device) -> bool {
+        // BTN_GAMEPAD is BTN_SOUTH's other name
+        device.supported_keys().is_some_and(|keys| keys.contains(KeyCode::BTN_SOUTH))
+    }
+
+    fn read(path: PathBuf, mut device: Device) {
+        // Last direction reported per hat axis, to release it when the hat centres
+        let mut hats = [0i32; 2];
+        loop {
+            let events = match device.fetch_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    eprintln!("Gamepad {} stopped: {}", path.display(), e);
+                    return;
+                }
+            };
+            for event in events {
+                match event.destructure() {
+                    EventSummary::Key(_, key, _) if event.value() != 2 => {
+                        if let Some(button) = button_name(key) {
+                            report(button, event.value() == 1);
+                        }
+                    }
+                    EventSummary::AbsoluteAxis(_, axis, _) => {
+                        let Some((negative, positive)) = hat_names(axis) else {
+                            continue;
+                        };
+                        let last = &mut hats[(axis == AbsoluteAxisCode::ABS_HAT0Y) as usize];
+                        let direction = event.value().signum();
+                        if direction == *last {
+                            continue;
+                        }
+                        match *last {
+                            -1 => report(negative, false),
+                            1 => report(positive, false),
+                            _ => {}
+                        }
+                        match direction {
+                            -1 => report(negative, true),
+                            1 => report(positive, true),
+                            _ => {}
+                        }
+                        *last = direction;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub fn watch() {
+        let Ok(entries) = std::fs::read_dir("/dev/input") else {
+            eprintln!("Gamepad capture unavailable: cannot read /dev/input");
+            return;
+        };
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if !path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("event")) {
+                continue;
+            }
+            let Ok(device) = input_access::open(&path) else {
+                continue;
+            };
+            if !is_gamepad(&device) {
+                continue;
+            }
+            eprintln!("Found gamepad: {} ({})", device.name().unwrap_or("Unknown"), path.display());
+            thread::spawn(move || read(path, device));
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::thread;
+    use std::time::Duration;
+
+    use windows_sys::Win32::UI::Input::XboxController::{
+        XInputGetState, XINPUT_GAMEPAD_A, XINPUT_GAMEPAD_B, XINPUT_GAMEPAD_BACK, XINPUT_GAMEPAD_DPAD_DOWN,
+        XINPUT_GAMEPAD_DPAD_LEFT, XINPUT_GAMEPAD_DPAD_RIGHT, XINPUT_GAMEPAD_DPAD_UP, XINPUT_GAMEPAD_LEFT_SHOULDER,
+        XINPUT_GAMEPAD_LEFT_THUMB, XINPUT_GAMEPAD_RIGHT_SHOULDER, XINPUT_GAMEPAD_RIGHT_THUMB, XINPUT_GAMEPAD_START,
+        XINPUT_GAMEPAD_TRIGGER_THRESHOLD, XINPUT_GAMEPAD_X, XINPUT_GAMEPAD_Y, XINPUT_STATE,
+    };
+
+    use super::report;
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// Polls between looks at empty slots, which are slow to query
+    const RESCAN_EVERY: u32 = 100;
+
+    /// Bits of our button mask for the analog triggers, above XInput's own
+    const LEFT_TRIGGER: u32 = 1 << 16;
+    const RIGHT_TRIGGER: u32 = 1 << 17;
+
+    const BUTTONS: [(u32, &str); 16] = [
+        (XINPUT_GAMEPAD_A as u32, "South"),
+        (XINPUT_GAMEPAD_B as u32, "East"),
+        (XINPUT_GAMEPAD_X as u32, "West"),
+        (XINPUT_GAMEPAD_Y as u32, "North"),
+        (XINPUT_GAMEPAD_LEFT_SHOULDER as u32, "LeftBumper"),
+        (XINPUT_GAMEPAD_RIGHT_SHOULDER as u32, "RightBumper"),
+        (LEFT_TRIGGER, "LeftTrigger"),
+        (RIGHT_TRIGGER, "RightTrigger"),
+        (XINPUT_GAMEPAD_BACK as u32, "Select"),
+        (XINPUT_GAMEPAD_START as u32, "Start"),
+        (XINPUT_GAMEPAD_LEFT_THUMB as u32, "LeftStick"),
+        (XINPUT_GAMEPAD_RIGHT_THUMB as u32, "RightStick"),
+        (XINPUT_GAMEPAD_DPAD_UP as u32, "DPadUp"),
+        (XINPUT_GAMEPAD_DPAD_DOWN as u32, "DPadDown"),
+        (XINPUT_GAMEPAD_DPAD_LEFT as u32, "DPadLeft"),
+        (XINPUT_GAMEPAD_DPAD_RIGHT as u32, "DPadRight"),
+    ];
+
+    /// Buttons held on the pad in `slot`, or `None` when it isn't connected
+    fn held(slot: u32) -> Option<u32> {
+        let mut state: XINPUT_STATE = unsafe { std::mem::zeroed() };
+        if unsafe { XInputGetState(slot, &mut state) } != 0 {
+            return None;
+        }
+        let pad = state.Gamepad;
+        let threshold = XINPUT_GAMEPAD_TRIGGER_THRESHOLD as u8;
+        let mut held = pad.wButtons as u32;
+        if pad.bLeftTrigger > threshold {
+            held |= LEFT_TRIGGER;
+        }
+        if pad.bRightTrigger > threshold {
+            held |= RIGHT_TRIGGER;
+        }
+        Some(held)
+    }
+
+    pub fn watch() {
+        thread::spawn(|| {
+            let mut previous: [Option<u32>; 4] = [None; 4];
+            for poll in 0u32.. {
+                for (slot, previous) in previous.iter_mut().enumerate() {
+                    if previous.is_none() && poll % RESCAN_EVERY != 0 {
+                        continue;
+                    }
+                    let current = held(slot as u32);
+                    // A pad unplugged with buttons down releases them
+                    let (held, was_held) = (current.unwrap_or(0), previous.unwrap_or(0));
+                    let changed = held ^ was_held;
+                    for (bit, button) in BUTTONS {
+                        if changed & bit != 0 {
+                            report(button, held & bit != 0);
+                        }
+                    }
+                    *previous = current;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    pub fn watch() {
+        eprintln!("Gamepad capture is not supported on this platform");
+    }
+}
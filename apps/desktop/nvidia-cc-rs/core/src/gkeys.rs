@@ -0,0 +1,190 @@
+//! Logitech G-keys read over hidraw, with `--g-keys` (Linux).
+//!
+//! Gaming keyboards whose kernel driver maps their macro keys report them as
+//! `KEY_MACRO1`.. and `KEY_PROG1`.., and those are captured like any key, as
+//! `Macro1`.. and `Prog1`... Logitech boards speaking HID++ 2.0 (G910, G815,
+//! G915 and the like) only send theirs as vendor reports, which this module
+//! reads from `/dev/hidraw*`: G1 is emitted as `Macro1`, M1 to M3 as
+//! `MacroPreset1` to `MacroPreset3` and MR as `MacroRecordStart`, just as the
+//! kernel names them on the boards it supports, so one binding covers both.
+//!
+//! Reading them switches the keys to software control, so they stop sending
+//! the F-keys or onboard macros they otherwise do until the keyboard is
+//! replugged. The hidraw nodes must be readable and writable, which usually
+//! takes a udev rule. Razer and Corsair boards need their vendor drivers to
+//! report macro keys at all.
+
+/// Start reading G-keys from every Logitech keyboard that has them
+pub fn watch() {
+    platform::watch();
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{Read, Write};
+    use std::os::fd::AsRawFd;
+    use std::path::Path;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::listener::{self, MACRO_KEYS};
+
+    const LOGITECH_VENDOR: &str = "0000046D";
+
+    /// HID++ long report: id, device index, feature index, function and software id, 16 bytes of parameters
+    const LONG_REPORT: u8 = 0x11;
+    const REPORT_LEN: usize = 20;
+    /// Device index of a keyboard plugged in directly rather than through a receiver
+    const DIRECT_DEVICE: u8 = 0xff;
+    /// Arbitrary non-zero id, so replies can be told apart from notifications (which have 0)
+    const SOFTWARE_ID: u8 = 0x0a;
+    /// Feature index HID++ 2.0 replies with errors under
+    const ERROR_FEATURE: u8 = 0xff;
+    const REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+    const ROOT_FEATURE: u8 = 0x00;
+    const GKEY_FEATURE: u16 = 0x8010;
+    const MKEY_FEATURE: u16 = 0x8020;
+    const MR_FEATURE: u16 = 0x8030;
+    const GET_FEATURE: u8 = 0;
+    /// GKey's function to take the keys over from the keyboard
+    const ENABLE_SOFTWARE_CONTROL: u8 = 2;
+
+    /// A feature of one keyboard reporting keys as a bitmask, and the names of its bits
+    struct Keys {
+        index: u8,
+        names: &'static [&'static str],
+        held: u32,
+    }
+
+    fn is_logitech(hidraw: &str) -> bool {
+        let uevent = Path::new("/sys/class/hidraw").join(hidraw).join("device/uevent");
+        fs::read_to_string(uevent).is_ok_and(|uevent| {
+            uevent.lines().any(|line| line.strip_prefix("HID_ID=").is_some_and(|id| id.contains(LOGITECH_VENDOR)))
+        })
+    }
+
+    /// Next report from `file`, or `None` after `timeout` without one
+    fn read_report(file: &mut File, timeout: Duration) -> Option<[u8; REPORT_LEN]> {
+        let mut poll_fd = libc::pollfd {
+            fd: file.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as i32) };
+        if ready <= 0 {
+            return None;
+        }
+        let mut report = [0u8; REPORT_LEN];
+        let len = file.read(&mut report).ok()?;
+        (len > 4).then_some(report)
+    }
+
+    /// Call `function` of the feature at `feature`, returning the reply's parameters
+    fn request(file: &mut File, feature: u8, function: u8, params: &[u8]) -> Option<[u8; 16]> {
+        let mut report = [0u8; REPORT_LEN];
+        report[..4].copy_from_slice(&[LONG_REPORT, DIRECT_DEVICE, feature, function << 4 | SOFTWARE_ID]);
+        report[4..4 + params.len()].copy_from_slice(params);
+        file.write_all(&report).ok()?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+            let reply = read_report(file, remaining)?;
+            if reply[0] != LONG_REPORT || reply[3] != report[3] {
+                continue;
+            }
+            if reply[2] == ERROR_FEATURE {
+                return None;
+            }
+            if reply[2] == feature {
+                return reply[4..].try_into().ok();
+            }
+        }
+        None
+    }
+
+    /// Index of HID++ feature `id`, when the keyboard has it
+    fn feature_index(file: &mut File, id: u16) -> Option<u8> {
+        let reply = request(file, ROOT_FEATURE, GET_FEATURE, &id.to_be_bytes())?;
+        (reply[0] != 0).then_some(reply[0])
+    }
+
+    fn read_keys(path: String, mut file: File, mut features: Vec<Keys>) {
+        loop {
+            let mut report = [0u8; REPORT_LEN];
+            match file.read(&mut report) {
+                Ok(len) if len > 4 => {}
+                Ok(_) => continue,
+                Err(e) => {
+                    eprintln!("G-keys on {} stopped: {}", path, e);
+                    return;
+                }
+            }
+            // Notifications come as function 0 with no software id
+            if report[0] != LONG_REPORT || report[3] != 0 {
+                continue;
+            }
+            let Some(keys) = features.iter_mut().find(|keys| keys.index == report[2]) else {
+                continue;
+            };
+            let held = u32::from_le_bytes([report[4], report[5], report[6], report[7]]);
+            let changed = held ^ keys.held;
+            keys.held = held;
+            for (bit, name) in keys.names.iter().enumerate() {
+                if changed & 1 << bit != 0 {
+                    listener::report_key(&path, name, held & 1 << bit != 0);
+                }
+            }
+        }
+    }
+
+    pub fn watch() {
+        let Ok(entries) = fs::read_dir("/dev") else {
+            return;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if !name.starts_with("hidraw") || !is_logitech(&name) {
+                continue;
+            }
+            let path = entry.path().display().to_string();
+            let Ok(mut file) = OpenOptions::new().read(true).write(true).open(&path) else {
+                eprintln!("G-keys: cannot open {} for reading and writing", path);
+                continue;
+            };
+            // Other interfaces of the keyboard don't speak HID++ and never reply
+            let Some(gkeys) = feature_index(&mut file, GKEY_FEATURE) else {
+                continue;
+            };
+            if request(&mut file, gkeys, ENABLE_SOFTWARE_CONTROL, &[1]).is_none() {
+                eprintln!("G-keys: {} refused software control", path);
+                continue;
+            }
+            let mut features = vec![Keys {
+                index: gkeys,
+                names: &MACRO_KEYS,
+                held: 0,
+            }];
+            let others: [(u16, &'static [&'static str]); 2] = [
+                (MKEY_FEATURE, &["MacroPreset1", "MacroPreset2", "MacroPreset3"]),
+                (MR_FEATURE, &["MacroRecordStart"]),
+            ];
+            for (id, names) in others {
+                if let Some(index) = feature_index(&mut file, id) {
+                    features.push(Keys { index, names, held: 0 });
+                }
+            }
+            eprintln!("Found Logitech G-keys: {}", path);
+            thread::spawn(move || read_keys(path, file, features));
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    pub fn watch() {
+        eprintln!("G-key capture over hidraw is only available on Linux");
+    }
+}
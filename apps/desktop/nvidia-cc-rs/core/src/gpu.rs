@@ -0,0 +1,256 @@
+//! NVIDIA GPU telemetry and settings snapshots, through `nvidia-smi`.
+//!
+//! The helper doesn't link NVML: nvidia-smi ships with every driver, and the
+//! NVIDIA Container Toolkit injects it into containers along with the library,
+//! so asking it keeps GPUs optional. The `nvidia_cc_gpu_*` metrics, `/gpu` on
+//! `--http` and the container report in `doctor` read [`query`].
+//!
+//! `gpu snapshot save|list|restore <name>` keeps what nvidia-smi can set back:
+//! the power limit, the application clocks, persistence mode and compute mode
+//! of each GPU, as JSON under the data directory. Fan speed and performance
+//! state are only reported by nvidia-smi, never set, so they aren't kept.
+//! Restoring first saves the current settings as `pre-change`, then sets each
+//! one that differs with `nvidia-smi -i <gpu> -pl|-ac|-pm|-c`. Most of those
+//! need root or an administrator, and some GPUs don't support them; whatever
+//! nvidia-smi refuses is reported per setting and the rest still applied.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Fields asked of nvidia-smi, in the order of [`Gpu`]
+const QUERY: &str = "index,name,temperature.gpu,utilization.gpu,memory.used,memory.total,power.draw";
+
+#[derive(Serialize)]
+pub struct Gpu {
+    pub index: u32,
+    pub name: String,
+    // "[N/A]" and "[Not Supported]" readings are left empty
+    pub temperature_celsius: Option<f64>,
+    /// Fraction of time the GPU was busy
+    pub utilization_ratio: Option<f64>,
+    pub memory_used_bytes: Option<u64>,
+    pub memory_total_bytes: Option<u64>,
+    pub power_watts: Option<f64>,
+}
+
+/// Fields asked of nvidia-smi, in the order of [`Settings`]
+const SETTINGS_QUERY: &str =
+    "index,power.limit,clocks.applications.graphics,clocks.applications.memory,persistence_mode,compute_mode";
+
+/// Where `--driver-guidance` sends users whose driver nvidia-smi can't reach
+pub const DRIVER_DOWNLOAD_URL: &str = "https://www.nvidia.com/Download/index.aspx";
+
+/// The snapshot `restore` saves first
+pub const PRE_CHANGE: &str = "pre-change";
+
+/// What `nvidia-smi` can set back on one GPU; settings it reports as unavailable are left empty
+#[derive(Serialize, Deserialize)]
+pub struct Settings {
+    pub index: u32,
+    pub power_limit_watts: Option<f64>,
+    pub graphics_clock_mhz: Option<u32>,
+    pub memory_clock_mhz: Option<u32>,
+    pub persistence_mode: Option<bool>,
+    /// `Default`, `Exclusive_Process` or `Prohibited`
+    pub compute_mode: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub saved_unix_ms: u64,
+    pub gpus: Vec<Settings>,
+}
+
+/// A setting `restore` changed, or its reason for not changing it
+#[derive(Serialize)]
+pub struct Change {
+    pub gpu: u32,
+    /// `power_limit`, `application_clocks`, `persistence_mode` or `compute_mode`
+    pub setting: &'static str,
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Run nvidia-smi with `args`, returning its output, or why it failed
+fn nvidia_smi(args: &[String]) -> Result<String, String> {
+    let output = Command::new("nvidia-smi").args(args).output().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => "nvidia-smi isn't installed".to_string(),
+        _ => format!("Cannot run nvidia-smi: {}", e),
+    })?;
+    if !output.status.success() {
+        // It explains itself on stdout, e.g. when it can't reach the driver
+        let message = String::from_utf8_lossy(if output.stderr.is_empty() { &output.stdout } else { &output.stderr })
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        return Err(format!("nvidia-smi failed ({}): {}", output.status, message));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Ask nvidia-smi for `fields` of every GPU, one CSV row each
+fn query_gpus(fields: &str) -> Result<String, String> {
+    nvidia_smi(&[format!("--query-gpu={}", fields), "--format=csv,noheader,nounits".to_string()])
+}
+
+/// Every GPU nvidia-smi sees, or why it can't be asked
+pub fn query() -> Result<Vec<Gpu>, String> {
+    Ok(query_gpus(QUERY)?.lines().filter_map(parse).collect())
+}
+
+/// The current settings of every GPU
+pub fn settings() -> Result<Vec<Settings>, String> {
+    Ok(query_gpus(SETTINGS_QUERY)?.lines().filter_map(parse_settings).collect())
+}
+
+/// A CSV row of [`SETTINGS_QUERY`]
+fn parse_settings(line: &str) -> Option<Settings> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [index, power_limit, graphics_clock, memory_clock, persistence_mode, compute_mode] = fields[..] else {
+        return None;
+    };
+    // "[N/A]" and "[Not Supported]" don't parse, leaving the setting out
+    let available = |field: &str| Some(field.to_string()).filter(|field| !field.starts_with('['));
+    Some(Settings {
+        index: index.parse().ok()?,
+        power_limit_watts: power_limit.parse().ok(),
+        graphics_clock_mhz: graphics_clock.parse().ok(),
+        memory_clock_mhz: memory_clock.parse().ok(),
+        persistence_mode: match persistence_mode {
+            "Enabled" => Some(true),
+            "Disabled" => Some(false),
+            _ => None,
+        },
+        compute_mode: available(compute_mode),
+    })
+}
+
+fn snapshot_dir() -> Result<PathBuf, String> {
+    dirs::data_dir()
+        .map(|dir| dir.join("nvidia-cc").join("gpu-snapshots"))
+        .ok_or_else(|| "No data directory to keep snapshots in".to_string())
+}
+
+fn snapshot_path(name: &str) -> Result<PathBuf, String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid {
+        return Err(format!("Invalid snapshot name: {} (letters, digits, '.', '_' and '-')", name));
+    }
+    Ok(snapshot_dir()?.join(format!("{}.json", name)))
+}
+
+/// Save the current settings of every GPU as snapshot `name`, replacing any of that name
+pub fn save(name: &str) -> Result<Snapshot, String> {
+    let path = snapshot_path(name)?;
+    let snapshot = Snapshot {
+        name: name.to_string(),
+        saved_unix_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64),
+        gpus: settings()?,
+    };
+    let write = || -> io::Result<()> {
+        fs::create_dir_all(path.parent().unwrap_or(&path))?;
+        fs::write(&path, serde_json::to_vec_pretty(&snapshot).map_err(io::Error::other)?)
+    };
+    write().map_err(|e| format!("Cannot save {}: {}", path.display(), e))?;
+    Ok(snapshot)
+}
+
+/// The saved snapshots, by name
+pub fn list() -> Result<Vec<Snapshot>, String> {
+    let dir = snapshot_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Cannot read {}: {}", dir.display(), e)),
+    };
+    let mut snapshots: Vec<Snapshot> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "json"))
+        .filter_map(|entry| serde_json::from_slice(&fs::read(entry.path()).ok()?).ok())
+        .collect();
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+/// Set every GPU back to snapshot `name`, after saving the current settings as [`PRE_CHANGE`]
+pub fn restore(name: &str) -> Result<Vec<Change>, String> {
+    let path = snapshot_path(name)?;
+    let contents = fs::read(&path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => format!("No snapshot named {}", name),
+        _ => format!("Cannot read {}: {}", path.display(), e),
+    })?;
+    // Read before saving PRE_CHANGE, which may be the one restored
+    let snapshot: Snapshot =
+        serde_json::from_slice(&contents).map_err(|e| format!("Invalid snapshot {}: {}", path.display(), e))?;
+    let current = save(PRE_CHANGE)?.gpus;
+    let mut changes = Vec::new();
+    for wanted in &snapshot.gpus {
+        let Some(now) = current.iter().find(|gpu| gpu.index == wanted.index) else {
+            changes.push(Change {
+                gpu: wanted.index,
+                setting: "gpu",
+                value: String::new(),
+                error: Some("nvidia-smi no longer lists this GPU".to_string()),
+            });
+            continue;
+        };
+        // Persistence first: without it on Linux, the others may not outlast nvidia-smi
+        let mut wanted_settings = Vec::new();
+        if let Some(on) = wanted.persistence_mode.filter(|on| now.persistence_mode != Some(*on)) {
+            wanted_settings.push(("persistence_mode", "-pm", if on { "1" } else { "0" }.to_string()));
+        }
+        if let Some(mode) = wanted.compute_mode.as_ref().filter(|mode| now.compute_mode.as_ref() != Some(*mode)) {
+            wanted_settings.push(("compute_mode", "-c", mode.to_uppercase()));
+        }
+        if let Some(watts) = wanted.power_limit_watts.filter(|watts| now.power_limit_watts != Some(*watts)) {
+            wanted_settings.push(("power_limit", "-pl", watts.to_string()));
+        }
+        if let (Some(memory), Some(graphics)) = (wanted.memory_clock_mhz, wanted.graphics_clock_mhz) {
+            if (now.memory_clock_mhz, now.graphics_clock_mhz) != (Some(memory), Some(graphics)) {
+                wanted_settings.push(("application_clocks", "-ac", format!("{},{}", memory, graphics)));
+            }
+        }
+        for (setting, flag, value) in wanted_settings {
+            let args = ["-i".to_string(), wanted.index.to_string(), flag.to_string(), value.clone()];
+            let error = nvidia_smi(&args).err().map(|e| {
+                if e.to_lowercase().contains("permission") {
+                    format!("setting it needs root or an administrator ({})", e)
+                } else {
+                    e
+                }
+            });
+            changes.push(Change { gpu: wanted.index, setting, value, error });
+        }
+    }
+    Ok(changes)
+}
+
+/// A CSV row of [`QUERY`]
+fn parse(line: &str) -> Option<Gpu> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    let [index, name, temperature, utilization, memory_used, memory_total, power] = fields[..] else {
+        return None;
+    };
+    let number = |field: &str| field.parse::<f64>().ok();
+    // nvidia-smi counts memory in MiB
+    let mebibytes = |field: &str| field.parse::<u64>().ok().map(|mib| mib * 1024 * 1024);
+    Some(Gpu {
+        index: index.parse().ok()?,
+        name: name.to_string(),
+        temperature_celsius: number(temperature),
+        utilization_ratio: number(utilization).map(|percent| percent / 100.0),
+        memory_used_bytes: mebibytes(memory_used),
+        memory_total_bytes: mebibytes(memory_total),
+        power_watts: number(power),
+    })
+}
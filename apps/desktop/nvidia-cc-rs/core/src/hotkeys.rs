@@ -0,0 +1,764 @@
+//! Hotkeys matched inside the helper (`listen --hotkeys <file>`).
+//!
+//! The file is a JSON array of definitions:
+//!
+//!   [{"id": "dictate", "keys": "ControlLeft+Space",
+//!     "never_in": [{"app_id": "(?i)1password|keepassxc"}, {"title": "(?i)terminal"}]}]
+//!
+//! `keys` are emitted key names joined by `+`. A hotkey fires as
+//! `HotkeyTriggered{id}` when exactly its keys are down, and `HotkeyReleased{id}`
+//! follows when the first of them comes up. Injected keystrokes never trigger
+//! hotkeys. Raw key events are emitted as usual either way.
+//!
+//! Mouse buttons and the wheel can be part of `keys` too:
+//! `ControlLeft+MouseButton4`, `MetaLeft+ScrollUp`. The buttons are
+//! `MouseButton1` to `MouseButton5` (left, right, middle, back, forward); a
+//! wheel notch (`ScrollUp`, `ScrollDown`, `ScrollLeft`, `ScrollRight`) is
+//! pressed and released at once, so it only suits plain hotkeys. Nothing is
+//! emitted for mouse input itself. On Linux mice are only opened when a
+//! hotkey loaded at startup uses them.
+//!
+//! Keypad digits (`Kp7`) match whatever the NumLock state; `KpHome` and the
+//! other navigation names only match with NumLock off (see [`crate::numpad`]).
+//!
+//! `"mode": "hold"` is for push-to-talk: `HoldStart{id}` is emitted once the
+//! keys have been held for `threshold_ms` (250 by default), and
+//! `HoldEnd{id, duration_ms}` on release, with the duration counted from the
+//! press. Taps shorter than the threshold emit nothing, and so does a hold
+//! interrupted by another key (Ctrl+C while holding Ctrl). Timing runs on a
+//! timer thread in the helper, so it doesn't depend on how promptly the
+//! consumer reads events.
+//!
+//! `"mode": "double_tap"` emits `DoubleTap{id, key, interval_ms}` when the
+//! keys are tapped twice with the presses at most `interval_ms` (300 by
+//! default) apart, `interval_ms` in the event being the measured gap. Taps
+//! only count when no other key is pressed in between.
+//!
+//! `"mode": "long_press"` emits `LongPress{id, key, threshold_ms}` once the
+//! keys have been held for `threshold_ms` (1000 by default) without being
+//! released, and nothing on release ("hold Escape to cancel"). As with
+//! holds, another key pressed before then cancels it.
+//!
+//! `"mode": "modifier_tap"` is for modifier-only hotkeys (tap Meta to
+//! dictate): `ModifierTap{id, key}` is emitted when the keys are released
+//! without any other key having been pressed while they were down, so Meta
+//! used for Meta+L emits nothing.
+//!
+//! `"sequence": ["ControlLeft+Space", "KeyD"]` in place of `keys` defines a
+//! multi-step hotkey (Ctrl+Space, then D), emitting `SequenceTriggered{id}`
+//! once the last step is pressed. Each step must follow the previous one
+//! within `step_timeout_ms` (1000 by default); keys still held from an earlier
+//! step are ignored, and any other key abandons the sequence. The helper
+//! listens without grabbing devices, so the intermediate keys still reach the
+//! focused app as well.
+//!
+//! `only_in` and `never_in` scope a hotkey by the focused window (see
+//! [`crate::active_window`]). Each rule has an `app_id` and/or `title` regex and
+//! matches when every one it gives matches. With `only_in` the hotkey fires only
+//! while some rule matches, so never while the focused window is unknown; with
+//! `never_in` it is ignored while any does. Presses scoped out this way emit
+//! nothing at all.
+//!
+//! A running listener also takes `hotkey add <json definition>`, `hotkey
+//! remove <id>` and `hotkey list` on stdin (see [`crate::control`]). Adding or
+//! removing one hotkey leaves the others' state alone, so a push-to-talk hold
+//! in progress carries on; a hotkey removed while held emits no release. A
+//! config reload replaces them all with the configured ones again.
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::active_window::{self, Window};
+use crate::clock;
+use crate::event::{Event, EventKind};
+use crate::numpad;
+use crate::stream;
+
+/// A hotkey as written in a `--hotkeys` file or a `[[hotkey]]` table of the config file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Definition {
+    id: String,
+    keys: Option<String>,
+    sequence: Option<Vec<String>>,
+    #[serde(default)]
+    mode: ModeName,
+    threshold_ms: Option<u64>,
+    interval_ms: Option<u64>,
+    step_timeout_ms: Option<u64>,
+    #[serde(default)]
+    only_in: Vec<RuleDefinition>,
+    #[serde(default)]
+    never_in: Vec<RuleDefinition>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+enum ModeName {
+    #[default]
+    Press,
+    Hold,
+    DoubleTap,
+    LongPress,
+    ModifierTap,
+}
+
+/// Held this long before `HoldStart`, unless `threshold_ms` says otherwise
+const DEFAULT_HOLD_THRESHOLD: Duration = Duration::from_millis(250);
+
+/// Held this long before `LongPress`, unless `threshold_ms` says otherwise
+const DEFAULT_LONG_PRESS_THRESHOLD: Duration = Duration::from_millis(1000);
+
+/// Longest gap between the presses of a double tap, unless `interval_ms` says otherwise
+const DEFAULT_DOUBLE_TAP_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Longest wait for a sequence's next step, unless `step_timeout_ms` says otherwise
+const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// An `only_in`/`never_in` entry, also used to scope hotstrings
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleDefinition {
+    app_id: Option<String>,
+    title: Option<String>,
+}
+
+struct Rule {
+    app_id: Option<Regex>,
+    title: Option<Regex>,
+}
+
+impl Rule {
+    fn compile(definition: &RuleDefinition) -> Result<Self, String> {
+        if definition.app_id.is_none() && definition.title.is_none() {
+            return Err("app rules need an app_id or title pattern".to_string());
+        }
+        let compile = |pattern: &Option<String>| {
+            pattern
+                .as_deref()
+                .map(|pattern| Regex::new(pattern).map_err(|e| format!("Invalid pattern {:?}: {}", pattern, e)))
+                .transpose()
+        };
+        Ok(Rule {
+            app_id: compile(&definition.app_id)?,
+            title: compile(&definition.title)?,
+        })
+    }
+
+    fn matches(&self, window: &Window) -> bool {
+        self.app_id.as_ref().is_none_or(|pattern| pattern.is_match(&window.app_id))
+            && self.title.as_ref().is_none_or(|pattern| pattern.is_match(&window.title))
+    }
+}
+
+/// Compiled `only_in` and `never_in` rules
+pub struct Scope {
+    only_in: Vec<Rule>,
+    never_in: Vec<Rule>,
+}
+
+impl Scope {
+    pub fn compile(only_in: &[RuleDefinition], never_in: &[RuleDefinition]) -> Result<Self, String> {
+        let rules = |definitions: &[RuleDefinition]| definitions.iter().map(Rule::compile).collect::<Result<Vec<_>, _>>();
+        Ok(Scope {
+            only_in: rules(only_in)?,
+            never_in: rules(never_in)?,
+        })
+    }
+
+    /// Whether the rules let something fire while `window` has focus
+    pub fn allows(&self, window: Option<&Window>) -> bool {
+        let any_match = |rules: &[Rule]| window.is_some_and(|window| rules.iter().any(|rule| rule.matches(window)));
+        (self.only_in.is_empty() || any_match(&self.only_in)) && !any_match(&self.never_in)
+    }
+}
+
+enum Mode {
+    /// `HotkeyTriggered` on press, `HotkeyReleased` on release
+    Press,
+    /// `HoldStart` once held for `threshold`, `HoldEnd` on release; shorter taps emit nothing
+    Hold { threshold: Duration },
+    /// `DoubleTap` on the second of two clean taps whose presses are within `interval`
+    DoubleTap { interval: Duration },
+    /// `LongPress` once held for `threshold`, nothing on release
+    LongPress { threshold: Duration },
+    /// `ModifierTap` on release, unless another key was pressed while the keys were down
+    ModifierTap,
+    /// `SequenceTriggered` once `steps` follow the hotkey's keys, each within `timeout` of the last
+    Sequence { steps: Vec<Vec<String>>, timeout: Duration },
+}
+
+/// Whether `binding`, a key named in `keys`, is the key down under `pressed`
+///
+/// A keypad digit stays bound whatever NumLock does; see [`crate::numpad`].
+fn binds(binding: &str, pressed: &str) -> bool {
+    binding == pressed || binding == numpad::physical(pressed)
+}
+
+/// Whether `a` and `b` name the same physical key
+fn same_key(a: &str, b: &str) -> bool {
+    numpad::physical(a) == numpad::physical(b)
+}
+
+/// `A+B` as key names
+fn parse_keys(id: &str, keys: &str) -> Result<Vec<String>, String> {
+    let parsed: Vec<String> = keys.split('+').map(|key| key.trim().to_string()).collect();
+    if parsed.iter().any(String::is_empty) {
+        return Err(format!("Hotkey {}: invalid keys {:?}", id, keys));
+    }
+    Ok(parsed)
+}
+
+pub struct Hotkey {
+    id: String,
+    keys: Vec<String>,
+    mode: Mode,
+    scope: Scope,
+}
+
+impl Hotkey {
+    fn compile(definition: Definition) -> Result<Self, String> {
+        let (keys, mode) = match (&definition.keys, &definition.sequence) {
+            (Some(keys), None) => (parse_keys(&definition.id, keys)?, None),
+            (None, Some(sequence)) => {
+                if sequence.len() < 2 {
+                    return Err(format!("Hotkey {}: a sequence needs at least two steps", definition.id));
+                }
+                if !matches!(definition.mode, ModeName::Press) {
+                    return Err(format!("Hotkey {}: sequences can't have a mode", definition.id));
+                }
+                let mut steps = sequence
+                    .iter()
+                    .map(|step| parse_keys(&definition.id, step))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let first = steps.remove(0);
+                let timeout = definition.step_timeout_ms.map_or(DEFAULT_STEP_TIMEOUT, Duration::from_millis);
+                (first, Some(Mode::Sequence { steps, timeout }))
+            }
+            _ => return Err(format!("Hotkey {}: needs either keys or a sequence", definition.id)),
+        };
+        let mode = mode.unwrap_or(match definition.mode {
+            ModeName::Press => Mode::Press,
+            ModeName::Hold => Mode::Hold {
+                threshold: definition.threshold_ms.map_or(DEFAULT_HOLD_THRESHOLD, Duration::from_millis),
+            },
+            ModeName::DoubleTap => Mode::DoubleTap {
+                interval: definition.interval_ms.map_or(DEFAULT_DOUBLE_TAP_INTERVAL, Duration::from_millis),
+            },
+            ModeName::LongPress => Mode::LongPress {
+                threshold: definition.threshold_ms.map_or(DEFAULT_LONG_PRESS_THRESHOLD, Duration::from_millis),
+            },
+            ModeName::ModifierTap => Mode::ModifierTap,
+        });
+        // Timing options another mode would ignore are mistakes in the file
+        if definition.threshold_ms.is_some() && !matches!(mode, Mode::Hold { .. } | Mode::LongPress { .. }) {
+            return Err(format!(
+                "Hotkey {}: threshold_ms only applies to hold and long_press hotkeys",
+                definition.id
+            ));
+        }
+        if definition.interval_ms.is_some() && !matches!(mode, Mode::DoubleTap { .. }) {
+            return Err(format!("Hotkey {}: interval_ms only applies to double_tap hotkeys", definition.id));
+        }
+        if definition.step_timeout_ms.is_some() && !matches!(mode, Mode::Sequence { .. }) {
+            return Err(format!("Hotkey {}: step_timeout_ms only applies to sequences", definition.id));
+        }
+        Ok(Hotkey {
+            scope: Scope::compile(&definition.only_in, &definition.never_in)
+                .map_err(|e| format!("Hotkey {}: {}", definition.id, e))?,
+            id: definition.id,
+            keys,
+            mode,
+        })
+    }
+
+    /// The hotkey as listed by `hotkey list`
+    fn describe(&self) -> Value {
+        let keys = self.keys.join("+");
+        let (mode, mut description) = match &self.mode {
+            Mode::Press => ("press", json!({"keys": keys})),
+            Mode::Hold { threshold } => ("hold", json!({"keys": keys, "threshold_ms": threshold.as_millis() as u64})),
+            Mode::DoubleTap { interval } => {
+                ("double_tap", json!({"keys": keys, "interval_ms": interval.as_millis() as u64}))
+            }
+            Mode::LongPress { threshold } => {
+                ("long_press", json!({"keys": keys, "threshold_ms": threshold.as_millis() as u64}))
+            }
+            Mode::ModifierTap => ("modifier_tap", json!({"keys": keys})),
+            Mode::Sequence { steps, timeout } => {
+                let sequence: Vec<String> =
+                    std::iter::once(keys).chain(steps.iter().map(|step| step.join("+"))).collect();
+                ("sequence", json!({"sequence": sequence, "step_timeout_ms": timeout.as_millis() as u64}))
+            }
+        };
+        description["id"] = json!(self.id);
+        description["mode"] = json!(mode);
+        description
+    }
+
+    /// Every key the hotkey names, in `keys` and in its sequence steps
+    fn all_keys(&self) -> impl Iterator<Item = &String> {
+        let steps = match &self.mode {
+            Mode::Sequence { steps, .. } => steps.as_slice(),
+            _ => &[],
+        };
+        self.keys.iter().chain(steps.iter().flatten())
+    }
+
+    /// Exactly this hotkey's keys are down
+    fn matches(&self, pressed: &[String]) -> bool {
+        self.keys.len() == pressed.len() && self.keys.iter().all(|key| pressed.iter().any(|down| binds(key, down)))
+    }
+}
+
+/// A hotkey whose keys are down
+struct Active {
+    /// Index into `Engine::hotkeys`
+    hotkey: usize,
+    pressed_at: Duration,
+    /// `HotkeyTriggered`/`HoldStart` was emitted, so the release gets reported too
+    started: bool,
+}
+
+impl Active {
+    /// When a pending hold turns into `HoldStart` (or `LongPress`)
+    fn deadline(&self, hotkey: &Hotkey) -> Option<Duration> {
+        match hotkey.mode {
+            Mode::Hold { threshold } | Mode::LongPress { threshold } if !self.started => {
+                Some(self.pressed_at + threshold)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The first tap of a possible double tap
+struct Tap {
+    hotkey: usize,
+    pressed_at: Duration,
+}
+
+/// A sequence partway through
+struct Progress {
+    hotkey: usize,
+    /// Index into the sequence's `steps` of the step expected next
+    step: usize,
+    /// The next step must be pressed by then
+    deadline: Duration,
+}
+
+struct Engine {
+    hotkeys: Vec<Hotkey>,
+    /// Keys currently down, in press order
+    pressed: Vec<String>,
+    active: Vec<Active>,
+    taps: Vec<Tap>,
+    sequences: Vec<Progress>,
+}
+
+impl Engine {
+    fn press(&mut self, key: &str, at: Duration, events: &mut Vec<EventKind<'static>>) {
+        // rdev repeats KeyPress while a key is held
+        if self.pressed.iter().any(|down| same_key(down, key)) {
+            return;
+        }
+        self.pressed.push(key.to_string());
+
+        // Another key before the threshold means the keys are part of a different combo
+        let hotkeys = &self.hotkeys;
+        let pressed = &self.pressed;
+        self.active.retain(|active| active.started || hotkeys[active.hotkey].matches(pressed));
+        // ...and a key outside a hotkey between two taps of it breaks the double tap
+        self.taps.retain(|tap| hotkeys[tap.hotkey].keys.iter().any(|hotkey_key| binds(hotkey_key, key)));
+
+        // Sequences under way move on a step, finish, or are abandoned
+        let mut advanced = Vec::new();
+        self.sequences.retain_mut(|progress| {
+            let hotkey = &hotkeys[progress.hotkey];
+            let Mode::Sequence { steps, timeout } = &hotkey.mode else {
+                return false;
+            };
+            let step = &steps[progress.step];
+            if at > progress.deadline || !step.iter().any(|step_key| binds(step_key, key)) {
+                return false;
+            }
+            if !step.iter().all(|step_key| pressed.iter().any(|down| binds(step_key, down))) {
+                // Part of a multi-key step so far
+                return true;
+            }
+            advanced.push(progress.hotkey);
+            progress.step += 1;
+            progress.deadline = at + *timeout;
+            if progress.step < steps.len() {
+                return true;
+            }
+            events.push(EventKind::SequenceTriggered { id: hotkey.id.clone() });
+            false
+        });
+
+        let window = active_window::current();
+        for (index, hotkey) in self.hotkeys.iter().enumerate() {
+            if !hotkey.matches(&self.pressed) || !hotkey.scope.allows(window.as_ref()) {
+                continue;
+            }
+            let started = match hotkey.mode {
+                Mode::Press => {
+                    events.push(EventKind::HotkeyTriggered { id: hotkey.id.clone() });
+                    true
+                }
+                // Another key pressed meanwhile drops the pending tap, like a pending hold
+                Mode::Hold { .. } | Mode::LongPress { .. } | Mode::ModifierTap => false,
+                Mode::DoubleTap { interval } => {
+                    let first = self.taps.iter().position(|tap| tap.hotkey == index);
+                    if let Some(first) = first.map(|first| self.taps.remove(first)) {
+                        let gap = at.saturating_sub(first.pressed_at);
+                        if gap <= interval {
+                            events.push(EventKind::DoubleTap {
+                                id: hotkey.id.clone(),
+                                key: hotkey.keys.join("+"),
+                                interval_ms: gap.as_millis() as u64,
+                            });
+                            // The second tap is used up; it can't start the next double tap
+                            continue;
+                        }
+                    }
+                    false
+                }
+                Mode::Sequence { timeout, .. } => {
+                    // A press that just moved this sequence on can't also restart it
+                    if !advanced.contains(&index) {
+                        self.sequences.retain(|progress| progress.hotkey != index);
+                        self.sequences.push(Progress {
+                            hotkey: index,
+                            step: 0,
+                            deadline: at + timeout,
+                        });
+                    }
+                    continue;
+                }
+            };
+            self.active.push(Active {
+                hotkey: index,
+                pressed_at: at,
+                started,
+            });
+        }
+    }
+
+    fn release(&mut self, key: &str, at: Duration, events: &mut Vec<EventKind<'static>>) {
+        // NumLock may have changed since the press, so keys are matched by the physical key
+        self.pressed.retain(|down| !same_key(down, key));
+
+        let hotkeys = &self.hotkeys;
+        let taps = &mut self.taps;
+        self.active.retain(|active| {
+            let hotkey = &hotkeys[active.hotkey];
+            if !hotkey.keys.iter().any(|hotkey_key| same_key(hotkey_key, key)) {
+                return true;
+            }
+            let id = hotkey.id.clone();
+            match hotkey.mode {
+                Mode::Press if active.started => events.push(EventKind::HotkeyReleased { id }),
+                Mode::Hold { .. } if active.started => events.push(EventKind::HoldEnd {
+                    id,
+                    duration_ms: at.saturating_sub(active.pressed_at).as_millis() as u64,
+                }),
+                Mode::DoubleTap { .. } => taps.push(Tap {
+                    hotkey: active.hotkey,
+                    pressed_at: active.pressed_at,
+                }),
+                Mode::ModifierTap => events.push(EventKind::ModifierTap {
+                    id,
+                    key: hotkey.keys.join("+"),
+                }),
+                _ => {}
+            }
+            false
+        });
+    }
+
+    /// Fire every timer due by `now`
+    fn tick(&mut self, now: Duration, events: &mut Vec<EventKind<'static>>) {
+        for active in &mut self.active {
+            let hotkey = &self.hotkeys[active.hotkey];
+            if active.deadline(hotkey).is_some_and(|deadline| deadline <= now) {
+                active.started = true;
+                let id = hotkey.id.clone();
+                events.push(match hotkey.mode {
+                    Mode::LongPress { threshold } => EventKind::LongPress {
+                        id,
+                        key: hotkey.keys.join("+"),
+                        threshold_ms: threshold.as_millis() as u64,
+                    },
+                    _ => EventKind::HoldStart { id },
+                });
+            }
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        self.active
+            .iter()
+            .filter_map(|active| active.deadline(&self.hotkeys[active.hotkey]))
+            .min()
+    }
+}
+
+static ENGINE: Mutex<Engine> = Mutex::new(Engine {
+    hotkeys: Vec::new(),
+    pressed: Vec::new(),
+    active: Vec::new(),
+    taps: Vec::new(),
+    sequences: Vec::new(),
+});
+
+/// Wakes the timer thread when the next deadline may have moved
+static TIMER_WAKE: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Engine> {
+    ENGINE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Check and compile hotkey definitions
+pub fn compile(definitions: Vec<Definition>) -> Result<Vec<Hotkey>, String> {
+    let hotkeys = definitions.into_iter().map(Hotkey::compile).collect::<Result<Vec<_>, _>>()?;
+    for (index, hotkey) in hotkeys.iter().enumerate() {
+        if hotkeys[..index].iter().any(|other| other.id == hotkey.id) {
+            return Err(format!("Duplicate hotkey id: {}", hotkey.id));
+        }
+    }
+    Ok(hotkeys)
+}
+
+/// Read and compile the hotkey definitions in `path`
+pub fn read(path: &Path) -> Result<Vec<Hotkey>, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let definitions: Vec<Definition> =
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+    compile(definitions).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Replace the active hotkeys, returning how many there are
+pub fn install(hotkeys: Vec<Hotkey>) -> usize {
+    let count = hotkeys.len();
+    let mut engine = lock();
+    engine.hotkeys = hotkeys;
+    engine.active.clear();
+    engine.taps.clear();
+    engine.sequences.clear();
+    count
+}
+
+/// Whether some active hotkey has `key` among its keys or sequence steps
+pub fn uses_key(key: &str) -> bool {
+    lock().hotkeys.iter().any(|hotkey| hotkey.all_keys().any(|k| same_key(k, key)))
+}
+
+/// The id and keys of each active press-mode hotkey, for shortcut portals that only report activations
+pub fn press_bindings() -> Vec<(String, Vec<String>)> {
+    lock()
+        .hotkeys
+        .iter()
+        .filter(|hotkey| matches!(hotkey.mode, Mode::Press))
+        .map(|hotkey| (hotkey.id.clone(), hotkey.keys.clone()))
+        .collect()
+}
+
+/// Whether hotkey `id` exists and its `only_in`/`never_in` rules let it fire with the current focus
+pub fn allowed_now(id: &str) -> bool {
+    let window = active_window::current();
+    lock().hotkeys.iter().any(|hotkey| hotkey.id == id && hotkey.scope.allows(window.as_ref()))
+}
+
+/// `keys` names of the mouse buttons: left, right, middle, back and forward
+pub const MOUSE_BUTTONS: [&str; 5] = ["MouseButton1", "MouseButton2", "MouseButton3", "MouseButton4", "MouseButton5"];
+
+/// `keys` names of the wheel directions
+pub const SCROLL_DIRECTIONS: [&str; 4] = ["ScrollUp", "ScrollDown", "ScrollLeft", "ScrollRight"];
+
+fn is_mouse_trigger(key: &str) -> bool {
+    MOUSE_BUTTONS.contains(&key) || SCROLL_DIRECTIONS.contains(&key)
+}
+
+/// Whether some active hotkey is triggered by a mouse button or the wheel
+pub fn uses_mouse() -> bool {
+    lock().hotkeys.iter().any(|hotkey| hotkey.all_keys().any(|k| is_mouse_trigger(k)))
+}
+
+/// Feed a user's mouse button or wheel notch (`pressed` is `None` for the wheel) to the engine
+///
+/// Ignored unless a hotkey uses the mouse, so clicks don't interrupt keyboard-only holds.
+pub fn observe_mouse(trigger: &str, pressed: Option<bool>, at: Duration) {
+    if !uses_mouse() {
+        return;
+    }
+    match pressed {
+        Some(pressed) => observe(pressed, trigger, at),
+        None => {
+            observe(true, trigger, at);
+            observe(false, trigger, at);
+        }
+    }
+}
+
+/// Handle on the process-wide hotkey engine that the listener feeds
+///
+/// Every handle refers to the same engine, as there is only one keyboard.
+#[derive(Clone, Copy, Default)]
+pub struct HotkeyEngine;
+
+impl HotkeyEngine {
+    /// Replace the hotkeys with a JSON array of definitions, returning how many there are
+    pub fn load_json(&self, json: &str) -> Result<usize, String> {
+        let definitions: Vec<Definition> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        Ok(install(compile(definitions)?))
+    }
+
+    /// Replace the hotkeys with those in a `--hotkeys` file, returning how many there are
+    pub fn load_file(&self, path: &Path) -> Result<usize, String> {
+        Ok(install(read(path)?))
+    }
+
+    /// Register one more hotkey from its JSON definition, returning its id
+    pub fn add_json(&self, json: &str) -> Result<String, String> {
+        add(serde_json::from_str(json).map_err(|e| e.to_string())?)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        remove(id)
+    }
+
+    /// The active hotkeys, as listed by `hotkey list`
+    pub fn list(&self) -> Vec<Value> {
+        list()
+    }
+
+    /// Feed a user key event by its rdev-style name, for keys not captured by [`crate::KeyboardListener`]
+    pub fn observe(&self, pressed: bool, key: &str) {
+        observe(pressed, key, now());
+    }
+
+    /// Start the thread that times holds and long presses; call it once
+    pub fn start_timer(&self) {
+        start_timer();
+    }
+}
+
+/// Register one more hotkey without disturbing the others, returning its id
+pub fn add(definition: Definition) -> Result<String, String> {
+    let hotkey = Hotkey::compile(definition)?;
+    let mut engine = lock();
+    if engine.hotkeys.iter().any(|other| other.id == hotkey.id) {
+        return Err(format!("Duplicate hotkey id: {}", hotkey.id));
+    }
+    let id = hotkey.id.clone();
+    engine.hotkeys.push(hotkey);
+    Ok(id)
+}
+
+/// Unregister the hotkey `id` without disturbing the others
+pub fn remove(id: &str) -> Result<(), String> {
+    let mut engine = lock();
+    let removed = engine
+        .hotkeys
+        .iter()
+        .position(|hotkey| hotkey.id == id)
+        .ok_or_else(|| format!("No hotkey with id {}", id))?;
+    engine.hotkeys.remove(removed);
+    // Drop the removed hotkey's state and shift the indices of the ones after it
+    let keep = |hotkey: &mut usize| {
+        if *hotkey > removed {
+            *hotkey -= 1;
+            return true;
+        }
+        *hotkey != removed
+    };
+    engine.active.retain_mut(|active| keep(&mut active.hotkey));
+    engine.taps.retain_mut(|tap| keep(&mut tap.hotkey));
+    engine.sequences.retain_mut(|progress| keep(&mut progress.hotkey));
+    Ok(())
+}
+
+/// The active hotkeys, as listed by `hotkey list`
+pub fn list() -> Vec<Value> {
+    lock().hotkeys.iter().map(Hotkey::describe).collect()
+}
+
+pub fn count() -> usize {
+    lock().hotkeys.len()
+}
+
+/// Forget which keys are down, after a stretch where key events weren't seen (e.g. pause)
+pub fn reset() {
+    let mut engine = lock();
+    engine.pressed.clear();
+    engine.active.clear();
+    engine.taps.clear();
+    engine.sequences.clear();
+}
+
+/// Engine time for live events: the helper's monotonic clock
+///
+/// `decode` passes the dump's timestamps instead, so recorded timing replays exactly.
+pub fn now() -> Duration {
+    Duration::from_micros(clock::monotonic_us())
+}
+
+fn emit_all(events: Vec<EventKind<'static>>) {
+    for kind in events {
+        stream::emit(&Event::now(kind));
+    }
+}
+
+/// Feed a user key event that happened at `at` to the engine, emitting any hotkey events it causes
+pub fn observe(pressed: bool, key: &str, at: Duration) {
+    let mut engine = lock();
+    if engine.hotkeys.is_empty() {
+        return;
+    }
+
+    let mut events = Vec::new();
+    // Run timers due before this event first; a replayed dump has no timer thread doing it
+    engine.tick(at, &mut events);
+    if pressed {
+        engine.press(key, at, &mut events);
+    } else {
+        engine.release(key, at, &mut events);
+    }
+    drop(engine);
+    TIMER_WAKE.notify_one();
+    emit_all(events);
+}
+
+/// Fire hold timers on a background thread while listening
+pub fn start_timer() {
+    thread::spawn(|| {
+        let mut engine = lock();
+        loop {
+            let now = now();
+            let mut events = Vec::new();
+            engine.tick(now, &mut events);
+            if !events.is_empty() {
+                drop(engine);
+                emit_all(events);
+                engine = lock();
+                continue;
+            }
+            engine = match engine.next_deadline() {
+                Some(deadline) => {
+                    TIMER_WAKE
+                        .wait_timeout(engine, deadline.saturating_sub(now))
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+                None => TIMER_WAKE.wait(engine).unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    });
+}
@@ -0,0 +1,217 @@
+//! Hotstrings: text expansion on typed triggers (`listen --hotstrings <file>`).
+//!
+//! The file is TOML:
+//!
+//!   [[hotstring]]
+//!   id = "signature"
+//!   trigger = ";sig"
+//!   expansion = "Best regards,\nAJ"
+//!   never_in = [{ app_id = "(?i)terminal" }]
+//!
+//! The characters the user types are matched against every trigger; when the
+//! text typed so far ends with one, `HotstringTriggered{id, trigger, expanded}`
+//! is emitted. With an `expansion` the helper also replaces the trigger: it
+//! presses Backspace once per trigger character and types the expansion
+//! (`expanded: true`). Without one only the event is emitted.
+//!
+//! Backspace takes back the last character. Any other key that types nothing
+//! (Enter, arrows, Ctrl/Alt/Meta shortcuts) starts over, but Shift, AltGr and
+//! Caps Lock don't. Mouse clicks aren't seen, so a trigger split by moving the
+//! cursor with the mouse still matches. Typed characters come from the
+//! layout (xkbcommon) on Linux and from rdev elsewhere; injected keystrokes
+//! are never matched. `only_in` and `never_in` work as for hotkeys (see
+//! [`crate::hotkeys`]).
+
+use enigo::{Direction, Key};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+
+use crate::active_window;
+use crate::backend::{self, Injector};
+use crate::event::{Event, EventKind};
+use crate::hotkeys::{RuleDefinition, Scope};
+use crate::stream;
+use crate::synthetic;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    hotstring: Vec<Definition>,
+}
+
+/// A hotstring as written in a `--hotstrings` file or a `[[hotstring]]` table of the config file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Definition {
+    id: String,
+    trigger: String,
+    expansion: Option<String>,
+    #[serde(default)]
+    only_in: Vec<RuleDefinition>,
+    #[serde(default)]
+    never_in: Vec<RuleDefinition>,
+}
+
+pub struct Hotstring {
+    id: String,
+    trigger: String,
+    expansion: Option<String>,
+    scope: Scope,
+}
+
+/// Keys that change what other keys type without typing anything themselves
+const TEXT_MODIFIERS: [&str; 4] = ["ShiftLeft", "ShiftRight", "AltGr", "CapsLock"];
+
+/// Keys that turn other keys into shortcuts
+const COMMAND_MODIFIERS: [&str; 5] = ["ControlLeft", "ControlRight", "Alt", "MetaLeft", "MetaRight"];
+
+struct Engine {
+    hotstrings: Vec<Hotstring>,
+    /// Characters typed since the last reset, at most as many as the longest trigger
+    typed: String,
+    /// Command modifiers currently down
+    commands: Vec<String>,
+}
+
+static ENGINE: Mutex<Engine> = Mutex::new(Engine {
+    hotstrings: Vec::new(),
+    typed: String::new(),
+    commands: Vec::new(),
+});
+
+static REPLACE: AtomicBool = AtomicBool::new(false);
+
+fn lock() -> MutexGuard<'static, Engine> {
+    ENGINE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Inject expansions while listening; `decode` only reports the triggers it finds
+pub fn set_replace(replace: bool) {
+    REPLACE.store(replace, Ordering::Relaxed);
+}
+
+/// Check and compile hotstring definitions
+pub fn compile(definitions: Vec<Definition>) -> Result<Vec<Hotstring>, String> {
+    let mut hotstrings: Vec<Hotstring> = Vec::with_capacity(definitions.len());
+    for definition in definitions {
+        if definition.trigger.is_empty() || definition.trigger.chars().any(char::is_control) {
+            return Err(format!("Hotstring {}: invalid trigger {:?}", definition.id, definition.trigger));
+        }
+        if hotstrings.iter().any(|other| other.id == definition.id) {
+            return Err(format!("Duplicate hotstring id: {}", definition.id));
+        }
+        hotstrings.push(Hotstring {
+            scope: Scope::compile(&definition.only_in, &definition.never_in)
+                .map_err(|e| format!("Hotstring {}: {}", definition.id, e))?,
+            id: definition.id,
+            trigger: definition.trigger,
+            expansion: definition.expansion,
+        });
+    }
+    Ok(hotstrings)
+}
+
+/// Read and compile the hotstring definitions in `path`
+pub fn read(path: &Path) -> Result<Vec<Hotstring>, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let file: File = toml::from_str(&source).map_err(|e| format!("{}: {}", path.display(), e))?;
+    compile(file.hotstring).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Replace the active hotstrings, returning how many there are
+pub fn install(hotstrings: Vec<Hotstring>) -> usize {
+    let count = hotstrings.len();
+    let mut engine = lock();
+    engine.hotstrings = hotstrings;
+    engine.typed.clear();
+    count
+}
+
+pub fn count() -> usize {
+    lock().hotstrings.len()
+}
+
+/// Forget what was typed, after a stretch where key events weren't seen (e.g. pause)
+pub fn reset() {
+    let mut engine = lock();
+    engine.typed.clear();
+    engine.commands.clear();
+}
+
+/// Feed a user key event to the engine; `text` is what the key typed, if anything
+pub fn observe(pressed: bool, key: &str, text: Option<&str>) {
+    let mut engine = lock();
+    if engine.hotstrings.is_empty() {
+        return;
+    }
+
+    if COMMAND_MODIFIERS.contains(&key) {
+        engine.commands.retain(|down| down != key);
+        if pressed {
+            engine.commands.push(key.to_string());
+        }
+        return;
+    }
+    if !pressed || TEXT_MODIFIERS.contains(&key) {
+        return;
+    }
+    if key == "Backspace" {
+        engine.typed.pop();
+        return;
+    }
+    let text = text.filter(|text| !text.is_empty() && !text.chars().any(char::is_control));
+    let Some(text) = text.filter(|_| engine.commands.is_empty()) else {
+        engine.typed.clear();
+        return;
+    };
+
+    engine.typed.push_str(text);
+    let longest = engine.hotstrings.iter().map(|hotstring| hotstring.trigger.chars().count()).max().unwrap_or(0);
+    let excess = engine.typed.chars().count().saturating_sub(longest);
+    if let Some((cut, _)) = engine.typed.char_indices().nth(excess) {
+        engine.typed.drain(..cut);
+    }
+
+    let window = active_window::current();
+    let Some(hotstring) = engine
+        .hotstrings
+        .iter()
+        .find(|hotstring| engine.typed.ends_with(&hotstring.trigger) && hotstring.scope.allows(window.as_ref()))
+    else {
+        return;
+    };
+    let expansion = hotstring.expansion.clone().filter(|_| REPLACE.load(Ordering::Relaxed));
+    let event = EventKind::HotstringTriggered {
+        id: hotstring.id.clone(),
+        trigger: hotstring.trigger.clone(),
+        expanded: expansion.is_some(),
+    };
+    let erase = hotstring.trigger.chars().count();
+    engine.typed.clear();
+    drop(engine);
+
+    stream::emit(&Event::now(event));
+    if let Some(expansion) = expansion {
+        // Off the listener thread, which must keep reading key events
+        thread::spawn(move || {
+            if let Err(e) = expand(erase, &expansion) {
+                eprintln!("Failed to expand hotstring: {}", e);
+            }
+        });
+    }
+}
+
+/// Erase the trigger's `erase` characters and type `expansion` in their place
+fn expand(erase: usize, expansion: &str) -> Result<(), String> {
+    let mut injector = Injector::new(backend::Options::default());
+    let _injection = synthetic::begin_injection();
+    for _ in 0..erase {
+        injector.key(Key::Backspace, Direction::Click)?;
+    }
+    injector.text(expansion)
+}
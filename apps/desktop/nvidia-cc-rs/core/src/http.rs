@@ -0,0 +1,232 @@
+//! Read-only HTTP status endpoint for a running listener (`listen --http ADDR`,
+//! or `serve --http ADDR`).
+//!
+//! Handy for checking the helper from a browser or curl without the desktop app.
+//! Every request must carry the session token, either as `?token=...` or as an
+//! `Authorization: Bearer ...` header:
+//!   /          - human-readable status page
+//!   /status    - JSON status (uptime, pause state, event counts, capabilities)
+//!   /devices   - JSON list of attached input devices
+//!   /gpu       - JSON list of NVIDIA GPUs and their telemetry, 503 without nvidia-smi
+//!
+//! At most `MAX_CONNECTIONS` requests are handled at once; more are closed on
+//! arrival.
+
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::clock;
+use crate::control;
+use crate::devices;
+use crate::gpu;
+use crate::stream;
+use crate::throttle;
+
+/// Slow or idle clients are dropped after this long
+pub(crate) const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Requests with a head larger than this are rejected
+pub(crate) const MAX_REQUEST_HEAD_BYTES: usize = 8 * 1024;
+
+/// Most requests handled at once
+const MAX_CONNECTIONS: usize = 16;
+
+struct Server {
+    token: String,
+    /// The listen session's Capabilities payload, echoed in /status
+    capabilities: Value,
+    /// Requests being handled
+    connections: AtomicUsize,
+}
+
+/// One of [`Server::connections`], until dropped
+struct Connection<'a>(&'a AtomicUsize);
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Random 128-bit hex token from the OS's CSPRNG
+pub fn generate_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| io::Error::other(format!("Cannot generate a token: {}", e)))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Bind `addr` and answer status requests on a background thread
+pub fn serve(addr: &str, token: String, capabilities: Value) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    if !local_addr.ip().is_loopback() {
+        eprintln!("Warning: HTTP status page is reachable from other machines on {}", local_addr);
+    }
+
+    let server = Arc::new(Server {
+        token,
+        capabilities,
+        connections: AtomicUsize::new(0),
+    });
+    thread::spawn(move || {
+        for connection in listener.incoming() {
+            let Ok(client) = connection else { continue };
+            if server.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                server.connections.fetch_sub(1, Ordering::SeqCst);
+                let peer = client.peer_addr().map(|peer| peer.to_string()).unwrap_or_default();
+                eprintln!("Refused HTTP client {}: {} requests are already being handled", peer, MAX_CONNECTIONS);
+                continue;
+            }
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                let _connection = Connection(&server.connections);
+                if let Err(e) = server.handle(client) {
+                    eprintln!("HTTP client error: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(local_addr)
+}
+
+impl Server {
+    fn handle(&self, mut client: TcpStream) -> io::Result<()> {
+        client.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        client.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+        let mut reader = BufReader::new(client.try_clone()?);
+        let mut request_line = String::new();
+        if !read_head_line(&mut reader, &mut request_line, MAX_REQUEST_HEAD_BYTES)? {
+            return respond(&mut client, "400 Bad Request", "text/plain", "Request line too long");
+        }
+
+        let mut authorization = None;
+        let mut head_bytes = request_line.len();
+        loop {
+            let mut header = String::new();
+            if !read_head_line(&mut reader, &mut header, MAX_REQUEST_HEAD_BYTES - head_bytes)? {
+                return respond(&mut client, "431 Request Header Fields Too Large", "text/plain", "Headers too large");
+            }
+            if header.trim().is_empty() {
+                break;
+            }
+            head_bytes += header.len();
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("authorization") {
+                    authorization = value.trim().strip_prefix("Bearer ").map(str::to_string);
+                }
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return respond(&mut client, "400 Bad Request", "text/plain", "Malformed request");
+        };
+        if method != "GET" {
+            return respond(&mut client, "405 Method Not Allowed", "text/plain", "Only GET is supported");
+        }
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query_token = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("token="));
+        let presented = authorization.as_deref().or(query_token).unwrap_or("");
+        if !constant_time_eq(presented.as_bytes(), self.token.as_bytes()) {
+            return respond(&mut client, "401 Unauthorized", "text/plain", "Missing or invalid token");
+        }
+
+        match path {
+            "/" => respond(&mut client, "200 OK", "text/html; charset=utf-8", &self.status_page()),
+            "/status" => respond(&mut client, "200 OK", "application/json", &self.status().to_string()),
+            "/devices" => {
+                let body = serde_json::to_string(&devices::snapshot()).unwrap();
+                respond(&mut client, "200 OK", "application/json", &body)
+            }
+            "/gpu" => match gpu::query() {
+                Ok(gpus) => respond(&mut client, "200 OK", "application/json", &serde_json::to_string(&gpus).unwrap()),
+                Err(e) => {
+                    let body = json!({ "error": e }).to_string();
+                    respond(&mut client, "503 Service Unavailable", "application/json", &body)
+                }
+            },
+            _ => respond(&mut client, "404 Not Found", "text/plain", "Not found"),
+        }
+    }
+
+    fn status(&self) -> Value {
+        json!({
+            "uptime_ms": clock::monotonic_us() / 1000,
+            "paused": control::is_paused(),
+            "events_emitted": stream::last_seq(),
+            "duplicates_dropped": throttle::duplicates(),
+            "rate_limited": throttle::rate_limited(),
+            "capabilities": self.capabilities,
+        })
+    }
+
+    fn status_page(&self) -> String {
+        let mut rows = String::new();
+        for device in devices::snapshot() {
+            rows.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                html_escape(&device.name),
+                html_escape(device.path.as_deref().unwrap_or("-")),
+                if device.active {
+                    "active".to_string()
+                } else {
+                    html_escape(&format!("stopped: {}", device.error.as_deref().unwrap_or("unknown")))
+                },
+            ));
+        }
+
+        format!(
+            "<!doctype html><html><head><title>nvidia-cc-rs status</title>\
+             <meta http-equiv=\"refresh\" content=\"5\"></head><body>\
+             <h1>nvidia-cc-rs {}</h1>\
+             <p>Uptime: {} s &middot; Capture: {} &middot; Events emitted: {}</p>\
+             <table border=\"1\" cellpadding=\"4\"><tr><th>Device</th><th>Path</th><th>State</th></tr>{}</table>\
+             </body></html>",
+            env!("CARGO_PKG_VERSION"),
+            clock::monotonic_us() / 1_000_000,
+            if control::is_paused() { "paused" } else { "running" },
+            stream::last_seq(),
+            rows,
+        )
+    }
+}
+
+/// Read a line of a request head into `line`, `limit` bytes at most; false when it's longer
+pub(crate) fn read_head_line(reader: &mut BufReader<TcpStream>, line: &mut String, limit: usize) -> io::Result<bool> {
+    let read = reader.by_ref().take(limit as u64).read_line(line)?;
+    Ok(read < limit || line.ends_with('\n'))
+}
+
+pub(crate) fn respond(client: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        client,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    client.flush()
+}
+
+/// Compare tokens without leaking the matching prefix length through timing
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,195 @@
+//! System idle time for `idle`, the `idle` stdin command and
+//! `UserIdle`/`UserActive` events with `--idle-after`.
+//!
+//! Idle time counts from the last keyboard or mouse input to any app, so the
+//! app can stop recording and release the microphone once the user has gone.
+//! Sources: GNOME's Mutter idle monitor, then `xprintidle` on X11, on Linux;
+//! `GetLastInputInfo` on Windows; the combined session event source on macOS.
+//! When none answers (wlroots compositors, say), a listening helper falls
+//! back to the last key or button it read from its own devices.
+
+use serde::Serialize;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+/// How often `--idle-after` looks at the idle time
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When the listener last read a key or button from a physical device
+static LAST_INPUT: Mutex<Option<Instant>> = Mutex::new(None);
+
+#[derive(Serialize)]
+pub struct Idle {
+    pub idle_secs: u64,
+    /// `mutter`, `xprintidle`, `windows`, `macos` or `listener`
+    pub source: &'static str,
+}
+
+/// Time since the last input, from the first source that answers
+pub fn query() -> Result<Idle, String> {
+    let (idle, source) = idle_time()?;
+    Ok(Idle {
+        idle_secs: idle.as_secs(),
+        source,
+    })
+}
+
+fn idle_time() -> Result<(Duration, &'static str), String> {
+    let last_input = *LAST_INPUT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match (platform::idle(), last_input) {
+        (Ok(found), _) => Ok(found),
+        (Err(_), Some(last_input)) => Ok((last_input.elapsed(), "listener")),
+        (Err(e), None) => Err(e),
+    }
+}
+
+/// A key or button was read from a physical device, paused or not
+pub(crate) fn note_input() {
+    *LAST_INPUT.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Instant::now());
+}
+
+/// Emit `UserIdle` once idle for `threshold`, then `UserActive` at the next input
+pub fn watch(threshold: Duration) {
+    // Counts from startup until the first input when only the listener can tell
+    note_input();
+    thread::spawn(move || {
+        let mut idle = false;
+        let mut last = Duration::ZERO;
+        let mut reported_error = false;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let current = match idle_time() {
+                Ok((current, _)) => current,
+                Err(e) => {
+                    if !reported_error {
+                        eprintln!("Idle tracking unavailable: {}", e);
+                        reported_error = true;
+                    }
+                    continue;
+                }
+            };
+            if !idle && current >= threshold {
+                idle = true;
+                stream::emit(&Event::now(EventKind::UserIdle { idle_secs: current.as_secs() }));
+            } else if idle && current < last {
+                // The idle time went back down, so there was input since the last look
+                idle = false;
+                stream::emit(&Event::now(EventKind::UserActive { idle_secs: last.as_secs() }));
+            }
+            last = current;
+        }
+    });
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::Command;
+    use std::time::Duration;
+
+    /// Standard output of a successful run of `program`
+    fn output(command: &mut Command, program: &str) -> Result<String, String> {
+        let output = command.output().map_err(|e| format!("{} unavailable: {}", program, e))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("{} exited with {}: {}", program, output.status, stderr.trim()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// GNOME, X11 or Wayland: `(uint64 12345,)`
+    fn mutter() -> Result<Duration, String> {
+        let reply = output(
+            Command::new("gdbus").args([
+                "call",
+                "--session",
+                "--dest=org.gnome.Mutter.IdleMonitor",
+                "--object-path=/org/gnome/Mutter/IdleMonitor/Core",
+                "--method=org.gnome.Mutter.IdleMonitor.GetIdletime",
+            ]),
+            "gdbus",
+        )?;
+        reply
+            .trim()
+            .strip_prefix("(uint64 ")
+            .and_then(|rest| rest.strip_suffix(",)"))
+            .and_then(|ms| ms.parse().ok())
+            .map(Duration::from_millis)
+            .ok_or_else(|| format!("unexpected idle monitor reply: {}", reply.trim()))
+    }
+
+    /// The X screen saver extension's idle time, in milliseconds
+    fn xprintidle() -> Result<Duration, String> {
+        if std::env::var_os("DISPLAY").is_none() {
+            return Err("xprintidle needs an X display".to_string());
+        }
+        let ms = output(&mut Command::new("xprintidle"), "xprintidle")?;
+        ms.trim()
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|_| format!("unexpected xprintidle output: {}", ms.trim()))
+    }
+
+    pub fn idle() -> Result<(Duration, &'static str), String> {
+        mutter()
+            .map(|idle| (idle, "mutter"))
+            .or_else(|mutter_error| {
+                xprintidle()
+                    .map(|idle| (idle, "xprintidle"))
+                    .map_err(|xprintidle_error| format!("{}; {}", mutter_error, xprintidle_error))
+            })
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::time::Duration;
+
+    use windows_sys::Win32::System::SystemInformation::GetTickCount;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    pub fn idle() -> Result<(Duration, &'static str), String> {
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+        if unsafe { GetLastInputInfo(&mut info) } == 0 {
+            return Err(format!("GetLastInputInfo failed: {}", std::io::Error::last_os_error()));
+        }
+        // Both tick counts wrap after 49.7 days
+        let ms = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+        Ok((Duration::from_millis(u64::from(ms)), "windows"))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::time::Duration;
+
+    const COMBINED_SESSION_STATE: i32 = 0;
+    const ANY_INPUT_EVENT_TYPE: u32 = !0;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceSecondsSinceLastEventType(state: i32, event_type: u32) -> f64;
+    }
+
+    pub fn idle() -> Result<(Duration, &'static str), String> {
+        let secs = unsafe { CGEventSourceSecondsSinceLastEventType(COMBINED_SESSION_STATE, ANY_INPUT_EVENT_TYPE) };
+        Duration::try_from_secs_f64(secs)
+            .map(|idle| (idle, "macos"))
+            .map_err(|_| format!("unexpected idle time: {}", secs))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    use std::time::Duration;
+
+    pub fn idle() -> Result<(Duration, &'static str), String> {
+        Err("Idle time isn't available on this platform".to_string())
+    }
+}
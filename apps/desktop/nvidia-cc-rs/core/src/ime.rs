@@ -0,0 +1,110 @@
+//! Input method detection for `write --ime-safe`.
+//!
+//! A CJK input method composes keystrokes into candidates, so text typed as
+//! synthetic key events comes out as pinyin or half-converted kana. When one
+//! is active, `--ime-safe` inserts the text by clipboard paste instead, which
+//! input methods pass through untouched.
+//!
+//! Detection: fcitx/fcitx5 and ibus through their command-line clients on
+//! Linux, the open status of the foreground window's IME on Windows, and the
+//! selected input source being an input method rather than a plain keyboard
+//! layout on macOS.
+
+/// Description of the active input method, or None when keystrokes reach apps unchanged
+pub fn active() -> Option<String> {
+    platform::active()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::Command;
+
+    fn command_output(program: &str, args: &[&str]) -> Option<String> {
+        let output = Command::new(program).args(args).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    pub fn active() -> Option<String> {
+        // fcitx*-remote prints 2 while an input method is on, 1 while typing goes straight through
+        for remote in ["fcitx5-remote", "fcitx-remote"] {
+            if command_output(remote, &[]).as_deref() == Some("2") {
+                let name = command_output(remote, &["-n"]).unwrap_or_default();
+                return Some(format!("{} ({})", remote.trim_end_matches("-remote"), name));
+            }
+        }
+
+        // ibus reports plain layouts as xkb:<layout>:: engines
+        let engine = command_output("ibus", &["engine"])?;
+        (!engine.is_empty() && !engine.starts_with("xkb:")).then(|| format!("ibus ({})", engine))
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use crate::input_source;
+
+    pub fn active() -> Option<String> {
+        // Keyboard layouts are com.apple.keylayout.*; IMEs register as *.inputmethod.*
+        input_source::active().filter(|id| id.contains(".inputmethod."))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ptr;
+
+    use windows_sys::Win32::UI::Input::Ime::ImmGetDefaultIMEWnd;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::GetKeyboardLayout;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowThreadProcessId, SendMessageTimeoutW, SMTO_ABORTIFHUNG, WM_IME_CONTROL,
+    };
+
+    const IMC_GETOPENSTATUS: usize = 0x0005;
+    const IME_QUERY_TIMEOUT_MS: u32 = 200;
+
+    /// Primary languages whose IMEs compose keystrokes
+    const LANG_CHINESE: u32 = 0x04;
+    const LANG_JAPANESE: u32 = 0x11;
+    const LANG_KOREAN: u32 = 0x12;
+
+    pub fn active() -> Option<String> {
+        let window = unsafe { GetForegroundWindow() };
+        if window.is_null() {
+            return None;
+        }
+        let thread = unsafe { GetWindowThreadProcessId(window, ptr::null_mut()) };
+        let language = (unsafe { GetKeyboardLayout(thread) } as usize & 0xffff) as u32;
+        if ![LANG_CHINESE, LANG_JAPANESE, LANG_KOREAN].contains(&(language & 0x3ff)) {
+            return None;
+        }
+
+        // A CJK layout with its IME switched off (alphanumeric mode) types keys unchanged
+        let ime_window = unsafe { ImmGetDefaultIMEWnd(window) };
+        if ime_window.is_null() {
+            return None;
+        }
+        let mut open = 0usize;
+        let answered = unsafe {
+            SendMessageTimeoutW(
+                ime_window,
+                WM_IME_CONTROL,
+                IMC_GETOPENSTATUS,
+                0,
+                SMTO_ABORTIFHUNG,
+                IME_QUERY_TIMEOUT_MS,
+                &mut open,
+            )
+        } != 0;
+        (answered && open != 0).then(|| format!("IME for language {:04x}", language))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    pub fn active() -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,200 @@
+//! Keeping the system awake for `inhibit-sleep` and the `inhibit-sleep`
+//! stdin command.
+//!
+//! Long dictation or a GPU benchmark shouldn't be cut short by the system
+//! suspending for idleness, so the app holds an inhibitor for as long as it
+//! needs one. Only idle sleep is held off: closing the lid or choosing
+//! Suspend still suspends. The inhibitor goes away with the helper, so one
+//! that crashed never keeps the machine up.
+//!
+//! Sources: a `systemd-inhibit` child holding a logind inhibitor lock on
+//! Linux, which exits when its stdin (our end of a pipe) closes;
+//! `SetThreadExecutionState` from a thread kept for it on Windows; an
+//! `IOPMAssertion` on macOS.
+
+use std::sync::Mutex;
+
+/// Shown by `systemd-inhibit --list` and `pmset -g assertions`
+#[cfg_attr(windows, allow(dead_code))]
+const WHO: &str = "NVIDIA Control Center";
+
+pub const DEFAULT_REASON: &str = "Dictation or benchmark in progress";
+
+static INHIBITOR: Mutex<Option<platform::Inhibitor>> = Mutex::new(None);
+
+/// Keep the system from sleeping until [`stop`], replacing an earlier inhibitor
+pub fn start(reason: &str) -> Result<(), String> {
+    let mut inhibitor = INHIBITOR.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    // Released first, so a failure leaves nothing behind
+    inhibitor.take();
+    *inhibitor = Some(platform::Inhibitor::acquire(reason)?);
+    Ok(())
+}
+
+/// Let the system sleep again; whether there was an inhibitor to release
+pub fn stop() -> bool {
+    INHIBITOR.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take().is_some()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io::Read;
+    use std::process::{Child, Command, Stdio};
+
+    use super::WHO;
+
+    pub struct Inhibitor {
+        child: Child,
+    }
+
+    impl Inhibitor {
+        pub fn acquire(reason: &str) -> Result<Self, String> {
+            let mut child = Command::new("systemd-inhibit")
+                .args(["--what=sleep:idle", "--mode=block"])
+                .arg(format!("--who={}", WHO))
+                .arg(format!("--why={}", reason))
+                .arg("cat")
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("systemd-inhibit unavailable: {}", e))?;
+            // The lock is taken before `cat` runs; a refusal ends systemd-inhibit at once
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            if let Ok(Some(status)) = child.try_wait() {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = child.stderr.take() {
+                    pipe.read_to_string(&mut stderr).ok();
+                }
+                return Err(format!("systemd-inhibit exited with {}: {}", status, stderr.trim()));
+            }
+            Ok(Inhibitor { child })
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            // Closing stdin ends `cat`, and with it the lock
+            self.child.stdin.take();
+            if self.child.wait().is_err() {
+                self.child.kill().ok();
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::sync::mpsc::{self, Sender};
+    use std::thread;
+
+    use windows_sys::Win32::System::Power::{SetThreadExecutionState, ES_CONTINUOUS, ES_SYSTEM_REQUIRED};
+
+    /// The execution state belongs to the thread that set it, which waits here until dropped
+    pub struct Inhibitor {
+        release: Sender<()>,
+    }
+
+    impl Inhibitor {
+        pub fn acquire(_reason: &str) -> Result<Self, String> {
+            let (release, released) = mpsc::channel();
+            let (acquired, result) = mpsc::channel();
+            thread::spawn(move || {
+                if unsafe { SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED) } == 0 {
+                    acquired.send(Err("SetThreadExecutionState failed".to_string())).ok();
+                    return;
+                }
+                acquired.send(Ok(())).ok();
+                released.recv().ok();
+                unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+            });
+            result.recv().map_err(|_| "execution state thread exited".to_string())??;
+            Ok(Inhibitor { release })
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            self.release.send(()).ok();
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void, CString};
+    use std::ptr;
+
+    use super::WHO;
+
+    type CFStringRef = *const c_void;
+
+    const UTF8: u32 = 0x0800_0100;
+    const ASSERTION_LEVEL_ON: u32 = 255;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithCString(allocator: *const c_void, string: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFRelease(object: *const c_void);
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(kind: CFStringRef, level: u32, name: CFStringRef, id: *mut u32) -> i32;
+        fn IOPMAssertionRelease(id: u32) -> i32;
+    }
+
+    /// A CFString for `text`, which the caller releases
+    fn cf_string(text: &str) -> Result<CFStringRef, String> {
+        let text = CString::new(text).map_err(|_| "text contains a NUL character".to_string())?;
+        let string = unsafe { CFStringCreateWithCString(ptr::null(), text.as_ptr(), UTF8) };
+        if string.is_null() {
+            return Err("CFStringCreateWithCString failed".to_string());
+        }
+        Ok(string)
+    }
+
+    pub struct Inhibitor {
+        id: u32,
+    }
+
+    impl Inhibitor {
+        pub fn acquire(reason: &str) -> Result<Self, String> {
+            let kind = cf_string("PreventUserIdleSystemSleep")?;
+            let name = match cf_string(&format!("{}: {}", WHO, reason)) {
+                Ok(name) => name,
+                Err(e) => {
+                    unsafe { CFRelease(kind) };
+                    return Err(e);
+                }
+            };
+            let mut id = 0;
+            let status = unsafe { IOPMAssertionCreateWithName(kind, ASSERTION_LEVEL_ON, name, &mut id) };
+            unsafe {
+                CFRelease(kind);
+                CFRelease(name);
+            }
+            if status != 0 {
+                return Err(format!("IOPMAssertionCreateWithName failed: {:#x}", status));
+            }
+            Ok(Inhibitor { id })
+        }
+    }
+
+    impl Drop for Inhibitor {
+        fn drop(&mut self) {
+            unsafe { IOPMAssertionRelease(self.id) };
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    pub struct Inhibitor;
+
+    impl Inhibitor {
+        pub fn acquire(_reason: &str) -> Result<Self, String> {
+            Err("Sleep inhibition isn't supported on this platform".to_string())
+        }
+    }
+}
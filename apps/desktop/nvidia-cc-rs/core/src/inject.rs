@@ -0,0 +1,411 @@
+//! Keystroke injection for `write`, `press` and `key down`/`key up`, and
+//! pointer input for `mouse`, on top of the backend fallback chain in
+//! [`crate::backend`].
+//!
+//! `key down` has to outlive the call that started it: backends release held
+//! keys when they are dropped, so the command keeps running while the key is held. It
+//! records itself in a per-key state file; `key up` removes that file to make
+//! the holder release and exit. The holder also releases on its own after a
+//! timeout, so a caller that crashes between the two can't leave a key stuck.
+
+use enigo::{Button, Direction, Key};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{self, Injector};
+use crate::clipboard::Selection;
+use crate::ime;
+use crate::keys::{self, Segment};
+use crate::parent;
+use crate::stats;
+use crate::synthetic;
+
+/// How often a holder checks whether `key up` asked it to release
+const HOLD_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Safety auto-release for `key down` when no `--timeout-ms` is given
+pub const DEFAULT_HOLD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long `key up` waits for the holder to release before doing it itself
+const RELEASE_HANDOFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// `[injection] ime_safe` from the config file
+static IME_SAFE_BY_DEFAULT: AtomicBool = AtomicBool::new(false);
+
+/// Make every write behave as if `--ime-safe` was given
+pub fn set_ime_safe_by_default(ime_safe: bool) {
+    IME_SAFE_BY_DEFAULT.store(ime_safe, Ordering::Relaxed);
+}
+
+pub fn ime_safe_by_default() -> bool {
+    IME_SAFE_BY_DEFAULT.load(Ordering::Relaxed)
+}
+
+/// How `write` inserts text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextMethod {
+    /// Typed as key events
+    Type,
+    /// Typed, or pasted while an input method is active (`--ime-safe`)
+    ImeSafe,
+    /// Pasted through the clipboard or, with a middle click, the primary selection (`--paste [--primary]`)
+    Paste(Selection),
+    /// Inserted through the named injection backend alone (`--backend <name>`)
+    Backend(&'static str),
+}
+
+impl TextMethod {
+    /// `ImeSafe` with `[injection] ime_safe` in the config file, else `Type`
+    pub fn default_for_write() -> Self {
+        if ime_safe_by_default() {
+            TextMethod::ImeSafe
+        } else {
+            TextMethod::Type
+        }
+    }
+
+    /// `Backend` for an injection backend's name, such as `ax`
+    pub fn backend(name: &str) -> Result<Self, String> {
+        backend_name(name).map(TextMethod::Backend)
+    }
+}
+
+fn backend_name(name: &str) -> Result<&'static str, String> {
+    backend::NAMES
+        .iter()
+        .find(|&&known| known == name)
+        .copied()
+        .ok_or_else(|| format!("unknown backend {:?} (expected {})", name, backend::NAMES.join(", ")))
+}
+
+/// Restrict and reorder the injection chain to `names` (`--inject-backend`), over `[injection] backends`
+pub fn set_backends(names: &[String]) -> Result<(), String> {
+    for name in names {
+        backend_name(name)?;
+    }
+    backend::set_override(names.to_vec());
+    Ok(())
+}
+
+/// The `--inject-backend` names; empty unless it was given
+pub fn backends() -> Vec<String> {
+    backend::overridden()
+}
+
+/// Types text and presses key combos, as the `write` and `press` commands do
+pub struct TextInjector {
+    method: TextMethod,
+    key_escapes: bool,
+}
+
+impl Default for TextInjector {
+    fn default() -> Self {
+        TextInjector {
+            method: TextMethod::default_for_write(),
+            key_escapes: false,
+        }
+    }
+}
+
+impl TextInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Paste text instead of typing it while an input method is active (`--ime-safe`)
+    pub fn ime_safe(mut self, ime_safe: bool) -> Self {
+        self.method = if ime_safe { TextMethod::ImeSafe } else { TextMethod::Type };
+        self
+    }
+
+    /// Always paste text through `selection` (`--paste [--primary]`)
+    pub fn paste(mut self, selection: Selection) -> Self {
+        self.method = TextMethod::Paste(selection);
+        self
+    }
+
+    /// Treat `{key}` and `{combo}` in the text as key presses (`--keys`)
+    pub fn key_escapes(mut self, key_escapes: bool) -> Self {
+        self.key_escapes = key_escapes;
+        self
+    }
+
+    pub fn write(&self, text: &str) -> Result<(), Box<dyn Error>> {
+        write_segments(&self.segments(text)?, self.method)
+    }
+
+    /// Like [`TextInjector::write`], calling `progress` as [`write_segments_with_progress`] does
+    pub fn write_with_progress(
+        &self,
+        text: &str,
+        progress: impl FnMut(usize, usize) -> bool,
+    ) -> Result<bool, Box<dyn Error>> {
+        write_segments_with_progress(&self.segments(text)?, self.method, progress)
+    }
+
+    /// Press a combo such as `ctrl+shift+v` (see [`keys::parse_combo`])
+    pub fn press(&self, combo: &str) -> Result<(), Box<dyn Error>> {
+        press_combo(&keys::parse_combo(combo)?)
+    }
+
+    fn segments(&self, text: &str) -> Result<Vec<Segment>, String> {
+        if self.key_escapes {
+            keys::parse_key_escapes(text)
+        } else {
+            Ok(vec![Segment::Text(text.to_string())])
+        }
+    }
+}
+
+/// Type text and key presses in order through a single injector, inserting text by `method`
+pub fn write_segments(segments: &[Segment], method: TextMethod) -> Result<(), Box<dyn Error>> {
+    write_segments_with_progress(segments, method, |_, _| true).map(|_| ())
+}
+
+fn text_injector(method: TextMethod) -> Injector {
+    let (paste_text, paste_selection, only) = match method {
+        TextMethod::Type => (false, Selection::Clipboard, None),
+        TextMethod::ImeSafe => {
+            let ime = ime::active();
+            if let Some(ime) = &ime {
+                eprintln!("Input method {} is active, pasting text instead of typing it", ime);
+            }
+            (ime.is_some(), Selection::Clipboard, None)
+        }
+        TextMethod::Paste(selection) => (true, selection, None),
+        TextMethod::Backend(name) => (false, Selection::Clipboard, Some(name)),
+    };
+    Injector::new(backend::Options {
+        paste_text,
+        paste_selection,
+        only,
+        ..backend::Options::default()
+    })
+}
+
+/// Characters typed per injector call when progress is reported between calls
+const PROGRESS_CHUNK_CHARS: usize = 32;
+
+/// Characters per injector call for `method`
+fn chunk_chars(method: TextMethod) -> usize {
+    match method {
+        // Each insert looks the focused field up again, through a helper process on Linux and Windows
+        TextMethod::Backend("ax") => usize::MAX,
+        _ => PROGRESS_CHUNK_CHARS,
+    }
+}
+
+/// Like [`write_segments`], reporting `(chars_done, total)` after every chunk
+///
+/// A key escape counts as one character. Returning `false` from `progress`
+/// stops the write after the current chunk; the result is whether it ran to
+/// completion.
+pub fn write_segments_with_progress(
+    segments: &[Segment],
+    method: TextMethod,
+    progress: impl FnMut(usize, usize) -> bool,
+) -> Result<bool, Box<dyn Error>> {
+    let result = inject_segments(segments, method, progress);
+    stats::count_write(result.is_ok());
+    result
+}
+
+fn inject_segments(
+    segments: &[Segment],
+    method: TextMethod,
+    mut progress: impl FnMut(usize, usize) -> bool,
+) -> Result<bool, Box<dyn Error>> {
+    let total = segments.iter().map(Segment::char_count).sum();
+    let mut injector = text_injector(method);
+    let chunk_chars = chunk_chars(method);
+
+    // Lets a running listener recognize the keystrokes we are about to inject
+    let _injection = synthetic::begin_injection();
+
+    let mut done = 0;
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => {
+                let mut rest = text.as_str();
+                while !rest.is_empty() {
+                    let split = rest
+                        .char_indices()
+                        .nth(chunk_chars)
+                        .map_or(rest.len(), |(index, _)| index);
+                    let (chunk, remainder) = rest.split_at(split);
+                    if let Err(e) = injector.text(chunk) {
+                        eprintln!("Failed to write text: {}", e);
+                        return Err(e.into());
+                    }
+                    done += chunk.chars().count();
+                    rest = remainder;
+                    if !progress(done, total) {
+                        return Ok(false);
+                    }
+                }
+            }
+            Segment::Keys(keys) => {
+                press_keys(&mut injector, keys)?;
+                done += 1;
+                if !progress(done, total) {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Press `keys` in order, then release them in reverse order
+pub fn press_combo(keys: &[Key]) -> Result<(), Box<dyn Error>> {
+    let mut injector = Injector::new(backend::Options::default());
+    let _injection = synthetic::begin_injection();
+    press_keys(&mut injector, keys)
+}
+
+fn press_keys(injector: &mut Injector, keys: &[Key]) -> Result<(), Box<dyn Error>> {
+    let mut held = Vec::with_capacity(keys.len());
+    let mut result = Ok(());
+    for &key in keys {
+        if let Err(e) = injector.key(key, Direction::Press) {
+            eprintln!("Failed to press {:?}: {}", key, e);
+            result = Err(e.into());
+            break;
+        }
+        held.push(key);
+    }
+
+    // Release everything that went down, even after a failure, so no modifier stays stuck
+    for &key in held.iter().rev() {
+        if let Err(e) = injector.key(key, Direction::Release) {
+            eprintln!("Failed to release {:?}: {}", key, e);
+            if result.is_ok() {
+                result = Err(e.into());
+            }
+        }
+    }
+
+    result
+}
+
+/// Move the pointer to desktop coordinates `(x, y)`, or by `(x, y)` pixels when `relative`
+pub fn mouse_move(x: i32, y: i32, relative: bool) -> Result<(), Box<dyn Error>> {
+    let mut injector = Injector::new(backend::Options::default());
+    let _injection = synthetic::begin_injection();
+    injector.mouse_move(x, y, relative).map_err(|e| {
+        eprintln!("Failed to move the pointer: {}", e);
+        e.into()
+    })
+}
+
+/// Click `button` `count` times (2 for a double click)
+pub fn mouse_click(button: Button, count: u32) -> Result<(), Box<dyn Error>> {
+    let mut injector = Injector::new(backend::Options::default());
+    let _injection = synthetic::begin_injection();
+    for _ in 0..count {
+        if let Err(e) = injector.mouse_button(button, Direction::Click) {
+            eprintln!("Failed to click {:?}: {}", button, e);
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+/// Scroll by wheel notches; positive `dx` scrolls right, positive `dy` down
+pub fn mouse_scroll(dx: i32, dy: i32) -> Result<(), Box<dyn Error>> {
+    let mut injector = Injector::new(backend::Options::default());
+    let _injection = synthetic::begin_injection();
+    injector.scroll(dx, dy).map_err(|e| {
+        eprintln!("Failed to scroll: {}", e);
+        e.into()
+    })
+}
+
+/// Per-user state file of the process holding `key` down
+fn held_key_path(key: Key) -> PathBuf {
+    let name: String = format!("{:?}", key).chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    synthetic::user_runtime_path(&format!("key-{}.held", name))
+}
+
+fn holder_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Whether a `key down` process is holding `key`
+pub fn is_held(key: Key) -> bool {
+    holder_pid(&held_key_path(key)).is_some_and(parent::is_alive)
+}
+
+/// Press `key` and hold it until `key up` or until `timeout` runs out
+pub fn hold_key(key: Key, timeout: Duration) -> Result<(), Box<dyn Error>> {
+    let path = held_key_path(key);
+    if let Some(pid) = holder_pid(&path).filter(|&pid| parent::is_alive(pid)) {
+        return Err(format!("{:?} is already held by process {}", key, pid).into());
+    }
+
+    // Releasing is this function's job; the drop-time release would double up with `key up`'s
+    let mut injector = Injector::new(backend::Options {
+        release_keys_when_dropped: false,
+        ..backend::Options::default()
+    });
+
+    {
+        let _injection = synthetic::begin_injection();
+        if let Err(e) = injector.key(key, Direction::Press) {
+            eprintln!("Failed to press {:?}: {}", key, e);
+            return Err(e.into());
+        }
+    }
+    let pid = std::process::id();
+    fs::write(&path, pid.to_string())?;
+
+    let started = Instant::now();
+    while holder_pid(&path) == Some(pid) {
+        if started.elapsed() >= timeout {
+            eprintln!("Auto-releasing {:?} after {} ms", key, timeout.as_millis());
+            fs::remove_file(&path).ok();
+            break;
+        }
+        thread::sleep(HOLD_POLL_INTERVAL);
+    }
+
+    let _injection = synthetic::begin_injection();
+    if let Err(e) = injector.key(key, Direction::Release) {
+        eprintln!("Failed to release {:?}: {}", key, e);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Release `key`, handing off to the `key down` process holding it when there is one
+pub fn release_key(key: Key) -> Result<(), Box<dyn Error>> {
+    let path = held_key_path(key);
+    let holder = holder_pid(&path).filter(|&pid| parent::is_alive(pid));
+    fs::remove_file(&path).ok();
+
+    if let Some(pid) = holder {
+        let started = Instant::now();
+        while started.elapsed() < RELEASE_HANDOFF_TIMEOUT {
+            if !parent::is_alive(pid) {
+                return Ok(());
+            }
+            thread::sleep(HOLD_POLL_INTERVAL);
+        }
+        eprintln!("Holder process {} did not release {:?}, releasing directly", pid, key);
+    }
+
+    // No live holder (or it hung): the key may still be down from a crashed caller
+    let mut injector = Injector::new(backend::Options::default());
+    let _injection = synthetic::begin_injection();
+    match injector.key(key, Direction::Release) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            eprintln!("Failed to release {:?}: {}", key, e);
+            Err(e.into())
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Opening input devices without the `input` group, which would let every
+//! process of the user read every keystroke.
+//!
+//! With file capabilities (`setcap cap_dac_read_search=p nvidia-cc-rs`) the
+//! listener holds CAP_DAC_READ_SEARCH in its permitted set only: [`restrict`]
+//! keeps it out of the effective, inheritable and ambient sets, and [`open`]
+//! raises it just for the open of an event node the user can't read. Tools
+//! the helper runs never get it.
+//!
+//! Or a broker, `input-broker`, run by root (or by a user in the `input`
+//! group), opens event nodes for the listener and passes the descriptors
+//! over its socket (`SCM_RIGHTS`), read-only. It only answers processes of the
+//! users it is told to allow that run this same executable, checked through
+//! the peer's credentials; `listen --input-broker <socket>` opens devices
+//! through it. A process of that user can still ptrace the listener unless
+//! `kernel.yama.ptrace_scope` forbids it.
+//!
+//! The broker reads one event node path per connection, and answers `ok`
+//! with the descriptor, or `error <message>`.
+
+use evdev::Device;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+/// Where `input-broker` listens without `--socket`
+pub const DEFAULT_BROKER_SOCKET: &str = "/run/nvidia-cc/input-broker.sock";
+
+const CAP_DAC_READ_SEARCH: u32 = 2;
+const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+/// Longest reply the broker sends
+const MAX_REPLY: usize = 512;
+
+static BROKER: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Open devices through the broker at `socket` from now on, or directly with `None`
+pub fn set_broker(socket: Option<PathBuf>) {
+    *BROKER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = socket;
+}
+
+fn broker() -> Option<PathBuf> {
+    BROKER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// Whether devices are opened through the broker
+pub fn uses_broker() -> bool {
+    broker().is_some()
+}
+
+/// Open the event node at `path` through the broker, or directly, with CAP_DAC_READ_SEARCH if it's permitted
+pub fn open(path: &Path) -> io::Result<Device> {
+    if let Some(socket) = broker() {
+        return Device::from_fd(request(&socket, path)?);
+    }
+    match Device::open(path) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && permitted() => {
+            with_capability(|| Device::open(path))
+        }
+        result => result,
+    }
+}
+
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+fn capabilities() -> io::Result<[CapData; 2]> {
+    let mut header = CapHeader { version: CAPABILITY_VERSION_3, pid: 0 };
+    let mut data = [CapData::default(); 2];
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(data)
+}
+
+fn set_capabilities(data: &[CapData; 2]) -> io::Result<()> {
+    let mut header = CapHeader { version: CAPABILITY_VERSION_3, pid: 0 };
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Whether CAP_DAC_READ_SEARCH is in the permitted set, as `setcap cap_dac_read_search=p` leaves it
+fn permitted() -> bool {
+    capabilities().is_ok_and(|data| data[0].permitted & (1 << CAP_DAC_READ_SEARCH) != 0)
+}
+
+/// Raise CAP_DAC_READ_SEARCH for `f`, then lower it again
+fn with_capability<T>(f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    let mut data = capabilities()?;
+    data[0].effective |= 1 << CAP_DAC_READ_SEARCH;
+    set_capabilities(&data)?;
+    let result = f();
+    data[0].effective &= !(1 << CAP_DAC_READ_SEARCH);
+    set_capabilities(&data)?;
+    result
+}
+
+/// Lower every capability the executable's file capabilities gave, keeping them permitted for [`open`]
+///
+/// Nothing to do without file capabilities, or as root.
+pub fn restrict() {
+    let Ok(mut data) = capabilities() else { return };
+    if data.iter().all(|set| set.effective == 0 && set.inheritable == 0) || unsafe { libc::geteuid() } == 0 {
+        return;
+    }
+    // Ambient capabilities go with the inheritable ones
+    for set in &mut data {
+        set.effective = 0;
+        set.inheritable = 0;
+    }
+    if let Err(e) = set_capabilities(&data) {
+        eprintln!("Cannot lower file capabilities: {}", e);
+    }
+}
+
+/// Ask the broker at `socket` for the event node at `path`
+fn request(socket: &Path, path: &Path) -> io::Result<OwnedFd> {
+    let mut stream = UnixStream::connect(socket).map_err(|e| {
+        io::Error::new(e.kind(), format!("Cannot reach the input broker at {}: {}", socket.display(), e))
+    })?;
+    writeln!(stream, "{}", path.display())?;
+    let mut reply = [0; MAX_REPLY];
+    let (received, fd) = receive_fd(&stream, &mut reply)?;
+    let reply = String::from_utf8_lossy(&reply[..received]);
+    match (reply.trim_end().strip_prefix("error "), fd) {
+        (Some(message), _) => {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("input broker: {}", message)))
+        }
+        (None, Some(fd)) if reply.trim_end() == "ok" => Ok(fd),
+        _ => Err(io::Error::other(format!("Unexpected reply from the input broker: {:?}", reply))),
+    }
+}
+
+fn receive_fd(stream: &UnixStream, buf: &mut [u8]) -> io::Result<(usize, Option<OwnedFd>)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut control = [0u8; 64];
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = control.len() as _;
+    let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut message, libc::MSG_CMSG_CLOEXEC) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut fd = None;
+    let mut header = unsafe { libc::CMSG_FIRSTHDR(&message) };
+    while !header.is_null() {
+        let cmsg = unsafe { &*header };
+        if cmsg.cmsg_level == libc::SOL_SOCKET && cmsg.cmsg_type == libc::SCM_RIGHTS {
+            let raw = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(header).cast::<RawFd>()) };
+            fd = Some(unsafe { OwnedFd::from_raw_fd(raw) });
+        }
+        header = unsafe { libc::CMSG_NXTHDR(&message, header) };
+    }
+    Ok((received as usize, fd))
+}
+
+/// Send `data` on `stream` with `fd` alongside it
+pub(crate) fn send_fd(stream: &UnixStream, data: &[u8], fd: RawFd) -> io::Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut _,
+        iov_len: data.len(),
+    };
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as usize;
+    let mut control = vec![0u8; space];
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = space as _;
+    unsafe {
+        let header = libc::CMSG_FIRSTHDR(&message);
+        (*header).cmsg_level = libc::SOL_SOCKET;
+        (*header).cmsg_type = libc::SCM_RIGHTS;
+        (*header).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(header).cast::<RawFd>(), fd);
+    }
+    if unsafe { libc::sendmsg(stream.as_raw_fd(), &message, libc::MSG_NOSIGNAL) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Uid and pid of the process at the other end of `stream`
+fn peer(stream: &UnixStream) -> io::Result<(u32, i32)> {
+    let mut credentials: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut credentials as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((credentials.uid, credentials.pid))
+}
+
+/// `name` as a uid: a number, or a user in /etc/passwd
+pub fn resolve_user(name: &str) -> Result<u32, String> {
+    if let Ok(uid) = name.parse() {
+        return Ok(uid);
+    }
+    let passwd = fs::read_to_string("/etc/passwd").map_err(|e| format!("Cannot read /etc/passwd: {}", e))?;
+    passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 2 && fields[0] == name)
+        .and_then(|fields| fields[2].parse().ok())
+        .ok_or_else(|| format!("Unknown user: {}", name))
+}
+
+/// Serve event nodes on `socket` to `allowed` users running this executable, until the socket fails
+pub fn serve_broker(socket: &Path, allowed: &[u32]) -> io::Result<()> {
+    let exe = std::env::current_exe()?;
+    if fs::symlink_metadata(socket).is_ok_and(|meta| meta.file_type().is_socket()) {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another broker is serving it"));
+        }
+        fs::remove_file(socket)?;
+    }
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    // Anyone may connect; who gets an answer is decided by their credentials
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o666))?;
+    eprintln!("Brokering input devices on {}", socket.display());
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let (allowed, exe) = (allowed.to_vec(), exe.clone());
+        thread::spawn(move || answer(stream, &allowed, &exe));
+    }
+    Ok(())
+}
+
+fn answer(stream: UnixStream, allowed: &[u32], exe: &Path) {
+    let mut request = String::new();
+    let reply = BufReader::new(&stream)
+        .read_line(&mut request)
+        .map_err(|e| format!("cannot read the request: {}", e))
+        .and_then(|_| grant(&stream, request.trim_end(), allowed, exe));
+    let sent = match reply {
+        Ok((file, uid)) => {
+            eprintln!("Opened {} for uid {}", request.trim_end(), uid);
+            send_fd(&stream, b"ok\n", file.as_raw_fd())
+        }
+        Err(message) => {
+            eprintln!("Refused {:?}: {}", request.trim_end(), message);
+            (&stream).write_all(format!("error {}\n", message).as_bytes())
+        }
+    };
+    if let Err(e) = sent {
+        eprintln!("Cannot answer an input broker client: {}", e);
+    }
+}
+
+/// Open `path` read-only for the peer, if it may have it
+fn grant(stream: &UnixStream, path: &str, allowed: &[u32], exe: &Path) -> Result<(File, u32), String> {
+    let (uid, pid) = peer(stream).map_err(|e| format!("cannot tell who is asking: {}", e))?;
+    if !allowed.contains(&uid) {
+        return Err(format!("uid {} isn't allowed", uid));
+    }
+    let peer_exe = fs::read_link(format!("/proc/{}/exe", pid)).map_err(|e| format!("cannot check the client: {}", e))?;
+    if peer_exe != exe {
+        return Err(format!("only {} may ask, not {}", exe.display(), peer_exe.display()));
+    }
+    let canonical = fs::canonicalize(path).map_err(|e| format!("{}: {}", path, e))?;
+    let is_event_node = canonical
+        .strip_prefix("/dev/input")
+        .ok()
+        .and_then(|name| name.to_str()?.strip_prefix("event"))
+        .is_some_and(|number| !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()));
+    if !is_event_node {
+        return Err(format!("{} isn't an input event node", path));
+    }
+    let file = File::open(&canonical).map_err(|e| format!("{}: {}", canonical.display(), e))?;
+    Ok((file, uid))
+}
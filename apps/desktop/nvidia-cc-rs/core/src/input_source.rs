@@ -0,0 +1,183 @@
+//! Active keyboard layout tracking on macOS and Windows.
+//!
+//! macOS posts a distributed notification when the selected input source
+//! changes; it is delivered on the run loop rdev's `listen` runs, so [`watch`]
+//! must be called from that (the main) thread. Windows only sends
+//! WM_INPUTLANGCHANGE to the focused window, so the layout of the foreground
+//! window's thread is polled instead, which also reports per-app layouts as
+//! focus moves. Either way a change is emitted as `LayoutChanged`.
+
+use std::sync::Mutex;
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+/// Last layout seen, so only actual changes are emitted
+static LAST: Mutex<Option<String>> = Mutex::new(None);
+
+/// Identifier of the active layout, e.g. `com.apple.keylayout.French` or `fr-FR`
+pub fn current() -> Option<String> {
+    platform::current()
+}
+
+/// The layout the watcher last saw, or a fresh query when nothing is watching
+///
+/// Lets worker threads avoid Text Input Source calls, which macOS wants on the main thread.
+pub fn active() -> Option<String> {
+    let last = LAST.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+    last.or_else(current)
+}
+
+/// Start emitting `LayoutChanged` whenever the active layout changes
+pub fn watch() {
+    update(current());
+    platform::watch();
+}
+
+fn update(current: Option<String>) {
+    let Some(current) = current else {
+        return;
+    };
+    let mut last = LAST.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(previous) = last.replace(current.clone()).filter(|previous| *previous != current) {
+        drop(last);
+        eprintln!("Keyboard layout changed: {} -> {}", previous, current);
+        stream::emit(&Event::now(EventKind::LayoutChanged {
+            layout: current,
+            previous,
+        }));
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void, CStr};
+    use std::ptr;
+
+    type CFTypeRef = *const c_void;
+    type CFStringRef = *const c_void;
+    type CFNotificationCallback = extern "C" fn(CFTypeRef, *const c_void, CFStringRef, *const c_void, CFTypeRef);
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const CF_NOTIFICATION_SUSPENSION_BEHAVIOR_DELIVER_IMMEDIATELY: isize = 4;
+
+    #[link(name = "Carbon", kind = "framework")]
+    extern "C" {
+        static kTISPropertyInputSourceID: CFStringRef;
+        static kTISNotifySelectedKeyboardInputSourceChanged: CFStringRef;
+        fn TISCopyCurrentKeyboardInputSource() -> CFTypeRef;
+        fn TISGetInputSourceProperty(source: CFTypeRef, key: CFStringRef) -> CFTypeRef;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: CFTypeRef);
+        fn CFStringGetCString(string: CFStringRef, buffer: *mut c_char, size: isize, encoding: u32) -> u8;
+        fn CFNotificationCenterGetDistributedCenter() -> CFTypeRef;
+        fn CFNotificationCenterAddObserver(
+            center: CFTypeRef,
+            observer: *const c_void,
+            callback: CFNotificationCallback,
+            name: CFStringRef,
+            object: *const c_void,
+            suspension_behavior: isize,
+        );
+    }
+
+    pub fn current() -> Option<String> {
+        unsafe {
+            let source = TISCopyCurrentKeyboardInputSource();
+            if source.is_null() {
+                return None;
+            }
+            // Owned by `source`, so it is only read before the release
+            let id = TISGetInputSourceProperty(source, kTISPropertyInputSourceID);
+            let mut buffer = [0 as c_char; 256];
+            let copied = !id.is_null()
+                && CFStringGetCString(id, buffer.as_mut_ptr(), buffer.len() as isize, CF_STRING_ENCODING_UTF8) != 0;
+            CFRelease(source);
+            copied.then(|| CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+
+    extern "C" fn selected_source_changed(
+        _center: CFTypeRef,
+        _observer: *const c_void,
+        _name: CFStringRef,
+        _object: *const c_void,
+        _user_info: CFTypeRef,
+    ) {
+        super::update(current());
+    }
+
+    pub fn watch() {
+        unsafe {
+            CFNotificationCenterAddObserver(
+                CFNotificationCenterGetDistributedCenter(),
+                ptr::null(),
+                selected_source_changed,
+                kTISNotifySelectedKeyboardInputSourceChanged,
+                ptr::null(),
+                CF_NOTIFICATION_SUSPENSION_BEHAVIOR_DELIVER_IMMEDIATELY,
+            );
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ptr;
+    use std::thread;
+    use std::time::Duration;
+
+    use windows_sys::Win32::Globalization::LCIDToLocaleName;
+    use windows_sys::Win32::System::SystemServices::LOCALE_NAME_MAX_LENGTH;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::GetKeyboardLayout;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn current() -> Option<String> {
+        let window = unsafe { GetForegroundWindow() };
+        if window.is_null() {
+            return None;
+        }
+        let thread = unsafe { GetWindowThreadProcessId(window, ptr::null_mut()) };
+        let layout = unsafe { GetKeyboardLayout(thread) } as usize;
+        if layout == 0 {
+            return None;
+        }
+
+        // Low word: language; high word: the physical layout, which differs for e.g. Dvorak
+        let language = (layout & 0xffff) as u32;
+        let device = ((layout >> 16) & 0xffff) as u32;
+        let mut buffer = [0u16; LOCALE_NAME_MAX_LENGTH as usize];
+        let len = unsafe { LCIDToLocaleName(language, buffer.as_mut_ptr(), buffer.len() as i32, 0) };
+        let locale = if len > 1 {
+            String::from_utf16_lossy(&buffer[..len as usize - 1])
+        } else {
+            format!("{:04x}", language)
+        };
+        Some(if device == language {
+            locale
+        } else {
+            format!("{} ({:04x})", locale, device)
+        })
+    }
+
+    pub fn watch() {
+        thread::spawn(|| loop {
+            thread::sleep(POLL_INTERVAL);
+            super::update(current());
+        });
+    }
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+mod platform {
+    pub fn current() -> Option<String> {
+        None
+    }
+
+    pub fn watch() {}
+}
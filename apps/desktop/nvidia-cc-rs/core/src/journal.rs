@@ -0,0 +1,317 @@
+//! Keeping emitted events for diagnosis: `--record-to` and `--record-last`.
+//!
+//! `--record-to <path>` appends every event `listen` emits to an NDJSON file.
+//! Once the file reaches 10 MiB it is rotated to `<path>.1`, the previous
+//! `.1` to `.2` and so on, keeping three old files. `--record-last <n>` keeps
+//! only the last n events in memory instead, written out by the stdin command
+//! `dump [<path>]` (by default `nvidia-cc-rs-<uid>.events.ndjson` in the
+//! runtime directory), which answers with `EventsDumped{path, events}`.
+//!
+//! Either way, when a hotkey didn't fire the events around it can be read
+//! back, or replayed with `listen --backend replay`. SIGUSR1 already pauses
+//! capture, so dumping has no signal of its own.
+//!
+//! A path ending in `.zst` is written zstd-compressed, `--record-to` and
+//! `dump` alike; rotation then counts compressed bytes. A compressed
+//! recording is flushed once a second rather than after every event, so one
+//! cut off by a crash loses at most the last second. Rotated files, and the
+//! current one once `listen` exits on its own, are complete frames.
+//!
+//! Recordings hold every keystroke, so the files are created readable by
+//! the user only, as the socket is, and `HttpListening`/`TcpListening` are
+//! left out of them: their tokens are for whoever reads stdout. Without a
+//! runtime directory the default dump goes to the shared temporary
+//! directory, where it is only ever created anew, never opened through
+//! whatever another user left at that name.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::stream;
+use crate::synthetic;
+use crate::zstd;
+
+/// Size a recording grows to before it is rotated
+pub const ROTATE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Rotated recordings kept besides the current one
+pub const ROTATED_FILES: usize = 3;
+
+/// How often a compressed recording is flushed
+const COMPRESSED_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+enum Output {
+    Plain(File),
+    Zstd(zstd::Encoder<File>),
+}
+
+/// A recording being appended to and rotated
+struct Recording {
+    path: PathBuf,
+    output: Output,
+    /// Size of the file when it was opened
+    existing: u64,
+    /// Bytes written to a plain file since
+    written: u64,
+    /// Compressed events written since the last flush
+    unflushed: bool,
+}
+
+impl Recording {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = private(OpenOptions::new().create(true).append(true)).open(path)?;
+        let existing = file.metadata()?.len();
+        let output = if zstd::is_compressed(path) {
+            Output::Zstd(zstd::Encoder::new(file).map_err(io::Error::other)?)
+        } else {
+            Output::Plain(file)
+        };
+        Ok(Recording {
+            path: path.to_path_buf(),
+            output,
+            existing,
+            written: 0,
+            unflushed: false,
+        })
+    }
+
+    /// Bytes in the file, compressed ones for a compressed recording
+    fn size(&self) -> u64 {
+        match &self.output {
+            Output::Plain(_) => self.existing + self.written,
+            Output::Zstd(encoder) => self.existing + encoder.written(),
+        }
+    }
+
+    /// `<path>.<index>`
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Output::Zstd(encoder) = &mut self.output {
+            encoder.finish()?;
+        }
+        for index in (1..ROTATED_FILES).rev() {
+            fs::rename(self.rotated(index), self.rotated(index + 1)).ok();
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        *self = Recording::open(&self.path)?;
+        Ok(())
+    }
+}
+
+impl Write for Recording {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only between lines, so no event is split across two files
+        if self.size() >= ROTATE_BYTES && buf.first() != Some(&b'\n') {
+            self.rotate()?;
+        }
+        match &mut self.output {
+            Output::Plain(file) => {
+                let written = file.write(buf)?;
+                self.written += written as u64;
+                Ok(written)
+            }
+            Output::Zstd(encoder) => {
+                self.unflushed = true;
+                encoder.write(buf)
+            }
+        }
+    }
+
+    /// Called after every event; compressed recordings are left to [`flush_compressed`],
+    /// as flushing ends a zstd block and an event per block compresses poorly
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.output {
+            Output::Plain(file) => file.flush(),
+            Output::Zstd(_) => Ok(()),
+        }
+    }
+}
+
+/// `options` creating files readable and writable by the user only
+fn private(options: &mut OpenOptions) -> &mut OpenOptions {
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(options, 0o600);
+    options
+}
+
+/// Events whose serialized `line` carries a token, which recordings leave out
+fn carries_token(line: &[u8]) -> bool {
+    [&b"\"event_type\":\"HttpListening\""[..], &b"\"event_type\":\"TcpListening\""[..]]
+        .iter()
+        .any(|needle| line.windows(needle.len()).any(|window| window == *needle))
+}
+
+/// The `--record-to` recording
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+fn recording() -> MutexGuard<'static, Option<Recording>> {
+    RECORDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Stream subscriber writing to [`RECORDING`]
+#[derive(Default)]
+struct Recorder {
+    line: Vec<u8>,
+}
+
+impl Write for Recorder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            if !carries_token(&self.line) {
+                if let Some(recording) = recording().as_mut() {
+                    // Apart, as the recording only rotates between lines
+                    recording.write_all(&self.line)?;
+                    recording.write_all(b"\n")?;
+                }
+            }
+            self.line.clear();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        recording().as_mut().map_or(Ok(()), Recording::flush)
+    }
+}
+
+/// Flush the compressed recording every [`COMPRESSED_FLUSH_INTERVAL`] it was written to
+fn flush_compressed() {
+    loop {
+        thread::sleep(COMPRESSED_FLUSH_INTERVAL);
+        if let Some(recording) = recording().as_mut() {
+            if let (Output::Zstd(encoder), true) = (&mut recording.output, std::mem::take(&mut recording.unflushed)) {
+                encoder.flush().ok();
+            }
+        }
+    }
+}
+
+/// Start appending every emitted event to `path`
+pub fn record_to(path: &Path) -> Result<(), String> {
+    let opened = Recording::open(path).map_err(|e| format!("Cannot record to {}: {}", path.display(), e))?;
+    let compressed = matches!(opened.output, Output::Zstd(_));
+    if recording().replace(opened).is_some() {
+        return Err("Already recording".to_string());
+    }
+    if compressed {
+        thread::spawn(flush_compressed);
+    }
+    stream::subscribe(Box::new(Recorder::default()), None).map_err(|e| e.to_string())
+}
+
+/// Complete the recording's file before exiting: end a compressed recording's frame
+pub fn finish() {
+    if let Some(recording) = recording().as_mut() {
+        match &mut recording.output {
+            Output::Plain(file) => file.flush().ok(),
+            Output::Zstd(encoder) => encoder.finish().ok(),
+        };
+    }
+}
+
+/// The last events emitted, with `--record-last`
+struct Ring {
+    capacity: usize,
+    lines: VecDeque<Vec<u8>>,
+}
+
+static LAST: Mutex<Option<Ring>> = Mutex::new(None);
+
+/// Stream subscriber filling [`LAST`]
+#[derive(Default)]
+struct Keeper {
+    line: Vec<u8>,
+}
+
+impl Write for Keeper {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let mut last = LAST.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(ring) = last.as_mut().filter(|_| !carries_token(&self.line)) {
+                // Once full, the oldest line's buffer takes the new one
+                let mut line = if ring.lines.len() == ring.capacity {
+                    ring.lines.pop_front().unwrap_or_default()
+                } else {
+                    Vec::new()
+                };
+                line.clear();
+                line.extend_from_slice(&self.line);
+                ring.lines.push_back(line);
+            }
+            self.line.clear();
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Start keeping the last `count` emitted events for [`dump`]
+pub fn keep_last(count: usize) -> Result<(), String> {
+    *LAST.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(Ring {
+        capacity: count.max(1),
+        lines: VecDeque::with_capacity(count.max(1)),
+    });
+    stream::subscribe(Box::new(Keeper::default()), None).map_err(|e| e.to_string())
+}
+
+/// Where `dump` writes when not given a path
+pub fn default_dump_path() -> PathBuf {
+    synthetic::user_runtime_path("events.ndjson")
+}
+
+/// Write the kept events to `path`, oldest first, returning how many there were
+pub fn dump(path: &Path) -> Result<usize, String> {
+    let mut contents = Vec::new();
+    let count = {
+        let last = LAST.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let ring = last.as_ref().ok_or("No events are kept; start listen with --record-last <n>")?;
+        for line in &ring.lines {
+            contents.extend_from_slice(line);
+            contents.push(b'\n');
+        }
+        ring.lines.len()
+    };
+    let write = || -> io::Result<()> {
+        let mut options = OpenOptions::new();
+        if path.parent() == Some(std::env::temp_dir().as_path()) {
+            // Only our own earlier dump can be removed from a sticky directory
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            options.write(true).create_new(true);
+        } else {
+            options.write(true).create(true).truncate(true);
+        }
+        let mut file = private(&mut options).open(path)?;
+        if !zstd::is_compressed(path) {
+            return file.write_all(&contents);
+        }
+        let mut encoder = zstd::Encoder::new(file).map_err(io::Error::other)?;
+        encoder.write_all(&contents)?;
+        encoder.finish()
+    };
+    write().map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(count)
+}
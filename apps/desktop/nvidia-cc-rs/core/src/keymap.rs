@@ -0,0 +1,85 @@
+//! `keymap dump`: every key code the listener can report and the name it is
+//! emitted as, for debugging keys that come out under an unexpected name.
+//!
+//! Each entry has `code` (what `--raw-scancodes` reports), `native` (the evdev
+//! `KEY_*` name on Linux, the virtual key on Windows), `name` with `[remap]`
+//! applied, `default` when a rule changed it, and `devices` listing the
+//! `[[device_remap]]` rules that rename it on matching devices.
+
+use serde_json::{json, Value};
+
+use crate::remap;
+
+/// The effective mapping, as printed by `keymap dump`
+pub fn dump() -> Value {
+    json!({
+        "platform": std::env::consts::OS,
+        "keys": platform::keys(),
+    })
+}
+
+fn entry(code: Option<u32>, native: Value, default: &str) -> Value {
+    let name = remap::apply(&[], default);
+    let mut entry = json!({
+        "code": code,
+        "native": native,
+        "name": name,
+    });
+    if name != default {
+        entry["default"] = json!(default);
+    }
+    let devices = remap::device_rules(default);
+    if !devices.is_empty() {
+        entry["devices"] = devices
+            .into_iter()
+            .map(|(device, name)| json!({"device": device, "name": name}))
+            .collect();
+    }
+    entry
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use serde_json::{json, Value};
+
+    use super::entry;
+    use crate::listener::evdev_key_to_rdev_name;
+
+    /// Highest key code evdev defines (`KEY_MAX`)
+    const KEY_MAX: u16 = 0x2ff;
+
+    pub fn keys() -> Vec<Value> {
+        (0..=KEY_MAX)
+            .map(evdev::KeyCode::new)
+            .filter_map(|key| {
+                let native = format!("{:?}", key);
+                // Codes the kernel leaves unassigned
+                if key == evdev::KeyCode::KEY_RESERVED || native.starts_with("unknown") {
+                    return None;
+                }
+                Some(entry(Some(key.code() as u32), json!(native), evdev_key_to_rdev_name(key)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use serde_json::{json, Value};
+
+    use super::entry;
+    use crate::media;
+    use crate::scancode;
+
+    pub fn keys() -> Vec<Value> {
+        let named = scancode::rdev_keys()
+            .iter()
+            .map(|(key, native)| entry(scancode::reported(*native), json!(native), &format!("{:?}", key)));
+        // Keys rdev reports as Unknown, named by the listener
+        let unknown = (0..=u8::MAX as u32).filter_map(|native| {
+            let name = media::rdev_unknown_name(native)?;
+            Some(entry(scancode::reported(native), json!(native), name))
+        });
+        named.chain(unknown).collect()
+    }
+}
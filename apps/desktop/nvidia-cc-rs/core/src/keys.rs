@@ -0,0 +1,234 @@
+//! Key names accepted by the injection commands.
+//!
+//! Names are case-insensitive and come in two flavours: short human names
+//! (`ctrl`, `cmd`, `enter`, `pagedown`, `f5`, `v`) and the rdev-style names the
+//! listener emits (`ControlLeft`, `KeyV`, `Num1`, `UpArrow`), so a captured
+//! key can be fed straight back into `press`. The `Digit1`/`Numpad1` style the
+//! Linux listener used to emit is still accepted. Mouse button names live
+//! here too.
+
+use enigo::{Button, Key};
+
+/// Resolve a single key name
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let lower = name.to_ascii_lowercase();
+
+    // A single character types that character's key
+    let mut chars = lower.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Unicode(c));
+    }
+
+    // rdev-style letter and digit names: KeyA, Num1 (or Digit1)
+    if let Some(rest) = lower
+        .strip_prefix("key")
+        .or_else(|| lower.strip_prefix("digit"))
+        .or_else(|| lower.strip_prefix("num"))
+    {
+        let mut chars = rest.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphanumeric() {
+                return Ok(Key::Unicode(c));
+            }
+        }
+    }
+
+    if let Some(number) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        return function_key(number).ok_or_else(|| format!("Unsupported function key: {}", name));
+    }
+
+    if let Some(digit) = lower
+        .strip_prefix("numpad")
+        .or_else(|| lower.strip_prefix("kp"))
+        .and_then(|n| n.parse::<u8>().ok())
+    {
+        return numpad_key(digit).ok_or_else(|| format!("Unknown key: {}", name));
+    }
+
+    let key = match lower.as_str() {
+        // Modifiers
+        "ctrl" | "control" => Key::Control,
+        "controlleft" | "lctrl" => Key::LControl,
+        "controlright" | "rctrl" => Key::RControl,
+        "shift" => Key::Shift,
+        "shiftleft" | "lshift" => Key::LShift,
+        "shiftright" | "rshift" => Key::RShift,
+        "alt" | "option" | "opt" => Key::Alt,
+        "meta" | "cmd" | "command" | "super" | "win" | "windows" | "metaleft" | "metaright" => Key::Meta,
+
+        // Editing and navigation
+        "enter" | "return" | "kpreturn" | "numpadenter" => Key::Return,
+        "tab" => Key::Tab,
+        "space" => Key::Space,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "escape" | "esc" => Key::Escape,
+        "up" | "uparrow" => Key::UpArrow,
+        "down" | "downarrow" => Key::DownArrow,
+        "left" | "leftarrow" => Key::LeftArrow,
+        "right" | "rightarrow" => Key::RightArrow,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" | "pgup" => Key::PageUp,
+        "pagedown" | "pgdn" => Key::PageDown,
+        "capslock" => Key::CapsLock,
+        #[cfg(not(target_os = "macos"))]
+        "insert" | "ins" => Key::Insert,
+        #[cfg(not(target_os = "macos"))]
+        "printscreen" | "print" => Key::PrintScr,
+        #[cfg(not(target_os = "macos"))]
+        "pause" => Key::Pause,
+        #[cfg(not(target_os = "macos"))]
+        "numlock" => Key::Numlock,
+
+        // Punctuation by name, for combos where the symbol itself is awkward to write
+        "plus" => Key::Unicode('+'),
+        "minus" => Key::Unicode('-'),
+        "equal" => Key::Unicode('='),
+        "comma" => Key::Unicode(','),
+        "period" | "dot" => Key::Unicode('.'),
+        "slash" => Key::Unicode('/'),
+        "backslash" => Key::Unicode('\\'),
+        "semicolon" => Key::Unicode(';'),
+        "quote" => Key::Unicode('\''),
+        "backquote" | "grave" => Key::Unicode('`'),
+        "bracketleft" | "leftbracket" => Key::Unicode('['),
+        "bracketright" | "rightbracket" => Key::Unicode(']'),
+
+        // Numpad operators
+        "numpadadd" | "kpplus" => Key::Add,
+        "numpadsubtract" | "kpminus" => Key::Subtract,
+        "numpadmultiply" | "kpmultiply" => Key::Multiply,
+        "numpaddivide" | "kpdivide" => Key::Divide,
+        "numpaddecimal" | "kpdelete" => Key::Decimal,
+
+        // Media
+        "volumeup" => Key::VolumeUp,
+        "volumedown" => Key::VolumeDown,
+        "mute" | "volumemute" => Key::VolumeMute,
+        "playpause" | "mediaplaypause" => Key::MediaPlayPause,
+        "next" | "nexttrack" | "medianexttrack" => Key::MediaNextTrack,
+        "prev" | "previous" | "prevtrack" | "mediaprevtrack" => Key::MediaPrevTrack,
+
+        _ => return Err(format!("Unknown key: {}", name)),
+    };
+    Ok(key)
+}
+
+fn function_key(number: u8) -> Option<Key> {
+    const KEYS: [Key; 20] = [
+        Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+        Key::F11, Key::F12, Key::F13, Key::F14, Key::F15, Key::F16, Key::F17, Key::F18, Key::F19, Key::F20,
+    ];
+    KEYS.get(usize::from(number).checked_sub(1)?).copied()
+}
+
+fn numpad_key(digit: u8) -> Option<Key> {
+    const KEYS: [Key; 10] = [
+        Key::Numpad0, Key::Numpad1, Key::Numpad2, Key::Numpad3, Key::Numpad4,
+        Key::Numpad5, Key::Numpad6, Key::Numpad7, Key::Numpad8, Key::Numpad9,
+    ];
+    KEYS.get(usize::from(digit)).copied()
+}
+
+/// Button names shared by `mouse click` and macro files
+pub fn parse_mouse_button(name: &str) -> Result<Button, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "left" => Ok(Button::Left),
+        "right" => Ok(Button::Right),
+        "middle" => Ok(Button::Middle),
+        "back" => Ok(Button::Back),
+        "forward" => Ok(Button::Forward),
+        _ => Err(format!("Unknown mouse button: {} (expected left, right, middle, back or forward)", name)),
+    }
+}
+
+/// Parse a `+`-separated combo such as `ctrl+shift+v` into keys in press order
+///
+/// A literal plus sign is written as `plus` (`ctrl+plus`), or as a trailing `+` (`ctrl++`).
+pub fn parse_combo(combo: &str) -> Result<Vec<Key>, String> {
+    let combo = combo.trim();
+    let (body, plus_key) = match combo {
+        "+" => ("", true),
+        _ => match combo.strip_suffix("++") {
+            Some(body) => (body, true),
+            None => (combo, false),
+        },
+    };
+
+    let mut keys = Vec::new();
+    if !body.is_empty() {
+        for part in body.split('+').map(str::trim) {
+            if part.is_empty() {
+                return Err(format!("Empty key in combo: {}", combo));
+            }
+            keys.push(parse_key(part)?);
+        }
+    }
+    if plus_key {
+        keys.push(Key::Unicode('+'));
+    }
+
+    if keys.is_empty() {
+        return Err("Empty key combo".to_string());
+    }
+    Ok(keys)
+}
+
+/// A run of `write --keys` input
+pub enum Segment {
+    Text(String),
+    /// A key or combo written as `{Enter}` or `{ctrl+a}`
+    Keys(Vec<Key>),
+}
+
+impl Segment {
+    /// Progress units: one per character, one per key escape
+    pub fn char_count(&self) -> usize {
+        match self {
+            Segment::Text(text) => text.chars().count(),
+            Segment::Keys(_) => 1,
+        }
+    }
+}
+
+/// Split `write --keys` input into text and `{key}` escapes, in order
+///
+/// `{{` and `}}` stand for literal braces.
+pub fn parse_key_escapes(input: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut combo = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => combo.push(c),
+                        None => return Err(format!("Unterminated key escape: {{{}", combo)),
+                    }
+                }
+                if !text.is_empty() {
+                    segments.push(Segment::Text(std::mem::take(&mut text)));
+                }
+                segments.push(Segment::Keys(parse_combo(&combo)?));
+            }
+            '}' => return Err("Unmatched '}' in key escapes (use }} for a literal brace)".to_string()),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+    Ok(segments)
+}
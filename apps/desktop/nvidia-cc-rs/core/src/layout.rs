@@ -0,0 +1,648 @@
+//! Keyboard-layout resolution for the evdev backend (Linux).
+//!
+//! evdev reports physical key positions, so on AZERTY the key labelled "A"
+//! arrives as `KeyQ`. Key events keep that physical name in `key` (hotkeys bound
+//! by position keep working) and add the keysym and character the active layout
+//! produces, resolved with libxkbcommon.
+//!
+//! libxkbcommon is loaded at runtime; without it (or without a compilable
+//! keymap) events simply carry no layout fields. The layout is taken from, in
+//! order: the `XKB_DEFAULT_*` environment variables, the running X server
+//! (`setxkbmap -query`), `/etc/default/keyboard`, and finally xkbcommon's
+//! built-in default (us).
+//!
+//! `LayoutChanged` is emitted when the effective layout group switches (e.g. a
+//! `grp:alt_shift_toggle` chord) and, while listening, when the configured
+//! layout itself changes (`setxkbmap de` or a new `/etc/default/keyboard`).
+//!
+//! The same keymap drives injection by key code: [`plan_text`] works out which
+//! keys (with Shift and AltGr) type each character on this layout, and which
+//! dead key to press first for accented letters the layout has no key for.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::os::raw::c_char;
+use std::process::Command;
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use xkbcommon_dl::{
+    xkb_compose_compile_flags, xkb_compose_feed_result, xkb_compose_state_flags, xkb_compose_status, xkb_context,
+    xkb_context_flags, xkb_key_direction, xkb_keymap, xkb_keymap_compile_flags, xkb_keysym_t, xkb_rule_names,
+    xkb_state, xkb_state_component, xkbcommon_compose_option, xkbcommon_option, XkbCommon, XKB_MOD_INVALID,
+    XKB_MOD_NAME_NUM,
+};
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+/// evdev key codes are offset by 8 in the X keycode space xkbcommon uses
+const EVDEV_KEYCODE_OFFSET: u32 = 8;
+
+/// How often the configured layout is re-read while listening
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Highest evdev key code the injection device exposes
+const MAX_INJECTABLE_CODE: u16 = 248;
+
+const EVDEV_KEY_LEFTSHIFT: u16 = 42;
+const EVDEV_KEY_RIGHTALT: u16 = 100;
+const XKB_KEY_SHIFT_L: xkb_keysym_t = 0xffe1;
+const XKB_KEY_ISO_LEVEL3_SHIFT: xkb_keysym_t = 0xfe03;
+
+/// Layout source that can't change while we run, so it isn't polled
+const ENV_SOURCE: &str = "XKB_DEFAULT_LAYOUT";
+
+/// Layout source that is the X server's own layout
+const SETXKBMAP_SOURCE: &str = "setxkbmap";
+
+/// RMLVO names describing a layout; empty fields fall back to xkbcommon's defaults
+#[derive(Default, Clone, PartialEq)]
+struct RuleNames {
+    rules: String,
+    model: String,
+    layout: String,
+    variant: String,
+    options: String,
+}
+
+struct Layout {
+    xkb: &'static XkbCommon,
+    context: *mut xkb_context,
+    keymap: *mut xkb_keymap,
+    /// Shared by every device, like a single seat: Shift on one keyboard affects the others
+    state: *mut xkb_state,
+    names: RuleNames,
+    source: &'static str,
+    /// Effective layout group, an index into the comma-separated `names.layout`
+    group: u32,
+}
+
+// Only ever used under the LAYOUT mutex
+unsafe impl Send for Layout {}
+
+impl Drop for Layout {
+    fn drop(&mut self) {
+        unsafe {
+            (self.xkb.xkb_state_unref)(self.state);
+            (self.xkb.xkb_keymap_unref)(self.keymap);
+            (self.xkb.xkb_context_unref)(self.context);
+        }
+    }
+}
+
+/// Layout-resolved names for one key event, copied out of libxkbcommon
+pub struct Resolved {
+    keysym: [u8; 64],
+    keysym_len: usize,
+    text: [u8; 16],
+    text_len: usize,
+}
+
+impl Resolved {
+    /// Keysym name, e.g. `a`, `A`, `eacute`, `Return`
+    pub fn keysym(&self) -> Option<&str> {
+        std::str::from_utf8(&self.keysym[..self.keysym_len]).ok().filter(|s| !s.is_empty())
+    }
+
+    /// Character the key types, when it types a printable one
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.text[..self.text_len])
+            .ok()
+            .filter(|s| !s.is_empty() && !s.chars().any(char::is_control))
+    }
+}
+
+impl Layout {
+    fn description(&self) -> String {
+        let layout = if self.names.layout.is_empty() { "default" } else { &self.names.layout };
+        if self.names.variant.is_empty() {
+            format!("{} from {}", layout, self.source)
+        } else {
+            format!("{} ({}) from {}", layout, self.names.variant, self.source)
+        }
+    }
+
+    /// The active group's layout in xkb notation, e.g. `ru` or `fr(oss)`
+    fn active_name(&self) -> String {
+        let group = self.group as usize;
+        let layout = self.names.layout.split(',').nth(group).unwrap_or_default();
+        let layout = if layout.is_empty() { "default" } else { layout };
+        match self.names.variant.split(',').nth(group).filter(|variant| !variant.is_empty()) {
+            Some(variant) => format!("{}({})", layout, variant),
+            None => layout.to_string(),
+        }
+    }
+}
+
+fn lock(layout: &Mutex<Layout>) -> MutexGuard<'_, Layout> {
+    layout.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn layout() -> &'static Option<Mutex<Layout>> {
+    static LAYOUT: OnceLock<Option<Mutex<Layout>>> = OnceLock::new();
+    LAYOUT.get_or_init(|| match load() {
+        Ok(layout) => {
+            eprintln!("Keyboard layout: {}", layout.description());
+            Some(Mutex::new(layout))
+        }
+        Err(e) => {
+            eprintln!("Layout-aware key names unavailable: {}", e);
+            None
+        }
+    })
+}
+
+/// Human-readable description of the layout in use, e.g. `fr (oss) from setxkbmap`
+pub fn description() -> Option<String> {
+    Some(lock(layout().as_ref()?).description())
+}
+
+/// Whether the layout was read from the running X server (or set explicitly), so X
+/// key codes type what [`plan_text`] expects
+pub fn matches_x_server() -> bool {
+    layout()
+        .as_ref()
+        .is_some_and(|layout| matches!(lock(layout).source, SETXKBMAP_SOURCE | ENV_SOURCE))
+}
+
+fn emit_changed(previous: String, layout: String) {
+    eprintln!("Keyboard layout changed: {} -> {}", previous, layout);
+    stream::emit(&Event::now(EventKind::LayoutChanged { layout, previous }));
+}
+
+/// Resolve `code` under the current layout and modifier state, then apply the event to that state
+///
+/// `value` is the evdev key value; repeats (2) don't change the state and aren't resolved.
+pub fn resolve(code: u16, value: i32) -> Option<Resolved> {
+    let direction = match value {
+        0 => xkb_key_direction::XKB_KEY_UP,
+        1 => xkb_key_direction::XKB_KEY_DOWN,
+        _ => return None,
+    };
+    let mut layout = lock(layout().as_ref()?);
+    let keycode = u32::from(code) + EVDEV_KEYCODE_OFFSET;
+
+    let mut resolved = Resolved {
+        keysym: [0; 64],
+        keysym_len: 0,
+        text: [0; 16],
+        text_len: 0,
+    };
+    unsafe {
+        // Resolved before the state update, so Shift's own press doesn't count and a released key
+        // reports what it typed
+        let keysym = (layout.xkb.xkb_state_key_get_one_sym)(layout.state, keycode);
+        if keysym != 0 {
+            let len = (layout.xkb.xkb_keysym_get_name)(
+                keysym,
+                resolved.keysym.as_mut_ptr() as *mut c_char,
+                resolved.keysym.len(),
+            );
+            resolved.keysym_len = usize::try_from(len).unwrap_or(0).min(resolved.keysym.len() - 1);
+        }
+        let len = (layout.xkb.xkb_state_key_get_utf8)(
+            layout.state,
+            keycode,
+            resolved.text.as_mut_ptr() as *mut c_char,
+            resolved.text.len(),
+        );
+        resolved.text_len = usize::try_from(len).unwrap_or(0).min(resolved.text.len() - 1);
+
+        let changed = (layout.xkb.xkb_state_update_key)(layout.state, keycode, direction);
+        if changed.contains(xkb_state_component::XKB_STATE_LAYOUT_EFFECTIVE) {
+            let group = (layout.xkb.xkb_state_serialize_layout)(
+                layout.state,
+                xkb_state_component::XKB_STATE_LAYOUT_EFFECTIVE,
+            );
+            if group != layout.group {
+                let previous = layout.active_name();
+                layout.group = group;
+                let current = layout.active_name();
+                drop(layout);
+                emit_changed(previous, current);
+            }
+        }
+    }
+    Some(resolved)
+}
+
+impl Layout {
+    /// Index of the modifier NumLock locks, when the keymap has one
+    unsafe fn num_lock_index(&self) -> Option<u32> {
+        let index = (self.xkb.xkb_keymap_mod_get_index)(self.keymap, XKB_MOD_NAME_NUM.as_ptr() as *const c_char);
+        (index != XKB_MOD_INVALID).then_some(index)
+    }
+}
+
+/// Whether NumLock is on in the tracked keyboard state
+pub fn num_lock() -> Option<bool> {
+    let layout = lock(layout().as_ref()?);
+    unsafe {
+        let index = layout.num_lock_index()?;
+        let active = (layout.xkb.xkb_state_mod_index_is_active)(
+            layout.state,
+            index,
+            xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
+        );
+        Some(active > 0)
+    }
+}
+
+/// Lock or unlock NumLock in the tracked state, to match a keyboard's LED
+pub fn sync_num_lock(on: bool) {
+    let Some(layout) = layout().as_ref() else {
+        return;
+    };
+    let layout = lock(layout);
+    unsafe {
+        let Some(index) = layout.num_lock_index() else {
+            return;
+        };
+        let serialize = |component| (layout.xkb.xkb_state_serialize_mods)(layout.state, component);
+        let locked = serialize(xkb_state_component::XKB_STATE_MODS_LOCKED);
+        let updated = if on { locked | 1 << index } else { locked & !(1 << index) };
+        if updated == locked {
+            return;
+        }
+        let depressed = serialize(xkb_state_component::XKB_STATE_MODS_DEPRESSED);
+        let latched = serialize(xkb_state_component::XKB_STATE_MODS_LATCHED);
+        let group = |component| (layout.xkb.xkb_state_serialize_layout)(layout.state, component);
+        (layout.xkb.xkb_state_update_mask)(
+            layout.state,
+            depressed,
+            latched,
+            updated,
+            group(xkb_state_component::XKB_STATE_LAYOUT_DEPRESSED),
+            group(xkb_state_component::XKB_STATE_LAYOUT_LATCHED),
+            group(xkb_state_component::XKB_STATE_LAYOUT_LOCKED),
+        );
+    }
+}
+
+/// Follow changes to the configured layout while listening, recompiling the keymap
+///
+/// The new keymap starts with no modifiers held, like a freshly plugged keyboard.
+pub fn watch_config() {
+    let Some(layout) = layout().as_ref() else {
+        return;
+    };
+    if lock(layout).source == ENV_SOURCE {
+        return;
+    }
+
+    thread::spawn(move || {
+        let mut failed: Option<RuleNames> = None;
+        loop {
+            thread::sleep(CONFIG_POLL_INTERVAL);
+            let (names, source) = configured_names();
+            if lock(layout).names == names || failed.as_ref() == Some(&names) {
+                continue;
+            }
+            match compile(&names, source) {
+                Ok(replacement) => {
+                    failed = None;
+                    let current = replacement.active_name();
+                    let previous = std::mem::replace(&mut *lock(layout), replacement).active_name();
+                    emit_changed(previous, current);
+                }
+                Err(e) => {
+                    eprintln!("Failed to load changed keyboard layout: {}", e);
+                    failed = Some(names);
+                }
+            }
+        }
+    });
+}
+
+/// One key tap, with the modifier keys held around it (evdev codes)
+#[derive(Clone)]
+pub struct Stroke {
+    pub modifiers: Vec<u16>,
+    pub code: u16,
+}
+
+/// How the active layout types one character directly
+#[derive(Clone)]
+struct Direct {
+    stroke: Stroke,
+    keysym: xkb_keysym_t,
+}
+
+/// Key strokes that type `text` on the active layout
+///
+/// `None` when no layout is loaded; an error names the first character the
+/// layout can type neither directly nor through one of its dead keys.
+pub fn plan_text(text: &str) -> Option<Result<Vec<Stroke>, String>> {
+    let layout = lock(layout().as_ref()?);
+    let (direct, dead_keys) = unsafe { layout.typeable() };
+
+    let mut composed: HashMap<char, [Stroke; 2]> = HashMap::new();
+    let mut strokes = Vec::new();
+    for c in text.chars() {
+        // Enter types "\r" in xkb terms
+        let lookup = if c == '\n' { '\r' } else { c };
+        if let Some(found) = direct.get(&lookup) {
+            strokes.push(found.stroke.clone());
+            continue;
+        }
+        let sequence = match composed.entry(c) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match unsafe { layout.dead_key_sequence(c, &direct, &dead_keys) } {
+                Some(sequence) => entry.insert(sequence),
+                None => {
+                    return Some(Err(format!(
+                        "{:?} can't be typed on keyboard layout {}",
+                        c,
+                        layout.active_name()
+                    )))
+                }
+            },
+        };
+        strokes.extend(sequence.iter().cloned());
+    }
+    Some(Ok(strokes))
+}
+
+impl Layout {
+    /// Characters the active group types directly, and its dead keys
+    ///
+    /// Tries no modifiers first, then Shift, AltGr and Shift+AltGr, so each
+    /// character gets the simplest stroke that produces it.
+    unsafe fn typeable(&self) -> (HashMap<char, Direct>, Vec<(xkb_keysym_t, Stroke)>) {
+        let shift = self.modifier_key(XKB_KEY_SHIFT_L, EVDEV_KEY_LEFTSHIFT);
+        let level3 = self.modifier_key(XKB_KEY_ISO_LEVEL3_SHIFT, EVDEV_KEY_RIGHTALT);
+        let mut combinations: Vec<Vec<u16>> = vec![vec![]];
+        combinations.extend(shift.map(|shift| vec![shift]));
+        if let Some(level3) = level3 {
+            combinations.push(vec![level3]);
+            combinations.extend(shift.map(|shift| vec![level3, shift]));
+        }
+
+        let mut direct = HashMap::new();
+        let mut dead_keys = Vec::new();
+        for modifiers in combinations {
+            let state = self.scratch_state(&modifiers);
+            if state.is_null() {
+                continue;
+            }
+            for code in 1..=MAX_INJECTABLE_CODE {
+                if modifiers.contains(&code) {
+                    continue;
+                }
+                let keycode = u32::from(code) + EVDEV_KEYCODE_OFFSET;
+                let keysym = (self.xkb.xkb_state_key_get_one_sym)(state, keycode);
+                if keysym == 0 {
+                    continue;
+                }
+                let stroke = Stroke {
+                    modifiers: modifiers.clone(),
+                    code,
+                };
+                match char::from_u32((self.xkb.xkb_state_key_get_utf32)(state, keycode)).filter(|&c| c != '\0') {
+                    Some(c) => {
+                        direct.entry(c).or_insert(Direct { stroke, keysym });
+                    }
+                    None if self.keysym_name(keysym).starts_with("dead_")
+                        && !dead_keys.iter().any(|(dead, _)| *dead == keysym) =>
+                    {
+                        dead_keys.push((keysym, stroke));
+                    }
+                    None => {}
+                }
+            }
+            (self.xkb.xkb_state_unref)(state);
+        }
+        (direct, dead_keys)
+    }
+
+    /// A dead key followed by a directly typed key that composes to `c`
+    unsafe fn dead_key_sequence(
+        &self,
+        c: char,
+        direct: &HashMap<char, Direct>,
+        dead_keys: &[(xkb_keysym_t, Stroke)],
+    ) -> Option<[Stroke; 2]> {
+        if dead_keys.is_empty() {
+            return None;
+        }
+        let compose = xkbcommon_compose_option()?;
+        let table = compose_locales().into_iter().find_map(|locale| {
+            let locale = CString::new(locale).ok()?;
+            let table = (compose.xkb_compose_table_new_from_locale)(
+                self.context,
+                locale.as_ptr(),
+                xkb_compose_compile_flags::XKB_COMPOSE_COMPILE_NO_FLAGS,
+            );
+            (!table.is_null()).then_some(table)
+        })?;
+        let state = (compose.xkb_compose_state_new)(table, xkb_compose_state_flags::XKB_COMPOSE_STATE_NO_FLAGS);
+
+        let mut found = None;
+        let mut buffer = [0u8; 16];
+        'search: for (dead, dead_stroke) in dead_keys {
+            for base in direct.values() {
+                (compose.xkb_compose_state_reset)(state);
+                let accepted = (compose.xkb_compose_state_feed)(state, *dead)
+                    == xkb_compose_feed_result::XKB_COMPOSE_FEED_ACCEPTED
+                    && (compose.xkb_compose_state_feed)(state, base.keysym)
+                        == xkb_compose_feed_result::XKB_COMPOSE_FEED_ACCEPTED;
+                if !accepted
+                    || (compose.xkb_compose_state_get_status)(state) != xkb_compose_status::XKB_COMPOSE_COMPOSED
+                {
+                    continue;
+                }
+                let len = (compose.xkb_compose_state_get_utf8)(state, buffer.as_mut_ptr() as *mut c_char, buffer.len());
+                let len = usize::try_from(len).unwrap_or(0).min(buffer.len() - 1);
+                if std::str::from_utf8(&buffer[..len]).is_ok_and(|composed| composed.chars().eq([c])) {
+                    found = Some([dead_stroke.clone(), base.stroke.clone()]);
+                    break 'search;
+                }
+            }
+        }
+
+        (compose.xkb_compose_state_unref)(state);
+        (compose.xkb_compose_table_unref)(table);
+        found
+    }
+
+    /// evdev code of a key that produces `keysym` without modifiers, `preferred` if it does
+    ///
+    /// Keymaps also bind modifiers to keys no keyboard has (evdev's LVL3 key), which
+    /// is why the search doesn't just take the first match.
+    unsafe fn modifier_key(&self, keysym: xkb_keysym_t, preferred: u16) -> Option<u16> {
+        let state = self.scratch_state(&[]);
+        if state.is_null() {
+            return None;
+        }
+        let code = std::iter::once(preferred).chain(1..=MAX_INJECTABLE_CODE).find(|&code| {
+            (self.xkb.xkb_state_key_get_one_sym)(state, u32::from(code) + EVDEV_KEYCODE_OFFSET) == keysym
+        });
+        (self.xkb.xkb_state_unref)(state);
+        code
+    }
+
+    /// Fresh state locked to the active group with `modifiers` held; the caller unrefs it
+    unsafe fn scratch_state(&self, modifiers: &[u16]) -> *mut xkb_state {
+        let state = (self.xkb.xkb_state_new)(self.keymap);
+        if state.is_null() {
+            return state;
+        }
+        (self.xkb.xkb_state_update_mask)(state, 0, 0, 0, 0, 0, self.group);
+        for &code in modifiers {
+            (self.xkb.xkb_state_update_key)(
+                state,
+                u32::from(code) + EVDEV_KEYCODE_OFFSET,
+                xkb_key_direction::XKB_KEY_DOWN,
+            );
+        }
+        state
+    }
+
+    unsafe fn keysym_name(&self, keysym: xkb_keysym_t) -> String {
+        let mut buffer = [0 as c_char; 64];
+        if (self.xkb.xkb_keysym_get_name)(keysym, buffer.as_mut_ptr(), buffer.len()) < 0 {
+            return String::new();
+        }
+        CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned()
+    }
+}
+
+/// Locales whose compose tables describe dead keys, most specific first
+///
+/// Dead keys compose the same way in every UTF-8 locale, so en_US.UTF-8 covers
+/// the C locale many services run under.
+fn compose_locales() -> Vec<String> {
+    let mut locales: Vec<String> = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .filter(|locale| !locale.is_empty())
+        .take(1)
+        .collect();
+    locales.push("en_US.UTF-8".to_string());
+    locales
+}
+
+fn load() -> Result<Layout, String> {
+    let (names, source) = configured_names();
+    compile(&names, source)
+}
+
+fn compile(names: &RuleNames, source: &'static str) -> Result<Layout, String> {
+    let xkb = xkbcommon_option().ok_or("libxkbcommon could not be loaded")?;
+
+    let cstrings = [&names.rules, &names.model, &names.layout, &names.variant, &names.options]
+        .map(|name| CString::new(name.as_str()).unwrap_or_default());
+    // Empty fields are passed as NULL so xkbcommon fills in its defaults (and XKB_DEFAULT_*)
+    let field = |index: usize| {
+        if cstrings[index].as_bytes().is_empty() {
+            ptr::null()
+        } else {
+            cstrings[index].as_ptr()
+        }
+    };
+    let rule_names = xkb_rule_names {
+        rules: field(0),
+        model: field(1),
+        layout: field(2),
+        variant: field(3),
+        options: field(4),
+    };
+
+    unsafe {
+        let context = (xkb.xkb_context_new)(xkb_context_flags::XKB_CONTEXT_NO_FLAGS);
+        if context.is_null() {
+            return Err("cannot create xkb context".to_string());
+        }
+        let keymap = (xkb.xkb_keymap_new_from_names)(
+            context,
+            &rule_names,
+            xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+        );
+        if keymap.is_null() {
+            (xkb.xkb_context_unref)(context);
+            return Err(format!("cannot compile keymap for layout {:?}", names.layout));
+        }
+        let state = (xkb.xkb_state_new)(keymap);
+        if state.is_null() {
+            (xkb.xkb_keymap_unref)(keymap);
+            (xkb.xkb_context_unref)(context);
+            return Err("cannot create xkb state".to_string());
+        }
+
+        Ok(Layout {
+            xkb,
+            context,
+            keymap,
+            state,
+            names: names.clone(),
+            source,
+            group: 0,
+        })
+    }
+}
+
+/// The configured layout and where it came from
+fn configured_names() -> (RuleNames, &'static str) {
+    if let Ok(layout) = std::env::var("XKB_DEFAULT_LAYOUT") {
+        // xkbcommon reads the remaining XKB_DEFAULT_* variables itself
+        let names = RuleNames {
+            layout,
+            ..RuleNames::default()
+        };
+        return (names, ENV_SOURCE);
+    }
+    if std::env::var_os("DISPLAY").is_some() {
+        if let Some(names) = setxkbmap_names() {
+            return (names, SETXKBMAP_SOURCE);
+        }
+    }
+    if let Some(names) = etc_default_keyboard_names() {
+        return (names, "/etc/default/keyboard");
+    }
+    (RuleNames::default(), "xkbcommon defaults")
+}
+
+/// `setxkbmap -query` reports the layout the X server is actually using
+fn setxkbmap_names() -> Option<RuleNames> {
+    let output = Command::new("setxkbmap").arg("-query").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let mut names = RuleNames::default();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let Some((key, value)) = line.split_once(':') else { continue };
+        let value = value.trim().to_string();
+        match key.trim() {
+            "rules" => names.rules = value,
+            "model" => names.model = value,
+            "layout" => names.layout = value,
+            "variant" => names.variant = value,
+            "options" => names.options = value,
+            _ => {}
+        }
+    }
+    (!names.layout.is_empty()).then_some(names)
+}
+
+/// Debian-style `XKBLAYOUT="fr"` system keyboard configuration
+fn etc_default_keyboard_names() -> Option<RuleNames> {
+    let config = fs::read_to_string("/etc/default/keyboard").ok()?;
+    let mut names = RuleNames::default();
+    for line in config.lines() {
+        let Some((key, value)) = line.split_once('=') else { continue };
+        let value = value.trim().trim_matches('"').to_string();
+        match key.trim() {
+            "XKBMODEL" => names.model = value,
+            "XKBLAYOUT" => names.layout = value,
+            "XKBVARIANT" => names.variant = value,
+            "XKBOPTIONS" => names.options = value,
+            _ => {}
+        }
+    }
+    (!names.layout.is_empty()).then_some(names)
+}
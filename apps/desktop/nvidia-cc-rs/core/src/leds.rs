@@ -0,0 +1,286 @@
+//! Keyboard lock LEDs: `leds get`, `leds set <led> on|off`, and `LockChanged`.
+//!
+//! `leds get` prints the state of `capslock`, `numlock` and `scrolllock` as
+//! JSON. While listening, `LockChanged{lock, on}` is emitted whenever one of
+//! them changes, and the same commands work on stdin (`leds get` answering
+//! with `LedState{leds}`).
+//!
+//! On Linux `set` only lights or darkens the LED on every keyboard that has
+//! it, through EV_LED; the lock itself is left alone, which suits blinking
+//! Scroll Lock as a recording indicator. The display server sets the LEDs
+//! again on the next lock change. `LockChanged` follows the LEDs of the
+//! keyboards being read, so LEDs set this way are reported too.
+//!
+//! Windows has no separate LED control, so `set` presses the lock key when
+//! the lock isn't already in the wanted state. macOS only has Caps Lock, and
+//! only reports it.
+
+use serde_json::{json, Value};
+use std::sync::Mutex;
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+/// A lock LED, in the order `leds get` lists them
+#[derive(Clone, Copy, PartialEq)]
+pub enum Led {
+    CapsLock,
+    NumLock,
+    ScrollLock,
+}
+
+impl Led {
+    pub const ALL: [Led; 3] = [Led::CapsLock, Led::NumLock, Led::ScrollLock];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Led::CapsLock => "capslock",
+            Led::NumLock => "numlock",
+            Led::ScrollLock => "scrolllock",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Led, String> {
+        Led::ALL
+            .into_iter()
+            .find(|led| led.name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("Unknown LED {:?} (expected capslock, numlock or scrolllock)", name))
+    }
+}
+
+/// `<led> on|off` to the LED and its wanted state
+pub fn parse_set(args: &[&str]) -> Result<(Led, bool), String> {
+    let [led, state] = args else {
+        return Err("Expected: leds set <capslock|numlock|scrolllock> <on|off>".to_string());
+    };
+    let on = match *state {
+        "on" => true,
+        "off" => false,
+        other => return Err(format!("Expected on or off, got {:?}", other)),
+    };
+    Ok((Led::parse(led)?, on))
+}
+
+/// Every LED the platform reports, as `{"capslock": false, ...}`
+pub fn get() -> Result<Value, String> {
+    let state = platform::get()?;
+    let leds: serde_json::Map<_, _> = state.iter().map(|(led, on)| (led.name().to_string(), json!(on))).collect();
+    Ok(leds.into())
+}
+
+/// Turn `led` on or off
+pub fn set(led: Led, on: bool) -> Result<(), String> {
+    platform::set(led, on)
+}
+
+/// Last state reported per LED, to only emit changes
+static LAST: Mutex<[Option<bool>; 3]> = Mutex::new([None; 3]);
+
+/// Note `led` is now `on`, emitting `LockChanged` when that's a change from the last state seen
+///
+/// The first state seen for an LED is the starting point and emits nothing.
+pub fn observe(led: Led, on: bool) {
+    let index = Led::ALL.iter().position(|&known| known == led).unwrap_or_default();
+    let mut last = LAST.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let previous = last[index].replace(on);
+    drop(last);
+    if previous.is_some_and(|previous| previous != on) {
+        stream::emit(&Event::now(EventKind::LockChanged {
+            lock: led.name().to_string(),
+            on,
+        }));
+    }
+}
+
+/// Start reporting `LockChanged` while listening
+///
+/// On Linux the listener reports LED events from the keyboards it reads.
+pub fn watch() {
+    platform::watch();
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use evdev::{Device, EventType, InputEvent, LedCode};
+
+    use super::Led;
+
+    pub fn led_type(led: Led) -> LedCode {
+        match led {
+            Led::CapsLock => LedCode::LED_CAPSL,
+            Led::NumLock => LedCode::LED_NUML,
+            Led::ScrollLock => LedCode::LED_SCROLLL,
+        }
+    }
+
+    /// Every input device with a Caps Lock LED, by path
+    fn keyboards() -> Vec<(String, Device)> {
+        let Ok(entries) = std::fs::read_dir("/dev/input") else {
+            return Vec::new();
+        };
+        let mut keyboards: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("event")))
+            .filter_map(|path| Some((path.display().to_string(), Device::open(&path).ok()?)))
+            .filter(|(_, device)| device.supported_leds().is_some_and(|leds| leds.contains(LedCode::LED_CAPSL)))
+            .collect();
+        keyboards.sort_by(|a, b| a.0.cmp(&b.0));
+        keyboards
+    }
+
+    pub fn get() -> Result<Vec<(Led, bool)>, String> {
+        // Every keyboard shows the same state, so the first one will do
+        let (path, device) = keyboards()
+            .into_iter()
+            .next()
+            .ok_or("No keyboard with LEDs found in /dev/input (is the user in the input group?)")?;
+        let state = device.get_led_state().map_err(|e| format!("Cannot read LEDs of {}: {}", path, e))?;
+        Ok(Led::ALL.into_iter().map(|led| (led, state.contains(led_type(led)))).collect())
+    }
+
+    pub fn set(led: Led, on: bool) -> Result<(), String> {
+        let code = led_type(led);
+        let mut set = 0;
+        let mut failure = None;
+        for (path, mut device) in keyboards() {
+            if !device.supported_leds().is_some_and(|leds| leds.contains(code)) {
+                continue;
+            }
+            match device.send_events(&[InputEvent::new(EventType::LED.0, code.0, on as i32)]) {
+                Ok(()) => set += 1,
+                Err(e) => failure = Some(format!("Cannot set LEDs of {}: {}", path, e)),
+            }
+        }
+        match (set, failure) {
+            (0, Some(failure)) => Err(failure),
+            (0, None) => Err(format!("No keyboard has a {} LED", led.name())),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn watch() {}
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::thread;
+    use std::time::Duration;
+
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+        GetKeyState, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, KEYBDINPUT, KEYEVENTF_KEYUP, VIRTUAL_KEY, VK_CAPITAL,
+        VK_NUMLOCK, VK_SCROLL,
+    };
+
+    use super::{observe, Led};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    fn virtual_key(led: Led) -> VIRTUAL_KEY {
+        match led {
+            Led::CapsLock => VK_CAPITAL,
+            Led::NumLock => VK_NUMLOCK,
+            Led::ScrollLock => VK_SCROLL,
+        }
+    }
+
+    fn toggled(led: Led) -> bool {
+        // The low bit is the toggle state
+        unsafe { GetKeyState(virtual_key(led) as i32) & 1 != 0 }
+    }
+
+    pub fn get() -> Result<Vec<(Led, bool)>, String> {
+        Ok(Led::ALL.into_iter().map(|led| (led, toggled(led))).collect())
+    }
+
+    pub fn set(led: Led, on: bool) -> Result<(), String> {
+        if toggled(led) == on {
+            return Ok(());
+        }
+        let key = |flags| INPUT {
+            r#type: INPUT_KEYBOARD,
+            Anonymous: INPUT_0 {
+                ki: KEYBDINPUT {
+                    wVk: virtual_key(led),
+                    wScan: 0,
+                    dwFlags: flags,
+                    time: 0,
+                    dwExtraInfo: 0,
+                },
+            },
+        };
+        let inputs = [key(0), key(KEYEVENTF_KEYUP)];
+        let sent = unsafe { SendInput(inputs.len() as u32, inputs.as_ptr(), std::mem::size_of::<INPUT>() as i32) };
+        if sent as usize == inputs.len() {
+            Ok(())
+        } else {
+            Err("SendInput was blocked".to_string())
+        }
+    }
+
+    pub fn watch() {
+        thread::spawn(|| loop {
+            for led in Led::ALL {
+                observe(led, toggled(led));
+            }
+            thread::sleep(POLL_INTERVAL);
+        });
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{observe, Led};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+    const HID_SYSTEM_STATE: i32 = 1;
+    const FLAG_MASK_ALPHA_SHIFT: u64 = 0x0001_0000;
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn CGEventSourceFlagsState(state: i32) -> u64;
+    }
+
+    fn caps_lock() -> bool {
+        unsafe { CGEventSourceFlagsState(HID_SYSTEM_STATE) & FLAG_MASK_ALPHA_SHIFT != 0 }
+    }
+
+    pub fn get() -> Result<Vec<(Led, bool)>, String> {
+        Ok(vec![(Led::CapsLock, caps_lock())])
+    }
+
+    pub fn set(_led: Led, _on: bool) -> Result<(), String> {
+        Err("Setting keyboard LEDs is not supported on macOS".to_string())
+    }
+
+    pub fn watch() {
+        thread::spawn(|| loop {
+            observe(Led::CapsLock, caps_lock());
+            thread::sleep(POLL_INTERVAL);
+        });
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+mod platform {
+    use super::Led;
+
+    pub fn get() -> Result<Vec<(Led, bool)>, String> {
+        Err("Keyboard LEDs are not supported on this platform".to_string())
+    }
+
+    pub fn set(_led: Led, _on: bool) -> Result<(), String> {
+        Err("Keyboard LEDs are not supported on this platform".to_string())
+    }
+
+    pub fn watch() {}
+}
+
+/// The LED evdev reports as `led`, if it's one of ours
+#[cfg(target_os = "linux")]
+pub fn from_evdev(led: evdev::LedCode) -> Option<Led> {
+    Led::ALL.into_iter().find(|&known| platform::led_type(known) == led)
+}
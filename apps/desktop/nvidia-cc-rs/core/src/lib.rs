@@ -0,0 +1,103 @@
+//! Keyboard capture, key mapping and input injection behind the `nvidia-cc-rs`
+//! helper, for Rust tools that want them in-process instead of spawning it.
+//!
+//! [`KeyboardListener`] captures key events, [`TextInjector`] types text and
+//! presses combos, and [`HotkeyEngine`] manages the hotkeys matched against
+//! captured keys. Events are emitted on the process-wide event stream: as JSON
+//! lines on stdout, and to every sink registered with [`stream::subscribe`].
+//!
+//! With the `cdylib` feature the crate is also a C library; see
+//! `include/nvidia_cc.h`.
+//!
+//! The other public modules are what the CLI is built from. They follow the
+//! helper's command line and wire format rather than promising a stable API.
+
+pub mod active_window;
+mod backend;
+pub mod backends;
+pub mod backlight;
+pub mod bench;
+pub mod clipboard;
+pub mod clock;
+pub mod config;
+pub mod context;
+pub mod control;
+#[cfg(target_os = "linux")]
+pub mod dbus;
+mod devices;
+pub mod embed;
+pub mod environment;
+pub mod event;
+#[cfg(target_os = "linux")]
+pub mod evtest;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod gamepad;
+pub mod gkeys;
+pub mod gpu;
+pub mod hotkeys;
+pub mod hotstrings;
+pub mod http;
+pub mod idle;
+pub mod inhibit;
+mod ime;
+pub mod inject;
+#[cfg(target_os = "linux")]
+pub mod input_access;
+#[cfg(not(target_os = "linux"))]
+pub mod input_source;
+pub mod journal;
+pub mod keymap;
+pub mod keys;
+#[cfg(target_os = "linux")]
+pub mod layout;
+pub mod leds;
+pub mod listener;
+#[cfg(target_os = "linux")]
+mod loopback;
+pub mod macros;
+mod media;
+pub mod metrics;
+pub mod monitors;
+mod msgpack;
+pub mod notify;
+pub mod numpad;
+pub mod output;
+pub mod parent;
+#[cfg(target_os = "linux")]
+pub mod permissions;
+pub mod playback;
+#[cfg(target_os = "linux")]
+pub mod portal;
+pub mod power;
+pub mod privacy;
+mod realtime;
+pub mod remap;
+#[cfg(target_os = "linux")]
+pub mod sandbox;
+pub mod scancode;
+pub mod screenshot;
+pub mod secure_input;
+pub mod selftest;
+pub mod service;
+pub mod session;
+#[cfg(any(unix, windows))]
+pub mod socket;
+pub mod stats;
+pub mod stream;
+pub mod synthetic;
+#[cfg(any(unix, windows))]
+pub mod tcp;
+pub mod throttle;
+#[cfg(any(unix, windows))]
+pub mod tls;
+#[cfg(target_os = "linux")]
+pub mod wayland;
+pub mod xdo;
+#[cfg(target_os = "linux")]
+pub mod ydotool;
+pub mod zstd;
+
+pub use hotkeys::HotkeyEngine;
+pub use inject::TextInjector;
+pub use listener::KeyboardListener;
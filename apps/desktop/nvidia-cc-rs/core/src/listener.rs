@@ -0,0 +1,986 @@
+//! Keyboard capture: evdev on Linux, rdev's global hook elsewhere.
+//!
+//! Every key event goes through the same path whatever started the listener:
+//! pause and self-injection checks, then the event stream, the hotkey and
+//! hotstring engines, or the macro recorder while one is recording.
+//!
+//! Inside a Flatpak or Snap sandbox with no devices, only hotkeys are seen,
+//! through the GlobalShortcuts portal (see [`crate::portal`]).
+
+use regex::Regex;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::control;
+use crate::devices;
+use crate::event::{self, Event, EventKind};
+use crate::hotkeys;
+use crate::hotstrings;
+#[cfg(target_os = "linux")]
+use crate::idle;
+#[cfg(target_os = "linux")]
+use crate::input_access;
+#[cfg(target_os = "linux")]
+use crate::layout;
+#[cfg(target_os = "linux")]
+use crate::leds;
+use crate::macros;
+use crate::media;
+use crate::numpad;
+#[cfg(target_os = "linux")]
+use crate::portal;
+#[cfg(target_os = "linux")]
+use crate::power;
+use crate::privacy;
+use crate::realtime;
+use crate::remap;
+#[cfg(target_os = "linux")]
+use crate::sandbox;
+use crate::scancode;
+use crate::secure_input;
+use crate::session;
+#[cfg(target_os = "linux")]
+use crate::stats;
+#[cfg(target_os = "linux")]
+use crate::stream;
+use crate::synthetic;
+use crate::throttle;
+
+// On non-Linux platforms, use rdev
+#[cfg(not(target_os = "linux"))]
+use rdev::{listen, EventType};
+
+/// Captures key events from every keyboard until it fails
+#[derive(Default)]
+pub struct KeyboardListener {
+    realtime: bool,
+    media_keys: bool,
+    ignored_devices: Vec<Regex>,
+    device_paths: Vec<PathBuf>,
+    any_keys: bool,
+    all_sessions: bool,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    sandbox: bool,
+}
+
+impl KeyboardListener {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Raise listener thread priority to cut activation latency under load
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Emit media and brightness keys, and on Linux open devices that only have those
+    pub fn media_keys(mut self, media_keys: bool) -> Self {
+        self.media_keys = media_keys;
+        self
+    }
+
+    /// Leave devices whose name or path matches one of `patterns` alone (Linux)
+    pub fn ignore_devices(mut self, patterns: Vec<Regex>) -> Self {
+        self.ignored_devices = patterns;
+        self
+    }
+
+    /// Open exactly the event nodes at `paths`, whatever keys they report, instead of looking for keyboards (Linux)
+    pub fn devices(mut self, paths: Vec<PathBuf>) -> Self {
+        self.device_paths = paths;
+        self
+    }
+
+    /// Open every device that has keys or buttons, not only keyboards and trigger devices (Linux)
+    pub fn any_keys(mut self, any_keys: bool) -> Self {
+        self.any_keys = any_keys;
+        self
+    }
+
+    /// Also capture keys from other seats and while our session is inactive (Linux)
+    pub fn all_sessions(mut self, all_sessions: bool) -> Self {
+        self.all_sessions = all_sessions;
+        self
+    }
+
+    /// Confine the process to reading devices and writing to stdout once they're open (Linux)
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Listen on the calling thread; only returns when capture can't continue
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        media::set_enabled(self.media_keys);
+        session::set_all_sessions(self.all_sessions);
+        start_keyboard_listener(self)
+    }
+}
+
+/// When each key still held went down, for `hold_ms` on its release
+static PRESSED_AT: Mutex<Vec<(String, SystemTime)>> = Mutex::new(Vec::new());
+
+/// Note a press of `key` at `at`; on its release, how long it was held
+fn hold_ms(pressed: bool, key: &str, at: SystemTime) -> Option<u64> {
+    let mut pressed_at = PRESSED_AT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let held = pressed_at.iter().position(|(held, _)| held == key);
+    if pressed {
+        // Autorepeat presses keep the time of the first one
+        if held.is_none() {
+            pressed_at.push((key.to_string(), at));
+        }
+        return None;
+    }
+    let (_, since) = pressed_at.swap_remove(held?);
+    Some(at.duration_since(since).unwrap_or_default().as_millis() as u64)
+}
+
+// ============ Non-Linux (macOS/Windows) implementation using rdev ============
+#[cfg(not(target_os = "linux"))]
+fn keyboard_callback(event: rdev::Event) {
+    let (pressed, key) = match event.event_type {
+        EventType::KeyPress(key) => (true, key),
+        EventType::KeyRelease(key) => (false, key),
+        pointer => {
+            if macros::recording() && !control::is_paused() && !synthetic::injection_active() {
+                macros::record_rdev_pointer(pointer);
+            } else if !control::is_paused() && !synthetic::injection_active() {
+                observe_rdev_pointer(pointer);
+            }
+            return;
+        }
+    };
+    if control::is_paused() {
+        return;
+    }
+    // rdev doesn't expose the OS "injected" flag, so rely on the injection marker
+    let synthetic = synthetic::injection_active();
+    if synthetic && synthetic::suppress_self() {
+        return;
+    }
+
+    let code = scancode::rdev(key);
+    let key_name = match key {
+        rdev::Key::Unknown(code) => media::rdev_unknown_name(code).map_or_else(|| format!("{:?}", key), str::to_string),
+        key => format!("{:?}", key),
+    };
+    if media::filtered(&key_name) {
+        return;
+    }
+    if macros::recording() {
+        if !synthetic && !secure_input::active() {
+            macros::record_key(pressed, &key_name);
+        }
+        return;
+    }
+    // Macros keep the real key so they replay it; everything else sees the remapped name
+    let key_name = remap::apply(&[], &key_name).into_owned();
+    if secure_input::active() {
+        if !synthetic {
+            hotkeys::observe(pressed, &key_name, hotkeys::now());
+        }
+        return;
+    }
+    let key = event::Key {
+        key: &key_name,
+        name: event.name.as_deref(),
+        keysym: None,
+        scancode: code,
+        text: None,
+        num_lock: numpad::is_keypad(&key_name).then(numpad::state).flatten(),
+        synthetic,
+        hold_ms: hold_ms(pressed, &key_name, event.time),
+    };
+    let kind = if pressed {
+        EventKind::KeyPress(key)
+    } else {
+        EventKind::KeyRelease(key)
+    };
+    devices::count_event(None);
+    if throttle::admit() {
+        privacy::emit_key(&Event {
+            time: event.time,
+            kind,
+        });
+    }
+    if !synthetic {
+        hotkeys::observe(pressed, &key_name, hotkeys::now());
+        hotstrings::observe(pressed, &key_name, event.name.as_deref());
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn start_keyboard_listener(options: &KeyboardListener) -> Result<(), Box<dyn Error>> {
+    devices::register(None, "rdev global hook");
+
+    // rdev delivers events on the thread that calls listen()
+    if options.realtime {
+        realtime::elevate_current_thread("rdev");
+    }
+
+    if let Err(error) = listen(move |event| {
+        keyboard_callback(event);
+    }) {
+        return Err(format!("Failed to listen for keyboard events: {:?}", error).into());
+    }
+    Ok(())
+}
+
+// ============ Linux implementation using evdev directly ============
+// This approach works on both X11 and Wayland without any X11 dependencies.
+// Requires access to /dev/input: `install-permissions`, or the 'input' group (sudo usermod -aG input $USER)
+
+/// Convert evdev Key to rdev-compatible key name
+/// The TypeScript handler expects rdev-style names like "ControlLeft", "KeyA", etc.
+///
+/// Every key rdev has a name for gets exactly that name (its `Debug` output on
+/// macOS/Windows), so a binding captured on one platform matches on the others.
+/// Keys rdev reports as `Unknown` get CamelCase names in the same style.
+#[cfg(target_os = "linux")]
+pub fn evdev_key_to_rdev_name(key: evdev::KeyCode) -> &'static str {
+    use evdev::KeyCode;
+    if let Some(name) = macro_key_name(key.code()) {
+        return name;
+    }
+    match key {
+        // Modifier keys
+        KeyCode::KEY_LEFTCTRL => "ControlLeft",
+        KeyCode::KEY_RIGHTCTRL => "ControlRight",
+        KeyCode::KEY_LEFTSHIFT => "ShiftLeft",
+        KeyCode::KEY_RIGHTSHIFT => "ShiftRight",
+        KeyCode::KEY_LEFTALT => "Alt",  // rdev uses "Alt" for left alt
+        KeyCode::KEY_RIGHTALT => "AltGr",
+        KeyCode::KEY_LEFTMETA => "MetaLeft",
+        KeyCode::KEY_RIGHTMETA => "MetaRight",
+
+        // Letter keys (rdev uses "KeyA", "KeyB", etc.)
+        KeyCode::KEY_A => "KeyA",
+        KeyCode::KEY_B => "KeyB",
+        KeyCode::KEY_C => "KeyC",
+        KeyCode::KEY_D => "KeyD",
+        KeyCode::KEY_E => "KeyE",
+        KeyCode::KEY_F => "KeyF",
+        KeyCode::KEY_G => "KeyG",
+        KeyCode::KEY_H => "KeyH",
+        KeyCode::KEY_I => "KeyI",
+        KeyCode::KEY_J => "KeyJ",
+        KeyCode::KEY_K => "KeyK",
+        KeyCode::KEY_L => "KeyL",
+        KeyCode::KEY_M => "KeyM",
+        KeyCode::KEY_N => "KeyN",
+        KeyCode::KEY_O => "KeyO",
+        KeyCode::KEY_P => "KeyP",
+        KeyCode::KEY_Q => "KeyQ",
+        KeyCode::KEY_R => "KeyR",
+        KeyCode::KEY_S => "KeyS",
+        KeyCode::KEY_T => "KeyT",
+        KeyCode::KEY_U => "KeyU",
+        KeyCode::KEY_V => "KeyV",
+        KeyCode::KEY_W => "KeyW",
+        KeyCode::KEY_X => "KeyX",
+        KeyCode::KEY_Y => "KeyY",
+        KeyCode::KEY_Z => "KeyZ",
+
+        // Number keys
+        KeyCode::KEY_0 => "Num0",
+        KeyCode::KEY_1 => "Num1",
+        KeyCode::KEY_2 => "Num2",
+        KeyCode::KEY_3 => "Num3",
+        KeyCode::KEY_4 => "Num4",
+        KeyCode::KEY_5 => "Num5",
+        KeyCode::KEY_6 => "Num6",
+        KeyCode::KEY_7 => "Num7",
+        KeyCode::KEY_8 => "Num8",
+        KeyCode::KEY_9 => "Num9",
+
+        // Function keys (rdev stops at F12; the fallback names the rest "F13".."F24")
+        KeyCode::KEY_F1 => "F1",
+        KeyCode::KEY_F2 => "F2",
+        KeyCode::KEY_F3 => "F3",
+        KeyCode::KEY_F4 => "F4",
+        KeyCode::KEY_F5 => "F5",
+        KeyCode::KEY_F6 => "F6",
+        KeyCode::KEY_F7 => "F7",
+        KeyCode::KEY_F8 => "F8",
+        KeyCode::KEY_F9 => "F9",
+        KeyCode::KEY_F10 => "F10",
+        KeyCode::KEY_F11 => "F11",
+        KeyCode::KEY_F12 => "F12",
+
+        // Special keys
+        KeyCode::KEY_ESC => "Escape",
+        KeyCode::KEY_TAB => "Tab",
+        KeyCode::KEY_CAPSLOCK => "CapsLock",
+        KeyCode::KEY_SPACE => "Space",
+        KeyCode::KEY_ENTER => "Return",
+        KeyCode::KEY_BACKSPACE => "Backspace",
+        KeyCode::KEY_DELETE => "Delete",
+        KeyCode::KEY_INSERT => "Insert",
+        KeyCode::KEY_HOME => "Home",
+        KeyCode::KEY_END => "End",
+        KeyCode::KEY_PAGEUP => "PageUp",
+        KeyCode::KEY_PAGEDOWN => "PageDown",
+
+        // Arrow keys
+        KeyCode::KEY_UP => "UpArrow",
+        KeyCode::KEY_DOWN => "DownArrow",
+        KeyCode::KEY_LEFT => "LeftArrow",
+        KeyCode::KEY_RIGHT => "RightArrow",
+
+        // Punctuation/symbols
+        KeyCode::KEY_MINUS => "Minus",
+        KeyCode::KEY_EQUAL => "Equal",
+        KeyCode::KEY_LEFTBRACE => "LeftBracket",
+        KeyCode::KEY_RIGHTBRACE => "RightBracket",
+        KeyCode::KEY_BACKSLASH => "BackSlash",
+        KeyCode::KEY_SEMICOLON => "SemiColon",
+        KeyCode::KEY_APOSTROPHE => "Quote",
+        KeyCode::KEY_GRAVE => "BackQuote",
+        KeyCode::KEY_COMMA => "Comma",
+        KeyCode::KEY_DOT => "Dot",
+        KeyCode::KEY_SLASH => "Slash",
+
+        // Numpad
+        KeyCode::KEY_KP0 => "Kp0",
+        KeyCode::KEY_KP1 => "Kp1",
+        KeyCode::KEY_KP2 => "Kp2",
+        KeyCode::KEY_KP3 => "Kp3",
+        KeyCode::KEY_KP4 => "Kp4",
+        KeyCode::KEY_KP5 => "Kp5",
+        KeyCode::KEY_KP6 => "Kp6",
+        KeyCode::KEY_KP7 => "Kp7",
+        KeyCode::KEY_KP8 => "Kp8",
+        KeyCode::KEY_KP9 => "Kp9",
+        KeyCode::KEY_KPENTER => "KpReturn",
+        KeyCode::KEY_KPPLUS => "KpPlus",
+        KeyCode::KEY_KPMINUS => "KpMinus",
+        KeyCode::KEY_KPASTERISK => "KpMultiply",
+        KeyCode::KEY_KPSLASH => "KpDivide",
+        KeyCode::KEY_KPDOT => "KpDelete",
+        KeyCode::KEY_KPEQUAL => "KpEqual",
+        KeyCode::KEY_KPCOMMA => "KpComma",
+        KeyCode::KEY_NUMLOCK => "NumLock",
+
+        // Other
+        KeyCode::KEY_SCROLLLOCK => "ScrollLock",
+        KeyCode::KEY_PAUSE => "Pause",
+        // PC keyboards send SysRq for the Print Screen key
+        KeyCode::KEY_PRINT | KeyCode::KEY_SYSRQ => "PrintScreen",
+        KeyCode::KEY_FN => "Function",
+        // The Menu/Application key on PC keyboards
+        KeyCode::KEY_COMPOSE => "ContextMenu",
+
+        // International keys: ISO, JIS and Korean layouts
+        KeyCode::KEY_102ND => "IntlBackslash",
+        KeyCode::KEY_RO => "IntlRo",
+        KeyCode::KEY_YEN => "IntlYen",
+        KeyCode::KEY_KATAKANAHIRAGANA => "KatakanaHiragana",
+        KeyCode::KEY_KATAKANA => "Katakana",
+        KeyCode::KEY_HIRAGANA => "Hiragana",
+        KeyCode::KEY_HENKAN => "Henkan",
+        KeyCode::KEY_MUHENKAN => "Muhenkan",
+        KeyCode::KEY_ZENKAKUHANKAKU => "ZenkakuHankaku",
+        KeyCode::KEY_HANGEUL => "Hangeul",
+        KeyCode::KEY_HANJA => "Hanja",
+
+        // Media and laptop function-row keys
+        KeyCode::KEY_VOLUMEUP => "VolumeUp",
+        KeyCode::KEY_VOLUMEDOWN => "VolumeDown",
+        KeyCode::KEY_MUTE => "VolumeMute",
+        KeyCode::KEY_MICMUTE => "MicMute",
+        KeyCode::KEY_PLAYPAUSE => "MediaPlayPause",
+        KeyCode::KEY_PLAYCD => "MediaPlay",
+        KeyCode::KEY_PAUSECD => "MediaPause",
+        KeyCode::KEY_STOPCD => "MediaStop",
+        KeyCode::KEY_NEXTSONG => "MediaNextTrack",
+        KeyCode::KEY_PREVIOUSSONG => "MediaPrevTrack",
+        KeyCode::KEY_BRIGHTNESSDOWN => "BrightnessDown",
+        KeyCode::KEY_BRIGHTNESSUP => "BrightnessUp",
+
+        // Programmable keys on gaming and multimedia keyboards
+        KeyCode::KEY_PROG1 => "Prog1",
+        KeyCode::KEY_PROG2 => "Prog2",
+        KeyCode::KEY_PROG3 => "Prog3",
+        KeyCode::KEY_PROG4 => "Prog4",
+
+        // Fallback: use the Debug format but strip the "KEY_" prefix
+        _ => fallback_key_name(key),
+    }
+}
+
+/// Names of `KEY_MACRO1` to `KEY_MACRO30`, the G-keys of gaming keyboards
+pub const MACRO_KEYS: [&str; 30] = [
+    "Macro1", "Macro2", "Macro3", "Macro4", "Macro5", "Macro6", "Macro7", "Macro8", "Macro9", "Macro10", "Macro11",
+    "Macro12", "Macro13", "Macro14", "Macro15", "Macro16", "Macro17", "Macro18", "Macro19", "Macro20", "Macro21",
+    "Macro22", "Macro23", "Macro24", "Macro25", "Macro26", "Macro27", "Macro28", "Macro29", "Macro30",
+];
+
+/// The macro keys and the macro record and preset keys, which evdev has no names for
+#[cfg(target_os = "linux")]
+fn macro_key_name(code: u16) -> Option<&'static str> {
+    let name = match code {
+        0x290..=0x2ad => MACRO_KEYS[usize::from(code - 0x290)],
+        0x2b0 => "MacroRecordStart",
+        0x2b1 => "MacroRecordStop",
+        0x2b2 => "MacroPresetCycle",
+        0x2b3 => "MacroPreset1",
+        0x2b4 => "MacroPreset2",
+        0x2b5 => "MacroPreset3",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Names for keys outside the table are built from the Debug format once and interned,
+/// so the hot path never allocates for them again
+#[cfg(target_os = "linux")]
+fn fallback_key_name(key: evdev::KeyCode) -> &'static str {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, OnceLock};
+
+    static NAMES: OnceLock<Mutex<HashMap<u16, &'static str>>> = OnceLock::new();
+    let mut names = NAMES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    names.entry(key.code()).or_insert_with(|| {
+        let debug_name = format!("{:?}", key);
+        let name = match debug_name.strip_prefix("KEY_") {
+            Some(stripped) => stripped.to_string(),
+            None => debug_name,
+        };
+        Box::leak(name.into_boxed_str())
+    })
+}
+
+/// Output an error event to stdout in JSON format so the desktop app can read it
+/// The app typically only consumes stdout, so stderr errors may not be visible to users
+#[cfg(target_os = "linux")]
+fn output_error_event(error_type: &str, message: &str) {
+    // Output to stdout so the app can read it
+    stream::emit(&Event::now(EventKind::Error {
+        error: error_type.to_string(),
+        message: message.to_string(),
+    }));
+    // Also output to stderr for debugging
+    eprintln!("!error: {} - {}", error_type, message);
+}
+
+/// Open the devices the listener was told to use; any of them failing to open is an error
+#[cfg(target_os = "linux")]
+fn open_selected_devices(paths: &[PathBuf]) -> Result<Vec<(PathBuf, evdev::Device)>, Box<dyn Error>> {
+    let mut selected = Vec::new();
+    for path in paths {
+        let device = input_access::open(path).map_err(|e| {
+            let message = format!("Cannot open device {}: {}", path.display(), e);
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                output_error_event("PermissionDenied", &message);
+            } else {
+                output_error_event("DeviceUnavailable", &message);
+            }
+            message
+        })?;
+        eprintln!("Using device: {} ({})", device.name().unwrap_or("Unknown"), path.display());
+        devices::register(Some(path.display().to_string()), device.name().unwrap_or("Unknown"));
+        selected.push((path.clone(), device));
+    }
+    Ok(selected)
+}
+
+/// Every keyboard in /dev/input, leaving out ignored devices and other seats'
+#[cfg(target_os = "linux")]
+fn detect_keyboards(options: &KeyboardListener) -> Result<Vec<(PathBuf, evdev::Device)>, Box<dyn Error>> {
+    use evdev::{Device, KeyCode};
+    use std::fs;
+
+    let input_dir = "/dev/input";
+    let mut last_error: Option<String> = None;
+    let mut keyboard_devices: Vec<(PathBuf, Device)> = Vec::new();
+
+    // Enumerate devices in /dev/input/ to find ALL keyboards
+    let entries = fs::read_dir(input_dir)
+        .map_err(|e| format!("Cannot access {}: {}", input_dir, e))?;
+
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+        // Only look at eventN devices
+        if !name.starts_with("event") {
+            continue;
+        }
+
+        // Try to open the device
+        match input_access::open(&path) {
+            Ok(device) => {
+                let device_name = device.name().unwrap_or("Unknown");
+                let path_str = path.display().to_string();
+                if options.ignored_devices.iter().any(|pattern| pattern.is_match(device_name) || pattern.is_match(&path_str)) {
+                    eprintln!("Ignoring device: {} ({})", device_name, path_str);
+                    continue;
+                }
+                if !session::owns_device(&path) {
+                    eprintln!("Skipping device on another seat: {} ({})", device_name, path_str);
+                    continue;
+                }
+                // Check if this device has keyboard capabilities (has letter keys or modifier keys),
+                // or with --media-keys, media keys (headsets, consumer control interfaces)
+                if device.supported_keys().is_some_and(|keys| {
+                    keys.contains(KeyCode::KEY_A) || keys.contains(KeyCode::KEY_SPACE) ||
+                    keys.contains(KeyCode::KEY_LEFTCTRL) || keys.contains(KeyCode::KEY_LEFTALT) ||
+                    (options.media_keys && media::has_media_keys(keys))
+                }) || devices::is_trigger_device(&device)
+                    || (options.any_keys && devices::has_any_keys(&device))
+                    || ((macros::recording() || hotkeys::uses_mouse()) && macros::is_pointer(&device)) {
+                    eprintln!("Found keyboard: {} ({})",
+                        device.name().unwrap_or("Unknown"),
+                        path.display());
+                    devices::register(Some(path.display().to_string()), device.name().unwrap_or("Unknown"));
+                    keyboard_devices.push((path.clone(), device));
+                }
+            }
+            Err(e) => {
+                if e.kind() == std::io::ErrorKind::PermissionDenied {
+                    last_error = Some(format!("Permission denied for {}", path.display()));
+                }
+            }
+        }
+    }
+
+    // No keyboard found - provide helpful error message
+    if keyboard_devices.is_empty() {
+        if let Some(err) = last_error {
+            let message = "No access to keyboards. Run: nvidia-cc-rs install-permissions \
+                           (or sudo usermod -aG input $USER, then log out and back in).";
+            output_error_event("PermissionDenied", message);
+            return Err(format!("Failed to access keyboard devices: {}", err).into());
+        }
+        let message = "No keyboard device found in /dev/input/";
+        output_error_event("NoKeyboardFound", message);
+        return Err(message.into());
+    }
+    Ok(keyboard_devices)
+}
+
+#[cfg(target_os = "linux")]
+fn start_keyboard_listener(options: &KeyboardListener) -> Result<(), Box<dyn Error>> {
+    use std::thread;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // Flatpak and Snap builds see no devices; hotkeys still come through the shortcuts portal
+    if options.device_paths.is_empty() {
+        if let Some(sandbox) = portal::confined() {
+            if options.sandbox {
+                return Err(format!("--sandbox can't be used inside {}, whose portals it would block", sandbox).into());
+            }
+            return Err(portal::capture(sandbox).into());
+        }
+    }
+
+    // Explicitly selected devices skip autodetection, for pedals and macro pads without typing keys
+    let keyboard_devices = if options.device_paths.is_empty() {
+        detect_keyboards(options)?
+    } else {
+        open_selected_devices(&options.device_paths)?
+    };
+    // Before the device threads start, so Landlock binds them too
+    if options.sandbox {
+        sandbox::apply(&[])?;
+    }
+
+    eprintln!("Listening on {} keyboard device(s)", keyboard_devices.len());
+
+    // If only one keyboard, no need for threading
+    if keyboard_devices.len() == 1 {
+        let (path, device) = keyboard_devices.into_iter().next().unwrap();
+        let path_str = path.display().to_string();
+        if options.realtime {
+            realtime::elevate_current_thread(&path_str);
+        }
+        return Err(supervise_keyboard_device(&path, device));
+    }
+
+    // Multiple keyboards: spawn a thread for each
+    // Track how many devices are still active - treat per-device failures as non-fatal
+    let active_count = Arc::new(AtomicUsize::new(keyboard_devices.len()));
+
+    for (path, device) in keyboard_devices {
+        let active_count = Arc::clone(&active_count);
+        let realtime = options.realtime;
+        thread::spawn(move || {
+            if realtime {
+                realtime::elevate_current_thread(&path.display().to_string());
+            }
+            // Only returns once the device is lost; the rest keep running so hotkeys
+            // continue to work on other devices (e.g. if a USB keyboard is unplugged)
+            supervise_keyboard_device(&path, device);
+            let remaining = active_count.fetch_sub(1, Ordering::SeqCst) - 1;
+            if remaining == 0 {
+                // All devices have failed - output error to stdout so app can see it
+                output_error_event("AllDevicesFailed", "All keyboard devices have stopped");
+            }
+        });
+    }
+
+    // Block the main thread forever - the spawned threads will handle events
+    // This prevents the function from returning while devices are still being monitored
+    loop {
+        thread::sleep(std::time::Duration::from_secs(60));
+        // Check if all devices have failed
+        if active_count.load(Ordering::SeqCst) == 0 {
+            return Err("All keyboard devices have stopped".into());
+        }
+    }
+}
+
+/// Consecutive failures (the listener, then each reopen) before a device is declared lost
+#[cfg(target_os = "linux")]
+const DEVICE_RETRIES: u32 = 5;
+
+/// Wait before the first reopen, doubled after every further failure up to `MAX_RETRY_BACKOFF`
+#[cfg(target_os = "linux")]
+const RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(250);
+
+#[cfg(target_os = "linux")]
+const MAX_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_secs(8);
+
+/// A reopened device that keeps working this long starts counting failures from zero again
+#[cfg(target_os = "linux")]
+const STABLE_AFTER: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Listen on a device, reopening its event node with exponential backoff whenever reading fails
+/// (a transient EIO after suspend, say), and straight away after a resume. Returns the last error
+/// once the device is lost.
+#[cfg(target_os = "linux")]
+fn supervise_keyboard_device(path: &std::path::Path, mut device: evdev::Device) -> Box<dyn Error> {
+    use std::thread;
+    use std::time::Instant;
+
+    let path_str = path.display().to_string();
+    let name = device.name().unwrap_or("Unknown").to_string();
+    let mut failures = 0;
+
+    loop {
+        let started = Instant::now();
+        let resumes = power::resumes();
+        let mut error = match listen_keyboard_device(device, &path_str, resumes) {
+            Ok(never) => match never {},
+            Err(e) => e,
+        };
+        if power::resumes() != resumes {
+            // Not a failure: the node may just have gone stale while the system slept
+            eprintln!("Reopening device {} after resume", path_str);
+            match input_access::open(path) {
+                Ok(reopened) => {
+                    device = reopened;
+                    continue;
+                }
+                Err(e) => error = e.into(),
+            }
+        }
+        if started.elapsed() >= STABLE_AFTER {
+            failures = 0;
+        }
+        eprintln!("Device {} failed: {}", path_str, error);
+        devices::mark_stopped(&path_str, &error.to_string());
+
+        device = loop {
+            failures += 1;
+            if failures >= DEVICE_RETRIES {
+                eprintln!("Device {} stopped after {} failures", path_str, failures);
+                stream::emit(&Event::now(EventKind::DeviceLost {
+                    path: path_str,
+                    name,
+                    attempts: failures,
+                    error: error.to_string(),
+                }));
+                return error;
+            }
+            thread::sleep(RETRY_BACKOFF.saturating_mul(1 << (failures - 1)).min(MAX_RETRY_BACKOFF));
+            match input_access::open(path) {
+                Ok(device) => break device,
+                Err(e) => error = e.into(),
+            }
+        };
+
+        eprintln!("Device {} reopened", path_str);
+        devices::mark_active(&path_str);
+        stream::emit(&Event::now(EventKind::DeviceRecovered {
+            path: path_str.clone(),
+            name: name.clone(),
+            attempts: failures,
+        }));
+    }
+}
+
+/// Feed a mouse button or wheel event from rdev to the hotkey engine
+#[cfg(not(target_os = "linux"))]
+fn observe_rdev_pointer(event: EventType) {
+    let (trigger, pressed) = match event {
+        EventType::ButtonPress(button) | EventType::ButtonRelease(button) => {
+            let index = match button {
+                rdev::Button::Left => 0,
+                rdev::Button::Right => 1,
+                rdev::Button::Middle => 2,
+                // XBUTTON1 and XBUTTON2 on Windows
+                rdev::Button::Unknown(1) => 3,
+                rdev::Button::Unknown(2) => 4,
+                rdev::Button::Unknown(_) => return,
+            };
+            (hotkeys::MOUSE_BUTTONS[index], Some(matches!(event, EventType::ButtonPress(_))))
+        }
+        // rdev counts scrolling up and right as positive
+        EventType::Wheel { delta_x, delta_y } => {
+            let direction = match (delta_x.signum(), delta_y.signum()) {
+                (_, 1) => 0,
+                (_, -1) => 1,
+                (-1, _) => 2,
+                (1, _) => 3,
+                _ => return,
+            };
+            (hotkeys::SCROLL_DIRECTIONS[direction], None)
+        }
+        _ => return,
+    };
+    hotkeys::observe_mouse(trigger, pressed, hotkeys::now());
+}
+
+/// The hotkey trigger a mouse button or wheel event from evdev stands for, `None` as the press for the wheel
+#[cfg(target_os = "linux")]
+fn evdev_mouse_trigger(event: &evdev::InputEvent) -> Option<(&'static str, Option<bool>)> {
+    use evdev::{EventSummary, KeyCode, RelativeAxisCode};
+
+    match event.destructure() {
+        EventSummary::Key(_, key, _) => {
+            let index = match key {
+                KeyCode::BTN_LEFT => 0,
+                KeyCode::BTN_RIGHT => 1,
+                KeyCode::BTN_MIDDLE => 2,
+                KeyCode::BTN_SIDE => 3,
+                KeyCode::BTN_EXTRA => 4,
+                _ => return None,
+            };
+            Some((hotkeys::MOUSE_BUTTONS[index], Some(event.value() != 0)))
+        }
+        // The wheel counts scrolling up and right as positive
+        EventSummary::RelativeAxis(_, RelativeAxisCode::REL_WHEEL, _) if event.value() != 0 => {
+            Some((hotkeys::SCROLL_DIRECTIONS[if event.value() > 0 { 0 } else { 1 }], None))
+        }
+        EventSummary::RelativeAxis(_, RelativeAxisCode::REL_HWHEEL, _) if event.value() != 0 => {
+            Some((hotkeys::SCROLL_DIRECTIONS[if event.value() > 0 { 3 } else { 2 }], None))
+        }
+        _ => None,
+    }
+}
+
+/// Emit a key read from `device` other than through evdev (a G-key over hidraw), feeding hotkeys as usual
+#[cfg(target_os = "linux")]
+pub fn report_key(device: &str, key: &str, pressed: bool) {
+    if control::is_paused() || !session::owns_input() || media::filtered(key) {
+        return;
+    }
+    // Macros replay through evdev, which has no way to send these
+    if macros::recording() {
+        return;
+    }
+    let name = remap::apply(&[device], key);
+    if !secure_input::active() {
+        let at = SystemTime::now();
+        let key = event::Key {
+            key: &name,
+            name: Some(&name),
+            keysym: None,
+            scancode: None,
+            text: None,
+            num_lock: None,
+            synthetic: false,
+            hold_ms: hold_ms(pressed, &name, at),
+        };
+        let kind = if pressed {
+            EventKind::KeyPress(key)
+        } else {
+            EventKind::KeyRelease(key)
+        };
+        if throttle::admit() {
+            privacy::emit_key(&Event { time: at, kind });
+        }
+        hotstrings::observe(pressed, &name, None);
+    }
+    hotkeys::observe(pressed, &name, hotkeys::now());
+}
+
+/// Map a raw evdev key event read at `at` under `num_lock` to the event we emit under `name`, or `None`
+/// for key repeats
+/// Shared by live capture and `decode` so recorded dumps exercise the exact same path.
+#[cfg(target_os = "linux")]
+pub fn key_event_from_evdev<'a>(
+    key: evdev::KeyCode,
+    name: &'a str,
+    value: i32,
+    resolved: Option<&'a layout::Resolved>,
+    num_lock: Option<bool>,
+    at: SystemTime,
+) -> Option<Event<'a>> {
+    let pressed = match value {
+        0 => false,
+        1 => true,
+        2 => return None, // Key repeat, skip
+        _ => return None,
+    };
+
+    let scancode = scancode::evdev(key);
+
+    // evdev has no notion of the typed character, so the key name doubles as `name`
+    let key = event::Key {
+        key: name,
+        name: Some(name),
+        keysym: resolved.and_then(layout::Resolved::keysym),
+        scancode,
+        text: resolved.and_then(layout::Resolved::text),
+        num_lock,
+        synthetic: false,
+        hold_ms: hold_ms(pressed, name, at),
+    };
+    Some(Event::now(if pressed {
+        EventKind::KeyPress(key)
+    } else {
+        EventKind::KeyRelease(key)
+    }))
+}
+
+/// Read events from the device at `path` until it fails, or until the system resumes after `resumes` resumes
+#[cfg(target_os = "linux")]
+fn listen_keyboard_device(
+    mut device: evdev::Device,
+    path: &str,
+    resumes: u64,
+) -> Result<std::convert::Infallible, Box<dyn Error>> {
+    use evdev::{EventSummary, LedCode};
+    use std::os::fd::AsRawFd;
+
+    // Everything from our own uinput keyboard was injected by us
+    let virtual_device = device.name() == Some(synthetic::VIRTUAL_DEVICE_NAME);
+    let phys = device.physical_path().unwrap_or_default().to_string();
+    let device_name = device.name().unwrap_or_default().to_string();
+    // The NumLock LED, kept up to date by the display server; `None` on keyboards without one
+    let mut num_lock_led = device
+        .supported_leds()
+        .is_some_and(|leds| leds.contains(LedCode::LED_NUML))
+        .then(|| device.get_led_state().is_ok_and(|leds| leds.contains(LedCode::LED_NUML)));
+    if let (Some(supported), Ok(lit)) = (device.supported_leds(), device.get_led_state()) {
+        for led in supported.iter() {
+            if let Some(known) = leds::from_evdev(led) {
+                leds::observe(known, lit.contains(led));
+            }
+        }
+    }
+
+    loop {
+        // Wake up every second to notice a resume, since a stale node just never becomes readable
+        let mut poll_fd = libc::pollfd {
+            fd: device.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, 1000) };
+        if power::resumes() != resumes {
+            return Err("system resumed".into());
+        }
+        if ready < 0 {
+            let error = std::io::Error::last_os_error();
+            if error.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error.into());
+        }
+        if ready == 0 {
+            continue;
+        }
+        for event in device.fetch_events()? {
+            if macros::recording() {
+                let recordable = !control::is_paused() && session::owns_input() && !secure_input::active();
+                if !virtual_device && recordable && !synthetic::injection_active() {
+                    macros::record_evdev(&event);
+                }
+                continue;
+            }
+            if let EventSummary::Led(_, led, _) = event.destructure() {
+                if led == LedCode::LED_NUML && num_lock_led.is_some() {
+                    num_lock_led = Some(event.value() != 0);
+                }
+                if let Some(led) = leds::from_evdev(led) {
+                    leds::observe(led, event.value() != 0);
+                }
+                continue;
+            }
+            // Mouse buttons and the wheel only ever reach hotkeys
+            if let Some((trigger, pressed)) = evdev_mouse_trigger(&event) {
+                let user = !virtual_device && !synthetic::injection_active();
+                if user {
+                    idle::note_input();
+                }
+                if event.value() != 2 && user && !control::is_paused() && session::owns_input() {
+                    hotkeys::observe_mouse(trigger, pressed, hotkeys::now());
+                }
+                continue;
+            }
+            if let EventSummary::Key(_, key, _) = event.destructure() {
+                if event.value() != 2 && throttle::duplicate(&phys, path, key.code(), event.value(), event.timestamp()) {
+                    continue;
+                }
+                let keypad = numpad::is_keypad(evdev_key_to_rdev_name(key));
+                if let (true, Some(on)) = (keypad, num_lock_led) {
+                    layout::sync_num_lock(on);
+                }
+                let num_lock = if keypad { num_lock_led.or_else(numpad::state) } else { None };
+                // Tracked even while paused so modifier state stays in sync with the keyboard
+                let resolved = layout::resolve(key.code(), event.value());
+                if !virtual_device && !synthetic::injection_active() {
+                    idle::note_input();
+                }
+                // Keys typed while another session is in front are that user's
+                if control::is_paused() || !session::owns_input() {
+                    continue;
+                }
+                if media::filtered(evdev_key_to_rdev_name(key)) {
+                    continue;
+                }
+                let name = remap::apply(&[&device_name, path], evdev_key_to_rdev_name(key));
+                let timestamp = event.timestamp();
+                let key_event = key_event_from_evdev(key, &name, event.value(), resolved.as_ref(), num_lock, timestamp);
+                if let Some(mut key_event) = key_event {
+                    let synthetic = virtual_device || synthetic::injection_active();
+                    if synthetic && synthetic::suppress_self() {
+                        continue;
+                    }
+                    if let EventKind::KeyPress(key) | EventKind::KeyRelease(key) = &mut key_event.kind {
+                        key.synthetic = synthetic;
+                    }
+                    if secure_input::active() {
+                        if !synthetic {
+                            hotkeys::observe(event.value() == 1, numpad::effective(&name, num_lock), hotkeys::now());
+                        }
+                        continue;
+                    }
+                    devices::count_event(Some(path));
+                    if throttle::admit() {
+                        privacy::emit_key(&key_event);
+                        if let Ok(latency) = timestamp.elapsed() {
+                            stats::observe_latency(latency);
+                        }
+                    }
+                    if !synthetic {
+                        hotkeys::observe(event.value() == 1, numpad::effective(&name, num_lock), hotkeys::now());
+                        hotstrings::observe(event.value() == 1, &name, resolved.as_ref().and_then(layout::Resolved::text));
+                    }
+                }
+            }
+        }
+    }
+}
+
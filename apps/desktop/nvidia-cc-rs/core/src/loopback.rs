@@ -0,0 +1,118 @@
+//! Capturing our own injected keys, for `bench latency` and `selftest` (Linux).
+//!
+//! A [`Loopback`] types on the uinput virtual keyboard `write` uses while the
+//! listener runs in-process, and hands back the synthetic key events it
+//! captures. Events go to it instead of stdout.
+
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use enigo::{Direction, Key};
+use serde_json::Value;
+
+use crate::backend::{self, Injector, Options};
+use crate::output;
+use crate::stream;
+use crate::KeyboardListener;
+
+/// How long the listener gets to open the virtual keyboard and see a first press
+const WARM_UP: Duration = Duration::from_secs(5);
+
+/// Time for answers to the warm-up presses to trickle in before they are discarded
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// Stream subscriber passing on each synthetic key event and when it was serialized
+struct Captured {
+    sender: Sender<(Instant, Value)>,
+    line: Vec<u8>,
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let arrived = Instant::now();
+            let event: Value = serde_json::from_slice(&self.line).unwrap_or_default();
+            self.line.clear();
+            let key_event = event["event_type"] == "KeyPress" || event["event_type"] == "KeyRelease";
+            // Whatever the user types meanwhile isn't ours
+            if key_event && event["synthetic"] == true {
+                self.sender.send((arrived, event)).ok();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Loopback {
+    pub injector: Injector,
+    events: Receiver<(Instant, Value)>,
+}
+
+impl Loopback {
+    /// Create the virtual keyboard, start capturing and wait until its keys come back
+    pub fn start() -> Result<Self, String> {
+        backend::set_order(vec!["uinput".to_string()]);
+        let mut injector = Injector::new(Options::default());
+        // Creates the virtual keyboard before the listener looks for keyboards
+        injector.key(Key::F20, Direction::Release)?;
+
+        output::set_stdout_enabled(false);
+        let (sender, events) = mpsc::channel();
+        let sink = Captured {
+            sender,
+            line: Vec::new(),
+        };
+        stream::subscribe(Box::new(sink), None).map_err(|e| e.to_string())?;
+        thread::spawn(|| {
+            if let Err(e) = KeyboardListener::new().run() {
+                eprintln!("!error: {}", e);
+            }
+        });
+
+        let mut loopback = Loopback { injector, events };
+        let deadline = Instant::now() + WARM_UP;
+        loop {
+            if Instant::now() >= deadline {
+                return Err("The listener never reported a key press from the virtual keyboard".to_string());
+            }
+            loopback.tap(Key::F20)?;
+            if loopback.next(Duration::from_millis(100)).is_some() {
+                break;
+            }
+        }
+        thread::sleep(SETTLE);
+        loopback.discard();
+        Ok(loopback)
+    }
+
+    /// Press and release `key`
+    pub fn tap(&mut self, key: Key) -> Result<(), String> {
+        self.injector.key(key, Direction::Press)?;
+        self.injector.key(key, Direction::Release)
+    }
+
+    /// Next captured key event and when it was serialized, or `None` after `timeout` without one
+    pub fn next(&self, timeout: Duration) -> Option<(Instant, Value)> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Every key event captured until `quiet` passes without another
+    pub fn collect(&self, quiet: Duration) -> Vec<Value> {
+        std::iter::from_fn(|| self.next(quiet)).map(|(_, event)| event).collect()
+    }
+
+    /// Forget the events captured so far
+    pub fn discard(&self) {
+        while self.events.try_recv().is_ok() {}
+    }
+}
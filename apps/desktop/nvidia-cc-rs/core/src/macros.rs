@@ -0,0 +1,354 @@
+//! Macro recording and replay (`record --out <file>`, `replay <file> [--speed X]`).
+//!
+//! `record` captures the user's key and mouse input (injected input is left
+//! out) until a line or EOF arrives on stdin, then writes it as JSON:
+//!
+//!   {"version": 1, "steps": [{"at_ms": 0, "type": "key_down", "key": "ControlLeft"},
+//!                            {"at_ms": 140, "type": "mouse_down", "button": "left"}, ...]}
+//!
+//! `at_ms` counts from the first recorded step. Keys use the listener's names,
+//! buttons the `mouse click` names and scroll deltas the `mouse scroll` sign
+//! (positive: right and down). rdev reports pointer positions, recorded as
+//! `mouse_move {x, y}` in desktop coordinates; evdev only reports motion, so
+//! Linux recordings have `mouse_move_by {dx, dy}` instead, which only
+//! approximate the pointer's path once acceleration is applied, and touchpads
+//! (which report absolute positions) aren't recorded. Keys and buttons still down when recording stops
+//! (such as the Enter that stopped it) are dropped.
+//!
+//! `replay` injects the steps with their original timing, `--speed 2` playing
+//! them twice as fast. The whole file is checked before anything is injected.
+
+use enigo::{Button, Direction, Key};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backend::{self, Injector};
+use crate::clock;
+use crate::keys;
+use crate::synthetic;
+
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Macro {
+    version: u32,
+    steps: Vec<Step>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Step {
+    at_ms: u64,
+    #[serde(flatten)]
+    action: Action,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Action {
+    KeyDown { key: String },
+    KeyUp { key: String },
+    /// Desktop coordinates
+    MouseMove { x: i32, y: i32 },
+    /// Motion in device units (evdev)
+    MouseMoveBy { dx: i32, dy: i32 },
+    MouseDown { button: String },
+    MouseUp { button: String },
+    Scroll { dx: i32, dy: i32 },
+}
+
+struct Recording {
+    path: PathBuf,
+    /// `(monotonic µs, action)`
+    steps: Vec<(u64, Action)>,
+    /// Keys and buttons down, so auto-repeat isn't recorded as extra presses
+    held: Vec<String>,
+    /// evdev motion since the last SYN_REPORT
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    motion: (i32, i32),
+}
+
+static RECORDING_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<Recording>> {
+    RECORDING.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Whether the listener feeds a recording instead of emitting events
+pub fn recording() -> bool {
+    RECORDING_ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Start recording into `path`; a line or EOF on stdin writes the file and exits
+pub fn start_recording(path: &Path) {
+    *lock() = Some(Recording {
+        path: path.to_path_buf(),
+        steps: Vec::new(),
+        held: Vec::new(),
+        motion: (0, 0),
+    });
+    RECORDING_ACTIVE.store(true, Ordering::Relaxed);
+    eprintln!("Recording to {}; press Enter (or close stdin) to stop", path.display());
+
+    thread::spawn(|| {
+        let mut line = String::new();
+        std::io::stdin().lock().read_line(&mut line).ok();
+        match finish_recording() {
+            Ok(()) => std::process::exit(0),
+            Err(error) => {
+                eprintln!("!error: {}", error);
+                std::process::exit(1);
+            }
+        }
+    });
+}
+
+fn finish_recording() -> Result<(), String> {
+    RECORDING_ACTIVE.store(false, Ordering::Relaxed);
+    let Some(mut recording) = lock().take() else {
+        return Ok(());
+    };
+
+    // A press with no release was still down when recording stopped
+    for held in &recording.held {
+        if let Some(index) = recording.steps.iter().rposition(|(_, action)| press_name(action) == Some(held)) {
+            recording.steps.remove(index);
+        }
+    }
+
+    let start = recording.steps.first().map_or(0, |&(at_us, _)| at_us);
+    let recorded = Macro {
+        version: FORMAT_VERSION,
+        steps: recording
+            .steps
+            .into_iter()
+            .map(|(at_us, action)| Step {
+                at_ms: (at_us - start) / 1000,
+                action,
+            })
+            .collect(),
+    };
+    let json = serde_json::to_string_pretty(&recorded).map_err(|e| e.to_string())?;
+    fs::write(&recording.path, json + "\n").map_err(|e| format!("Cannot write {}: {}", recording.path.display(), e))?;
+    eprintln!("Recorded {} step(s) to {}", recorded.steps.len(), recording.path.display());
+    Ok(())
+}
+
+/// The key or button a press step holds down
+fn press_name(action: &Action) -> Option<&String> {
+    match action {
+        Action::KeyDown { key } => Some(key),
+        Action::MouseDown { button } => Some(button),
+        _ => None,
+    }
+}
+
+fn record(action: Action) {
+    let at_us = clock::monotonic_us();
+    if let Some(recording) = lock().as_mut() {
+        match &action {
+            Action::KeyDown { key: name } | Action::MouseDown { button: name } => {
+                if recording.held.contains(name) {
+                    return;
+                }
+                recording.held.push(name.clone());
+            }
+            Action::KeyUp { key: name } | Action::MouseUp { button: name } => recording.held.retain(|held| held != name),
+            _ => {}
+        }
+        recording.steps.push((at_us, action));
+    }
+}
+
+/// Record a key event named as the listener emits it
+pub fn record_key(pressed: bool, key: &str) {
+    let key = key.to_string();
+    record(if pressed { Action::KeyDown { key } } else { Action::KeyUp { key } });
+}
+
+fn record_button(pressed: bool, button: &str) {
+    let button = button.to_string();
+    record(if pressed {
+        Action::MouseDown { button }
+    } else {
+        Action::MouseUp { button }
+    });
+}
+
+/// Record an rdev pointer event
+#[cfg(not(target_os = "linux"))]
+pub fn record_rdev_pointer(event: rdev::EventType) {
+    use rdev::EventType;
+
+    match event {
+        EventType::MouseMove { x, y } => record(Action::MouseMove {
+            x: x.round() as i32,
+            y: y.round() as i32,
+        }),
+        EventType::ButtonPress(button) | EventType::ButtonRelease(button) => {
+            let name = match button {
+                rdev::Button::Left => "left",
+                rdev::Button::Right => "right",
+                rdev::Button::Middle => "middle",
+                // XBUTTON1 and XBUTTON2 on Windows
+                rdev::Button::Unknown(1) => "back",
+                rdev::Button::Unknown(2) => "forward",
+                rdev::Button::Unknown(_) => return,
+            };
+            record_button(matches!(event, EventType::ButtonPress(_)), name);
+        }
+        // rdev counts scrolling up as positive
+        EventType::Wheel { delta_x, delta_y } => record(Action::Scroll {
+            dx: delta_x as i32,
+            dy: -delta_y as i32,
+        }),
+        EventType::KeyPress(_) | EventType::KeyRelease(_) => {}
+    }
+}
+
+/// Pointers worth opening while recording: mice, trackballs and trackpoints
+#[cfg(target_os = "linux")]
+pub fn is_pointer(device: &evdev::Device) -> bool {
+    use evdev::{KeyCode, RelativeAxisCode};
+    device.supported_relative_axes().is_some_and(|axes| axes.contains(RelativeAxisCode::REL_X))
+        && device.supported_keys().is_some_and(|keys| keys.contains(KeyCode::BTN_LEFT))
+}
+
+/// Record an evdev event from a keyboard or pointer
+#[cfg(target_os = "linux")]
+pub fn record_evdev(event: &evdev::InputEvent) {
+    use evdev::{EventSummary, KeyCode, RelativeAxisCode, SynchronizationCode};
+
+    match event.destructure() {
+        EventSummary::Key(_, key, _) if event.value() != 2 => {
+            let pressed = event.value() == 1;
+            let button = match key {
+                KeyCode::BTN_LEFT => "left",
+                KeyCode::BTN_RIGHT => "right",
+                KeyCode::BTN_MIDDLE => "middle",
+                KeyCode::BTN_SIDE => "back",
+                KeyCode::BTN_EXTRA => "forward",
+                // Other buttons, and touch and tool state
+                key if (KeyCode::BTN_0.code()..KeyCode::KEY_OK.code()).contains(&key.code()) => return,
+                _ => {
+                    let name = crate::listener::evdev_key_to_rdev_name(key);
+                    if !crate::media::filtered(name) {
+                        record_key(pressed, name);
+                    }
+                    return;
+                }
+            };
+            record_button(pressed, button);
+        }
+        EventSummary::RelativeAxis(_, axis, _) => {
+            let value = event.value();
+            match axis {
+                RelativeAxisCode::REL_X | RelativeAxisCode::REL_Y => {
+                    if let Some(recording) = lock().as_mut() {
+                        if axis == RelativeAxisCode::REL_X {
+                            recording.motion.0 += value;
+                        } else {
+                            recording.motion.1 += value;
+                        }
+                    }
+                }
+                // The wheel counts scrolling up as positive
+                RelativeAxisCode::REL_WHEEL => record(Action::Scroll { dx: 0, dy: -value }),
+                RelativeAxisCode::REL_HWHEEL => record(Action::Scroll { dx: value, dy: 0 }),
+                _ => {}
+            }
+        }
+        // One move per report rather than one per axis
+        EventSummary::Synchronization(_, SynchronizationCode::SYN_REPORT, _) => {
+            let motion = lock().as_mut().map(|recording| std::mem::take(&mut recording.motion));
+            if let Some((dx, dy)) = motion.filter(|&motion| motion != (0, 0)) {
+                record(Action::MouseMoveBy { dx, dy });
+            }
+        }
+        _ => {}
+    }
+}
+
+enum Planned {
+    Key(Key, Direction),
+    Move { x: i32, y: i32, relative: bool },
+    Button(Button, Direction),
+    Scroll { dx: i32, dy: i32 },
+}
+
+impl Planned {
+    fn from_action(action: Action) -> Result<Self, String> {
+        Ok(match action {
+            Action::KeyDown { key } => Planned::Key(keys::parse_key(&key)?, Direction::Press),
+            Action::KeyUp { key } => Planned::Key(keys::parse_key(&key)?, Direction::Release),
+            Action::MouseMove { x, y } => Planned::Move { x, y, relative: false },
+            Action::MouseMoveBy { dx, dy } => Planned::Move {
+                x: dx,
+                y: dy,
+                relative: true,
+            },
+            Action::MouseDown { button } => Planned::Button(crate::keys::parse_mouse_button(&button)?, Direction::Press),
+            Action::MouseUp { button } => Planned::Button(crate::keys::parse_mouse_button(&button)?, Direction::Release),
+            Action::Scroll { dx, dy } => Planned::Scroll { dx, dy },
+        })
+    }
+}
+
+/// A macro file, checked and ready to replay
+pub struct Replay {
+    steps: Vec<(Duration, Planned)>,
+}
+
+/// Read and check the macro in `path`
+pub fn load(path: &Path) -> Result<Replay, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let recorded: Macro = serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if recorded.version != FORMAT_VERSION {
+        return Err(format!("{}: unsupported macro version {}", path.display(), recorded.version));
+    }
+    let steps = recorded
+        .steps
+        .into_iter()
+        .enumerate()
+        .map(|(index, step)| {
+            let planned = Planned::from_action(step.action)
+                .map_err(|e| format!("{}: step {}: {}", path.display(), index, e))?;
+            Ok((Duration::from_millis(step.at_ms), planned))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(Replay { steps })
+}
+
+/// Inject the steps of `replay`, `speed` times faster than recorded
+pub fn replay(replay: &Replay, speed: f64) -> Result<(), Box<dyn Error>> {
+    let mut injector = Injector::new(backend::Options::default());
+    let _injection = synthetic::begin_injection();
+
+    let started = Instant::now();
+    for (at, step) in &replay.steps {
+        let due = at.div_f64(speed);
+        if let Some(wait) = due.checked_sub(started.elapsed()) {
+            thread::sleep(wait);
+        }
+        let result = match *step {
+            Planned::Key(key, direction) => injector.key(key, direction),
+            Planned::Move { x, y, relative } => injector.mouse_move(x, y, relative),
+            Planned::Button(button, direction) => injector.mouse_button(button, direction),
+            Planned::Scroll { dx, dy } => injector.scroll(dx, dy),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to replay step at {} ms: {}", at.as_millis(), e);
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
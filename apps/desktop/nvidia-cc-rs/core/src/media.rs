@@ -0,0 +1,90 @@
+//! Media and brightness keys (`listen --media-keys`).
+//!
+//! Volume, mute, play/pause, next/previous and brightness keys are emitted
+//! as regular `KeyPress`/`KeyRelease` events under the same names on every
+//! backend, e.g. `MediaPlayPause` for a headset's play/pause button. They are
+//! dropped unless `--media-keys` is given. On Linux the flag also opens
+//! devices that have media keys but no letter keys (headsets, "Consumer
+//! Control" interfaces). rdev on macOS doesn't see media keys: macOS delivers
+//! them as system-defined events rather than key events.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Names both backends use for media keys
+const NAMES: [&str; 12] = [
+    "VolumeUp",
+    "VolumeDown",
+    "VolumeMute",
+    "MicMute",
+    "MediaPlayPause",
+    "MediaPlay",
+    "MediaPause",
+    "MediaStop",
+    "MediaNextTrack",
+    "MediaPrevTrack",
+    "BrightnessDown",
+    "BrightnessUp",
+];
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether `key` (an emitted key name) is a media key
+pub fn is_media_key(key: &str) -> bool {
+    NAMES.contains(&key)
+}
+
+/// Whether a key event named `key` should be dropped under the current options
+pub fn filtered(key: &str) -> bool {
+    !enabled() && is_media_key(key)
+}
+
+/// Media key name for a Windows virtual-key code, which rdev reports as `Unknown`
+#[cfg(windows)]
+pub fn rdev_unknown_name(code: u32) -> Option<&'static str> {
+    let name = match code {
+        0xAD => "VolumeMute",
+        0xAE => "VolumeDown",
+        0xAF => "VolumeUp",
+        0xB0 => "MediaNextTrack",
+        0xB1 => "MediaPrevTrack",
+        0xB2 => "MediaStop",
+        0xB3 => "MediaPlayPause",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn rdev_unknown_name(_code: u32) -> Option<&'static str> {
+    None
+}
+
+/// Devices worth opening only for their media keys
+#[cfg(target_os = "linux")]
+pub fn has_media_keys(keys: &evdev::AttributeSetRef<evdev::KeyCode>) -> bool {
+    use evdev::KeyCode;
+    [
+        KeyCode::KEY_VOLUMEUP,
+        KeyCode::KEY_VOLUMEDOWN,
+        KeyCode::KEY_MUTE,
+        KeyCode::KEY_MICMUTE,
+        KeyCode::KEY_PLAYPAUSE,
+        KeyCode::KEY_PLAYCD,
+        KeyCode::KEY_PAUSECD,
+        KeyCode::KEY_STOPCD,
+        KeyCode::KEY_NEXTSONG,
+        KeyCode::KEY_PREVIOUSSONG,
+        KeyCode::KEY_BRIGHTNESSDOWN,
+        KeyCode::KEY_BRIGHTNESSUP,
+    ]
+    .into_iter()
+    .any(|key| keys.contains(key))
+}
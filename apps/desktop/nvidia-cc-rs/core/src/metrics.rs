@@ -0,0 +1,236 @@
+//! Prometheus metrics for a running listener (`listen --metrics-addr ADDR`).
+//!
+//! `GET /metrics` answers in the Prometheus text format, so the helper can be
+//! scraped and graphed next to system metrics. No token is asked for, as
+//! scrapers rarely send one; keep the address on loopback.
+//!
+//!   nvidia_cc_uptime_seconds, nvidia_cc_paused
+//!   nvidia_cc_events_emitted_total, nvidia_cc_events_dropped_total
+//!   nvidia_cc_duplicates_dropped_total, nvidia_cc_rate_limited_total
+//!   nvidia_cc_writes_total{result="ok|failed"}
+//!   nvidia_cc_inject_backend_info{backend}
+//!   nvidia_cc_output_queue_depth, nvidia_cc_subscribers
+//!   nvidia_cc_device_active{device,path}, nvidia_cc_device_events_total{device,path}
+//!   nvidia_cc_input_latency_seconds - histogram of kernel timestamp to emit (Linux)
+//!   nvidia_cc_gpu_temperature_celsius{gpu,name}, nvidia_cc_gpu_utilization_ratio,
+//!   nvidia_cc_gpu_memory_used_bytes, nvidia_cc_gpu_memory_total_bytes,
+//!   nvidia_cc_gpu_power_watts - from the gpu module, left out without nvidia-smi
+
+use std::fmt::{Display, Write as _};
+use std::io::{self, BufReader};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+
+use crate::clock;
+use crate::control;
+use crate::devices;
+use crate::gpu::{self, Gpu};
+use crate::http::{self, CLIENT_TIMEOUT, MAX_REQUEST_HEAD_BYTES};
+use crate::output;
+use crate::stats::{self, LATENCY_BUCKETS_US};
+use crate::stream;
+use crate::throttle;
+
+/// Content type of the text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Bind `addr` and answer scrapes on a background thread
+pub fn serve(addr: &str) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    if !local_addr.ip().is_loopback() {
+        eprintln!("Warning: metrics are reachable from other machines on {}", local_addr);
+    }
+
+    thread::spawn(move || {
+        for connection in listener.incoming() {
+            let Ok(client) = connection else { continue };
+            thread::spawn(move || {
+                if let Err(e) = handle(client) {
+                    eprintln!("Metrics client error: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(local_addr)
+}
+
+fn handle(mut client: TcpStream) -> io::Result<()> {
+    client.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    client.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(client.try_clone()?);
+    let mut request_line = String::new();
+    if !http::read_head_line(&mut reader, &mut request_line, MAX_REQUEST_HEAD_BYTES)? {
+        return http::respond(&mut client, "400 Bad Request", "text/plain", "Request line too long");
+    }
+    let mut head_bytes = request_line.len();
+    loop {
+        let mut header = String::new();
+        if !http::read_head_line(&mut reader, &mut header, MAX_REQUEST_HEAD_BYTES - head_bytes)? {
+            return http::respond(&mut client, "431 Request Header Fields Too Large", "text/plain", "Headers too large");
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        head_bytes += header.len();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return http::respond(&mut client, "400 Bad Request", "text/plain", "Malformed request");
+    };
+    if method != "GET" {
+        return http::respond(&mut client, "405 Method Not Allowed", "text/plain", "Only GET is supported");
+    }
+    match target.split_once('?').map_or(target, |(path, _)| path) {
+        "/metrics" => http::respond(&mut client, "200 OK", CONTENT_TYPE, &render()),
+        _ => http::respond(&mut client, "404 Not Found", "text/plain", "Not found"),
+    }
+}
+
+/// Metric families in the text exposition format
+#[derive(Default)]
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        writeln!(self.text, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.text, "# TYPE {} {}", name, kind).unwrap();
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> =
+                labels.iter().map(|(label, value)| format!("{}=\"{}\"", label, escape(value))).collect();
+            write!(self.text, "{{{}}}", labels.join(",")).unwrap();
+        }
+        writeln!(self.text, " {}", value).unwrap();
+    }
+
+    /// A family with a single unlabelled sample
+    fn single(&mut self, name: &str, kind: &str, help: &str, value: impl Display) {
+        self.family(name, kind, help);
+        self.sample(name, &[], value);
+    }
+}
+
+/// Label values escape backslashes, quotes and newlines
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Every metric, as `/metrics` answers
+pub fn render() -> String {
+    let mut out = Exposition::default();
+    let uptime = seconds(clock::monotonic_us());
+    out.single("nvidia_cc_uptime_seconds", "gauge", "Seconds since the helper started.", uptime);
+    out.single("nvidia_cc_paused", "gauge", "1 while capture is paused.", u8::from(control::is_paused()));
+    out.single(
+        "nvidia_cc_events_emitted_total",
+        "counter",
+        "Events put on the stream, every kind counted.",
+        stream::last_seq(),
+    );
+    out.single(
+        "nvidia_cc_events_dropped_total",
+        "counter",
+        "Events dropped because stdout wasn't read.",
+        output::dropped(),
+    );
+    out.single(
+        "nvidia_cc_duplicates_dropped_total",
+        "counter",
+        "Key events seen twice through two interfaces of one keyboard.",
+        throttle::duplicates(),
+    );
+    out.single(
+        "nvidia_cc_rate_limited_total",
+        "counter",
+        "Key events held back by --max-event-rate.",
+        throttle::rate_limited(),
+    );
+
+    let (writes_ok, writes_failed) = stats::writes();
+    out.family("nvidia_cc_writes_total", "counter", "Finished write commands.");
+    out.sample("nvidia_cc_writes_total", &[("result", "ok")], writes_ok);
+    out.sample("nvidia_cc_writes_total", &[("result", "failed")], writes_failed);
+    if let Some(backend) = stats::backend() {
+        out.family("nvidia_cc_inject_backend_info", "gauge", "Backend the last injection went through.");
+        out.sample("nvidia_cc_inject_backend_info", &[("backend", backend)], 1);
+    }
+    out.single(
+        "nvidia_cc_output_queue_depth",
+        "gauge",
+        "Events waiting for stdout.",
+        output::queued(),
+    );
+    out.single(
+        "nvidia_cc_subscribers",
+        "gauge",
+        "Socket clients and other sinks receiving events.",
+        stream::subscribers(),
+    );
+
+    let devices = devices::snapshot();
+    out.family("nvidia_cc_device_active", "gauge", "1 while the input device is read.");
+    for device in &devices {
+        let labels = [("device", device.name.as_str()), ("path", device.path.as_deref().unwrap_or(""))];
+        out.sample("nvidia_cc_device_active", &labels, u8::from(device.active));
+    }
+    out.family("nvidia_cc_device_events_total", "counter", "Key events read from the input device.");
+    for device in &devices {
+        let labels = [("device", device.name.as_str()), ("path", device.path.as_deref().unwrap_or(""))];
+        out.sample("nvidia_cc_device_events_total", &labels, device.events);
+    }
+
+    let latency = stats::latency();
+    let name = "nvidia_cc_input_latency_seconds";
+    out.family(name, "histogram", "Time from the kernel's key event timestamp to the event being emitted.");
+    for (bound_us, count) in LATENCY_BUCKETS_US.iter().zip(&latency.buckets) {
+        let bound = seconds(*bound_us);
+        out.sample(&format!("{}_bucket", name), &[("le", &bound)], count);
+    }
+    out.sample(&format!("{}_bucket", name), &[("le", "+Inf")], latency.count);
+    out.sample(&format!("{}_sum", name), &[], seconds(latency.sum_us));
+    out.sample(&format!("{}_count", name), &[], latency.count);
+
+    gpus(&mut out);
+    out.text
+}
+
+fn seconds(us: u64) -> String {
+    format!("{}", us as f64 / 1_000_000.0)
+}
+
+/// One of a GPU's readings
+type Reading = fn(&Gpu) -> Option<f64>;
+
+/// Per-GPU metric families and the reading each one samples
+const GPU_FAMILIES: [(&str, &str, Reading); 5] = [
+    ("nvidia_cc_gpu_temperature_celsius", "GPU core temperature.", |gpu| gpu.temperature_celsius),
+    ("nvidia_cc_gpu_utilization_ratio", "Fraction of time the GPU was busy.", |gpu| gpu.utilization_ratio),
+    ("nvidia_cc_gpu_memory_used_bytes", "GPU memory in use.", |gpu| Some(gpu.memory_used_bytes? as f64)),
+    ("nvidia_cc_gpu_memory_total_bytes", "GPU memory installed.", |gpu| Some(gpu.memory_total_bytes? as f64)),
+    ("nvidia_cc_gpu_power_watts", "GPU board power draw.", |gpu| gpu.power_watts),
+];
+
+/// GPU telemetry; nothing when nvidia-smi isn't installed or finds no GPU
+fn gpus(out: &mut Exposition) {
+    let Ok(gpus) = gpu::query() else { return };
+    for (name, help, reading) in GPU_FAMILIES {
+        // A GPU without the reading is left out of that family
+        let samples: Vec<(&Gpu, f64)> = gpus.iter().filter_map(|gpu| Some((gpu, reading(gpu)?))).collect();
+        if samples.is_empty() {
+            continue;
+        }
+        out.family(name, "gauge", help);
+        for (gpu, value) in samples {
+            out.sample(name, &[("gpu", &gpu.index.to_string()), ("name", &gpu.name)], value);
+        }
+    }
+}
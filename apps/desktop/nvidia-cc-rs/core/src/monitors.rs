@@ -0,0 +1,197 @@
+//! Monitor geometry for `mouse move --monitor` and `mouse monitors`.
+//!
+//! Positions are in the desktop coordinates `mouse move` takes. On Windows and
+//! macOS the primary monitor's top-left corner is (0, 0) and monitors left of
+//! or above it have negative origins; on X11 (0, 0) is the top-left corner of
+//! the screen spanning all monitors. xrandr supplies the layout on Linux (X11
+//! and XWayland), EnumDisplayMonitors on Windows and Quartz Display Services
+//! on macOS.
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct Monitor {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub primary: bool,
+}
+
+/// Active monitors, in the order the platform reports them; `--monitor N` indexes this list
+pub fn list() -> Result<Vec<Monitor>, String> {
+    let monitors = platform::list()?;
+    if monitors.is_empty() {
+        return Err("No monitors found".to_string());
+    }
+    Ok(monitors)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::Command;
+
+    use super::Monitor;
+
+    pub fn list() -> Result<Vec<Monitor>, String> {
+        let output = Command::new("xrandr")
+            .arg("--listactivemonitors")
+            .output()
+            .map_err(|e| format!("xrandr unavailable: {}", e))?;
+        if !output.status.success() {
+            return Err(format!(
+                "xrandr failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).lines().filter_map(parse_line).collect())
+    }
+
+    /// ` 1: +*HDMI-1 1920/527x1080/296+2560+0  HDMI-1` (`*` marks the primary monitor)
+    fn parse_line(line: &str) -> Option<Monitor> {
+        let (_, rest) = line.trim().split_once(": ")?;
+        let mut fields = rest.split_whitespace();
+        let flagged_name = fields.next()?;
+        let geometry = fields.next()?;
+        let primary = flagged_name.contains('*');
+        let name = flagged_name.trim_start_matches(['+', '*']).to_string();
+
+        // <width>/<mm>x<height>/<mm>+<x>+<y>
+        let (size, origin) = geometry.split_at(geometry.find(['+', '-'])?);
+        let (width, height) = size.split_once('x')?;
+        let dimension = |value: &str| value.split('/').next()?.parse::<i32>().ok();
+        let (x, y) = parse_origin(origin)?;
+        Some(Monitor {
+            name,
+            x,
+            y,
+            width: dimension(width)?,
+            height: dimension(height)?,
+            primary,
+        })
+    }
+
+    /// `+2560+0` or `-1920+0`
+    fn parse_origin(origin: &str) -> Option<(i32, i32)> {
+        let split = origin[1..].find(['+', '-'])? + 1;
+        let (x, y) = origin.split_at(split);
+        Some((x.parse().ok()?, y.parse().ok()?))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::mem;
+    use std::ptr;
+
+    use windows_sys::core::BOOL;
+    use windows_sys::Win32::Foundation::{LPARAM, RECT};
+    use windows_sys::Win32::Graphics::Gdi::{
+        EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO, MONITORINFOEXW,
+    };
+    use windows_sys::Win32::UI::WindowsAndMessaging::MONITORINFOF_PRIMARY;
+
+    use super::Monitor;
+
+    unsafe extern "system" fn collect(monitor: HMONITOR, _hdc: HDC, _clip: *mut RECT, data: LPARAM) -> BOOL {
+        let monitors = &mut *(data as *mut Vec<Monitor>);
+        let mut info: MONITORINFOEXW = mem::zeroed();
+        info.monitorInfo.cbSize = mem::size_of::<MONITORINFOEXW>() as u32;
+        if GetMonitorInfoW(monitor, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO) != 0 {
+            let rect = info.monitorInfo.rcMonitor;
+            let len = info.szDevice.iter().position(|&c| c == 0).unwrap_or(info.szDevice.len());
+            monitors.push(Monitor {
+                name: String::from_utf16_lossy(&info.szDevice[..len]),
+                x: rect.left,
+                y: rect.top,
+                width: rect.right - rect.left,
+                height: rect.bottom - rect.top,
+                primary: info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
+            });
+        }
+        1
+    }
+
+    pub fn list() -> Result<Vec<Monitor>, String> {
+        let mut monitors: Vec<Monitor> = Vec::new();
+        let enumerated = unsafe {
+            EnumDisplayMonitors(
+                ptr::null_mut(),
+                ptr::null(),
+                Some(collect),
+                &mut monitors as *mut Vec<Monitor> as LPARAM,
+            )
+        };
+        if enumerated == 0 {
+            return Err("EnumDisplayMonitors failed".to_string());
+        }
+        Ok(monitors)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::Monitor;
+
+    #[repr(C)]
+    struct CGPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[repr(C)]
+    struct CGSize {
+        width: f64,
+        height: f64,
+    }
+
+    #[repr(C)]
+    struct CGRect {
+        origin: CGPoint,
+        size: CGSize,
+    }
+
+    const MAX_DISPLAYS: u32 = 32;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGGetActiveDisplayList(max_displays: u32, displays: *mut u32, count: *mut u32) -> i32;
+        fn CGDisplayBounds(display: u32) -> CGRect;
+        fn CGMainDisplayID() -> u32;
+    }
+
+    pub fn list() -> Result<Vec<Monitor>, String> {
+        let mut displays = [0u32; MAX_DISPLAYS as usize];
+        let mut count = 0u32;
+        let error = unsafe { CGGetActiveDisplayList(MAX_DISPLAYS, displays.as_mut_ptr(), &mut count) };
+        if error != 0 {
+            return Err(format!("CGGetActiveDisplayList failed ({})", error));
+        }
+        let main = unsafe { CGMainDisplayID() };
+        Ok(displays[..count as usize]
+            .iter()
+            .map(|&display| {
+                // Global display coordinates, in points, with the main display's top-left at the origin
+                let bounds = unsafe { CGDisplayBounds(display) };
+                Monitor {
+                    name: format!("display {}", display),
+                    x: bounds.origin.x as i32,
+                    y: bounds.origin.y as i32,
+                    width: bounds.size.width as i32,
+                    height: bounds.size.height as i32,
+                    primary: display == main,
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    use super::Monitor;
+
+    pub fn list() -> Result<Vec<Monitor>, String> {
+        Err("Monitor layout is not available on this platform".to_string())
+    }
+}
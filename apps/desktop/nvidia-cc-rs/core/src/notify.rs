@@ -0,0 +1,194 @@
+//! Desktop notifications for `notify` and the `notify` stdin command.
+//!
+//! Lets the app raise alerts such as "microphone is live" or "permission
+//! missing" while its own window is hidden. Linux goes through the
+//! freedesktop notification service, with `notify-send` or else `gdbus`;
+//! Windows shows a toast through PowerShell's WinRT bindings; macOS uses
+//! `display notification`, which always shows the Script Editor icon, so
+//! `icon` is ignored there.
+//!
+//! Title, body and icon reach the scripts as arguments or environment
+//! variables, never spliced into script text.
+//!
+//! Toasts can carry a button opening a URL, as `--driver-guidance` uses for
+//! the driver download page; other platforms show the notification without it.
+
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct Notification {
+    pub title: String,
+    #[serde(default)]
+    pub body: String,
+    /// Icon name from the icon theme (Linux) or path to an image
+    #[serde(default)]
+    pub icon: Option<String>,
+    /// Button opening a URL (Windows); not taken from the `notify` command
+    #[serde(skip)]
+    pub action: Option<Action>,
+}
+
+pub struct Action {
+    pub label: String,
+    pub url: String,
+}
+
+/// Shown as the sender on Linux; toasts and macOS notifications name the script host
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const APP_NAME: &str = "NVIDIA Control Center";
+
+pub fn show(notification: &Notification) -> Result<(), String> {
+    if notification.title.is_empty() {
+        return Err("A notification needs a title".to_string());
+    }
+    platform::show(notification)
+}
+
+/// Run `command`, failing with its stderr when it fails
+fn run(command: &mut std::process::Command, program: &str) -> Result<(), String> {
+    let output = command.output().map_err(|e| format!("{} unavailable: {}", program, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{} exited with {}: {}", program, output.status, stderr.trim()));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::Command;
+
+    use super::{run, Notification, APP_NAME};
+
+    /// `text` as a GVariant string literal, for `gdbus call`
+    fn gvariant_string(text: &str) -> String {
+        let mut literal = String::from("\"");
+        for c in text.chars() {
+            match c {
+                '"' | '\\' => {
+                    literal.push('\\');
+                    literal.push(c);
+                }
+                '\n' => literal.push_str("\\n"),
+                c if c.is_control() => literal.push_str(&format!("\\u{:04x}", c as u32)),
+                c => literal.push(c),
+            }
+        }
+        literal.push('"');
+        literal
+    }
+
+    pub fn show(notification: &Notification) -> Result<(), String> {
+        let icon = notification.icon.as_deref().unwrap_or_default();
+        let mut notify_send = Command::new("notify-send");
+        notify_send.arg(format!("--app-name={}", APP_NAME));
+        if !icon.is_empty() {
+            notify_send.arg(format!("--icon={}", icon));
+        }
+        notify_send.args(["--", &notification.title, &notification.body]);
+        run(&mut notify_send, "notify-send").or_else(|notify_send_error| {
+            // The service itself, when libnotify's client isn't installed
+            run(
+                Command::new("gdbus").args([
+                    "call",
+                    "--session",
+                    "--dest=org.freedesktop.Notifications",
+                    "--object-path=/org/freedesktop/Notifications",
+                    "--method=org.freedesktop.Notifications.Notify",
+                    &gvariant_string(APP_NAME),
+                    "0",
+                    &gvariant_string(icon),
+                    &gvariant_string(&notification.title),
+                    &gvariant_string(&notification.body),
+                    "[]",
+                    "{}",
+                    "-1",
+                ]),
+                "gdbus",
+            )
+            .map_err(|gdbus_error| format!("{}; {}", notify_send_error, gdbus_error))
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+
+    use super::{run, Notification};
+
+    const SCRIPT: &str = r#"
+        function run([title, body]) {
+            const app = Application.currentApplication();
+            app.includeStandardAdditions = true;
+            app.displayNotification(body, { withTitle: title });
+        }"#;
+
+    pub fn show(notification: &Notification) -> Result<(), String> {
+        run(
+            Command::new("osascript").args(["-l", "JavaScript", "-e", SCRIPT, &notification.title, &notification.body]),
+            "osascript",
+        )
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::process::Command;
+
+    use super::{run, Notification};
+
+    /// Toasts need a registered app id; PowerShell's own is always there
+    const SCRIPT: &str = r#"
+        $manager = [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications,
+            ContentType = WindowsRuntime]
+        $kind = if ($env:NVIDIA_CC_NOTIFY_ICON) { 'ToastImageAndText02' } else { 'ToastText02' }
+        $toast = $manager::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::$kind)
+        $texts = $toast.GetElementsByTagName('text')
+        $texts.Item(0).AppendChild($toast.CreateTextNode($env:NVIDIA_CC_NOTIFY_TITLE)) > $null
+        $texts.Item(1).AppendChild($toast.CreateTextNode($env:NVIDIA_CC_NOTIFY_BODY)) > $null
+        if ($env:NVIDIA_CC_NOTIFY_ICON) {
+            $toast.GetElementsByTagName('image').Item(0).SetAttribute('src', $env:NVIDIA_CC_NOTIFY_ICON)
+        }
+        if ($env:NVIDIA_CC_NOTIFY_ACTION_URL) {
+            # Protocol activation opens the URL in the default browser, with no app to call back
+            $action = $toast.CreateElement('action')
+            $action.SetAttribute('content', $env:NVIDIA_CC_NOTIFY_ACTION_LABEL)
+            $action.SetAttribute('arguments', $env:NVIDIA_CC_NOTIFY_ACTION_URL)
+            $action.SetAttribute('activationType', 'protocol')
+            $actions = $toast.CreateElement('actions')
+            $actions.AppendChild($action) > $null
+            $toast.DocumentElement.AppendChild($actions) > $null
+        }
+        $app = '{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe'
+        $manager::CreateToastNotifier($app).Show([Windows.UI.Notifications.ToastNotification]::new($toast))
+    "#;
+
+    pub fn show(notification: &Notification) -> Result<(), String> {
+        let icon = notification.icon.as_deref().map(|icon| {
+            // Toast images are URIs
+            std::fs::canonicalize(icon)
+                .map(|path| format!("file:///{}", path.display().to_string().trim_start_matches(r"\\?\")))
+                .unwrap_or_else(|_| icon.to_string())
+        });
+        run(
+            Command::new("powershell")
+                .args(["-NoProfile", "-Command", SCRIPT])
+                .env("NVIDIA_CC_NOTIFY_TITLE", &notification.title)
+                .env("NVIDIA_CC_NOTIFY_BODY", &notification.body)
+                .env("NVIDIA_CC_NOTIFY_ICON", icon.unwrap_or_default())
+                .env("NVIDIA_CC_NOTIFY_ACTION_LABEL", notification.action.as_ref().map_or("", |action| &action.label))
+                .env("NVIDIA_CC_NOTIFY_ACTION_URL", notification.action.as_ref().map_or("", |action| &action.url)),
+            "powershell",
+        )
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use super::Notification;
+
+    pub fn show(_notification: &Notification) -> Result<(), String> {
+        Err("Notifications aren't supported on this platform".to_string())
+    }
+}
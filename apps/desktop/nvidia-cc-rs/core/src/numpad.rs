@@ -0,0 +1,76 @@
+//! NumLock and the numeric keypad.
+//!
+//! Keypad key events carry `num_lock`, the NumLock state they were typed
+//! under. `key` stays the physical key (`Kp7`) either way, while on Linux the
+//! keysym says what the focused app receives: `KP_7`, or `KP_Home` with
+//! NumLock off. The state comes from the keyboard's NumLock LED, which the
+//! display server keeps in sync, or from the NumLock presses seen so far when
+//! the keyboard has no LED.
+//!
+//! Hotkeys bound to a digit (`Kp7`) fire in both states. The navigation names
+//! (`KpInsert`, `KpEnd`, `KpDown`, `KpPageDown`, `KpLeft`, `KpBegin`,
+//! `KpRight`, `KpHome`, `KpUp`, `KpPageUp` for `Kp0` to `Kp9`) only match with
+//! NumLock off. Windows itself reports the keypad as the navigation keys
+//! (`Home`) while NumLock is off, so there only the digits with NumLock on are
+//! keypad keys; macOS has no NumLock.
+
+/// Keypad digits and the navigation key each one is with NumLock off
+const NAVIGATION: [(&str, &str); 10] = [
+    ("Kp0", "KpInsert"),
+    ("Kp1", "KpEnd"),
+    ("Kp2", "KpDown"),
+    ("Kp3", "KpPageDown"),
+    ("Kp4", "KpLeft"),
+    ("Kp5", "KpBegin"),
+    ("Kp6", "KpRight"),
+    ("Kp7", "KpHome"),
+    ("Kp8", "KpUp"),
+    ("Kp9", "KpPageUp"),
+];
+
+/// Whether `key` is on the numeric keypad
+pub fn is_keypad(key: &str) -> bool {
+    key.starts_with("Kp")
+}
+
+/// Name hotkeys see for `key` under `num_lock`: the navigation name for a digit with NumLock off
+pub fn effective(key: &str, num_lock: Option<bool>) -> &str {
+    match num_lock {
+        Some(false) => NAVIGATION.iter().find(|(digit, _)| *digit == key).map_or(key, |(_, navigation)| navigation),
+        _ => key,
+    }
+}
+
+/// The physical key behind `key`, `Kp7` for `KpHome`
+pub fn physical(key: &str) -> &str {
+    NAVIGATION.iter().find(|(_, navigation)| *navigation == key).map_or(key, |(digit, _)| digit)
+}
+
+/// Current NumLock state, when the platform has one
+pub fn state() -> Option<bool> {
+    platform::state()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    pub fn state() -> Option<bool> {
+        crate::layout::num_lock()
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetKeyState, VK_NUMLOCK};
+
+    pub fn state() -> Option<bool> {
+        // The low bit is the toggle state
+        Some(unsafe { GetKeyState(VK_NUMLOCK as i32) } & 1 != 0)
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    pub fn state() -> Option<bool> {
+        None
+    }
+}
@@ -0,0 +1,410 @@
+//! Non-blocking stdout delivery.
+//!
+//! If the parent app stops reading stdout, a direct write blocks as soon as the
+//! pipe fills and keyboard handling freezes with it. Lines are instead handed to
+//! a bounded queue drained by a dedicated writer thread. When the queue is full
+//! the oldest line is dropped, and once the consumer catches up it is told how
+//! many events it missed with a `DroppedEvents` event.
+//!
+//! By default every event is flushed as soon as it is written, since pipes can
+//! otherwise sit on buffered events on some platforms. High-rate streams can
+//! opt into coalescing with `--flush-interval-ms`, which batches everything
+//! queued within the interval into a single write.
+//!
+//! `--output` picks what stdout looks like. `json` (the default) is the one
+//! machine contract; socket clients and recordings always get JSON. `pretty`
+//! prints an aligned line per event for reading in a terminal, colored unless
+//! stdout isn't one or `NO_COLOR` is set. `csv` starts with a header and has
+//! fixed columns, every other field going into `fields` as JSON. Lines are
+//! converted by the writer thread, so capture doesn't pay for it.
+//!
+//! `msgpack` is for consumers that find JSON too slow at high event rates:
+//! each event is a MessagePack map of the same fields, serialized directly
+//! instead of as JSON, in a frame led by its length as a big-endian u32.
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+/// Lines buffered for a stalled consumer before the oldest start being dropped
+pub const OUTPUT_QUEUE_CAPACITY: usize = 4096;
+
+/// When queued lines are flushed to stdout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlushStrategy {
+    /// Write and flush each event as soon as it is queued
+    PerEvent,
+    /// Collect events for up to this long after the first one, then write them in one go
+    Interval(Duration),
+}
+
+impl FlushStrategy {
+    /// Name reported in the capabilities handshake
+    pub fn name(self) -> &'static str {
+        match self {
+            FlushStrategy::PerEvent => "per-event",
+            FlushStrategy::Interval(_) => "interval",
+        }
+    }
+}
+
+static FLUSH_STRATEGY: OnceLock<FlushStrategy> = OnceLock::new();
+
+/// What stdout lines look like
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Csv,
+    Pretty,
+    Msgpack,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 4] = ["json", "csv", "pretty", "msgpack"];
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "pretty" => Ok(Format::Pretty),
+            "msgpack" => Ok(Format::Msgpack),
+            _ => Err(format!("unknown output format {:?} (expected {})", name, Format::NAMES.join(", "))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        Format::NAMES[self as usize]
+    }
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+/// Cleared when the helper is loaded as a library and stdout belongs to the host
+static STDOUT_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Stop (or resume) writing events to stdout; subscribers still get them
+pub fn set_stdout_enabled(enabled: bool) {
+    STDOUT_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Choose the flush strategy; only effective before the first event is emitted
+pub fn set_flush_strategy(strategy: FlushStrategy) {
+    FLUSH_STRATEGY.set(strategy).ok();
+}
+
+pub fn flush_strategy() -> FlushStrategy {
+    *FLUSH_STRATEGY.get_or_init(|| FlushStrategy::PerEvent)
+}
+
+/// Choose the stdout format; only effective before the first event is emitted
+pub fn set_format(format: Format) {
+    FORMAT.set(format).ok();
+}
+
+pub fn format() -> Format {
+    *FORMAT.get_or_init(Format::default)
+}
+
+struct OutputQueue {
+    pending: VecDeque<Vec<u8>>,
+    /// Buffers returned by the writer, reused so steady-state output doesn't allocate
+    free: Vec<Vec<u8>>,
+    dropped: u64,
+    writing: bool,
+}
+
+struct Output {
+    queue: Mutex<OutputQueue>,
+    /// Signalled when lines are queued and whenever the writer finishes one
+    changed: Condvar,
+}
+
+impl Output {
+    fn lock(&self) -> MutexGuard<'_, OutputQueue> {
+        self.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn output() -> &'static Output {
+    static OUTPUT: OnceLock<Output> = OnceLock::new();
+    OUTPUT.get_or_init(|| {
+        thread::spawn(run_writer);
+        Output {
+            queue: Mutex::new(OutputQueue {
+                pending: VecDeque::with_capacity(OUTPUT_QUEUE_CAPACITY),
+                free: Vec::new(),
+                dropped: 0,
+                writing: false,
+            }),
+            changed: Condvar::new(),
+        }
+    })
+}
+
+/// Queue a serialized line (without trailing newline), or a MessagePack event, for stdout.
+/// Never blocks on the consumer.
+pub fn enqueue(line: &[u8]) {
+    if !STDOUT_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let output = output();
+    let mut queue = output.lock();
+
+    let mut buffer = if queue.pending.len() == OUTPUT_QUEUE_CAPACITY {
+        queue.dropped += 1;
+        DROPPED_TOTAL.fetch_add(1, Ordering::Relaxed);
+        queue.pending.pop_front().unwrap_or_default()
+    } else {
+        queue.free.pop().unwrap_or_default()
+    };
+    buffer.clear();
+    buffer.extend_from_slice(line);
+    queue.pending.push_back(buffer);
+
+    output.changed.notify_all();
+}
+
+/// Lines dropped for a stalled consumer since startup
+static DROPPED_TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// Events dropped because stdout wasn't read, since startup
+pub fn dropped() -> u64 {
+    DROPPED_TOTAL.load(Ordering::Relaxed)
+}
+
+/// Lines waiting for the writer thread
+pub fn queued() -> usize {
+    output().lock().pending.len()
+}
+
+/// Wait up to `timeout` for queued lines to reach stdout, e.g. right before exiting
+pub fn flush(timeout: Duration) {
+    let output = output();
+    let deadline = Instant::now() + timeout;
+    let mut queue = output.lock();
+    while !queue.pending.is_empty() || queue.writing {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return;
+        }
+        queue = output
+            .changed
+            .wait_timeout(queue, remaining)
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .0;
+    }
+}
+
+fn run_writer() {
+    let output = output();
+    let strategy = flush_strategy();
+    let mut renderer = Renderer::new(format());
+    let mut batch = Vec::with_capacity(4096);
+    loop {
+        {
+            let mut queue = output.lock();
+            while queue.pending.is_empty() {
+                queue = output
+                    .changed
+                    .wait(queue)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+
+            // Let more events accumulate, but never hold the first one longer than the interval
+            if let FlushStrategy::Interval(interval) = strategy {
+                let deadline = Instant::now() + interval;
+                loop {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        break;
+                    }
+                    queue = output
+                        .changed
+                        .wait_timeout(queue, remaining)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0;
+                }
+            }
+
+            let lines = match strategy {
+                FlushStrategy::PerEvent => 1,
+                FlushStrategy::Interval(_) => queue.pending.len(),
+            };
+            batch.clear();
+            for _ in 0..lines {
+                if let Some(line) = queue.pending.pop_front() {
+                    renderer.line(&line, &mut batch);
+                    queue.free.push(line);
+                }
+            }
+            queue.writing = true;
+        }
+
+        // A closed pipe means the consumer is gone; keep draining so capture never blocks
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&batch).and_then(|_| stdout.flush()).ok();
+        drop(stdout);
+
+        let caught_up_after_drops = {
+            let mut queue = output.lock();
+            queue.writing = false;
+            output.changed.notify_all();
+            if queue.pending.is_empty() && queue.dropped > 0 {
+                Some(std::mem::take(&mut queue.dropped))
+            } else {
+                None
+            }
+        };
+
+        // Emitted outside the queue lock: emit() takes the stream lock and then re-enters enqueue()
+        if let Some(count) = caught_up_after_drops {
+            eprintln!("stdout consumer stalled, dropped {} event(s)", count);
+            stream::emit(&Event::now(EventKind::DroppedEvents { count }));
+        }
+    }
+}
+
+/// Turns JSON lines into the `--output` format
+struct Renderer {
+    format: Format,
+    color: bool,
+    header_written: bool,
+}
+
+/// CSV columns before `fields`
+const CSV_COLUMNS: [&str; 8] = ["seq", "mono_us", "time", "event_type", "key", "name", "text", "synthetic"];
+
+impl Renderer {
+    fn new(format: Format) -> Self {
+        Renderer {
+            format,
+            color: io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            header_written: false,
+        }
+    }
+
+    /// Append `line`, newline included, to `out`
+    fn line(&mut self, line: &[u8], out: &mut Vec<u8>) {
+        if self.format == Format::Msgpack {
+            out.extend_from_slice(&(line.len() as u32).to_be_bytes());
+            out.extend_from_slice(line);
+            return;
+        }
+        let event = match self.format {
+            Format::Json | Format::Msgpack => None,
+            Format::Csv | Format::Pretty => serde_json::from_slice::<Map<String, Value>>(line).ok(),
+        };
+        match (self.format, event) {
+            (Format::Csv, Some(event)) => self.csv(event, out),
+            (Format::Pretty, Some(event)) => self.pretty(event, out),
+            // JSON, or a line that somehow isn't an object: passed on as is
+            _ => out.extend_from_slice(line),
+        }
+        out.push(b'\n');
+    }
+
+    fn csv(&mut self, mut event: Map<String, Value>, out: &mut Vec<u8>) {
+        if !self.header_written {
+            out.extend_from_slice(CSV_COLUMNS.join(",").as_bytes());
+            out.extend_from_slice(b",fields\n");
+            self.header_written = true;
+        }
+        let mut cells: Vec<String> = CSV_COLUMNS
+            .iter()
+            .map(|column| match event.remove(*column) {
+                Some(Value::String(text)) => text,
+                Some(Value::Null) | None => String::new(),
+                Some(value) if *column == "time" => time_text(&value),
+                Some(value) => value.to_string(),
+            })
+            .collect();
+        cells.push(if event.is_empty() { String::new() } else { Value::Object(event).to_string() });
+        let cells: Vec<String> = cells.iter().map(|cell| csv_escape(cell)).collect();
+        out.extend_from_slice(cells.join(",").as_bytes());
+    }
+
+    fn pretty(&self, mut event: Map<String, Value>, out: &mut Vec<u8>) {
+        let mono_us = event.remove("mono_us").and_then(|value| value.as_u64()).unwrap_or_default();
+        let seq = event.remove("seq").and_then(|value| value.as_u64()).unwrap_or_default();
+        event.remove("time");
+        let event_type = match event.remove("event_type") {
+            Some(Value::String(event_type)) => event_type,
+            _ => String::new(),
+        };
+        // Key events lead with the key, hotkey and hotstring events with their id
+        let subject = match event.remove("key").or_else(|| event.remove("id")) {
+            Some(Value::String(subject)) => subject,
+            _ => String::new(),
+        };
+        let fields: Vec<String> =
+            event.iter().map(|(name, value)| format!("{}={}", name, pretty_value(value))).collect();
+
+        let (on, off) = if self.color { (color(&event_type), "\x1b[0m") } else { ("", "") };
+        let text = format!(
+            "{:>5}.{:06} {:>6}  {}{:<20}{} {:<16} {}",
+            mono_us / 1_000_000,
+            mono_us % 1_000_000,
+            seq,
+            on,
+            event_type,
+            off,
+            subject,
+            fields.join(" ")
+        );
+        out.extend_from_slice(text.trim_end().as_bytes());
+    }
+}
+
+/// ANSI color for an event type: presses green, releases dim, hotkeys magenta, errors red
+fn color(event_type: &str) -> &'static str {
+    match event_type {
+        "KeyPress" => "\x1b[32m",
+        "KeyRelease" => "\x1b[2m",
+        "Error" | "DeviceFailed" | "DroppedEvents" => "\x1b[31m",
+        event_type if event_type.starts_with("Hotkey") || event_type.starts_with("Hotstring") => "\x1b[35m",
+        _ => "\x1b[33m",
+    }
+}
+
+/// Strings bare unless they need quoting, everything else as JSON
+fn pretty_value(value: &Value) -> String {
+    match value {
+        Value::String(text) if !text.is_empty() && !text.contains(|c: char| c.is_whitespace() || c == '"') => {
+            text.clone()
+        }
+        value => value.to_string(),
+    }
+}
+
+/// `time` as text: serde's `SystemTime` struct becomes seconds since the epoch
+fn time_text(time: &Value) -> String {
+    match (time["secs_since_epoch"].as_u64(), time["nanos_since_epoch"].as_u64()) {
+        (Some(secs), Some(nanos)) => format!("{}.{:09}", secs, nanos),
+        _ => time.to_string(),
+    }
+}
+
+fn csv_escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// Write one event line and flush it. Callers hold the writer's lock, so lines never interleave.
+pub fn write_line<W: Write + ?Sized>(out: &mut W, line: &[u8]) -> io::Result<()> {
+    out.write_all(line)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
@@ -0,0 +1,117 @@
+//! Exit when the spawning app goes away.
+//!
+//! If the Electron app crashes, a surviving listener would keep running forever
+//! holding /dev/input file descriptors. With `--parent-pid <pid>` the helper
+//! watches that process and exits as soon as it disappears. On Linux the kernel
+//! is also asked to deliver SIGTERM on parent death (PR_SET_PDEATHSIG), which
+//! covers the case where the watcher thread is starved. On Windows a parent
+//! that exists but can't be opened, such as an elevated one, is refused up
+//! front rather than taken for gone.
+
+use std::thread;
+use std::time::Duration;
+
+use crate::event::{Event, EventKind};
+use crate::journal;
+use crate::output;
+use crate::stream;
+
+/// How often the parent is polled on platforms without a blocking wait
+#[cfg(unix)]
+const PARENT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Start watching `parent_pid`, exiting the helper once it is gone
+#[cfg(unix)]
+pub fn watch(parent_pid: u32) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    set_parent_death_signal(parent_pid);
+
+    if !is_alive(parent_pid) {
+        exit_orphaned(parent_pid);
+    }
+
+    thread::spawn(move || {
+        wait_for_exit(parent_pid);
+        exit_orphaned(parent_pid);
+    });
+    Ok(())
+}
+
+/// Start watching `parent_pid`, exiting the helper once it is gone. The handle
+/// is opened here so a pid we may not wait on is an error, not an exit.
+#[cfg(windows)]
+pub fn watch(parent_pid: u32) -> Result<(), String> {
+    use windows_sys::Win32::Foundation::{CloseHandle, ERROR_INVALID_PARAMETER};
+    use windows_sys::Win32::System::Threading::{OpenProcess, WaitForSingleObject, INFINITE, PROCESS_SYNCHRONIZE};
+
+    let handle = unsafe { OpenProcess(PROCESS_SYNCHRONIZE, 0, parent_pid) };
+    if handle.is_null() {
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() == Some(ERROR_INVALID_PARAMETER as i32) {
+            exit_orphaned(parent_pid);
+        }
+        return Err(format!("Cannot watch --parent-pid {}: {}", parent_pid, error));
+    }
+
+    // HANDLE isn't Send; the thread owns it from here on
+    let handle = handle as usize;
+    thread::spawn(move || {
+        let handle = handle as windows_sys::Win32::Foundation::HANDLE;
+        unsafe {
+            WaitForSingleObject(handle, INFINITE);
+            CloseHandle(handle);
+        }
+        exit_orphaned(parent_pid);
+    });
+    Ok(())
+}
+
+fn exit_orphaned(parent_pid: u32) -> ! {
+    eprintln!("Parent process {} exited, shutting down", parent_pid);
+    stream::emit(&Event::now(EventKind::ParentExited { parent_pid }));
+    output::flush(Duration::from_millis(200));
+    journal::finish();
+    std::process::exit(0);
+}
+
+/// Only armed when `parent_pid` really is our parent; a wrapper shell in between
+/// would otherwise tie our lifetime to the wrapper instead of the app
+#[cfg(target_os = "linux")]
+fn set_parent_death_signal(parent_pid: u32) {
+    if unsafe { libc::getppid() } as u32 != parent_pid {
+        return;
+    }
+    if unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) } != 0 {
+        eprintln!("Failed to set PR_SET_PDEATHSIG: {}", std::io::Error::last_os_error());
+    }
+}
+
+#[cfg(unix)]
+pub fn is_alive(pid: u32) -> bool {
+    // Signal 0 only checks for existence; EPERM still means the process exists
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(unix)]
+fn wait_for_exit(pid: u32) {
+    while is_alive(pid) {
+        thread::sleep(PARENT_POLL_INTERVAL);
+    }
+}
+
+/// OpenProcess fails with ERROR_INVALID_PARAMETER only for a pid that doesn't
+/// exist; any other failure, such as ERROR_ACCESS_DENIED, means it does
+#[cfg(windows)]
+pub fn is_alive(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::{CloseHandle, ERROR_INVALID_PARAMETER, WAIT_TIMEOUT};
+    use windows_sys::Win32::System::Threading::{OpenProcess, WaitForSingleObject, PROCESS_SYNCHRONIZE};
+
+    let handle = unsafe { OpenProcess(PROCESS_SYNCHRONIZE, 0, pid) };
+    if handle.is_null() {
+        return std::io::Error::last_os_error().raw_os_error() != Some(ERROR_INVALID_PARAMETER as i32);
+    }
+    let alive = unsafe { WaitForSingleObject(handle, 0) } == WAIT_TIMEOUT;
+    unsafe { CloseHandle(handle) };
+    alive
+}
@@ -0,0 +1,114 @@
+//! `install-permissions`: input device access for the logged-in user,
+//! without `usermod -aG input` and a re-login.
+//!
+//!   install-permissions [--remove|--print]
+//!
+//! Installs a udev rule tagging keyboards and `/dev/uinput` with `uaccess`,
+//! so logind grants the user of the active local session an ACL on them
+//! (and takes it back when they switch away or log out). The rule is applied
+//! to devices already plugged in, so listening works straight away.
+//!
+//! Writing to `/etc/udev/rules.d` takes root: run as a user, the command runs
+//! itself again through `pkexec`, which asks for authentication under the
+//! `io.github.aj47.NvidiaCC.install-permissions` action that packages ship
+//! in `/usr/share/polkit-1/actions` (or polkit's generic one without it).
+//! root can't reach into the FUSE mount of an AppImage, so there `--print`
+//! gives the rule for `sudo tee` instead.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+pub const RULE_PATH: &str = "/etc/udev/rules.d/70-nvidia-cc.rules";
+
+/// Before 73-seat-late.rules, which turns the `uaccess` tag into ACLs
+pub const RULE: &str = r#"# Installed by nvidia-cc-rs install-permissions
+# Lets the user of the active local session read keyboards and create a virtual one
+SUBSYSTEM=="input", KERNEL=="event*", ENV{ID_INPUT_KEY}=="1", TAG+="uaccess"
+SUBSYSTEM=="misc", KERNEL=="uinput", OPTIONS+="static_node=uinput", TAG+="uaccess"
+"#;
+
+/// What pkexec exits with when authorization is dismissed, or refused
+const PKEXEC_DISMISSED: i32 = 126;
+const PKEXEC_NOT_AUTHORIZED: i32 = 127;
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Install the rule and apply it, elevating through pkexec unless already root
+pub fn install() -> Result<(), String> {
+    if !is_root() {
+        return elevate(&["install-permissions"]);
+    }
+    let path = Path::new(RULE_PATH);
+    if fs::read_to_string(path).is_ok_and(|installed| installed == RULE) {
+        eprintln!("{} is already installed", RULE_PATH);
+    } else {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        fs::write(path, RULE).map_err(|e| format!("Cannot write {}: {}", RULE_PATH, e))?;
+        eprintln!("Installed {}", RULE_PATH);
+    }
+    reload()
+}
+
+/// Remove the rule, and with it the access it gave
+pub fn remove() -> Result<(), String> {
+    if !is_root() {
+        return elevate(&["install-permissions", "--remove"]);
+    }
+    match fs::remove_file(RULE_PATH) {
+        Ok(()) => eprintln!("Removed {}", RULE_PATH),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(format!("{} isn't installed", RULE_PATH));
+        }
+        Err(e) => return Err(format!("Cannot remove {}: {}", RULE_PATH, e)),
+    }
+    reload()
+}
+
+/// Run this executable with `args` as root
+fn elevate(args: &[&str]) -> Result<(), String> {
+    let exe = std::env::current_exe().map_err(|e| format!("Cannot find this executable: {}", e))?;
+    let fallback = || format!("run `{} install-permissions --print | sudo tee {}`", exe.display(), RULE_PATH);
+    let status = match Command::new("pkexec").arg(&exe).args(args).status() {
+        Ok(status) => status,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(format!("pkexec isn't installed (it comes with polkit); {}", fallback()));
+        }
+        Err(e) => return Err(format!("Cannot run pkexec: {}", e)),
+    };
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(PKEXEC_DISMISSED) => Err("Authentication was dismissed".to_string()),
+        Some(PKEXEC_NOT_AUTHORIZED) => Err(format!(
+            "Not authorized, or root can't run {} (as in an AppImage); {}",
+            exe.display(),
+            fallback()
+        )),
+        _ => Err(format!("install-permissions as root failed ({})", status)),
+    }
+}
+
+fn udevadm(args: &[&str]) -> Result<(), String> {
+    let status = Command::new("udevadm")
+        .args(args)
+        .status()
+        .map_err(|e| format!("Cannot run udevadm: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("udevadm {} failed ({})", args.join(" "), status))
+    }
+}
+
+/// Have udev reread its rules and re-tag the devices already there
+fn reload() -> Result<(), String> {
+    udevadm(&["control", "--reload"])?;
+    udevadm(&["trigger", "--action=change", "--subsystem-match=input"])?;
+    udevadm(&["trigger", "--action=change", "--subsystem-match=misc", "--sysname-match=uinput"])?;
+    udevadm(&["settle"])
+}
@@ -0,0 +1,104 @@
+//! The replay capture backend: `listen --backend replay --input <file>`.
+//!
+//! Instead of reading keyboards, `listen` reads key events from a JSON lines
+//! file and runs them through everything a captured key goes through: the
+//! `[remap]` rules, privacy and rate limiting, the hotkey and hotstring
+//! engines and serialization. Nothing opens an input device, so hotkey flows
+//! can be tested in containers without `/dev/input`; hotstrings are reported
+//! but not typed.
+//!
+//! The file is what `listen` prints, so a captured session replays as is. Only
+//! `KeyPress` and `KeyRelease` lines count, and of those only `key` is
+//! required; `name`, `keysym`, `text`, `num_lock`, `scancode`, `synthetic` and
+//! `hold_ms` are passed on when present. Events are spaced out as their
+//! `mono_us` stamps were, or by `delay_ms` before the event in hand-written
+//! files, so holds and taps time out as they did. `listen` exits once the
+//! file is done. A `.zst` file is decompressed as it is read.
+
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::control;
+use crate::event::{Event, EventKind, Key};
+use crate::hotkeys;
+use crate::hotstrings;
+use crate::numpad;
+use crate::privacy;
+use crate::remap;
+use crate::synthetic;
+use crate::throttle;
+use crate::zstd;
+
+/// Feed a recorded key event through the pipeline, as the listener does with a captured one
+fn feed(pressed: bool, key: &str, recorded: &Value) {
+    if control::is_paused() {
+        return;
+    }
+    let synthetic = recorded["synthetic"] == true;
+    if synthetic && synthetic::suppress_self() {
+        return;
+    }
+    let name = remap::apply(&[], key);
+    let num_lock = recorded["num_lock"].as_bool();
+    let text = recorded["text"].as_str();
+    let key_event = Key {
+        key: &name,
+        name: recorded["name"].as_str(),
+        keysym: recorded["keysym"].as_str(),
+        scancode: recorded["scancode"].as_u64().and_then(|scancode| u32::try_from(scancode).ok()),
+        text,
+        num_lock,
+        synthetic,
+        hold_ms: recorded["hold_ms"].as_u64(),
+    };
+    let kind = if pressed { EventKind::KeyPress(key_event) } else { EventKind::KeyRelease(key_event) };
+    if throttle::admit() {
+        privacy::emit_key(&Event::now(kind));
+    }
+    if !synthetic {
+        hotkeys::observe(pressed, numpad::effective(&name, num_lock), hotkeys::now());
+        hotstrings::observe(pressed, &name, text);
+    }
+}
+
+/// Replay every key event in `path`, with its original spacing
+pub fn run(path: &Path) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let file: Box<dyn Read> = if zstd::is_compressed(path) {
+        Box::new(zstd::Decoder::new(file).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?)
+    } else {
+        Box::new(file)
+    };
+    // Hotstrings are matched, but typing their replacements would need a real keyboard
+    hotstrings::set_replace(false);
+    let mut last_mono_us = None;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let at = || format!("{}:{}", path.display(), index + 1);
+        let recorded: Value = serde_json::from_str(&line).map_err(|e| format!("{}: {}", at(), e))?;
+        let pressed = match recorded["event_type"].as_str() {
+            Some("KeyPress") => true,
+            Some("KeyRelease") => false,
+            _ => continue,
+        };
+        let key = recorded["key"].as_str().ok_or_else(|| format!("{}: key event without a key", at()))?;
+
+        let mono_us = recorded["mono_us"].as_u64();
+        let delay = match (recorded["delay_ms"].as_u64(), last_mono_us, mono_us) {
+            (Some(delay_ms), _, _) => Duration::from_millis(delay_ms),
+            (None, Some(last), Some(mono_us)) => Duration::from_micros(mono_us.saturating_sub(last)),
+            _ => Duration::ZERO,
+        };
+        last_mono_us = mono_us.or(last_mono_us);
+        thread::sleep(delay);
+        feed(pressed, key, &recorded);
+    }
+    Ok(())
+}
@@ -0,0 +1,840 @@
+//! The desktop portals, for Flatpak and Snap builds (Linux), which can't
+//! reach /dev/input or uinput: hotkeys come through the GlobalShortcuts
+//! portal and injection goes through the RemoteDesktop portal's
+//! `NotifyKeyboardKeycode`.
+//!
+//! [`confined`] says when that applies: inside a Flatpak (`/.flatpak-info`,
+//! `FLATPAK_ID`) or Snap (`SNAP`) sandbox with no input device to open, since
+//! `--device=all` and Snap's `raw-input` interface bring the devices back.
+//! `listen` then binds the press-mode hotkeys (see [`crate::hotkeys`]) as
+//! portal shortcuts, proposing their keys as the trigger, and emits
+//! `HotkeyTriggered`/`HotkeyReleased` as the portal activates them. The
+//! desktop lets the user confirm or change the triggers, and no other key
+//! events are seen; hotkeys of other modes, and those added after startup,
+//! aren't bound.
+//!
+//! The `portal` injection backend then goes first in the chain (see
+//! [`crate::backend`]). Its RemoteDesktop session is started on first use
+//! and kept for the process, so the desktop asks once; it persists until
+//! revoked, its restore token kept in the data directory, so later runs
+//! don't ask again where the desktop supports that. The pointer only moves
+//! relatively, as with uinput.
+//!
+//! libdbus is loaded when a portal is first used, as for `--dbus`.
+
+use libloading::Library;
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::event::{Event, EventKind};
+use crate::hotkeys;
+use crate::stream;
+
+const LIBRARY_NAMES: &[&str] = &["libdbus-1.so.3", "libdbus-1.so"];
+
+const DESTINATION: &CStr = c"org.freedesktop.portal.Desktop";
+const OBJECT_PATH: &CStr = c"/org/freedesktop/portal/desktop";
+const GLOBAL_SHORTCUTS: &str = "org.freedesktop.portal.GlobalShortcuts";
+const REMOTE_DESKTOP: &str = "org.freedesktop.portal.RemoteDesktop";
+const REQUEST: &str = "org.freedesktop.portal.Request";
+
+/// Method calls answer straight away; what needs the user comes later as a `Response`
+const CALL_TIMEOUT_MS: c_int = 25_000;
+
+/// Asking a portal's version only needs it started, which shouldn't hold up startup for long
+const PROBE_TIMEOUT_MS: c_int = 5_000;
+
+/// How long the desktop's dialogs may wait for the user
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// `RemoteDesktop` device types
+const DEVICE_KEYBOARD: u32 = 1;
+const DEVICE_POINTER: u32 = 2;
+
+/// `persist_mode`: until the user revokes it
+const PERSIST_UNTIL_REVOKED: u32 = 2;
+
+/// `NotifyPointerAxisDiscrete` axes
+const AXIS_VERTICAL: u32 = 0;
+const AXIS_HORIZONTAL: u32 = 1;
+
+/// `DBusBusType`
+const BUS_SESSION: c_int = 0;
+/// `DBUS_MESSAGE_TYPE_SIGNAL`
+const SIGNAL: c_int = 4;
+const TYPE_INVALID: c_int = 0;
+const TYPE_STRING: c_int = b's' as c_int;
+const TYPE_OBJECT_PATH: c_int = b'o' as c_int;
+const TYPE_BOOLEAN: c_int = b'b' as c_int;
+const TYPE_INT32: c_int = b'i' as c_int;
+const TYPE_UINT32: c_int = b'u' as c_int;
+const TYPE_UINT64: c_int = b't' as c_int;
+const TYPE_DOUBLE: c_int = b'd' as c_int;
+const TYPE_VARIANT: c_int = b'v' as c_int;
+const TYPE_ARRAY: c_int = b'a' as c_int;
+const TYPE_STRUCT: c_int = b'r' as c_int;
+const TYPE_DICT_ENTRY: c_int = b'e' as c_int;
+
+/// Request and session tokens, unique within the process
+static NEXT_TOKEN: AtomicU64 = AtomicU64::new(1);
+
+/// The RemoteDesktop session injection goes through, once started
+static SESSION: Mutex<Option<RemoteDesktop>> = Mutex::new(None);
+
+/// `DBusError`
+#[repr(C)]
+struct Error {
+    name: *const c_char,
+    message: *const c_char,
+    dummy: c_uint,
+    padding: *mut c_void,
+}
+
+/// `DBusMessageIter`: opaque, 72 bytes on 64-bit targets, with room to spare
+#[repr(C)]
+struct Iter([usize; 10]);
+
+impl Iter {
+    fn new() -> Iter {
+        Iter([0; 10])
+    }
+}
+
+type Connection = c_void;
+type Message = c_void;
+
+struct Api {
+    error_init: unsafe extern "C" fn(*mut Error),
+    error_free: unsafe extern "C" fn(*mut Error),
+    bus_get_private: unsafe extern "C" fn(c_int, *mut Error) -> *mut Connection,
+    bus_add_match: unsafe extern "C" fn(*mut Connection, *const c_char, *mut Error),
+    connection_close: unsafe extern "C" fn(*mut Connection),
+    connection_unref: unsafe extern "C" fn(*mut Connection),
+    read_write: unsafe extern "C" fn(*mut Connection, c_int) -> u32,
+    pop_message: unsafe extern "C" fn(*mut Connection) -> *mut Message,
+    send_with_reply_and_block: unsafe extern "C" fn(*mut Connection, *mut Message, c_int, *mut Error) -> *mut Message,
+    message_new_method_call:
+        unsafe extern "C" fn(*const c_char, *const c_char, *const c_char, *const c_char) -> *mut Message,
+    message_get_type: unsafe extern "C" fn(*mut Message) -> c_int,
+    message_get_interface: unsafe extern "C" fn(*mut Message) -> *const c_char,
+    message_get_member: unsafe extern "C" fn(*mut Message) -> *const c_char,
+    message_get_path: unsafe extern "C" fn(*mut Message) -> *const c_char,
+    message_unref: unsafe extern "C" fn(*mut Message),
+    iter_init: unsafe extern "C" fn(*mut Message, *mut Iter) -> u32,
+    iter_init_append: unsafe extern "C" fn(*mut Message, *mut Iter),
+    iter_append_basic: unsafe extern "C" fn(*mut Iter, c_int, *const c_void) -> u32,
+    iter_open_container: unsafe extern "C" fn(*mut Iter, c_int, *const c_char, *mut Iter) -> u32,
+    iter_close_container: unsafe extern "C" fn(*mut Iter, *mut Iter) -> u32,
+    iter_get_arg_type: unsafe extern "C" fn(*mut Iter) -> c_int,
+    iter_get_element_type: unsafe extern "C" fn(*mut Iter) -> c_int,
+    iter_get_basic: unsafe extern "C" fn(*mut Iter, *mut c_void),
+    iter_next: unsafe extern "C" fn(*mut Iter) -> u32,
+    iter_recurse: unsafe extern "C" fn(*mut Iter, *mut Iter),
+    /// Keeps the functions above loaded
+    _library: Library,
+}
+
+impl Api {
+    unsafe fn load() -> Result<Api, String> {
+        let library = LIBRARY_NAMES
+            .iter()
+            .find_map(|name| Library::new(name).ok())
+            .ok_or_else(|| format!("The desktop portals need libdbus ({})", LIBRARY_NAMES[0]))?;
+        macro_rules! symbol {
+            ($name:literal) => {
+                *library
+                    .get(concat!($name, "\0").as_bytes())
+                    .map_err(|e| format!("libdbus lacks {}: {}", $name, e))?
+            };
+        }
+        Ok(Api {
+            error_init: symbol!("dbus_error_init"),
+            error_free: symbol!("dbus_error_free"),
+            bus_get_private: symbol!("dbus_bus_get_private"),
+            bus_add_match: symbol!("dbus_bus_add_match"),
+            connection_close: symbol!("dbus_connection_close"),
+            connection_unref: symbol!("dbus_connection_unref"),
+            read_write: symbol!("dbus_connection_read_write"),
+            pop_message: symbol!("dbus_connection_pop_message"),
+            send_with_reply_and_block: symbol!("dbus_connection_send_with_reply_and_block"),
+            message_new_method_call: symbol!("dbus_message_new_method_call"),
+            message_get_type: symbol!("dbus_message_get_type"),
+            message_get_interface: symbol!("dbus_message_get_interface"),
+            message_get_member: symbol!("dbus_message_get_member"),
+            message_get_path: symbol!("dbus_message_get_path"),
+            message_unref: symbol!("dbus_message_unref"),
+            iter_init: symbol!("dbus_message_iter_init"),
+            iter_init_append: symbol!("dbus_message_iter_init_append"),
+            iter_append_basic: symbol!("dbus_message_iter_append_basic"),
+            iter_open_container: symbol!("dbus_message_iter_open_container"),
+            iter_close_container: symbol!("dbus_message_iter_close_container"),
+            iter_get_arg_type: symbol!("dbus_message_iter_get_arg_type"),
+            iter_get_element_type: symbol!("dbus_message_iter_get_element_type"),
+            iter_get_basic: symbol!("dbus_message_iter_get_basic"),
+            iter_next: symbol!("dbus_message_iter_next"),
+            iter_recurse: symbol!("dbus_message_iter_recurse"),
+            _library: library,
+        })
+    }
+
+    /// A fresh `DBusError`
+    fn error(&self) -> Error {
+        let mut error = Error {
+            name: ptr::null(),
+            message: ptr::null(),
+            dummy: 0,
+            padding: ptr::null_mut(),
+        };
+        unsafe { (self.error_init)(&mut error) };
+        error
+    }
+
+    /// The message of `error` if set, freeing it
+    fn take_error(&self, error: &mut Error) -> Option<String> {
+        if error.name.is_null() {
+            return None;
+        }
+        let message = unsafe { text(error.message) }.unwrap_or_default();
+        unsafe { (self.error_free)(error) };
+        Some(message)
+    }
+
+    /// Append `value` at `iter`; false when libdbus runs out of memory
+    fn append(&self, iter: &mut Iter, value: &Value) -> bool {
+        let basic = |iter: &mut Iter, kind: c_int, pointer: *const c_void| unsafe {
+            (self.iter_append_basic)(iter, kind, pointer) != 0
+        };
+        let string = |iter: &mut Iter, kind: c_int, value: &str| {
+            let Ok(value) = CString::new(value) else { return false };
+            let pointer = value.as_ptr();
+            basic(iter, kind, (&pointer as *const *const c_char).cast())
+        };
+        match value {
+            Value::Str(value) => string(iter, TYPE_STRING, value),
+            Value::Path(value) => string(iter, TYPE_OBJECT_PATH, value),
+            Value::Bool(value) => basic(iter, TYPE_BOOLEAN, (&u32::from(*value) as *const u32).cast()),
+            Value::I32(value) => basic(iter, TYPE_INT32, (value as *const i32).cast()),
+            Value::U32(value) => basic(iter, TYPE_UINT32, (value as *const u32).cast()),
+            Value::U64(value) => basic(iter, TYPE_UINT64, (value as *const u64).cast()),
+            Value::F64(value) => basic(iter, TYPE_DOUBLE, (value as *const f64).cast()),
+            Value::Dict(entries) => self.container(iter, TYPE_ARRAY, Some("{sv}"), |array| {
+                entries.iter().all(|(key, value)| {
+                    self.container(array, TYPE_DICT_ENTRY, None, |entry| {
+                        string(entry, TYPE_STRING, key)
+                            && self.container(entry, TYPE_VARIANT, Some(&value.signature()), |variant| {
+                                self.append(variant, value)
+                            })
+                    })
+                })
+            }),
+            Value::Array(signature, values) => self.container(iter, TYPE_ARRAY, Some(signature), |array| {
+                values.iter().all(|value| self.append(array, value))
+            }),
+            Value::Struct(fields) => self.container(iter, TYPE_STRUCT, None, |fields_iter| {
+                fields.iter().all(|field| self.append(fields_iter, field))
+            }),
+            Value::Other => false,
+        }
+    }
+
+    /// Open a container at `iter`, fill it with `fill` and close it
+    fn container(
+        &self,
+        iter: &mut Iter,
+        kind: c_int,
+        signature: Option<&str>,
+        fill: impl FnOnce(&mut Iter) -> bool,
+    ) -> bool {
+        let signature = signature.map(|signature| CString::new(signature).unwrap_or_default());
+        let signature = signature.as_ref().map_or(ptr::null(), |signature| signature.as_ptr());
+        let mut sub = Iter::new();
+        if unsafe { (self.iter_open_container)(iter, kind, signature, &mut sub) } == 0 {
+            return false;
+        }
+        let filled = fill(&mut sub);
+        unsafe { (self.iter_close_container)(iter, &mut sub) != 0 && filled }
+    }
+
+    /// The value at `iter`
+    fn read(&self, iter: &mut Iter) -> Value {
+        macro_rules! basic {
+            ($type:ty) => {{
+                let mut value: $type = Default::default();
+                unsafe { (self.iter_get_basic)(iter, (&mut value as *mut $type).cast()) };
+                value
+            }};
+        }
+        match unsafe { (self.iter_get_arg_type)(iter) } {
+            TYPE_STRING | TYPE_OBJECT_PATH => {
+                let mut value: *const c_char = ptr::null();
+                unsafe { (self.iter_get_basic)(iter, (&mut value as *mut *const c_char).cast()) };
+                Value::Str(unsafe { text(value) }.unwrap_or_default())
+            }
+            TYPE_BOOLEAN => Value::Bool(basic!(u32) != 0),
+            TYPE_INT32 => Value::I32(basic!(i32)),
+            TYPE_UINT32 => Value::U32(basic!(u32)),
+            TYPE_UINT64 => Value::U64(basic!(u64)),
+            TYPE_DOUBLE => Value::F64(basic!(f64)),
+            TYPE_VARIANT => {
+                let mut inner = Iter::new();
+                unsafe { (self.iter_recurse)(iter, &mut inner) };
+                self.read(&mut inner)
+            }
+            TYPE_ARRAY => {
+                let dict = unsafe { (self.iter_get_element_type)(iter) } == TYPE_DICT_ENTRY;
+                let mut elements = Iter::new();
+                unsafe { (self.iter_recurse)(iter, &mut elements) };
+                let mut values = Vec::new();
+                let mut entries = Vec::new();
+                while unsafe { (self.iter_get_arg_type)(&mut elements) } != TYPE_INVALID {
+                    if dict {
+                        let mut entry = Iter::new();
+                        unsafe { (self.iter_recurse)(&mut elements, &mut entry) };
+                        let key = self.read(&mut entry);
+                        unsafe { (self.iter_next)(&mut entry) };
+                        if let Value::Str(key) = key {
+                            entries.push((key, self.read(&mut entry)));
+                        }
+                    } else {
+                        values.push(self.read(&mut elements));
+                    }
+                    unsafe { (self.iter_next)(&mut elements) };
+                }
+                if dict {
+                    Value::Dict(entries)
+                } else {
+                    Value::Array(String::new(), values)
+                }
+            }
+            TYPE_STRUCT => {
+                let mut fields = Iter::new();
+                unsafe { (self.iter_recurse)(iter, &mut fields) };
+                Value::Struct(self.read_all(&mut fields))
+            }
+            _ => Value::Other,
+        }
+    }
+
+    /// The values from `iter` on
+    fn read_all(&self, iter: &mut Iter) -> Vec<Value> {
+        let mut values = Vec::new();
+        while unsafe { (self.iter_get_arg_type)(iter) } != TYPE_INVALID {
+            values.push(self.read(iter));
+            unsafe { (self.iter_next)(iter) };
+        }
+        values
+    }
+
+    /// The arguments of `message`
+    fn arguments(&self, message: *mut Message) -> Vec<Value> {
+        let mut iter = Iter::new();
+        if unsafe { (self.iter_init)(message, &mut iter) } == 0 {
+            return Vec::new();
+        }
+        self.read_all(&mut iter)
+    }
+}
+
+fn api() -> Result<&'static Api, String> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(|| unsafe { Api::load() }).as_ref().map_err(String::clone)
+}
+
+/// A C string from libdbus, if not null
+unsafe fn text(pointer: *const c_char) -> Option<String> {
+    (!pointer.is_null()).then(|| CStr::from_ptr(pointer).to_string_lossy().into_owned())
+}
+
+/// A D-Bus value, of the types the portals use
+#[derive(Clone, PartialEq)]
+enum Value {
+    Str(String),
+    /// An object path; read back as `Str`
+    Path(String),
+    Bool(bool),
+    I32(i32),
+    U32(u32),
+    U64(u64),
+    F64(f64),
+    /// `a{sv}`
+    Dict(Vec<(String, Value)>),
+    /// Elements of the given signature; read back with it empty
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    /// Anything else, when reading
+    Other,
+}
+
+impl Value {
+    fn signature(&self) -> String {
+        match self {
+            Value::Str(_) => "s".to_string(),
+            Value::Path(_) => "o".to_string(),
+            Value::Bool(_) => "b".to_string(),
+            Value::I32(_) => "i".to_string(),
+            Value::U32(_) => "u".to_string(),
+            Value::U64(_) => "t".to_string(),
+            Value::F64(_) => "d".to_string(),
+            Value::Dict(_) => "a{sv}".to_string(),
+            Value::Array(signature, _) => format!("a{}", signature),
+            Value::Struct(fields) => format!("({})", fields.iter().map(Value::signature).collect::<String>()),
+            Value::Other => String::new(),
+        }
+    }
+
+    fn str(&self) -> Option<&str> {
+        match self {
+            Value::Str(value) | Value::Path(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The entry `key` of a dict
+    fn get(&self, key: &str) -> Option<&Value> {
+        let Value::Dict(entries) = self else { return None };
+        entries.iter().find(|(name, _)| name == key).map(|(_, value)| value)
+    }
+}
+
+/// A token for `handle_token` and `session_handle_token`
+fn token() -> String {
+    format!("nvidia_cc_{}_{}", std::process::id(), NEXT_TOKEN.fetch_add(1, Ordering::Relaxed))
+}
+
+/// A private connection to the session bus
+struct Bus {
+    api: &'static Api,
+    connection: *mut Connection,
+}
+
+// Each connection is only used by one thread at a time: the capture thread, or under `SESSION`'s lock
+unsafe impl Send for Bus {}
+
+impl Bus {
+    fn connect() -> Result<Bus, String> {
+        let api = api()?;
+        let mut error = api.error();
+        let connection = unsafe { (api.bus_get_private)(BUS_SESSION, &mut error) };
+        if let Some(message) = api.take_error(&mut error) {
+            return Err(format!("Cannot connect to the session bus: {}", message));
+        }
+        if connection.is_null() {
+            return Err("Cannot connect to the session bus".to_string());
+        }
+        let bus = Bus { api, connection };
+        bus.add_match(REQUEST)?;
+        Ok(bus)
+    }
+
+    /// Receive the signals of `interface`
+    fn add_match(&self, interface: &str) -> Result<(), String> {
+        let rule = CString::new(format!("type='signal',interface='{}'", interface)).unwrap_or_default();
+        let mut error = self.api.error();
+        unsafe { (self.api.bus_add_match)(self.connection, rule.as_ptr(), &mut error) };
+        match self.api.take_error(&mut error) {
+            Some(message) => Err(format!("Cannot watch {} signals: {}", interface, message)),
+            None => Ok(()),
+        }
+    }
+
+    /// Call `interface.method` on the portal, returning what it returns
+    fn call(&self, interface: &str, method: &str, args: &[Value]) -> Result<Vec<Value>, String> {
+        self.call_within(interface, method, args, CALL_TIMEOUT_MS)
+    }
+
+    fn call_within(
+        &self,
+        interface: &str,
+        method: &str,
+        args: &[Value],
+        timeout_ms: c_int,
+    ) -> Result<Vec<Value>, String> {
+        let failed = |reason: String| format!("{}.{} failed: {}", interface, method, reason);
+        let c_interface = CString::new(interface).unwrap_or_default();
+        let c_method = CString::new(method).unwrap_or_default();
+        let (destination, path) = (DESTINATION.as_ptr(), OBJECT_PATH.as_ptr());
+        let api = self.api;
+        let message =
+            unsafe { (api.message_new_method_call)(destination, path, c_interface.as_ptr(), c_method.as_ptr()) };
+        if message.is_null() {
+            return Err(failed("out of memory".to_string()));
+        }
+        let mut iter = Iter::new();
+        unsafe { (api.iter_init_append)(message, &mut iter) };
+        let appended = args.iter().all(|arg| api.append(&mut iter, arg));
+        let mut error = api.error();
+        let reply = if appended {
+            unsafe { (api.send_with_reply_and_block)(self.connection, message, timeout_ms, &mut error) }
+        } else {
+            ptr::null_mut()
+        };
+        unsafe { (api.message_unref)(message) };
+        if let Some(message) = api.take_error(&mut error) {
+            return Err(failed(message));
+        }
+        if reply.is_null() {
+            return Err(failed("no reply".to_string()));
+        }
+        let values = api.arguments(reply);
+        unsafe { (api.message_unref)(reply) };
+        Ok(values)
+    }
+
+    /// Call a method answering through a `Request` object, with `handle_token` and `options` as its last
+    /// argument, and wait for the results of its `Response`
+    fn request(
+        &self,
+        interface: &str,
+        method: &str,
+        mut args: Vec<Value>,
+        mut options: Vec<(String, Value)>,
+    ) -> Result<Value, String> {
+        options.push(("handle_token".to_string(), Value::Str(token())));
+        args.push(Value::Dict(options));
+        let reply = self.call(interface, method, &args)?;
+        let handle = reply
+            .first()
+            .and_then(Value::str)
+            .ok_or_else(|| format!("{}.{} returned no request", interface, method))?;
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(format!("{}.{} was never answered", interface, method));
+            }
+            let (member, path, args) = self.next_signal(REQUEST, Some(timeout))?;
+            if member != "Response" || path != handle {
+                continue;
+            }
+            return match (args.first(), args.get(1)) {
+                (Some(Value::U32(0)), Some(results)) => Ok(results.clone()),
+                (Some(Value::U32(1)), _) => Err(format!("{}.{} was cancelled by the user", interface, method)),
+                _ => Err(format!("{}.{} failed", interface, method)),
+            };
+        }
+    }
+
+    /// The next signal of `interface` as its member, object path and arguments, waiting up to `timeout`
+    fn next_signal(&self, interface: &str, timeout: Option<Duration>) -> Result<(String, String, Vec<Value>), String> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let api = self.api;
+        loop {
+            let message = unsafe { (api.pop_message)(self.connection) };
+            if message.is_null() {
+                let wait = match deadline {
+                    Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                    None => Duration::from_secs(60),
+                };
+                if deadline.is_some() && wait.is_zero() {
+                    return Err(format!("No {} signal came", interface));
+                }
+                let wait_ms = wait.as_millis().clamp(1, c_int::MAX as u128) as c_int;
+                if unsafe { (api.read_write)(self.connection, wait_ms) } == 0 {
+                    return Err("The session bus connection closed".to_string());
+                }
+                continue;
+            }
+            let matches = unsafe { (api.message_get_type)(message) } == SIGNAL
+                && unsafe { text((api.message_get_interface)(message)) }.as_deref() == Some(interface);
+            let signal = matches.then(|| unsafe {
+                let member = text((api.message_get_member)(message)).unwrap_or_default();
+                let path = text((api.message_get_path)(message)).unwrap_or_default();
+                (member, path, api.arguments(message))
+            });
+            unsafe { (api.message_unref)(message) };
+            if let Some(signal) = signal {
+                return Ok(signal);
+            }
+        }
+    }
+
+    /// Create a session of `interface`, returning its handle
+    fn create_session(&self, interface: &str) -> Result<String, String> {
+        let options = vec![("session_handle_token".to_string(), Value::Str(token()))];
+        let results = self.request(interface, "CreateSession", Vec::new(), options)?;
+        results
+            .get("session_handle")
+            .and_then(Value::str)
+            .map(str::to_string)
+            .ok_or_else(|| format!("{}.CreateSession returned no session", interface))
+    }
+
+    /// The `version` property of the portal `interface`
+    fn version(&self, interface: &str) -> Result<u32, String> {
+        let args = [Value::Str(interface.to_string()), Value::Str("version".to_string())];
+        match self.call_within("org.freedesktop.DBus.Properties", "Get", &args, PROBE_TIMEOUT_MS)?.first() {
+            Some(Value::U32(version)) => Ok(*version),
+            _ => Err(format!("{} has no version", interface)),
+        }
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        unsafe {
+            (self.api.connection_close)(self.connection);
+            (self.api.connection_unref)(self.connection);
+        }
+    }
+}
+
+/// The sandbox the helper runs in, `Flatpak` or `Snap`, if any
+pub fn sandbox() -> Option<&'static str> {
+    if Path::new("/.flatpak-info").exists() || std::env::var_os("FLATPAK_ID").is_some() {
+        Some("Flatpak")
+    } else if std::env::var_os("SNAP").is_some() {
+        Some("Snap")
+    } else {
+        None
+    }
+}
+
+/// The sandbox the helper runs confined in, `Flatpak` or `Snap`, when there's no input device to open
+pub fn confined() -> Option<&'static str> {
+    static CONFINED: OnceLock<Option<&'static str>> = OnceLock::new();
+    *CONFINED.get_or_init(|| {
+        let sandbox = sandbox()?;
+        let device_readable = fs::read_dir("/dev/input").is_ok_and(|entries| {
+            entries.filter_map(|entry| entry.ok()).any(|entry| {
+                entry.file_name().to_string_lossy().starts_with("event") && fs::File::open(entry.path()).is_ok()
+            })
+        });
+        (!device_readable).then_some(sandbox)
+    })
+}
+
+/// Bind the press-mode hotkeys as GlobalShortcuts and report their activations; returns why that stopped
+pub fn capture(sandbox: &str) -> String {
+    match bind_and_watch(sandbox) {
+        Ok(()) => "The GlobalShortcuts session ended".to_string(),
+        Err(e) => e,
+    }
+}
+
+fn bind_and_watch(sandbox: &str) -> Result<(), String> {
+    let bus = Bus::connect()?;
+    bus.add_match(GLOBAL_SHORTCUTS)?;
+    let session = bus.create_session(GLOBAL_SHORTCUTS)?;
+    let bindings = hotkeys::press_bindings();
+    let shortcuts = bindings
+        .iter()
+        .map(|(id, keys)| {
+            let mut options = vec![("description".to_string(), Value::Str(id.clone()))];
+            if let Some(trigger) = trigger(keys) {
+                options.push(("preferred_trigger".to_string(), Value::Str(trigger)));
+            }
+            Value::Struct(vec![Value::Str(id.clone()), Value::Dict(options)])
+        })
+        .collect();
+    let args = vec![
+        Value::Path(session.clone()),
+        Value::Array("(sa{sv})".to_string(), shortcuts),
+        Value::Str(String::new()),
+    ];
+    bus.request(GLOBAL_SHORTCUTS, "BindShortcuts", args, Vec::new())?;
+    eprintln!(
+        "Listening through the GlobalShortcuts portal ({} has no input devices): {} hotkey(s) bound",
+        sandbox,
+        bindings.len()
+    );
+
+    // Hotkeys that fired, so a release is only reported after its press
+    let mut down: Vec<String> = Vec::new();
+    loop {
+        let (member, _, args) = bus.next_signal(GLOBAL_SHORTCUTS, None)?;
+        if args.first().and_then(Value::str) != Some(session.as_str()) {
+            continue;
+        }
+        let Some(id) = args.get(1).and_then(Value::str).map(str::to_string) else { continue };
+        let kind = match member.as_str() {
+            "Activated" if !down.contains(&id) && hotkeys::allowed_now(&id) => {
+                down.push(id.clone());
+                EventKind::HotkeyTriggered { id }
+            }
+            "Deactivated" if down.contains(&id) => {
+                down.retain(|other| *other != id);
+                EventKind::HotkeyReleased { id }
+            }
+            _ => continue,
+        };
+        stream::emit(&Event::now(kind));
+    }
+}
+
+/// `keys` as a trigger of the shortcuts spec (`CTRL+SHIFT+a`), when they are modifiers and one key it names
+fn trigger(keys: &[String]) -> Option<String> {
+    let mut modifiers: Vec<String> = Vec::new();
+    let mut key = None;
+    for name in keys {
+        let modifier = match name.as_str() {
+            "ControlLeft" | "ControlRight" => "CTRL",
+            "ShiftLeft" | "ShiftRight" => "SHIFT",
+            "Alt" | "AltGr" => "ALT",
+            "MetaLeft" | "MetaRight" => "LOGO",
+            _ => {
+                if key.replace(keysym(name)?).is_some() {
+                    return None;
+                }
+                continue;
+            }
+        };
+        if !modifiers.iter().any(|other| other == modifier) {
+            modifiers.push(modifier.to_string());
+        }
+    }
+    modifiers.push(key?);
+    Some(modifiers.join("+"))
+}
+
+/// The XKB keysym name of the key with emitted name `name`
+fn keysym(name: &str) -> Option<String> {
+    let single = |rest: &str, valid: fn(&u8) -> bool| rest.len() == 1 && valid(&rest.as_bytes()[0]);
+    if let Some(letter) = name.strip_prefix("Key").filter(|letter| single(letter, u8::is_ascii_uppercase)) {
+        return Some(letter.to_ascii_lowercase());
+    }
+    if let Some(digit) = name.strip_prefix("Num").filter(|digit| single(digit, u8::is_ascii_digit)) {
+        return Some(digit.to_string());
+    }
+    let function_key = |number: &str| number.parse::<u8>().is_ok_and(|number| (1..=24).contains(&number));
+    if name.strip_prefix('F').is_some_and(function_key) {
+        return Some(name.to_string());
+    }
+    let keysym = match name {
+        "Escape" | "Tab" | "Return" | "Delete" | "Insert" | "Home" | "End" | "Pause" => name,
+        "Space" => "space",
+        "Backspace" => "BackSpace",
+        "PageUp" => "Page_Up",
+        "PageDown" => "Page_Down",
+        "UpArrow" => "Up",
+        "DownArrow" => "Down",
+        "LeftArrow" => "Left",
+        "RightArrow" => "Right",
+        "PrintScreen" => "Print",
+        "Minus" => "minus",
+        "Equal" => "equal",
+        "LeftBracket" => "bracketleft",
+        "RightBracket" => "bracketright",
+        "BackSlash" => "backslash",
+        "SemiColon" => "semicolon",
+        "Quote" => "apostrophe",
+        "BackQuote" => "grave",
+        "Comma" => "comma",
+        "Dot" => "period",
+        "Slash" => "slash",
+        _ => return None,
+    };
+    Some(keysym.to_string())
+}
+
+/// A started RemoteDesktop session
+struct RemoteDesktop {
+    bus: Bus,
+    session: String,
+}
+
+impl RemoteDesktop {
+    fn start() -> Result<RemoteDesktop, String> {
+        let bus = Bus::connect()?;
+        let session = bus.create_session(REMOTE_DESKTOP)?;
+        let mut options = vec![
+            ("types".to_string(), Value::U32(DEVICE_KEYBOARD | DEVICE_POINTER)),
+            ("persist_mode".to_string(), Value::U32(PERSIST_UNTIL_REVOKED)),
+        ];
+        let token_path = restore_token_path();
+        if let Some(token) = token_path.as_ref().and_then(|path| fs::read_to_string(path).ok()) {
+            options.push(("restore_token".to_string(), Value::Str(token.trim().to_string())));
+        }
+        bus.request(REMOTE_DESKTOP, "SelectDevices", vec![Value::Path(session.clone())], options)?;
+        let args = vec![Value::Path(session.clone()), Value::Str(String::new())];
+        let results = bus.request(REMOTE_DESKTOP, "Start", args, Vec::new())?;
+        // A token is single use; the one for the next run comes with each start
+        if let (Some(path), Some(token)) = (token_path, results.get("restore_token").and_then(Value::str)) {
+            let saved = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|()| fs::write(&path, token));
+            if let Err(e) = saved {
+                eprintln!("Warning: cannot save the RemoteDesktop restore token to {}: {}", path.display(), e);
+            }
+        }
+        eprintln!("Injecting through the RemoteDesktop portal");
+        Ok(RemoteDesktop { bus, session })
+    }
+
+    /// Call `method` of the session with no options and `args`
+    fn notify(&self, method: &str, args: Vec<Value>) -> Result<(), String> {
+        let mut all = vec![Value::Path(self.session.clone()), Value::Dict(Vec::new())];
+        all.extend(args);
+        self.bus.call(REMOTE_DESKTOP, method, &all).map(|_| ())
+    }
+}
+
+fn restore_token_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("nvidia-cc").join("portal-restore-token"))
+}
+
+/// Run `f` on the RemoteDesktop session, starting it first if need be; a failure drops the session, so the
+/// next use starts another
+fn with_session(f: impl FnOnce(&RemoteDesktop) -> Result<(), String>) -> Result<(), String> {
+    let mut session = SESSION.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if session.is_none() {
+        *session = Some(RemoteDesktop::start()?);
+    }
+    let result = session.as_ref().map_or(Ok(()), f);
+    if result.is_err() {
+        *session = None;
+    }
+    result
+}
+
+/// Start the RemoteDesktop session for injection, if it isn't yet
+pub fn start_session() -> Result<(), String> {
+    with_session(|_| Ok(()))
+}
+
+/// Whether the RemoteDesktop portal is there to ask, without starting a session
+pub fn available() -> Result<(), String> {
+    let bus = Bus::connect()?;
+    bus.version(REMOTE_DESKTOP).map(|_| ()).map_err(|e| format!("the RemoteDesktop portal isn't available ({})", e))
+}
+
+/// The version of each portal the helper uses, or why it can't be asked
+pub fn versions() -> Vec<(&'static str, Result<u32, String>)> {
+    let bus = Bus::connect();
+    [GLOBAL_SHORTCUTS, REMOTE_DESKTOP]
+        .into_iter()
+        .map(|interface| (interface, bus.as_ref().map_err(String::clone).and_then(|bus| bus.version(interface))))
+        .collect()
+}
+
+/// Press or release the key with evdev code `code`
+pub fn key(code: u16, pressed: bool) -> Result<(), String> {
+    let args = vec![Value::I32(code.into()), Value::U32(pressed.into())];
+    with_session(|session| session.notify("NotifyKeyboardKeycode", args))
+}
+
+/// Press or release the button with evdev code `code`
+pub fn button(code: u16, pressed: bool) -> Result<(), String> {
+    let args = vec![Value::I32(code.into()), Value::U32(pressed.into())];
+    with_session(|session| session.notify("NotifyPointerButton", args))
+}
+
+/// Move the pointer by `(dx, dy)` pixels
+pub fn pointer_motion(dx: i32, dy: i32) -> Result<(), String> {
+    let args = vec![Value::F64(dx.into()), Value::F64(dy.into())];
+    with_session(|session| session.notify("NotifyPointerMotion", args))
+}
+
+/// Scroll by wheel notches; positive `dx` scrolls right, positive `dy` down
+pub fn scroll(dx: i32, dy: i32) -> Result<(), String> {
+    with_session(|session| {
+        for (axis, steps) in [(AXIS_HORIZONTAL, dx), (AXIS_VERTICAL, dy)] {
+            if steps != 0 {
+                session.notify("NotifyPointerAxisDiscrete", vec![Value::U32(axis), Value::I32(steps)])?;
+            }
+        }
+        Ok(())
+    })
+}
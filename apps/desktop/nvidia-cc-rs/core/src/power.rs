@@ -0,0 +1,203 @@
+//! System suspend/resume tracking for `SystemSuspend`/`SystemResume` events.
+//!
+//! Event nodes don't always survive a laptop's sleep: a keyboard can be
+//! re-enumerated on resume, leaving the old node open but silent, so the evdev
+//! listener reopens every device as soon as [`resumes`] counts a new resume
+//! rather than waiting for reads to fail. The listener never grabs devices,
+//! so reopening them is all there is to re-establish.
+//!
+//! Sources: logind's `PrepareForSleep` signal on Linux (`gdbus monitor`), a
+//! suspend/resume notification callback on Windows, and IOKit's system power
+//! notifications on macOS.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+static RESUMES: AtomicU64 = AtomicU64::new(0);
+
+/// Start emitting `SystemSuspend`/`SystemResume` on a background thread
+pub fn watch() {
+    platform::watch();
+}
+
+/// Resumes seen since the helper started
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn resumes() -> u64 {
+    RESUMES.load(Ordering::SeqCst)
+}
+
+fn suspending() {
+    // Sources can report the same transition more than once
+    if !SUSPENDED.swap(true, Ordering::SeqCst) {
+        eprintln!("System is suspending");
+        stream::emit(&Event::now(EventKind::SystemSuspend {}));
+    }
+}
+
+fn resumed() {
+    if SUSPENDED.swap(false, Ordering::SeqCst) {
+        eprintln!("System resumed");
+        RESUMES.fetch_add(1, Ordering::SeqCst);
+        stream::emit(&Event::now(EventKind::SystemResume {}));
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    pub fn watch() {
+        thread::spawn(|| {
+            if let Err(e) = logind() {
+                eprintln!("Suspend/resume tracking unavailable: {}", e);
+            }
+        });
+    }
+
+    /// Follow `PrepareForSleep(true)` before suspend and `PrepareForSleep(false)` after resume
+    fn logind() -> Result<(), String> {
+        let mut child = Command::new("gdbus")
+            .args([
+                "monitor",
+                "--system",
+                "--dest",
+                "org.freedesktop.login1",
+                "--object-path",
+                "/org/freedesktop/login1",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("failed to run gdbus: {}", e))?;
+        let stdout = child.stdout.take().ok_or("gdbus has no stdout")?;
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            // /org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep (true,)
+            let Some((_, arguments)) = line.split_once(".PrepareForSleep ") else {
+                continue;
+            };
+            if arguments.starts_with("(true") {
+                super::suspending();
+            } else if arguments.starts_with("(false") {
+                super::resumed();
+            }
+        }
+        child.wait().ok();
+        Err("gdbus exited".to_string())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+    use std::ptr;
+
+    use windows_sys::Win32::System::Power::{PowerRegisterSuspendResumeNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND};
+
+    unsafe extern "system" fn power_changed(_context: *const c_void, kind: u32, _setting: *const c_void) -> u32 {
+        match kind {
+            PBT_APMSUSPEND => super::suspending(),
+            // Sent on every resume, whether or not the user is back at the machine
+            PBT_APMRESUMEAUTOMATIC => super::resumed(),
+            _ => {}
+        }
+        0
+    }
+
+    pub fn watch() {
+        // Registered for the life of the process, so the parameters are never freed
+        let parameters = Box::leak(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+            Callback: Some(power_changed),
+            Context: ptr::null_mut(),
+        }));
+        let mut registration = ptr::null_mut();
+        let error = unsafe {
+            PowerRegisterSuspendResumeNotification(
+                DEVICE_NOTIFY_CALLBACK,
+                parameters as *mut DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS as *mut c_void,
+                &mut registration,
+            )
+        };
+        if error != 0 {
+            eprintln!("Suspend/resume tracking unavailable: error {}", error);
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::c_void;
+    use std::ptr;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::thread;
+
+    type CFTypeRef = *const c_void;
+    type IONotificationPortRef = *mut c_void;
+
+    const IO_MESSAGE_CAN_SYSTEM_SLEEP: u32 = 0xe000_0270;
+    const IO_MESSAGE_SYSTEM_WILL_SLEEP: u32 = 0xe000_0280;
+    const IO_MESSAGE_SYSTEM_HAS_POWERED_ON: u32 = 0xe000_0300;
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IORegisterForSystemPower(
+            refcon: *mut c_void,
+            port: *mut IONotificationPortRef,
+            callback: extern "C" fn(*mut c_void, u32, u32, *mut c_void),
+            notifier: *mut u32,
+        ) -> u32;
+        fn IONotificationPortGetRunLoopSource(port: IONotificationPortRef) -> CFTypeRef;
+        fn IOAllowPowerChange(root_port: u32, notification_id: isize) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        static kCFRunLoopDefaultMode: CFTypeRef;
+        fn CFRunLoopGetCurrent() -> CFTypeRef;
+        fn CFRunLoopAddSource(run_loop: CFTypeRef, source: CFTypeRef, mode: CFTypeRef);
+        fn CFRunLoopRun();
+    }
+
+    /// Connection to the power management root domain, needed to acknowledge sleep
+    static ROOT_PORT: AtomicU32 = AtomicU32::new(0);
+
+    extern "C" fn power_changed(_refcon: *mut c_void, _service: u32, message: u32, argument: *mut c_void) {
+        match message {
+            // Sleep waits (up to 30 s) for every registered client to acknowledge
+            IO_MESSAGE_CAN_SYSTEM_SLEEP => unsafe {
+                IOAllowPowerChange(ROOT_PORT.load(Ordering::SeqCst), argument as isize);
+            },
+            IO_MESSAGE_SYSTEM_WILL_SLEEP => {
+                super::suspending();
+                unsafe { IOAllowPowerChange(ROOT_PORT.load(Ordering::SeqCst), argument as isize) };
+            }
+            IO_MESSAGE_SYSTEM_HAS_POWERED_ON => super::resumed(),
+            _ => {}
+        }
+    }
+
+    pub fn watch() {
+        thread::spawn(|| unsafe {
+            let mut port: IONotificationPortRef = ptr::null_mut();
+            let mut notifier = 0u32;
+            let root_port = IORegisterForSystemPower(ptr::null_mut(), &mut port, power_changed, &mut notifier);
+            if root_port == 0 {
+                eprintln!("Suspend/resume tracking unavailable: IORegisterForSystemPower failed");
+                return;
+            }
+            ROOT_PORT.store(root_port, Ordering::SeqCst);
+            CFRunLoopAddSource(CFRunLoopGetCurrent(), IONotificationPortGetRunLoopSource(port), kCFRunLoopDefaultMode);
+            CFRunLoopRun();
+        });
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    pub fn watch() {}
+}
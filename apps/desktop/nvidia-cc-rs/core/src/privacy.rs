@@ -0,0 +1,108 @@
+//! Privacy modes that keep typing content out of the event stream.
+//!
+//! `--privacy allowlist` emits key events verbatim only for keys some hotkey
+//! uses (any step of a sequence counts), so push-to-talk and the app's own
+//! shortcut handling keep working; every other key becomes
+//! `KeyActivity{pressed}`, which says a key went down or up but not which.
+//! `--privacy hash` keeps the `KeyPress`/`KeyRelease` shape for those keys but
+//! replaces `key` with a hash salted with a per-process random key and drops
+//! `name`, `keysym` and `text`, so the app can still tell repeats of one key
+//! apart without learning what was typed. The salt changes every run.
+//!
+//! Hotkeys and hotstrings are matched in the helper on the real keys either
+//! way; their events only carry the configured ids.
+
+use serde::Deserialize;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+use crate::event::{self, Event, EventKind};
+use crate::hotkeys;
+use crate::stream;
+
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Every key verbatim
+    #[default]
+    Off,
+    Allowlist,
+    Hash,
+}
+
+impl Mode {
+    pub const NAMES: [&'static str; 3] = ["off", "allowlist", "hash"];
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "off" => Ok(Mode::Off),
+            "allowlist" => Ok(Mode::Allowlist),
+            "hash" => Ok(Mode::Hash),
+            _ => Err(format!("unknown privacy mode {:?} (expected {})", name, Mode::NAMES.join(", "))),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        Mode::NAMES[self as usize]
+    }
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Off as u8);
+
+pub fn set_mode(mode: Mode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => Mode::Allowlist,
+        2 => Mode::Hash,
+        _ => Mode::Off,
+    }
+}
+
+/// Salted hash of a key name, `h:` and 16 hex digits
+fn hash(key: &str) -> String {
+    static SALT: OnceLock<RandomState> = OnceLock::new();
+    format!("h:{:016x}", SALT.get_or_init(RandomState::new).hash_one(key))
+}
+
+/// Emit a key event as the privacy mode allows; other events are emitted unchanged
+pub fn emit_key(event: &Event) {
+    let (pressed, key) = match &event.kind {
+        EventKind::KeyPress(key) => (true, key),
+        EventKind::KeyRelease(key) => (false, key),
+        _ => return stream::emit(event),
+    };
+    let mode = mode();
+    if mode == Mode::Off || hotkeys::uses_key(key.key) {
+        return stream::emit(event);
+    }
+    if mode == Mode::Allowlist {
+        return stream::emit(&Event {
+            time: event.time,
+            kind: EventKind::KeyActivity { pressed },
+        });
+    }
+    let hashed = hash(key.key);
+    let key = event::Key {
+        key: &hashed,
+        name: None,
+        keysym: None,
+        scancode: None,
+        text: None,
+        num_lock: None,
+        synthetic: key.synthetic,
+        hold_ms: key.hold_ms,
+    };
+    stream::emit(&Event {
+        time: event.time,
+        kind: if pressed {
+            EventKind::KeyPress(key)
+        } else {
+            EventKind::KeyRelease(key)
+        },
+    });
+}
@@ -0,0 +1,153 @@
+//! Best-effort low-latency scheduling for `listen --realtime`.
+//!
+//! Under heavy system load the listener thread can be delayed long enough to
+//! make push-to-talk feel sluggish. Elevation is attempted with the strongest
+//! mechanism first and falls back gracefully; the outcome is reported to the
+//! app as a `RealtimeStatus` event so it can tell the user why it didn't work.
+
+use crate::stream;
+use crate::event::{Event, EventKind};
+
+/// SCHED_FIFO priority requested on Unix. rtkit caps clients at 20 by default.
+#[cfg(unix)]
+const REALTIME_PRIORITY: i32 = 10;
+
+/// Largest CPU time slice (µs) a realtime thread may use without blocking, required by rtkit
+#[cfg(target_os = "linux")]
+const REALTIME_RTTIME_LIMIT_US: u64 = 200_000;
+
+/// Stack region touched up front so the hot path never page-faults on it
+const PREFAULT_STACK_BYTES: usize = 256 * 1024;
+
+/// Elevate the calling thread and report the result on the event stream
+///
+/// `source` identifies the listener thread (device path, or the backend name)
+pub fn elevate_current_thread(source: &str) {
+    prefault_stack();
+    let memory_locked = lock_memory();
+
+    let (method, error) = match elevate() {
+        Ok(method) => (Some(method), None),
+        Err(error) => {
+            eprintln!("Realtime elevation failed for {}: {}", source, error);
+            (None, Some(error))
+        }
+    };
+
+    stream::emit(&Event::now(EventKind::RealtimeStatus {
+        source: source.to_string(),
+        elevated: method.is_some(),
+        method,
+        memory_locked,
+        error,
+    }));
+}
+
+fn prefault_stack() {
+    let mut region = [0u8; PREFAULT_STACK_BYTES];
+    // Keep the writes from being optimized away
+    std::hint::black_box(&mut region);
+}
+
+/// Lock the pages touched so far into RAM. MCL_FUTURE is deliberately not used:
+/// with a low RLIMIT_MEMLOCK it would make later allocations fail outright.
+#[cfg(unix)]
+fn lock_memory() -> bool {
+    unsafe { libc::mlockall(libc::MCL_CURRENT) == 0 }
+}
+
+#[cfg(not(unix))]
+fn lock_memory() -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+fn elevate() -> Result<&'static str, String> {
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::pid_t;
+
+    // 1. Direct SCHED_FIFO: works with CAP_SYS_NICE or a non-zero RLIMIT_RTPRIO
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = REALTIME_PRIORITY;
+    if unsafe { libc::sched_setscheduler(tid, libc::SCHED_FIFO, &param) } == 0 {
+        return Ok("sched_fifo");
+    }
+    let direct_error = std::io::Error::last_os_error();
+
+    // 2. Ask rtkit over the system bus, which is how unprivileged desktop apps get SCHED_FIFO
+    let rtkit_error = match rtkit_make_thread_realtime(tid) {
+        Ok(()) => return Ok("rtkit"),
+        Err(e) => e,
+    };
+
+    // 3. At least get ahead of normal-priority work
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, -10) } == 0 {
+        return Ok("nice");
+    }
+
+    Err(format!(
+        "sched_setscheduler: {}; rtkit: {}; setpriority: {}",
+        direct_error,
+        rtkit_error,
+        std::io::Error::last_os_error()
+    ))
+}
+
+/// Call RealtimeKit1.MakeThreadRealtimeWithPID through `busctl` to avoid a D-Bus dependency
+#[cfg(target_os = "linux")]
+fn rtkit_make_thread_realtime(tid: libc::pid_t) -> Result<(), String> {
+    // rtkit refuses threads that could monopolize a CPU, so cap the realtime slice first
+    let limit = libc::rlimit {
+        rlim_cur: REALTIME_RTTIME_LIMIT_US,
+        rlim_max: REALTIME_RTTIME_LIMIT_US,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_RTTIME, &limit) } != 0 {
+        return Err(format!("setrlimit(RLIMIT_RTTIME): {}", std::io::Error::last_os_error()));
+    }
+
+    let output = std::process::Command::new("busctl")
+        .args([
+            "call",
+            "--system",
+            "org.freedesktop.RealtimeKit1",
+            "/org/freedesktop/RealtimeKit1",
+            "org.freedesktop.RealtimeKit1",
+            "MakeThreadRealtimeWithPID",
+            "ttu",
+            &std::process::id().to_string(),
+            &tid.to_string(),
+            &REALTIME_PRIORITY.to_string(),
+        ])
+        .output()
+        .map_err(|e| format!("failed to run busctl: {}", e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn elevate() -> Result<&'static str, String> {
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = REALTIME_PRIORITY;
+    let result = unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_RR, &param) };
+    if result == 0 {
+        Ok("sched_rr")
+    } else {
+        Err(format!("pthread_setschedparam: {}", std::io::Error::from_raw_os_error(result)))
+    }
+}
+
+#[cfg(windows)]
+fn elevate() -> Result<&'static str, String> {
+    use windows_sys::Win32::System::Threading::{
+        GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) } != 0 {
+        Ok("thread_priority_time_critical")
+    } else {
+        Err(format!("SetThreadPriority: {}", std::io::Error::last_os_error()))
+    }
+}
@@ -0,0 +1,105 @@
+//! Key name overrides from the config file's `[remap]` and `[[device_remap]]`.
+//!
+//! A remapped key is emitted under its new name, and hotkeys, hotstrings and
+//! the privacy allowlist only ever see that name: with `CapsLock = "Hyper"` a
+//! `Hyper+Space` hotkey fires on Caps Lock+Space. Rules apply to the name the
+//! key had, never to another rule's result, so two rules swap a pair of keys.
+//! Device rules win over `[remap]`; rdev reports no device, so on macOS and
+//! Windows only `[remap]` applies.
+
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Rules for the devices whose name or path matches `device`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceRemap {
+    device: String,
+    keys: BTreeMap<String, String>,
+}
+
+pub struct Table {
+    keys: HashMap<String, String>,
+    devices: Vec<(Regex, HashMap<String, String>)>,
+}
+
+static TABLE: Mutex<Option<Table>> = Mutex::new(None);
+
+/// Rules keyed by the name keys are emitted under, accepting evdev `KEY_*` names on Linux
+fn compile_keys(keys: &BTreeMap<String, String>) -> Result<HashMap<String, String>, String> {
+    keys.iter()
+        .map(|(from, to)| {
+            if to.is_empty() {
+                return Err(format!("remap of {} has an empty name", from));
+            }
+            Ok((canonical(from)?, to.clone()))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn canonical(name: &str) -> Result<String, String> {
+    if !name.starts_with("KEY_") {
+        return Ok(name.to_string());
+    }
+    let key: evdev::KeyCode = name.parse().map_err(|_| format!("unknown evdev key {}", name))?;
+    Ok(crate::listener::evdev_key_to_rdev_name(key).to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn canonical(name: &str) -> Result<String, String> {
+    Ok(name.to_string())
+}
+
+pub fn compile(keys: &BTreeMap<String, String>, devices: &[DeviceRemap]) -> Result<Table, String> {
+    let devices = devices
+        .iter()
+        .map(|remap| {
+            let pattern = Regex::new(&remap.device)
+                .map_err(|e| format!("invalid device pattern {:?}: {}", remap.device, e))?;
+            Ok((pattern, compile_keys(&remap.keys)?))
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Table {
+        keys: compile_keys(keys)?,
+        devices,
+    })
+}
+
+/// Replace the rules in effect
+pub fn install(table: Table) {
+    *TABLE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(table);
+}
+
+/// The `[[device_remap]]` rules for `key`: each one's device pattern and the name it gives the key
+pub fn device_rules(key: &str) -> Vec<(String, String)> {
+    let table = TABLE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let Some(table) = table.as_ref() else {
+        return Vec::new();
+    };
+    table
+        .devices
+        .iter()
+        .filter_map(|(pattern, keys)| Some((pattern.as_str().to_string(), keys.get(key)?.clone())))
+        .collect()
+}
+
+/// Name `key` is emitted under when it comes from the device named or found at one of `device`
+pub fn apply<'a>(device: &[&str], key: &'a str) -> Cow<'a, str> {
+    let table = TABLE.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let Some(table) = table.as_ref() else {
+        return Cow::Borrowed(key);
+    };
+    let device_rule = table
+        .devices
+        .iter()
+        .filter(|(pattern, _)| device.iter().any(|name| pattern.is_match(name)))
+        .find_map(|(_, keys)| keys.get(key));
+    match device_rule.or_else(|| table.keys.get(key)) {
+        Some(name) => Cow::Owned(name.clone()),
+        None => Cow::Borrowed(key),
+    }
+}
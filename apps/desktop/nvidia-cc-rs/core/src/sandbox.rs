@@ -0,0 +1,290 @@
+//! `listen --sandbox`: once the keyboards are open, confine the listener to
+//! reading them and writing events to stdout, so a bug in handling a crafted
+//! device or message can't be turned into code that does more with every
+//! keystroke than print it.
+//!
+//! Two layers, both unprivileged and neither undone for the process's life:
+//!
+//! - A seccomp filter, on every thread: the syscalls the listener and the
+//!   watchers already running make on descriptors they hold. No exec or fork,
+//!   no new sockets (but a Unix one with `--input-broker`), files only opened
+//!   read-only, and no TIOCSTI or TIOCLINUX to type into a terminal on stdout.
+//!   Anything else fails with EPERM instead of killing the helper, so a
+//!   watcher that needs more stops with an error.
+//! - A Landlock ruleset, where the kernel has one (5.13): the listening
+//!   threads only open what reopening a keyboard reads, under /dev/input,
+//!   /sys and /run/udev, and the time zone; from 6.7 they make no TCP
+//!   connections either. Landlock binds the thread that applies it and the
+//!   ones it starts, so watchers started before keep their file access,
+//!   read-only under the filter.
+//!
+//! Options needing more (serving sockets, D-Bus, HTTP, ydotool, a rotating
+//! `--record-to` file, `--record-last` dumps, hidraw) are refused with
+//! `--sandbox`, and so are the stdin commands that would inject, notify or
+//! write files once it's applied (see [`crate::control`]).
+
+use crate::input_access;
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static APPLIED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xC000_003E;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+/// Offsets into `struct seccomp_data`
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+
+/// Low 32 bits of syscall argument `n` (both architectures are little-endian)
+const fn data_arg(n: u32) -> u32 {
+    16 + 8 * n
+}
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+/// Everything ABI 1 knows, from EXECUTE to MAKE_SYM
+const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+const ACCESS_FS_REFER: u64 = 1 << 13;
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+const ACCESS_NET_BIND_TCP: u64 = 1 << 0;
+const ACCESS_NET_CONNECT_TCP: u64 = 1 << 1;
+
+/// What the listening threads may read, besides the descriptors they hold
+const READABLE: &[&str] = &["/dev/input", "/sys", "/run/udev", "/etc/localtime", "/usr/share/zoneinfo"];
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+    /// From ABI 4
+    handled_access_net: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// Sandbox the calling thread and those it starts, and filter every thread's syscalls
+///
+/// `readable` adds paths to what the listening threads may read (the replay backend's recording).
+pub fn apply(readable: &[&Path]) -> Result<(), String> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(format!("Cannot set no_new_privs: {}", io::Error::last_os_error()));
+    }
+    let landlock = match restrict_files(readable) {
+        Ok(abi) => format!("Landlock ABI {}", abi),
+        Err(e) => {
+            eprintln!("Landlock unavailable, sandboxing with seccomp only: {}", e);
+            "no Landlock".to_string()
+        }
+    };
+    install_filter(&filter(input_access::uses_broker()))
+        .map_err(|e| format!("Cannot install the seccomp filter: {}", e))?;
+    APPLIED.store(true, Ordering::SeqCst);
+    eprintln!("Sandboxed listener (seccomp, {})", landlock);
+    Ok(())
+}
+
+/// Whether [`apply`] has confined the process
+pub fn applied() -> bool {
+    APPLIED.load(Ordering::SeqCst)
+}
+
+/// Apply the Landlock ruleset, returning the ABI version it was written against
+fn restrict_files(readable: &[&Path]) -> io::Result<i64> {
+    let abi = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            LANDLOCK_CREATE_RULESET_VERSION,
+        )
+    };
+    if abi < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut handled_access_fs = ACCESS_FS_V1;
+    if abi >= 2 {
+        handled_access_fs |= ACCESS_FS_REFER;
+    }
+    if abi >= 3 {
+        handled_access_fs |= ACCESS_FS_TRUNCATE;
+    }
+    let attr = RulesetAttr {
+        handled_access_fs,
+        handled_access_net: ACCESS_NET_BIND_TCP | ACCESS_NET_CONNECT_TCP,
+    };
+    // Before ABI 4 the kernel doesn't know handled_access_net, and refuses a struct with it set
+    let size = if abi >= 4 { std::mem::size_of::<RulesetAttr>() } else { std::mem::size_of::<u64>() };
+    let ruleset = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, &attr, size, 0u32) };
+    if ruleset < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset as i32) };
+
+    let paths = READABLE.iter().map(Path::new).chain(readable.iter().copied());
+    for path in paths {
+        let Ok(name) = CString::new(path.as_os_str().as_bytes()) else { continue };
+        let fd = unsafe { libc::open(name.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            // Not on this system (no udev, say)
+            continue;
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // Directory rights only apply beneath a directory
+        let allowed_access = if path.is_dir() { ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR } else { ACCESS_FS_READ_FILE };
+        let rule = PathBeneathAttr { allowed_access, parent_fd: fd.as_raw_fd() };
+        let added = unsafe {
+            libc::syscall(libc::SYS_landlock_add_rule, ruleset.as_raw_fd(), LANDLOCK_RULE_PATH_BENEATH, &rule, 0u32)
+        };
+        if added != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(abi)
+}
+
+/// Syscalls allowed whatever their arguments
+const ALLOWED: &[libc::c_long] = &[
+    // Devices, stdout and the descriptors already open
+    libc::SYS_read, libc::SYS_readv, libc::SYS_pread64, libc::SYS_write, libc::SYS_writev, libc::SYS_close,
+    libc::SYS_lseek, libc::SYS_fcntl, libc::SYS_fstat, libc::SYS_newfstatat, libc::SYS_statx,
+    libc::SYS_faccessat, libc::SYS_faccessat2, libc::SYS_getdents64, libc::SYS_readlinkat, libc::SYS_getcwd,
+    libc::SYS_ppoll, libc::SYS_pselect6, libc::SYS_epoll_pwait, libc::SYS_epoll_ctl, libc::SYS_inotify_add_watch,
+    libc::SYS_recvfrom, libc::SYS_recvmsg, libc::SYS_sendto, libc::SYS_sendmsg, libc::SYS_shutdown,
+    libc::SYS_getsockopt, libc::SYS_getsockname,
+    // Memory and threads
+    libc::SYS_mmap, libc::SYS_munmap, libc::SYS_mremap, libc::SYS_mprotect, libc::SYS_madvise, libc::SYS_brk,
+    libc::SYS_mlockall, libc::SYS_munlockall, libc::SYS_futex, libc::SYS_set_robust_list, libc::SYS_rseq,
+    libc::SYS_set_tid_address, libc::SYS_membarrier, libc::SYS_sched_yield, libc::SYS_sched_getaffinity,
+    libc::SYS_sched_setscheduler, libc::SYS_sched_getscheduler, libc::SYS_sched_getparam, libc::SYS_prlimit64,
+    libc::SYS_exit, libc::SYS_exit_group,
+    // Time, signals and identity
+    libc::SYS_clock_gettime, libc::SYS_clock_getres, libc::SYS_clock_nanosleep, libc::SYS_nanosleep,
+    libc::SYS_gettimeofday, libc::SYS_rt_sigaction, libc::SYS_rt_sigprocmask, libc::SYS_rt_sigreturn,
+    libc::SYS_rt_sigtimedwait, libc::SYS_sigaltstack, libc::SYS_restart_syscall, libc::SYS_tgkill,
+    libc::SYS_gettid, libc::SYS_getpid, libc::SYS_getppid, libc::SYS_getuid, libc::SYS_geteuid, libc::SYS_getgid,
+    libc::SYS_getegid, libc::SYS_getrandom, libc::SYS_uname,
+    // Lowering or raising CAP_DAC_READ_SEARCH within the permitted set, to reopen a keyboard
+    libc::SYS_capget, libc::SYS_capset,
+];
+
+#[cfg(target_arch = "x86_64")]
+const ALLOWED_X86_64: &[libc::c_long] = &[
+    libc::SYS_poll, libc::SYS_select, libc::SYS_epoll_wait, libc::SYS_stat, libc::SYS_lstat, libc::SYS_access,
+    libc::SYS_readlink,
+];
+#[cfg(not(target_arch = "x86_64"))]
+const ALLOWED_X86_64: &[libc::c_long] = &[];
+
+/// Flags that would open a file for anything but reading
+const WRITE_FLAGS: u32 = (libc::O_ACCMODE | libc::O_CREAT | libc::O_TRUNC | libc::O_APPEND) as u32;
+
+fn statement(code: u32, k: u32) -> libc::sock_filter {
+    libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code: code as u16, jt, jf, k }
+}
+
+fn load(offset: u32) -> libc::sock_filter {
+    statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset)
+}
+
+fn ret(action: u32) -> libc::sock_filter {
+    statement(libc::BPF_RET | libc::BPF_K, action)
+}
+
+fn errno(errno: i32) -> u32 {
+    libc::SECCOMP_RET_ERRNO | errno as u32
+}
+
+/// Skip `block` unless the syscall is `nr`; every block ends in a return
+fn on_syscall(program: &mut Vec<libc::sock_filter>, nr: libc::c_long, block: &[libc::sock_filter]) {
+    program.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, nr as u32, 0, block.len() as u8));
+    program.extend_from_slice(block);
+}
+
+/// The seccomp program; with `unix_sockets`, connecting to the input broker stays possible
+fn filter(unix_sockets: bool) -> Vec<libc::sock_filter> {
+    let allow = ret(libc::SECCOMP_RET_ALLOW);
+    let deny = ret(errno(libc::EPERM));
+    let mut program = vec![
+        load(DATA_ARCH),
+        jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, AUDIT_ARCH, 1, 0),
+        // Syscall numbers mean something else under another ABI
+        ret(libc::SECCOMP_RET_KILL_PROCESS),
+        load(DATA_NR),
+    ];
+    for &nr in ALLOWED.iter().chain(ALLOWED_X86_64) {
+        on_syscall(&mut program, nr, &[allow]);
+    }
+    // Read-only opens; EACCES so evdev falls back from read-write
+    on_syscall(&mut program, libc::SYS_openat, &[
+        load(data_arg(2)),
+        jump(libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K, WRITE_FLAGS, 0, 1),
+        ret(errno(libc::EACCES)),
+        allow,
+    ]);
+    // Threads, not processes
+    on_syscall(&mut program, libc::SYS_clone, &[
+        load(data_arg(0)),
+        jump(libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K, libc::CLONE_THREAD as u32, 0, 1),
+        allow,
+        deny,
+    ]);
+    // Its flags are behind a pointer the filter can't read; ENOSYS has glibc fall back to clone
+    on_syscall(&mut program, libc::SYS_clone3, &[ret(errno(libc::ENOSYS))]);
+    on_syscall(&mut program, libc::SYS_ioctl, &[
+        load(data_arg(1)),
+        jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::TIOCSTI as u32, 2, 0),
+        jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::TIOCLINUX as u32, 1, 0),
+        allow,
+        deny,
+    ]);
+    if unix_sockets {
+        on_syscall(&mut program, libc::SYS_socket, &[
+            load(data_arg(0)),
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::AF_UNIX as u32, 0, 1),
+            allow,
+            deny,
+        ]);
+        on_syscall(&mut program, libc::SYS_connect, &[allow]);
+    }
+    program.push(deny);
+    program
+}
+
+/// Install `program` on every thread of the process
+fn install_filter(program: &[libc::sock_filter]) -> io::Result<()> {
+    let prog = libc::sock_fprog { len: program.len() as u16, filter: program.as_ptr() as *mut _ };
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &prog,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        // With TSYNC, a positive result is the id of a thread that couldn't be synchronized
+        tid if tid > 0 => Err(io::Error::other(format!("thread {} has another filter", tid))),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
@@ -0,0 +1,271 @@
+//! Raw key codes for `--raw-scancodes`.
+//!
+//! With the flag, key events carry `scancode` beside `key`: the evdev key code
+//! on Linux, the scan code on Windows (extended keys with `0xE0` in the high
+//! byte) and the virtual key code on macOS. Unusual keys are named by a debug
+//! fallback that can change between releases; their code doesn't.
+//!
+//! rdev doesn't pass the raw code through, so on Windows and macOS it is
+//! recovered from the key: the macOS code directly, the Windows scan code from
+//! the virtual key through `MapVirtualKeyW`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Code of an evdev key, when `--raw-scancodes` is on
+#[cfg(target_os = "linux")]
+pub fn evdev(key: evdev::KeyCode) -> Option<u32> {
+    enabled().then(|| key.code() as u32)
+}
+
+/// Code of a key rdev reported, when `--raw-scancodes` is on and the key has one
+#[cfg(not(target_os = "linux"))]
+pub fn rdev(key: rdev::Key) -> Option<u32> {
+    if !enabled() {
+        return None;
+    }
+    let native = match key {
+        rdev::Key::Unknown(code) => code,
+        key => platform::KEYS.iter().find(|(known, _)| *known == key)?.1,
+    };
+    platform::reported(native)
+}
+
+/// Every key rdev has a name for, with its native code: the virtual key on Windows, the key code on macOS
+#[cfg(not(target_os = "linux"))]
+pub fn rdev_keys() -> &'static [(rdev::Key, u32)] {
+    &platform::KEYS
+}
+
+/// The code `--raw-scancodes` reports for the native code `native`
+#[cfg(not(target_os = "linux"))]
+pub fn reported(native: u32) -> Option<u32> {
+    platform::reported(native)
+}
+
+#[cfg(windows)]
+mod platform {
+    use rdev::Key;
+    use windows_sys::Win32::UI::Input::KeyboardAndMouse::{MapVirtualKeyW, MAPVK_VK_TO_VSC_EX};
+
+    /// Virtual key codes rdev maps keys from
+    pub const KEYS: [(Key, u32); 102] = [
+        (Key::Alt, 164),
+        (Key::AltGr, 165),
+        (Key::Backspace, 8),
+        (Key::CapsLock, 20),
+        (Key::ControlLeft, 162),
+        (Key::ControlRight, 163),
+        (Key::Delete, 46),
+        (Key::DownArrow, 40),
+        (Key::End, 35),
+        (Key::Escape, 27),
+        (Key::F1, 112),
+        (Key::F10, 121),
+        (Key::F11, 122),
+        (Key::F12, 123),
+        (Key::F2, 113),
+        (Key::F3, 114),
+        (Key::F4, 115),
+        (Key::F5, 116),
+        (Key::F6, 117),
+        (Key::F7, 118),
+        (Key::F8, 119),
+        (Key::F9, 120),
+        (Key::Home, 36),
+        (Key::LeftArrow, 37),
+        (Key::MetaLeft, 91),
+        (Key::PageDown, 34),
+        (Key::PageUp, 33),
+        (Key::Return, 13),
+        (Key::RightArrow, 39),
+        (Key::ShiftLeft, 160),
+        (Key::ShiftRight, 161),
+        (Key::Space, 32),
+        (Key::Tab, 9),
+        (Key::UpArrow, 38),
+        (Key::PrintScreen, 44),
+        (Key::ScrollLock, 145),
+        (Key::Pause, 19),
+        (Key::NumLock, 144),
+        (Key::BackQuote, 192),
+        (Key::Num1, 49),
+        (Key::Num2, 50),
+        (Key::Num3, 51),
+        (Key::Num4, 52),
+        (Key::Num5, 53),
+        (Key::Num6, 54),
+        (Key::Num7, 55),
+        (Key::Num8, 56),
+        (Key::Num9, 57),
+        (Key::Num0, 48),
+        (Key::Minus, 189),
+        (Key::Equal, 187),
+        (Key::KeyQ, 81),
+        (Key::KeyW, 87),
+        (Key::KeyE, 69),
+        (Key::KeyR, 82),
+        (Key::KeyT, 84),
+        (Key::KeyY, 89),
+        (Key::KeyU, 85),
+        (Key::KeyI, 73),
+        (Key::KeyO, 79),
+        (Key::KeyP, 80),
+        (Key::LeftBracket, 219),
+        (Key::RightBracket, 221),
+        (Key::KeyA, 65),
+        (Key::KeyS, 83),
+        (Key::KeyD, 68),
+        (Key::KeyF, 70),
+        (Key::KeyG, 71),
+        (Key::KeyH, 72),
+        (Key::KeyJ, 74),
+        (Key::KeyK, 75),
+        (Key::KeyL, 76),
+        (Key::SemiColon, 186),
+        (Key::Quote, 222),
+        (Key::BackSlash, 220),
+        (Key::IntlBackslash, 226),
+        (Key::KeyZ, 90),
+        (Key::KeyX, 88),
+        (Key::KeyC, 67),
+        (Key::KeyV, 86),
+        (Key::KeyB, 66),
+        (Key::KeyN, 78),
+        (Key::KeyM, 77),
+        (Key::Comma, 188),
+        (Key::Dot, 190),
+        (Key::Slash, 191),
+        (Key::Insert, 45),
+        (Key::KpMinus, 109),
+        (Key::KpPlus, 107),
+        (Key::KpMultiply, 106),
+        (Key::KpDivide, 111),
+        (Key::Kp0, 96),
+        (Key::Kp1, 97),
+        (Key::Kp2, 98),
+        (Key::Kp3, 99),
+        (Key::Kp4, 100),
+        (Key::Kp5, 101),
+        (Key::Kp6, 102),
+        (Key::Kp7, 103),
+        (Key::Kp8, 104),
+        (Key::Kp9, 105),
+        (Key::KpDelete, 110),
+    ];
+
+    /// Scan code of the virtual key `virtual_key`
+    pub fn reported(virtual_key: u32) -> Option<u32> {
+        let scancode = unsafe { MapVirtualKeyW(virtual_key, MAPVK_VK_TO_VSC_EX) };
+        (scancode != 0).then_some(scancode)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use rdev::Key;
+
+    /// Key codes rdev maps keys from
+    pub const KEYS: [(Key, u32); 78] = [
+        (Key::Alt, 58),
+        (Key::AltGr, 61),
+        (Key::Backspace, 51),
+        (Key::CapsLock, 57),
+        (Key::ControlLeft, 59),
+        (Key::ControlRight, 62),
+        (Key::DownArrow, 125),
+        (Key::Escape, 53),
+        (Key::F1, 122),
+        (Key::F10, 109),
+        (Key::F11, 103),
+        (Key::F12, 111),
+        (Key::F2, 120),
+        (Key::F3, 99),
+        (Key::F4, 118),
+        (Key::F5, 96),
+        (Key::F6, 97),
+        (Key::F7, 98),
+        (Key::F8, 100),
+        (Key::F9, 101),
+        (Key::LeftArrow, 123),
+        (Key::MetaLeft, 55),
+        (Key::MetaRight, 54),
+        (Key::Return, 36),
+        (Key::RightArrow, 124),
+        (Key::ShiftLeft, 56),
+        (Key::ShiftRight, 60),
+        (Key::Space, 49),
+        (Key::Tab, 48),
+        (Key::UpArrow, 126),
+        (Key::BackQuote, 50),
+        (Key::Num1, 18),
+        (Key::Num2, 19),
+        (Key::Num3, 20),
+        (Key::Num4, 21),
+        (Key::Num5, 23),
+        (Key::Num6, 22),
+        (Key::Num7, 26),
+        (Key::Num8, 28),
+        (Key::Num9, 25),
+        (Key::Num0, 29),
+        (Key::Minus, 27),
+        (Key::Equal, 24),
+        (Key::KeyQ, 12),
+        (Key::KeyW, 13),
+        (Key::KeyE, 14),
+        (Key::KeyR, 15),
+        (Key::KeyT, 17),
+        (Key::KeyY, 16),
+        (Key::KeyU, 32),
+        (Key::KeyI, 34),
+        (Key::KeyO, 31),
+        (Key::KeyP, 35),
+        (Key::LeftBracket, 33),
+        (Key::RightBracket, 30),
+        (Key::KeyA, 0),
+        (Key::KeyS, 1),
+        (Key::KeyD, 2),
+        (Key::KeyF, 3),
+        (Key::KeyG, 5),
+        (Key::KeyH, 4),
+        (Key::KeyJ, 38),
+        (Key::KeyK, 40),
+        (Key::KeyL, 37),
+        (Key::SemiColon, 41),
+        (Key::Quote, 39),
+        (Key::BackSlash, 42),
+        (Key::KeyZ, 6),
+        (Key::KeyX, 7),
+        (Key::KeyC, 8),
+        (Key::KeyV, 9),
+        (Key::KeyB, 11),
+        (Key::KeyN, 45),
+        (Key::KeyM, 46),
+        (Key::Comma, 43),
+        (Key::Dot, 47),
+        (Key::Slash, 44),
+        (Key::Function, 63),
+    ];
+
+    pub fn reported(key_code: u32) -> Option<u32> {
+        Some(key_code)
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+mod platform {
+    pub const KEYS: [(rdev::Key, u32); 0] = [];
+
+    pub fn reported(code: u32) -> Option<u32> {
+        Some(code)
+    }
+}
@@ -0,0 +1,303 @@
+//! Screen capture for `screenshot`, as PNG.
+//!
+//! The app attaches what was on screen to dictation context. The whole
+//! desktop is taken unless a region or a monitor (indexed as in
+//! [`crate::monitors::list`]) narrows it; with both, the region is relative
+//! to that monitor's top-left corner.
+//!
+//! Sources: `grim` on wlroots compositors, else the screenshot portal
+//! (xdg-desktop-portal, whole screens only), on Wayland; `maim`, else
+//! ImageMagick's `import`, on X11; `screencapture` on macOS; .NET's
+//! `CopyFromScreen` through PowerShell on Windows.
+
+use crate::monitors;
+
+/// Every PNG file starts with these bytes
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// A rectangle in desktop coordinates (see [`crate::monitors`])
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// `x,y,w,h`
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid region {:?} (expected x,y,width,height)", text);
+        let fields: Vec<&str> = text.split(',').map(str::trim).collect();
+        let [x, y, width, height] = fields[..] else {
+            return Err(invalid());
+        };
+        let region = Region {
+            x: x.parse().map_err(|_| invalid())?,
+            y: y.parse().map_err(|_| invalid())?,
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+        };
+        if region.width == 0 || region.height == 0 {
+            return Err(format!("Region {:?} is empty", text));
+        }
+        Ok(region)
+    }
+}
+
+/// PNG of `region`, or of monitor `display`, or of the whole desktop
+pub fn capture(region: Option<Region>, display: Option<usize>) -> Result<Vec<u8>, String> {
+    let region = match display {
+        Some(index) => {
+            let monitors = monitors::list()?;
+            let monitor = monitors
+                .get(index)
+                .ok_or_else(|| format!("No display {} (found {})", index, monitors.len()))?;
+            Some(match region {
+                Some(region) => Region {
+                    x: monitor.x + region.x,
+                    y: monitor.y + region.y,
+                    ..region
+                },
+                None => Region {
+                    x: monitor.x,
+                    y: monitor.y,
+                    width: monitor.width as u32,
+                    height: monitor.height as u32,
+                },
+            })
+        }
+        None => region,
+    };
+    let (png, program) = platform::capture(region)?;
+    if !png.starts_with(PNG_SIGNATURE) {
+        return Err(format!("{} produced no PNG", program));
+    }
+    Ok(png)
+}
+
+/// Standard output of a successful run of `program`
+fn output(command: &mut std::process::Command, program: &str) -> Result<Vec<u8>, String> {
+    let output = command.output().map_err(|e| format!("{} unavailable: {}", program, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{} exited with {}: {}", program, output.status, stderr.trim()));
+    }
+    Ok(output.stdout)
+}
+
+/// Where tools that only write files put the capture
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn scratch_file() -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("nvidia-cc-screenshot-{}.png", std::process::id()))
+}
+
+/// The capture a tool wrote to `path`, which is removed
+#[cfg_attr(target_os = "linux", allow(dead_code))]
+fn take_file(path: &std::path::Path, program: &str) -> Result<Vec<u8>, String> {
+    let png = std::fs::read(path).map_err(|e| format!("{} wrote no screenshot: {}", program, e));
+    std::fs::remove_file(path).ok();
+    png
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{output, Region};
+
+    /// The portal may first ask the user for permission
+    const PORTAL_TIMEOUT: Duration = Duration::from_secs(60);
+
+    pub fn capture(region: Option<Region>) -> Result<(Vec<u8>, &'static str), String> {
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            grim(region).map(|png| (png, "grim")).or_else(|grim_error| match region {
+                Some(_) => Err(format!("{}; the screenshot portal only takes whole screens", grim_error)),
+                None => portal()
+                    .map(|png| (png, "the screenshot portal"))
+                    .map_err(|portal_error| format!("{}; {}", grim_error, portal_error)),
+            })
+        } else {
+            maim(region).map(|png| (png, "maim")).or_else(|maim_error| {
+                import(region)
+                    .map(|png| (png, "import"))
+                    .map_err(|import_error| format!("{}; {}", maim_error, import_error))
+            })
+        }
+    }
+
+    fn grim(region: Option<Region>) -> Result<Vec<u8>, String> {
+        let mut grim = Command::new("grim");
+        if let Some(region) = region {
+            grim.args(["-g", &format!("{},{} {}x{}", region.x, region.y, region.width, region.height)]);
+        }
+        output(grim.args(["-t", "png", "-"]), "grim")
+    }
+
+    /// X's geometry syntax: `WxH+X+Y`
+    fn geometry(region: Region) -> String {
+        format!("{}x{}{:+}{:+}", region.width, region.height, region.x, region.y)
+    }
+
+    fn maim(region: Option<Region>) -> Result<Vec<u8>, String> {
+        let mut maim = Command::new("maim");
+        if let Some(region) = region {
+            maim.args(["-g", &geometry(region)]);
+        }
+        output(maim.args(["-f", "png"]), "maim")
+    }
+
+    fn import(region: Option<Region>) -> Result<Vec<u8>, String> {
+        let mut import = Command::new("import");
+        import.args(["-window", "root"]);
+        if let Some(region) = region {
+            import.args(["-crop", &geometry(region), "+repage"]);
+        }
+        output(import.arg("png:-"), "import")
+    }
+
+    /// `file:///home/me/Pictures/Screenshot%20from%202024.png` as a path
+    fn file_uri_path(uri: &str) -> Option<String> {
+        let encoded = uri.strip_prefix("file://")?.as_bytes();
+        let mut path = Vec::with_capacity(encoded.len());
+        let mut i = 0;
+        while i < encoded.len() {
+            if encoded[i] == b'%' {
+                let hex = std::str::from_utf8(encoded.get(i + 1..i + 3)?).ok()?;
+                path.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            } else {
+                path.push(encoded[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8(path).ok()
+    }
+
+    /// `org.freedesktop.portal.Screenshot`, answered by a `Response` signal on the request object
+    fn portal() -> Result<Vec<u8>, String> {
+        let token = format!("nvidia_cc_{}", std::process::id());
+        // Listening first: the response may come before `gdbus call` returns
+        let mut monitor = Command::new("gdbus")
+            .args(["monitor", "--session", "--dest", "org.freedesktop.portal.Desktop"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("gdbus unavailable: {}", e))?;
+        let stdout = monitor.stdout.take().ok_or("gdbus has no stdout")?;
+        let (response, responses) = mpsc::channel();
+        let ending = format!("/{}: org.freedesktop.portal.Request.Response ", token);
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if let Some((_, arguments)) = line.split_once(&ending) {
+                    response.send(arguments.to_string()).ok();
+                    return;
+                }
+            }
+        });
+        thread::sleep(Duration::from_millis(100));
+        let options = format!("{{'handle_token': <'{}'>, 'interactive': <false>}}", token);
+        let called = output(
+            Command::new("gdbus").args([
+                "call",
+                "--session",
+                "--dest=org.freedesktop.portal.Desktop",
+                "--object-path=/org/freedesktop/portal/desktop",
+                "--method=org.freedesktop.portal.Screenshot.Screenshot",
+                "",
+                &options,
+            ]),
+            "gdbus",
+        );
+        let arguments = called.and_then(|_| {
+            responses
+                .recv_timeout(PORTAL_TIMEOUT)
+                .map_err(|_| "the screenshot portal didn't answer".to_string())
+        });
+        monitor.kill().ok();
+        monitor.wait().ok();
+
+        // (uint32 0, {'uri': <'file:///...'>})
+        let arguments = arguments?;
+        if !arguments.starts_with("(uint32 0,") {
+            return Err(format!("the screenshot portal refused: {}", arguments));
+        }
+        let uri = arguments
+            .split_once("'uri': <'")
+            .and_then(|(_, rest)| rest.split_once("'>"))
+            .map(|(uri, _)| uri)
+            .ok_or_else(|| format!("unexpected portal response: {}", arguments))?;
+        let path = file_uri_path(uri).ok_or_else(|| format!("unexpected screenshot location: {}", uri))?;
+        let png = std::fs::read(&path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+        // The portal saves into the user's pictures; the caller only asked for the image
+        std::fs::remove_file(&path).ok();
+        Ok(png)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+
+    use super::{output, scratch_file, take_file, Region};
+
+    pub fn capture(region: Option<Region>) -> Result<(Vec<u8>, &'static str), String> {
+        let path = scratch_file();
+        let mut screencapture = Command::new("screencapture");
+        // No shutter sound
+        screencapture.args(["-x", "-t", "png"]);
+        if let Some(region) = region {
+            screencapture.arg(format!("-R{},{},{},{}", region.x, region.y, region.width, region.height));
+        }
+        output(screencapture.arg(&path), "screencapture")?;
+        take_file(&path, "screencapture").map(|png| (png, "screencapture"))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::process::Command;
+
+    use super::{output, scratch_file, take_file, Region};
+
+    const SCRIPT: &str = r#"
+        Add-Type -AssemblyName System.Windows.Forms, System.Drawing
+        $bounds = [System.Windows.Forms.SystemInformation]::VirtualScreen
+        if ($env:NVIDIA_CC_SCREENSHOT_REGION) {
+            $x, $y, $width, $height = $env:NVIDIA_CC_SCREENSHOT_REGION.Split(',') | ForEach-Object { [int]$_ }
+            $bounds = [System.Drawing.Rectangle]::new($x, $y, $width, $height)
+        }
+        $bitmap = [System.Drawing.Bitmap]::new($bounds.Width, $bounds.Height)
+        $graphics = [System.Drawing.Graphics]::FromImage($bitmap)
+        $graphics.CopyFromScreen($bounds.Location, [System.Drawing.Point]::Empty, $bounds.Size)
+        $bitmap.Save($env:NVIDIA_CC_SCREENSHOT_OUT, [System.Drawing.Imaging.ImageFormat]::Png)
+    "#;
+
+    pub fn capture(region: Option<Region>) -> Result<(Vec<u8>, &'static str), String> {
+        let path = scratch_file();
+        let region = region
+            .map(|region| format!("{},{},{},{}", region.x, region.y, region.width, region.height))
+            .unwrap_or_default();
+        output(
+            Command::new("powershell")
+                .args(["-NoProfile", "-Command", SCRIPT])
+                .env("NVIDIA_CC_SCREENSHOT_REGION", region)
+                .env("NVIDIA_CC_SCREENSHOT_OUT", &path),
+            "powershell",
+        )?;
+        take_file(&path, "powershell").map(|png| (png, "powershell"))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    use super::Region;
+
+    pub fn capture(_region: Option<Region>) -> Result<(Vec<u8>, &'static str), String> {
+        Err("Screenshots aren't supported on this platform".to_string())
+    }
+}
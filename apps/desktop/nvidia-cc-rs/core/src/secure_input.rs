@@ -0,0 +1,105 @@
+//! Secure text field detection.
+//!
+//! While a password field or other secure input has focus, key events are not
+//! emitted at all: the app gets `SecureInputActive` when it takes focus and
+//! `SecureInputEnded` when it loses it, and nothing in between says which keys
+//! were typed. Hotstrings don't see those keys either; hotkeys still match, as
+//! they only ever report their own ids.
+//!
+//! Sources: `IsSecureEventInputEnabled` on macOS, which is on whenever any
+//! app asked for secure input (password fields, Terminal's Secure Keyboard
+//! Entry), and the `ES_PASSWORD` style of the focused control on Windows, which
+//! only covers classic edit controls. Both are polled. Linux has no equivalent
+//! hint, so there nothing is detected.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether key identities are currently withheld
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Start tracking secure input focus on a background thread
+pub fn watch() {
+    platform::watch();
+}
+
+#[cfg_attr(not(any(windows, target_os = "macos")), allow(dead_code))]
+fn update(active: bool) {
+    if ACTIVE.swap(active, Ordering::SeqCst) == active {
+        return;
+    }
+    eprintln!("Secure input {}", if active { "active: key events withheld" } else { "ended" });
+    stream::emit(&Event::now(if active {
+        EventKind::SecureInputActive {}
+    } else {
+        EventKind::SecureInputEnded {}
+    }));
+}
+
+#[cfg(any(windows, target_os = "macos"))]
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::thread;
+
+    use super::{update, POLL_INTERVAL};
+
+    #[link(name = "Carbon", kind = "framework")]
+    extern "C" {
+        fn IsSecureEventInputEnabled() -> u8;
+    }
+
+    pub fn watch() {
+        thread::spawn(|| loop {
+            update(unsafe { IsSecureEventInputEnabled() } != 0);
+            thread::sleep(POLL_INTERVAL);
+        });
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::thread;
+
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        GetClassNameW, GetGUIThreadInfo, GetWindowLongW, ES_PASSWORD, GUITHREADINFO, GWL_STYLE,
+    };
+
+    use super::{update, POLL_INTERVAL};
+
+    fn password_focused() -> bool {
+        let mut info: GUITHREADINFO = unsafe { std::mem::zeroed() };
+        info.cbSize = std::mem::size_of::<GUITHREADINFO>() as u32;
+        // Thread 0: whichever thread owns the foreground window
+        if unsafe { GetGUIThreadInfo(0, &mut info) } == 0 || info.hwndFocus.is_null() {
+            return false;
+        }
+        // The same style bit means something else for other window classes
+        let mut class = [0u16; 64];
+        let len = unsafe { GetClassNameW(info.hwndFocus, class.as_mut_ptr(), class.len() as i32) };
+        if !String::from_utf16_lossy(&class[..len.max(0) as usize]).eq_ignore_ascii_case("edit") {
+            return false;
+        }
+        let style = unsafe { GetWindowLongW(info.hwndFocus, GWL_STYLE) };
+        style & ES_PASSWORD != 0
+    }
+
+    pub fn watch() {
+        thread::spawn(|| loop {
+            update(password_focused());
+            thread::sleep(POLL_INTERVAL);
+        });
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+mod platform {
+    pub fn watch() {}
+}
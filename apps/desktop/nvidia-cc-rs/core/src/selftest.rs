@@ -0,0 +1,140 @@
+//! `selftest`: checks the whole capture and injection pipeline on this machine.
+//!
+//! The helper creates the uinput virtual keyboard `write` uses and captures
+//! it in-process. It taps F17 to F19 and checks they come back as presses and
+//! releases in order, then types a line as `write` would and checks the
+//! keyboard layout turns the captured keys back into the same text. The
+//! result is printed as JSON, one entry per check, and the exit status is 1
+//! if any failed.
+//!
+//! Linux only. It needs `/dev/uinput` and `/dev/input` access, and the typed
+//! keys reach the focused window, so run it from a terminal or an empty text
+//! field.
+
+use serde::Serialize;
+
+/// How a check went
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Fail,
+    /// Couldn't be checked on this machine, which is not a failure
+    Skip,
+}
+
+#[derive(Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    /// What went wrong, or why it was skipped
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    fn new(name: &'static str, status: Status, detail: Option<String>) -> Self {
+        Check { name, status, detail }
+    }
+}
+
+/// Run every check, in order
+pub fn run() -> Result<Vec<Check>, String> {
+    platform::run()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::time::Duration;
+
+    use enigo::Key;
+    use serde_json::Value;
+
+    use super::{Check, Status};
+    use crate::loopback::Loopback;
+
+    /// Longest wait for the next event before a check counts what it has
+    const QUIET: Duration = Duration::from_millis(500);
+
+    /// Typed by the write check: letters, shifted characters, digits and a space
+    const TEXT: &str = "Self-test 123!";
+
+    /// `<event_type> <key>` for each event
+    fn summaries(events: &[Value]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| {
+                let event_type = event["event_type"].as_str().unwrap_or_default();
+                format!("{} {}", event_type, event["key"].as_str().unwrap_or_default())
+            })
+            .collect()
+    }
+
+    fn key_sequence(loopback: &mut Loopback) -> Check {
+        let keys = [(Key::F17, "F17"), (Key::F18, "F18"), (Key::F19, "F19")];
+        for (key, _) in keys {
+            if let Err(e) = loopback.tap(key) {
+                return Check::new("key sequence", Status::Fail, Some(format!("Injecting failed: {}", e)));
+            }
+        }
+        let expected: Vec<String> =
+            keys.iter().flat_map(|(_, name)| [format!("KeyPress {}", name), format!("KeyRelease {}", name)]).collect();
+        let captured = summaries(&loopback.collect(QUIET));
+        if captured == expected {
+            Check::new("key sequence", Status::Pass, None)
+        } else {
+            let detail = format!("Expected {:?}, captured {:?}", expected, captured);
+            Check::new("key sequence", Status::Fail, Some(detail))
+        }
+    }
+
+    fn write(loopback: &mut Loopback) -> Check {
+        if let Err(e) = loopback.injector.text(TEXT) {
+            return Check::new("write", Status::Fail, Some(format!("Typing failed: {}", e)));
+        }
+        let events = loopback.collect(QUIET);
+        if events.iter().all(|event| event["text"].is_null()) {
+            let detail = "No keyboard layout was loaded, so captured keys have no text to compare".to_string();
+            return Check::new("write", Status::Skip, Some(detail));
+        }
+        let typed: String = events
+            .iter()
+            .filter(|event| event["event_type"] == "KeyPress")
+            .filter_map(|event| event["text"].as_str())
+            .collect();
+        if typed == TEXT {
+            Check::new("write", Status::Pass, None)
+        } else {
+            Check::new("write", Status::Fail, Some(format!("Typed {:?}, captured {:?}", TEXT, typed)))
+        }
+    }
+
+    pub fn run() -> Result<Vec<Check>, String> {
+        let mut loopback = match Loopback::start() {
+            Ok(loopback) => loopback,
+            Err(e) => {
+                let skipped = |name| Check::new(name, Status::Skip, Some("Capture didn't start".to_string()));
+                return Ok(vec![
+                    Check::new("capture", Status::Fail, Some(e)),
+                    skipped("key sequence"),
+                    skipped("write"),
+                ]);
+            }
+        };
+        Ok(vec![
+            Check::new("capture", Status::Pass, None),
+            key_sequence(&mut loopback),
+            write(&mut loopback),
+        ])
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::Check;
+
+    pub fn run() -> Result<Vec<Check>, String> {
+        Err("selftest injects through uinput and is only available on Linux".to_string())
+    }
+}
@@ -0,0 +1,763 @@
+//! `service install|uninstall|status`: running the helper as a user service,
+//! so it outlives app restarts and starts at login.
+//!
+//!   service install [--socket <path>] [-- <listen flags>...]
+//!   service uninstall
+//!   service status
+//!
+//! On Linux `install` writes a systemd user unit, `nvidia-cc.service` in
+//! `~/.config/systemd/user`, running `listen --socket` (by default
+//! `$XDG_RUNTIME_DIR/nvidia-cc.sock`) with whatever flags follow `--`, and
+//! enables and starts it. The unit is `Type=notify`: [`notify_ready`] tells
+//! systemd once the listener is set up, and while `WatchdogSec` is set the
+//! helper pings the watchdog for as long as events can still be emitted, so
+//! a wedged helper is restarted. The service gets the hardening options a
+//! user unit can use without user namespaces: no new privileges, a private
+//! umask, native syscalls only and no writable-executable memory.
+//!
+//! On Windows `install` registers a Task Scheduler task, started at the
+//! user's logon and restarted if it fails, that serves events on the named
+//! pipe `\\.\pipe\nvidia-cc` (or `--socket`). A task rather than a Windows
+//! service, as services run in session 0 where the user's keyboard can't be
+//! hooked. The task runs `service run`, which lets go of its console window
+//! and carries on as `listen`; stdout is discarded, so the app subscribes on
+//! the pipe and `[log] file` keeps stderr.
+//!
+//! On macOS `install` writes a LaunchAgent, `io.github.aj47.NvidiaCC.plist`
+//! in `~/Library/LaunchAgents`, running `listen --socket` (by default
+//! `$TMPDIR/nvidia-cc.sock`, resolved when installing) in the user's login
+//! session, and bootstraps it; stderr goes to `~/Library/Logs/nvidia-cc.log`.
+//! Accessibility and Input Monitoring are granted to the agent's binary, and
+//! macOS drops them when an update changes its signature, so the agent checks
+//! them as it starts and records what it found for `status`.
+//!
+//! `status` prints JSON: whether the service is installed, where, what the
+//! service manager says of it, and the socket it serves. On macOS it adds the
+//! agent's permissions, with `lost_after_update` when the binary had both
+//! before it last changed.
+
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// What `service install` sets up
+pub struct Install {
+    /// Where the service serves events; the service manager's default when unset
+    pub socket: Option<PathBuf>,
+    /// Further `listen` flags
+    pub listen_args: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Status {
+    pub installed: bool,
+    /// The unit or service definition
+    pub path: Option<PathBuf>,
+    /// As the service manager reports it, e.g. `active` or `inactive`
+    pub state: Option<String>,
+    /// Whether it starts at login
+    pub enabled: bool,
+    /// Where the service serves events, read back from its definition (not on Windows)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket: Option<PathBuf>,
+    /// What the macOS agent last found of its privacy permissions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+}
+
+#[derive(Serialize)]
+pub struct Permissions {
+    pub accessibility: bool,
+    pub input_monitoring: bool,
+    /// The agent's binary changed since it last had both, and lost them with the update
+    pub lost_after_update: bool,
+}
+
+impl Status {
+    #[cfg_attr(not(any(target_os = "linux", target_os = "macos", windows)), allow(dead_code))]
+    fn not_installed(path: Option<PathBuf>) -> Self {
+        Status {
+            installed: false,
+            path,
+            state: None,
+            enabled: false,
+            socket: None,
+            permissions: None,
+        }
+    }
+}
+
+/// Parse `[--socket <path>] [-- <listen flags>...]`
+pub fn parse_install(args: &[String]) -> Result<Install, String> {
+    let mut install = Install {
+        socket: None,
+        listen_args: Vec::new(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--socket" => install.socket = Some(PathBuf::from(args.next().ok_or("--socket requires a path")?)),
+            "--" => {
+                install.listen_args = args.cloned().collect();
+                break;
+            }
+            other => return Err(format!("Unknown service install option: {} (listen flags go after --)", other)),
+        }
+    }
+    if install.listen_args.iter().any(|arg| arg == "--socket") {
+        return Err("Give the socket as service install --socket, not as a listen flag".to_string());
+    }
+    Ok(install)
+}
+
+pub fn install(install: &Install) -> Result<Status, String> {
+    let exe = std::env::current_exe().map_err(|e| format!("Cannot find this executable: {}", e))?;
+    platform::install(&exe, install)?;
+    status()
+}
+
+pub fn uninstall() -> Result<(), String> {
+    platform::uninstall()
+}
+
+pub fn status() -> Result<Status, String> {
+    platform::status()
+}
+
+/// Tell the service manager the listener is up, and keep its watchdog fed
+///
+/// Does nothing unless the helper runs as a `Type=notify` service.
+pub fn notify_ready() {
+    platform::notify_ready();
+}
+
+/// For `service run`, what the Windows task starts: detach from the console and carry on as `listen`
+#[cfg(windows)]
+pub fn detach_console() {
+    platform::detach_console();
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::fs;
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{Install, Status};
+    use crate::stream;
+
+    const UNIT_NAME: &str = "nvidia-cc.service";
+
+    /// Restarts a helper that stops pinging for this long
+    const WATCHDOG_SECS: u64 = 30;
+
+    fn unit_path() -> Result<PathBuf, String> {
+        let dir = dirs::config_dir().ok_or("Cannot find the config directory for systemd user units")?;
+        Ok(dir.join("systemd").join("user").join(UNIT_NAME))
+    }
+
+    /// `arg` as a word of a unit's command line
+    fn quote(arg: &str) -> String {
+        let escaped = arg.replace('%', "%%");
+        if !escaped.is_empty() && !escaped.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\' || c == '\'')
+        {
+            return escaped;
+        }
+        format!("\"{}\"", escaped.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    fn unit(exe: &Path, install: &Install) -> String {
+        let socket = match &install.socket {
+            Some(path) => quote(&path.to_string_lossy()),
+            // %t is the user's runtime directory
+            None => "%t/nvidia-cc.sock".to_string(),
+        };
+        let mut command = vec![quote(&exe.to_string_lossy()), "listen".to_string(), "--socket".to_string(), socket];
+        command.extend(install.listen_args.iter().map(|arg| quote(arg)));
+        format!(
+            "[Unit]
+Description=NVIDIA Control Center input helper
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+Type=notify
+NotifyAccess=main
+ExecStart={}
+Restart=on-failure
+RestartSec=2
+WatchdogSec={}
+NoNewPrivileges=yes
+LockPersonality=yes
+RestrictSUIDSGID=yes
+MemoryDenyWriteExecute=yes
+SystemCallArchitectures=native
+UMask=0077
+
+[Install]
+WantedBy=default.target
+",
+            command.join(" "),
+            WATCHDOG_SECS,
+        )
+    }
+
+    fn systemctl(args: &[&str]) -> Result<(), String> {
+        let output = Command::new("systemctl")
+            .arg("--user")
+            .args(args)
+            .output()
+            .map_err(|e| format!("Cannot run systemctl: {}", e))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(format!("systemctl --user {} failed: {}", args.join(" "), stderr.trim()))
+        }
+    }
+
+    pub fn install(exe: &Path, install: &Install) -> Result<(), String> {
+        let path = unit_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, unit(exe, install)).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        systemctl(&["daemon-reload"])?;
+        // Restarted rather than just started, so a reinstall runs with the new unit
+        systemctl(&["enable", UNIT_NAME])?;
+        systemctl(&["restart", UNIT_NAME])?;
+        Ok(())
+    }
+
+    pub fn uninstall() -> Result<(), String> {
+        let path = unit_path()?;
+        if !path.exists() {
+            return Err(format!("{} isn't installed ({})", UNIT_NAME, path.display()));
+        }
+        systemctl(&["disable", "--now", UNIT_NAME])?;
+        fs::remove_file(&path).map_err(|e| format!("Cannot remove {}: {}", path.display(), e))?;
+        systemctl(&["daemon-reload"])
+    }
+
+    pub fn status() -> Result<Status, String> {
+        let path = unit_path()?;
+        let Ok(unit) = fs::read_to_string(&path) else {
+            return Ok(Status::not_installed(Some(path)));
+        };
+        // Both exit non-zero for inactive and disabled units, still printing the state
+        let state_of = |query: &str| {
+            Command::new("systemctl")
+                .args(["--user", query, UNIT_NAME])
+                .output()
+                .ok()
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                .filter(|state| !state.is_empty())
+        };
+        Ok(Status {
+            installed: true,
+            path: Some(path),
+            state: state_of("is-active"),
+            enabled: state_of("is-enabled").as_deref() == Some("enabled"),
+            socket: unit_socket(&unit),
+            permissions: None,
+        })
+    }
+
+    /// The `--socket` of a unit's ExecStart, with the specifiers [`unit`] writes expanded
+    fn unit_socket(unit: &str) -> Option<PathBuf> {
+        let command = unit.lines().find_map(|line| line.strip_prefix("ExecStart="))?;
+        let mut words = command.split_whitespace();
+        words.find(|&word| word == "--socket")?;
+        let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_default();
+        let parts: Vec<String> = words
+            .next()?
+            .trim_matches('"')
+            .split("%%")
+            .map(|part| part.replace("%t", &runtime_dir))
+            .collect();
+        Some(PathBuf::from(parts.join("%")))
+    }
+
+    fn notify(socket: &UnixDatagram, message: &str) -> bool {
+        socket.send(message.as_bytes()).is_ok()
+    }
+
+    pub fn notify_ready() {
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else { return };
+        let path = path.to_string_lossy().into_owned();
+        let address = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(&path),
+        };
+        let Ok(socket) = UnixDatagram::unbound() else { return };
+        if address.and_then(|address| socket.connect_addr(&address)).is_err() || !notify(&socket, "READY=1") {
+            eprintln!("Cannot notify the service manager on {}", path);
+            return;
+        }
+
+        let watchdog_usec = std::env::var("WATCHDOG_USEC").ok().and_then(|usec| usec.parse::<u64>().ok());
+        let for_us = std::env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string());
+        let Some(watchdog_usec) = watchdog_usec.filter(|&usec| usec > 0 && for_us) else { return };
+        thread::spawn(move || loop {
+            // Pinging twice per period, as sd_watchdog_enabled(3) advises
+            thread::sleep(Duration::from_micros(watchdog_usec / 2));
+            // Blocks, and so stops the pings, if event emission is wedged
+            stream::last_seq();
+            if !notify(&socket, "WATCHDOG=1") {
+                return;
+            }
+        });
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::fs::{self, OpenOptions};
+    use std::os::windows::io::IntoRawHandle;
+    use std::path::Path;
+    use std::process::Command;
+    use windows_sys::Win32::Storage::FileSystem::{GetFileType, FILE_TYPE_CHAR};
+    use windows_sys::Win32::System::Console::{
+        FreeConsole, GetStdHandle, SetStdHandle, STD_ERROR_HANDLE, STD_HANDLE, STD_INPUT_HANDLE, STD_OUTPUT_HANDLE,
+    };
+
+    use super::{Install, Status};
+
+    const TASK_NAME: &str = "NVIDIA Control Center helper";
+
+    const DEFAULT_PIPE: &str = r"\\.\pipe\nvidia-cc";
+
+    fn xml_escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    /// `arg` as CommandLineToArgvW reads it back
+    fn quote(arg: &str) -> String {
+        if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+            return arg.to_string();
+        }
+        let mut quoted = String::from('"');
+        let mut backslashes = 0;
+        for c in arg.chars() {
+            match c {
+                '\\' => backslashes += 1,
+                '"' => {
+                    quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                    backslashes = 0;
+                }
+                _ => {
+                    quoted.extend(std::iter::repeat_n('\\', backslashes));
+                    backslashes = 0;
+                }
+            }
+            if c != '\\' {
+                quoted.push(c);
+            }
+        }
+        quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+        quoted.push('"');
+        quoted
+    }
+
+    /// A task started at this user's logon, restarted when it fails, and never stopped for running long
+    fn task(exe: &Path, install: &Install, user: &str) -> String {
+        let pipe = install.socket.as_ref().map_or(DEFAULT_PIPE.to_string(), |path| path.display().to_string());
+        let mut arguments = vec!["service".to_string(), "run".to_string(), "--socket".to_string(), quote(&pipe)];
+        arguments.extend(install.listen_args.iter().map(|arg| quote(arg)));
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>Captures keys for NVIDIA Control Center and serves them on {pipe}</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+      <UserId>{user}</UserId>
+    </LogonTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>{user}</UserId>
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>LeastPrivilege</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <RestartOnFailure>
+      <Interval>PT1M</Interval>
+      <Count>999</Count>
+    </RestartOnFailure>
+    <Priority>4</Priority>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{command}</Command>
+      <Arguments>{arguments}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+            pipe = xml_escape(&pipe),
+            user = xml_escape(user),
+            command = xml_escape(&exe.display().to_string()),
+            arguments = xml_escape(&arguments.join(" ")),
+        )
+    }
+
+    fn schtasks(args: &[&str]) -> Result<String, String> {
+        let output = Command::new("schtasks")
+            .args(args)
+            .output()
+            .map_err(|e| format!("Cannot run schtasks: {}", e))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(format!("schtasks {} failed: {}", args[0], stderr.trim()))
+        }
+    }
+
+    pub fn install(exe: &Path, install: &Install) -> Result<(), String> {
+        let user = match (std::env::var("USERDOMAIN"), std::env::var("USERNAME")) {
+            (Ok(domain), Ok(name)) => format!("{}\\{}", domain, name),
+            _ => return Err("Cannot tell the current user (USERDOMAIN and USERNAME are unset)".to_string()),
+        };
+        // schtasks reads task XML as UTF-16
+        let mut xml = vec![0xFF, 0xFE];
+        xml.extend(task(exe, install, &user).encode_utf16().flat_map(u16::to_le_bytes));
+        let path = std::env::temp_dir().join(format!("nvidia-cc-task-{}.xml", std::process::id()));
+        fs::write(&path, xml).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        let created = schtasks(&["/Create", "/TN", TASK_NAME, "/XML", &path.to_string_lossy(), "/F"]);
+        fs::remove_file(&path).ok();
+        created?;
+        // Ended first, so a reinstall runs with the new arguments
+        schtasks(&["/End", "/TN", TASK_NAME]).ok();
+        schtasks(&["/Run", "/TN", TASK_NAME]).map(|_| ())
+    }
+
+    pub fn uninstall() -> Result<(), String> {
+        if status()?.installed {
+            schtasks(&["/End", "/TN", TASK_NAME]).ok();
+            schtasks(&["/Delete", "/TN", TASK_NAME, "/F"]).map(|_| ())
+        } else {
+            Err(format!("The {} task isn't installed", TASK_NAME))
+        }
+    }
+
+    pub fn status() -> Result<Status, String> {
+        // Fails when there is no such task
+        let Ok(query) = schtasks(&["/Query", "/TN", TASK_NAME, "/FO", "CSV", "/NH"]) else {
+            return Ok(Status::not_installed(None));
+        };
+        // "\NVIDIA Control Center helper","<next run time>","Running"
+        let state = query.lines().next().and_then(|line| line.rsplit(',').next()).map(|state| {
+            state.trim_matches('"').to_lowercase()
+        });
+        Ok(Status {
+            installed: true,
+            path: None,
+            enabled: state.as_deref() != Some("disabled"),
+            state,
+            socket: None,
+            permissions: None,
+        })
+    }
+
+    pub fn notify_ready() {}
+
+    /// Let go of the console the task was started with, so no window stays open for the helper
+    ///
+    /// Standard handles still on the console go to NUL; a `[log] file` keeps stderr.
+    pub fn detach_console() {
+        for (id, write) in [(STD_INPUT_HANDLE, false), (STD_OUTPUT_HANDLE, true), (STD_ERROR_HANDLE, true)] {
+            let on_console = unsafe { GetFileType(GetStdHandle(id)) } == FILE_TYPE_CHAR;
+            if on_console {
+                redirect_to_nul(id, write);
+            }
+        }
+        unsafe { FreeConsole() };
+    }
+
+    fn redirect_to_nul(id: STD_HANDLE, write: bool) {
+        if let Ok(nul) = OpenOptions::new().read(!write).write(write).open("NUL") {
+            unsafe { SetStdHandle(id, nul.into_raw_handle()) };
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use serde::{Deserialize, Serialize};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use std::time::UNIX_EPOCH;
+
+    use super::{Install, Permissions, Status};
+
+    const LABEL: &str = "io.github.aj47.NvidiaCC";
+
+    /// `IOHIDRequestType` for reading keys, and the `IOHIDAccessType` of a granted request
+    const HID_REQUEST_LISTEN_EVENT: u32 = 1;
+    const HID_ACCESS_GRANTED: u32 = 0;
+
+    #[link(name = "ApplicationServices", kind = "framework")]
+    extern "C" {
+        fn AXIsProcessTrusted() -> u8;
+    }
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOHIDCheckAccess(request: u32) -> u32;
+    }
+
+    /// What the agent found of its grants when it last started
+    #[derive(Serialize, Deserialize)]
+    struct AgentPermissions {
+        accessibility: bool,
+        input_monitoring: bool,
+        /// Modification time of the agent's binary, in seconds since the epoch
+        exe_modified: Option<u64>,
+        /// `exe_modified` when it last had both
+        granted_exe_modified: Option<u64>,
+    }
+
+    fn home() -> Result<PathBuf, String> {
+        dirs::home_dir().ok_or_else(|| "Cannot find the home directory".to_string())
+    }
+
+    fn plist_path() -> Result<PathBuf, String> {
+        Ok(home()?.join("Library").join("LaunchAgents").join(format!("{}.plist", LABEL)))
+    }
+
+    fn permissions_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("nvidia-cc").join("agent-permissions.json"))
+    }
+
+    /// The launchd domain of this user's GUI session
+    fn domain() -> String {
+        format!("gui/{}", unsafe { libc::getuid() })
+    }
+
+    fn xml_escape(text: &str) -> String {
+        text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+    }
+
+    fn xml_unescape(text: &str) -> String {
+        text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+    }
+
+    /// An agent for the user's login session, restarted unless it exits cleanly
+    fn plist(exe: &Path, socket: &Path, install: &Install, log: &Path) -> String {
+        let mut arguments = vec![exe.display().to_string(), "listen".to_string(), "--socket".to_string()];
+        arguments.push(socket.display().to_string());
+        arguments.extend(install.listen_args.iter().cloned());
+        let arguments: String = arguments
+            .iter()
+            .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+            .collect();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ProcessType</key>
+    <string>Interactive</string>
+    <key>LimitLoadToSessionType</key>
+    <string>Aqua</string>
+    <key>StandardErrorPath</key>
+    <string>{log}</string>
+</dict>
+</plist>
+"#,
+            label = LABEL,
+            log = xml_escape(&log.display().to_string()),
+        )
+    }
+
+    /// The `--socket` among a plist's ProgramArguments
+    fn plist_socket(plist: &str) -> Option<PathBuf> {
+        let mut strings = plist
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("<string>")?.strip_suffix("</string>"));
+        strings.find(|&string| string == "--socket")?;
+        strings.next().map(|socket| PathBuf::from(xml_unescape(socket)))
+    }
+
+    fn launchctl(args: &[&str]) -> Result<String, String> {
+        let output = Command::new("launchctl")
+            .args(args)
+            .output()
+            .map_err(|e| format!("Cannot run launchctl: {}", e))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(format!("launchctl {} failed: {}", args.join(" "), stderr.trim()))
+        }
+    }
+
+    pub fn install(exe: &Path, install: &Install) -> Result<(), String> {
+        let path = plist_path()?;
+        let log = home()?.join("Library").join("Logs").join("nvidia-cc.log");
+        // Resolved now: the agent's TMPDIR is the same per-user directory as this shell's
+        let socket = install.socket.clone().unwrap_or_else(|| std::env::temp_dir().join("nvidia-cc.sock"));
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, plist(exe, &socket, install, &log))
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        let domain = domain();
+        let service = format!("{}/{}", domain, LABEL);
+        // Booted out first, so a reinstall runs with the new plist; fails when it wasn't loaded
+        launchctl(&["bootout", &service]).ok();
+        launchctl(&["enable", &service])?;
+        launchctl(&["bootstrap", &domain, &path.to_string_lossy()]).map(|_| ())
+    }
+
+    pub fn uninstall() -> Result<(), String> {
+        let path = plist_path()?;
+        if !path.exists() {
+            return Err(format!("{} isn't installed ({})", LABEL, path.display()));
+        }
+        launchctl(&["bootout", &format!("{}/{}", domain(), LABEL)]).ok();
+        fs::remove_file(&path).map_err(|e| format!("Cannot remove {}: {}", path.display(), e))
+    }
+
+    pub fn status() -> Result<Status, String> {
+        let path = plist_path()?;
+        let Ok(plist) = fs::read_to_string(&path) else {
+            return Ok(Status::not_installed(Some(path)));
+        };
+        // Fails when the agent isn't loaded
+        let state = match launchctl(&["print", &format!("{}/{}", domain(), LABEL)]) {
+            Ok(print) => print.lines().find_map(|line| line.trim().strip_prefix("state = ").map(str::to_string)),
+            Err(_) => Some("not loaded".to_string()),
+        };
+        // "io.github.aj47.NvidiaCC" => disabled (or => true before macOS 13)
+        let disabled = launchctl(&["print-disabled", &domain()]).is_ok_and(|print| {
+            print.lines().any(|line| {
+                line.contains(&format!("\"{}\"", LABEL)) && (line.ends_with("disabled") || line.ends_with("true"))
+            })
+        });
+        Ok(Status {
+            installed: true,
+            path: Some(path),
+            state,
+            enabled: !disabled,
+            socket: plist_socket(&plist),
+            permissions: agent_permissions(),
+        })
+    }
+
+    /// The grants the agent recorded; this process's own would be those of the terminal it runs in
+    fn agent_permissions() -> Option<Permissions> {
+        let recorded = fs::read_to_string(permissions_path()?).ok()?;
+        let recorded: AgentPermissions = serde_json::from_str(&recorded).ok()?;
+        let missing = !recorded.accessibility || !recorded.input_monitoring;
+        Some(Permissions {
+            accessibility: recorded.accessibility,
+            input_monitoring: recorded.input_monitoring,
+            lost_after_update: missing
+                && recorded.granted_exe_modified.is_some()
+                && recorded.granted_exe_modified != recorded.exe_modified,
+        })
+    }
+
+    fn exe_modified() -> Option<u64> {
+        let modified = std::env::current_exe().ok()?.metadata().ok()?.modified().ok()?;
+        modified.duration_since(UNIX_EPOCH).ok().map(|since| since.as_secs())
+    }
+
+    /// Record the agent's grants for `status`, and warn when one is missing
+    ///
+    /// TCC ties grants to the binary's code signature, so an update that re-signs
+    /// it (or an ad-hoc signed rebuild) silently loses them.
+    pub fn notify_ready() {
+        if std::env::var("XPC_SERVICE_NAME").as_deref() != Ok(LABEL) {
+            return;
+        }
+        let Some(path) = permissions_path() else { return };
+        let previous: Option<AgentPermissions> =
+            fs::read_to_string(&path).ok().and_then(|recorded| serde_json::from_str(&recorded).ok());
+        let accessibility = unsafe { AXIsProcessTrusted() } != 0;
+        let input_monitoring = unsafe { IOHIDCheckAccess(HID_REQUEST_LISTEN_EVENT) } == HID_ACCESS_GRANTED;
+        let exe_modified = exe_modified();
+        let granted_exe_modified = if accessibility && input_monitoring {
+            exe_modified
+        } else {
+            previous.and_then(|previous| previous.granted_exe_modified)
+        };
+        if !accessibility || !input_monitoring {
+            let lost = granted_exe_modified.is_some() && granted_exe_modified != exe_modified;
+            eprintln!(
+                "The agent is missing the {} permission{} (System Settings > Privacy & Security)",
+                match (accessibility, input_monitoring) {
+                    (false, false) => "Accessibility and Input Monitoring",
+                    (false, true) => "Accessibility",
+                    _ => "Input Monitoring",
+                },
+                if lost { ", lost when its binary was updated; grant it again" } else { "" },
+            );
+        }
+        let recorded = AgentPermissions {
+            accessibility,
+            input_monitoring,
+            exe_modified,
+            granted_exe_modified,
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+        if let Err(e) = fs::write(&path, serde_json::to_string(&recorded).unwrap_or_default()) {
+            eprintln!("Cannot record the agent's permissions in {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use std::path::Path;
+
+    use super::{Install, Status};
+
+    const UNSUPPORTED: &str =
+        "service is only supported with systemd on Linux, launchd on macOS and Task Scheduler on Windows";
+
+    pub fn install(_exe: &Path, _install: &Install) -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn uninstall() -> Result<(), String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn status() -> Result<Status, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+
+    pub fn notify_ready() {}
+}
@@ -0,0 +1,344 @@
+//! Session lock tracking for `SessionLocked`/`SessionUnlocked` events, and on
+//! Linux which keys belong to our session at all.
+//!
+//! With `--pause-when-locked` capture is paused for as long as the session is
+//! locked, so nothing typed at the lock screen (the password, on Linux where
+//! evdev sees every key) reaches the app. A pause the app asked for itself is
+//! left alone: unlocking only resumes capture the lock paused.
+//!
+//! Sources: logind on Linux, both its `Lock`/`Unlock` signals and the
+//! `LockedHint` screen lockers set on the session (`gdbus monitor`); WTS
+//! session notifications on Windows; and the `com.apple.screenIsLocked`/
+//! `com.apple.screenIsUnlocked` distributed notifications on macOS, which are
+//! delivered on the main thread's run loop.
+//!
+//! evdev sees every keyboard on the machine, so on multi-seat and
+//! fast-user-switching systems it would also capture what other users type.
+//! Unless `--all-sessions` is given, the Linux listener only opens devices on
+//! our seat (`XDG_SEAT`, from udev's `ID_SEAT`) and drops key events while
+//! logind reports our session inactive, e.g. switched away from.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::control;
+use crate::event::{Event, EventKind};
+use crate::hotkeys;
+use crate::hotstrings;
+use crate::stream;
+
+static LOCKED: AtomicBool = AtomicBool::new(false);
+static ACTIVE: AtomicBool = AtomicBool::new(true);
+static ALL_SESSIONS: AtomicBool = AtomicBool::new(false);
+static PAUSE_WHEN_LOCKED: AtomicBool = AtomicBool::new(false);
+/// Whether the current pause is the lock's, so unlocking doesn't undo a stdin `pause`
+static PAUSED_BY_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// `source` of the `Paused`/`Resumed` events the lock causes
+pub const PAUSE_SOURCE: &str = "session-lock";
+
+/// Start emitting `SessionLocked`/`SessionUnlocked`, pausing capture while locked if asked to
+pub fn watch(pause_when_locked: bool) {
+    PAUSE_WHEN_LOCKED.store(pause_when_locked, Ordering::SeqCst);
+    platform::watch();
+}
+
+/// Whether the session is locked, as far as the watcher knows
+pub fn is_locked() -> bool {
+    LOCKED.load(Ordering::SeqCst)
+}
+
+/// Capture keys typed in other sessions and on other seats too (Linux)
+pub fn set_all_sessions(all_sessions: bool) {
+    ALL_SESSIONS.store(all_sessions, Ordering::SeqCst);
+}
+
+/// Whether keys typed now are our session's: it is active, or `--all-sessions` is on
+pub fn owns_input() -> bool {
+    ALL_SESSIONS.load(Ordering::SeqCst) || ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Whether a device is on our seat; every device is with `--all-sessions` or outside a seat
+#[cfg(target_os = "linux")]
+pub fn owns_device(path: &std::path::Path) -> bool {
+    if ALL_SESSIONS.load(Ordering::SeqCst) {
+        return true;
+    }
+    match std::env::var("XDG_SEAT") {
+        Ok(ours) if !ours.is_empty() => platform::device_seat(path) == ours,
+        _ => true,
+    }
+}
+
+/// Whether capture has to stay paused: the session is locked and `--pause-when-locked` is on
+pub fn holds_pause() -> bool {
+    PAUSE_WHEN_LOCKED.load(Ordering::SeqCst) && is_locked()
+}
+
+/// Leave the current pause in place on unlock, as a pause the app asked for
+pub fn release_pause() {
+    PAUSED_BY_LOCK.store(false, Ordering::SeqCst);
+}
+
+fn locked() {
+    // Sources can report the same transition more than once
+    if LOCKED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    eprintln!("Session locked");
+    if PAUSE_WHEN_LOCKED.load(Ordering::SeqCst) && !control::is_paused() {
+        PAUSED_BY_LOCK.store(true, Ordering::SeqCst);
+        control::set_paused(true, PAUSE_SOURCE);
+    }
+    stream::emit(&Event::now(EventKind::SessionLocked {}));
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn set_active(active: bool) {
+    if ACTIVE.swap(active, Ordering::SeqCst) == active {
+        return;
+    }
+    eprintln!("Session {}", if active { "active" } else { "inactive" });
+    if active {
+        // Presses and releases elsewhere were never seen
+        hotkeys::reset();
+        hotstrings::reset();
+    }
+}
+
+fn unlocked() {
+    if !LOCKED.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    eprintln!("Session unlocked");
+    stream::emit(&Event::now(EventKind::SessionUnlocked {}));
+    if PAUSED_BY_LOCK.swap(false, Ordering::SeqCst) {
+        control::set_paused(false, PAUSE_SOURCE);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::env;
+    use std::fs;
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    pub fn watch() {
+        thread::spawn(|| {
+            if let Err(e) = logind() {
+                eprintln!("Session lock tracking unavailable: {}", e);
+            }
+        });
+    }
+
+    /// D-Bus object path of a logind session id: `2` becomes `_32`, as sd_bus_path_encode does
+    fn session_path(id: &str) -> String {
+        let mut path = String::from("/org/freedesktop/login1/session/");
+        for (i, byte) in id.bytes().enumerate() {
+            if byte.is_ascii_alphabetic() || (byte.is_ascii_digit() && i > 0) {
+                path.push(byte as char);
+            } else {
+                path.push_str(&format!("_{:02x}", byte));
+            }
+        }
+        path
+    }
+
+    /// udev's `ID_SEAT` for an event node; devices without one are on `seat0`
+    pub fn device_seat(path: &Path) -> String {
+        let rdev = match fs::metadata(path) {
+            Ok(metadata) => metadata.rdev(),
+            Err(_) => return "seat0".to_string(),
+        };
+        let data = format!("/run/udev/data/c{}:{}", libc::major(rdev), libc::minor(rdev));
+        fs::read_to_string(data)
+            .ok()
+            .and_then(|data| data.lines().find_map(|line| line.strip_prefix("E:ID_SEAT=")).map(String::from))
+            .unwrap_or_else(|| "seat0".to_string())
+    }
+
+    /// The session's `Active` property when the watch starts; changes arrive with the rest
+    fn query_active(path: &str) -> Option<bool> {
+        let output = Command::new("gdbus")
+            .args(["call", "--system", "--dest", "org.freedesktop.login1", "--object-path", path])
+            .args(["--method", "org.freedesktop.DBus.Properties.Get", "org.freedesktop.login1.Session", "Active"])
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        // (<true>,)
+        match String::from_utf8_lossy(&output.stdout).trim() {
+            "(<true>,)" => Some(true),
+            "(<false>,)" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn logind() -> Result<(), String> {
+        let id = env::var("XDG_SESSION_ID").map_err(|_| "not in a logind session (no XDG_SESSION_ID)".to_string())?;
+        let path = session_path(&id);
+        if let Some(active) = query_active(&path) {
+            super::set_active(active);
+        }
+        let mut child = Command::new("gdbus")
+            .args(["monitor", "--system", "--dest", "org.freedesktop.login1", "--object-path", &path])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("failed to run gdbus: {}", e))?;
+        let stdout = child.stdout.take().ok_or("gdbus has no stdout")?;
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            // <path>: org.freedesktop.login1.Session.Lock ()
+            // <path>: org.freedesktop.DBus.Properties.PropertiesChanged ('org.freedesktop.login1.Session',
+            //         {'LockedHint': <true>}, @as [])
+            if line.ends_with(".Session.Lock ()") || line.contains("'LockedHint': <true>") {
+                super::locked();
+            } else if line.ends_with(".Session.Unlock ()") || line.contains("'LockedHint': <false>") {
+                super::unlocked();
+            }
+            if line.contains("'Active': <true>") {
+                super::set_active(true);
+            } else if line.contains("'Active': <false>") {
+                super::set_active(false);
+            }
+        }
+        child.wait().ok();
+        Err("gdbus exited".to_string())
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::ptr;
+    use std::thread;
+
+    use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+    use windows_sys::Win32::System::RemoteDesktop::{WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, HWND_MESSAGE, MSG, WNDCLASSW,
+        WM_WTSSESSION_CHANGE, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+    };
+
+    unsafe extern "system" fn window_proc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if message == WM_WTSSESSION_CHANGE {
+            match wparam as u32 {
+                WTS_SESSION_LOCK => super::locked(),
+                WTS_SESSION_UNLOCK => super::unlocked(),
+                _ => {}
+            }
+            return 0;
+        }
+        DefWindowProcW(window, message, wparam, lparam)
+    }
+
+    pub fn watch() {
+        thread::spawn(|| {
+            // Session notifications need a window; a message-only one is never shown
+            let class_name: Vec<u16> = "nvidia-cc-session\0".encode_utf16().collect();
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                lpszClassName: class_name.as_ptr(),
+                ..unsafe { std::mem::zeroed() }
+            };
+            let window = unsafe {
+                RegisterClassW(&class);
+                CreateWindowExW(
+                    0,
+                    class_name.as_ptr(),
+                    ptr::null(),
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    HWND_MESSAGE,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null(),
+                )
+            };
+            if window.is_null() || unsafe { WTSRegisterSessionNotification(window, NOTIFY_FOR_THIS_SESSION) } == 0 {
+                eprintln!("Session lock tracking unavailable: WTSRegisterSessionNotification failed");
+                return;
+            }
+            let mut message: MSG = unsafe { std::mem::zeroed() };
+            while unsafe { GetMessageW(&mut message, ptr::null_mut(), 0, 0) } > 0 {
+                unsafe { DispatchMessageW(&message) };
+            }
+        });
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void};
+    use std::ptr;
+
+    type CFTypeRef = *const c_void;
+    type CFStringRef = *const c_void;
+    type CFNotificationCallback = extern "C" fn(CFTypeRef, *const c_void, CFStringRef, *const c_void, CFTypeRef);
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const CF_NOTIFICATION_SUSPENSION_BEHAVIOR_DELIVER_IMMEDIATELY: isize = 4;
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFStringCreateWithCString(allocator: CFTypeRef, string: *const c_char, encoding: u32) -> CFStringRef;
+        fn CFNotificationCenterGetDistributedCenter() -> CFTypeRef;
+        fn CFNotificationCenterAddObserver(
+            center: CFTypeRef,
+            observer: *const c_void,
+            callback: CFNotificationCallback,
+            name: CFStringRef,
+            object: *const c_void,
+            suspension_behavior: isize,
+        );
+    }
+
+    extern "C" fn screen_locked(
+        _center: CFTypeRef,
+        _observer: *const c_void,
+        _name: CFStringRef,
+        _object: *const c_void,
+        _user_info: CFTypeRef,
+    ) {
+        super::locked();
+    }
+
+    extern "C" fn screen_unlocked(
+        _center: CFTypeRef,
+        _observer: *const c_void,
+        _name: CFStringRef,
+        _object: *const c_void,
+        _user_info: CFTypeRef,
+    ) {
+        super::unlocked();
+    }
+
+    pub fn watch() {
+        for (name, callback) in [
+            (c"com.apple.screenIsLocked", screen_locked as CFNotificationCallback),
+            (c"com.apple.screenIsUnlocked", screen_unlocked as CFNotificationCallback),
+        ] {
+            unsafe {
+                // Observed for the life of the process, so the name is never released
+                let name = CFStringCreateWithCString(ptr::null(), name.as_ptr(), CF_STRING_ENCODING_UTF8);
+                CFNotificationCenterAddObserver(
+                    CFNotificationCenterGetDistributedCenter(),
+                    ptr::null(),
+                    callback,
+                    name,
+                    ptr::null(),
+                    CF_NOTIFICATION_SUSPENSION_BEHAVIOR_DELIVER_IMMEDIATELY,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows, target_os = "macos")))]
+mod platform {
+    pub fn watch() {}
+}
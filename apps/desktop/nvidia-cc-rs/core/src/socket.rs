@@ -0,0 +1,319 @@
+//! Local socket transport for the event stream: a Unix domain socket, or on
+//! Windows a named pipe such as `\\.\pipe\nvidia-cc`.
+//!
+//! Clients connect and send a single command line:
+//!   subscribe                 - stream live events only
+//!   subscribe --since-seq N   - replay buffered events after N, then stream live events;
+//!                               `ReplayGap` comes first if some were lost, or if N is past
+//!                               the last sequence, as after the helper restarted
+//!
+//! Adding `--compress zstd` makes everything after the command line one zstd
+//! stream, flushed after every event so nothing waits for a block to fill.
+//! If libzstd can't be loaded the client gets an uncompressed `Error`.
+//!
+//! The stream carries every keystroke, so only the owning user may connect:
+//! the socket is created 0600, the pipe with an owner-only DACL that also
+//! turns away remote clients.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+use crate::zstd;
+
+/// A subscriber that can't accept a line within this window is disconnected
+#[cfg_attr(windows, allow(dead_code))]
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A connected client of the platform's transport
+trait Connection: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+
+    /// Keep a client that stops reading from stalling capture, once it has subscribed
+    fn limit_writes(&self) -> io::Result<()>;
+}
+
+/// Bind `path` and accept subscribers on a background thread
+pub fn serve(path: &Path) -> io::Result<()> {
+    let mut listener = platform::Listener::bind(path)?;
+    eprintln!("Serving events on {}", path.display());
+
+    thread::spawn(move || loop {
+        match listener.accept() {
+            Ok(client) => {
+                thread::spawn(move || {
+                    if let Err(e) = handle_client(client) {
+                        eprintln!("Socket client error: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to accept socket client: {}", e),
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_client<C: Connection>(client: C) -> io::Result<()> {
+    let mut command = String::new();
+    BufReader::new(client.try_clone()?).read_line(&mut command)?;
+    client.limit_writes()?;
+    answer(command.trim(), client)
+}
+
+/// Stream events to `client` as its `subscribe` command line asks, or answer an `Error` event;
+/// also serves [`crate::tcp`] clients once authenticated
+pub(crate) fn answer<W: Write + Send + 'static>(command: &str, mut client: W) -> io::Result<()> {
+    let subscribe = parse_subscribe(command).and_then(|subscribe| {
+        if subscribe.compress {
+            zstd::require()?;
+        }
+        Ok(subscribe)
+    });
+    match subscribe {
+        Ok(subscribe) => {
+            let sink: Box<dyn Write + Send> = if subscribe.compress {
+                Box::new(zstd::Encoder::new(client).map_err(io::Error::other)?)
+            } else {
+                Box::new(client)
+            };
+            stream::subscribe(sink, subscribe.since_seq)
+        }
+        Err(message) => {
+            let error_event = Event::now(EventKind::Error {
+                error: "InvalidCommand".to_string(),
+                message,
+            });
+            writeln!(client, "{}", serde_json::to_string(&error_event).unwrap())
+        }
+    }
+}
+
+#[derive(Default)]
+struct Subscribe {
+    /// Sequence to resume after
+    since_seq: Option<u64>,
+    compress: bool,
+}
+
+/// Parse `subscribe [--since-seq N] [--compress zstd]`
+fn parse_subscribe(command: &str) -> Result<Subscribe, String> {
+    let mut parts = command.split_whitespace();
+    if parts.next() != Some("subscribe") {
+        return Err(format!("Expected 'subscribe', got '{}'", command));
+    }
+
+    let mut subscribe = Subscribe::default();
+    while let Some(part) = parts.next() {
+        match (part, parts.next()) {
+            ("--since-seq", Some(seq)) => {
+                let seq = seq.parse().map_err(|_| format!("Invalid sequence number: {}", seq))?;
+                subscribe.since_seq = Some(seq);
+            }
+            ("--compress", Some("zstd")) => subscribe.compress = true,
+            ("--compress", Some(other)) => return Err(format!("Unknown compression: {} (expected zstd)", other)),
+            _ => return Err("Usage: subscribe [--since-seq N] [--compress zstd]".to_string()),
+        }
+    }
+    Ok(subscribe)
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+
+    use super::{Connection, CLIENT_WRITE_TIMEOUT};
+
+    pub struct Listener(UnixListener);
+
+    impl Listener {
+        pub fn bind(path: &Path) -> io::Result<Self> {
+            // Only clean up a stale socket left behind by a previous run, never a regular file
+            if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                fs::remove_file(path)?;
+            }
+            let listener = UnixListener::bind(path)?;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            Ok(Listener(listener))
+        }
+
+        pub fn accept(&mut self) -> io::Result<UnixStream> {
+            self.0.accept().map(|(client, _)| client)
+        }
+    }
+
+    impl Connection for UnixStream {
+        fn try_clone(&self) -> io::Result<Self> {
+            UnixStream::try_clone(self)
+        }
+
+        fn limit_writes(&self) -> io::Result<()> {
+            self.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::io::{self, Read, Write};
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle};
+    use std::path::Path;
+    use std::ptr;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use windows_sys::Win32::Foundation::{
+        GetLastError, LocalFree, ERROR_BROKEN_PIPE, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE,
+    };
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+    };
+    use windows_sys::Win32::Security::SECURITY_ATTRIBUTES;
+    use windows_sys::Win32::Storage::FileSystem::{
+        ReadFile, WriteFile, FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows_sys::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, SetNamedPipeHandleState, PIPE_NOWAIT, PIPE_READMODE_BYTE,
+        PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    use super::Connection;
+
+    /// Full access for the pipe's owner and SYSTEM only
+    const SECURITY_DESCRIPTOR: &str = "D:P(A;;GA;;;OW)(A;;GA;;;SY)";
+
+    /// Events queued for a client before it counts as not reading
+    const OUT_BUFFER_BYTES: u32 = 256 * 1024;
+    const IN_BUFFER_BYTES: u32 = 4096;
+
+    /// Between attempts when pipe instances can't be created
+    const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+    fn wide(text: &std::ffi::OsStr) -> Vec<u16> {
+        text.encode_wide().chain(Some(0)).collect()
+    }
+
+    /// A new instance of the pipe `name`; the first one fails if another process already serves the name
+    fn create(name: &[u16], first: bool) -> io::Result<OwnedHandle> {
+        let descriptor = wide(SECURITY_DESCRIPTOR.as_ref());
+        let mut security = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: ptr::null_mut(),
+            bInheritHandle: 0,
+        };
+        let converted = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                descriptor.as_ptr(),
+                SDDL_REVISION_1,
+                &mut security.lpSecurityDescriptor,
+                ptr::null_mut(),
+            )
+        };
+        if converted == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let open_mode = PIPE_ACCESS_DUPLEX | if first { FILE_FLAG_FIRST_PIPE_INSTANCE } else { 0 };
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                open_mode,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                OUT_BUFFER_BYTES,
+                IN_BUFFER_BYTES,
+                0,
+                &security,
+            )
+        };
+        let error = io::Error::last_os_error();
+        unsafe { LocalFree(security.lpSecurityDescriptor) };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(error);
+        }
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle) })
+    }
+
+    pub struct Listener {
+        name: Vec<u16>,
+        /// The instance the next client connects to
+        next: Option<OwnedHandle>,
+    }
+
+    impl Listener {
+        pub fn bind(path: &Path) -> io::Result<Self> {
+            let name = wide(path.as_os_str());
+            // Created now, so a name already in use fails at startup
+            let next = Some(create(&name, true)?);
+            Ok(Listener { name, next })
+        }
+
+        pub fn accept(&mut self) -> io::Result<Pipe> {
+            let handle = match self.next.take() {
+                Some(handle) => handle,
+                None => create(&self.name, false).inspect_err(|_| thread::sleep(RETRY_DELAY))?,
+            };
+            let connected = unsafe { ConnectNamedPipe(handle.as_raw_handle(), ptr::null_mut()) } != 0;
+            // A client that connected between creation and ConnectNamedPipe is connected all the same
+            if !connected && unsafe { GetLastError() } != ERROR_PIPE_CONNECTED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Pipe(Arc::new(handle)))
+        }
+    }
+
+    /// The server end of a connected pipe instance
+    pub struct Pipe(Arc<OwnedHandle>);
+
+    impl Read for Pipe {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let mut read = 0;
+            let len = buffer.len().min(u32::MAX as usize) as u32;
+            if unsafe { ReadFile(self.0.as_raw_handle(), buffer.as_mut_ptr(), len, &mut read, ptr::null_mut()) } == 0 {
+                return match unsafe { GetLastError() } {
+                    ERROR_BROKEN_PIPE => Ok(0),
+                    _ => Err(io::Error::last_os_error()),
+                };
+            }
+            Ok(read as usize)
+        }
+    }
+
+    impl Write for Pipe {
+        /// Once writes are limited, what doesn't fit the pipe's buffer is refused, ending in a WriteZero error
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            let mut written = 0;
+            let len = buffer.len().min(u32::MAX as usize) as u32;
+            if unsafe { WriteFile(self.0.as_raw_handle(), buffer.as_ptr(), len, &mut written, ptr::null_mut()) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(written as usize)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for Pipe {
+        fn try_clone(&self) -> io::Result<Self> {
+            Ok(Pipe(Arc::clone(&self.0)))
+        }
+
+        /// Pipes have no write timeout; a nonblocking pipe returns at once when its buffer is full instead
+        fn limit_writes(&self) -> io::Result<()> {
+            let mode = PIPE_READMODE_BYTE | PIPE_NOWAIT;
+            if unsafe { SetNamedPipeHandleState(self.0.as_raw_handle(), &mode, ptr::null(), ptr::null()) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+}
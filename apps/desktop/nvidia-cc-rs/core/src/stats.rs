@@ -0,0 +1,113 @@
+//! Internal counters, reported by the stdin command `stats`.
+//!
+//! `stats` answers with a single `Stats{stats}` event:
+//!
+//!   uptime_ms, paused
+//!   events_emitted       - events put on the stream, every kind counted
+//!   events_dropped       - dropped because stdout wasn't read (see DroppedEvents)
+//!   duplicates_dropped   - key events seen twice through two interfaces of one keyboard
+//!   rate_limited         - key events held back by --max-event-rate
+//!   writes_ok, writes_failed - `write` commands, on stdin or the command line
+//!   inject_backend       - backend the last injection went through, null before any
+//!   output_queue_depth   - events waiting for stdout
+//!   subscribers          - socket clients and other sinks receiving events
+//!   devices              - each device's path, name, state and key events read
+//!
+//! SIGUSR1 already pauses capture, so there is no signal for it. The same
+//! counters, and how long captured keys took to be emitted, are exported for
+//! Prometheus by `--metrics-addr` (see `metrics`).
+
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::clock;
+use crate::control;
+use crate::devices;
+use crate::output;
+use crate::stream;
+use crate::throttle;
+
+static WRITES_OK: AtomicU64 = AtomicU64::new(0);
+static WRITES_FAILED: AtomicU64 = AtomicU64::new(0);
+
+/// Name of the backend the last injection went through
+static BACKEND: Mutex<Option<&'static str>> = Mutex::new(None);
+
+/// Upper bounds of the input latency buckets, in microseconds
+pub const LATENCY_BUCKETS_US: [u64; 9] = [250, 500, 1_000, 2_000, 5_000, 10_000, 25_000, 50_000, 100_000];
+
+/// Key events per latency bucket, the last one past every bound
+static LATENCY_COUNTS: [AtomicU64; LATENCY_BUCKETS_US.len() + 1] = [const { AtomicU64::new(0) }; 10];
+static LATENCY_SUM_US: AtomicU64 = AtomicU64::new(0);
+
+/// Input latency so far, as Prometheus histograms count it
+pub struct Latency {
+    /// Key events at or under each of [`LATENCY_BUCKETS_US`], cumulative
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum_us: u64,
+}
+
+/// Count a finished `write`
+pub fn count_write(ok: bool) {
+    let counter = if ok { &WRITES_OK } else { &WRITES_FAILED };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Note that an injection just went through `backend`
+pub fn note_backend(backend: &'static str) {
+    *BACKEND.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(backend);
+}
+
+/// `write` commands that succeeded and failed
+pub fn writes() -> (u64, u64) {
+    (WRITES_OK.load(Ordering::Relaxed), WRITES_FAILED.load(Ordering::Relaxed))
+}
+
+/// Backend the last injection went through, if any went through one
+pub fn backend() -> Option<&'static str> {
+    *BACKEND.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Record how long a captured key took from the kernel to the event stream
+pub fn observe_latency(latency: Duration) {
+    let us = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+    let bucket = LATENCY_BUCKETS_US.iter().position(|&bound| us <= bound).unwrap_or(LATENCY_BUCKETS_US.len());
+    LATENCY_COUNTS[bucket].fetch_add(1, Ordering::Relaxed);
+    LATENCY_SUM_US.fetch_add(us, Ordering::Relaxed);
+}
+
+pub fn latency() -> Latency {
+    let mut buckets = Vec::with_capacity(LATENCY_BUCKETS_US.len());
+    let mut count = 0;
+    for counter in &LATENCY_COUNTS {
+        count += counter.load(Ordering::Relaxed);
+        buckets.push(count);
+    }
+    buckets.truncate(LATENCY_BUCKETS_US.len());
+    Latency {
+        buckets,
+        count,
+        sum_us: LATENCY_SUM_US.load(Ordering::Relaxed),
+    }
+}
+
+/// Every counter, as the `Stats` event carries them
+pub fn snapshot() -> Value {
+    json!({
+        "uptime_ms": clock::monotonic_us() / 1000,
+        "paused": control::is_paused(),
+        "events_emitted": stream::last_seq(),
+        "events_dropped": output::dropped(),
+        "duplicates_dropped": throttle::duplicates(),
+        "rate_limited": throttle::rate_limited(),
+        "writes_ok": writes().0,
+        "writes_failed": writes().1,
+        "inject_backend": backend(),
+        "output_queue_depth": output::queued(),
+        "subscribers": stream::subscribers(),
+        "devices": devices::snapshot(),
+    })
+}
@@ -0,0 +1,152 @@
+//! Event emission shared by every listener backend.
+//!
+//! Each event is stamped with a monotonically increasing sequence number and
+//! kept in a short in-memory ring, so a socket client that reconnects after a
+//! brief UI restart can resume from the last sequence it saw instead of
+//! missing hotkeys.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use crate::clock;
+use crate::control;
+use crate::event::{Event, EventKind};
+use crate::msgpack;
+use crate::output::{self, write_line};
+
+/// Number of recent events kept around for `subscribe --since-seq`
+pub const REPLAY_RING_CAPACITY: usize = 1024;
+
+/// Wire representation of an event: the sequence number and monotonic
+/// timestamp, the `--id` of the stdin command that caused it, followed by
+/// the event fields
+#[derive(Serialize)]
+struct Sequenced<'e, 'a> {
+    seq: u64,
+    mono_us: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<&'e str>,
+    #[serde(flatten)]
+    event: &'e Event<'a>,
+}
+
+struct EventStream {
+    next_seq: u64,
+    /// Serialized lines (without the trailing newline). Once full, the oldest
+    /// slot's buffer is reused for the next event, so steady-state emission
+    /// doesn't allocate.
+    ring: VecDeque<(u64, Vec<u8>)>,
+    subscribers: Vec<Box<dyn Write + Send>>,
+    /// Reused for the stdout copy with `--output msgpack`
+    frame: Vec<u8>,
+}
+
+fn stream() -> MutexGuard<'static, EventStream> {
+    static STREAM: OnceLock<Mutex<EventStream>> = OnceLock::new();
+    STREAM
+        .get_or_init(|| {
+            Mutex::new(EventStream {
+                next_seq: 1,
+                ring: VecDeque::with_capacity(REPLAY_RING_CAPACITY),
+                subscribers: Vec::new(),
+                frame: Vec::new(),
+            })
+        })
+        .lock()
+        // A panicking device thread must not take the whole stream down with it
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Assign the next sequence number to `event` and write it to every subscriber and stdout
+///
+/// Subscribers come first, so whoever sees an event on stdout and acts on it (`dump`, say) finds it in the
+/// `--record-last` ring and the other sinks too.
+pub fn emit(event: &Event) {
+    let mut stream = stream();
+    let seq = stream.next_seq;
+    stream.next_seq += 1;
+
+    let mut line = if stream.ring.len() == REPLAY_RING_CAPACITY {
+        stream.ring.pop_front().map(|(_, buffer)| buffer).unwrap_or_default()
+    } else {
+        Vec::with_capacity(256)
+    };
+    line.clear();
+
+    let mono_us = clock::monotonic_us();
+    let msgpack = output::format() == output::Format::Msgpack;
+    let frame = &mut stream.frame;
+    control::with_request_id(|request_id| {
+        let sequenced = Sequenced {
+            seq,
+            mono_us,
+            request_id,
+            event,
+        };
+        serde_json::to_writer(&mut line, &sequenced).unwrap();
+
+        // Socket clients and the replay ring keep JSON whatever stdout gets
+        if msgpack {
+            frame.clear();
+            msgpack::to_writer(frame, &sequenced).unwrap();
+        }
+    });
+
+    // Subscribers that stopped reading (or hung up) are dropped rather than blocking capture
+    stream
+        .subscribers
+        .retain_mut(|subscriber| write_line(subscriber, &line).is_ok());
+
+    output::enqueue(if msgpack { &stream.frame } else { &line });
+
+    stream.ring.push_back((seq, line));
+}
+
+/// Sequence number of the most recently emitted event (0 before the first one)
+pub fn last_seq() -> u64 {
+    stream().next_seq - 1
+}
+
+/// Socket clients and other sinks currently receiving events
+pub fn subscribers() -> usize {
+    stream().subscribers.len()
+}
+
+/// Register a new subscriber, first replaying every buffered event newer than `since_seq`
+///
+/// If the requested sequence has already been evicted from the ring, the client is
+/// sent a `ReplayGap` event before the replay so it knows some events were lost. So it
+/// is when `since_seq` was never emitted: the helper restarted and its sequence with
+/// it, and the whole ring is replayed. The gap is numbered `oldest_seq - 1`, the last
+/// sequence lost, without taking a number from the stream.
+pub fn subscribe(mut sink: Box<dyn Write + Send>, since_seq: Option<u64>) -> io::Result<()> {
+    let mut stream = stream();
+
+    if let Some(since_seq) = since_seq {
+        let oldest_seq = stream
+            .ring
+            .front()
+            .map_or(stream.next_seq, |(seq, _)| *seq);
+        let restarted = since_seq >= stream.next_seq;
+        if restarted || since_seq.saturating_add(1) < oldest_seq {
+            let gap_event = Event::now(EventKind::ReplayGap { since_seq, oldest_seq });
+            let gap = Sequenced {
+                seq: oldest_seq - 1,
+                mono_us: clock::monotonic_us(),
+                request_id: None,
+                event: &gap_event,
+            };
+            write_line(&mut sink, &serde_json::to_vec(&gap).unwrap())?;
+        }
+
+        let since_seq = if restarted { 0 } else { since_seq };
+        for (_, line) in stream.ring.iter().filter(|(seq, _)| *seq > since_seq) {
+            write_line(&mut sink, line)?;
+        }
+    }
+
+    stream.subscribers.push(sink);
+    Ok(())
+}
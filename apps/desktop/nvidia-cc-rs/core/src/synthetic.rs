@@ -0,0 +1,109 @@
+//! Recognizing keystrokes injected by this helper.
+//!
+//! When `write` types text while `listen` is running, the injected keys come back
+//! as capture events and can re-trigger hotkeys. Two signals identify them:
+//!
+//! - Our own uinput virtual keyboard, recognized by its device name (Linux).
+//! - An injection marker file that `write` holds for the duration of the
+//!   injection plus a short grace period, which covers backends whose events are
+//!   indistinguishable from real ones once captured (XTEST, rdev on macOS/Windows).
+//!
+//! Matching events are tagged `"synthetic": true`; with `--suppress-self` they are
+//! dropped from the listen stream instead.
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::parent;
+
+/// Device name of the uinput keyboard the helper creates for injection
+pub const VIRTUAL_DEVICE_NAME: &str = "nvidia-cc-rs virtual keyboard";
+
+/// Injected events can still be arriving shortly after the injector returns
+const INJECTION_GRACE_PERIOD: Duration = Duration::from_millis(150);
+
+/// How long a marker lookup is reused before the file is read again
+const MARKER_CACHE_MS: u64 = 10;
+
+static SUPPRESS_SELF: AtomicBool = AtomicBool::new(false);
+
+/// Drop synthetic events instead of tagging them
+pub fn set_suppress_self(suppress: bool) {
+    SUPPRESS_SELF.store(suppress, Ordering::Relaxed);
+}
+
+pub fn suppress_self() -> bool {
+    SUPPRESS_SELF.load(Ordering::Relaxed)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Per-user marker path, so injections by another user's helper are never hidden
+fn marker_path() -> PathBuf {
+    user_runtime_path("inject")
+}
+
+/// `nvidia-cc-rs-<user>.<suffix>` in the per-user runtime directory
+pub fn user_runtime_path(suffix: &str) -> PathBuf {
+    #[cfg(unix)]
+    let (dir, user) = (
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir),
+        unsafe { libc::getuid() }.to_string(),
+    );
+    #[cfg(not(unix))]
+    let (dir, user) = (
+        std::env::temp_dir(),
+        std::env::var("USERNAME").unwrap_or_default(),
+    );
+    dir.join(format!("nvidia-cc-rs-{}.{}", user, suffix))
+}
+
+/// Held by `write` while it injects; on drop the marker stays valid for the grace period
+pub struct InjectionGuard;
+
+/// Mark that this process is about to inject keystrokes
+pub fn begin_injection() -> InjectionGuard {
+    // Until the guard drops, the marker never expires on its own
+    fs::write(marker_path(), format!("{} {}", std::process::id(), u64::MAX)).ok();
+    InjectionGuard
+}
+
+impl Drop for InjectionGuard {
+    fn drop(&mut self) {
+        let until = now_ms() + INJECTION_GRACE_PERIOD.as_millis() as u64;
+        fs::write(marker_path(), format!("0 {}", until)).ok();
+    }
+}
+
+/// Whether some `write` process is (or was just) injecting keystrokes
+pub fn injection_active() -> bool {
+    static CHECKED_AT_MS: AtomicU64 = AtomicU64::new(0);
+    static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+    let now = now_ms();
+    if now.saturating_sub(CHECKED_AT_MS.load(Ordering::Relaxed)) < MARKER_CACHE_MS {
+        return ACTIVE.load(Ordering::Relaxed);
+    }
+
+    let active = fs::read_to_string(marker_path())
+        .ok()
+        .and_then(|marker| {
+            let (pid, until) = marker.trim().split_once(' ')?;
+            Some((pid.parse::<u32>().ok()?, until.parse::<u64>().ok()?))
+        })
+        // An open-ended marker from a writer that crashed must not hide input forever
+        .is_some_and(|(pid, until)| now < until && (until != u64::MAX || parent::is_alive(pid)));
+
+    ACTIVE.store(active, Ordering::Relaxed);
+    CHECKED_AT_MS.store(now, Ordering::Relaxed);
+    active
+}
@@ -0,0 +1,215 @@
+//! Remote transport for the event stream and control commands, for the app
+//! on one machine and the helper capturing input on another
+//! (`listen --tcp ADDR --token <t> [--tls <cert> <key>]`).
+//!
+//! A client's first line must be `auth <token>`; anything else gets an
+//! `Error` event and the connection closed. Its second line is then either
+//!   subscribe [--since-seq N] [--compress zstd] - stream events, as on the
+//!             local socket (see [`crate::socket`])
+//! or a command as on stdin (see [`crate::control`]), after which every line
+//! is one. What commands result in comes on the subscribed connections,
+//! carrying their `--id` as `request_id`.
+//!
+//! The stream carries every keystroke and the commands type text, so serving
+//! anything but a loopback address without `--token` is refused; on loopback
+//! a token is generated and given in `TcpListening`. Without `--tls`, tokens
+//! and keystrokes cross the network in the clear. A client gets
+//! `AUTH_TIMEOUT` for the TLS handshake and `auth` together, and only
+//! `MAX_PENDING` clients can be at that stage at once; more are closed on
+//! arrival.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use crate::control;
+use crate::event::{Event, EventKind};
+use crate::http;
+use crate::socket;
+use crate::tls;
+
+/// Clients that haven't finished the handshake and `auth` by then are dropped
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most clients handshaking or yet to `auth` at once
+const MAX_PENDING: usize = 16;
+
+/// A subscriber that can't accept a line within this window is disconnected
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Longest command line read before authentication
+const MAX_LINE_BYTES: u64 = 4096;
+
+struct Server {
+    token: String,
+    tls: Option<tls::Acceptor>,
+    /// Clients handshaking or yet to `auth`
+    pending: AtomicUsize,
+}
+
+/// One of [`Server::pending`], until dropped
+struct Pending<'a>(&'a AtomicUsize);
+
+impl Drop for Pending<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A client connection, with TLS or without
+enum Client {
+    Plain(TcpStream),
+    Tls(tls::Stream),
+}
+
+impl Client {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            Client::Plain(tcp) => tcp,
+            Client::Tls(stream) => stream.tcp(),
+        }
+    }
+}
+
+impl Read for Client {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Client::Plain(tcp) => tcp.read(buf),
+            Client::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Client {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Client::Plain(tcp) => tcp.write(buf),
+            Client::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Client::Plain(tcp) => tcp.flush(),
+            Client::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Bind `addr` and serve authenticated clients on a background thread, returning the bound address and the
+/// token clients must present: `token`, or without one on loopback, a generated one
+pub fn serve(addr: &str, token: Option<String>, tls: Option<tls::Acceptor>) -> io::Result<(SocketAddr, String)> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let loopback = local_addr.ip().is_loopback();
+    let token = match token {
+        Some(token) if token.is_empty() => {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "--token can't be empty"));
+        }
+        Some(token) => token,
+        None if loopback => http::generate_token()?,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is reachable from other machines; refusing to serve it without --token", local_addr),
+            ));
+        }
+    };
+    if !loopback && tls.is_none() {
+        eprintln!("Warning: the token and keystrokes cross the network in the clear on {}; see --tls", local_addr);
+    }
+    eprintln!("Serving events on tcp://{}{}", local_addr, if tls.is_some() { " with TLS" } else { "" });
+
+    let server = Arc::new(Server {
+        token,
+        tls,
+        pending: AtomicUsize::new(0),
+    });
+    let token = server.token.clone();
+    thread::spawn(move || {
+        for connection in listener.incoming() {
+            let Ok(client) = connection else { continue };
+            if server.pending.fetch_add(1, Ordering::SeqCst) >= MAX_PENDING {
+                server.pending.fetch_sub(1, Ordering::SeqCst);
+                let peer = client.peer_addr().map(|peer| peer.to_string()).unwrap_or_default();
+                eprintln!("Refused TCP client {}: {} others are still authenticating", peer, MAX_PENDING);
+                continue;
+            }
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                let pending = Pending(&server.pending);
+                if let Err(e) = server.handle(client, pending) {
+                    eprintln!("TCP client error: {}", e);
+                }
+            });
+        }
+    });
+    Ok((local_addr, token))
+}
+
+impl Server {
+    fn handle(&self, tcp: TcpStream, pending: Pending) -> io::Result<()> {
+        let deadline = auth_deadline(&tcp)?;
+        tcp.set_read_timeout(Some(AUTH_TIMEOUT))?;
+        tcp.set_write_timeout(Some(AUTH_TIMEOUT))?;
+        let client = match &self.tls {
+            Some(acceptor) => Client::Tls(acceptor.accept(tcp)?),
+            None => Client::Plain(tcp),
+        };
+        let mut reader = BufReader::new(client);
+        let auth = read_line(&mut reader)?;
+        let presented = auth.strip_prefix("auth ").unwrap_or_default();
+        if !http::constant_time_eq(presented.as_bytes(), self.token.as_bytes()) {
+            let peer = reader.get_ref().tcp().peer_addr()?;
+            eprintln!("Refused TCP client {}: missing or invalid token", peer);
+            return refuse(reader.into_inner(), "Unauthorized", "Expected auth <token> first");
+        }
+        drop((deadline, pending));
+
+        let command = read_line(&mut reader)?;
+        if command.split_whitespace().next() == Some("subscribe") {
+            let client = reader.into_inner();
+            client.tcp().set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+            return socket::answer(&command, client);
+        }
+        // A command connection may idle between commands as long as it likes
+        reader.get_ref().tcp().set_read_timeout(None)?;
+        control::handle_line(&command);
+        for line in reader.lines() {
+            control::handle_line(&line?);
+        }
+        Ok(())
+    }
+}
+
+/// Shut `tcp` down once `AUTH_TIMEOUT` is up, unless the returned guard is dropped first
+///
+/// The read timeout alone restarts with every byte, so a client trickling them could hold on for good.
+fn auth_deadline(tcp: &TcpStream) -> io::Result<mpsc::Sender<()>> {
+    let connection = tcp.try_clone()?;
+    let (guard, dropped) = mpsc::channel();
+    thread::spawn(move || {
+        if dropped.recv_timeout(AUTH_TIMEOUT) == Err(mpsc::RecvTimeoutError::Timeout) {
+            connection.shutdown(Shutdown::Both).ok();
+        }
+    });
+    Ok(guard)
+}
+
+/// The next line, without its line ending, reading `MAX_LINE_BYTES` at most
+fn read_line(reader: &mut BufReader<Client>) -> io::Result<String> {
+    let mut line = String::new();
+    reader.by_ref().take(MAX_LINE_BYTES).read_line(&mut line)?;
+    Ok(line.trim_end().to_string())
+}
+
+fn refuse(mut client: Client, error: &str, message: &str) -> io::Result<()> {
+    let event = Event::now(EventKind::Error {
+        error: error.to_string(),
+        message: message.to_string(),
+    });
+    writeln!(client, "{}", serde_json::to_string(&event).unwrap())
+}
@@ -0,0 +1,133 @@
+//! Duplicate-device deduplication and the key event rate limit.
+//!
+//! Some laptops expose their internal keyboard through two event nodes with
+//! the same `phys`, so every key arrives twice. A key event is dropped when a
+//! different node with the same `phys` reported the same key and value less
+//! than [`DEDUP_WINDOW`] earlier (kernel timestamps). Dropped duplicates never
+//! reach the hotkey or hotstring engines either.
+//!
+//! `--max-event-rate <n>` caps the key events emitted per second, with bursts
+//! of up to `n`, for keyboards that flood thousands of events a second. Keys
+//! over the limit are still matched against hotkeys and hotstrings, only not
+//! emitted; the next event let through is preceded by `RateLimited{dropped}`.
+//! Both counts are on the HTTP `/status` page.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::event::{Event, EventKind};
+use crate::stream;
+
+/// How close together two nodes' reports of one key must be to count as the same event
+pub const DEDUP_WINDOW: Duration = Duration::from_millis(5);
+
+static DUPLICATES: AtomicU64 = AtomicU64::new(0);
+static RATE_LIMITED: AtomicU64 = AtomicU64::new(0);
+
+struct Reported {
+    phys: String,
+    path: String,
+    code: u16,
+    value: i32,
+    time: SystemTime,
+}
+
+static RECENT: Mutex<VecDeque<Reported>> = Mutex::new(VecDeque::new());
+
+struct Bucket {
+    /// Events per second; `None` for no limit
+    rate: Option<u32>,
+    tokens: f64,
+    refilled: Option<Instant>,
+    /// Dropped since the last event let through
+    dropped: u64,
+}
+
+static BUCKET: Mutex<Bucket> = Mutex::new(Bucket {
+    rate: None,
+    tokens: 0.0,
+    refilled: None,
+    dropped: 0,
+});
+
+fn bucket() -> MutexGuard<'static, Bucket> {
+    BUCKET.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Whether the key event just read from `path` repeats one another node with the same `phys` reported
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn duplicate(phys: &str, path: &str, code: u16, value: i32, time: SystemTime) -> bool {
+    // Nodes without a physical path can't be told to be the same keyboard
+    if phys.is_empty() {
+        return false;
+    }
+    let mut recent = RECENT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let within_window = |earlier: SystemTime| time.duration_since(earlier).map_or(true, |gap| gap < DEDUP_WINDOW);
+    while recent.front().is_some_and(|reported| !within_window(reported.time)) {
+        recent.pop_front();
+    }
+    let duplicate = recent.iter().any(|reported| {
+        reported.phys == phys && reported.path != path && reported.code == code && reported.value == value
+    });
+    if duplicate {
+        DUPLICATES.fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+    recent.push_back(Reported {
+        phys: phys.to_string(),
+        path: path.to_string(),
+        code,
+        value,
+        time,
+    });
+    false
+}
+
+/// Cap emitted key events at `rate` a second, or lift the cap with `None`
+pub fn set_max_rate(rate: Option<u32>) {
+    let mut bucket = bucket();
+    bucket.rate = rate.filter(|&rate| rate > 0);
+    bucket.tokens = bucket.rate.unwrap_or(0) as f64;
+    bucket.refilled = None;
+}
+
+pub fn max_rate() -> Option<u32> {
+    bucket().rate
+}
+
+/// Whether a key event may be emitted now; counts it as dropped when not
+pub fn admit() -> bool {
+    let mut bucket = bucket();
+    let Some(rate) = bucket.rate else {
+        return true;
+    };
+    let now = Instant::now();
+    if let Some(refilled) = bucket.refilled {
+        bucket.tokens = (bucket.tokens + now.duration_since(refilled).as_secs_f64() * rate as f64).min(rate as f64);
+    }
+    bucket.refilled = Some(now);
+    if bucket.tokens < 1.0 {
+        bucket.dropped += 1;
+        RATE_LIMITED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    bucket.tokens -= 1.0;
+    let dropped = std::mem::take(&mut bucket.dropped);
+    drop(bucket);
+    if dropped > 0 {
+        stream::emit(&Event::now(EventKind::RateLimited { dropped }));
+    }
+    true
+}
+
+/// Duplicate key events dropped so far
+pub fn duplicates() -> u64 {
+    DUPLICATES.load(Ordering::Relaxed)
+}
+
+/// Key events the rate limit kept from being emitted so far
+pub fn rate_limited() -> u64 {
+    RATE_LIMITED.load(Ordering::Relaxed)
+}
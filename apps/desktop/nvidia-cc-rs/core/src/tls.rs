@@ -0,0 +1,289 @@
+//! TLS for `listen --tcp` (see [`crate::tcp`]), through the system's
+//! OpenSSL.
+//!
+//! libssl and libcrypto are loaded when `--tls` is given, as libzstd is for
+//! compression, so the helper runs without them; asking for TLS then fails
+//! with a message naming the library. Connections take TLS 1.2 or later, with the PEM
+//! certificate chain and private key `--tls` names.
+
+use libloading::Library;
+use std::ffi::{c_char, c_int, c_long, c_ulong, c_void, CString};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// `SSL_FILETYPE_PEM`
+const FILETYPE_PEM: c_int = 1;
+
+/// `SSL_CTRL_SET_MIN_PROTO_VERSION`, behind the `SSL_CTX_set_min_proto_version` macro
+const CTRL_SET_MIN_PROTO_VERSION: c_int = 123;
+const TLS1_2_VERSION: c_long = 0x0303;
+
+/// `SSL_get_error` results
+const ERROR_SYSCALL: c_int = 5;
+const ERROR_ZERO_RETURN: c_int = 6;
+
+#[cfg(target_os = "linux")]
+const LIBRARY_NAMES: &[&str] = &["libssl.so.3", "libssl.so.1.1", "libssl.so"];
+#[cfg(target_os = "macos")]
+const LIBRARY_NAMES: &[&str] = &[
+    "/opt/homebrew/opt/openssl@3/lib/libssl.3.dylib",
+    "/usr/local/opt/openssl@3/lib/libssl.3.dylib",
+];
+#[cfg(windows)]
+const LIBRARY_NAMES: &[&str] = &["libssl-3-x64.dll", "libssl-3.dll"];
+
+/// libcrypto, for the error queue: Windows doesn't look up its exports through libssl's handle
+#[cfg(target_os = "linux")]
+const CRYPTO_LIBRARY_NAMES: &[&str] = &["libcrypto.so.3", "libcrypto.so.1.1", "libcrypto.so"];
+#[cfg(target_os = "macos")]
+const CRYPTO_LIBRARY_NAMES: &[&str] = &[
+    "/opt/homebrew/opt/openssl@3/lib/libcrypto.3.dylib",
+    "/usr/local/opt/openssl@3/lib/libcrypto.3.dylib",
+];
+#[cfg(windows)]
+const CRYPTO_LIBRARY_NAMES: &[&str] = &["libcrypto-3-x64.dll", "libcrypto-3.dll"];
+
+struct Api {
+    server_method: unsafe extern "C" fn() -> *const c_void,
+    ctx_new: unsafe extern "C" fn(*const c_void) -> *mut c_void,
+    ctx_free: unsafe extern "C" fn(*mut c_void),
+    ctx_ctrl: unsafe extern "C" fn(*mut c_void, c_int, c_long, *mut c_void) -> c_long,
+    use_certificate_chain_file: unsafe extern "C" fn(*mut c_void, *const c_char) -> c_int,
+    use_private_key_file: unsafe extern "C" fn(*mut c_void, *const c_char, c_int) -> c_int,
+    check_private_key: unsafe extern "C" fn(*const c_void) -> c_int,
+    ssl_new: unsafe extern "C" fn(*mut c_void) -> *mut c_void,
+    ssl_free: unsafe extern "C" fn(*mut c_void),
+    set_fd: unsafe extern "C" fn(*mut c_void, c_int) -> c_int,
+    accept: unsafe extern "C" fn(*mut c_void) -> c_int,
+    read: unsafe extern "C" fn(*mut c_void, *mut c_void, c_int) -> c_int,
+    write: unsafe extern "C" fn(*mut c_void, *const c_void, c_int) -> c_int,
+    shutdown: unsafe extern "C" fn(*mut c_void) -> c_int,
+    get_error: unsafe extern "C" fn(*const c_void, c_int) -> c_int,
+    /// From libcrypto
+    err_get_error: unsafe extern "C" fn() -> c_ulong,
+    err_error_string_n: unsafe extern "C" fn(c_ulong, *mut c_char, usize),
+    /// Keep the functions above loaded
+    _libssl: Library,
+    _libcrypto: Library,
+}
+
+impl Api {
+    unsafe fn load() -> Result<Api, String> {
+        let open = |names: &[&str], library: &str| {
+            names
+                .iter()
+                .find_map(|name| Library::new(name).ok())
+                .ok_or_else(|| format!("--tls needs OpenSSL's {} ({})", library, names[0]))
+        };
+        let libssl = open(LIBRARY_NAMES, "libssl")?;
+        let libcrypto = open(CRYPTO_LIBRARY_NAMES, "libcrypto")?;
+        macro_rules! symbol {
+            ($library:ident, $name:literal) => {
+                *$library
+                    .get(concat!($name, "\0").as_bytes())
+                    .map_err(|e| format!("{} lacks {}: {}", stringify!($library), $name, e))?
+            };
+        }
+        Ok(Api {
+            server_method: symbol!(libssl, "TLS_server_method"),
+            ctx_new: symbol!(libssl, "SSL_CTX_new"),
+            ctx_free: symbol!(libssl, "SSL_CTX_free"),
+            ctx_ctrl: symbol!(libssl, "SSL_CTX_ctrl"),
+            use_certificate_chain_file: symbol!(libssl, "SSL_CTX_use_certificate_chain_file"),
+            use_private_key_file: symbol!(libssl, "SSL_CTX_use_PrivateKey_file"),
+            check_private_key: symbol!(libssl, "SSL_CTX_check_private_key"),
+            ssl_new: symbol!(libssl, "SSL_new"),
+            ssl_free: symbol!(libssl, "SSL_free"),
+            set_fd: symbol!(libssl, "SSL_set_fd"),
+            accept: symbol!(libssl, "SSL_accept"),
+            read: symbol!(libssl, "SSL_read"),
+            write: symbol!(libssl, "SSL_write"),
+            shutdown: symbol!(libssl, "SSL_shutdown"),
+            get_error: symbol!(libssl, "SSL_get_error"),
+            err_get_error: symbol!(libcrypto, "ERR_get_error"),
+            err_error_string_n: symbol!(libcrypto, "ERR_error_string_n"),
+            _libssl: libssl,
+            _libcrypto: libcrypto,
+        })
+    }
+
+    /// OpenSSL's queued errors for the thread, oldest first, as one message
+    fn errors(&self) -> String {
+        let mut messages = Vec::new();
+        loop {
+            let code = unsafe { (self.err_get_error)() };
+            if code == 0 {
+                break;
+            }
+            let mut buffer = [0 as c_char; 256];
+            unsafe { (self.err_error_string_n)(code, buffer.as_mut_ptr(), buffer.len()) };
+            let message = unsafe { std::ffi::CStr::from_ptr(buffer.as_ptr()) };
+            messages.push(message.to_string_lossy().into_owned());
+        }
+        if messages.is_empty() {
+            "unknown error".to_string()
+        } else {
+            messages.join("; ")
+        }
+    }
+
+    /// The error for the result `ret` of an operation on `ssl`; `Ok(())` for a clean close
+    fn check(&self, ssl: *mut c_void, ret: c_int) -> io::Result<()> {
+        match unsafe { (self.get_error)(ssl, ret) } {
+            ERROR_ZERO_RETURN => Ok(()),
+            ERROR_SYSCALL => {
+                let error = io::Error::last_os_error();
+                // No errno either: the peer went away without closing the session
+                if error.raw_os_error() == Some(0) {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+                }
+                Err(error)
+            }
+            _ => Err(io::Error::other(format!("TLS: {}", self.errors()))),
+        }
+    }
+}
+
+fn api() -> Result<&'static Api, String> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(|| unsafe { Api::load() }).as_ref().map_err(String::clone)
+}
+
+fn c_path(path: &Path) -> Result<CString, String> {
+    CString::new(path.to_string_lossy().into_owned()).map_err(|_| format!("Invalid path: {}", path.display()))
+}
+
+/// Server side of TLS with one certificate: `SSL_CTX`
+pub struct Acceptor {
+    api: &'static Api,
+    ctx: *mut c_void,
+}
+
+// OpenSSL contexts can be shared once configured; sessions are created from it under its own locks
+unsafe impl Send for Acceptor {}
+unsafe impl Sync for Acceptor {}
+
+impl Acceptor {
+    /// Load the PEM certificate chain at `cert` and its private key at `key`
+    pub fn new(cert: &Path, key: &Path) -> Result<Acceptor, String> {
+        let api = api()?;
+        let ctx = unsafe { (api.ctx_new)((api.server_method)()) };
+        if ctx.is_null() {
+            return Err(format!("Cannot create a TLS context: {}", api.errors()));
+        }
+        // Frees the context on the way out of every error below
+        let acceptor = Acceptor { api, ctx };
+        unsafe { (api.ctx_ctrl)(ctx, CTRL_SET_MIN_PROTO_VERSION, TLS1_2_VERSION, std::ptr::null_mut()) };
+        let cert_path = c_path(cert)?;
+        if unsafe { (api.use_certificate_chain_file)(ctx, cert_path.as_ptr()) } != 1 {
+            return Err(format!("Cannot load the certificate {}: {}", cert.display(), api.errors()));
+        }
+        let key_path = c_path(key)?;
+        if unsafe { (api.use_private_key_file)(ctx, key_path.as_ptr(), FILETYPE_PEM) } != 1 {
+            return Err(format!("Cannot load the private key {}: {}", key.display(), api.errors()));
+        }
+        if unsafe { (api.check_private_key)(ctx) } != 1 {
+            return Err(format!("{} isn't the key of {}: {}", key.display(), cert.display(), api.errors()));
+        }
+        Ok(acceptor)
+    }
+
+    /// Run the server side of the handshake on `tcp`, within its read timeout
+    pub fn accept(&self, tcp: TcpStream) -> io::Result<Stream> {
+        let ssl = unsafe { (self.api.ssl_new)(self.ctx) };
+        if ssl.is_null() {
+            return Err(io::Error::other(format!("TLS: {}", self.api.errors())));
+        }
+        let mut stream = Stream { api: self.api, ssl, tcp, open: false };
+        if unsafe { (self.api.set_fd)(ssl, raw_socket(&stream.tcp)) } != 1 {
+            return Err(io::Error::other(format!("TLS: {}", self.api.errors())));
+        }
+        let ret = unsafe { (self.api.accept)(ssl) };
+        if ret != 1 {
+            self.api.check(ssl, ret)?;
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        stream.open = true;
+        Ok(stream)
+    }
+}
+
+impl Drop for Acceptor {
+    fn drop(&mut self) {
+        // Sessions hold their own reference to the context
+        unsafe { (self.api.ctx_free)(self.ctx) };
+    }
+}
+
+#[cfg(unix)]
+fn raw_socket(tcp: &TcpStream) -> c_int {
+    use std::os::fd::AsRawFd;
+    tcp.as_raw_fd()
+}
+
+/// OpenSSL takes Windows sockets as ints too
+#[cfg(windows)]
+fn raw_socket(tcp: &TcpStream) -> c_int {
+    use std::os::windows::io::AsRawSocket;
+    tcp.as_raw_socket() as c_int
+}
+
+/// A TLS session over a TCP connection
+pub struct Stream {
+    api: &'static Api,
+    ssl: *mut c_void,
+    tcp: TcpStream,
+    /// Whether the handshake completed, so closing sends close_notify
+    open: bool,
+}
+
+// A session is only ever used through `&mut self`
+unsafe impl Send for Stream {}
+
+impl Stream {
+    /// The connection underneath, for its timeouts
+    pub fn tcp(&self) -> &TcpStream {
+        &self.tcp
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(c_int::MAX as usize) as c_int;
+        let ret = unsafe { (self.api.read)(self.ssl, buf.as_mut_ptr().cast(), len) };
+        if ret > 0 {
+            return Ok(ret as usize);
+        }
+        self.api.check(self.ssl, ret).map(|()| 0)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(c_int::MAX as usize) as c_int;
+        let ret = unsafe { (self.api.write)(self.ssl, buf.as_ptr().cast(), len) };
+        if ret > 0 {
+            return Ok(ret as usize);
+        }
+        self.api.check(self.ssl, ret)?;
+        Err(io::Error::from(io::ErrorKind::WriteZero))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if self.open {
+            unsafe { (self.api.shutdown)(self.ssl) };
+        }
+        unsafe { (self.api.ssl_free)(self.ssl) };
+    }
+}
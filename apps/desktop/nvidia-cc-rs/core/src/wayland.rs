@@ -0,0 +1,417 @@
+//! Virtual input devices of wlroots compositors (Sway, Hyprland, river):
+//! `zwp_virtual_keyboard_v1` for keys and text, `zwlr_virtual_pointer_v1`
+//! for the pointer. The `wayland` injection backend uses them (see
+//! [`crate::backend`]), needing no uinput access.
+//!
+//! A virtual keyboard's key codes mean what the keymap its client uploads
+//! says, so rather than the user's layout the backend sends a keymap of its
+//! own: each keysym typed gets a key code, and the keymap is uploaded again
+//! whenever text brings new ones, up to X11's 247 codes at a time. Any
+//! character types that way whatever the layout, without Shift or AltGr;
+//! modifiers held through `key` are sent as modifier state.
+//!
+//! The little of the protocol this needs is spoken directly on the
+//! compositor's socket, `$XDG_RUNTIME_DIR/$WAYLAND_DISPLAY`.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use crate::input_access;
+
+const KEYBOARD_MANAGER: &str = "zwp_virtual_keyboard_manager_v1";
+const POINTER_MANAGER: &str = "zwlr_virtual_pointer_manager_v1";
+
+/// `wl_display`, the one object that exists from the start
+const DISPLAY: u32 = 1;
+
+/// Request opcodes
+const DISPLAY_SYNC: u16 = 0;
+const DISPLAY_GET_REGISTRY: u16 = 1;
+const REGISTRY_BIND: u16 = 0;
+const MANAGER_CREATE: u16 = 0;
+const KEYBOARD_KEYMAP: u16 = 0;
+const KEYBOARD_KEY: u16 = 1;
+const KEYBOARD_MODIFIERS: u16 = 2;
+const POINTER_MOTION: u16 = 0;
+const POINTER_BUTTON: u16 = 2;
+const POINTER_AXIS_SOURCE: u16 = 5;
+const POINTER_FRAME: u16 = 4;
+const POINTER_AXIS_DISCRETE: u16 = 7;
+
+/// Event opcodes
+const DISPLAY_ERROR: u16 = 0;
+const REGISTRY_GLOBAL: u16 = 0;
+const CALLBACK_DONE: u16 = 0;
+
+/// `wl_keyboard.keymap_format.xkb_v1`
+const KEYMAP_FORMAT_XKB_V1: u32 = 1;
+
+/// Key codes of the uploaded keymap; X11 clients can't take any above 255
+const FIRST_KEYCODE: u32 = 9;
+const LAST_KEYCODE: u32 = 255;
+
+/// `wl_pointer` axes and the wheel as their source
+const AXIS_VERTICAL: u32 = 0;
+const AXIS_HORIZONTAL: u32 = 1;
+const AXIS_SOURCE_WHEEL: u32 = 0;
+
+/// Scroll distance of one wheel notch, as libinput reports it
+const NOTCH: f64 = 15.0;
+
+/// The compositor answers a round trip at once; longer means it is stuck
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Masks of the real modifiers in the uploaded keymap
+pub const SHIFT: u32 = 1;
+pub const CONTROL: u32 = 4;
+pub const ALT: u32 = 8;
+pub const LOGO: u32 = 64;
+
+/// A request being encoded
+struct Request {
+    bytes: Vec<u8>,
+}
+
+impl Request {
+    fn new(object: u32, opcode: u16) -> Request {
+        let mut bytes = object.to_ne_bytes().to_vec();
+        bytes.extend(u32::from(opcode).to_ne_bytes());
+        Request { bytes }
+    }
+
+    fn uint(mut self, value: u32) -> Request {
+        self.bytes.extend(value.to_ne_bytes());
+        self
+    }
+
+    fn int(mut self, value: i32) -> Request {
+        self.bytes.extend(value.to_ne_bytes());
+        self
+    }
+
+    /// `wl_fixed`: 24.8 fixed point
+    fn fixed(self, value: f64) -> Request {
+        self.int((value * 256.0) as i32)
+    }
+
+    fn string(mut self, value: &str) -> Request {
+        self.bytes.extend((value.len() as u32 + 1).to_ne_bytes());
+        self.bytes.extend(value.as_bytes());
+        self.bytes.push(0);
+        while !self.bytes.len().is_multiple_of(4) {
+            self.bytes.push(0);
+        }
+        self
+    }
+
+    /// The request with its size written into the header
+    fn finish(mut self) -> Vec<u8> {
+        let opcode = u32::from_ne_bytes(self.bytes[4..8].try_into().unwrap());
+        let word = (self.bytes.len() as u32) << 16 | opcode;
+        self.bytes[4..8].copy_from_slice(&word.to_ne_bytes());
+        self.bytes
+    }
+}
+
+/// An event being decoded
+struct Arguments<'a> {
+    bytes: &'a [u8],
+}
+
+impl Arguments<'_> {
+    fn uint(&mut self) -> Option<u32> {
+        let value = u32::from_ne_bytes(self.bytes.get(..4)?.try_into().ok()?);
+        self.bytes = &self.bytes[4..];
+        Some(value)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.uint()? as usize;
+        let padded = len.div_ceil(4) * 4;
+        let value = self.bytes.get(..len.saturating_sub(1))?;
+        let value = String::from_utf8_lossy(value).into_owned();
+        self.bytes = self.bytes.get(padded..)?;
+        Some(value)
+    }
+}
+
+/// A virtual keyboard, and pointer where the compositor offers one
+pub struct VirtualInput {
+    socket: UnixStream,
+    /// Events read but not yet decoded
+    pending: Vec<u8>,
+    next_id: u32,
+    keyboard: u32,
+    pointer: Option<u32>,
+    /// The keysym of each key code from `FIRST_KEYCODE` on, as uploaded
+    keysyms: Vec<u32>,
+    uploaded: bool,
+    modifiers: u32,
+    started: Instant,
+}
+
+/// The compositor's socket
+pub(crate) fn socket_path() -> Result<PathBuf, String> {
+    let display = std::env::var_os("WAYLAND_DISPLAY").ok_or("WAYLAND_DISPLAY is not set")?;
+    let display = PathBuf::from(display);
+    if display.is_absolute() {
+        return Ok(display);
+    }
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR").ok_or("XDG_RUNTIME_DIR is not set")?;
+    Ok(PathBuf::from(runtime_dir).join(display))
+}
+
+impl VirtualInput {
+    /// Connect to the compositor and create the devices
+    pub fn connect() -> Result<VirtualInput, String> {
+        let path = socket_path()?;
+        let socket = UnixStream::connect(&path).map_err(|e| format!("cannot connect to {}: {}", path.display(), e))?;
+        socket.set_read_timeout(Some(REPLY_TIMEOUT)).map_err(|e| e.to_string())?;
+        let mut input = VirtualInput {
+            socket,
+            pending: Vec::new(),
+            next_id: 2,
+            keyboard: 0,
+            pointer: None,
+            keysyms: Vec::new(),
+            uploaded: false,
+            modifiers: 0,
+            started: Instant::now(),
+        };
+
+        let registry = input.new_id();
+        input.send(Request::new(DISPLAY, DISPLAY_GET_REGISTRY).uint(registry))?;
+        let globals = input.roundtrip(Some(registry))?;
+        let find = |interface: &str| globals.iter().find(|(_, name, _)| name == interface).map(|(id, _, _)| *id);
+        let seat = find("wl_seat").ok_or("the compositor has no seat")?;
+        let keyboard_manager = find(KEYBOARD_MANAGER).ok_or_else(|| {
+            format!("the compositor doesn't offer {} (wlroots compositors such as Sway do)", KEYBOARD_MANAGER)
+        })?;
+
+        let seat = input.bind(registry, seat, "wl_seat")?;
+        let keyboard_manager = input.bind(registry, keyboard_manager, KEYBOARD_MANAGER)?;
+        input.keyboard = input.new_id();
+        input.send(Request::new(keyboard_manager, MANAGER_CREATE).uint(seat).uint(input.keyboard))?;
+        if let Some(pointer_manager) = find(POINTER_MANAGER) {
+            let pointer_manager = input.bind(registry, pointer_manager, POINTER_MANAGER)?;
+            let pointer = input.new_id();
+            input.send(Request::new(pointer_manager, MANAGER_CREATE).uint(seat).uint(pointer))?;
+            input.pointer = Some(pointer);
+        }
+        // Protocol errors, such as a compositor refusing unprivileged clients, come back now
+        input.roundtrip(None)?;
+        Ok(input)
+    }
+
+    fn new_id(&mut self) -> u32 {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Bind version 1 of global `name`, returning the new object
+    fn bind(&mut self, registry: u32, name: u32, interface: &str) -> Result<u32, String> {
+        let id = self.new_id();
+        self.send(Request::new(registry, REGISTRY_BIND).uint(name).string(interface).uint(1).uint(id))?;
+        Ok(id)
+    }
+
+    fn send(&mut self, request: Request) -> Result<(), String> {
+        self.socket.write_all(&request.finish()).map_err(|e| format!("the compositor connection failed: {}", e))
+    }
+
+    /// Wait until the compositor has handled every request, returning the globals `registry` announced meanwhile
+    fn roundtrip(&mut self, registry: Option<u32>) -> Result<Vec<(u32, String, u32)>, String> {
+        let callback = self.new_id();
+        self.send(Request::new(DISPLAY, DISPLAY_SYNC).uint(callback))?;
+        let mut globals = Vec::new();
+        loop {
+            while let Some((object, opcode, arguments)) = self.next_event() {
+                let mut arguments = Arguments { bytes: &arguments };
+                if object == DISPLAY && opcode == DISPLAY_ERROR {
+                    let (_, _, message) = (arguments.uint(), arguments.uint(), arguments.string());
+                    return Err(format!("the compositor refused: {}", message.unwrap_or_default()));
+                }
+                if Some(object) == registry && opcode == REGISTRY_GLOBAL {
+                    if let (Some(name), Some(interface), Some(version)) =
+                        (arguments.uint(), arguments.string(), arguments.uint())
+                    {
+                        globals.push((name, interface, version));
+                    }
+                }
+                if object == callback && opcode == CALLBACK_DONE {
+                    return Ok(globals);
+                }
+            }
+            let mut buffer = [0u8; 4096];
+            match self.socket.read(&mut buffer) {
+                Ok(0) => return Err("the compositor closed the connection".to_string()),
+                Ok(read) => self.pending.extend_from_slice(&buffer[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(format!("the compositor didn't answer: {}", e)),
+            }
+        }
+    }
+
+    /// The next complete event read, as its object, opcode and arguments
+    fn next_event(&mut self) -> Option<(u32, u16, Vec<u8>)> {
+        let header = self.pending.get(..8)?;
+        let object = u32::from_ne_bytes(header[..4].try_into().ok()?);
+        let word = u32::from_ne_bytes(header[4..8].try_into().ok()?);
+        let size = ((word >> 16) as usize).max(8);
+        if self.pending.len() < size {
+            return None;
+        }
+        let arguments = self.pending[8..size].to_vec();
+        self.pending.drain(..size);
+        Some((object, (word & 0xffff) as u16, arguments))
+    }
+
+    fn time(&self) -> u32 {
+        self.started.elapsed().as_millis() as u32
+    }
+
+    /// Give each of `keysyms` a key code, uploading a new keymap if some had none
+    fn map_keysyms(&mut self, keysyms: &[u32]) -> Result<(), String> {
+        let capacity = (LAST_KEYCODE - FIRST_KEYCODE + 1) as usize;
+        let mut missing: Vec<u32> = keysyms.iter().copied().filter(|keysym| !self.keysyms.contains(keysym)).collect();
+        missing.sort_unstable();
+        missing.dedup();
+        if missing.is_empty() && self.uploaded {
+            return Ok(());
+        }
+        if self.keysyms.len() + missing.len() > capacity {
+            // Start over with only what this text needs
+            self.keysyms.clear();
+            missing = keysyms.to_vec();
+            missing.sort_unstable();
+            missing.dedup();
+            if missing.len() > capacity {
+                return Err(format!("text needs more than {} distinct keys at once", capacity));
+            }
+        }
+        self.keysyms.extend(missing);
+        self.upload_keymap()
+    }
+
+    fn upload_keymap(&mut self) -> Result<(), String> {
+        let mut keymap = keymap(&self.keysyms).into_bytes();
+        keymap.push(0);
+        let fd = unsafe { libc::memfd_create(c"nvidia-cc-keymap".as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(format!("cannot create the keymap: {}", io::Error::last_os_error()));
+        }
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(&keymap).map_err(|e| format!("cannot write the keymap: {}", e))?;
+        let request = Request::new(self.keyboard, KEYBOARD_KEYMAP).uint(KEYMAP_FORMAT_XKB_V1).uint(keymap.len() as u32);
+        input_access::send_fd(&self.socket, &request.finish(), file.as_raw_fd())
+            .map_err(|e| format!("cannot send the keymap: {}", e))?;
+        self.uploaded = true;
+        Ok(())
+    }
+
+    /// Press and release each of `keysyms` in turn
+    pub fn type_keysyms(&mut self, keysyms: &[u32]) -> Result<(), String> {
+        self.map_keysyms(keysyms)?;
+        for &keysym in keysyms {
+            self.send_key(keysym, true)?;
+            self.send_key(keysym, false)?;
+        }
+        self.roundtrip(None).map(|_| ())
+    }
+
+    /// Press or release `keysym`
+    pub fn key(&mut self, keysym: u32, pressed: bool) -> Result<(), String> {
+        self.map_keysyms(&[keysym])?;
+        self.send_key(keysym, pressed)?;
+        self.roundtrip(None).map(|_| ())
+    }
+
+    fn send_key(&mut self, keysym: u32, pressed: bool) -> Result<(), String> {
+        let index = self.keysyms.iter().position(|&mapped| mapped == keysym).unwrap_or_default() as u32;
+        // Wayland key codes are evdev codes, 8 below XKB's
+        let code = FIRST_KEYCODE + index - 8;
+        let request = Request::new(self.keyboard, KEYBOARD_KEY).uint(self.time()).uint(code).uint(pressed.into());
+        self.send(request)
+    }
+
+    /// Hold or let go of the modifiers in `mask`
+    pub fn modifiers(&mut self, mask: u32, held: bool) -> Result<(), String> {
+        // The keymap must come first
+        self.map_keysyms(&[])?;
+        if held {
+            self.modifiers |= mask;
+        } else {
+            self.modifiers &= !mask;
+        }
+        let request = Request::new(self.keyboard, KEYBOARD_MODIFIERS).uint(self.modifiers).uint(0).uint(0).uint(0);
+        self.send(request)?;
+        self.roundtrip(None).map(|_| ())
+    }
+
+    fn pointer(&self) -> Result<u32, String> {
+        self.pointer.ok_or_else(|| format!("the compositor doesn't offer {}", POINTER_MANAGER))
+    }
+
+    /// Move the pointer by `(dx, dy)` pixels
+    pub fn pointer_motion(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+        let pointer = self.pointer()?;
+        self.send(Request::new(pointer, POINTER_MOTION).uint(self.time()).fixed(dx.into()).fixed(dy.into()))?;
+        self.send(Request::new(pointer, POINTER_FRAME))?;
+        self.roundtrip(None).map(|_| ())
+    }
+
+    /// Press or release the button with evdev code `code`
+    pub fn button(&mut self, code: u16, pressed: bool) -> Result<(), String> {
+        let pointer = self.pointer()?;
+        let request = Request::new(pointer, POINTER_BUTTON).uint(self.time()).uint(code.into()).uint(pressed.into());
+        self.send(request)?;
+        self.send(Request::new(pointer, POINTER_FRAME))?;
+        self.roundtrip(None).map(|_| ())
+    }
+
+    /// Scroll by wheel notches; positive `dx` scrolls right, positive `dy` down
+    pub fn scroll(&mut self, dx: i32, dy: i32) -> Result<(), String> {
+        let pointer = self.pointer()?;
+        self.send(Request::new(pointer, POINTER_AXIS_SOURCE).uint(AXIS_SOURCE_WHEEL))?;
+        for (axis, notches) in [(AXIS_HORIZONTAL, dx), (AXIS_VERTICAL, dy)] {
+            if notches != 0 {
+                let distance = f64::from(notches) * NOTCH;
+                let request = Request::new(pointer, POINTER_AXIS_DISCRETE).uint(self.time()).uint(axis);
+                self.send(request.fixed(distance).int(notches))?;
+            }
+        }
+        self.send(Request::new(pointer, POINTER_FRAME))?;
+        self.roundtrip(None).map(|_| ())
+    }
+}
+
+/// An XKB keymap giving each of `keysyms` its own key, from `FIRST_KEYCODE` on
+fn keymap(keysyms: &[u32]) -> String {
+    let codes = (FIRST_KEYCODE..).zip(keysyms);
+    let keycodes: String = codes.clone().map(|(code, _)| format!("        <I{}> = {};\n", code, code)).collect();
+    let symbols: String =
+        codes.map(|(code, keysym)| format!("        key <I{}> {{ [ {:#x} ] }};\n", code, keysym)).collect();
+    format!(
+        "xkb_keymap {{\n    xkb_keycodes \"nvidia-cc\" {{\n        minimum = 8;\n        maximum = {};\n{}    }};\n    \
+         xkb_types \"nvidia-cc\" {{ include \"complete\" }};\n    \
+         xkb_compatibility \"nvidia-cc\" {{ include \"complete\" }};\n    \
+         xkb_symbols \"nvidia-cc\" {{\n{}    }};\n}};\n",
+        LAST_KEYCODE, keycodes, symbols
+    )
+}
+
+/// The keysym that types `c`: its Latin-1 code or the Unicode keysym, with keysyms of their own for control characters
+pub fn char_keysym(c: char) -> Option<u32> {
+    match c {
+        '\n' | '\r' => Some(0xff0d),
+        '\t' => Some(0xff09),
+        '\u{8}' => Some(0xff08),
+        ' '..='~' | '\u{a0}'..='\u{ff}' => Some(c as u32),
+        c if c.is_control() => None,
+        c => Some(0x0100_0000 + c as u32),
+    }
+}
@@ -0,0 +1,251 @@
+//! `xdo`: the part of xdotool's command line that scripts use to type,
+//! run on the injection backend chain instead of XTEST.
+//!
+//!   xdo key [--delay <ms>] [--repeat <n>] [--window <match>] <keysequence>...
+//!   xdo type [--delay <ms>] [--window <match>] <text>...
+//!   xdo keydown <keysequence>...
+//!   xdo keyup <keysequence>...
+//!
+//! A keysequence is keysyms joined by `+`, as xdotool takes them
+//! (`ctrl+shift+v`, `Return`, `Page_Down`, `KP_Enter`, `XF86AudioMute`);
+//! the names of the `press` command work too. `--window` focuses the window
+//! as `windows focus` matches it for the input and then gives focus back,
+//! where xdotool sends the keys without focusing. `--clearmodifiers` is
+//! accepted and does nothing. `type --delay` is accepted, but text goes at
+//! the backend's pace.
+//!
+//! xdotool's `keydown` returns with the key still down; here every key is
+//! held by a `key down` process started in the background, which `keyup`
+//! (or `key up`) releases, and which lets go on its own after
+//! [`inject::DEFAULT_HOLD_TIMEOUT`]. Invoked as `xdotool` (through a symlink),
+//! the binary runs `xdo` with its arguments.
+
+use enigo::Key;
+use std::error::Error;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::active_window;
+use crate::inject::{self, TextMethod};
+use crate::keys::{self, Segment};
+
+/// xdotool's default delay between keysequences
+const DEFAULT_KEY_DELAY: Duration = Duration::from_millis(12);
+
+/// How long `keydown` waits for a holder to report the key down
+const HOLD_START_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A parsed `xdo` command line
+pub enum Invocation {
+    Key {
+        sequences: Vec<Vec<Key>>,
+        repeat: u32,
+        delay: Duration,
+        window: Option<String>,
+    },
+    Type {
+        text: String,
+        window: Option<String>,
+    },
+    /// With the `key down` names of each key, for the holders
+    KeyDown(Vec<(String, Key)>),
+    KeyUp(Vec<Key>),
+}
+
+/// The `press`-style name of an xdotool keysym
+fn key_name(keysym: &str) -> String {
+    let name = match keysym {
+        "Control_L" => "controlleft",
+        "Control_R" => "controlright",
+        "Shift_L" => "shiftleft",
+        "Shift_R" => "shiftright",
+        "Alt_L" | "Alt_R" | "ISO_Level3_Shift" => "alt",
+        "Super_L" | "Super_R" | "Meta_L" | "Meta_R" | "Hyper_L" | "Hyper_R" => "meta",
+        // Keysyms for the same keys as Page_Up and Page_Down
+        "Prior" => "pageup",
+        "Next" => "pagedown",
+        "KP_Enter" => "kpreturn",
+        "KP_Add" => "kpplus",
+        "KP_Subtract" => "kpminus",
+        "KP_Multiply" => "kpmultiply",
+        "KP_Divide" => "kpdivide",
+        "KP_Decimal" | "KP_Separator" => "numpaddecimal",
+        "apostrophe" => "quote",
+        "XF86AudioRaiseVolume" => "volumeup",
+        "XF86AudioLowerVolume" => "volumedown",
+        "XF86AudioMute" => "mute",
+        "XF86AudioPlay" | "XF86AudioPause" => "playpause",
+        "XF86AudioNext" => "nexttrack",
+        "XF86AudioPrev" => "prevtrack",
+        // Page_Down, Caps_Lock, KP_1 and the like are the `press` names with underscores
+        other => return other.replace('_', ""),
+    };
+    name.to_string()
+}
+
+/// `ctrl+shift+v` or `Control_L+Return` as keys in press order, with their `press` names
+fn parse_sequence(sequence: &str) -> Result<Vec<(String, Key)>, String> {
+    if sequence.is_empty() {
+        return Err("Empty keysequence".to_string());
+    }
+    // A trailing `+` is the plus key, as in `ctrl++`
+    let (rest, plus) = match sequence.strip_suffix("++") {
+        Some(rest) => (rest, true),
+        None => (sequence, false),
+    };
+    let mut keys = Vec::new();
+    for keysym in rest.split('+') {
+        let name = key_name(keysym);
+        let key = keys::parse_key(&name).map_err(|_| format!("Unknown keysym {:?} in {:?}", keysym, sequence))?;
+        keys.push((name, key));
+    }
+    if plus {
+        keys.push(("plus".to_string(), Key::Unicode('+')));
+    }
+    Ok(keys)
+}
+
+fn milliseconds(flag: &str, value: Option<&String>) -> Result<Duration, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("{} expects milliseconds", flag))
+}
+
+/// Parse `<command> [options] <arguments>...`
+pub fn parse(args: &[String]) -> Result<Invocation, String> {
+    let (command, mut rest) = args.split_first().ok_or("xdo expects key, type, keydown or keyup")?;
+    if !["key", "type", "keydown", "keyup"].contains(&command.as_str()) {
+        return Err(format!("xdo doesn't support the {} command (expected key, type, keydown or keyup)", command));
+    }
+    let (mut delay, mut repeat, mut window) = (None, 1, None);
+    while let Some((flag, after)) = rest.split_first().filter(|(flag, _)| flag.starts_with("--")) {
+        rest = after;
+        match flag.as_str() {
+            "--clearmodifiers" => {}
+            "--delay" => {
+                delay = Some(milliseconds(flag, rest.first())?);
+                rest = &rest[1..];
+            }
+            "--repeat" if command == "key" => {
+                repeat = rest
+                    .first()
+                    .and_then(|value| value.parse().ok())
+                    .filter(|&repeat| repeat > 0)
+                    .ok_or("--repeat expects a count")?;
+                rest = &rest[1..];
+            }
+            "--window" if command == "key" || command == "type" => {
+                window = Some(rest.first().ok_or("--window expects a window id, title or JSON match")?.clone());
+                rest = &rest[1..];
+            }
+            // `--` ends the options, so text may start with dashes
+            "--" => break,
+            other => return Err(format!("xdo {} doesn't support {}", command, other)),
+        }
+    }
+    if rest.is_empty() {
+        return Err(format!("xdo {} expects at least one argument", command));
+    }
+    let sequences = || rest.iter().map(|sequence| parse_sequence(sequence)).collect::<Result<Vec<_>, _>>();
+    Ok(match command.as_str() {
+        "key" => Invocation::Key {
+            sequences: sequences()?
+                .into_iter()
+                .map(|keys| keys.into_iter().map(|(_, key)| key).collect())
+                .collect(),
+            repeat,
+            delay: delay.unwrap_or(DEFAULT_KEY_DELAY),
+            window,
+        },
+        "type" => Invocation::Type {
+            text: rest.concat(),
+            window,
+        },
+        "keydown" => Invocation::KeyDown(sequences()?.concat()),
+        _ => Invocation::KeyUp(sequences()?.concat().into_iter().map(|(_, key)| key).collect()),
+    })
+}
+
+/// Inject what `invocation` asks for
+pub fn run(invocation: Invocation) -> Result<(), Box<dyn Error>> {
+    match invocation {
+        Invocation::Key {
+            sequences,
+            repeat,
+            delay,
+            window,
+        } => {
+            let segments: Vec<Segment> = (0..repeat)
+                .flat_map(|_| sequences.iter().cloned().map(Segment::Keys))
+                .collect();
+            // One injector for all of them; progress comes after each keysequence
+            let press = || {
+                inject::write_segments_with_progress(&segments, TextMethod::Type, |done, total| {
+                    if done < total {
+                        thread::sleep(delay);
+                    }
+                    true
+                })
+                .map(|_| ())
+            };
+            with_window(window.as_deref(), press)
+        }
+        Invocation::Type { text, window } => with_window(window.as_deref(), || {
+            inject::write_segments(&[Segment::Text(text)], TextMethod::default_for_write())
+        }),
+        Invocation::KeyDown(keys) => {
+            for (name, key) in keys {
+                hold_in_background(&name, key)?;
+            }
+            Ok(())
+        }
+        Invocation::KeyUp(keys) => {
+            for key in keys.into_iter().rev() {
+                inject::release_key(key)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn with_window(
+    window: Option<&str>,
+    f: impl FnOnce() -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    match window {
+        Some(spec) => active_window::with_focus(spec, f)?,
+        None => f(),
+    }
+}
+
+/// Start `key down <name>` in the background and wait until it holds the key
+fn hold_in_background(name: &str, key: Key) -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe().map_err(|e| format!("Cannot find this executable: {}", e))?;
+    let mut holder = Command::new(exe);
+    let backends = inject::backends();
+    if !backends.is_empty() {
+        holder.args(["--inject-backend", &backends.join(",")]);
+    }
+    let mut holder = holder
+        .args(["key", "down", name])
+        // Not inherited: the holder outlives this process, and would keep a caller's pipes open
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Cannot start a holder for {}: {}", name, e))?;
+    let started = Instant::now();
+    while !inject::is_held(key) {
+        if let Ok(Some(status)) = holder.try_wait() {
+            return Err(format!("Holding {} failed ({})", name, status).into());
+        }
+        if started.elapsed() >= HOLD_START_TIMEOUT {
+            holder.kill().ok();
+            return Err(format!("Holding {} timed out", name).into());
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! A ydotoold-compatible socket in front of the uinput virtual keyboard, for
+//! `listen --ydotool-socket` and `ydotoold`.
+//!
+//! ydotool 1.x clients send each `struct input_event` as one datagram to a
+//! Unix datagram socket, `$YDOTOOL_SOCKET` or `/tmp/.ydotool_socket`, and
+//! the daemon writes them to its uinput device. Serving that socket from the
+//! helper lets `ydotool key`, `type` and `mousemove` scripts run without a
+//! second privileged daemon. Events are written to the device each time a
+//! client's `SYN_REPORT` arrives, and come back through the listener marked
+//! synthetic like the rest of our injection.
+//!
+//! Anyone who can write to the socket can type into the session, so it is
+//! created owner-only unless a mode is given.
+
+use evdev::uinput::VirtualDevice;
+use evdev::{EventType, InputEvent};
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::backend;
+
+/// Where ydotool looks without `YDOTOOL_SOCKET`
+pub const DEFAULT_SOCKET: &str = "/tmp/.ydotool_socket";
+
+/// ydotoold's default `--socket-perm`
+pub const DEFAULT_MODE: u32 = 0o600;
+
+/// A `struct input_event` on 64-bit Linux: a `timeval`, then type, code and value
+const EVENT_SIZE: usize = 24;
+
+/// `$YDOTOOL_SOCKET`, else [`DEFAULT_SOCKET`], as ydotool picks it
+pub fn default_socket() -> PathBuf {
+    std::env::var_os("YDOTOOL_SOCKET")
+        .filter(|path| !path.is_empty())
+        .map_or_else(|| PathBuf::from(DEFAULT_SOCKET), PathBuf::from)
+}
+
+/// Bind `path` with permissions `mode` and forward what clients send on a background thread
+pub fn serve(path: &Path, mode: u32) -> io::Result<()> {
+    let device = backend::virtual_device().map_err(io::Error::other)?;
+    let socket = bind(path, mode)?;
+    eprintln!("Serving ydotool clients on {}", path.display());
+    thread::spawn(move || forward(socket, device));
+    Ok(())
+}
+
+fn bind(path: &Path, mode: u32) -> io::Result<UnixDatagram> {
+    if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+        // A live ydotoold answers; only a stale socket left behind by a previous run is replaced
+        if UnixDatagram::unbound().and_then(|probe| probe.connect(path)).is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another ydotool daemon is serving it"));
+        }
+        fs::remove_file(path)?;
+    }
+    let socket = UnixDatagram::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(socket)
+}
+
+/// Type, code and value of a datagram holding one `input_event`
+fn parse_event(datagram: &[u8]) -> Option<(u16, u16, i32)> {
+    if datagram.len() != EVENT_SIZE {
+        return None;
+    }
+    let kind = u16::from_ne_bytes([datagram[16], datagram[17]]);
+    let code = u16::from_ne_bytes([datagram[18], datagram[19]]);
+    let value = i32::from_ne_bytes([datagram[20], datagram[21], datagram[22], datagram[23]]);
+    Some((kind, code, value))
+}
+
+fn forward(socket: UnixDatagram, mut device: VirtualDevice) {
+    let mut datagram = [0; EVENT_SIZE + 1];
+    let mut pending = Vec::new();
+    loop {
+        let received = match socket.recv(&mut datagram) {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("ydotool socket closed: {}", e);
+                return;
+            }
+        };
+        let Some((kind, code, value)) = parse_event(&datagram[..received]) else {
+            eprintln!("Ignoring a {}-byte ydotool datagram (expected {})", received, EVENT_SIZE);
+            continue;
+        };
+        if EventType(kind) != EventType::SYNCHRONIZATION {
+            pending.push(InputEvent::new(kind, code, value));
+            continue;
+        }
+        // emit() ends the batch with its own SYN_REPORT
+        if !pending.is_empty() {
+            if let Err(e) = device.emit(&pending) {
+                eprintln!("Failed to forward ydotool input: {}", e);
+            }
+            pending.clear();
+        }
+    }
+}
@@ -0,0 +1,283 @@
+//! zstd compression for recordings and socket clients.
+//!
+//! `--record-to` compresses when the path ends in `.zst`, `dump` likewise,
+//! and `listen --backend replay` reads `.zst` inputs. Socket clients ask for
+//! it with `subscribe --compress zstd`. libzstd is loaded when first needed,
+//! as xkbcommon is, so the helper runs without it; asking for compression
+//! then fails with a message naming the library.
+//!
+//! An [`Encoder`] writes one zstd frame. Flushing it ends the current block,
+//! so whatever was written decompresses at once; dropping it without
+//! [`Encoder::finish`] leaves the frame without its epilogue, which decoders
+//! report as truncated after yielding everything flushed.
+
+use libloading::Library;
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr};
+use std::io::{self, Read, Write};
+use std::sync::OnceLock;
+
+/// Compression level used throughout; zstd's own default
+const LEVEL: c_int = 3;
+
+/// `ZSTD_c_compressionLevel`
+const COMPRESSION_LEVEL: c_int = 100;
+
+/// `ZSTD_EndDirective`
+const CONTINUE: c_int = 0;
+const FLUSH: c_int = 1;
+const END: c_int = 2;
+
+const BUFFER_BYTES: usize = 64 * 1024;
+
+#[cfg(target_os = "linux")]
+const LIBRARY_NAMES: &[&str] = &["libzstd.so.1", "libzstd.so"];
+#[cfg(target_os = "macos")]
+const LIBRARY_NAMES: &[&str] =
+    &["libzstd.1.dylib", "/opt/homebrew/lib/libzstd.1.dylib", "/usr/local/lib/libzstd.1.dylib"];
+#[cfg(windows)]
+const LIBRARY_NAMES: &[&str] = &["zstd.dll", "libzstd.dll"];
+
+#[repr(C)]
+struct InBuffer {
+    src: *const c_void,
+    size: usize,
+    pos: usize,
+}
+
+#[repr(C)]
+struct OutBuffer {
+    dst: *mut c_void,
+    size: usize,
+    pos: usize,
+}
+
+type Stream = unsafe extern "C" fn(*mut c_void, *mut OutBuffer, *mut InBuffer) -> usize;
+
+struct Api {
+    create_cctx: unsafe extern "C" fn() -> *mut c_void,
+    free_cctx: unsafe extern "C" fn(*mut c_void) -> usize,
+    set_parameter: unsafe extern "C" fn(*mut c_void, c_int, c_int) -> usize,
+    compress_stream2: unsafe extern "C" fn(*mut c_void, *mut OutBuffer, *mut InBuffer, c_int) -> usize,
+    create_dctx: unsafe extern "C" fn() -> *mut c_void,
+    free_dctx: unsafe extern "C" fn(*mut c_void) -> usize,
+    decompress_stream: Stream,
+    is_error: unsafe extern "C" fn(usize) -> c_uint,
+    error_name: unsafe extern "C" fn(usize) -> *const c_char,
+    /// Keeps the functions above loaded
+    _library: Library,
+}
+
+impl Api {
+    unsafe fn load() -> Result<Api, String> {
+        let library = LIBRARY_NAMES
+            .iter()
+            .find_map(|name| Library::new(name).ok())
+            .ok_or_else(|| format!("zstd compression needs libzstd ({})", LIBRARY_NAMES[0]))?;
+        macro_rules! symbol {
+            ($name:literal) => {
+                *library
+                    .get(concat!($name, "\0").as_bytes())
+                    .map_err(|e| format!("libzstd lacks {}: {}", $name, e))?
+            };
+        }
+        Ok(Api {
+            create_cctx: symbol!("ZSTD_createCCtx"),
+            free_cctx: symbol!("ZSTD_freeCCtx"),
+            set_parameter: symbol!("ZSTD_CCtx_setParameter"),
+            compress_stream2: symbol!("ZSTD_compressStream2"),
+            create_dctx: symbol!("ZSTD_createDCtx"),
+            free_dctx: symbol!("ZSTD_freeDCtx"),
+            decompress_stream: symbol!("ZSTD_decompressStream"),
+            is_error: symbol!("ZSTD_isError"),
+            error_name: symbol!("ZSTD_getErrorName"),
+            _library: library,
+        })
+    }
+
+    /// `code` as an io::Result, zstd's error name as the message
+    fn check(&self, code: usize) -> io::Result<usize> {
+        if unsafe { (self.is_error)(code) } == 0 {
+            return Ok(code);
+        }
+        let name = unsafe { CStr::from_ptr((self.error_name)(code)) };
+        Err(io::Error::other(format!("zstd: {}", name.to_string_lossy())))
+    }
+}
+
+fn api() -> Result<&'static Api, String> {
+    static API: OnceLock<Result<Api, String>> = OnceLock::new();
+    API.get_or_init(|| unsafe { Api::load() }).as_ref().map_err(String::clone)
+}
+
+/// Whether libzstd could be loaded
+pub fn available() -> bool {
+    api().is_ok()
+}
+
+/// libzstd, or why it can't be loaded
+pub fn require() -> Result<(), String> {
+    api().map(|_| ())
+}
+
+/// Whether `path` names a compressed file, by its `.zst` extension
+pub fn is_compressed(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "zst")
+}
+
+/// Compresses everything written to it into `inner`
+pub struct Encoder<W: Write> {
+    api: &'static Api,
+    cctx: *mut c_void,
+    inner: W,
+    buffer: Vec<u8>,
+    /// Compressed bytes handed to `inner`
+    written: u64,
+}
+
+// The context is only ever used through `&mut self`
+unsafe impl<W: Write + Send> Send for Encoder<W> {}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(inner: W) -> Result<Self, String> {
+        let api = api()?;
+        let cctx = unsafe { (api.create_cctx)() };
+        if cctx.is_null() {
+            return Err("zstd: cannot create a compression context".to_string());
+        }
+        unsafe { (api.set_parameter)(cctx, COMPRESSION_LEVEL, LEVEL) };
+        Ok(Encoder {
+            api,
+            cctx,
+            inner,
+            buffer: vec![0; BUFFER_BYTES],
+            written: 0,
+        })
+    }
+
+    /// Compressed bytes written to the inner writer so far
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Run the compressor over `input` with `directive` until it is consumed
+    /// and, unless continuing, until zstd has nothing left to write
+    fn run(&mut self, input: &[u8], directive: c_int) -> io::Result<()> {
+        let mut input = InBuffer {
+            src: input.as_ptr().cast(),
+            size: input.len(),
+            pos: 0,
+        };
+        loop {
+            let mut output = OutBuffer {
+                dst: self.buffer.as_mut_ptr().cast(),
+                size: self.buffer.len(),
+                pos: 0,
+            };
+            let remaining =
+                self.api.check(unsafe { (self.api.compress_stream2)(self.cctx, &mut output, &mut input, directive) })?;
+            self.inner.write_all(&self.buffer[..output.pos])?;
+            self.written += output.pos as u64;
+            let done = match directive {
+                CONTINUE => input.pos == input.size,
+                _ => remaining == 0,
+            };
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    /// End the frame, leaving a complete zstd file; later writes start a new frame
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.run(&[], END)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.run(buf, CONTINUE)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.run(&[], FLUSH)?;
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        unsafe { (self.api.free_cctx)(self.cctx) };
+    }
+}
+
+/// Decompresses `inner`, concatenated frames included
+pub struct Decoder<R: Read> {
+    api: &'static Api,
+    dctx: *mut c_void,
+    inner: R,
+    buffer: Vec<u8>,
+    /// Compressed bytes in `buffer` not yet decompressed: `buffer[pos..end]`
+    pos: usize,
+    end: usize,
+}
+
+unsafe impl<R: Read + Send> Send for Decoder<R> {}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(inner: R) -> Result<Self, String> {
+        let api = api()?;
+        let dctx = unsafe { (api.create_dctx)() };
+        if dctx.is_null() {
+            return Err("zstd: cannot create a decompression context".to_string());
+        }
+        Ok(Decoder {
+            api,
+            dctx,
+            inner,
+            buffer: vec![0; BUFFER_BYTES],
+            pos: 0,
+            end: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.pos == self.end {
+                self.end = self.inner.read(&mut self.buffer)?;
+                self.pos = 0;
+                // A truncated last frame ends the data that was flushed before it
+                if self.end == 0 {
+                    return Ok(0);
+                }
+            }
+            let mut input = InBuffer {
+                src: self.buffer[self.pos..self.end].as_ptr().cast(),
+                size: self.end - self.pos,
+                pos: 0,
+            };
+            let mut output = OutBuffer {
+                dst: buf.as_mut_ptr().cast(),
+                size: buf.len(),
+                pos: 0,
+            };
+            self.api.check(unsafe { (self.api.decompress_stream)(self.dctx, &mut output, &mut input) })?;
+            self.pos += input.pos;
+            if output.pos > 0 {
+                return Ok(output.pos);
+            }
+        }
+    }
+}
+
+impl<R: Read> Drop for Decoder<R> {
+    fn drop(&mut self) {
+        unsafe { (self.api.free_dctx)(self.dctx) };
+    }
+}
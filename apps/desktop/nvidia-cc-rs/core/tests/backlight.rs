@@ -0,0 +1,16 @@
+//! `backlight set` arguments.
+
+use nvidia_cc_core::backlight;
+
+#[test]
+fn set_takes_a_percentage_color_and_device() {
+    let setting = backlight::parse_set(&["40%", "--color", "#ff8000", "--device", "rgb:kbd_backlight"]).expect("parse");
+    assert_eq!(setting.percent, 40);
+    assert_eq!(setting.color, Some([0xff, 0x80, 0x00]));
+    assert_eq!(setting.device.as_deref(), Some("rgb:kbd_backlight"));
+
+    assert_eq!(backlight::parse_set(&["off"]).expect("parse").percent, 0);
+    for invalid in [&["101"][..], &["on", "--color", "red"], &["on", "--color"], &["on", "--blink"], &[]] {
+        assert!(backlight::parse_set(invalid).is_err(), "accepted {:?}", invalid);
+    }
+}
@@ -0,0 +1,13 @@
+//! C interface argument checks (`--features cdylib`); nothing is captured or typed.
+#![cfg(feature = "cdylib")]
+
+use nvidia_cc_core::ffi::{ncc_listen, ncc_shutdown, ncc_write};
+
+#[test]
+fn invalid_arguments_fail_without_side_effects() {
+    assert_eq!(ncc_listen(None), -1);
+    assert_eq!(unsafe { ncc_write(std::ptr::null()) }, -1);
+    assert_eq!(unsafe { ncc_write(c"\xff\xfe".as_ptr()) }, -1);
+    // Without a listener there is nothing to stop
+    ncc_shutdown();
+}
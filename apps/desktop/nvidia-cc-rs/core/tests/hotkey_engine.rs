@@ -0,0 +1,59 @@
+//! `HotkeyEngine` embedded in-process: hotkey events reach a stream subscriber.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use nvidia_cc_core::{stream, HotkeyEngine};
+
+/// Collects the event stream's JSON lines
+#[derive(Clone, Default)]
+struct Sink(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Sink {
+    /// `<event_type> <id>` for every hotkey event received so far
+    fn hotkey_events(&self) -> Vec<String> {
+        String::from_utf8_lossy(&self.0.lock().unwrap())
+            .lines()
+            .filter_map(|line| {
+                let event: serde_json::Value = serde_json::from_str(line).expect("events are JSON lines");
+                Some(format!("{} {}", event["event_type"].as_str()?, event["id"].as_str()?))
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn bindings_change_without_losing_a_held_hotkey() {
+    let sink = Sink::default();
+    stream::subscribe(Box::new(sink.clone()), None).expect("subscribe");
+
+    let engine = HotkeyEngine;
+    engine.load_json(r#"[{"id": "cancel", "keys": "Escape"}]"#).expect("load hotkeys");
+    assert_eq!(engine.add_json(r#"{"id": "dictate", "keys": "ControlLeft+Space"}"#), Ok("dictate".to_string()));
+    assert!(engine.add_json(r#"{"id": "dictate", "keys": "KeyD"}"#).is_err(), "duplicate id accepted");
+    let ids: Vec<_> = engine.list().iter().map(|hotkey| hotkey["id"].as_str().unwrap().to_string()).collect();
+    assert_eq!(ids, ["cancel", "dictate"]);
+
+    engine.observe(true, "ControlLeft");
+    engine.observe(true, "Space");
+    // Removing another hotkey mid-press must not lose this one's release
+    engine.remove("cancel").expect("remove");
+    engine.observe(true, "Escape");
+    engine.observe(false, "Escape");
+    engine.observe(false, "Space");
+    engine.observe(false, "ControlLeft");
+
+    assert!(engine.remove("cancel").is_err(), "removed twice");
+    assert_eq!(sink.hotkey_events(), ["HotkeyTriggered dictate", "HotkeyReleased dictate"]);
+}
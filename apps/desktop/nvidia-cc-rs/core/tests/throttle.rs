@@ -0,0 +1,32 @@
+//! Duplicate-node deduplication and the event rate limit.
+
+use std::time::{Duration, SystemTime};
+
+use nvidia_cc_core::throttle;
+
+#[test]
+fn same_key_from_a_sibling_node_is_a_duplicate() {
+    let phys = "isa0060/serio0/input0";
+    let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert!(!throttle::duplicate(phys, "/dev/input/event3", 30, 1, at));
+    assert!(throttle::duplicate(phys, "/dev/input/event7", 30, 1, at + Duration::from_millis(1)));
+    // The release is a different event, and so is a second press past the window
+    assert!(!throttle::duplicate(phys, "/dev/input/event3", 30, 0, at + Duration::from_millis(2)));
+    assert!(!throttle::duplicate(phys, "/dev/input/event7", 30, 1, at + Duration::from_millis(50)));
+    // Presses repeated on one node are real
+    assert!(!throttle::duplicate(phys, "/dev/input/event7", 30, 1, at + Duration::from_millis(51)));
+    // Without a phys nothing can be matched up
+    assert!(!throttle::duplicate("", "/dev/input/event3", 31, 1, at + Duration::from_millis(60)));
+    assert!(!throttle::duplicate("", "/dev/input/event7", 31, 1, at + Duration::from_millis(60)));
+}
+
+#[test]
+fn rate_limit_drops_events_over_the_burst() {
+    throttle::set_max_rate(Some(3));
+    let admitted = (0..10).filter(|_| throttle::admit()).count();
+    assert_eq!(admitted, 3);
+    assert_eq!(throttle::rate_limited(), 7);
+
+    throttle::set_max_rate(None);
+    assert!((0..10).all(|_| throttle::admit()));
+}
@@ -0,0 +1,65 @@
+//! zstd streams written by the encoder must read back whole, flushed and
+//! finished frames alike. Skipped where libzstd isn't installed.
+
+use std::io::{Read, Write};
+
+use nvidia_cc_core::zstd;
+
+fn decompress(bytes: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::new();
+    zstd::Decoder::new(bytes).expect("decoder").read_to_end(&mut decoded).expect("decompress");
+    decoded
+}
+
+#[test]
+fn finished_frames_round_trip() {
+    if !zstd::available() {
+        eprintln!("libzstd not installed, skipping");
+        return;
+    }
+    let line = b"{\"seq\":1,\"mono_us\":1000,\"event_type\":\"KeyPress\",\"key\":\"KeyA\"}\n";
+    let mut compressed = Vec::new();
+    let mut encoder = zstd::Encoder::new(&mut compressed).expect("encoder");
+    for _ in 0..1000 {
+        encoder.write_all(line).unwrap();
+    }
+    encoder.finish().unwrap();
+    let first_frame = encoder.written();
+    // Two frames back to back, as in a recording appended to twice
+    encoder.write_all(b"last\n").unwrap();
+    encoder.finish().unwrap();
+    let written = encoder.written();
+    drop(encoder);
+
+    assert!(first_frame < 1000, "{} bytes for 64 KB of repeated events", first_frame);
+    assert_eq!(written, compressed.len() as u64);
+    let mut expected = line.repeat(1000);
+    expected.extend_from_slice(b"last\n");
+    assert_eq!(decompress(&compressed), expected);
+}
+
+#[test]
+fn flushed_data_reads_back_before_the_frame_ends() {
+    if !zstd::available() {
+        eprintln!("libzstd not installed, skipping");
+        return;
+    }
+    let mut compressed = Vec::new();
+    {
+        let mut encoder = zstd::Encoder::new(&mut compressed).expect("encoder");
+        encoder.write_all(b"first\n").unwrap();
+        encoder.flush().unwrap();
+        encoder.write_all(b"second\n").unwrap();
+        encoder.finish().unwrap();
+        encoder.write_all(b"third\n").unwrap();
+        encoder.flush().unwrap();
+        // Dropped without finishing, as by a crash
+    }
+    assert_eq!(decompress(&compressed), b"first\nsecond\nthird\n");
+}
+
+#[test]
+fn compressed_paths_are_recognized_by_extension() {
+    assert!(zstd::is_compressed("session.ndjson.zst".as_ref()));
+    assert!(!zstd::is_compressed("session.ndjson".as_ref()));
+}
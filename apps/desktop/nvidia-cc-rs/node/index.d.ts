@@ -0,0 +1,24 @@
+import { EventEmitter } from "node:events"
+
+export type HelperEvent = {
+  event_type: string
+  [field: string]: unknown
+}
+
+export interface WriteOptions {
+  /** Paste instead of typing while an input method is active */
+  imeSafe?: boolean
+  /** Treat {key} and {combo} in the text as key presses */
+  keys?: boolean
+}
+
+export declare class KeyboardListener extends EventEmitter {
+  /** Load hotkeys and hotstrings from the config file and start capturing */
+  start(): void
+  /** Stop emitting events and matching hotkeys and hotstrings */
+  stop(): void
+  on(event: string, listener: (event: HelperEvent) => void): this
+}
+
+export declare function write(text: string, options?: WriteOptions): Promise<void>
+export declare function press(combo: string): Promise<void>
@@ -0,0 +1,27 @@
+// The native listener as an EventEmitter. Every event is emitted as "event"
+// and under its event_type ("KeyPress", "HotkeyTriggered", ...), with the same
+// fields as a line of the helper's stdout.
+const { EventEmitter } = require("node:events")
+const native = require("./binding")
+
+class KeyboardListener extends EventEmitter {
+  // There is one keyboard per process: starting a second listener takes the
+  // events away from the first
+  start() {
+    native.startListener((json) => {
+      const event = JSON.parse(json)
+      this.emit("event", event)
+      this.emit(event.event_type, event)
+    })
+  }
+
+  stop() {
+    native.stopListener()
+  }
+}
+
+module.exports = {
+  KeyboardListener,
+  write: native.write,
+  press: native.press,
+}
@@ -0,0 +1,80 @@
+//! Node.js binding of nvidia-cc-core, for Electron shells that would rather
+//! load the helper than spawn it and parse its stdout.
+//!
+//! `index.js` wraps this module: the listener becomes an EventEmitter, and
+//! `write`/`press` return promises resolved once the keys are typed. Injection
+//! runs on the libuv thread pool, so it never blocks the event loop. See
+//! [`nvidia_cc_core::embed`] for what starting and stopping the listener
+//! does.
+
+use napi::bindgen_prelude::*;
+use napi::threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi_derive::napi;
+use nvidia_cc_core::{embed, TextInjector};
+
+/// Start capturing, calling `onEvent` on the JS thread with each event's JSON
+#[napi]
+pub fn start_listener(on_event: ThreadsafeFunction<String, (), String, Status, false>) -> Result<()> {
+    embed::start(move |event| {
+        // Only queues the call, as embed::start requires
+        on_event.call(event.to_string(), ThreadsafeFunctionCallMode::NonBlocking);
+    })
+    .map_err(Error::from_reason)
+}
+
+/// Stop delivering events and matching hotkeys and hotstrings
+#[napi]
+pub fn stop_listener() {
+    embed::stop();
+}
+
+#[napi(object)]
+pub struct WriteOptions {
+    /// Paste instead of typing while an input method is active
+    pub ime_safe: Option<bool>,
+    /// Treat `{key}` and `{combo}` in the text as key presses
+    pub keys: Option<bool>,
+}
+
+pub enum Injection {
+    Write { text: String, options: Option<WriteOptions> },
+    Press { combo: String },
+}
+
+impl Task for Injection {
+    type Output = ();
+    type JsValue = ();
+
+    fn compute(&mut self) -> Result<()> {
+        let result = match self {
+            Injection::Write { text, options } => {
+                let mut injector = TextInjector::new();
+                if let Some(options) = options {
+                    if let Some(ime_safe) = options.ime_safe {
+                        injector = injector.ime_safe(ime_safe);
+                    }
+                    injector = injector.key_escapes(options.keys.unwrap_or(false));
+                }
+                injector.write(text)
+            }
+            Injection::Press { combo } => TextInjector::new().press(combo),
+        };
+        result.map_err(|e| Error::from_reason(e.to_string()))
+    }
+
+    fn resolve(&mut self, _env: Env, _output: ()) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Type `text`
+#[napi(ts_return_type = "Promise<void>")]
+pub fn write(text: String, options: Option<WriteOptions>) -> AsyncTask<Injection> {
+    AsyncTask::new(Injection::Write { text, options })
+}
+
+/// Press a combo such as `ctrl+shift+v`
+#[napi(ts_return_type = "Promise<void>")]
+pub fn press(combo: String) -> AsyncTask<Injection> {
+    AsyncTask::new(Injection::Press { combo })
+}
@@ -1,401 +1,1647 @@
-use serde::Serialize;
-use serde_json::json;
+use std::path::PathBuf;
 
-// On non-Linux platforms, use rdev
+#[cfg(target_os = "linux")]
+use nvidia_cc_core::{
+    dbus, evtest, input_access, layout, listener::evdev_key_to_rdev_name, permissions, portal, sandbox, ydotool,
+};
 #[cfg(not(target_os = "linux"))]
-use rdev::{listen, Event, EventType};
-
-#[derive(Serialize)]
-struct KeyboardEvent {
-    event_type: String,
-    name: Option<String>,
-    time: std::time::SystemTime,
-    data: String,
+use nvidia_cc_core::input_source;
+#[cfg(any(unix, windows))]
+use nvidia_cc_core::{socket, tcp, tls};
+use nvidia_cc_core::{
+    active_window, backends, backlight, bench, clipboard, clock, config, context, control, environment, event, gamepad,
+    gkeys, gpu, hotkeys, hotstrings, http, idle, inhibit, inject, journal, keymap, keys, leds, macros, metrics,
+    monitors, notify, output, parent, playback, power, privacy, scancode, screenshot, secure_input, selftest, service,
+    session, stream, synthetic, throttle, xdo, zstd, KeyboardListener,
+};
+
+use event::{Event, EventKind};
+
+/// Options accepted after the `listen` command
+#[derive(Default)]
+struct ListenOptions {
+    /// Also serve the event stream on this Unix domain socket (named pipe on Windows)
+    socket_path: Option<PathBuf>,
+    /// Serve ydotool clients on this datagram socket through the uinput keyboard (Linux)
+    ydotool_socket: Option<PathBuf>,
+    /// Open input devices through the `input-broker` on this socket (Linux)
+    input_broker: Option<PathBuf>,
+    /// Confine the process to reading devices and writing to stdout once they're open (Linux)
+    sandbox: bool,
+    /// Emit DriverProblem and raise a toast to download the driver when nvidia-smi can't be asked (Windows)
+    driver_guidance: bool,
+    /// Serve io.github.aj47.NvidiaCC on the session bus (Linux)
+    dbus: bool,
+    /// Raise listener thread priority to cut activation latency under load
+    realtime: bool,
+    /// Coalesce stdout writes over this interval instead of flushing every event
+    flush_interval: Option<std::time::Duration>,
+    /// What stdout lines look like; everything else keeps getting JSON
+    output_format: output::Format,
+    /// Exit automatically once this process (normally the spawning app) is gone
+    parent_pid: Option<u32>,
+    /// Serve a read-only HTTP status page on this address
+    http_addr: Option<String>,
+    /// Token required by the HTTP status page; generated when not given
+    http_token: Option<String>,
+    /// Serve Prometheus metrics on this address
+    metrics_addr: Option<String>,
+    /// Serve the event stream and commands to authenticated clients on this TCP address
+    tcp_addr: Option<String>,
+    /// Token `--tcp` clients must present; generated when not given, on loopback only
+    tcp_token: Option<String>,
+    /// PEM certificate chain and private key to serve `--tcp` over TLS with
+    tls: Option<(PathBuf, PathBuf)>,
+    /// Drop keystrokes injected by this helper instead of tagging them
+    suppress_self: bool,
+    /// Emit the original `data`-string event shape for consumers that haven't migrated
+    legacy_format: bool,
+    /// Emit media and brightness keys, and on Linux open devices that only have those
+    media_keys: bool,
+    /// Pause capture while the session is locked
+    pause_when_locked: bool,
+    /// Capture other seats' keyboards and keys typed while our session is inactive (Linux)
+    all_sessions: bool,
+    /// Which key identities are streamed
+    privacy: privacy::Mode,
+    /// How event timestamps are serialized
+    time_format: event::TimeFormat,
+    /// Add each key's evdev code, scan code or macOS key code to key events
+    raw_scancodes: bool,
+    /// Key events emitted per second at most
+    max_event_rate: Option<u32>,
+    /// Emit `UserIdle` after this long without input, then `UserActive`
+    idle_after: Option<std::time::Duration>,
+    /// Hotkey definitions matched in the helper
+    hotkeys_path: Option<PathBuf>,
+    /// Hotstring definitions expanded by the helper
+    hotstrings_path: Option<PathBuf>,
+    /// Devices not to open, from the config file (Linux)
+    ignored_devices: Vec<regex::Regex>,
+    /// Devices to open instead of autodetecting keyboards (Linux)
+    device_paths: Vec<PathBuf>,
+    /// Open every device with keys or buttons, not only keyboards and trigger devices (Linux)
+    any_keys: bool,
+    /// Emit gamepad buttons and match them against hotkeys
+    gamepads: bool,
+    /// Read Logitech G-keys over hidraw (Linux)
+    g_keys: bool,
+    /// Where key events come from
+    backend: CaptureBackend,
+    /// Append every emitted event to this file, rotating it
+    record_to: Option<PathBuf>,
+    /// Keep this many of the last events in memory for the `dump` command
+    record_last: Option<usize>,
 }
 
-// ============ Non-Linux (macOS/Windows) implementation using rdev ============
-#[cfg(not(target_os = "linux"))]
-fn deal_event_to_json(event: Event) -> KeyboardEvent {
-    let mut jsonify_event = KeyboardEvent {
-        event_type: "".to_string(),
-        name: event.name,
-        time: event.time,
-        data: "".to_string(),
-    };
-    match event.event_type {
-        EventType::KeyPress(key) => {
-            jsonify_event.event_type = "KeyPress".to_string();
-            jsonify_event.data = json!({"key": format!("{:?}", key)}).to_string();
+/// Where `listen` gets key events from
+#[derive(Default)]
+enum CaptureBackend {
+    /// The keyboards, through evdev on Linux and rdev elsewhere
+    #[default]
+    Devices,
+    /// Recorded events read from a file, with `--backend replay --input <file>`
+    Replay(PathBuf),
+}
+
+impl ListenOptions {
+    /// Flags on the command line, on top of the `[listen]` defaults from the config file and environment
+    fn parse(args: &[String], defaults: &config::Listen) -> Result<Self, String> {
+        let mut options = ListenOptions {
+            socket_path: defaults.socket.clone(),
+            ydotool_socket: defaults.ydotool_socket.clone(),
+            input_broker: defaults.input_broker.clone(),
+            sandbox: defaults.sandbox,
+            driver_guidance: defaults.driver_guidance,
+            dbus: defaults.dbus,
+            realtime: defaults.realtime,
+            suppress_self: defaults.suppress_self,
+            media_keys: defaults.media_keys,
+            legacy_format: defaults.legacy_format,
+            pause_when_locked: defaults.pause_when_locked,
+            all_sessions: defaults.all_sessions,
+            any_keys: defaults.any_keys,
+            gamepads: defaults.gamepads,
+            g_keys: defaults.g_keys,
+            privacy: defaults.privacy,
+            time_format: defaults.time_format,
+            raw_scancodes: defaults.raw_scancodes,
+            max_event_rate: defaults.max_event_rate,
+            idle_after: defaults.idle_after_secs.map(std::time::Duration::from_secs),
+            record_to: defaults.record_to.clone(),
+            record_last: defaults.record_last,
+            flush_interval: defaults.flush_interval_ms.map(std::time::Duration::from_millis),
+            output_format: defaults.output,
+            hotkeys_path: defaults.hotkeys.clone(),
+            hotstrings_path: defaults.hotstrings.clone(),
+            http_addr: defaults.http.clone(),
+            http_token: defaults.http_token.clone(),
+            metrics_addr: defaults.metrics_addr.clone(),
+            tcp_addr: defaults.tcp.clone(),
+            tcp_token: defaults.tcp_token.clone(),
+            tls: defaults.tls_cert.clone().zip(defaults.tls_key.clone()),
+            ..Self::default()
+        };
+        let mut backend = None;
+        let mut input = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                // --backend predates --inject-backend and is kept for existing callers
+                "--capture-backend" | "--backend" => {
+                    backend = Some(args.next().ok_or_else(|| format!("{} requires a name (devices or replay)", arg))?)
+                }
+                "--input" => input = Some(PathBuf::from(args.next().ok_or("--input requires a path")?)),
+                "--socket" => {
+                    let path = args.next().ok_or("--socket requires a path")?;
+                    options.socket_path = Some(PathBuf::from(path));
+                }
+                "--ydotool-socket" => {
+                    let path = args.next().ok_or("--ydotool-socket requires a path, e.g. /tmp/.ydotool_socket")?;
+                    options.ydotool_socket = Some(PathBuf::from(path));
+                }
+                "--input-broker" => {
+                    let path = args
+                        .next()
+                        .ok_or("--input-broker requires a path, e.g. /run/nvidia-cc/input-broker.sock")?;
+                    options.input_broker = Some(PathBuf::from(path));
+                }
+                "--sandbox" => options.sandbox = true,
+                "--driver-guidance" => options.driver_guidance = true,
+                "--dbus" => options.dbus = true,
+                "--realtime" => options.realtime = true,
+                "--suppress-self" => options.suppress_self = true,
+                "--legacy-format" => options.legacy_format = true,
+                "--media-keys" => options.media_keys = true,
+                "--pause-when-locked" => options.pause_when_locked = true,
+                "--all-sessions" => options.all_sessions = true,
+                "--any-keys" => options.any_keys = true,
+                "--gamepads" => options.gamepads = true,
+                "--g-keys" => options.g_keys = true,
+                "--raw-scancodes" => options.raw_scancodes = true,
+                "--privacy" => {
+                    let mode = args.next().ok_or("--privacy requires a mode (off, allowlist or hash)")?;
+                    options.privacy = privacy::Mode::parse(mode).map_err(|e| format!("--privacy: {}", e))?;
+                }
+                "--output" => {
+                    let format = args.next().ok_or("--output requires a format (json, csv, pretty or msgpack)")?;
+                    options.output_format = output::Format::parse(format).map_err(|e| format!("--output: {}", e))?;
+                }
+                "--time-format" => {
+                    let format = args.next().ok_or("--time-format requires a format (epoch-ms, rfc3339 or monotonic)")?;
+                    let format = event::TimeFormat::parse(format).map_err(|e| format!("--time-format: {}", e))?;
+                    options.time_format = format;
+                }
+                "--device" => {
+                    let path = args.next().ok_or("--device requires a path")?;
+                    options.device_paths.push(PathBuf::from(path));
+                }
+                "--hotkeys" => {
+                    let path = args.next().ok_or("--hotkeys requires a path")?;
+                    options.hotkeys_path = Some(PathBuf::from(path));
+                }
+                "--hotstrings" => {
+                    let path = args.next().ok_or("--hotstrings requires a path")?;
+                    options.hotstrings_path = Some(PathBuf::from(path));
+                }
+                "--flush-interval-ms" => {
+                    let value = args.next().ok_or("--flush-interval-ms requires a value")?;
+                    let interval_ms: u64 = value
+                        .parse()
+                        .map_err(|_| format!("Invalid --flush-interval-ms value: {}", value))?;
+                    options.flush_interval = Some(std::time::Duration::from_millis(interval_ms));
+                }
+                "--max-event-rate" => {
+                    let value = args.next().ok_or("--max-event-rate requires a value")?;
+                    let rate = value
+                        .parse()
+                        .map_err(|_| format!("Invalid --max-event-rate value: {}", value))?;
+                    options.max_event_rate = Some(rate);
+                }
+                "--idle-after" => {
+                    let value = args.next().ok_or("--idle-after requires a number of seconds")?;
+                    let secs = value
+                        .parse()
+                        .map_err(|_| format!("Invalid --idle-after value: {}", value))?;
+                    options.idle_after = Some(std::time::Duration::from_secs(secs));
+                }
+                "--record-to" => {
+                    let path = args.next().ok_or("--record-to requires a path")?;
+                    options.record_to = Some(PathBuf::from(path));
+                }
+                "--record-last" => {
+                    let value = args.next().ok_or("--record-last requires a count")?;
+                    let count = value
+                        .parse()
+                        .map_err(|_| format!("Invalid --record-last value: {}", value))?;
+                    options.record_last = Some(count);
+                }
+                "--parent-pid" => {
+                    let value = args.next().ok_or("--parent-pid requires a pid")?;
+                    // kill(0, 0) signals our own process group and a pid past i32::MAX wraps to a
+                    // negative one, so both would always look alive
+                    let pid = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|pid| *pid != 0 && *pid <= i32::MAX as u32)
+                        .ok_or_else(|| format!("Invalid --parent-pid value: {}", value))?;
+                    options.parent_pid = Some(pid);
+                }
+                "--http" => {
+                    let addr = args.next().ok_or("--http requires an address, e.g. 127.0.0.1:9835")?;
+                    options.http_addr = Some(addr.clone());
+                }
+                "--http-token" => {
+                    let token = args.next().ok_or("--http-token requires a value")?;
+                    options.http_token = Some(token.clone());
+                }
+                "--metrics-addr" => {
+                    let addr = args.next().ok_or("--metrics-addr requires an address, e.g. 127.0.0.1:9836")?;
+                    options.metrics_addr = Some(addr.clone());
+                }
+                "--tcp" => {
+                    let addr = args.next().ok_or("--tcp requires an address, e.g. 0.0.0.0:9837")?;
+                    options.tcp_addr = Some(addr.clone());
+                }
+                "--token" => {
+                    let token = args.next().ok_or("--token requires a value")?;
+                    options.tcp_token = Some(token.clone());
+                }
+                "--tls" => {
+                    let (Some(cert), Some(key)) = (args.next(), args.next()) else {
+                        return Err("--tls requires a certificate and a key file (PEM)".to_string());
+                    };
+                    options.tls = Some((PathBuf::from(cert), PathBuf::from(key)));
+                }
+                other => return Err(format!("Unknown listen option: {}", other)),
+            }
         }
-        EventType::KeyRelease(key) => {
-            jsonify_event.event_type = "KeyRelease".to_string();
-            jsonify_event.data = json!({"key": format!("{:?}", key)}).to_string();
+        options.backend = match (backend.map(String::as_str), input) {
+            (None | Some("devices"), None) => CaptureBackend::Devices,
+            (Some("replay"), Some(input)) => CaptureBackend::Replay(input),
+            (Some("replay"), None) => return Err("--backend replay requires --input <file>".to_string()),
+            (None | Some("devices"), Some(_)) => return Err("--input only applies to --backend replay".to_string()),
+            (Some(other), _) => {
+                return Err(format!(
+                    "Unknown capture backend {:?} (expected {})",
+                    other,
+                    backends::CAPTURE_NAMES.join(" or ")
+                ))
+            }
+        };
+        if options.tcp_addr.is_none() && (options.tcp_token.is_some() || options.tls.is_some()) {
+            return Err("--token and --tls only apply to --tcp".to_string());
         }
-        _ => {}
+        if options.sandbox {
+            options.check_sandbox()?;
+        }
+        if options.driver_guidance && !cfg!(windows) {
+            return Err("--driver-guidance is only supported on Windows".to_string());
+        }
+        Ok(options)
     }
-    jsonify_event
-}
 
-#[cfg(not(target_os = "linux"))]
-fn keyboard_callback(event: Event) {
-    match event.event_type {
-        EventType::KeyPress(_) | EventType::KeyRelease(_) => {
-            let json_event = deal_event_to_json(event);
-            println!("{}", serde_json::to_string(&json_event).unwrap());
+    /// The sandbox leaves reading devices and writing to stdout, which these need more than
+    fn check_sandbox(&self) -> Result<(), String> {
+        if !cfg!(target_os = "linux") {
+            return Err("--sandbox is only supported on Linux".to_string());
+        }
+        let conflicting: Vec<&str> = [
+            ("--socket", self.socket_path.is_some()),
+            ("--ydotool-socket", self.ydotool_socket.is_some()),
+            ("--dbus", self.dbus),
+            ("--http", self.http_addr.is_some()),
+            ("--metrics-addr", self.metrics_addr.is_some()),
+            ("--tcp", self.tcp_addr.is_some()),
+            ("--record-to", self.record_to.is_some()),
+            // `dump` creates its file, which the filter refuses
+            ("--record-last", self.record_last.is_some()),
+            ("--g-keys", self.g_keys),
+        ]
+        .into_iter()
+        .filter_map(|(flag, set)| set.then_some(flag))
+        .collect();
+        if conflicting.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("--sandbox can't be combined with {}", conflicting.join(", ")))
+        }
+    }
+
+    fn flush_strategy(&self) -> output::FlushStrategy {
+        match self.flush_interval {
+            Some(interval) if !interval.is_zero() => output::FlushStrategy::Interval(interval),
+            _ => output::FlushStrategy::PerEvent,
         }
-        _ => {}
     }
 }
 
-#[cfg(not(target_os = "linux"))]
-fn start_keyboard_listener() -> Result<(), Box<dyn std::error::Error>> {
-    if let Err(error) = listen(move |event| {
-        keyboard_callback(event);
-    }) {
-        return Err(format!("Failed to listen for keyboard events: {:?}", error).into());
+/// Describes how this helper will behave for the current listen session
+fn capabilities(options: &ListenOptions) -> serde_json::Value {
+    let flush_strategy = output::flush_strategy();
+    let flush_interval_ms = match flush_strategy {
+        output::FlushStrategy::Interval(interval) => Some(interval.as_millis() as u64),
+        output::FlushStrategy::PerEvent => None,
+    };
+    #[cfg(target_os = "linux")]
+    let layout = layout::description();
+    #[cfg(not(target_os = "linux"))]
+    let layout = input_source::current();
+    // Flatpak and Snap builds without devices capture through the shortcuts portal
+    #[cfg(target_os = "linux")]
+    let portal = portal::confined().is_some() && options.device_paths.is_empty();
+    #[cfg(not(target_os = "linux"))]
+    let portal = false;
+
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "platform": std::env::consts::OS,
+        "capture_backend": match options.backend {
+            CaptureBackend::Devices if portal => "portal",
+            CaptureBackend::Devices if cfg!(target_os = "linux") => "evdev",
+            CaptureBackend::Devices => "rdev",
+            CaptureBackend::Replay(_) => "replay",
+        },
+        "flush_strategy": flush_strategy.name(),
+        "flush_interval_ms": flush_interval_ms,
+        "output_queue_capacity": output::OUTPUT_QUEUE_CAPACITY,
+        "replay_ring_capacity": stream::REPLAY_RING_CAPACITY,
+        "realtime": options.realtime,
+        "socket": options.socket_path.is_some(),
+        "ydotool_socket": options.ydotool_socket.is_some() && cfg!(target_os = "linux"),
+        "input_broker": options.input_broker.is_some() && cfg!(target_os = "linux"),
+        "sandbox": options.sandbox,
+        "driver_guidance": options.driver_guidance,
+        "dbus": options.dbus && cfg!(target_os = "linux"),
+        "http": options.http_addr.is_some(),
+        "metrics": options.metrics_addr.is_some(),
+        "tcp": options.tcp_addr.is_some(),
+        "tls": options.tls.is_some(),
+        "suppress_self": options.suppress_self,
+        "format": if options.legacy_format { "legacy" } else { "typed" },
+        "output": options.output_format.name(),
+        "layout": layout,
+        // rdev gets no media key events on macOS
+        "media_keys": options.media_keys && !cfg!(target_os = "macos"),
+        "pause_when_locked": options.pause_when_locked,
+        "all_sessions": options.all_sessions,
+        "any_keys": options.any_keys,
+        "gamepads": options.gamepads && !cfg!(target_os = "macos"),
+        "g_keys": options.g_keys && cfg!(target_os = "linux"),
+        "device_selection": if options.device_paths.is_empty() { "auto" } else { "explicit" },
+        "privacy": options.privacy.name(),
+        "time_format": options.time_format.name(),
+        "raw_scancodes": options.raw_scancodes,
+        "max_event_rate": options.max_event_rate,
+        "idle_after_secs": options.idle_after.map(|idle_after| idle_after.as_secs()),
+        "record_to": options.record_to.is_some(),
+        "record_last": options.record_last,
+        "zstd": zstd::available(),
+        "hotkeys": hotkeys::count(),
+        "hotstrings": hotstrings::count(),
+    })
+}
+
+/// Exit status for the injection commands: 0 on success, 101 on failure
+fn exit_after_injection(command: &str, result: Result<(), Box<dyn std::error::Error>>) -> ! {
+    // Events such as BackendSwitched may still be queued for stdout
+    output::flush(std::time::Duration::from_millis(500));
+    match result {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            eprintln!("{} command failed: {}", command, e);
+            std::process::exit(101);
+        }
     }
-    Ok(())
 }
 
-// ============ Linux implementation using evdev directly ============
-// This approach works on both X11 and Wayland without any X11 dependencies.
-// Requires user to be in 'input' group: sudo usermod -aG input $USER
+/// Parsed `write` command line
+struct WriteArgs {
+    segments: Vec<keys::Segment>,
+    dry_run: bool,
+    method: inject::TextMethod,
+    /// Window to focus for the write, as `windows focus` matches it
+    window: Option<String>,
+}
 
-/// Convert evdev Key to rdev-compatible key name
-/// The TypeScript handler expects rdev-style names like "ControlLeft", "KeyA", etc.
-#[cfg(target_os = "linux")]
-fn evdev_key_to_rdev_name(key: evdev::Key) -> String {
-    use evdev::Key;
-    match key {
-        // Modifier keys
-        Key::KEY_LEFTCTRL => "ControlLeft".to_string(),
-        Key::KEY_RIGHTCTRL => "ControlRight".to_string(),
-        Key::KEY_LEFTSHIFT => "ShiftLeft".to_string(),
-        Key::KEY_RIGHTSHIFT => "ShiftRight".to_string(),
-        Key::KEY_LEFTALT => "Alt".to_string(),  // rdev uses "Alt" for left alt
-        Key::KEY_RIGHTALT => "AltRight".to_string(),
-        Key::KEY_LEFTMETA => "MetaLeft".to_string(),
-        Key::KEY_RIGHTMETA => "MetaRight".to_string(),
-
-        // Letter keys (rdev uses "KeyA", "KeyB", etc.)
-        Key::KEY_A => "KeyA".to_string(),
-        Key::KEY_B => "KeyB".to_string(),
-        Key::KEY_C => "KeyC".to_string(),
-        Key::KEY_D => "KeyD".to_string(),
-        Key::KEY_E => "KeyE".to_string(),
-        Key::KEY_F => "KeyF".to_string(),
-        Key::KEY_G => "KeyG".to_string(),
-        Key::KEY_H => "KeyH".to_string(),
-        Key::KEY_I => "KeyI".to_string(),
-        Key::KEY_J => "KeyJ".to_string(),
-        Key::KEY_K => "KeyK".to_string(),
-        Key::KEY_L => "KeyL".to_string(),
-        Key::KEY_M => "KeyM".to_string(),
-        Key::KEY_N => "KeyN".to_string(),
-        Key::KEY_O => "KeyO".to_string(),
-        Key::KEY_P => "KeyP".to_string(),
-        Key::KEY_Q => "KeyQ".to_string(),
-        Key::KEY_R => "KeyR".to_string(),
-        Key::KEY_S => "KeyS".to_string(),
-        Key::KEY_T => "KeyT".to_string(),
-        Key::KEY_U => "KeyU".to_string(),
-        Key::KEY_V => "KeyV".to_string(),
-        Key::KEY_W => "KeyW".to_string(),
-        Key::KEY_X => "KeyX".to_string(),
-        Key::KEY_Y => "KeyY".to_string(),
-        Key::KEY_Z => "KeyZ".to_string(),
-
-        // Number keys
-        Key::KEY_0 => "Digit0".to_string(),
-        Key::KEY_1 => "Digit1".to_string(),
-        Key::KEY_2 => "Digit2".to_string(),
-        Key::KEY_3 => "Digit3".to_string(),
-        Key::KEY_4 => "Digit4".to_string(),
-        Key::KEY_5 => "Digit5".to_string(),
-        Key::KEY_6 => "Digit6".to_string(),
-        Key::KEY_7 => "Digit7".to_string(),
-        Key::KEY_8 => "Digit8".to_string(),
-        Key::KEY_9 => "Digit9".to_string(),
-
-        // Function keys
-        Key::KEY_F1 => "F1".to_string(),
-        Key::KEY_F2 => "F2".to_string(),
-        Key::KEY_F3 => "F3".to_string(),
-        Key::KEY_F4 => "F4".to_string(),
-        Key::KEY_F5 => "F5".to_string(),
-        Key::KEY_F6 => "F6".to_string(),
-        Key::KEY_F7 => "F7".to_string(),
-        Key::KEY_F8 => "F8".to_string(),
-        Key::KEY_F9 => "F9".to_string(),
-        Key::KEY_F10 => "F10".to_string(),
-        Key::KEY_F11 => "F11".to_string(),
-        Key::KEY_F12 => "F12".to_string(),
-
-        // Special keys
-        Key::KEY_ESC => "Escape".to_string(),
-        Key::KEY_TAB => "Tab".to_string(),
-        Key::KEY_CAPSLOCK => "CapsLock".to_string(),
-        Key::KEY_SPACE => "Space".to_string(),
-        Key::KEY_ENTER => "Return".to_string(),
-        Key::KEY_BACKSPACE => "BackSpace".to_string(),
-        Key::KEY_DELETE => "Delete".to_string(),
-        Key::KEY_INSERT => "Insert".to_string(),
-        Key::KEY_HOME => "Home".to_string(),
-        Key::KEY_END => "End".to_string(),
-        Key::KEY_PAGEUP => "PageUp".to_string(),
-        Key::KEY_PAGEDOWN => "PageDown".to_string(),
-
-        // Arrow keys
-        Key::KEY_UP => "UpArrow".to_string(),
-        Key::KEY_DOWN => "DownArrow".to_string(),
-        Key::KEY_LEFT => "LeftArrow".to_string(),
-        Key::KEY_RIGHT => "RightArrow".to_string(),
-
-        // Punctuation/symbols
-        Key::KEY_MINUS => "Minus".to_string(),
-        Key::KEY_EQUAL => "Equal".to_string(),
-        Key::KEY_LEFTBRACE => "BracketLeft".to_string(),
-        Key::KEY_RIGHTBRACE => "BracketRight".to_string(),
-        Key::KEY_BACKSLASH => "BackSlash".to_string(),
-        Key::KEY_SEMICOLON => "Semicolon".to_string(),
-        Key::KEY_APOSTROPHE => "Quote".to_string(),
-        Key::KEY_GRAVE => "BackQuote".to_string(),
-        Key::KEY_COMMA => "Comma".to_string(),
-        Key::KEY_DOT => "Period".to_string(),
-        Key::KEY_SLASH => "Slash".to_string(),
-
-        // Numpad
-        Key::KEY_KP0 => "Numpad0".to_string(),
-        Key::KEY_KP1 => "Numpad1".to_string(),
-        Key::KEY_KP2 => "Numpad2".to_string(),
-        Key::KEY_KP3 => "Numpad3".to_string(),
-        Key::KEY_KP4 => "Numpad4".to_string(),
-        Key::KEY_KP5 => "Numpad5".to_string(),
-        Key::KEY_KP6 => "Numpad6".to_string(),
-        Key::KEY_KP7 => "Numpad7".to_string(),
-        Key::KEY_KP8 => "Numpad8".to_string(),
-        Key::KEY_KP9 => "Numpad9".to_string(),
-        Key::KEY_KPENTER => "NumpadEnter".to_string(),
-        Key::KEY_KPPLUS => "NumpadAdd".to_string(),
-        Key::KEY_KPMINUS => "NumpadSubtract".to_string(),
-        Key::KEY_KPASTERISK => "NumpadMultiply".to_string(),
-        Key::KEY_KPSLASH => "NumpadDivide".to_string(),
-        Key::KEY_KPDOT => "NumpadDecimal".to_string(),
-        Key::KEY_NUMLOCK => "NumLock".to_string(),
-
-        // Other
-        Key::KEY_SCROLLLOCK => "ScrollLock".to_string(),
-        Key::KEY_PAUSE => "Pause".to_string(),
-        Key::KEY_PRINT => "PrintScreen".to_string(),
-        Key::KEY_FN => "Function".to_string(),
-
-        // Fallback: use the Debug format but strip the "KEY_" prefix
-        _ => {
-            let debug_name = format!("{:?}", key);
-            if debug_name.starts_with("KEY_") {
-                debug_name[4..].to_string()
+/// `write [--keys] [--ime-safe | --paste [--primary] | --backend <name>] [--window <match>] [--dry-run]
+/// (<text> | --stdin | --file <path>)`
+fn parse_write_args(mut args: &[String]) -> Result<WriteArgs, String> {
+    use std::io::Read;
+
+    let (mut escapes, mut dry_run, mut method) = (false, false, inject::TextMethod::default_for_write());
+    let (mut paste, mut primary, mut window, mut backend) = (false, false, None, None);
+    while let Some((flag, rest)) = args.split_first() {
+        match flag.as_str() {
+            "--keys" => escapes = true,
+            "--dry-run" => dry_run = true,
+            "--ime-safe" => method = inject::TextMethod::ImeSafe,
+            "--paste" => paste = true,
+            "--primary" => primary = true,
+            "--backend" => {
+                let (name, rest) = rest.split_first().ok_or("--backend expects a backend name")?;
+                backend = Some(inject::TextMethod::backend(name)?);
+                args = rest;
+                continue;
+            }
+            "--window" => {
+                let (query, rest) = rest.split_first().ok_or("--window expects a window id, title or JSON match")?;
+                window = Some(query.clone());
+                args = rest;
+                continue;
+            }
+            _ => break,
+        }
+        args = rest;
+    }
+    match (paste, primary) {
+        (true, _) => {
+            let selection = if primary { clipboard::Selection::Primary } else { clipboard::Selection::Clipboard };
+            method = inject::TextMethod::Paste(selection);
+        }
+        (false, true) => return Err("--primary goes with --paste".to_string()),
+        (false, false) => {}
+    }
+    if let Some(backend) = backend {
+        if paste || method == inject::TextMethod::ImeSafe {
+            return Err("--backend doesn't go with --paste or --ime-safe".to_string());
+        }
+        method = backend;
+    }
+
+    // Large payloads come through stdin or a file: argv has length limits and shows up in process listings
+    let text = match args {
+        [flag] if flag == "--stdin" => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("Failed to read text from stdin: {}", e))?;
+            text
+        }
+        [flag, path] if flag == "--file" => {
+            std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?
+        }
+        [text] => text.clone(),
+        _ => return Err("write expects <text>, --stdin or --file <path>".to_string()),
+    };
+
+    let segments = if escapes {
+        keys::parse_key_escapes(&text)?
+    } else {
+        vec![keys::Segment::Text(text)]
+    };
+    Ok(WriteArgs {
+        segments,
+        dry_run,
+        method,
+        window,
+    })
+}
+
+/// `windows list`, `windows focus <match>` and `windows raise <match>`,
+/// printing the windows (or the one matched) as JSON
+fn windows_command(command: &str, args: &[String]) -> Result<(), String> {
+    match (command, args) {
+        ("list", []) => {
+            let focused = active_window::focused().ok().flatten();
+            let windows: Vec<serde_json::Value> = active_window::list()?
+                .iter()
+                .map(|target| {
+                    let mut window = target.to_json();
+                    window["focused"] = serde_json::json!(focused.as_deref() == Some(target.id.as_str()));
+                    window
+                })
+                .collect();
+            println!("{}", serde_json::Value::Array(windows));
+            Ok(())
+        }
+        ("focus" | "raise", [spec]) => {
+            let target = active_window::find_spec(spec)?;
+            if command == "focus" {
+                active_window::focus(&target.id)?;
             } else {
-                debug_name
+                active_window::raise(&target.id)?;
             }
+            println!("{}", target.to_json());
+            Ok(())
         }
+        ("focus" | "raise", _) => Err(format!("windows {} expects one window id, title or JSON match", command)),
+        _ => Err(format!("Unknown windows command {:?}; expected list, focus or raise", command)),
     }
 }
 
-/// Output an error event to stdout in JSON format so the desktop app can read it
-/// The app typically only consumes stdout, so stderr errors may not be visible to users
-#[cfg(target_os = "linux")]
-fn output_error_event(error_type: &str, message: &str) {
-    let error_event = KeyboardEvent {
-        event_type: "Error".to_string(),
-        name: Some(error_type.to_string()),
-        time: std::time::SystemTime::now(),
-        data: json!({"error": error_type, "message": message}).to_string(),
+/// `clipboard get [--primary]` and `clipboard set [--primary] (<text> | --stdin)`
+fn clipboard_command(command: &str, mut args: &[String]) -> Result<(), String> {
+    use std::io::Read;
+
+    let selection = match args.split_first() {
+        Some((flag, rest)) if flag == "--primary" => {
+            args = rest;
+            clipboard::Selection::Primary
+        }
+        _ => clipboard::Selection::Clipboard,
     };
-    // Output to stdout so the app can read it
-    println!("{}", serde_json::to_string(&error_event).unwrap());
-    // Also output to stderr for debugging
-    eprintln!("!error: {} - {}", error_type, message);
+    match (command, args) {
+        ("get", []) => {
+            print!("{}", clipboard::text(selection)?.unwrap_or_default());
+            Ok(())
+        }
+        ("set", [flag]) if flag == "--stdin" => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("Failed to read text from stdin: {}", e))?;
+            clipboard::set_text(selection, &text)
+        }
+        ("set", [text]) => clipboard::set_text(selection, text),
+        ("set", _) => Err("clipboard set expects <text> or --stdin".to_string()),
+        _ => Err(format!("clipboard {} takes no arguments besides --primary", command)),
+    }
 }
 
-#[cfg(target_os = "linux")]
-fn start_keyboard_listener() -> Result<(), Box<dyn std::error::Error>> {
-    use evdev::{Device, Key};
-    use std::fs;
-    use std::path::PathBuf;
-    use std::thread;
-    use std::sync::atomic::{AtomicUsize, Ordering};
-    use std::sync::Arc;
-
-    let input_dir = "/dev/input";
-    let mut last_error: Option<String> = None;
-    let mut keyboard_devices: Vec<(PathBuf, Device)> = Vec::new();
-
-    // Enumerate devices in /dev/input/ to find ALL keyboards
-    let entries = fs::read_dir(input_dir)
-        .map_err(|e| format!("Cannot access {}: {}", input_dir, e))?;
-
-    for entry in entries.filter_map(|e| e.ok()) {
-        let path = entry.path();
-        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-
-        // Only look at eventN devices
-        if !name.starts_with("event") {
-            continue;
-        }
-
-        // Try to open the device
-        match Device::open(&path) {
-            Ok(device) => {
-                // Check if this device has keyboard capabilities (has letter keys or modifier keys)
-                if device.supported_keys().map_or(false, |keys| {
-                    keys.contains(Key::KEY_A) || keys.contains(Key::KEY_SPACE) ||
-                    keys.contains(Key::KEY_LEFTCTRL) || keys.contains(Key::KEY_LEFTALT)
-                }) {
-                    eprintln!("Found keyboard: {} ({})",
-                        device.name().unwrap_or("Unknown"),
-                        path.display());
-                    keyboard_devices.push((path.clone(), device));
-                }
+/// `context [--chars <n>]`
+fn context_command(args: &[String]) -> Result<(), String> {
+    let chars = match args {
+        [] => context::DEFAULT_CHARS,
+        [flag, chars] if flag == "--chars" => chars.parse().map_err(|_| format!("Invalid --chars value: {}", chars))?,
+        _ => return Err("Expected: context [--chars <n>]".to_string()),
+    };
+    println!("{}", serde_json::json!(context::read(chars)?));
+    Ok(())
+}
+
+/// `install-permissions [--remove|--print]`
+fn install_permissions_command(args: &[String]) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    {
+        match args.first().map(String::as_str) {
+            None => permissions::install(),
+            Some("--remove") if args.len() == 1 => permissions::remove(),
+            Some("--print") if args.len() == 1 => {
+                print!("{}", permissions::RULE);
+                Ok(())
             }
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::PermissionDenied {
-                    last_error = Some(format!("Permission denied for {}", path.display()));
-                }
+            _ => Err("Expected: install-permissions [--remove|--print]".to_string()),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = args;
+        Err("install-permissions is only needed on Linux".to_string())
+    }
+}
+
+/// `input-broker [--socket <path>] --allow-user <user>...`, serving until the socket fails
+fn input_broker_command(args: &[String]) -> Result<(), String> {
+    let (mut socket, mut allowed) = (None, Vec::new());
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} expects a value", flag))?;
+        match flag.as_str() {
+            "--socket" => socket = Some(PathBuf::from(value)),
+            "--allow-user" => allowed.push(value.clone()),
+            other => return Err(format!("Unknown input-broker option: {}", other)),
+        }
+    }
+    if allowed.is_empty() {
+        return Err("input-broker expects --allow-user <user> for each user it may open devices for".to_string());
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let allowed = allowed.iter().map(|user| input_access::resolve_user(user)).collect::<Result<Vec<_>, _>>()?;
+        let socket = socket.unwrap_or_else(|| PathBuf::from(input_access::DEFAULT_BROKER_SOCKET));
+        input_access::serve_broker(&socket, &allowed)
+            .map_err(|e| format!("Failed to broker input devices on {}: {}", socket.display(), e))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = socket;
+        Err("input-broker is only supported on Linux".to_string())
+    }
+}
+
+/// `ydotoold [--socket-path <path>] [--socket-perm <octal>]`, serving until killed
+fn ydotoold_command(args: &[String]) -> Result<(), String> {
+    let (mut path, mut mode) = (None, None);
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} expects a value", flag))?;
+        match flag.as_str() {
+            "--socket-path" | "-p" => path = Some(PathBuf::from(value)),
+            "--socket-perm" | "-P" => {
+                let perm = u32::from_str_radix(value, 8).map_err(|_| format!("Invalid --socket-perm value: {}", value))?;
+                mode = Some(perm);
             }
+            other => return Err(format!("Unknown ydotoold option: {}", other)),
+        }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let path = path.unwrap_or_else(ydotool::default_socket);
+        ydotool::serve(&path, mode.unwrap_or(ydotool::DEFAULT_MODE))
+            .map_err(|e| format!("Failed to serve ydotool clients on {}: {}", path.display(), e))?;
+        loop {
+            std::thread::park();
         }
     }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (path, mode);
+        Err("ydotoold is only supported on Linux".to_string())
+    }
+}
+
+/// `screenshot [--region x,y,w,h] [--display <n>] [--out <file>]`, to stdout without `--out`
+fn screenshot_command(args: &[String]) -> Result<(), String> {
+    use std::io::Write;
 
-    // No keyboard found - provide helpful error message
-    if keyboard_devices.is_empty() {
-        if let Some(err) = last_error {
-            let message = "User must be in 'input' group. Run: sudo usermod -aG input $USER, then log out and back in.";
-            output_error_event("PermissionDenied", message);
-            return Err(format!("Failed to access keyboard devices: {}", err).into());
+    let (mut region, mut display, mut out) = (None, None, None);
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} expects a value", flag))?;
+        match flag.as_str() {
+            "--region" => region = Some(screenshot::Region::parse(value)?),
+            "--display" => display = Some(value.parse().map_err(|_| format!("Invalid --display value: {}", value))?),
+            "--out" => out = Some(PathBuf::from(value)),
+            _ => return Err(format!("Unknown screenshot option: {}", flag)),
         }
-        let message = "No keyboard device found in /dev/input/";
-        output_error_event("NoKeyboardFound", message);
-        return Err(message.into());
     }
+    let png = screenshot::capture(region, display)?;
+    match out {
+        Some(path) => std::fs::write(&path, png).map_err(|e| format!("Cannot write {}: {}", path.display(), e)),
+        None => std::io::stdout()
+            .write_all(&png)
+            .and_then(|()| std::io::stdout().flush())
+            .map_err(|e| format!("Failed to write the screenshot: {}", e)),
+    }
+}
 
-    eprintln!("Listening on {} keyboard device(s)", keyboard_devices.len());
+/// `--driver-guidance`: when nvidia-smi can't be asked, tell the app and offer the driver download in a toast
+fn driver_guidance() {
+    let Err(error) = gpu::query() else { return };
+    stream::emit(&Event::now(EventKind::DriverProblem {
+        error: error.clone(),
+        download_url: gpu::DRIVER_DOWNLOAD_URL,
+    }));
+    let notification = notify::Notification {
+        title: "NVIDIA driver not found".to_string(),
+        body: format!("NVIDIA Control Center can't reach the GPU driver: {}", error),
+        icon: None,
+        action: Some(notify::Action {
+            label: "Download driver".to_string(),
+            url: gpu::DRIVER_DOWNLOAD_URL.to_string(),
+        }),
+    };
+    if let Err(e) = notify::show(&notification) {
+        eprintln!("!error: Cannot show the driver toast: {}", e);
+    }
+}
 
-    // If only one keyboard, no need for threading
-    if keyboard_devices.len() == 1 {
-        let (_, device) = keyboard_devices.into_iter().next().unwrap();
-        return listen_keyboard_device(device);
+/// `gpu snapshot save|list|restore [<name>]`, printing the result as JSON
+fn gpu_snapshot_command(args: &[String]) -> Result<(), String> {
+    let name = || match args {
+        [_, name] => Ok(name.as_str()),
+        _ => Err(format!("gpu snapshot {} expects a name", args[0])),
+    };
+    match args.first().map(String::as_str) {
+        Some("save") => println!("{}", serde_json::json!(gpu::save(name()?)?)),
+        Some("list") if args.len() == 1 => println!("{}", serde_json::json!(gpu::list()?)),
+        Some("restore") => {
+            let changes = gpu::restore(name()?)?;
+            println!("{}", serde_json::json!({ "pre_change": gpu::PRE_CHANGE, "changes": changes }));
+            let failed = changes.iter().filter(|change| change.error.is_some()).count();
+            if failed > 0 {
+                return Err(format!("{} of {} settings couldn't be restored", failed, changes.len()));
+            }
+        }
+        _ => return Err("Usage: gpu snapshot save <name>|list|restore <name>".to_string()),
     }
+    Ok(())
+}
 
-    // Multiple keyboards: spawn a thread for each
-    // Track how many devices are still active - treat per-device failures as non-fatal
-    let active_count = Arc::new(AtomicUsize::new(keyboard_devices.len()));
+/// `inhibit-sleep start [--reason <text>]`: hold the inhibitor until stdin closes
+fn inhibit_sleep_command(args: &[String]) -> Result<(), String> {
+    let reason = match args {
+        [start] if start == "start" => inhibit::DEFAULT_REASON,
+        [start, flag, reason] if start == "start" && flag == "--reason" => reason,
+        [stop] if stop == "stop" => {
+            return Err("inhibit-sleep stop is for a listening helper's stdin; close start's stdin instead".to_string())
+        }
+        _ => return Err("Expected: inhibit-sleep start [--reason <text>]".to_string()),
+    };
+    inhibit::start(reason)?;
+    eprintln!("Sleep inhibited until stdin closes");
+    std::io::copy(&mut std::io::stdin(), &mut std::io::sink()).ok();
+    inhibit::stop();
+    Ok(())
+}
 
-    for (path, device) in keyboard_devices {
-        let active_count = Arc::clone(&active_count);
-        let path_str = path.display().to_string();
-        thread::spawn(move || {
-            if let Err(e) = listen_keyboard_device(device) {
-                // Log the error but don't bring down the whole listener
-                // This allows hotkeys to continue working on other devices
-                // (e.g., if a USB keyboard is unplugged)
-                eprintln!("Device {} stopped: {}", path_str, e);
-                let remaining = active_count.fetch_sub(1, Ordering::SeqCst) - 1;
-                if remaining == 0 {
-                    // All devices have failed - output error to stdout so app can see it
-                    output_error_event("AllDevicesFailed", "All keyboard devices have stopped");
+/// `notify --title <title> [--body <text>] [--icon <icon>]`
+fn notify_command(args: &[String]) -> Result<(), String> {
+    let mut notification = notify::Notification {
+        title: String::new(),
+        body: String::new(),
+        icon: None,
+        action: None,
+    };
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} expects a value", flag))?.clone();
+        match flag.as_str() {
+            "--title" => notification.title = value,
+            "--body" => notification.body = value,
+            "--icon" => notification.icon = Some(value),
+            _ => return Err(format!("Unknown notify option: {}", flag)),
+        }
+    }
+    if notification.title.is_empty() {
+        return Err("notify expects --title <title>".to_string());
+    }
+    notify::show(&notification)
+}
+
+/// `write --dry-run`: print the key taps the active layout needs, one per line
+#[cfg(target_os = "linux")]
+fn print_write_plan(segments: &[keys::Segment]) -> Result<(), String> {
+    for segment in segments {
+        match segment {
+            keys::Segment::Text(text) => {
+                let strokes = layout::plan_text(text).ok_or("No keyboard layout is available")??;
+                for stroke in strokes {
+                    let names: Vec<&str> = stroke
+                        .modifiers
+                        .iter()
+                        .chain([&stroke.code])
+                        .map(|&code| evdev_key_to_rdev_name(evdev::KeyCode::new(code)))
+                        .collect();
+                    println!("{}", names.join("+"));
                 }
             }
-        });
+            keys::Segment::Keys(keys) => {
+                let names: Vec<String> = keys.iter().map(|key| format!("{:?}", key)).collect();
+                println!("{{{}}}", names.join("+"));
+            }
+        }
     }
+    Ok(())
+}
 
-    // Block the main thread forever - the spawned threads will handle events
-    // This prevents the function from returning while devices are still being monitored
-    loop {
-        thread::sleep(std::time::Duration::from_secs(60));
-        // Check if all devices have failed
-        if active_count.load(Ordering::SeqCst) == 0 {
-            return Err("All keyboard devices have stopped".into());
+/// `--timeout-ms <ms>` after `key down <key>`
+fn parse_hold_timeout(args: &[String]) -> Result<std::time::Duration, String> {
+    match args {
+        [] => Ok(inject::DEFAULT_HOLD_TIMEOUT),
+        [flag, value] if flag == "--timeout-ms" => match value.parse::<u64>() {
+            Ok(timeout_ms) if timeout_ms > 0 => Ok(std::time::Duration::from_millis(timeout_ms)),
+            _ => Err(format!("Invalid --timeout-ms value: {}", value)),
+        },
+        _ => Err(format!("Unexpected key down arguments: {}", args.join(" "))),
+    }
+}
+
+/// Parsed `mouse` command line
+enum MouseCommand {
+    /// Desktop coordinates, or an offset from the current position when `relative`
+    Move { x: i32, y: i32, relative: bool },
+    Click { button: enigo::Button, count: u32 },
+    Scroll { dx: i32, dy: i32 },
+    Monitors,
+}
+
+fn parse_coordinate(value: &str) -> Result<i32, String> {
+    value.parse().map_err(|_| format!("Invalid coordinate: {}", value))
+}
+
+/// `mouse move <x> <y> [--relative | --monitor <n>]`, `mouse click <button> [--count <n>]`,
+/// `mouse scroll <dx> <dy>` or `mouse monitors`
+fn parse_mouse_args(args: &[String]) -> Result<MouseCommand, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["move", x, y, options @ ..] => {
+            let (x, y) = (parse_coordinate(x)?, parse_coordinate(y)?);
+            match options {
+                [] => Ok(MouseCommand::Move { x, y, relative: false }),
+                ["--relative"] => Ok(MouseCommand::Move { x, y, relative: true }),
+                ["--monitor", index] => {
+                    let monitors = monitors::list()?;
+                    let monitor = index
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| monitors.get(index))
+                        .ok_or_else(|| format!("No monitor {} (found {})", index, monitors.len()))?;
+                    if !(0..monitor.width).contains(&x) || !(0..monitor.height).contains(&y) {
+                        return Err(format!(
+                            "({}, {}) is outside monitor {} ({}x{})",
+                            x, y, index, monitor.width, monitor.height
+                        ));
+                    }
+                    Ok(MouseCommand::Move {
+                        x: monitor.x + x,
+                        y: monitor.y + y,
+                        relative: false,
+                    })
+                }
+                _ => Err(format!("Unexpected mouse move arguments: {}", options.join(" "))),
+            }
+        }
+        ["click", button, options @ ..] => {
+            let button = keys::parse_mouse_button(button)?;
+            let count = match options {
+                [] => 1,
+                ["--count", value] => match value.parse::<u32>() {
+                    Ok(count) if count > 0 => count,
+                    _ => return Err(format!("Invalid --count value: {}", value)),
+                },
+                _ => return Err(format!("Unexpected mouse click arguments: {}", options.join(" "))),
+            };
+            Ok(MouseCommand::Click { button, count })
         }
+        ["scroll", dx, dy] => Ok(MouseCommand::Scroll {
+            dx: parse_coordinate(dx)?,
+            dy: parse_coordinate(dy)?,
+        }),
+        ["monitors"] => Ok(MouseCommand::Monitors),
+        _ => Err(format!("Unexpected mouse arguments: {}", args.join(" "))),
     }
 }
 
-#[cfg(target_os = "linux")]
-fn listen_keyboard_device(mut device: evdev::Device) -> Result<(), Box<dyn std::error::Error>> {
-    use evdev::InputEventKind;
-
-    loop {
-        for event in device.fetch_events()? {
-            if let InputEventKind::Key(key) = event.kind() {
-                let event_type = match event.value() {
-                    0 => "KeyRelease",
-                    1 => "KeyPress",
-                    2 => continue, // Key repeat, skip
-                    _ => continue,
-                };
+/// `record --out <file>`
+fn parse_record_args(args: &[String]) -> Result<PathBuf, String> {
+    match args {
+        [flag, path] if flag == "--out" => Ok(PathBuf::from(path)),
+        _ => Err(format!("record expects --out <file>, got: {}", args.join(" "))),
+    }
+}
 
-                // Convert evdev key name to rdev-compatible format
-                let rdev_key_name = evdev_key_to_rdev_name(key);
+/// `replay <file> [--speed <x>]`
+fn parse_replay_args(args: &[String]) -> Result<(PathBuf, f64), String> {
+    let speed = match &args[1..] {
+        [] => 1.0,
+        [flag, value] if flag == "--speed" => match value.parse::<f64>() {
+            Ok(speed) if speed.is_finite() && speed > 0.0 => speed,
+            _ => return Err(format!("Invalid --speed value: {}", value)),
+        },
+        rest => return Err(format!("Unexpected replay arguments: {}", rest.join(" "))),
+    };
+    Ok((PathBuf::from(&args[0]), speed))
+}
 
-                let json_event = KeyboardEvent {
-                    event_type: event_type.to_string(),
-                    name: Some(rdev_key_name.clone()),
-                    time: std::time::SystemTime::now(),
-                    data: json!({"key": rdev_key_name}).to_string(),
-                };
+enum BenchCommand {
+    Throughput { events: u64, flush: output::FlushStrategy },
+    Latency { presses: u32 },
+}
 
-                println!("{}", serde_json::to_string(&json_event).unwrap());
+/// `bench throughput [--events <n>] [--flush-interval-ms <ms>]` or `bench latency [--count <n>]`
+fn parse_bench_args(args: &[String]) -> Result<BenchCommand, String> {
+    let count = |flag: &str, value: &str| match value.parse::<u32>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("Invalid {} value: {}", flag, value)),
+    };
+    let (mode, mut options) = args.split_first().ok_or("bench expects throughput or latency")?;
+    match mode.as_str() {
+        "throughput" => {
+            let mut events = bench::DEFAULT_EVENTS;
+            let mut flush = output::FlushStrategy::PerEvent;
+            while let [flag, value, rest @ ..] = options {
+                match flag.as_str() {
+                    "--events" => events = count(flag, value)?.into(),
+                    "--flush-interval-ms" => {
+                        let ms = count(flag, value)?;
+                        flush = output::FlushStrategy::Interval(std::time::Duration::from_millis(ms.into()));
+                    }
+                    _ => break,
+                }
+                options = rest;
             }
+            if !options.is_empty() {
+                return Err(format!("Unexpected bench arguments: {}", options.join(" ")));
+            }
+            Ok(BenchCommand::Throughput { events, flush })
         }
+        "latency" => match options {
+            [] => Ok(BenchCommand::Latency { presses: bench::DEFAULT_PRESSES }),
+            [flag, value] if flag == "--count" => Ok(BenchCommand::Latency { presses: count(flag, value)? }),
+            _ => Err(format!("Unexpected bench arguments: {}", options.join(" "))),
+        },
+        other => Err(format!("Unknown bench {:?} (expected throughput or latency)", other)),
     }
 }
 
-// ============ Common functions ============
+/// `decode <dump> [--hotkeys <file>] [--hotstrings <file>] [--privacy <mode>] [--raw-scancodes]`
+#[cfg(target_os = "linux")]
+fn decode(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut options = &args[1..];
+    while !options.is_empty() {
+        options = match options {
+            [flag, rest @ ..] if flag == "--raw-scancodes" => {
+                scancode::set_enabled(true);
+                rest
+            }
+            [flag, path, rest @ ..] if flag == "--hotkeys" => {
+                hotkeys::install(hotkeys::read(std::path::Path::new(path))?);
+                rest
+            }
+            [flag, path, rest @ ..] if flag == "--hotstrings" => {
+                hotstrings::install(hotstrings::read(std::path::Path::new(path))?);
+                rest
+            }
+            [flag, mode, rest @ ..] if flag == "--privacy" => {
+                privacy::set_mode(privacy::Mode::parse(mode)?);
+                rest
+            }
+            _ => return Err(format!("Unexpected decode arguments: {}", args[1..].join(" ")).into()),
+        };
+    }
+    evtest::decode(std::path::Path::new(&args[0]))
+}
 
-fn write_text(text: &str) -> Result<(), Box<dyn std::error::Error>> {
-    use enigo::{Enigo, Keyboard, Settings};
+/// First events of every listen session: what the helper can do, then the desktop it runs in
+fn emit_capabilities(options: &ListenOptions) {
+    stream::emit(&Event::now(EventKind::Capabilities(capabilities(options))));
+    let environment = serde_json::to_value(environment::detect()).expect("environment report serializes");
+    stream::emit(&Event::now(EventKind::Environment(environment)));
+}
 
-    let mut enigo = match Enigo::new(&Settings::default()) {
-        Ok(enigo) => enigo,
-        Err(e) => {
-            eprintln!("Failed to create Enigo instance: {}", e);
-            return Err(Box::new(e));
+fn main() {
+    clock::init();
+    let mut args: Vec<String> = std::env::args().collect();
+
+    let mut config = match config::load().and_then(|config| config::apply_global(&config).map(|()| config)) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("!error: {}", error);
+            std::process::exit(1);
         }
     };
 
-    match enigo.text(text) {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            eprintln!("Failed to write text: {}", e);
-            Err(Box::new(e))
+    // Symlinked as xdotool or ydotoold, scripts written for them run unchanged
+    match std::path::Path::new(&args[0]).file_stem().and_then(|stem| stem.to_str()) {
+        Some("xdotool") => args.insert(1, "xdo".to_string()),
+        Some("ydotoold") => args.insert(1, "ydotoold".to_string()),
+        _ => {}
+    }
+
+    // What the Windows task runs: listen, without keeping a console window open
+    #[cfg(windows)]
+    if args.len() > 2 && args[1] == "service" && args[2] == "run" {
+        service::detach_console();
+        args.splice(1..3, ["listen".to_string()]);
+    }
+
+    // Before the command, as every command that injects takes it
+    if args.len() > 1 && args[1] == "--inject-backend" {
+        let names: Vec<String> = args
+            .get(2)
+            .map_or("", String::as_str)
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect();
+        if names.is_empty() {
+            eprintln!("!error: --inject-backend expects backend names, comma-separated");
+            std::process::exit(1);
         }
+        if let Err(error) = inject::set_backends(&names) {
+            eprintln!("!error: --inject-backend: {}", error);
+            std::process::exit(1);
+        }
+        args.drain(1..3);
     }
-}
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    // `serve` reads better for the status page (`serve --http ADDR`)
+    if args.len() > 1 && (args[1] == "listen" || args[1] == "serve") {
+        let options = match ListenOptions::parse(&args[2..], &config.listen) {
+            Ok(mut options) => {
+                // --device replaces the config's paths rather than adding to them
+                if options.device_paths.is_empty() {
+                    options.device_paths = config.device_paths();
+                }
+                ListenOptions {
+                    ignored_devices: config.ignored_devices(),
+                    ..options
+                }
+            }
+            Err(error) => {
+                eprintln!("!error: {}", error);
+                std::process::exit(1);
+            }
+        };
 
-    if args.len() > 1 && args[1] == "listen" {
-        if let Err(error) = start_keyboard_listener() {
+        // Before anything spawns a thread, so all threads inherit the signal mask
+        control::start_signal_handler();
+
+        event::set_legacy_format(options.legacy_format);
+        event::set_time_format(options.time_format);
+        output::set_flush_strategy(options.flush_strategy());
+        output::set_format(options.output_format);
+        synthetic::set_suppress_self(options.suppress_self);
+        privacy::set_mode(options.privacy);
+        scancode::set_enabled(options.raw_scancodes);
+        throttle::set_max_rate(options.max_event_rate);
+        let sources = config::Sources {
+            hotkeys_path: options.hotkeys_path.clone(),
+            hotstrings_path: options.hotstrings_path.clone(),
+        };
+        match config::apply_definitions(&mut config, &sources) {
+            Ok((hotkeys, hotstrings)) => eprintln!("Loaded {} hotkey(s) and {} hotstring(s)", hotkeys, hotstrings),
+            Err(error) => {
+                eprintln!("!error: {}", error);
+                std::process::exit(1);
+            }
+        }
+        hotstrings::set_replace(true);
+        config::enable_reload(sources);
+        // Before the first event, so recordings start with the handshake
+        let recording = options.record_to.as_deref().map(journal::record_to).unwrap_or(Ok(()));
+        if let Err(error) = recording.and_then(|()| options.record_last.map(journal::keep_last).unwrap_or(Ok(()))) {
             eprintln!("!error: {}", error);
             std::process::exit(1);
         }
+        emit_capabilities(&options);
+
+        if options.driver_guidance {
+            // nvidia-smi takes a moment, which capture shouldn't wait for
+            std::thread::spawn(driver_guidance);
+        }
+
+        if let Some(parent_pid) = options.parent_pid {
+            if let Err(error) = parent::watch(parent_pid) {
+                eprintln!("!error: {}", error);
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(path) = &options.socket_path {
+            #[cfg(any(unix, windows))]
+            if let Err(error) = socket::serve(path) {
+                eprintln!("!error: Failed to serve socket {}: {}", path.display(), error);
+                std::process::exit(1);
+            }
+            #[cfg(not(any(unix, windows)))]
+            {
+                eprintln!("!error: --socket is not supported on this platform ({})", path.display());
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(path) = &options.ydotool_socket {
+            #[cfg(target_os = "linux")]
+            if let Err(error) = ydotool::serve(path, ydotool::DEFAULT_MODE) {
+                eprintln!("!error: Failed to serve ydotool clients on {}: {}", path.display(), error);
+                std::process::exit(1);
+            }
+            #[cfg(not(target_os = "linux"))]
+            {
+                eprintln!("!error: --ydotool-socket is only supported on Linux ({})", path.display());
+                std::process::exit(1);
+            }
+        }
+
+        if options.dbus {
+            #[cfg(target_os = "linux")]
+            if let Err(error) = dbus::serve() {
+                eprintln!("!error: Failed to serve {}: {}", dbus::BUS_NAME, error);
+                std::process::exit(1);
+            }
+            #[cfg(not(target_os = "linux"))]
+            {
+                eprintln!("!error: --dbus is only supported on Linux");
+                std::process::exit(1);
+            }
+        }
+
+        if let Some(addr) = &options.http_addr {
+            let served = options.http_token.clone().map_or_else(http::generate_token, Ok).and_then(|token| {
+                http::serve(addr, token.clone(), capabilities(&options)).map(|local_addr| (local_addr, token))
+            });
+            match served {
+                Ok((local_addr, token)) => {
+                    eprintln!("Status page: http://{}/?token={}", local_addr, token);
+                    stream::emit(&Event::now(EventKind::HttpListening {
+                        addr: local_addr.to_string(),
+                        token,
+                    }));
+                }
+                Err(error) => {
+                    eprintln!("!error: Failed to serve HTTP status on {}: {}", addr, error);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if let Some(addr) = &options.metrics_addr {
+            match metrics::serve(addr) {
+                Ok(local_addr) => {
+                    eprintln!("Metrics: http://{}/metrics", local_addr);
+                    stream::emit(&Event::now(EventKind::MetricsListening {
+                        addr: local_addr.to_string(),
+                    }));
+                }
+                Err(error) => {
+                    eprintln!("!error: Failed to serve metrics on {}: {}", addr, error);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if let Some(addr) = &options.tcp_addr {
+            #[cfg(any(unix, windows))]
+            {
+                let acceptor = match &options.tls {
+                    Some((cert, key)) => match tls::Acceptor::new(cert, key) {
+                        Ok(acceptor) => Some(acceptor),
+                        Err(error) => {
+                            eprintln!("!error: {}", error);
+                            std::process::exit(1);
+                        }
+                    },
+                    None => None,
+                };
+                let tls = acceptor.is_some();
+                match tcp::serve(addr, options.tcp_token.clone(), acceptor) {
+                    Ok((local_addr, token)) => stream::emit(&Event::now(EventKind::TcpListening {
+                        addr: local_addr.to_string(),
+                        token,
+                        tls,
+                    })),
+                    Err(error) => {
+                        eprintln!("!error: Failed to serve TCP clients on {}: {}", addr, error);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            #[cfg(not(any(unix, windows)))]
+            {
+                eprintln!("!error: --tcp is not supported on this platform ({})", addr);
+                std::process::exit(1);
+            }
+        }
+
+        clock::start_clock_sync();
+        hotkeys::start_timer();
+        control::start_command_reader();
+        #[cfg(target_os = "linux")]
+        layout::watch_config();
+        service::notify_ready();
+
+        if let CaptureBackend::Replay(path) = &options.backend {
+            #[cfg(target_os = "linux")]
+            if options.sandbox {
+                if let Err(error) = sandbox::apply(&[path]) {
+                    eprintln!("!error: {}", error);
+                    std::process::exit(1);
+                }
+            }
+            let result = playback::run(path);
+            if let Err(error) = &result {
+                eprintln!("!error: {}", error);
+            }
+            output::flush(std::time::Duration::from_secs(5));
+            journal::finish();
+            std::process::exit(if result.is_ok() { 0 } else { 1 });
+        }
+
+        // On macOS the notification observer must live on the main thread, which runs rdev's loop
+        #[cfg(not(target_os = "linux"))]
+        input_source::watch();
+        active_window::watch();
+        power::watch();
+        session::watch(options.pause_when_locked);
+        secure_input::watch();
+        leds::watch();
+        if let Some(threshold) = options.idle_after {
+            idle::watch(threshold);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            input_access::restrict();
+            input_access::set_broker(options.input_broker.clone());
+        }
+        #[cfg(not(target_os = "linux"))]
+        if let Some(path) = &options.input_broker {
+            eprintln!("!error: --input-broker is only supported on Linux ({})", path.display());
+            std::process::exit(1);
+        }
+        if options.gamepads {
+            gamepad::watch();
+        }
+        if options.g_keys {
+            gkeys::watch();
+        }
+
+        let listener = KeyboardListener::new()
+            .realtime(options.realtime)
+            .media_keys(options.media_keys)
+            .ignore_devices(options.ignored_devices)
+            .devices(options.device_paths)
+            .any_keys(options.any_keys)
+            .all_sessions(options.all_sessions)
+            .sandbox(options.sandbox);
+        if let Err(error) = listener.run() {
+            eprintln!("!error: {}", error);
+            // Give queued events (e.g. the structured error) a chance to reach the app
+            output::flush(std::time::Duration::from_secs(1));
+            std::process::exit(1);
+        }
+    } else if args.len() > 2 && args[1] == "decode" {
+        #[cfg(target_os = "linux")]
+        if let Err(error) = decode(&args[2..]) {
+            eprintln!("!error: {}", error);
+            std::process::exit(1);
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            eprintln!("!error: decode replays evdev dumps and is only available on Linux");
+            std::process::exit(1);
+        }
+    } else if args.len() == 3 && args[1] == "keymap" && args[2] == "dump" {
+        println!("{}", keymap::dump());
+    } else if args.len() == 3 && args[1] == "backlight" && args[2] == "list" {
+        match backlight::list() {
+            Ok(backlights) => println!("{}", serde_json::json!(backlights)),
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.len() > 2 && args[1] == "backlight" && args[2] == "set" {
+        let args: Vec<&str> = args[3..].iter().map(String::as_str).collect();
+        if let Err(e) = backlight::parse_set(&args).and_then(|setting| backlight::set(&setting)) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() == 3 && args[1] == "leds" && args[2] == "get" {
+        match leds::get() {
+            Ok(leds) => println!("{}", leds),
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.len() > 2 && args[1] == "leds" && args[2] == "set" {
+        let args: Vec<&str> = args[3..].iter().map(String::as_str).collect();
+        if let Err(e) = leds::parse_set(&args).and_then(|(led, on)| leds::set(led, on)) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 2 && args[1] == "windows" {
+        if let Err(e) = windows_command(&args[2], &args[3..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 2 && args[1] == "clipboard" && (args[2] == "get" || args[2] == "set") {
+        if let Err(e) = clipboard_command(&args[2], &args[3..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "context" {
+        if let Err(e) = context_command(&args[2..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "screenshot" {
+        if let Err(e) = screenshot_command(&args[2..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 3 && args[1] == "gpu" && args[2] == "snapshot" {
+        if let Err(e) = gpu_snapshot_command(&args[3..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "inhibit-sleep" {
+        if let Err(e) = inhibit_sleep_command(&args[2..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() == 2 && args[1] == "idle" {
+        match idle::query() {
+            Ok(idle) => println!("{}", serde_json::to_string(&idle).expect("idle time serializes")),
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.len() > 1 && args[1] == "notify" {
+        if let Err(e) = notify_command(&args[2..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
     } else if args.len() > 2 && args[1] == "write" {
-        let text = args[2].clone();
+        let write = match parse_write_args(&args[2..]) {
+            Ok(write) => write,
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        };
 
-        match write_text(text.as_str()) {
-            Ok(_) => {
-                std::process::exit(0);
+        if write.dry_run {
+            #[cfg(target_os = "linux")]
+            let result = print_write_plan(&write.segments);
+            #[cfg(not(target_os = "linux"))]
+            let result: Result<(), String> =
+                Err("--dry-run plans key codes from the xkb layout and is only available on Linux".to_string());
+            if let Err(e) = result {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+
+        let result = match &write.window {
+            Some(query) => active_window::with_focus(query, || inject::write_segments(&write.segments, write.method))
+                .map_err(Into::into)
+                .and_then(|written| written),
+            None => inject::write_segments(&write.segments, write.method),
+        };
+        exit_after_injection("Write", result);
+    } else if args.len() > 1 && args[1] == "ydotoold" {
+        if let Err(e) = ydotoold_command(&args[2..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "xdo" {
+        let invocation = match xdo::parse(&args[2..]) {
+            Ok(invocation) => invocation,
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        exit_after_injection("Xdo", xdo::run(invocation));
+    } else if args.len() > 2 && args[1] == "press" {
+        let keys = match keys::parse_combo(&args[2]) {
+            Ok(keys) => keys,
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        exit_after_injection("Press", inject::press_combo(&keys));
+    } else if args.len() > 3 && args[1] == "key" && (args[2] == "down" || args[2] == "up") {
+        let key = match keys::parse_key(&args[3]) {
+            Ok(key) => key,
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        let result = if args[2] == "down" {
+            let timeout = match parse_hold_timeout(&args[4..]) {
+                Ok(timeout) => timeout,
+                Err(e) => {
+                    eprintln!("!error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            inject::hold_key(key, timeout)
+        } else {
+            inject::release_key(key)
+        };
+
+        exit_after_injection(&format!("Key {}", args[2]), result);
+    } else if args.len() > 2 && args[1] == "mouse" {
+        let command = match parse_mouse_args(&args[2..]) {
+            Ok(command) => command,
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        let result = match command {
+            MouseCommand::Move { x, y, relative } => inject::mouse_move(x, y, relative),
+            MouseCommand::Click { button, count } => inject::mouse_click(button, count),
+            MouseCommand::Scroll { dx, dy } => inject::mouse_scroll(dx, dy),
+            MouseCommand::Monitors => match monitors::list() {
+                Ok(monitors) => {
+                    println!("{}", serde_json::json!(monitors));
+                    std::process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("!error: {}", e);
+                    std::process::exit(1);
+                }
             },
+        };
+
+        exit_after_injection("Mouse", result);
+    } else if args.len() > 2 && args[1] == "record" {
+        let path = match parse_record_args(&args[2..]) {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        macros::start_recording(&path);
+        if let Err(error) = KeyboardListener::new().run() {
+            eprintln!("!error: {}", error);
+            std::process::exit(1);
+        }
+    } else if args.len() > 2 && args[1] == "replay" {
+        let replay = match parse_replay_args(&args[2..]).and_then(|(path, speed)| Ok((macros::load(&path)?, speed))) {
+            Ok(replay) => replay,
             Err(e) => {
-                eprintln!("Write command failed: {}", e);
-                std::process::exit(101);
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        exit_after_injection("Replay", macros::replay(&replay.0, replay.1));
+    } else if args.len() > 1 && args[1] == "input-broker" {
+        if let Err(e) = input_broker_command(&args[2..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 1 && args[1] == "install-permissions" {
+        if let Err(e) = install_permissions_command(&args[2..]) {
+            eprintln!("!error: {}", e);
+            std::process::exit(1);
+        }
+    } else if args.len() > 2 && args[1] == "service" {
+        let result = match args[2].as_str() {
+            "install" => service::parse_install(&args[3..]).and_then(|install| service::install(&install)).map(Some),
+            "uninstall" if args.len() == 3 => service::uninstall().map(|()| None),
+            "status" if args.len() == 3 => service::status().map(Some),
+            _ => Err("Expected: service install [--socket <path>] [-- <listen flags>...], uninstall or status".into()),
+        };
+        match result {
+            Ok(Some(status)) => println!("{}", serde_json::to_string(&status).expect("service status serializes")),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
             }
         }
+    } else if args.len() == 2 && args[1] == "backends" {
+        println!("{}", serde_json::to_string(&backends::report()).expect("backend report serializes"));
+    } else if args.len() == 2 && args[1] == "doctor" {
+        let report = serde_json::json!({ "environment": environment::detect(), "backends": backends::report() });
+        println!("{}", report);
+    } else if args.len() == 2 && args[1] == "selftest" {
+        match selftest::run() {
+            Ok(checks) => {
+                let ok = checks.iter().all(|check| check.status != selftest::Status::Fail);
+                println!("{}", serde_json::json!({ "ok": ok, "checks": checks }));
+                std::process::exit(if ok { 0 } else { 1 });
+            }
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if args.len() > 2 && args[1] == "bench" {
+        let command = match parse_bench_args(&args[2..]) {
+            Ok(command) => command,
+            Err(e) => {
+                eprintln!("!error: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        match command {
+            // stdout carries the events being measured
+            BenchCommand::Throughput { events, flush } => {
+                output::set_flush_strategy(flush);
+                eprintln!("{}", serde_json::json!(bench::throughput(events)));
+            }
+            BenchCommand::Latency { presses } => match bench::latency(presses) {
+                Ok(latency) => println!("{}", serde_json::json!(latency)),
+                Err(e) => {
+                    eprintln!("!error: {}", e);
+                    std::process::exit(1);
+                }
+            },
+        }
     } else {
-        let name = args.get(0).map(|s| s.as_str()).unwrap_or("speakmcp-rs");
-        eprintln!("Usage: {} [listen|write <text>]", name);
+        let name = args.first().map(|s| s.as_str()).unwrap_or("speakmcp-rs");
+        eprintln!("Usage: {} [listen|serve [options]|decode <dump>|keymap dump|leds get|set|backlight list|set|gpu snapshot save|list|restore|write [options] <text>|press <combo>|key down|up <key>|xdo <command>|ydotoold|mouse <action>|record --out <file>|replay <file>|backends|doctor|service install|uninstall|status|install-permissions|input-broker|selftest|bench throughput|latency]", name);
+        eprintln!("Options:");
+        eprintln!("  --inject-backend <name>[,<name>...] - Before the command: inject through these backends only, in this");
+        eprintln!("                 order ([injection] backends in the config file; see 'backends')");
         eprintln!("Commands:");
-        eprintln!("  listen       - Listen for keyboard events");
+        eprintln!("  listen       - Listen for keyboard events (also 'serve', e.g. serve --http 127.0.0.1:9835)");
+        eprintln!("    --capture-backend replay --input <file>  Replay key events recorded from listen instead of reading");
+        eprintln!("                     keyboards (--backend works too)");
+        eprintln!("    --socket <path>  Also serve events on a Unix socket, or a named pipe like \\\\.\\pipe\\nvidia-cc on Windows");
+        eprintln!("                     (clients send 'subscribe [--since-seq N] [--compress zstd]')");
+        eprintln!("    --dbus           Serve io.github.aj47.NvidiaCC on the session bus: Listen signals, WriteText, Press, RegisterHotkey (Linux)");
+        eprintln!("    --ydotool-socket <path> Serve ydotool clients on this socket (their YDOTOOL_SOCKET) through the uinput keyboard (Linux)");
+        eprintln!("    --input-broker <path> Open input devices through the input-broker on this socket instead of directly (Linux)");
+        eprintln!("    --driver-guidance When nvidia-smi can't reach the driver, emit DriverProblem and offer the download in a toast (Windows)");
+        eprintln!("    --sandbox        Once devices are open, allow only reading them and writing to stdout: seccomp and Landlock (Linux)");
+        eprintln!("    --realtime       Raise listener thread priority for lower latency");
+        eprintln!("    --suppress-self  Drop keystrokes injected by this helper's write command");
+        eprintln!("    --media-keys     Emit volume, play/pause, next/previous and brightness keys (not on macOS)");
+        eprintln!("    --pause-when-locked  Pause capture while the session is locked (SessionLocked/SessionUnlocked)");
+        eprintln!("    --device <path>  Open only this event node, whatever keys it has; repeat for more (Linux)");
+        eprintln!("    --any-keys       Open every device with keys or buttons, not just keyboards, pedals and pads (Linux)");
+        eprintln!("    --gamepads       Emit GamepadButton{{button, pressed}}; hotkeys can use Gamepad<button> (not on macOS)");
+        eprintln!("    --g-keys         Read Logitech G-keys over hidraw, emitted as Macro1.. like other macro keys (Linux)");
+        eprintln!("    --all-sessions   Also capture other seats and other users' sessions while they are in front (Linux)");
+        eprintln!("    --privacy <mode> allowlist: only keys hotkeys use are named, others are KeyActivity; hash: salted hashes");
+        eprintln!("    --raw-scancodes  Add 'scancode' to key events: evdev code, Windows scan code or macOS key code");
+        eprintln!("    --hotkeys <file> Match the hotkeys defined in a JSON file, emitting HotkeyTriggered/HotkeyReleased");
+        eprintln!("    --hotstrings <file> Expand the hotstrings defined in a TOML file, emitting HotstringTriggered");
+        eprintln!("    --legacy-format  Emit payloads as a JSON string in 'data' (pre-typed event format)");
+        eprintln!("    --output <format>         Print events as json (default), csv, pretty (aligned, colored lines) or msgpack");
+        eprintln!("                              (MessagePack frames, each after its length as a big-endian u32)");
+        eprintln!("    --time-format <format>    Serialize 'time' as epoch-ms, rfc3339 or monotonic (default: system-time)");
+        eprintln!("    --flush-interval-ms <ms>  Coalesce stdout writes (default: flush every event)");
+        eprintln!("    --max-event-rate <n>      Emit at most n key events per second, then RateLimited{{dropped}}");
+        eprintln!("    --idle-after <secs>       Emit UserIdle{{idle_secs}} after that long without input, then UserActive");
+        eprintln!("    --record-to <file>        Also append every event to an NDJSON file, rotated at 10 MiB (zstd if *.zst)");
+        eprintln!("    --record-last <n>         Keep the last n events in memory for the stdin 'dump [<path>]' command");
+        eprintln!("    --parent-pid <pid>        Exit when this process exits");
+        eprintln!("    --http <addr>             Serve a read-only status page (/, /status, /devices, /gpu)");
+        eprintln!("    --http-token <token>      Token required by --http (generated if omitted)");
+        eprintln!("    --metrics-addr <addr>     Serve Prometheus metrics, GPU telemetry included, at /metrics");
+        eprintln!("    --tcp <addr>              Serve the event stream and stdin commands to clients on another machine");
+        eprintln!("    --token <token>           Token --tcp clients send first as 'auth <token>' (required unless on loopback)");
+        eprintln!("    --tls <cert> <key>        Serve --tcp over TLS with this PEM certificate chain and key (needs libssl)");
+        eprintln!("    stdin commands: pause, resume (or SIGUSR1/SIGUSR2 on Unix), write [--keys] [--ime-safe | --paste [--primary]");
+        eprintln!("                    | --backend <name>] <json string>, cancel [<id>], config reload (or SIGHUP), hotkey add <json>, hotkey remove <id>,");
+        eprintln!("                    hotkey list, leds get|set, backlight set <args>, notify <json>,");
+        eprintln!("                    context [<chars>], inhibit-sleep start [<reason>]|stop, idle, stats, dump [<path>];");
+        eprintln!("                    lead any with --id <id> to have the events it causes carry request_id");
+        eprintln!("  decode <dump> [--hotkeys <file>] [--hotstrings <file>] [--privacy <mode>] [--raw-scancodes] - Replay an evtest-format evdev dump through the key mapping (Linux)");
+        eprintln!("  keymap dump  - Print every key code with the name it is emitted as, [remap] rules included, as JSON");
+        eprintln!("  leds get     - Print the Caps Lock, Num Lock and Scroll Lock state as JSON");
+        eprintln!("  leds set <capslock|numlock|scrolllock> <on|off> - Light or darken a lock LED (Windows: toggles the lock)");
+        eprintln!("  backlight list - Print the keyboard backlights and their brightness and color as JSON (Linux)");
+        eprintln!("  backlight set <percent|on|off> [--color #rrggbb] [--device <name>] - Set keyboard backlights (Linux)");
+        eprintln!("  gpu snapshot save <name> - Save each GPU's power limit, application clocks, persistence and compute mode");
+        eprintln!("  gpu snapshot list - Print the saved snapshots as JSON");
+        eprintln!("  gpu snapshot restore <name> - Save the current settings as 'pre-change', then set back those of <name>");
+        eprintln!("                 through nvidia-smi (mostly needs root or an administrator)");
+        eprintln!("  windows list - Print the top-level windows as JSON: id, title, app_id, pid, focused");
+        eprintln!("  windows focus <match> - Focus a window: its id, (part of) its title, or a JSON object of id, title, app_id, pid");
+        eprintln!("  windows raise <match> - Bring a window to the front without focusing it (sway: focuses it)");
+        eprintln!("  clipboard get [--primary] - Print the clipboard's (or primary selection's) text");
+        eprintln!("  clipboard set [--primary] (<text> | --stdin) - Put text on the clipboard (or primary selection, Linux)");
+        eprintln!("  idle         - Print the seconds since the last keyboard or mouse input as JSON: idle_secs, source");
+        eprintln!("  context [--chars <n>] - Print the focused text field's text around the caret as JSON: app, role, before,");
+        eprintln!("                 selection, after, caret, length (AT-SPI, UI Automation or Accessibility; not password fields)");
+        eprintln!("  screenshot [--region x,y,w,h] [--display <n>] [--out <file.png>] - Capture the screen as PNG, to stdout without --out");
+        eprintln!("    --region x,y,w,h Only this rectangle, in desktop coordinates (relative to the display with --display)");
+        eprintln!("    --display <n>    Only monitor <n> (see 'mouse monitors')");
+        eprintln!("  inhibit-sleep start [--reason <text>] - Keep the system from suspending for idleness until stdin closes");
+        eprintln!("  notify --title <title> [--body <text>] [--icon <name|path>] - Show a desktop notification (macOS: no icon)");
         eprintln!("  write <text> - Write text using accessibility API");
+        eprintln!("    --stdin          Read the text from stdin instead of the command line");
+        eprintln!("    --file <path>    Read the text from a file");
+        eprintln!("    --ime-safe       Paste the text instead of typing it while an input method (IME) is active");
+        eprintln!("    --paste          Always paste the text, through the clipboard, putting its contents back after");
+        eprintln!("    --primary        With --paste, paste through the primary selection with a middle click (Linux)");
+        eprintln!("    --backend <name> Insert the text through this injection backend alone; ax puts it into the focused");
+        eprintln!("                     field through the accessibility APIs instead of typing it, sendinput types it as");
+        eprintln!("                     Unicode key events (Windows)");
+        eprintln!("    --window <match> Focus this window (as 'windows focus' matches it) for the write, then focus");
+        eprintln!("                     the previous window again");
+        eprintln!("    --dry-run        Print the key taps the keyboard layout needs instead of typing (Linux)");
+        eprintln!("    --keys           Treat {{key}} and {{combo}} as key presses, e.g. 'Hi{{Enter}}' ({{{{ and }}}} for braces)");
+        eprintln!("  press <combo> - Press a key combination, e.g. ctrl+shift+v or cmd+space");
+        eprintln!("  key down <key> [--timeout-ms <ms>] - Hold a key until 'key up' (auto-released after 10 s by default)");
+        eprintln!("  ydotoold [--socket-path <path>] [--socket-perm <octal>] - Serve ydotool clients until killed, in place of ydotool's");
+        eprintln!("                 daemon (Linux; also when run as 'ydotoold'; default $YDOTOOL_SOCKET or /tmp/.ydotool_socket, 0600)");
+        eprintln!("  xdo key|type|keydown|keyup [options] <args>... - Run an xdotool command line, keysyms and all, on the injection");
+        eprintln!("                 backends (also when run as 'xdotool'); options: --delay, --repeat, --window, --clearmodifiers");
+        eprintln!("  key up <key>  - Release a key held by 'key down'");
+        eprintln!("  mouse move <x> <y> - Move the pointer to desktop coordinates (may be negative on multi-monitor setups)");
+        eprintln!("    --relative       Move by <x> <y> pixels from the current position instead");
+        eprintln!("    --monitor <n>    Treat <x> <y> as relative to monitor <n>'s top-left corner (see 'mouse monitors')");
+        eprintln!("  mouse click <button> [--count <n>] - Click left, right, middle, back or forward (--count 2 double-clicks)");
+        eprintln!("  mouse scroll <dx> <dy> - Scroll by wheel notches (positive: right and down)");
+        eprintln!("  mouse monitors - Print the monitor layout as JSON");
+        eprintln!("  record --out <file> - Record key and mouse input to a macro file until Enter or EOF on stdin");
+        eprintln!("  replay <file> [--speed <x>] - Replay a recorded macro with its original timing (--speed 2: twice as fast)");
+        eprintln!("  backends     - Print the capture and injection backends as JSON: compiled in, usable here, and why not");
+        eprintln!("  service install [--socket <path>] [-- <listen flags>...] - Run listen --socket at login, restarted if it fails,");
+        eprintln!("                 and print its status: a systemd user service on Linux (default $XDG_RUNTIME_DIR/nvidia-cc.sock),");
+        eprintln!("                 a LaunchAgent on macOS (default $TMPDIR/nvidia-cc.sock),");
+        eprintln!("                 a Task Scheduler task on Windows (default \\\\.\\pipe\\nvidia-cc)");
+        eprintln!("  service uninstall - Stop and remove the service");
+        eprintln!("  service status - Print whether the service is installed, running and enabled as JSON, with its socket,");
+        eprintln!("                 and on macOS the agent's permissions and whether an update lost them");
+        eprintln!("  install-permissions [--remove|--print] - Let the session's user read keyboards and use /dev/uinput through a");
+        eprintln!("                 udev uaccess rule, instead of the input group and a re-login (Linux; asks for authentication");
+        eprintln!("                 through pkexec; --print writes the rule to stdout instead)");
+        eprintln!("  input-broker [--socket <path>] --allow-user <user>... - Open input devices for listen --input-broker, run by");
+        eprintln!("                 root or in the input group, so the user needn't be (Linux; default /run/nvidia-cc/input-broker.sock)");
+        eprintln!("  selftest     - Type on a virtual keyboard and check capture and write see the same keys (Linux)");
+        eprintln!("  bench throughput [--events <n>] [--flush-interval-ms <ms>] - Emit key events as fast as stdout takes them");
+        eprintln!("                   and report events/sec on stderr (pipe stdout into the consumer or /dev/null)");
+        eprintln!("  bench latency [--count <n>] - Time F20 presses injected through uinput until they are captured (Linux)");
+        if let Some(path) = config::path() {
+            eprintln!("Defaults and hotkeys are read from {} when it exists", path.display());
+        }
+        eprintln!("Flags win over NVIDIA_CC_* environment variables, which win over the config file");
         std::process::exit(1);
     }
 }
@@ -0,0 +1,5 @@
+const COMMANDS: &[&str] = &["listen", "stop", "write", "press", "hotkey_add", "hotkey_remove", "hotkey_list"];
+
+fn main() {
+    tauri_plugin::Builder::new(COMMANDS).build();
+}
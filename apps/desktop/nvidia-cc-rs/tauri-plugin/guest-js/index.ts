@@ -0,0 +1,40 @@
+import { invoke } from "@tauri-apps/api/core"
+import { listen as listenEvent, type UnlistenFn } from "@tauri-apps/api/event"
+
+/** An event as the helper emits it: `event_type` plus its fields */
+export type HelperEvent = {
+  event_type: string
+  [field: string]: unknown
+}
+
+/** Start capturing and call `handler` with every event */
+export async function listen(handler: (event: HelperEvent) => void): Promise<UnlistenFn> {
+  const unlisten = await listenEvent<HelperEvent>("nvidia-cc:event", (event) => handler(event.payload))
+  await invoke("plugin:nvidia-cc|listen")
+  return unlisten
+}
+
+export async function stop(): Promise<void> {
+  await invoke("plugin:nvidia-cc|stop")
+}
+
+export async function write(text: string, options: { imeSafe?: boolean; keys?: boolean } = {}): Promise<void> {
+  await invoke("plugin:nvidia-cc|write", { text, ...options })
+}
+
+export async function press(combo: string): Promise<void> {
+  await invoke("plugin:nvidia-cc|press", { combo })
+}
+
+/** Register a hotkey, defined as in a `--hotkeys` file; resolves to its id */
+export async function addHotkey(definition: Record<string, unknown>): Promise<string> {
+  return await invoke("plugin:nvidia-cc|hotkey_add", { definition })
+}
+
+export async function removeHotkey(id: string): Promise<void> {
+  await invoke("plugin:nvidia-cc|hotkey_remove", { id })
+}
+
+export async function listHotkeys(): Promise<Record<string, unknown>[]> {
+  return await invoke("plugin:nvidia-cc|hotkey_list")
+}
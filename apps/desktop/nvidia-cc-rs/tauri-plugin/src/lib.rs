@@ -0,0 +1,98 @@
+//! Tauri plugin running nvidia-cc-core inside the app, for a Tauri desktop
+//! shell in place of the spawned helper.
+//!
+//!   .plugin(tauri_plugin_nvidia_cc::init())
+//!
+//! Commands (`plugin:nvidia-cc|<name>`):
+//!   listen         - start capturing; every event is emitted to the webviews as
+//!                    `nvidia-cc:event`, the same object as a line of the
+//!                    helper's stdout
+//!   stop           - stop emitting events and matching hotkeys and hotstrings
+//!   write          - `{text, imeSafe?, keys?}`, resolved once typed
+//!   press          - `{combo}`, e.g. `ctrl+shift+v`
+//!   hotkey_add     - `{definition}` as in a `--hotkeys` file, returns its id
+//!   hotkey_remove  - `{id}`
+//!   hotkey_list    - the active hotkeys, as `hotkey list` reports them
+//!
+//! `listen` loads the config file as the helper does (see
+//! [`nvidia_cc_core::embed`]). `guest-js/index.ts` wraps the commands for the
+//! frontend; the `nvidia-cc:default` permission allows all of them.
+
+use nvidia_cc_core::hotkeys::{self, Definition};
+use nvidia_cc_core::{embed, TextInjector};
+use serde_json::Value;
+use tauri::plugin::{Builder, TauriPlugin};
+use tauri::{AppHandle, Emitter, Runtime};
+
+/// Name of the event every helper event is emitted as
+pub const EVENT: &str = "nvidia-cc:event";
+
+#[tauri::command]
+fn listen<R: Runtime>(app: AppHandle<R>) -> Result<(), String> {
+    embed::start(move |event| {
+        // Queued for the webviews, so it returns without waiting on them
+        if let Ok(event) = serde_json::from_str::<Value>(event) {
+            if let Err(e) = app.emit(EVENT, event) {
+                eprintln!("Failed to emit {}: {}", EVENT, e);
+            }
+        }
+    })
+}
+
+#[tauri::command]
+fn stop() {
+    embed::stop();
+}
+
+#[tauri::command]
+async fn write(text: String, ime_safe: Option<bool>, keys: Option<bool>) -> Result<(), String> {
+    inject(move || {
+        let mut injector = TextInjector::new().key_escapes(keys.unwrap_or(false));
+        if let Some(ime_safe) = ime_safe {
+            injector = injector.ime_safe(ime_safe);
+        }
+        injector.write(&text).map_err(|e| e.to_string())
+    })
+    .await
+}
+
+#[tauri::command]
+async fn press(combo: String) -> Result<(), String> {
+    inject(move || TextInjector::new().press(&combo).map_err(|e| e.to_string())).await
+}
+
+/// Run a blocking injection off the async runtime
+async fn inject(injection: impl FnOnce() -> Result<(), String> + Send + 'static) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(injection)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+fn hotkey_add(definition: Definition) -> Result<String, String> {
+    hotkeys::add(definition)
+}
+
+#[tauri::command]
+fn hotkey_remove(id: String) -> Result<(), String> {
+    hotkeys::remove(&id)
+}
+
+#[tauri::command]
+fn hotkey_list() -> Vec<Value> {
+    hotkeys::list()
+}
+
+pub fn init<R: Runtime>() -> TauriPlugin<R> {
+    Builder::new("nvidia-cc")
+        .invoke_handler(tauri::generate_handler![
+            listen,
+            stop,
+            write,
+            press,
+            hotkey_add,
+            hotkey_remove,
+            hotkey_list
+        ])
+        .build()
+}
@@ -0,0 +1,62 @@
+//! `write --backend ax` through AT-SPI, here a stand-in python3 that logs
+//! each insert it's asked for.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+mod common;
+
+/// Appends stdin, one insert per line, to `inserts.log` next to it
+const FAKE_PYTHON: &str = r#"#!/bin/sh
+log="$(dirname "$0")/inserts.log"
+cat >> "$log"
+echo >> "$log"
+"#;
+
+/// No focused field takes text
+const REFUSING_PYTHON: &str = "#!/bin/sh\nprintf \"The focused field isn't editable\" >&2\nexit 3\n";
+
+/// A scratch dir holding `python` as python3
+fn scratch_dir(name: &str, python: &str) -> PathBuf {
+    let dir = common::scratch_dir(&format!("ax-{}", name));
+    common::fake_tool(&dir, "python3", python);
+    dir
+}
+
+fn write(dir: &Path, args: &[&str]) -> Output {
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    std::process::Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("write")
+        .args(args)
+        .env("PATH", path)
+        .output()
+        .expect("run write")
+}
+
+#[test]
+fn text_is_inserted_in_one_go() {
+    let dir = scratch_dir("insert", FAKE_PYTHON);
+    let text = "Longer than one progress chunk of typing, with ümlauts and 漢字";
+    let output = write(&dir, &["--backend", "ax", text]);
+    let log = fs::read_to_string(dir.join("inserts.log"));
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(log.expect("python3 ran"), format!("{}\n", text));
+}
+
+#[test]
+fn refusals_and_bad_flags_are_errors() {
+    let dir = scratch_dir("errors", REFUSING_PYTHON);
+    let refused = write(&dir, &["--backend", "ax", "hello"]);
+    let unknown = write(&dir, &["--backend", "telepathy", "hello"]);
+    let both = write(&dir, &["--paste", "--backend", "ax", "hello"]);
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("The focused field isn't editable"));
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains(r#"unknown backend "telepathy""#));
+    assert!(String::from_utf8_lossy(&both.stderr).contains("--backend doesn't go with --paste"));
+}
@@ -0,0 +1,71 @@
+//! `backends` and `--inject-backend`, with no display and a stand-in
+//! python3 whose AT-SPI answers that nothing has focus.
+#![cfg(target_os = "linux")]
+
+use serde_json::Value;
+use std::fs;
+use std::process::Output;
+
+mod common;
+
+fn run(name: &str, args: &[&str]) -> Output {
+    let dir = common::scratch_dir(&format!("backends-{}", name));
+    common::fake_tool(&dir, "python3", "#!/bin/sh\necho null\n");
+
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(args)
+        .env("PATH", path)
+        .env_remove("DISPLAY")
+        .env_remove("WAYLAND_DISPLAY")
+        .output()
+        .expect("run nvidia-cc-rs");
+    fs::remove_dir_all(&dir).ok();
+    output
+}
+
+/// The entry for `name` in one of the report's lists
+fn entry<'a>(list: &'a Value, name: &str) -> &'a Value {
+    list.as_array()
+        .and_then(|entries| entries.iter().find(|entry| entry["name"] == name))
+        .unwrap_or_else(|| panic!("{} missing from {}", name, list))
+}
+
+#[test]
+fn report_lists_every_backend_with_reasons() {
+    let output = run("report", &["backends"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: Value = serde_json::from_slice(&output.stdout).expect("backends prints JSON");
+
+    let names: Vec<&str> = report["inject"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["enigo", "uinput", "portal", "wayland", "sendinput", "clipboard", "xdotool", "ax"]);
+    let sendinput = entry(&report["inject"], "sendinput");
+    assert_eq!((&sendinput["compiled"], &sendinput["usable"]), (&Value::Bool(false), &Value::Bool(false)));
+    assert_eq!(entry(&report["inject"], "xdotool")["reason"], "DISPLAY is not set");
+    let ax = entry(&report["inject"], "ax");
+    assert_eq!(ax["usable"], true, "{}", ax);
+    assert!(ax.get("reason").is_none());
+
+    let replay = entry(&report["capture"], "replay");
+    assert_eq!((&replay["compiled"], &replay["usable"]), (&Value::Bool(true), &Value::Bool(true)));
+    entry(&report["capture"], "devices");
+}
+
+#[test]
+fn inject_backend_restricts_the_chain() {
+    let only_xdotool = run("inject", &["--inject-backend", "xdotool", "write", "hi"]);
+    let unknown = run("unknown", &["--inject-backend", "xdotool,telepathy", "write", "hi"]);
+    let capture = run("capture", &["listen", "--capture-backend", "telepathy"]);
+
+    assert!(!only_xdotool.status.success());
+    let stderr = String::from_utf8_lossy(&only_xdotool.stderr);
+    assert!(stderr.contains("No injection backend worked (xdotool: DISPLAY is not set)"), "{}", stderr);
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains(r#"unknown backend "telepathy""#));
+    let stderr = String::from_utf8_lossy(&capture.stderr);
+    assert!(stderr.contains(r#"Unknown capture backend "telepathy" (expected devices or replay)"#), "{}", stderr);
+}
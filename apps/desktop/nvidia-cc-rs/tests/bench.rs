@@ -0,0 +1,45 @@
+//! `bench throughput` must deliver every event it counts, whatever the flush strategy.
+
+use std::process::Command;
+
+fn throughput(args: &[&str]) -> (Vec<serde_json::Value>, serde_json::Value) {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["bench", "throughput"])
+        .args(args)
+        .output()
+        .expect("run bench");
+    assert!(output.status.success(), "bench failed: {}", String::from_utf8_lossy(&output.stderr));
+    let events = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("bench emits JSON lines"))
+        .collect();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let report = stderr.lines().last().expect("bench reports on stderr");
+    (events, serde_json::from_str(report).expect("report is JSON"))
+}
+
+#[test]
+fn every_event_reaches_stdout() {
+    let (events, report) = throughput(&["--events", "10000"]);
+    assert_eq!(events.len(), 10_000);
+    assert!(events.iter().all(|event| event["key"] == "KeyA"));
+    assert_eq!(report["events"], 10_000);
+    assert_eq!(report["flush"], "per-event");
+    assert!(report["events_per_sec"].as_f64().is_some_and(|rate| rate > 0.0));
+}
+
+#[test]
+fn coalesced_flushes_drop_nothing() {
+    let (events, report) = throughput(&["--events", "10000", "--flush-interval-ms", "5"]);
+    assert_eq!(events.len(), 10_000);
+    assert_eq!(report["flush"], "interval");
+}
+
+#[test]
+fn unknown_options_are_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["bench", "throughput", "--bogus", "1"])
+        .output()
+        .expect("run bench");
+    assert!(!output.status.success());
+}
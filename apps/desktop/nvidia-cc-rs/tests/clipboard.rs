@@ -0,0 +1,57 @@
+//! `clipboard get|set [--primary]` through xclip, here a stand-in script that
+//! keeps each selection in a file, so the right selection is asked for.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+
+/// An `xclip` reading and writing `<dir>/<selection>`
+const FAKE_XCLIP: &str = r#"#!/bin/sh
+[ "$1" = "-selection" ] || exit 2
+file="$(dirname "$0")/$2"
+case "$3" in
+    -o) [ -f "$file" ] && cat "$file" ;;
+    *) cat > "$file" ;;
+esac
+"#;
+
+fn clipboard(dir: &Path, args: &[&str]) -> Output {
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("clipboard")
+        .args(args)
+        .env("PATH", path)
+        .env_remove("WAYLAND_DISPLAY")
+        .output()
+        .expect("run clipboard")
+}
+
+#[test]
+fn primary_selection_is_kept_apart_from_the_clipboard() {
+    let dir = common::scratch_dir("clipboard");
+    common::fake_tool(&dir, "xclip", FAKE_XCLIP);
+
+    assert!(clipboard(&dir, &["set", "copied"]).status.success());
+    assert!(clipboard(&dir, &["set", "--primary", "selected"]).status.success());
+    let primary = clipboard(&dir, &["get", "--primary"]);
+    let copied = clipboard(&dir, &["get"]);
+    let saved = fs::read_to_string(dir.join("primary"));
+    fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(String::from_utf8_lossy(&primary.stdout), "selected");
+    assert_eq!(String::from_utf8_lossy(&copied.stdout), "copied");
+    assert_eq!(saved.expect("primary written through xclip -selection primary"), "selected");
+}
+
+#[test]
+fn primary_needs_paste() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["write", "--primary", "text"])
+        .output()
+        .expect("run write");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--primary goes with --paste"));
+}
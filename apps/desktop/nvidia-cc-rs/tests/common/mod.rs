@@ -0,0 +1,24 @@
+//! Helpers shared by the integration tests: scratch directories, and tools
+//! the tests stand in for with scripts.
+// Each test crate uses its own share of these
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+
+/// A directory of `test`'s own under the temporary directory
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nvidia-cc-rs-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).expect("create scratch dir");
+    dir
+}
+
+/// Write `script`, shebang and all, to the executable `<dir>/<name>`
+#[cfg(unix)]
+pub fn fake_tool(dir: &std::path::Path, name: &str, script: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join(name);
+    fs::write(&path, script).expect("write fake tool");
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).expect("make fake tool executable");
+}
@@ -0,0 +1,191 @@
+//! Config file: every command reads `nvidia-cc/config.toml` from the config
+//! directory, pointed at a scratch directory here with `XDG_CONFIG_HOME`.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Scratch config directory for one test, with `config` as its config.toml
+fn config_home(test: &str, config: &str) -> PathBuf {
+    let home = std::env::temp_dir().join(format!("nvidia-cc-rs-config-{}-{}", test, std::process::id()));
+    fs::create_dir_all(home.join("nvidia-cc")).expect("create config dir");
+    fs::write(home.join("nvidia-cc/config.toml"), config).expect("write config");
+    home
+}
+
+/// `decode` of a missing dump: cheap, injects nothing, and reads the config like every command
+fn decode_missing_dump(home: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["decode", "/nonexistent.evtest"])
+        .env("XDG_CONFIG_HOME", home)
+        .output()
+        .expect("run decode")
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    for (test, config, expected) in [
+        ("section", "[listne]\nrealtime = true\n", "unknown field"),
+        ("backend", "[injection]\nbackends = [\"xdo\"]\n", "unknown backend"),
+        ("device", "[devices]\nignore = [\"(\"]\n", "invalid device pattern"),
+        ("remap", "[remap]\nKEY_NOPE = \"Hyper\"\n", "unknown evdev key"),
+    ] {
+        let home = config_home(test, config);
+        let output = decode_missing_dump(&home);
+        fs::remove_dir_all(&home).ok();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{} was accepted", test);
+        // Config errors are reported before the command runs
+        assert!(!stderr.contains("/nonexistent.evtest"), "{}: {}", test, stderr);
+        assert!(stderr.contains(expected), "{}: {}", test, stderr);
+    }
+}
+
+#[test]
+fn log_file_receives_stderr() {
+    let home = config_home("log", "");
+    let log = home.join("helper.log");
+    fs::write(
+        home.join("nvidia-cc/config.toml"),
+        format!("[log]\nfile = {:?}\n\n[listen]\nmedia_keys = true\n", log),
+    )
+    .expect("write config");
+
+    let output = decode_missing_dump(&home);
+    let logged = fs::read_to_string(&log).unwrap_or_default();
+    fs::remove_dir_all(&home).ok();
+    assert!(!output.status.success());
+    assert!(output.stderr.is_empty(), "stderr wasn't redirected");
+    assert!(logged.contains("/nonexistent.evtest"), "log: {}", logged);
+}
+
+#[test]
+fn environment_overrides_the_file() {
+    let home = config_home("env", "");
+    let (file_log, env_log) = (home.join("file.log"), home.join("env.log"));
+    fs::write(home.join("nvidia-cc/config.toml"), format!("[log]\nfile = {:?}\n", file_log)).expect("write config");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["decode", "/nonexistent.evtest"])
+        .env("XDG_CONFIG_HOME", &home)
+        .env("NVIDIA_CC_LOG_FILE", &env_log)
+        .env("NVIDIA_CC_BACKENDS", "uinput, xdotool")
+        .output()
+        .expect("run decode");
+    let logged = fs::read_to_string(&env_log).unwrap_or_default();
+    let file_logged = file_log.exists();
+    fs::remove_dir_all(&home).ok();
+    assert!(output.stderr.is_empty(), "stderr wasn't redirected");
+    assert!(logged.contains("/nonexistent.evtest"), "log: {}", logged);
+    assert!(!file_logged, "the config file's log was used");
+}
+
+#[test]
+fn invalid_environment_is_rejected() {
+    for (name, value, expected) in [
+        ("NVIDIA_CC_BACKENDS", "enigo,xdo", "NVIDIA_CC_BACKENDS: unknown backend"),
+        ("NVIDIA_CC_IGNORE_DEVICES", "(", "NVIDIA_CC_IGNORE_DEVICES: invalid device pattern"),
+        ("NVIDIA_CC_REALTIME", "maybe", "NVIDIA_CC_REALTIME"),
+        ("NVIDIA_CC_PAUSE_WHEN_LOCKED", "later", "NVIDIA_CC_PAUSE_WHEN_LOCKED"),
+        ("NVIDIA_CC_RAW_SCANCODES", "yes please", "NVIDIA_CC_RAW_SCANCODES"),
+        ("NVIDIA_CC_FLUSH_INTERVAL_MS", "soon", "NVIDIA_CC_FLUSH_INTERVAL_MS"),
+    ] {
+        let home = config_home(name, "");
+        let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .args(["decode", "/nonexistent.evtest"])
+            .env("XDG_CONFIG_HOME", &home)
+            .env(name, value)
+            .output()
+            .expect("run decode");
+        fs::remove_dir_all(&home).ok();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{} was accepted", name);
+        assert!(stderr.contains(expected), "{}: {}", name, stderr);
+    }
+}
+
+#[test]
+fn remapped_keys_are_emitted_and_matched_under_their_new_name() {
+    let home = config_home("remap", "[remap]\nKEY_CAPSLOCK = \"Hyper\"\nMetaLeft = \"Alt\"\nAlt = \"MetaLeft\"\n");
+    // Caps Lock, Space, then left Alt (KEY_LEFTALT, 56)
+    let dump = home.join("keys.evtest");
+    fs::write(
+        &dump,
+        "Event: time 1700000000.000000, type 1 (EV_KEY), code 58, value 1\n\
+         Event: time 1700000000.010000, type 1 (EV_KEY), code 57, value 1\n\
+         Event: time 1700000000.020000, type 1 (EV_KEY), code 57, value 0\n\
+         Event: time 1700000000.030000, type 1 (EV_KEY), code 58, value 0\n\
+         Event: time 1700000000.040000, type 1 (EV_KEY), code 56, value 1\n",
+    )
+    .expect("write dump");
+    let hotkeys = home.join("hotkeys.json");
+    fs::write(&hotkeys, r#"[{"id": "launcher", "keys": "Hyper+Space"}]"#).expect("write hotkeys");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("decode")
+        .arg(&dump)
+        .arg("--hotkeys")
+        .arg(&hotkeys)
+        .env("XDG_CONFIG_HOME", &home)
+        .output()
+        .expect("run decode");
+    fs::remove_dir_all(&home).ok();
+    assert!(output.status.success(), "decode failed: {}", String::from_utf8_lossy(&output.stderr));
+    let summary: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| {
+            let event: serde_json::Value = serde_json::from_str(line).expect("decode emits JSON lines");
+            format!("{} {}", event["event_type"].as_str().unwrap_or_default(), event["key"].as_str().unwrap_or_default())
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            "KeyPress Hyper",
+            "KeyPress Space",
+            "HotkeyTriggered ",
+            "KeyRelease Space",
+            "HotkeyReleased ",
+            "KeyRelease Hyper",
+            "KeyPress MetaLeft",
+        ]
+    );
+}
+
+#[test]
+fn keymap_dump_includes_remaps() {
+    let home = config_home(
+        "keymap",
+        "[remap]\nKEY_F13 = \"Dictate\"\n\n[[device_remap]]\ndevice = \"(?i)magic\"\nkeys = { MetaLeft = \"Alt\" }\n",
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["keymap", "dump"])
+        .env("XDG_CONFIG_HOME", &home)
+        .output()
+        .expect("run keymap dump");
+    fs::remove_dir_all(&home).ok();
+    assert!(output.status.success(), "keymap dump failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let dump: serde_json::Value = serde_json::from_slice(&output.stdout).expect("keymap dump prints JSON");
+    let keys = dump["keys"].as_array().expect("keys");
+    let key = |native: &str| keys.iter().find(|key| key["native"] == native).expect(native).clone();
+    assert_eq!(key("KEY_A"), serde_json::json!({"code": 30, "native": "KEY_A", "name": "KeyA"}));
+    let f13 = key("KEY_F13");
+    assert_eq!((f13["name"].as_str(), f13["default"].as_str()), (Some("Dictate"), Some("F13")));
+    assert_eq!(key("KEY_LEFTMETA")["devices"], serde_json::json!([{"device": "(?i)magic", "name": "Alt"}]));
+}
+
+#[test]
+fn selected_devices_skip_autodetection() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("listen")
+        .env("NVIDIA_CC_DEVICES", "/nonexistent/event9")
+        .output()
+        .expect("run listen");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""device_selection":"explicit""#), "{}", stdout);
+    // The named node is opened even though it isn't a keyboard, and failing to is fatal
+    assert!(stdout.contains("Cannot open device /nonexistent/event9"), "{}", stdout);
+}
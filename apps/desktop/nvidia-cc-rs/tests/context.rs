@@ -0,0 +1,65 @@
+//! `context` through AT-SPI, here a stand-in python3 answering as the
+//! script would for a given field.
+#![cfg(target_os = "linux")]
+
+use serde_json::{json, Value};
+use std::fs;
+use std::process::Output;
+
+mod common;
+
+/// `context` with a python3 that prints `reply`
+fn context(name: &str, reply: &str, args: &[&str]) -> Output {
+    let dir = common::scratch_dir(&format!("context-{}", name));
+    common::fake_tool(&dir, "python3", &format!("#!/bin/sh\ncat <<'REPLY'\n{}\nREPLY\n", reply));
+
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("context")
+        .args(args)
+        .env("PATH", path)
+        .output()
+        .expect("run context");
+    fs::remove_dir_all(&dir).ok();
+    output
+}
+
+#[test]
+fn text_is_cut_around_the_caret() {
+    let field = json!({
+        "app": "gedit",
+        "role": "text",
+        "before": "Grüße aus Köln. ",
+        "selection": "draft",
+        "after": " follows",
+        "source": "atspi",
+    });
+    let output = context("cut", &field.to_string(), &["--chars", "6"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let context: Value = serde_json::from_slice(&output.stdout).expect("context prints JSON");
+    assert_eq!(
+        context,
+        json!({
+            "app": "gedit",
+            "role": "text",
+            "before": "Köln. ",
+            "selection": "draft",
+            "after": " follo",
+            "caret": 16,
+            "length": 29,
+            "source": "atspi",
+        })
+    );
+}
+
+#[test]
+fn password_fields_and_no_focus_are_errors() {
+    let password = r#"{"app": "seahorse", "role": "password text", "secure": true, "before": "", "source": "atspi"}"#;
+    let secure = context("secure", password, &[]);
+    let unfocused = context("unfocused", "null", &[]);
+
+    assert!(!secure.status.success());
+    assert!(secure.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&secure.stderr).contains("password field"));
+    assert!(String::from_utf8_lossy(&unfocused.stderr).contains("No text field has focus"));
+}
@@ -0,0 +1,165 @@
+//! `listen --dbus` on a private session bus started for each test, called
+//! through `gdbus`. Injection fails without a display or uinput, so writes
+//! are followed as far as their queueing.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdout, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+
+struct Bus {
+    daemon: Child,
+    address: String,
+    dir: PathBuf,
+}
+
+impl Bus {
+    /// None when dbus-daemon isn't installed
+    fn start(name: &str) -> Option<Bus> {
+        let dir = common::scratch_dir(&format!("dbus-{}", name));
+        // Keeps listen running while it is called
+        fs::write(dir.join("events.jsonl"), r#"{"event_type":"KeyPress","key":"KeyA","delay_ms":5000}"#)
+            .expect("write events");
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .arg(format!("--address=unix:path={}", dir.join("bus").display()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().expect("stdout")).read_line(&mut address).expect("read bus address");
+        Some(Bus {
+            daemon,
+            address: address.trim().to_string(),
+            dir,
+        })
+    }
+
+    fn listen(&self) -> Child {
+        Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .args(["listen", "--backend", "replay", "--dbus", "--input"])
+            .arg(self.dir.join("events.jsonl"))
+            .env("DBUS_SESSION_BUS_ADDRESS", &self.address)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("run listen")
+    }
+
+    fn gdbus(&self, args: &[&str]) -> Output {
+        Command::new("gdbus")
+            .arg(args[0])
+            .args(["--session", "--dest", "io.github.aj47.NvidiaCC", "--object-path", "/io/github/aj47/NvidiaCC"])
+            .args(&args[1..])
+            .env("DBUS_SESSION_BUS_ADDRESS", &self.address)
+            .output()
+            .expect("run gdbus")
+    }
+
+    fn call(&self, method: &str, argument: &str) -> Output {
+        self.gdbus(&["call", "--method", &format!("io.github.aj47.NvidiaCC.{}", method), argument])
+    }
+
+    /// Wait for the listener to own its name
+    fn wait_for_service(&self) {
+        let started = Instant::now();
+        while !self.gdbus(&["introspect"]).status.success() {
+            assert!(started.elapsed() < Duration::from_secs(5), "the service never appeared");
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        self.daemon.kill().ok();
+        self.daemon.wait().ok();
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// stdout events up to the first of `event_type`
+fn read_until(stdout: ChildStdout, event_type: &str) -> Vec<serde_json::Value> {
+    let mut events = Vec::new();
+    for line in BufReader::new(stdout).lines() {
+        let event: serde_json::Value = serde_json::from_str(&line.expect("read stdout")).expect("JSON line");
+        let last = event["event_type"] == event_type;
+        events.push(event);
+        if last {
+            break;
+        }
+    }
+    events
+}
+
+#[test]
+fn methods_queue_writes_and_register_hotkeys() {
+    let Some(bus) = Bus::start("methods") else {
+        eprintln!("dbus-daemon isn't installed, skipping");
+        return;
+    };
+    let mut listen = bus.listen();
+    bus.wait_for_service();
+    let mut monitor = Command::new("gdbus")
+        .args(["monitor", "--session", "--dest", "io.github.aj47.NvidiaCC"])
+        .env("DBUS_SESSION_BUS_ADDRESS", &bus.address)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run gdbus monitor");
+    thread::sleep(Duration::from_millis(300));
+
+    let write = bus.call("WriteText", "hello");
+    let press = bus.call("Press", "ctrl+nosuchkey");
+    let register = bus.call("RegisterHotkey", r#"{"id": "dictate", "keys": "ControlLeft+Space"}"#);
+    let duplicate = bus.call("RegisterHotkey", r#"{"id": "dictate", "keys": "ControlLeft+Space"}"#);
+    let unregister = bus.call("UnregisterHotkey", "dictate");
+    let events = read_until(listen.stdout.take().expect("stdout"), "HotkeyRemoved");
+    thread::sleep(Duration::from_millis(300));
+    listen.kill().ok();
+    listen.wait().ok();
+    monitor.kill().ok();
+    let mut signals = String::new();
+    monitor.stdout.take().expect("stdout").read_to_string(&mut signals).ok();
+    monitor.wait().ok();
+
+    assert_eq!(stdout(&write), "('dbus-1',)");
+    let queued = events.iter().find(|event| event["event_type"] == "WriteQueued").expect("WriteQueued");
+    assert_eq!(queued["request_id"], "dbus-1");
+    assert!(String::from_utf8_lossy(&press.stderr).contains("InvalidArgs"), "{:?}", press);
+    assert_eq!(stdout(&register), "('dictate',)");
+    assert!(String::from_utf8_lossy(&duplicate.stderr).contains("Duplicate hotkey id"), "{:?}", duplicate);
+    assert_eq!(stdout(&unregister), "()");
+    assert!(events.iter().any(|event| event["event_type"] == "HotkeyAdded" && event["id"] == "dictate"));
+
+    let listened: Vec<&str> = signals.lines().filter(|line| line.contains("io.github.aj47.NvidiaCC.Listen")).collect();
+    assert!(listened.iter().any(|line| line.contains(r#""request_id":"dbus-1""#)), "{}", signals);
+    assert!(listened.iter().any(|line| line.contains("HotkeyRemoved")), "{}", signals);
+}
+
+#[test]
+fn one_listener_owns_the_name() {
+    let Some(bus) = Bus::start("owner") else {
+        eprintln!("dbus-daemon isn't installed, skipping");
+        return;
+    };
+    let mut first = bus.listen();
+    bus.wait_for_service();
+    let second = bus.listen().wait_with_output().expect("run second listen");
+    first.kill().ok();
+    first.wait().ok();
+
+    assert_eq!(second.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&second.stderr).contains("already owned"), "{:?}", second);
+}
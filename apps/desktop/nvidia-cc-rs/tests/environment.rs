@@ -0,0 +1,121 @@
+//! `doctor` and the `Environment` event: what kind of session this is, who
+//! serves its display sockets and whether the portals answer.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::process::{Command, Stdio};
+
+mod common;
+
+#[test]
+fn doctor_reports_the_wayland_socket_and_its_server() {
+    let dir = common::scratch_dir("environment");
+    let socket = dir.join("wayland-test");
+    // Accepted by the kernel's backlog; nothing needs to answer
+    let _compositor = UnixListener::bind(&socket).expect("bind the compositor socket");
+    fs::set_permissions(&socket, fs::Permissions::from_mode(0o755)).expect("set socket permissions");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("doctor")
+        .env("XDG_RUNTIME_DIR", &dir)
+        .env("WAYLAND_DISPLAY", "wayland-test")
+        .env("DISPLAY", "remote.example:0")
+        .env("DBUS_SESSION_BUS_ADDRESS", format!("unix:path={}", dir.join("no-bus").display()))
+        .env_remove("XDG_SESSION_TYPE")
+        .output()
+        .expect("run doctor");
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("doctor prints JSON");
+    assert!(report["backends"]["inject"].is_array());
+    let environment = &report["environment"];
+    assert_eq!(environment["display_server"], "wayland");
+
+    let wayland = &environment["sockets"][0];
+    assert_eq!(wayland["kind"], "wayland");
+    assert_eq!(wayland["path"], socket.to_str().unwrap());
+    assert_eq!(wayland["mode"], "0755");
+    assert_eq!(wayland["connectable"], true);
+    // This test serves the socket, so it's the compositor
+    let server = wayland["server"].as_str().expect("the server is named");
+    assert!(server.starts_with("environment"), "{}", server);
+    assert_eq!(environment["compositor"], server);
+
+    let x11 = &environment["sockets"][1];
+    assert_eq!(x11["path"], "remote.example:0");
+    assert_eq!(x11["connectable"], false);
+
+    let portals = environment["portals"].as_array().expect("portals");
+    let names: Vec<_> = portals.iter().map(|portal| portal["interface"].as_str().unwrap_or_default()).collect();
+    assert_eq!(names, ["org.freedesktop.portal.GlobalShortcuts", "org.freedesktop.portal.RemoteDesktop"]);
+    assert!(portals.iter().all(|portal| portal["available"] == false && portal["reason"].is_string()));
+}
+
+#[test]
+fn listen_emits_the_environment_after_capabilities() {
+    let dir = common::scratch_dir("environment-listen");
+    fs::write(dir.join("events.jsonl"), "").expect("write events");
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .env("XDG_SESSION_TYPE", "x11")
+        .env("DISPLAY", ":99")
+        .env("DBUS_SESSION_BUS_ADDRESS", format!("unix:path={}", dir.join("no-bus").display()))
+        .env_remove("WAYLAND_DISPLAY")
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("JSON line"))
+        .filter(|event: &serde_json::Value| event["event_type"] != "ClockSync")
+        .collect();
+    assert_eq!(events[0]["event_type"], "Capabilities");
+    let environment = &events[1];
+    assert_eq!(environment["event_type"], "Environment");
+    assert_eq!(environment["session_type"], "x11");
+    assert_eq!(environment["display_server"], "x11");
+    assert_eq!(environment["sockets"][0]["path"], "/tmp/.X11-unix/X99");
+}
+
+#[test]
+fn doctor_says_why_a_container_sees_no_gpu() {
+    let dir = common::scratch_dir("environment-container");
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(std::iter::once(dir.clone()).chain(std::env::split_paths(&path))).expect("PATH");
+    let doctor = |script: &str| {
+        common::fake_tool(&dir, "nvidia-smi", script);
+        let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .arg("doctor")
+            .env("container", "podman")
+            .env("NVIDIA_VISIBLE_DEVICES", "void")
+            .env("PATH", &path)
+            .env("DBUS_SESSION_BUS_ADDRESS", format!("unix:path={}", dir.join("no-bus").display()))
+            .output()
+            .expect("run doctor");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).expect("doctor prints JSON")
+    };
+    let failed = doctor("#!/bin/sh\necho 'NVIDIA-SMI has failed to reach the driver'\nexit 9\n");
+    let found = doctor("#!/bin/sh\necho '0, NVIDIA GeForce RTX 4090, 45, 12, 1024, 24564, 61.5'\n");
+    fs::remove_dir_all(&dir).ok();
+
+    let container = &failed["environment"]["container"];
+    assert_eq!(container["runtime"], "podman");
+    assert_eq!(container["nvidia_visible_devices"], "void");
+    assert!(container["devices"].is_array());
+    assert!(container["gpus"].is_null());
+    let error = container["error"].as_str().expect("the error is given");
+    assert!(error.contains("NVIDIA-SMI has failed to reach the driver"), "{}", error);
+    assert!(error.ends_with("NVIDIA_VISIBLE_DEVICES hides every GPU"), "{}", error);
+
+    let container = &found["environment"]["container"];
+    assert_eq!(container["gpus"], 1);
+    assert!(container.get("error").is_none(), "{}", container);
+}
@@ -0,0 +1,126 @@
+//! Golden corpus: evdev dumps in `tests/corpus/*.evtest` are replayed through the
+//! helper's key mapping with `decode`, and the resulting key events must match the
+//! neighbouring `.golden` file exactly.
+//!
+//! After an intentional mapping change, regenerate with:
+//!   UPDATE_GOLDEN=1 cargo test --test golden
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+mod common;
+
+fn corpus_dumps() -> Vec<PathBuf> {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+    let mut dumps: Vec<PathBuf> = fs::read_dir(&corpus)
+        .expect("tests/corpus exists")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "evtest"))
+        .collect();
+    dumps.sort();
+    dumps
+}
+
+/// One `<event_type> <key>` line per emitted key event, ignoring timing fields
+fn summarize(stdout: &[u8]) -> String {
+    let mut summary = String::new();
+    for line in String::from_utf8_lossy(stdout).lines() {
+        let event: serde_json::Value = serde_json::from_str(line).expect("decode emits JSON lines");
+        let event_type = event["event_type"].as_str().unwrap_or_default();
+        if event_type == "KeyPress" || event_type == "KeyRelease" {
+            let key = event["key"].as_str().unwrap_or_default();
+            summary.push_str(&format!("{} {}\n", event_type, key));
+        }
+    }
+    summary
+}
+
+#[test]
+fn corpus_matches_golden_output() {
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let dumps = corpus_dumps();
+    assert!(!dumps.is_empty(), "golden corpus is empty");
+
+    let mut mismatches = Vec::new();
+    for dump in dumps {
+        let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .arg("decode")
+            .arg(&dump)
+            .output()
+            .expect("run decode");
+        assert!(
+            output.status.success(),
+            "decode {} failed: {}",
+            dump.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let actual = summarize(&output.stdout);
+        let golden = dump.with_extension("golden");
+        if update {
+            fs::write(&golden, &actual).expect("write golden file");
+        } else if fs::read_to_string(&golden).unwrap_or_default() != actual {
+            mismatches.push(format!("{}:\n{}", golden.display(), actual));
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "key mapping output changed; actual output:\n{}\nrun with UPDATE_GOLDEN=1 if intentional",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn releases_carry_hold_duration() {
+    let dir = common::scratch_dir("hold");
+    // KEY_A down, autorepeat, up 180ms later; KEY_B up without a press
+    let dump = "Event: time 1700000000.100000, type 1 (EV_KEY), code 30, value 1\n\
+                Event: time 1700000000.200000, type 1 (EV_KEY), code 30, value 2\n\
+                Event: time 1700000000.280000, type 1 (EV_KEY), code 30, value 0\n\
+                Event: time 1700000000.300000, type 1 (EV_KEY), code 48, value 0\n";
+    fs::write(dir.join("hold.evtest"), dump).expect("write dump");
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("decode")
+        .arg(dir.join("hold.evtest"))
+        .output()
+        .expect("run decode");
+    fs::remove_dir_all(&dir).ok();
+    assert!(output.status.success(), "decode failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("decode emits JSON lines"))
+        .collect();
+    assert_eq!(events.len(), 3);
+    assert!(events[0].get("hold_ms").is_none(), "presses carry no hold_ms");
+    assert_eq!(events[1]["hold_ms"], 180);
+    assert!(events[2].get("hold_ms").is_none(), "a release without a press has nothing to measure");
+}
+
+#[test]
+fn raw_scancodes_carry_the_evdev_code() {
+    let dump = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus/laptop-internal.evtest");
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("decode")
+        .arg(&dump)
+        .arg("--raw-scancodes")
+        .output()
+        .expect("run decode");
+    assert!(output.status.success(), "decode failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let mut keys = 0;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let event: serde_json::Value = serde_json::from_str(line).expect("decode emits JSON lines");
+        if event["key"].is_string() {
+            assert!(event["scancode"].is_u64(), "key event without a scancode: {}", line);
+            keys += 1;
+        }
+    }
+    assert!(keys > 0, "the dump has key events");
+    // Without the flag the field stays out of the stream
+    let plain = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs")).arg("decode").arg(&dump).output().expect("run decode");
+    assert!(!String::from_utf8_lossy(&plain.stdout).contains("scancode"));
+}
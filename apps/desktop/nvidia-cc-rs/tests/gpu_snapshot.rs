@@ -0,0 +1,86 @@
+//! `gpu snapshot`: settings are read with nvidia-smi, and restoring sets back
+//! only what changed, saving `pre-change` first and reporting what nvidia-smi
+//! refused.
+#![cfg(unix)]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+
+/// Answers queries from `settings` and logs every other call to `calls`; refuses `-pl` while `deny` exists
+const NVIDIA_SMI: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+case "$1" in
+--query-gpu=*) cat "$dir/settings" ;;
+*)
+    echo "$@" >> "$dir/calls"
+    if [ "$3" = -pl ] && [ -e "$dir/deny" ]; then
+        echo 'Insufficient Permissions'
+        exit 4
+    fi
+    ;;
+esac
+"#;
+
+fn gpu(dir: &Path, args: &[&str]) -> Output {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(std::iter::once(dir.to_path_buf()).chain(std::env::split_paths(&path)))
+        .expect("join PATH");
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("gpu")
+        .args(args)
+        .env("PATH", path)
+        // The data directory under it, on Linux and macOS alike
+        .env("HOME", dir)
+        .env_remove("XDG_DATA_HOME")
+        .output()
+        .expect("run gpu")
+}
+
+fn json(output: &Output) -> serde_json::Value {
+    serde_json::from_slice(&output.stdout).expect("JSON output")
+}
+
+#[test]
+fn restore_sets_back_what_changed() {
+    let dir = common::scratch_dir("gpu-snapshot");
+    common::fake_tool(&dir, "nvidia-smi", NVIDIA_SMI);
+
+    fs::write(dir.join("settings"), "0, 450.00, 2520, 10501, Enabled, Default\n").expect("write settings");
+    let saved = gpu(&dir, &["snapshot", "save", "stock"]);
+    assert!(saved.status.success(), "{}", String::from_utf8_lossy(&saved.stderr));
+    assert_eq!(json(&saved)["gpus"][0]["power_limit_watts"], 450.0);
+
+    // Experimenting lowered the power limit and clocks and dropped persistence mode
+    fs::write(dir.join("settings"), "0, 300.00, 1800, 10501, Disabled, Default\n").expect("write settings");
+    let restored = gpu(&dir, &["snapshot", "restore", "stock"]);
+    let calls = fs::read_to_string(dir.join("calls")).unwrap_or_default();
+    assert!(restored.status.success(), "{}", String::from_utf8_lossy(&restored.stderr));
+    assert_eq!(calls, "-i 0 -pm 1\n-i 0 -pl 450\n-i 0 -ac 10501,2520\n");
+    assert_eq!(json(&restored)["pre_change"], "pre-change");
+
+    let listed = json(&gpu(&dir, &["snapshot", "list"]));
+    let names: Vec<_> = listed.as_array().expect("a list").iter().map(|snapshot| snapshot["name"].clone()).collect();
+    assert_eq!(names, ["pre-change", "stock"]);
+    assert_eq!(listed[0]["gpus"][0]["graphics_clock_mhz"], 1800);
+
+    // Without root the power limit is refused, and said so; the rest is still set
+    fs::write(dir.join("deny"), "").expect("deny -pl");
+    fs::remove_file(dir.join("calls")).ok();
+    let refused = gpu(&dir, &["snapshot", "restore", "stock"]);
+    let calls = fs::read_to_string(dir.join("calls")).unwrap_or_default();
+    let invalid = gpu(&dir, &["snapshot", "save", "../escape"]);
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(!refused.status.success());
+    assert_eq!(calls.lines().count(), 3, "{}", calls);
+    let failed = &json(&refused)["changes"][1];
+    assert_eq!(failed["setting"], "power_limit");
+    let error = failed["error"].as_str().expect("the error is given");
+    assert!(error.starts_with("setting it needs root or an administrator"), "{}", error);
+    assert!(error.contains("Insufficient Permissions"), "{}", error);
+    assert!(!invalid.status.success());
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("Invalid snapshot name"));
+}
@@ -0,0 +1,299 @@
+//! Hotkey engine: key events from an evdev dump are replayed with
+//! `decode --hotkeys`, and the hotkey events it emits must match.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::process::{Command, Output};
+
+mod common;
+
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_ESC: u16 = 1;
+const KEY_A: u16 = 30;
+const KEY_D: u16 = 32;
+const KEY_SPACE: u16 = 57;
+const KEY_RIGHTCTRL: u16 = 97;
+const KEY_LEFTMETA: u16 = 125;
+const KEY_NUMLOCK: u16 = 69;
+const KEY_KP7: u16 = 71;
+
+/// `(code, value)` key events one every 10 ms, as `(ms, code, value)`
+fn every_10ms(events: &[(u16, i32)]) -> Vec<(u64, u16, i32)> {
+    (0..).step_by(10).zip(events).map(|(ms, &(code, value))| (ms, code, value)).collect()
+}
+
+/// Replay `(ms, code, value)` key events against the hotkeys in `definitions`
+fn decode(test: &str, definitions: &str, events: &[(u64, u16, i32)]) -> Output {
+    let dir = common::scratch_dir(&format!("hotkeys-{}", test));
+    let dump: String = events
+        .iter()
+        .map(|(ms, code, value)| {
+            format!(
+                "Event: time {}.{:06}, type 1 (EV_KEY), code {}, value {}\n",
+                1_700_000_000 + ms / 1000,
+                ms % 1000 * 1000,
+                code,
+                value
+            )
+        })
+        .collect();
+    fs::write(dir.join("keys.evtest"), dump).expect("write dump");
+    fs::write(dir.join("hotkeys.json"), definitions).expect("write hotkeys");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("decode")
+        .arg(dir.join("keys.evtest"))
+        .arg("--hotkeys")
+        .arg(dir.join("hotkeys.json"))
+        .output()
+        .expect("run decode");
+    fs::remove_dir_all(&dir).ok();
+    output
+}
+
+/// `<event_type> <id> [<duration_ms>]` for every hotkey event
+fn hotkey_events(output: &Output) -> Vec<String> {
+    assert!(output.status.success(), "decode failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let event: serde_json::Value = serde_json::from_str(line).expect("decode emits JSON lines");
+            let event_type = event["event_type"].as_str()?;
+            let mut summary = format!("{} {}", event_type, event["id"].as_str()?);
+            if let Some(duration_ms) = event["duration_ms"].as_u64() {
+                summary.push_str(&format!(" {}", duration_ms));
+            }
+            Some(summary)
+        })
+        .collect()
+}
+
+#[test]
+fn combo_triggers_and_releases() {
+    let output = decode(
+        "combo",
+        r#"[{"id": "dictate", "keys": "ControlLeft+Space"}]"#,
+        &every_10ms(&[(KEY_LEFTCTRL, 1), (KEY_SPACE, 1), (KEY_SPACE, 2), (KEY_SPACE, 0), (KEY_LEFTCTRL, 0)]),
+    );
+    assert_eq!(hotkey_events(&output), ["HotkeyTriggered dictate", "HotkeyReleased dictate"]);
+}
+
+#[test]
+fn extra_keys_do_not_trigger() {
+    let output = decode(
+        "extra",
+        r#"[{"id": "dictate", "keys": "ControlLeft+Space"}]"#,
+        &every_10ms(&[(KEY_LEFTCTRL, 1), (KEY_A, 1), (KEY_SPACE, 1), (KEY_SPACE, 0), (KEY_A, 0), (KEY_LEFTCTRL, 0)]),
+    );
+    assert!(hotkey_events(&output).is_empty());
+}
+
+#[test]
+fn only_in_needs_a_known_window() {
+    // decode has no window tracking, so only_in hotkeys stay silent and never_in ones fire
+    let output = decode(
+        "scoped",
+        r#"[{"id": "editor", "keys": "Space", "only_in": [{"app_id": "(?i)code"}]},
+            {"id": "anywhere", "keys": "Space", "never_in": [{"title": "(?i)password"}]}]"#,
+        &every_10ms(&[(KEY_SPACE, 1), (KEY_SPACE, 0)]),
+    );
+    assert_eq!(hotkey_events(&output), ["HotkeyTriggered anywhere", "HotkeyReleased anywhere"]);
+}
+
+const HOLD: &str = r#"[{"id": "talk", "keys": "ControlLeft", "mode": "hold", "threshold_ms": 200}]"#;
+
+#[test]
+fn hold_starts_after_threshold() {
+    let output = decode("hold", HOLD, &[(0, KEY_LEFTCTRL, 1), (250, KEY_LEFTCTRL, 2), (900, KEY_LEFTCTRL, 0)]);
+    assert_eq!(hotkey_events(&output), ["HoldStart talk", "HoldEnd talk 900"]);
+}
+
+#[test]
+fn short_tap_is_not_a_hold() {
+    let output = decode("tap", HOLD, &[(0, KEY_LEFTCTRL, 1), (120, KEY_LEFTCTRL, 0)]);
+    assert!(hotkey_events(&output).is_empty());
+}
+
+#[test]
+fn combo_during_threshold_cancels_hold() {
+    // Ctrl+A before the threshold is a shortcut, not push-to-talk
+    let output = decode(
+        "interrupted",
+        HOLD,
+        &[(0, KEY_LEFTCTRL, 1), (50, KEY_A, 1), (80, KEY_A, 0), (600, KEY_LEFTCTRL, 0)],
+    );
+    assert!(hotkey_events(&output).is_empty());
+}
+
+const DOUBLE_TAP: &str = r#"[{"id": "dictate", "keys": "ControlRight", "mode": "double_tap", "interval_ms": 300}]"#;
+
+#[test]
+fn double_tap_within_interval() {
+    let output = decode(
+        "double",
+        DOUBLE_TAP,
+        &[(0, KEY_RIGHTCTRL, 1), (60, KEY_RIGHTCTRL, 0), (180, KEY_RIGHTCTRL, 1), (240, KEY_RIGHTCTRL, 0)],
+    );
+    assert_eq!(hotkey_events(&output), ["DoubleTap dictate"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(r#""interval_ms":180"#));
+}
+
+#[test]
+fn slow_or_interrupted_taps_are_not_double_taps() {
+    let slow = decode(
+        "slow",
+        DOUBLE_TAP,
+        &[(0, KEY_RIGHTCTRL, 1), (60, KEY_RIGHTCTRL, 0), (400, KEY_RIGHTCTRL, 1), (460, KEY_RIGHTCTRL, 0)],
+    );
+    assert!(hotkey_events(&slow).is_empty());
+
+    let interrupted = decode(
+        "interrupted-tap",
+        DOUBLE_TAP,
+        &[
+            (0, KEY_RIGHTCTRL, 1),
+            (40, KEY_RIGHTCTRL, 0),
+            (80, KEY_A, 1),
+            (100, KEY_A, 0),
+            (150, KEY_RIGHTCTRL, 1),
+            (190, KEY_RIGHTCTRL, 0),
+        ],
+    );
+    assert!(hotkey_events(&interrupted).is_empty());
+}
+
+#[test]
+fn triple_tap_fires_once() {
+    // A burst of taps pairs up: the second fires, the third starts a new pair
+    let output = decode(
+        "triple",
+        DOUBLE_TAP,
+        &every_10ms(&[
+            (KEY_RIGHTCTRL, 1),
+            (KEY_RIGHTCTRL, 0),
+            (KEY_RIGHTCTRL, 1),
+            (KEY_RIGHTCTRL, 0),
+            (KEY_RIGHTCTRL, 1),
+            (KEY_RIGHTCTRL, 0),
+        ]),
+    );
+    assert_eq!(hotkey_events(&output), ["DoubleTap dictate"]);
+}
+
+const LONG_PRESS: &str = r#"[{"id": "cancel", "keys": "Escape", "mode": "long_press", "threshold_ms": 1000}]"#;
+
+#[test]
+fn long_press_fires_once_past_threshold() {
+    let output = decode(
+        "long-press",
+        LONG_PRESS,
+        &[(0, KEY_ESC, 1), (500, KEY_ESC, 2), (1100, KEY_ESC, 2), (1600, KEY_ESC, 0)],
+    );
+    assert_eq!(hotkey_events(&output), ["LongPress cancel"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(r#""threshold_ms":1000"#));
+
+    let short = decode("long-press-short", LONG_PRESS, &[(0, KEY_ESC, 1), (900, KEY_ESC, 0)]);
+    assert!(hotkey_events(&short).is_empty());
+}
+
+const MODIFIER_TAP: &str = r#"[{"id": "dictate", "keys": "MetaLeft", "mode": "modifier_tap"}]"#;
+
+#[test]
+fn lone_modifier_tap_fires_on_release() {
+    let output = decode(
+        "modifier-tap",
+        MODIFIER_TAP,
+        &every_10ms(&[(KEY_LEFTMETA, 1), (KEY_LEFTMETA, 2), (KEY_LEFTMETA, 0)]),
+    );
+    assert_eq!(hotkey_events(&output), ["ModifierTap dictate"]);
+}
+
+#[test]
+fn modifier_used_in_a_combo_is_not_a_tap() {
+    let output = decode(
+        "modifier-combo",
+        MODIFIER_TAP,
+        &every_10ms(&[(KEY_LEFTMETA, 1), (KEY_A, 1), (KEY_A, 0), (KEY_LEFTMETA, 0)]),
+    );
+    assert!(hotkey_events(&output).is_empty());
+}
+
+const SEQUENCE: &str =
+    r#"[{"id": "dictate", "sequence": ["ControlLeft+Space", "KeyD"], "step_timeout_ms": 500}]"#;
+
+#[test]
+fn sequence_triggers_on_last_step() {
+    // Ctrl still held for the D doesn't matter
+    let output = decode(
+        "sequence",
+        SEQUENCE,
+        &[
+            (0, KEY_LEFTCTRL, 1),
+            (20, KEY_SPACE, 1),
+            (60, KEY_SPACE, 0),
+            (300, KEY_D, 1),
+            (340, KEY_D, 0),
+            (380, KEY_LEFTCTRL, 0),
+        ],
+    );
+    assert_eq!(hotkey_events(&output), ["SequenceTriggered dictate"]);
+}
+
+#[test]
+fn late_or_interrupted_sequences_do_not_trigger() {
+    let late = decode(
+        "sequence-late",
+        SEQUENCE,
+        &[(0, KEY_LEFTCTRL, 1), (20, KEY_SPACE, 1), (60, KEY_SPACE, 0), (80, KEY_LEFTCTRL, 0), (700, KEY_D, 1)],
+    );
+    assert!(hotkey_events(&late).is_empty());
+
+    let interrupted = decode(
+        "sequence-interrupted",
+        SEQUENCE,
+        &every_10ms(&[
+            (KEY_LEFTCTRL, 1),
+            (KEY_SPACE, 1),
+            (KEY_SPACE, 0),
+            (KEY_LEFTCTRL, 0),
+            (KEY_A, 1),
+            (KEY_A, 0),
+            (KEY_D, 1),
+        ]),
+    );
+    assert!(hotkey_events(&interrupted).is_empty());
+}
+
+#[test]
+fn keypad_digits_match_whatever_numlock_does() {
+    let output = decode(
+        "numlock",
+        r#"[{"id": "digit", "keys": "Kp7"}, {"id": "navigation", "keys": "KpHome"}]"#,
+        // A dump has NumLock off until its NumLock key is pressed
+        &every_10ms(&[(KEY_KP7, 1), (KEY_KP7, 0), (KEY_NUMLOCK, 1), (KEY_NUMLOCK, 0), (KEY_KP7, 1), (KEY_KP7, 0)]),
+    );
+    if !String::from_utf8_lossy(&output.stdout).contains("num_lock") {
+        // No libxkbcommon to track NumLock with
+        return;
+    }
+    let triggered: Vec<_> =
+        hotkey_events(&output).into_iter().filter(|event| event.starts_with("HotkeyTriggered")).collect();
+    assert_eq!(triggered, ["HotkeyTriggered digit", "HotkeyTriggered navigation", "HotkeyTriggered digit"]);
+}
+
+#[test]
+fn invalid_definitions_are_rejected() {
+    for (test, definitions) in [
+        ("regex", r#"[{"id": "bad", "keys": "Space", "never_in": [{"title": "("}]}]"#),
+        ("empty-rule", r#"[{"id": "bad", "keys": "Space", "only_in": [{}]}]"#),
+        ("duplicate", r#"[{"id": "a", "keys": "Space"}, {"id": "a", "keys": "KeyA"}]"#),
+        ("threshold", r#"[{"id": "a", "keys": "Space", "threshold_ms": 100}]"#),
+        ("interval", r#"[{"id": "a", "keys": "Space", "mode": "hold", "interval_ms": 100}]"#),
+        ("keys-and-sequence", r#"[{"id": "a", "keys": "Space", "sequence": ["Space", "KeyA"]}]"#),
+        ("one-step", r#"[{"id": "a", "sequence": ["Space"]}]"#),
+        ("step-timeout", r#"[{"id": "a", "keys": "Space", "step_timeout_ms": 100}]"#),
+    ] {
+        let output = decode(test, definitions, &[]);
+        assert!(!output.status.success(), "{} was accepted", test);
+    }
+}
@@ -0,0 +1,128 @@
+//! Hotstrings: typing replayed from an evdev dump with `decode --hotstrings`
+//! must report the triggers it contains. decode never injects expansions.
+//!
+//! Skipped when libxkbcommon (or the xkb data) isn't installed, since typed
+//! characters come from the layout.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::process::Command;
+
+mod common;
+
+const KEY_BACKSPACE: u16 = 14;
+const KEY_S: u16 = 31;
+const KEY_I: u16 = 23;
+const KEY_G: u16 = 34;
+const KEY_X: u16 = 45;
+const KEY_SEMICOLON: u16 = 39;
+const KEY_LEFTSHIFT: u16 = 42;
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_ENTER: u16 = 28;
+
+const SIGNATURE: &str = r#"
+[[hotstring]]
+id = "signature"
+trigger = ";sig"
+expansion = "Best regards"
+"#;
+
+/// Ids of the hotstrings triggered by pressing and releasing each of `keys` in turn
+/// on a US layout, or None when layouts can't be loaded here
+fn triggered(test: &str, definitions: &str, keys: &[u16]) -> Option<Vec<String>> {
+    let dir = common::scratch_dir(&format!("hotstrings-{}", test));
+    let dump: String = keys
+        .iter()
+        .flat_map(|&code| [(code, 1), (code, 0)])
+        .enumerate()
+        .map(|(index, (code, value))| {
+            format!("Event: time 1700000000.{:06}, type 1 (EV_KEY), code {}, value {}\n", index * 1000, code, value)
+        })
+        .collect();
+    fs::write(dir.join("keys.evtest"), dump).expect("write dump");
+    fs::write(dir.join("hotstrings.toml"), definitions).expect("write hotstrings");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("decode")
+        .arg(dir.join("keys.evtest"))
+        .arg("--hotstrings")
+        .arg(dir.join("hotstrings.toml"))
+        .env("XKB_DEFAULT_LAYOUT", "us")
+        .env_remove("XKB_DEFAULT_VARIANT")
+        .env_remove("XKB_DEFAULT_OPTIONS")
+        .output()
+        .expect("run decode");
+    fs::remove_dir_all(&dir).ok();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("Layout-aware key names unavailable") {
+        eprintln!("skipping: {}", stderr.trim());
+        return None;
+    }
+    assert!(output.status.success(), "decode failed: {}", stderr);
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let event: serde_json::Value = serde_json::from_str(line).expect("decode emits JSON lines");
+                if event["event_type"] != "HotstringTriggered" {
+                    return None;
+                }
+                assert_eq!(event["expanded"], false, "decode must not expand");
+                Some(event["id"].as_str()?.to_string())
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn typed_trigger_fires() {
+    let keys = [KEY_X, KEY_SEMICOLON, KEY_S, KEY_I, KEY_G];
+    if let Some(ids) = triggered("typed", SIGNATURE, &keys) {
+        assert_eq!(ids, ["signature"]);
+    }
+}
+
+#[test]
+fn backspace_corrections_count() {
+    let keys = [KEY_SEMICOLON, KEY_S, KEY_X, KEY_BACKSPACE, KEY_I, KEY_G];
+    if let Some(ids) = triggered("backspace", SIGNATURE, &keys) {
+        assert_eq!(ids, ["signature"]);
+    }
+}
+
+#[test]
+fn non_typing_keys_start_over() {
+    let keys = [KEY_SEMICOLON, KEY_S, KEY_ENTER, KEY_I, KEY_G];
+    if let Some(ids) = triggered("enter", SIGNATURE, &keys) {
+        assert!(ids.is_empty(), "{:?}", ids);
+    }
+}
+
+#[test]
+fn lone_modifier_taps_do_not_break_the_trigger() {
+    let keys = [KEY_LEFTSHIFT, KEY_SEMICOLON, KEY_S, KEY_I, KEY_LEFTCTRL, KEY_G];
+    if let Some(ids) = triggered("modifiers", SIGNATURE, &keys) {
+        assert_eq!(ids, ["signature"]);
+    }
+}
+
+#[test]
+fn invalid_definitions_are_rejected() {
+    for (test, definitions) in [
+        ("empty-trigger", "[[hotstring]]\nid = \"a\"\ntrigger = \"\"\n"),
+        ("duplicate", "[[hotstring]]\nid = \"a\"\ntrigger = \"x\"\n[[hotstring]]\nid = \"a\"\ntrigger = \"y\"\n"),
+        ("unknown-field", "[[hotstring]]\nid = \"a\"\ntrigger = \"x\"\nreplace = \"y\"\n"),
+        ("regex", "[[hotstring]]\nid = \"a\"\ntrigger = \"x\"\nonly_in = [{ title = \"(\" }]\n"),
+    ] {
+        let path = std::env::temp_dir().join(format!("nvidia-cc-rs-hotstrings-{}-{}.toml", test, std::process::id()));
+        fs::write(&path, definitions).expect("write hotstrings");
+        let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .args(["decode", "/dev/null", "--hotstrings"])
+            .arg(&path)
+            .output()
+            .expect("run decode");
+        fs::remove_file(&path).ok();
+        assert!(!output.status.success(), "{} was accepted", test);
+    }
+}
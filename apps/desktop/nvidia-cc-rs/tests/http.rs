@@ -0,0 +1,143 @@
+//! `listen --http`: the generated token, requests whose head runs past the
+//! limit are answered without reading further, `/gpu` asks nvidia-smi, and
+//! only so many requests are handled at once.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+mod common;
+
+/// Bytes of request head the server reads at most
+const MAX_HEAD: usize = 8 * 1024;
+
+/// Send `request` to `addr`, returning the whole response
+fn send(addr: &str, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).expect("connect to the status page");
+    stream.write_all(request).expect("send request");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("read response");
+    response
+}
+
+/// A listener up with `--http 127.0.0.1:0` and `dir` first on PATH, and its HttpListening event
+fn start(dir: &std::path::Path) -> (Child, serde_json::Value) {
+    start_as("listen", dir)
+}
+
+/// [`start`], through `command` (`listen` or its alias `serve`)
+fn start_as(command: &str, dir: &std::path::Path) -> (Child, serde_json::Value) {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let path = std::env::join_paths(std::iter::once(dir.to_path_buf()).chain(std::env::split_paths(&path)))
+        .expect("join PATH");
+    // The release waits, keeping the listener up while it's asked
+    let recorded = [
+        r#"{"event_type":"KeyPress","key":"KeyA"}"#,
+        r#"{"event_type":"KeyRelease","key":"KeyA","delay_ms":5000}"#,
+    ];
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args([command, "--backend", "replay", "--http", "127.0.0.1:0", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .env("PATH", path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run listen");
+    let listening = BufReader::new(child.stdout.take().expect("stdout"))
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(&line.expect("read stdout")).expect("JSON line"))
+        .find(|event| event["event_type"] == "HttpListening")
+        .expect("HttpListening is emitted");
+    (child, listening)
+}
+
+#[test]
+fn oversized_request_heads_are_refused() {
+    let dir = common::scratch_dir("http");
+    let (mut child, listening) = start(&dir);
+    let addr = listening["addr"].as_str().expect("addr").to_string();
+    let token = listening["token"].as_str().expect("token").to_string();
+
+    // Exactly the limit and no line ending, so nothing is left unread
+    let mut long_line = b"GET /".to_vec();
+    long_line.resize(MAX_HEAD, b'a');
+    let line_response = send(&addr, &long_line);
+    let mut long_header = b"GET /status HTTP/1.1\r\nX-Filler: ".to_vec();
+    long_header.resize(MAX_HEAD, b'a');
+    let header_response = send(&addr, &long_header);
+    let ok = send(&addr, format!("GET /status HTTP/1.1\r\nAuthorization: Bearer {}\r\n\r\n", token).as_bytes());
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(line_response.starts_with("HTTP/1.1 400"), "{}", line_response);
+    assert!(header_response.starts_with("HTTP/1.1 431"), "{}", header_response);
+    assert!(ok.starts_with("HTTP/1.1 200 OK"), "{}", ok);
+    assert_eq!(token.len(), 32);
+    assert!(token.bytes().all(|byte| byte.is_ascii_hexdigit()), "{}", token);
+}
+
+#[cfg(unix)]
+#[test]
+fn gpu_lists_what_nvidia_smi_reports() {
+    let dir = common::scratch_dir("http-gpu");
+    common::fake_tool(&dir, "nvidia-smi", "#!/bin/sh\necho '0, NVIDIA GeForce RTX 4090, 45, 12, 1024, 24564, 61.5'\n");
+    let (mut child, listening) = start(&dir);
+    let addr = listening["addr"].as_str().expect("addr");
+    let token = listening["token"].as_str().expect("token");
+
+    let unauthorized = send(addr, b"GET /gpu HTTP/1.1\r\n\r\n");
+    let response = send(addr, format!("GET /gpu?token={} HTTP/1.1\r\n\r\n", token).as_bytes());
+    // Without it the route says why there's nothing to list
+    common::fake_tool(&dir, "nvidia-smi", "#!/bin/sh\necho 'NVIDIA-SMI has failed to reach the driver'\nexit 9\n");
+    let failed = send(addr, format!("GET /gpu?token={} HTTP/1.1\r\n\r\n", token).as_bytes());
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(unauthorized.starts_with("HTTP/1.1 401"), "{}", unauthorized);
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    let body = response.split_once("\r\n\r\n").expect("a body").1;
+    let gpus: serde_json::Value = serde_json::from_str(body).expect("JSON body");
+    assert_eq!(gpus[0]["index"], 0);
+    assert_eq!(gpus[0]["name"], "NVIDIA GeForce RTX 4090");
+    assert_eq!(gpus[0]["utilization_ratio"], 0.12);
+    assert_eq!(gpus[0]["memory_total_bytes"], 24564u64 * 1024 * 1024);
+    assert_eq!(gpus[0]["power_watts"], 61.5);
+    assert!(failed.starts_with("HTTP/1.1 503"), "{}", failed);
+    assert!(failed.contains("NVIDIA-SMI has failed to reach the driver"), "{}", failed);
+}
+
+#[test]
+fn caps_requests_handled_at_once() {
+    let dir = common::scratch_dir("http-connections");
+    let (mut child, listening) = start_as("serve", &dir);
+    let addr = listening["addr"].as_str().expect("addr").to_string();
+    let token = listening["token"].as_str().expect("token").to_string();
+
+    // Clients yet to send their request, each holding a handler
+    let idle: Vec<TcpStream> = (0..16).map(|_| TcpStream::connect(&addr).expect("connect")).collect();
+    // Let the server count them before the next one arrives
+    std::thread::sleep(Duration::from_millis(200));
+    let mut refused = TcpStream::connect(&addr).expect("connect");
+    refused.write_all(format!("GET /status?token={} HTTP/1.1\r\n\r\n", token).as_bytes()).ok();
+    let mut reply = Vec::new();
+    // Closed unread, which may come as a reset
+    let refused = refused.read_to_end(&mut reply);
+    drop(idle);
+    std::thread::sleep(Duration::from_millis(200));
+    let ok = send(&addr, format!("GET /status?token={} HTTP/1.1\r\n\r\n", token).as_bytes());
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    match refused {
+        Ok(_) => assert!(reply.is_empty(), "{}", String::from_utf8_lossy(&reply)),
+        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+    }
+    assert!(ok.starts_with("HTTP/1.1 200 OK"), "{}", ok);
+}
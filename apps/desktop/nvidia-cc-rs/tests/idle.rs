@@ -0,0 +1,56 @@
+//! `idle` through Mutter's idle monitor, or xprintidle when there is none,
+//! both stand-in scripts here.
+#![cfg(target_os = "linux")]
+
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+use common::{fake_tool, scratch_dir};
+
+/// `idle` with the scratch dir's tools ahead of the system's
+fn idle(dir: &Path) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("idle")
+        .env("PATH", format!("{}:/bin", dir.display()))
+        .env("DISPLAY", ":nvidia-cc-rs-test")
+        .output()
+        .expect("run idle");
+    fs::remove_dir_all(dir).ok();
+    output
+}
+
+fn reply(output: &Output) -> Value {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).expect("idle prints JSON")
+}
+
+#[test]
+fn mutter_idle_monitor_comes_first() {
+    let dir = scratch_dir("idle-mutter");
+    let reply_if_asked = r#"#!/bin/sh
+[ "$5" = "--method=org.gnome.Mutter.IdleMonitor.GetIdletime" ] && echo '(uint64 42500,)'"#;
+    fake_tool(&dir, "gdbus", reply_if_asked);
+    fake_tool(&dir, "xprintidle", "#!/bin/sh\necho 1000");
+    assert_eq!(reply(&idle(&dir)), json!({"idle_secs": 42, "source": "mutter"}));
+}
+
+#[test]
+fn xprintidle_without_gnome() {
+    let dir = scratch_dir("idle-x11");
+    fake_tool(&dir, "gdbus", "#!/bin/sh\necho 'No such interface' >&2; exit 1");
+    fake_tool(&dir, "xprintidle", "#!/bin/sh\necho 7250");
+    assert_eq!(reply(&idle(&dir)), json!({"idle_secs": 7, "source": "xprintidle"}));
+}
+
+#[test]
+fn no_source_is_an_error() {
+    let dir = scratch_dir("idle-none");
+    fake_tool(&dir, "gdbus", "#!/bin/sh\nexit 1");
+    let output = idle(&dir);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("gdbus exited") && stderr.contains("xprintidle unavailable"), "{}", stderr);
+}
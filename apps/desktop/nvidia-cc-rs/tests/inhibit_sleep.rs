@@ -0,0 +1,70 @@
+//! `inhibit-sleep start|stop` on a listener's stdin, through a stand-in
+//! systemd-inhibit that logs its arguments and how long it held on.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+mod common;
+
+/// Holds its "lock" until stdin closes, as the real one does with `cat`
+const FAKE_SYSTEMD_INHIBIT: &str = r#"#!/bin/sh
+log="$(dirname "$0")/inhibit.log"
+printf '%s\n' "$@" > "$log"
+cat > /dev/null
+echo released >> "$log"
+"#;
+
+#[test]
+fn start_holds_the_lock_until_stop() {
+    let dir = common::scratch_dir("inhibit");
+    common::fake_tool(&dir, "systemd-inhibit", FAKE_SYSTEMD_INHIBIT);
+    // Keeps listen running while commands are sent
+    fs::write(dir.join("events.jsonl"), r#"{"event_type":"KeyPress","key":"KeyA","delay_ms":3000}"#)
+        .expect("write events");
+
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .env("PATH", path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run listen");
+    let mut stdin = child.stdin.take().expect("stdin");
+    writeln!(stdin, "--id start inhibit-sleep start Long dictation").unwrap();
+
+    let mut lines = BufReader::new(child.stdout.take().expect("stdout")).lines();
+    let mut next = |event_type: &str| loop {
+        let line = lines.next().expect("listen ended early").expect("read stdout");
+        let event: serde_json::Value = serde_json::from_str(&line).expect("JSON line");
+        if event["event_type"] == event_type {
+            break event;
+        }
+    };
+    let inhibited = next("SleepInhibited");
+    let held = fs::read_to_string(dir.join("inhibit.log")).expect("systemd-inhibit ran");
+    writeln!(stdin, "--id stop inhibit-sleep stop").unwrap();
+    let allowed = next("SleepAllowed");
+    let released = fs::read_to_string(dir.join("inhibit.log")).expect("read log");
+    writeln!(stdin, "--id again inhibit-sleep stop").unwrap();
+    let error = next("Error");
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(inhibited["request_id"], "start");
+    assert_eq!(inhibited["reason"], "Long dictation");
+    let args: Vec<&str> = held.lines().collect();
+    assert_eq!(
+        args,
+        ["--what=sleep:idle", "--mode=block", "--who=NVIDIA Control Center", "--why=Long dictation", "cat"]
+    );
+    assert_eq!(allowed["request_id"], "stop");
+    assert!(released.ends_with("released\n"), "the lock is gone once SleepAllowed is out: {}", released);
+    assert_eq!(error["request_id"], "again");
+    assert_eq!(error["error"], "InvalidCommand");
+}
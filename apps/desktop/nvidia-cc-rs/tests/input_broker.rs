@@ -0,0 +1,102 @@
+//! `input-broker` and `listen --input-broker`, up to the event nodes this
+//! sandbox doesn't have: what the broker refuses, and to whom.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+
+struct Broker {
+    child: Child,
+    socket: PathBuf,
+    dir: PathBuf,
+}
+
+impl Broker {
+    /// A broker answering `allowed`; `None` for the user running the tests
+    fn start(name: &str, allowed: Option<&str>) -> Broker {
+        let dir = common::scratch_dir(&format!("broker-{}", name));
+        let uid = fs::metadata(&dir).expect("scratch dir").uid().to_string();
+        let socket = dir.join("broker.sock");
+        let child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .arg("input-broker")
+            .arg("--socket")
+            .arg(&socket)
+            .args(["--allow-user", allowed.unwrap_or(&uid)])
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("start input-broker");
+        let started = Instant::now();
+        while !socket.exists() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(20));
+        }
+        Broker { child, socket, dir }
+    }
+
+    fn listen(&self, device: &str) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .arg("listen")
+            .arg("--input-broker")
+            .arg(&self.socket)
+            .args(["--device", device])
+            .output()
+            .expect("run listen")
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+fn ask(socket: &Path, device: &str) -> String {
+    let mut stream = UnixStream::connect(socket).expect("connect to the broker");
+    writeln!(stream, "{}", device).expect("send request");
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).expect("read reply");
+    reply
+}
+
+#[test]
+fn broker_only_opens_event_nodes() {
+    let broker = Broker::start("nodes", None);
+    let listen = broker.listen("/etc/passwd");
+
+    assert!(!listen.status.success());
+    let stderr = String::from_utf8_lossy(&listen.stderr);
+    assert!(stderr.contains("input broker: /etc/passwd isn't an input event node"), "{}", stderr);
+}
+
+#[test]
+fn broker_refuses_other_users_and_executables() {
+    let broker = Broker::start("peers", Some("65534"));
+    let listen = broker.listen("/dev/input/event0");
+    // The test harness isn't nvidia-cc-rs, whoever runs it
+    let allowed = Broker::start("exe", None);
+    let reply = ask(&allowed.socket, "/dev/input/event0");
+
+    let stderr = String::from_utf8_lossy(&listen.stderr);
+    assert!(stderr.contains("isn't allowed"), "{}", stderr);
+    assert!(reply.starts_with("error only "), "{}", reply);
+}
+
+#[test]
+fn broker_needs_allowed_users() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["input-broker", "--socket", "/tmp/unused.sock"])
+        .output()
+        .expect("run input-broker");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("expects --allow-user"));
+}
@@ -0,0 +1,147 @@
+//! Event recording: `--record-to` and `--record-last` must keep what `listen`
+//! emitted, shown here with the replay backend.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+mod common;
+
+const RECORDED: &str = concat!(
+    r#"{"event_type":"KeyPress","key":"KeyA","text":"a"}"#,
+    "\n",
+    r#"{"event_type":"KeyRelease","key":"KeyA"}"#,
+    "\n",
+    // Keeps listen running while the test talks to it
+    r#"{"event_type":"KeyPress","key":"KeyB","delay_ms":2000}"#,
+    "\n",
+);
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = common::scratch_dir(&format!("journal-{}", name));
+    fs::write(dir.join("events.jsonl"), RECORDED).expect("write events");
+    dir
+}
+
+fn listen(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"));
+    command.args(["listen", "--backend", "replay", "--input"]).arg(dir.join("events.jsonl"));
+    command
+}
+
+fn event_types(ndjson: &str) -> Vec<String> {
+    ndjson
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("JSON line"))
+        .filter_map(|event| event["event_type"].as_str().map(str::to_string))
+        .filter(|event_type| event_type != "ClockSync")
+        .collect()
+}
+
+#[test]
+fn record_to_keeps_every_event() {
+    let dir = scratch_dir("record-to");
+    let output = listen(&dir)
+        .arg("--record-to")
+        .arg(dir.join("recording.ndjson"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    assert!(output.status.success(), "listen failed: {}", String::from_utf8_lossy(&output.stderr));
+    let recording = fs::read_to_string(dir.join("recording.ndjson")).expect("read recording");
+    fs::remove_dir_all(&dir).ok();
+    assert_eq!(event_types(&recording), ["Capabilities", "Environment", "KeyPress", "KeyRelease", "KeyPress"]);
+    assert_eq!(recording, String::from_utf8_lossy(&output.stdout));
+}
+
+#[cfg(unix)]
+#[test]
+fn recordings_are_private_and_leave_out_tokens() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("private");
+    let output = listen(&dir)
+        .args(["--http", "127.0.0.1:0", "--record-to"])
+        .arg(dir.join("recording.ndjson"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    assert!(output.status.success(), "listen failed: {}", String::from_utf8_lossy(&output.stderr));
+    let recording = fs::read_to_string(dir.join("recording.ndjson")).expect("read recording");
+    let mode = fs::metadata(dir.join("recording.ndjson")).expect("stat recording").permissions().mode();
+    fs::remove_dir_all(&dir).ok();
+    assert_eq!(mode & 0o777, 0o600);
+    assert!(event_types(&String::from_utf8_lossy(&output.stdout)).contains(&"HttpListening".to_string()));
+    assert_eq!(event_types(&recording), ["Capabilities", "Environment", "KeyPress", "KeyRelease", "KeyPress"]);
+    assert!(!recording.contains("token"), "{}", recording);
+}
+
+#[test]
+fn dump_writes_the_last_events() {
+    let dir = scratch_dir("dump");
+    let mut child = listen(&dir)
+        .args(["--record-last", "2"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run listen");
+    let mut stdin = child.stdin.take().expect("stdin");
+    let mut lines = BufReader::new(child.stdout.take().expect("stdout")).lines();
+    let mut next_event_type = || {
+        let line = lines.next().expect("listen keeps emitting").expect("read stdout");
+        let event: serde_json::Value = serde_json::from_str(&line).expect("JSON line");
+        (event["event_type"].as_str().unwrap_or_default().to_string(), event)
+    };
+    while next_event_type().0 != "KeyRelease" {}
+    writeln!(stdin, "dump {}", dir.join("dump.ndjson").display()).expect("send dump");
+    let dumped = loop {
+        let (event_type, event) = next_event_type();
+        if event_type == "EventsDumped" {
+            break event;
+        }
+    };
+    child.kill().ok();
+    child.wait().ok();
+    let dump = fs::read_to_string(dir.join("dump.ndjson")).expect("read dump");
+    fs::remove_dir_all(&dir).ok();
+    assert_eq!(dumped["events"], 2);
+    assert_eq!(event_types(&dump).last().map(String::as_str), Some("KeyRelease"));
+    assert_eq!(dump.lines().count(), 2);
+}
+
+#[test]
+fn compressed_recordings_replay() {
+    let dir = scratch_dir("zstd");
+    let recorded = listen(&dir)
+        .arg("--record-to")
+        .arg(dir.join("recording.ndjson.zst"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    assert!(recorded.status.success(), "listen failed: {}", String::from_utf8_lossy(&recorded.stderr));
+    let capabilities: serde_json::Value =
+        serde_json::from_str(String::from_utf8_lossy(&recorded.stdout).lines().next().unwrap()).unwrap();
+    if capabilities["zstd"] != true {
+        eprintln!("libzstd not installed, skipping");
+        fs::remove_dir_all(&dir).ok();
+        return;
+    }
+
+    let compressed = fs::read(dir.join("recording.ndjson.zst")).expect("read recording");
+    assert_eq!(&compressed[..4], [0x28, 0xb5, 0x2f, 0xfd], "zstd magic number");
+    let replayed = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--input"])
+        .arg(dir.join("recording.ndjson.zst"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("replay recording");
+    fs::remove_dir_all(&dir).ok();
+    assert!(replayed.status.success(), "replay failed: {}", String::from_utf8_lossy(&replayed.stderr));
+    let keys: Vec<String> = event_types(&String::from_utf8_lossy(&replayed.stdout))
+        .into_iter()
+        .filter(|event_type| event_type.starts_with("Key"))
+        .collect();
+    assert_eq!(keys, ["KeyPress", "KeyRelease", "KeyPress"]);
+}
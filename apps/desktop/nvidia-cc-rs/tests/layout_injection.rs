@@ -0,0 +1,78 @@
+//! Layout-aware injection: `write --dry-run` prints the key taps a layout needs,
+//! which must use AltGr and dead keys where the layout requires them.
+//!
+//! Skipped when libxkbcommon (or the xkb data) isn't installed.
+#![cfg(target_os = "linux")]
+
+use std::process::Command;
+
+/// Key taps for `text` on `layout`, or None when layouts can't be loaded here
+fn plan(layout: &str, text: &str) -> Option<Vec<String>> {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["write", "--dry-run", text])
+        .env("XKB_DEFAULT_LAYOUT", layout)
+        .env_remove("XKB_DEFAULT_VARIANT")
+        .env_remove("XKB_DEFAULT_OPTIONS")
+        .env_remove("DISPLAY")
+        .output()
+        .expect("run write --dry-run");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("Layout-aware key names unavailable") {
+        eprintln!("skipping: {}", stderr.trim());
+        return None;
+    }
+    assert!(output.status.success(), "write --dry-run failed on {}: {}", layout, stderr);
+    Some(String::from_utf8_lossy(&output.stdout).lines().map(str::to_string).collect())
+}
+
+fn assert_plan(layout: &str, text: &str, expected: &[&str]) {
+    if let Some(actual) = plan(layout, text) {
+        assert_eq!(actual, expected, "{:?} on {}", text, layout);
+    }
+}
+
+#[test]
+fn german_layout() {
+    // QWERTZ, AltGr for €, dead acute (the key right of ß) for é, Shift for capitals
+    assert_plan("de", "z", &["KeyY"]);
+    assert_plan("de", "€", &["AltGr+KeyE"]);
+    assert_plan("de", "é", &["Equal", "KeyE"]);
+    assert_plan("de", "ß", &["Minus"]);
+    assert_plan("de", "Ä", &["ShiftLeft+Quote"]);
+}
+
+#[test]
+fn french_layout() {
+    // AZERTY, é on its own key, AltGr for € and @, dead circumflex for ê
+    assert_plan("fr", "a", &["KeyQ"]);
+    assert_plan("fr", "é", &["Num2"]);
+    assert_plan("fr", "€", &["AltGr+KeyE"]);
+    assert_plan("fr", "@", &["AltGr+Num0"]);
+    assert_plan("fr", "ê", &["LeftBracket", "KeyE"]);
+    assert_plan("fr", "ë", &["ShiftLeft+LeftBracket", "KeyE"]);
+}
+
+#[test]
+fn spanish_layout() {
+    // ñ on its own key, dead acute for á, dead circumflex typed alone via space
+    assert_plan("es", "ñ", &["SemiColon"]);
+    assert_plan("es", "á", &["Quote", "KeyA"]);
+    assert_plan("es", "€", &["AltGr+KeyE"]);
+    assert_plan("es", "^", &["ShiftLeft+LeftBracket", "Space"]);
+}
+
+#[test]
+fn untypeable_character_is_an_error() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["write", "--dry-run", "é"])
+        .env("XKB_DEFAULT_LAYOUT", "us")
+        .env_remove("DISPLAY")
+        .output()
+        .expect("run write --dry-run");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("Layout-aware key names unavailable") {
+        return;
+    }
+    assert!(!output.status.success());
+    assert!(stderr.contains("can't be typed on keyboard layout us"), "{}", stderr);
+}
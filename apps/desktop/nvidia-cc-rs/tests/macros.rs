@@ -0,0 +1,53 @@
+//! Macro files: `replay` must reject a bad file or option before injecting anything.
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn replay(test: &str, contents: &str, options: &[&str]) -> Output {
+    let path = std::env::temp_dir().join(format!("nvidia-cc-rs-macro-{}-{}.json", test, std::process::id()));
+    fs::write(&path, contents).expect("write macro");
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("replay")
+        .arg(&path)
+        .args(options)
+        .output()
+        .expect("run replay");
+    fs::remove_file(&path).ok();
+    output
+}
+
+fn error_text(output: &Output) -> String {
+    assert_eq!(output.status.code(), Some(1), "replay was accepted");
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+const VALID: &str = r#"{"version": 1, "steps": [
+    {"at_ms": 0, "type": "key_down", "key": "ControlLeft"},
+    {"at_ms": 30, "type": "key_up", "key": "ControlLeft"}]}"#;
+
+#[test]
+fn invalid_steps_are_rejected() {
+    for (test, steps, expected) in [
+        ("key", r#"[{"at_ms": 0, "type": "key_down", "key": "NoSuchKey"}]"#, "step 0"),
+        ("button", r#"[{"at_ms": 0, "type": "mouse_down", "button": "left"}, {"at_ms": 5, "type": "mouse_up", "button": "thumb"}]"#, "step 1"),
+        ("type", r#"[{"at_ms": 0, "type": "teleport"}]"#, "teleport"),
+    ] {
+        let output = replay(test, &format!(r#"{{"version": 1, "steps": {}}}"#, steps), &[]);
+        let error = error_text(&output);
+        assert!(error.contains(expected), "{}: {}", test, error);
+    }
+}
+
+#[test]
+fn unknown_versions_are_rejected() {
+    let output = replay("version", &VALID.replace(r#""version": 1"#, r#""version": 2"#), &[]);
+    assert!(error_text(&output).contains("unsupported macro version 2"));
+}
+
+#[test]
+fn speed_must_be_positive() {
+    for speed in ["0", "-1", "fast"] {
+        let output = replay("speed", VALID, &["--speed", speed]);
+        assert!(error_text(&output).contains("Invalid --speed value"));
+    }
+}
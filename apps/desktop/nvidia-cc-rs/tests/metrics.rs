@@ -0,0 +1,98 @@
+//! `listen --metrics-addr`: a running listener must answer Prometheus scrapes.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+mod common;
+
+/// `GET path` against `addr`, returning the whole response
+fn get(addr: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).expect("connect to metrics");
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr).expect("send request");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("read response");
+    response
+}
+
+/// A listener up with `--metrics-addr 127.0.0.1:0` and `path` as PATH, and the address it serves
+fn start(dir: &Path, path: &OsStr) -> (Child, String) {
+    // The last release waits, keeping the listener up while it is scraped
+    let recorded = [
+        r#"{"event_type":"KeyPress","key":"KeyA"}"#,
+        r#"{"event_type":"KeyRelease","key":"KeyA","delay_ms":3000}"#,
+    ];
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--metrics-addr", "127.0.0.1:0", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .env("PATH", path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run listen");
+    let stdout = BufReader::new(child.stdout.take().expect("stdout"));
+    let mut addr = None;
+    for line in stdout.lines() {
+        let event: serde_json::Value = serde_json::from_str(&line.expect("read stdout")).expect("JSON line");
+        if event["event_type"] == "Capabilities" {
+            assert_eq!(event["metrics"], true);
+        }
+        if event["event_type"] == "MetricsListening" {
+            addr = event["addr"].as_str().map(str::to_string);
+            break;
+        }
+    }
+    (child, addr.expect("MetricsListening is emitted"))
+}
+
+#[test]
+fn metrics_are_served_in_prometheus_format() {
+    let dir = common::scratch_dir("metrics");
+    let (mut child, addr) = start(&dir, &std::env::var_os("PATH").unwrap_or_default());
+
+    let response = get(&addr, "/metrics");
+    let missing = get(&addr, "/");
+    // A request line as long as the whole head limit, with nothing left unread
+    let mut oversized = String::new();
+    let mut stream = TcpStream::connect(&addr).expect("connect to metrics");
+    write!(stream, "GET /metrics?{}", "a".repeat(8 * 1024 - 13)).expect("send request");
+    stream.read_to_string(&mut oversized).expect("read response");
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4"), "{}", response);
+    assert!(response.contains("# TYPE nvidia_cc_events_emitted_total counter"), "{}", response);
+    assert!(response.contains("\nnvidia_cc_paused 0\n"), "{}", response);
+    assert!(response.contains("nvidia_cc_writes_total{result=\"ok\"} 0"), "{}", response);
+    assert!(response.contains("nvidia_cc_input_latency_seconds_bucket{le=\"+Inf\"}"), "{}", response);
+    assert!(missing.starts_with("HTTP/1.1 404"), "{}", missing);
+    assert!(oversized.starts_with("HTTP/1.1 400"), "{}", oversized);
+}
+
+#[cfg(unix)]
+#[test]
+fn gpu_telemetry_is_read_with_nvidia_smi() {
+    let dir = common::scratch_dir("metrics-gpu");
+    common::fake_tool(&dir, "nvidia-smi", "#!/bin/sh\necho '0, NVIDIA GeForce RTX 4090, 45, 12, 1024, 24564, [N/A]'\n");
+    let (mut child, addr) = start(&dir, dir.as_os_str());
+
+    let response = get(&addr, "/metrics");
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    let labels = r#"{gpu="0",name="NVIDIA GeForce RTX 4090"}"#;
+    assert!(response.contains(&format!("nvidia_cc_gpu_temperature_celsius{} 45\n", labels)), "{}", response);
+    assert!(response.contains(&format!("nvidia_cc_gpu_utilization_ratio{} 0.12\n", labels)), "{}", response);
+    assert!(response.contains(&format!("nvidia_cc_gpu_memory_used_bytes{} 1073741824\n", labels)), "{}", response);
+    // Not reported, so the family is left out
+    assert!(!response.contains("nvidia_cc_gpu_power_watts"), "{}", response);
+}
@@ -0,0 +1,135 @@
+//! `--output msgpack`: length-prefixed MessagePack frames carrying the same
+//! fields as the JSON lines.
+
+use serde_json::{json, Map, Value};
+use std::fs;
+use std::process::{Command, Stdio};
+
+mod common;
+
+/// Just enough of a MessagePack decoder for what the helper writes
+struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> &[u8] {
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        taken
+    }
+
+    fn uint(&mut self, len: usize) -> u64 {
+        self.take(len).iter().fold(0, |value, &byte| value << 8 | u64::from(byte))
+    }
+
+    fn string(&mut self, len: usize) -> Value {
+        Value::String(String::from_utf8(self.take(len).to_vec()).expect("UTF-8 string"))
+    }
+
+    fn array(&mut self, len: usize) -> Value {
+        Value::Array((0..len).map(|_| self.value()).collect())
+    }
+
+    fn map(&mut self, len: usize) -> Value {
+        let mut map = Map::new();
+        for _ in 0..len {
+            let Value::String(key) = self.value() else { panic!("non-string key") };
+            map.insert(key, self.value());
+        }
+        Value::Object(map)
+    }
+
+    fn value(&mut self) -> Value {
+        let tag = self.take(1)[0];
+        match tag {
+            0x00..=0x7f => json!(tag),
+            0x80..=0x8f => self.map(usize::from(tag & 0x0f)),
+            0x90..=0x9f => self.array(usize::from(tag & 0x0f)),
+            0xa0..=0xbf => self.string(usize::from(tag & 0x1f)),
+            0xc0 => Value::Null,
+            0xc2 => json!(false),
+            0xc3 => json!(true),
+            0xcb => json!(f64::from_bits(self.uint(8))),
+            0xcc => json!(self.uint(1)),
+            0xcd => json!(self.uint(2)),
+            0xce => json!(self.uint(4)),
+            0xcf => json!(self.uint(8)),
+            0xd9 => {
+                let len = self.uint(1) as usize;
+                self.string(len)
+            }
+            0xda => {
+                let len = self.uint(2) as usize;
+                self.string(len)
+            }
+            0xde => {
+                let len = self.uint(2) as usize;
+                self.map(len)
+            }
+            0xe0..=0xff => json!(tag as i8),
+            _ => panic!("unexpected MessagePack tag {:#x}", tag),
+        }
+    }
+}
+
+/// Events decoded from `listen --output msgpack` replaying a key tap
+fn frames(extra: &[&str]) -> Vec<Value> {
+    let dir = common::scratch_dir(&format!("msgpack-{}", extra.len()));
+    let recorded = [
+        r#"{"event_type":"KeyPress","key":"KeyA","text":"a"}"#,
+        r#"{"event_type":"KeyRelease","key":"KeyA","hold_ms":70,"synthetic":true}"#,
+    ];
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--output", "msgpack"])
+        .args(extra)
+        .arg("--input")
+        .arg(dir.join("events.jsonl"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    fs::remove_dir_all(&dir).ok();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut stdout = &output.stdout[..];
+    let mut events = Vec::new();
+    while !stdout.is_empty() {
+        let (length, rest) = stdout.split_at(4);
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+        let (frame, rest) = rest.split_at(length);
+        let mut decoder = Decoder { bytes: frame };
+        events.push(decoder.value());
+        assert!(decoder.bytes.is_empty(), "one object per frame");
+        stdout = rest;
+    }
+    events
+}
+
+#[test]
+fn frames_carry_the_json_fields() {
+    let events = frames(&[]);
+    assert_eq!(events[0]["event_type"], "Capabilities");
+    assert_eq!(events[0]["output"], "msgpack");
+    assert_eq!(events[0]["seq"], 1);
+
+    let press = events.iter().find(|event| event["event_type"] == "KeyPress").expect("press");
+    assert_eq!(press["key"], "KeyA");
+    assert_eq!(press["text"], "a");
+    assert!(press["time"]["secs_since_epoch"].as_u64().unwrap() > 1_600_000_000);
+    assert!(press.get("synthetic").is_none(), "skipped fields are left out: {}", press);
+
+    let release = events.iter().find(|event| event["event_type"] == "KeyRelease").expect("release");
+    assert_eq!(release["synthetic"], true);
+    assert_eq!(release["hold_ms"], 70);
+    assert_eq!(release["seq"].as_u64(), press["seq"].as_u64().map(|seq| seq + 1));
+}
+
+#[test]
+fn legacy_format_and_time_format_apply_too() {
+    let events = frames(&["--legacy-format", "--time-format", "rfc3339"]);
+    let press = events.iter().find(|event| event["event_type"] == "KeyPress").expect("press");
+    assert_eq!(press["data"], r#"{"key":"KeyA"}"#);
+    assert!(press["time"].as_str().unwrap().ends_with('Z'), "{}", press);
+}
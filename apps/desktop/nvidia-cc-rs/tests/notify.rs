@@ -0,0 +1,80 @@
+//! `notify` through notify-send, or gdbus when notify-send fails, both
+//! stand-in scripts here that log their arguments one per line.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+mod common;
+
+/// A script logging its arguments to `<dir>/<name>.log`, then exiting with `status`
+fn logging_tool(dir: &Path, name: &str, status: u8) {
+    let script = format!("#!/bin/sh\nprintf '%s\\n' \"$@\" > \"$(dirname \"$0\")/{}.log\"\nexit {}\n", name, status);
+    common::fake_tool(dir, name, &script);
+}
+
+fn notify(dir: &Path, args: &[&str]) -> Output {
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("notify")
+        .args(args)
+        .env("PATH", path)
+        .output()
+        .expect("run notify")
+}
+
+#[test]
+fn notify_send_gets_title_body_and_icon() {
+    let dir = common::scratch_dir("notify-send");
+    logging_tool(&dir, "notify-send", 0);
+    let output = notify(&dir, &["--title", "Microphone is live", "--body", "-not a flag", "--icon", "audio-input"]);
+    let log = fs::read_to_string(dir.join("notify-send.log"));
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        log.expect("notify-send ran"),
+        "--app-name=NVIDIA Control Center\n--icon=audio-input\n--\nMicrophone is live\n-not a flag\n"
+    );
+}
+
+#[test]
+fn gdbus_is_the_fallback() {
+    let dir = common::scratch_dir("notify-gdbus");
+    logging_tool(&dir, "notify-send", 1);
+    logging_tool(&dir, "gdbus", 0);
+    let output = notify(&dir, &["--title", "Say \"hi\""]);
+    let log = fs::read_to_string(dir.join("gdbus.log"));
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let log = log.expect("gdbus ran");
+    let args: Vec<&str> = log.lines().collect();
+    assert_eq!(args[..2], ["call", "--session"]);
+    assert_eq!(args[5..], ["\"NVIDIA Control Center\"", "0", "\"\"", r#""Say \"hi\"""#, "\"\"", "[]", "{}", "-1"]);
+}
+
+#[test]
+fn title_is_required() {
+    let dir = common::scratch_dir("notify-untitled");
+    logging_tool(&dir, "notify-send", 0);
+    let output = notify(&dir, &["--body", "no title"]);
+    let ran = dir.join("notify-send.log").exists();
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("notify expects --title <title>"));
+    assert!(!ran);
+}
+
+#[test]
+fn driver_guidance_is_refused_off_windows() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--driver-guidance"])
+        .output()
+        .expect("run listen");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--driver-guidance is only supported on Windows"));
+}
@@ -0,0 +1,65 @@
+//! `--output csv|pretty`: alternative stdout formats for people, JSON staying the default.
+
+use std::fs;
+use std::process::{Command, Stdio};
+
+mod common;
+
+/// stdout of `listen --output <format>` replaying a key tap
+fn listen(format: &str) -> String {
+    let dir = common::scratch_dir(&format!("output-{}", format));
+    let recorded = [
+        r#"{"event_type":"KeyPress","key":"Comma","text":","}"#,
+        r#"{"event_type":"KeyRelease","key":"Comma","synthetic":true}"#,
+    ];
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--output", format, "--input"])
+        .arg(dir.join("events.jsonl"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    fs::remove_dir_all(&dir).ok();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn csv_has_a_header_and_fixed_columns() {
+    let stdout = listen("csv");
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "seq,mono_us,time,event_type,key,name,text,synthetic,fields");
+    let cells = |event_type: &str| -> Vec<String> {
+        let line = lines.iter().find(|line| line.contains(&format!(",{},", event_type))).expect("event line");
+        line.split(',').map(str::to_string).collect()
+    };
+    // The comma typed is quoted, so the press still has nine columns
+    let press = cells("KeyPress");
+    assert_eq!(&press[3..5], ["KeyPress", "Comma"]);
+    assert!(stdout.contains(",KeyPress,Comma,,\",\",,"), "{}", stdout);
+    let release = cells("KeyRelease");
+    assert_eq!(release.len(), 9, "{:?}", release);
+    assert_eq!(release[7], "true");
+    assert!(release[2].contains('.'), "time in seconds: {:?}", release);
+}
+
+#[test]
+fn pretty_lines_are_aligned_and_uncolored_in_a_pipe() {
+    let stdout = listen("pretty");
+    assert!(!stdout.contains('\x1b'), "{}", stdout);
+    let press = stdout.lines().find(|line| line.contains("KeyPress")).expect("press line");
+    let release = stdout.lines().find(|line| line.contains("KeyRelease")).expect("release line");
+    assert_eq!(press.find("Comma"), release.find("Comma"), "{}", stdout);
+    assert!(press.ends_with("text=,"), "{}", press);
+    assert!(release.ends_with("synthetic=true"), "{}", release);
+}
+
+#[test]
+fn json_stays_the_default() {
+    let stdout = listen("json");
+    for line in stdout.lines() {
+        let event: serde_json::Value = serde_json::from_str(line).expect("JSON line");
+        assert!(event["event_type"].is_string());
+    }
+}
@@ -0,0 +1,33 @@
+//! `--parent-pid`: only pids kill() can tell the death of are accepted.
+
+use std::fs;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+
+fn listen(parent_pid: &str, input: &std::path::Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--parent-pid", parent_pid, "--input"])
+        .arg(input)
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen")
+}
+
+#[test]
+fn refuses_pids_that_would_always_look_alive() {
+    let dir = common::scratch_dir("parent");
+    let input = dir.join("events.jsonl");
+    fs::write(&input, "").expect("write events");
+    // 0 is our own process group, and anything past i32::MAX wraps to -1 or another group
+    let rejected: Vec<_> = ["0", "2147483648", "4294967295"].iter().map(|pid| (pid, listen(pid, &input))).collect();
+    let accepted = listen(&std::process::id().to_string(), &input);
+    fs::remove_dir_all(&dir).ok();
+
+    for (pid, output) in rejected {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "--parent-pid {} was accepted", pid);
+        assert!(stderr.contains(&format!("Invalid --parent-pid value: {}", pid)), "{}", stderr);
+    }
+    assert!(accepted.status.success(), "{}", String::from_utf8_lossy(&accepted.stderr));
+}
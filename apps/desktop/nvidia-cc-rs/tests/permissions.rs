@@ -0,0 +1,33 @@
+//! `install-permissions`, short of writing to /etc: the rule it installs and
+//! its command line.
+#![cfg(target_os = "linux")]
+
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(args)
+        .output()
+        .expect("run nvidia-cc-rs")
+}
+
+#[test]
+fn print_gives_the_uaccess_rule() {
+    let print = run(&["install-permissions", "--print"]);
+
+    assert!(print.status.success(), "{}", String::from_utf8_lossy(&print.stderr));
+    let rule = String::from_utf8_lossy(&print.stdout);
+    let rules: Vec<&str> = rule.lines().filter(|line| !line.starts_with('#')).collect();
+    assert_eq!(rules.len(), 2, "{}", rule);
+    assert!(rules[0].contains(r#"SUBSYSTEM=="input""#) && rules[0].ends_with(r#"TAG+="uaccess""#), "{}", rule);
+    assert!(rules[1].contains(r#"KERNEL=="uinput""#) && rules[1].ends_with(r#"TAG+="uaccess""#), "{}", rule);
+}
+
+#[test]
+fn rejects_unknown_options() {
+    let unknown = run(&["install-permissions", "--user", "me"]);
+
+    assert!(!unknown.status.success());
+    let stderr = String::from_utf8_lossy(&unknown.stderr);
+    assert!(stderr.contains("Expected: install-permissions [--remove|--print]"), "{}", stderr);
+}
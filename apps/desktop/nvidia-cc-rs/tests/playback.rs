@@ -0,0 +1,98 @@
+//! Replay capture backend: `listen --backend replay` must take recorded key
+//! events through the hotkey engine without any input device.
+
+use std::fs;
+use std::process::{Command, Stdio};
+
+mod common;
+
+const HOTKEYS: &str = r#"[{"id": "dictate", "keys": "ControlLeft+Space"}]"#;
+
+/// Events emitted by `listen` replaying `recorded` lines
+fn replay(name: &str, recorded: &[&str]) -> Result<Vec<serde_json::Value>, String> {
+    let dir = common::scratch_dir(&format!("playback-{}", name));
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+    fs::write(dir.join("hotkeys.json"), HOTKEYS).expect("write hotkeys");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .arg("--hotkeys")
+        .arg(dir.join("hotkeys.json"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    fs::remove_dir_all(&dir).ok();
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("listen emits JSON lines"))
+        .collect())
+}
+
+/// `<event_type> <key or id>` for every key and hotkey event, leaving out the handshake and clock syncs
+fn summaries(events: &[serde_json::Value]) -> Vec<String> {
+    events
+        .iter()
+        .filter(|event| {
+            let kind = event["event_type"].as_str().unwrap_or_default();
+            kind.starts_with("Key") || kind.starts_with("Hotkey")
+        })
+        .map(|event| {
+            let label = event["key"].as_str().or(event["id"].as_str()).unwrap_or_default();
+            format!("{} {}", event["event_type"].as_str().unwrap_or_default(), label)
+        })
+        .collect()
+}
+
+#[test]
+fn recorded_keys_trigger_hotkeys() {
+    let recorded = [
+        r#"{"seq":1,"mono_us":1000,"event_type":"Capabilities"}"#,
+        r#"{"seq":2,"mono_us":2000,"event_type":"KeyPress","key":"ControlLeft"}"#,
+        r#"{"seq":3,"mono_us":3000,"event_type":"KeyPress","key":"Space","text":" "}"#,
+        r#"{"seq":4,"mono_us":4000,"event_type":"KeyRelease","key":"Space"}"#,
+        r#"{"seq":5,"mono_us":5000,"event_type":"KeyRelease","key":"ControlLeft"}"#,
+    ];
+    let events = replay("hotkey", &recorded).expect("replay succeeds");
+    assert_eq!(events[0]["event_type"], "Capabilities");
+    assert_eq!(events[0]["capture_backend"], "replay");
+    let summaries = summaries(&events);
+    assert_eq!(&summaries[..3], ["KeyPress ControlLeft", "KeyPress Space", "HotkeyTriggered dictate"]);
+    assert!(summaries.contains(&"HotkeyReleased dictate".to_string()), "{:?}", summaries);
+}
+
+#[test]
+fn injected_keys_do_not_trigger_hotkeys() {
+    let recorded = [
+        r#"{"event_type":"KeyPress","key":"ControlLeft","synthetic":true}"#,
+        r#"{"event_type":"KeyPress","key":"Space","synthetic":true,"delay_ms":10}"#,
+        r#"{"event_type":"KeyRelease","key":"Space","synthetic":true}"#,
+        r#"{"event_type":"KeyRelease","key":"ControlLeft","synthetic":true}"#,
+    ];
+    let events = replay("synthetic", &recorded).expect("replay succeeds");
+    let summaries = summaries(&events);
+    assert_eq!(
+        summaries,
+        ["KeyPress ControlLeft", "KeyPress Space", "KeyRelease Space", "KeyRelease ControlLeft"]
+    );
+    let keys: Vec<_> = events.iter().filter(|event| event["key"].is_string()).collect();
+    assert!(keys.iter().all(|event| event["synthetic"] == true));
+}
+
+#[test]
+fn replay_requires_an_input() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay"])
+        .output()
+        .expect("run listen");
+    assert!(!output.status.success());
+}
+
+#[test]
+fn malformed_lines_are_reported() {
+    let error = replay("malformed", &[r#"{"event_type":"KeyPress"}"#]).expect_err("replay fails");
+    assert!(error.contains("events.jsonl:1"), "{}", error);
+}
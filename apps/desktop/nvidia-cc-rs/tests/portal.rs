@@ -0,0 +1,118 @@
+//! Running as a Flatpak (`FLATPAK_ID`) without input devices: capture and
+//! injection go to the desktop portals, asked on a private session bus that
+//! has none, so they fail naming the portal they needed.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+
+mod common;
+
+struct Bus {
+    daemon: Child,
+    address: String,
+    dir: PathBuf,
+}
+
+impl Bus {
+    /// None when dbus-daemon isn't installed
+    fn start(name: &str) -> Option<Bus> {
+        let dir = common::scratch_dir(&format!("portal-{}", name));
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address=1"])
+            .arg(format!("--address=unix:path={}", dir.join("bus").display()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().expect("stdout")).read_line(&mut address).expect("read bus address");
+        Some(Bus {
+            daemon,
+            address: address.trim().to_string(),
+            dir,
+        })
+    }
+
+    /// Run the helper as a Flatpak would, on this bus and with no display
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .args(args)
+            .env("FLATPAK_ID", "io.github.aj47.NvidiaCC")
+            .env("DBUS_SESSION_BUS_ADDRESS", &self.address)
+            .env("XDG_DATA_HOME", &self.dir)
+            .env_remove("DISPLAY")
+            .env_remove("WAYLAND_DISPLAY")
+            .stdin(Stdio::null())
+            .output()
+            .expect("run nvidia-cc-rs")
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        self.daemon.kill().ok();
+        self.daemon.wait().ok();
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// Whether some input device can be opened here, which keeps the helper off the portals
+fn devices_readable() -> bool {
+    fs::read_dir("/dev/input").is_ok_and(|entries| {
+        entries.filter_map(|entry| entry.ok()).any(|entry| {
+            entry.file_name().to_string_lossy().starts_with("event") && fs::File::open(entry.path()).is_ok()
+        })
+    })
+}
+
+/// The bus to run against, unless this machine can't stand in for a sandbox
+fn confined_bus(name: &str) -> Option<Bus> {
+    if devices_readable() {
+        eprintln!("input devices are readable here, skipping");
+        return None;
+    }
+    let bus = Bus::start(name);
+    if bus.is_none() {
+        eprintln!("dbus-daemon isn't installed, skipping");
+    }
+    bus
+}
+
+#[test]
+fn listen_asks_the_global_shortcuts_portal() {
+    let Some(bus) = confined_bus("listen") else { return };
+    let output = bus.run(&["listen"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("org.freedesktop.portal.GlobalShortcuts.CreateSession failed"), "{}", stderr);
+    assert!(!stderr.contains("/dev/input"), "{}", stderr);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let capabilities: serde_json::Value =
+        serde_json::from_str(stdout.lines().next().expect("Capabilities comes first")).expect("JSON line");
+    assert_eq!(capabilities["capture_backend"], "portal");
+}
+
+#[test]
+fn injection_tries_the_remote_desktop_portal_first() {
+    let Some(bus) = confined_bus("write") else { return };
+    let output = bus.run(&["write", "hi"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let expected = "No injection backend worked (portal: org.freedesktop.portal.RemoteDesktop.CreateSession failed";
+    assert!(stderr.contains(expected), "{}", stderr);
+
+    let report = bus.run(&["backends"]);
+    let report: serde_json::Value = serde_json::from_slice(&report.stdout).expect("backends prints JSON");
+    let portal = report["inject"]
+        .as_array()
+        .and_then(|entries| entries.iter().find(|entry| entry["name"] == "portal"))
+        .expect("portal is listed");
+    assert_eq!(portal["usable"], false);
+    let reason = portal["reason"].as_str().unwrap_or_default();
+    assert!(reason.starts_with("the RemoteDesktop portal isn't available"), "{}", reason);
+}
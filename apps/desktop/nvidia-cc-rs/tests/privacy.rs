@@ -0,0 +1,97 @@
+//! Privacy modes: key events replayed with `decode --privacy` must only name
+//! the keys a hotkey uses.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::process::Command;
+
+mod common;
+
+const KEY_LEFTCTRL: u16 = 29;
+const KEY_A: u16 = 30;
+const KEY_SPACE: u16 = 57;
+
+const HOTKEYS: &str = r#"[{"id": "dictate", "keys": "ControlLeft+Space"}]"#;
+
+/// Events emitted for typing A, then Ctrl+Space, in `mode`
+fn decode(mode: &str) -> Vec<serde_json::Value> {
+    let dir = common::scratch_dir(&format!("privacy-{}", mode));
+    let dump: String = [(KEY_A, 1), (KEY_A, 0), (KEY_LEFTCTRL, 1), (KEY_SPACE, 1), (KEY_SPACE, 0), (KEY_LEFTCTRL, 0)]
+        .iter()
+        .enumerate()
+        .map(|(index, (code, value))| {
+            format!("Event: time 1700000000.{:06}, type 1 (EV_KEY), code {}, value {}\n", index * 10_000, code, value)
+        })
+        .collect();
+    fs::write(dir.join("keys.evtest"), dump).expect("write dump");
+    fs::write(dir.join("hotkeys.json"), HOTKEYS).expect("write hotkeys");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("decode")
+        .arg(dir.join("keys.evtest"))
+        .arg("--hotkeys")
+        .arg(dir.join("hotkeys.json"))
+        .args(["--privacy", mode])
+        .output()
+        .expect("run decode");
+    fs::remove_dir_all(&dir).ok();
+    assert!(output.status.success(), "decode failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("decode emits JSON lines"))
+        .collect()
+}
+
+/// `<event_type> <key>` for every event, the key left out when there is none
+fn summaries(events: &[serde_json::Value]) -> Vec<String> {
+    events
+        .iter()
+        .map(|event| {
+            let event_type = event["event_type"].as_str().unwrap_or_default();
+            match event["key"].as_str() {
+                Some(key) => format!("{} {}", event_type, key),
+                None => event_type.to_string(),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn allowlist_only_names_hotkey_keys() {
+    let events = decode("allowlist");
+    assert_eq!(
+        summaries(&events),
+        [
+            "KeyActivity",
+            "KeyActivity",
+            "KeyPress ControlLeft",
+            "KeyPress Space",
+            "HotkeyTriggered",
+            "KeyRelease Space",
+            "HotkeyReleased",
+            "KeyRelease ControlLeft",
+        ]
+    );
+    assert_eq!(events[0]["pressed"], true);
+    assert_eq!(events[1]["pressed"], false);
+}
+
+#[test]
+fn hash_replaces_other_keys() {
+    let events = decode("hash");
+    let first = events[0]["key"].as_str().expect("hashed key");
+    assert!(first.starts_with("h:") && first.len() == 18, "unexpected hash {}", first);
+    assert_eq!(events[1]["key"].as_str(), Some(first), "one key hashes the same within a run");
+    assert!(events[0].get("name").is_none());
+    assert_eq!(summaries(&events[2..4]), ["KeyPress ControlLeft", "KeyPress Space"]);
+}
+
+#[test]
+fn unknown_mode_is_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--privacy", "redact"])
+        .output()
+        .expect("run listen");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown privacy mode"));
+}
@@ -0,0 +1,56 @@
+//! Correlation ids: events caused by a stdin command led by `--id` must carry
+//! it as `request_id`, and no other event may.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+mod common;
+
+#[test]
+fn command_results_echo_the_request_id() {
+    let dir = common::scratch_dir("request-ids");
+    // A key press at once, then a wait keeping listen running while commands are sent
+    let recorded = [
+        r#"{"event_type":"KeyPress","key":"KeyA"}"#,
+        r#"{"event_type":"KeyRelease","key":"KeyA","delay_ms":3000}"#,
+    ];
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run listen");
+    let mut stdin = child.stdin.take().expect("stdin");
+    let mut events = BufReader::new(child.stdout.take().expect("stdout"))
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(&line.expect("read stdout")).expect("JSON line"));
+    let mut next = |event_type: &str| {
+        events.find(|event| event["event_type"] == event_type).expect("listen keeps emitting")
+    };
+
+    let press = next("KeyPress");
+    assert!(press.get("request_id").is_none(), "{}", press);
+
+    writeln!(stdin, "--id one stats").unwrap();
+    writeln!(stdin, "--id two hotkey remove nonexistent").unwrap();
+    writeln!(stdin, "--id three pause").unwrap();
+    writeln!(stdin, "stats").unwrap();
+    let stats = next("Stats");
+    let error = next("Error");
+    let paused = next("Paused");
+    let unlabelled = next("Stats");
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(stats["request_id"], "one");
+    assert_eq!(error["error"], "UnknownHotkey");
+    assert_eq!(error["request_id"], "two");
+    assert_eq!(paused["request_id"], "three");
+    assert!(unlabelled.get("request_id").is_none(), "{}", unlabelled);
+}
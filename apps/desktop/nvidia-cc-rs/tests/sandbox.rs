@@ -0,0 +1,102 @@
+//! `listen --sandbox`, through the replay backend since there are no devices
+//! here: events still come out once it's applied, and options and stdin
+//! commands needing more than stdout are refused.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+mod common;
+
+#[test]
+fn replays_under_the_sandbox() {
+    let dir = common::scratch_dir("sandbox");
+    let recorded = [
+        r#"{"event_type":"KeyPress","key":"ControlLeft"}"#,
+        r#"{"event_type":"KeyRelease","key":"ControlLeft","delay_ms":10}"#,
+    ];
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--sandbox", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    fs::remove_dir_all(&dir).ok();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(stderr.contains("Sandboxed listener (seccomp"), "{}", stderr);
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("listen emits JSON lines"))
+        .collect();
+    assert_eq!(events[0]["sandbox"], true);
+    let keys: Vec<_> = events.iter().filter_map(|event| event["key"].as_str()).collect();
+    assert_eq!(keys, ["ControlLeft", "ControlLeft"]);
+}
+
+#[test]
+fn refuses_options_needing_more_than_stdout() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--sandbox", "--socket", "/tmp/unused.sock", "--metrics-addr", "127.0.0.1:0"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--sandbox can't be combined with --socket, --metrics-addr"), "{}", stderr);
+}
+
+#[test]
+fn refuses_record_last_whose_dumps_it_would_block() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--sandbox", "--record-last", "10"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--sandbox can't be combined with --record-last"), "{}", stderr);
+}
+
+#[test]
+fn answers_commands_it_blocks_with_an_error() {
+    let dir = common::scratch_dir("sandbox-commands");
+    // Keeps listen running while the test talks to it
+    fs::write(dir.join("events.jsonl"), r#"{"event_type":"KeyPress","key":"KeyA","delay_ms":3000}"#)
+        .expect("write events");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--sandbox", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run listen");
+    // Kept open to the end: the refusals are logged there too
+    let mut stderr = BufReader::new(child.stderr.take().expect("stderr")).lines();
+    let applied = stderr.by_ref().map_while(Result::ok).any(|line| line.starts_with("Sandboxed listener"));
+    assert!(applied, "the sandbox is applied");
+
+    let mut stdin = child.stdin.take().expect("stdin");
+    writeln!(stdin, "--id w write \"hi\"\n--id l leds set caps on\n--id s stats").expect("send commands");
+    let events: Vec<serde_json::Value> = BufReader::new(child.stdout.take().expect("stdout"))
+        .lines()
+        .map(|line| serde_json::from_str(&line.expect("read stdout")).expect("JSON line"))
+        .take_while(|event: &serde_json::Value| event["event_type"] != "Stats")
+        .filter(|event| event["request_id"].is_string())
+        .collect();
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    let errors: Vec<_> = events.iter().map(|event| (event["request_id"].as_str(), event["error"].as_str())).collect();
+    assert_eq!(errors, [(Some("w"), Some("Sandboxed")), (Some("l"), Some("Sandboxed"))]);
+    let message = events[1]["message"].as_str().unwrap_or_default();
+    assert!(message.starts_with("leds set isn't available with --sandbox"), "{}", message);
+}
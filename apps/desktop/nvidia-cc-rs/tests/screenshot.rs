@@ -0,0 +1,86 @@
+//! `screenshot` through grim on Wayland and maim on X11, here stand-in
+//! scripts that answer with a PNG signature followed by their arguments.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+use common::fake_tool;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Two monitors side by side, the second one at x 2560
+const XRANDR_MONITORS: &str = "Monitors: 2
+ 0: +*DP-1 2560/597x1440/336+0+0  DP-1
+ 1: +HDMI-1 1920/527x1080/296+2560+0  HDMI-1";
+
+/// A capture tool printing the PNG signature, then its arguments one per line
+fn fake_capture_tool(dir: &Path, name: &str) {
+    fake_tool(dir, name, r#"#!/bin/sh
+printf '\211PNG\r\n\032\n'; printf '%s\n' "$@""#);
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = common::scratch_dir(&format!("screenshot-{}", name));
+    fake_tool(&dir, "xrandr", &format!("#!/bin/sh\necho '{}'\n", XRANDR_MONITORS));
+    dir
+}
+
+fn screenshot(dir: &Path, wayland: bool, args: &[&str]) -> Output {
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    let mut command = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"));
+    command.arg("screenshot").args(args).env("PATH", path).env("DISPLAY", ":nvidia-cc-rs-test");
+    if wayland {
+        command.env("WAYLAND_DISPLAY", "nvidia-cc-rs-test");
+    } else {
+        command.env_remove("WAYLAND_DISPLAY");
+    }
+    command.output().expect("run screenshot")
+}
+
+/// The arguments the fake tool echoed after the signature
+fn tool_args(png: &[u8]) -> Vec<String> {
+    assert!(png.starts_with(PNG_SIGNATURE), "not a PNG: {:?}", String::from_utf8_lossy(png));
+    String::from_utf8_lossy(&png[PNG_SIGNATURE.len()..]).lines().map(String::from).collect()
+}
+
+#[test]
+fn grim_takes_the_region_on_wayland() {
+    let dir = scratch_dir("grim");
+    fake_capture_tool(&dir, "grim");
+    let output = screenshot(&dir, true, &["--region", "10,20,300,200"]);
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(tool_args(&output.stdout), ["-g", "10,20 300x200", "-t", "png", "-"]);
+}
+
+#[test]
+fn display_region_is_relative_and_out_writes_a_file() {
+    let dir = scratch_dir("maim");
+    fake_capture_tool(&dir, "maim");
+    let out = dir.join("shot.png");
+    let output = screenshot(&dir, false, &["--display", "1", "--region", "5,6,100,50", "--out", out.to_str().unwrap()]);
+    let png = fs::read(&out);
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.is_empty());
+    assert_eq!(tool_args(&png.expect("--out written")), ["-g", "100x50+2565+6", "-f", "png"]);
+}
+
+#[test]
+fn whole_display_and_bad_arguments() {
+    let dir = scratch_dir("display");
+    fake_capture_tool(&dir, "maim");
+    let whole = screenshot(&dir, false, &["--display", "1"]);
+    let missing = screenshot(&dir, false, &["--display", "2"]);
+    let invalid = screenshot(&dir, false, &["--region", "10,20,300"]);
+    fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(tool_args(&whole.stdout), ["-g", "1920x1080+2560+0", "-f", "png"]);
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("No display 2 (found 2)"));
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("expected x,y,width,height"));
+}
@@ -0,0 +1,118 @@
+//! `service` against a stand-in systemctl logging its arguments, and the
+//! readiness and watchdog notifications a `Type=notify` unit gets.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::Duration;
+
+mod common;
+
+/// Logs its arguments, answering the state queries as for a running unit
+const FAKE_SYSTEMCTL: &str = r#"#!/bin/sh
+echo "$@" >> "$SYSTEMCTL_LOG"
+case "$2" in
+    is-active) echo active ;;
+    is-enabled) echo enabled ;;
+esac
+"#;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = common::scratch_dir(&format!("service-{}", name));
+    common::fake_tool(&dir, "systemctl", FAKE_SYSTEMCTL);
+    dir
+}
+
+fn service(dir: &Path, args: &[&str]) -> Output {
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .arg("service")
+        .args(args)
+        .env("PATH", path)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("SYSTEMCTL_LOG", dir.join("systemctl.log"))
+        .env("XDG_RUNTIME_DIR", "/run/user/1000")
+        .output()
+        .expect("run service")
+}
+
+fn status(output: &Output) -> serde_json::Value {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    serde_json::from_slice(&output.stdout).expect("JSON status")
+}
+
+#[test]
+fn install_status_and_uninstall() {
+    let dir = scratch_dir("install");
+    let before = service(&dir, &["status"]);
+    let install = service(&dir, &["install", "--", "--hotkeys", "/home/me/my hotkeys.json", "--realtime"]);
+    let unit = fs::read_to_string(dir.join("config/systemd/user/nvidia-cc.service"));
+    let after = service(&dir, &["status"]);
+    let uninstall = service(&dir, &["uninstall"]);
+    let removed = !dir.join("config/systemd/user/nvidia-cc.service").exists();
+    let log = fs::read_to_string(dir.join("systemctl.log")).unwrap_or_default();
+    let misplaced = service(&dir, &["install", "--", "--socket", "/tmp/x.sock"]);
+    fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(status(&before)["installed"], false);
+    let unit = unit.expect("unit written");
+    let exec = unit.lines().find(|line| line.starts_with("ExecStart=")).expect("ExecStart");
+    assert!(
+        exec.ends_with(r#" listen --socket %t/nvidia-cc.sock --hotkeys "/home/me/my hotkeys.json" --realtime"#),
+        "{}",
+        exec
+    );
+    for line in ["Type=notify", "WatchdogSec=30", "NoNewPrivileges=yes", "WantedBy=default.target"] {
+        assert!(unit.lines().any(|unit_line| unit_line == line), "{} missing from\n{}", line, unit);
+    }
+    assert_eq!(status(&install)["state"], "active");
+    let after = status(&after);
+    assert_eq!((&after["installed"], &after["enabled"]), (&true.into(), &true.into()));
+    assert_eq!(after["socket"], "/run/user/1000/nvidia-cc.sock");
+    assert!(uninstall.status.success(), "{}", String::from_utf8_lossy(&uninstall.stderr));
+    assert!(removed);
+    let calls: Vec<&str> = log.lines().filter(|call| !call.contains(" is-")).collect();
+    assert_eq!(
+        calls,
+        [
+            "--user daemon-reload",
+            "--user enable nvidia-cc.service",
+            "--user restart nvidia-cc.service",
+            "--user disable --now nvidia-cc.service",
+            "--user daemon-reload",
+        ]
+    );
+    assert!(String::from_utf8_lossy(&misplaced.stderr).contains("service install --socket"));
+}
+
+#[test]
+fn listen_notifies_readiness_and_feeds_the_watchdog() {
+    let dir = scratch_dir("notify");
+    fs::write(dir.join("events.jsonl"), r#"{"event_type":"KeyPress","key":"KeyA","delay_ms":2000}"#)
+        .expect("write events");
+    let notify = UnixDatagram::bind(dir.join("notify")).expect("bind notify socket");
+    notify.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut listen = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .env("NOTIFY_SOCKET", dir.join("notify"))
+        .env("WATCHDOG_USEC", "200000")
+        .env_remove("WATCHDOG_PID")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run listen");
+    let mut messages = Vec::new();
+    let mut buffer = [0; 64];
+    while messages.len() < 3 {
+        let Ok(received) = notify.recv(&mut buffer) else { break };
+        messages.push(String::from_utf8_lossy(&buffer[..received]).into_owned());
+    }
+    listen.kill().ok();
+    listen.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    assert_eq!(messages, ["READY=1", "WATCHDOG=1", "WATCHDOG=1"]);
+}
@@ -0,0 +1,50 @@
+//! The stdin `stats` command must report the running listener's counters.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+mod common;
+
+#[test]
+fn stats_counts_emitted_events() {
+    let dir = common::scratch_dir("stats");
+    let recorded = [
+        r#"{"event_type":"KeyPress","key":"KeyA"}"#,
+        r#"{"event_type":"KeyRelease","key":"KeyA"}"#,
+        // Keeps listen running while the test talks to it
+        r#"{"event_type":"KeyPress","key":"KeyB","delay_ms":2000}"#,
+    ];
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run listen");
+    let mut stdin = child.stdin.take().expect("stdin");
+    let mut events = BufReader::new(child.stdout.take().expect("stdout"))
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(&line.expect("read stdout")).expect("JSON line"));
+    let mut released = None;
+    for event in events.by_ref() {
+        if event["event_type"] == "KeyRelease" {
+            released = Some(event);
+            break;
+        }
+    }
+    writeln!(stdin, "stats").expect("send stats");
+    let stats = events.find(|event| event["event_type"] == "Stats").expect("Stats event")["stats"].clone();
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    let released_seq = released.expect("KeyRelease event")["seq"].as_u64().expect("seq");
+    assert!(stats["events_emitted"].as_u64().is_some_and(|emitted| emitted >= released_seq), "{}", stats);
+    assert_eq!(stats["events_dropped"], 0);
+    assert_eq!(stats["writes_failed"], 0);
+    assert!(stats["devices"].is_array());
+}
@@ -0,0 +1,218 @@
+//! `listen --tcp`: clients must authenticate before anything else, can
+//! subscribe or send commands, and get TLS with `--tls`.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+mod common;
+
+struct Listener {
+    child: Child,
+    addr: String,
+    token: String,
+    dir: PathBuf,
+}
+
+impl Listener {
+    /// A listener replaying one slow key press, up on `--tcp 127.0.0.1:0` with `extra` flags
+    fn start(name: &str, extra: &[&str]) -> Listener {
+        let dir = common::scratch_dir(&format!("tcp-{}", name));
+        // The waits keep the listener up while clients come and go
+        let recorded = [
+            r#"{"event_type":"KeyPress","key":"KeyA","delay_ms":1000}"#,
+            r#"{"event_type":"KeyRelease","key":"KeyA","delay_ms":20000}"#,
+        ];
+        fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+        let mut child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .args(["listen", "--backend", "replay", "--tcp", "127.0.0.1:0", "--input"])
+            .arg(dir.join("events.jsonl"))
+            .args(extra)
+            .current_dir(&dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("run listen");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout"));
+        let listening = stdout
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(&line.expect("read stdout")).expect("JSON line"))
+            .find(|event| event["event_type"] == "TcpListening")
+            .expect("TcpListening is emitted");
+        Listener {
+            child,
+            addr: listening["addr"].as_str().expect("addr").to_string(),
+            token: listening["token"].as_str().expect("token").to_string(),
+            dir,
+        }
+    }
+
+    fn connect(&self, lines: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(&self.addr).expect("connect");
+        stream.set_read_timeout(Some(Duration::from_secs(5))).expect("set timeout");
+        stream.write_all(lines.as_bytes()).expect("send lines");
+        stream
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// Events read from `stream` up to and including the first `event_type` one
+fn read_until(stream: TcpStream, event_type: &str) -> Vec<serde_json::Value> {
+    let mut events = Vec::new();
+    for line in BufReader::new(stream).lines() {
+        let event: serde_json::Value = serde_json::from_str(&line.expect("read event")).expect("JSON line");
+        let last = event["event_type"] == event_type;
+        events.push(event);
+        if last {
+            break;
+        }
+    }
+    events
+}
+
+#[test]
+fn refuses_other_machines_without_a_token() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--tcp", "0.0.0.0:0"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("refusing to serve it without --token"), "{}", stderr);
+}
+
+#[test]
+fn clients_authenticate_then_subscribe_or_command() {
+    let listener = Listener::start("plain", &["--token", "s3cret"]);
+    assert_eq!(listener.token, "s3cret");
+
+    let mut refused = listener.connect("auth wrong\nsubscribe\n");
+    let mut reply = String::new();
+    refused.read_to_string(&mut reply).expect("read refusal");
+    assert!(reply.contains(r#""error":"Unauthorized""#), "{}", reply);
+    assert_eq!(reply.lines().count(), 1, "{}", reply);
+
+    let subscriber = listener.connect("auth s3cret\nsubscribe\n");
+    // Let the subscription register before the command's answer is emitted
+    std::thread::sleep(Duration::from_millis(200));
+    let _commands = listener.connect("auth s3cret\n--id remote-1 stats\n");
+    let events = read_until(subscriber, "Stats");
+    let stats = events.last().expect("Stats reaches the subscriber");
+    assert_eq!(stats["request_id"], "remote-1");
+}
+
+#[test]
+fn subscribing_past_the_last_sequence_is_a_restart() {
+    let listener = Listener::start("since-max", &[]);
+    // As a client still holding the sequence of the helper's previous run would
+    let subscriber = listener.connect(&format!("auth {}\nsubscribe --since-seq {}\n", listener.token, u64::MAX));
+    std::thread::sleep(Duration::from_millis(200));
+    let _commands = listener.connect(&format!("auth {}\n--id remote-2 stats\n", listener.token));
+    let events = read_until(subscriber, "Stats");
+
+    let gap = &events[0];
+    assert_eq!(gap["event_type"], "ReplayGap");
+    assert_eq!(gap["since_seq"], u64::MAX);
+    assert_eq!(gap["oldest_seq"], 1);
+    assert_eq!(gap["seq"], 0);
+    assert!(gap["mono_us"].is_u64(), "{}", gap);
+    // Everything this run emitted is replayed
+    assert_eq!(events[1]["seq"], 1);
+    assert_eq!(events[1]["event_type"], "Capabilities");
+    assert_eq!(events.last().unwrap()["request_id"], "remote-2");
+}
+
+#[test]
+fn tls_serves_the_stream() {
+    let dir = common::scratch_dir("tcp-cert");
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+    let generated = Command::new("openssl")
+        .args(["req", "-x509", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:prime256v1", "-nodes"])
+        .args(["-subj", "/CN=localhost", "-days", "1", "-keyout"])
+        .arg(&key)
+        .arg("-out")
+        .arg(&cert)
+        .stderr(Stdio::null())
+        .status();
+    if !generated.is_ok_and(|status| status.success()) {
+        eprintln!("openssl isn't installed, skipping");
+        fs::remove_dir_all(&dir).ok();
+        return;
+    }
+    let listener = Listener::start("tls", &["--tls", cert.to_str().unwrap(), key.to_str().unwrap()]);
+
+    // Plain text isn't a handshake
+    let mut plain = listener.connect(&format!("auth {}\nsubscribe\n", listener.token));
+    let mut reply = Vec::new();
+    plain.read_to_end(&mut reply).ok();
+    assert!(!String::from_utf8_lossy(&reply).contains("event_type"));
+
+    let mut client = Command::new("openssl")
+        .args(["s_client", "-quiet", "-connect", &listener.addr])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run openssl s_client");
+    let mut stdin = client.stdin.take().expect("stdin");
+    write!(stdin, "auth {}\nsubscribe --since-seq 0\n", listener.token).expect("send lines");
+    let press = BufReader::new(client.stdout.take().expect("stdout"))
+        .lines()
+        .map(|line| line.expect("read event"))
+        .find(|line| line.contains(r#""event_type":"KeyPress""#));
+    client.kill().ok();
+    client.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    let press = press.expect("KeyPress comes through TLS");
+    assert!(press.contains(r#""key":"KeyA""#), "{}", press);
+}
+
+#[test]
+fn caps_clients_yet_to_authenticate() {
+    let listener = Listener::start("pending", &[]);
+    let _idle: Vec<TcpStream> = (0..16).map(|_| listener.connect("")).collect();
+    // Let the server count them before the next one arrives
+    std::thread::sleep(Duration::from_millis(200));
+
+    let mut refused = listener.connect(&format!("auth {}\nstats\n", listener.token));
+    let mut reply = Vec::new();
+    // Closed unread, which may come as a reset
+    match refused.read_to_end(&mut reply) {
+        Ok(_) => assert!(reply.is_empty(), "{}", String::from_utf8_lossy(&reply)),
+        Err(e) => assert_eq!(e.kind(), std::io::ErrorKind::ConnectionReset),
+    }
+}
+
+#[test]
+fn trickling_clients_are_cut_off_at_the_auth_deadline() {
+    let listener = Listener::start("trickle", &[]);
+    let mut client = listener.connect("");
+    client.set_read_timeout(Some(Duration::from_secs(20))).expect("set timeout");
+    let mut writer = client.try_clone().expect("clone the connection");
+    let started = std::time::Instant::now();
+    // One byte at a time, each within the read timeout, never finishing the line
+    std::thread::spawn(move || {
+        while writer.write_all(b"a").is_ok() {
+            std::thread::sleep(Duration::from_millis(500));
+        }
+    });
+
+    let mut reply = Vec::new();
+    client.read_to_end(&mut reply).ok();
+    let elapsed = started.elapsed();
+    assert!(elapsed > Duration::from_secs(9) && elapsed < Duration::from_secs(15), "{:?}", elapsed);
+}
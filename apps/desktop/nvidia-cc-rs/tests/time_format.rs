@@ -0,0 +1,77 @@
+//! `--time-format`: event timestamps in the chosen representation.
+
+use std::fs;
+use std::process::{Command, Stdio};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod common;
+
+/// Key events `listen --time-format <format>` emits replaying one key tap
+fn key_events(format: &str) -> Vec<serde_json::Value> {
+    let dir = common::scratch_dir(&format!("time-{}", format));
+    let recorded = [r#"{"event_type":"KeyPress","key":"KeyA"}"#, r#"{"event_type":"KeyRelease","key":"KeyA"}"#];
+    fs::write(dir.join("events.jsonl"), recorded.join("\n")).expect("write events");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--time-format", format, "--input"])
+        .arg(dir.join("events.jsonl"))
+        .stdin(Stdio::null())
+        .output()
+        .expect("run listen");
+    fs::remove_dir_all(&dir).ok();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("listen emits JSON lines"))
+        .filter(|event| event["event_type"] == "KeyPress" || event["event_type"] == "KeyRelease")
+        .collect()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+#[test]
+fn default_keeps_the_system_time_struct() {
+    let events = key_events("system-time");
+    assert_eq!(events.len(), 2);
+    assert!(events[0]["time"]["secs_since_epoch"].is_u64(), "{}", events[0]);
+}
+
+#[test]
+fn epoch_ms_is_a_number_of_milliseconds() {
+    let events = key_events("epoch-ms");
+    let time = events[0]["time"].as_u64().expect("numeric time");
+    assert!(time <= now_ms() && now_ms() - time < 60_000, "{}", time);
+}
+
+#[test]
+fn rfc3339_is_a_utc_string() {
+    let events = key_events("rfc3339");
+    let time = events[0]["time"].as_str().expect("string time");
+    // 2026-10-14T09:30:00.125Z
+    assert_eq!(time.len(), 24, "{}", time);
+    assert_eq!(&time[4..5], "-");
+    assert_eq!(&time[10..11], "T");
+    assert!(time.ends_with('Z'), "{}", time);
+    let year: u64 = time[..4].parse().expect("year");
+    assert!(year >= 2024, "{}", time);
+}
+
+#[test]
+fn monotonic_matches_mono_us() {
+    for event in key_events("monotonic") {
+        let time = event["time"].as_u64().expect("numeric time");
+        let mono_us = event["mono_us"].as_u64().expect("mono_us");
+        assert!(time <= mono_us && mono_us - time < 1_000_000, "{}", event);
+    }
+}
+
+#[test]
+fn unknown_formats_are_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--time-format", "iso"])
+        .output()
+        .expect("run listen");
+    assert!(!output.status.success());
+}
@@ -0,0 +1,221 @@
+//! `--inject-backend wayland` against a stand-in compositor offering the
+//! virtual keyboard protocol: the uploaded keymap and the keys sent through
+//! it must spell out what was written.
+#![cfg(target_os = "linux")]
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::thread::{self, JoinHandle};
+
+mod common;
+
+/// What the client asked of the compositor
+#[derive(Default)]
+struct Log {
+    keymaps: Vec<String>,
+    /// (evdev key code, pressed)
+    keys: Vec<(u32, bool)>,
+    modifiers: Vec<u32>,
+}
+
+impl Log {
+    /// The keysym each key press typed, through the last keymap
+    fn typed(&self) -> Vec<u32> {
+        let keymap: HashMap<u32, u32> = self
+            .keymaps
+            .last()
+            .expect("a keymap was uploaded")
+            .lines()
+            .filter_map(|line| {
+                let (code, keysym) = line.trim().strip_prefix("key <I")?.split_once("> { [ ")?;
+                let keysym = keysym.strip_suffix(" ] };")?.strip_prefix("0x")?;
+                Some((code.parse().ok()?, u32::from_str_radix(keysym, 16).ok()?))
+            })
+            .collect();
+        self.keys
+            .iter()
+            .filter(|(_, pressed)| *pressed)
+            .map(|(code, _)| keymap[&(code + 8)])
+            .collect()
+    }
+}
+
+struct Compositor {
+    dir: PathBuf,
+    server: Option<JoinHandle<Log>>,
+}
+
+impl Compositor {
+    /// Serve one client, announcing a seat and the `globals` given
+    fn start(name: &str, globals: &'static [&'static str]) -> Compositor {
+        let dir = common::scratch_dir(&format!("wayland-{}", name));
+        let listener = UnixListener::bind(dir.join("wayland-test")).expect("bind the compositor socket");
+        let server = thread::spawn(move || {
+            let (client, _) = listener.accept().expect("accept the client");
+            serve(client, globals)
+        });
+        Compositor {
+            dir,
+            server: Some(server),
+        }
+    }
+
+    fn run(&mut self, args: &[&str]) -> (Output, Log) {
+        let output = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+            .args(["--inject-backend", "wayland"])
+            .args(args)
+            .env("XDG_RUNTIME_DIR", &self.dir)
+            .env("WAYLAND_DISPLAY", "wayland-test")
+            .env_remove("DISPLAY")
+            .output()
+            .expect("run nvidia-cc-rs");
+        let log = self.server.take().expect("one run").join().expect("compositor thread");
+        (output, log)
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// Read what's there, keeping the fds passed along
+fn receive(stream: &UnixStream, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> usize {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let mut control = [0u8; 256];
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr().cast();
+    message.msg_controllen = control.len() as _;
+    let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut message, 0) };
+    let mut header = unsafe { libc::CMSG_FIRSTHDR(&message) };
+    while !header.is_null() {
+        let raw = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(header).cast::<RawFd>()) };
+        fds.push(unsafe { OwnedFd::from_raw_fd(raw) });
+        header = unsafe { libc::CMSG_NXTHDR(&message, header) };
+    }
+    received.max(0) as usize
+}
+
+fn event(object: u32, opcode: u32, arguments: &[u8]) -> Vec<u8> {
+    let mut bytes = object.to_ne_bytes().to_vec();
+    bytes.extend((((8 + arguments.len()) as u32) << 16 | opcode).to_ne_bytes());
+    bytes.extend(arguments);
+    bytes
+}
+
+fn string(value: &str) -> Vec<u8> {
+    let mut bytes = (value.len() as u32 + 1).to_ne_bytes().to_vec();
+    bytes.extend(value.as_bytes());
+    bytes.push(0);
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(0);
+    }
+    bytes
+}
+
+fn words(arguments: &[u8]) -> Vec<u32> {
+    arguments.chunks_exact(4).map(|word| u32::from_ne_bytes(word.try_into().unwrap())).collect()
+}
+
+fn serve(mut client: UnixStream, globals: &[&str]) -> Log {
+    let mut log = Log::default();
+    let mut objects: HashMap<u32, String> = HashMap::from([(1, "wl_display".to_string())]);
+    let (mut pending, mut fds) = (Vec::new(), Vec::new());
+    let mut buf = [0u8; 4096];
+    loop {
+        let read = receive(&client, &mut buf, &mut fds);
+        if read == 0 {
+            return log;
+        }
+        pending.extend_from_slice(&buf[..read]);
+        while pending.len() >= 8 {
+            let size = (u32::from_ne_bytes(pending[4..8].try_into().unwrap()) >> 16) as usize;
+            if pending.len() < size {
+                break;
+            }
+            let request: Vec<u8> = pending.drain(..size).collect();
+            let object = u32::from_ne_bytes(request[..4].try_into().unwrap());
+            let opcode = u32::from_ne_bytes(request[4..8].try_into().unwrap()) & 0xffff;
+            let arguments = &request[8..];
+            let args = words(arguments);
+            let interface = objects.get(&object).cloned().unwrap_or_default();
+            let mut reply = Vec::new();
+            match (interface.as_str(), opcode) {
+                ("wl_display", 0) => reply.extend(event(args[0], 0, &0u32.to_ne_bytes())),
+                ("wl_display", 1) => {
+                    objects.insert(args[0], "wl_registry".to_string());
+                    for (name, interface) in ["wl_seat"].iter().chain(globals).enumerate() {
+                        let mut global = (name as u32 + 1).to_ne_bytes().to_vec();
+                        global.extend(string(interface));
+                        global.extend(1u32.to_ne_bytes());
+                        reply.extend(event(args[0], 0, &global));
+                    }
+                }
+                ("wl_registry", 0) => {
+                    let len = args[1] as usize;
+                    let interface = String::from_utf8_lossy(&arguments[8..8 + len - 1]).into_owned();
+                    let id = *words(&arguments[8 + len.div_ceil(4) * 4..]).last().unwrap();
+                    objects.insert(id, interface);
+                }
+                ("zwp_virtual_keyboard_manager_v1", 0) => {
+                    objects.insert(args[1], "zwp_virtual_keyboard_v1".to_string());
+                }
+                ("zwp_virtual_keyboard_v1", 0) => {
+                    // The offset is shared with the client, which left it at the end; compositors map the file
+                    let mut file = File::from(fds.remove(0));
+                    let mut keymap = String::new();
+                    file.seek(SeekFrom::Start(0)).and_then(|_| file.read_to_string(&mut keymap)).expect("read keymap");
+                    assert_eq!(keymap.len() as u32, args[1], "keymap size");
+                    log.keymaps.push(keymap.trim_end_matches('\0').to_string());
+                }
+                ("zwp_virtual_keyboard_v1", 1) => log.keys.push((args[1], args[2] == 1)),
+                ("zwp_virtual_keyboard_v1", 2) => log.modifiers.push(args[0]),
+                _ => {}
+            }
+            client.write_all(&reply).expect("answer the client");
+        }
+    }
+}
+
+#[test]
+fn write_types_through_an_uploaded_keymap() {
+    let mut compositor = Compositor::start("write", &["zwp_virtual_keyboard_manager_v1"]);
+    let (output, log) = compositor.run(&["write", "Hé→\n"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let keymap = log.keymaps.last().expect("a keymap was uploaded");
+    assert!(keymap.starts_with("xkb_keymap {"), "{}", keymap);
+    assert_eq!(log.typed(), [0x48, 0xe9, 0x0100_2192, 0xff0d]);
+    assert_eq!(log.keys.iter().filter(|(_, pressed)| !pressed).count(), 4);
+}
+
+#[test]
+fn combos_hold_modifiers_as_state() {
+    let mut compositor = Compositor::start("press", &["zwp_virtual_keyboard_manager_v1"]);
+    let (output, log) = compositor.run(&["press", "ctrl+v"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(log.modifiers, [4, 0]);
+    assert_eq!(log.typed(), [u32::from(b'v')]);
+}
+
+#[test]
+fn needs_the_virtual_keyboard_protocol() {
+    let mut compositor = Compositor::start("missing", &[]);
+    let (output, _) = compositor.run(&["write", "hi"]);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("wayland: the compositor doesn't offer zwp_virtual_keyboard_manager_v1"), "{}", stderr);
+}
@@ -0,0 +1,114 @@
+//! `windows list|focus|raise`, and `write --window`: the matched window is
+//! focused for the write and the previous one focused again after. Runs
+//! against an X11 desktop made of stand-in `xprop` and `xdotool` scripts;
+//! the fake xdotool also does the typing, as the injection chain falls
+//! through to it.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+
+/// Two windows, the terminal focused
+const FAKE_XPROP: &str = r#"#!/bin/sh
+case "$*" in
+    "-root _NET_CLIENT_LIST") echo '_NET_CLIENT_LIST(WINDOW): window id # 0x1a00003, 0x2c00007' ;;
+    "-root _NET_ACTIVE_WINDOW") echo '_NET_ACTIVE_WINDOW(WINDOW): window id # 0x1a00003' ;;
+    "-id 0x1a00003 "*) printf '%s\n' '_NET_WM_NAME(UTF8_STRING) = "Terminal"' 'WM_CLASS(STRING) = "term", "Term"' ;;
+    "-id 0x2c00007 "*) printf '%s\n' '_NET_WM_NAME(UTF8_STRING) = "notes.txt - Editor"' '_NET_WM_PID(CARDINAL) = 4242' ;;
+    *) exit 1 ;;
+esac
+"#;
+
+/// Logs each call, and what it was asked to type
+const FAKE_XDOTOOL: &str = r#"#!/bin/sh
+log="$(dirname "$0")/xdotool.log"
+if [ "$1" = type ]; then
+    echo "type $(cat)" >> "$log"
+else
+    echo "$*" >> "$log"
+fi
+"#;
+
+fn fake_desktop() -> PathBuf {
+    let dir = common::scratch_dir("windows");
+    common::fake_tool(&dir, "xprop", FAKE_XPROP);
+    common::fake_tool(&dir, "xdotool", FAKE_XDOTOOL);
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(args)
+        .env("PATH", format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default()))
+        .env("DISPLAY", ":nvidia-cc-rs-test")
+        .env_remove("WAYLAND_DISPLAY")
+        .env_remove("SWAYSOCK")
+        .env_remove("HYPRLAND_INSTANCE_SIGNATURE")
+        .output()
+        .expect("run nvidia-cc-rs")
+}
+
+#[test]
+fn write_focuses_the_window_and_gives_focus_back() {
+    let dir = fake_desktop();
+    let output = run(&dir, &["write", "--window", "editor", "hello"]);
+    let log = fs::read_to_string(dir.join("xdotool.log")).unwrap_or_default();
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let calls: Vec<&str> = log.lines().filter(|line| *line != "version").collect();
+    assert_eq!(
+        calls,
+        ["windowactivate --sync 0x2c00007", "type hello", "windowactivate --sync 0x1a00003"],
+        "{}",
+        log
+    );
+}
+
+#[test]
+fn unknown_windows_fail_without_typing() {
+    let dir = fake_desktop();
+    let output = run(&dir, &["write", "--window", "Browser", "hello"]);
+    let log = fs::read_to_string(dir.join("xdotool.log")).unwrap_or_default();
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No window matches \"Browser\""));
+    assert!(!log.contains("type"), "{}", log);
+}
+
+#[test]
+fn list_marks_the_focused_window() {
+    let dir = fake_desktop();
+    let output = run(&dir, &["windows", "list"]);
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let windows: serde_json::Value = serde_json::from_slice(&output.stdout).expect("JSON array");
+    assert_eq!(
+        windows,
+        serde_json::json!([
+            {"id": "0x1a00003", "title": "Terminal", "app_id": "Term", "pid": null, "focused": true},
+            {"id": "0x2c00007", "title": "notes.txt - Editor", "app_id": "", "pid": 4242, "focused": false},
+        ])
+    );
+}
+
+#[test]
+fn focus_and_raise_take_json_matches() {
+    let dir = fake_desktop();
+    let focused = run(&dir, &["windows", "focus", r#"{"pid": 4242}"#]);
+    let raised = run(&dir, &["windows", "raise", r#"{"app_id": "term", "title": "term"}"#]);
+    let unmatched = run(&dir, &["windows", "raise", r#"{"app_id": "term", "pid": 4242}"#]);
+    let log = fs::read_to_string(dir.join("xdotool.log")).unwrap_or_default();
+    fs::remove_dir_all(&dir).ok();
+
+    let window: serde_json::Value = serde_json::from_slice(&focused.stdout).expect("matched window");
+    assert_eq!(window["id"], "0x2c00007");
+    assert!(raised.status.success(), "{}", String::from_utf8_lossy(&raised.stderr));
+    assert!(!unmatched.status.success(), "every field must match");
+    assert_eq!(log.lines().collect::<Vec<_>>(), ["windowactivate --sync 0x2c00007", "windowraise 0x1a00003"]);
+}
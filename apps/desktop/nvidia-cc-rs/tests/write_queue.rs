@@ -0,0 +1,77 @@
+//! Queued stdin writes: each is acknowledged with its queue position, they
+//! finish in the order sent, and `cancel <id>` reaches a specific one.
+//!
+//! Injection itself fails without a display or uinput, which still ends each
+//! write with an outcome, so only the queueing is checked here.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+mod common;
+
+/// Events marking a write as finished
+const OUTCOMES: [&str; 3] = ["WriteComplete", "WriteCancelled", "Error"];
+
+#[test]
+fn writes_run_in_order_and_cancel_by_id() {
+    let dir = common::scratch_dir("write-queue");
+    // Keeps listen running while commands are sent
+    fs::write(dir.join("events.jsonl"), r#"{"event_type":"KeyPress","key":"KeyA","delay_ms":3000}"#)
+        .expect("write events");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["listen", "--backend", "replay", "--input"])
+        .arg(dir.join("events.jsonl"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run listen");
+    let mut stdin = child.stdin.take().expect("stdin");
+    writeln!(stdin, "--id a write \"first\"").unwrap();
+    writeln!(stdin, "--id b write \"second\"").unwrap();
+    writeln!(stdin, "--id c write {:?}", "third".repeat(50)).unwrap();
+    writeln!(stdin, "--id d cancel c").unwrap();
+    writeln!(stdin, "--id e cancel missing").unwrap();
+
+    let mut queued = Vec::new();
+    let mut finished = Vec::new();
+    let mut unknown = Vec::new();
+    for line in BufReader::new(child.stdout.take().expect("stdout")).lines() {
+        let event: serde_json::Value = serde_json::from_str(&line.expect("read stdout")).expect("JSON line");
+        let id = event["request_id"].as_str().unwrap_or_default().to_string();
+        match event["event_type"].as_str().unwrap_or_default() {
+            "WriteQueued" => queued.push((id, event["position"].as_u64().expect("position"))),
+            "Error" if event["error"] == "UnknownWrite" => unknown.push(id),
+            outcome if OUTCOMES.contains(&outcome) && ["a", "b", "c"].contains(&id.as_str()) => {
+                finished.push((id, event));
+            }
+            _ => {}
+        }
+        if finished.len() == 3 && unknown.contains(&"e".to_string()) {
+            break;
+        }
+    }
+    child.kill().ok();
+    child.wait().ok();
+    fs::remove_dir_all(&dir).ok();
+
+    let ids: Vec<&str> = queued.iter().map(|(id, _)| id.as_str()).collect();
+    assert_eq!(ids, ["a", "b", "c"]);
+    assert_eq!(queued[0].1, 0, "nothing runs before the first write");
+    for (index, (id, position)) in queued.iter().enumerate() {
+        assert!(*position <= index as u64, "{} at {}", id, position);
+    }
+
+    let (_, third) = finished.iter().find(|(id, _)| id == "c").expect("third write ends");
+    let dropped = third["event_type"] == "WriteCancelled" && third["chars_done"] == 0;
+    if dropped {
+        assert_eq!(third["total"], 250);
+    } else {
+        // It had already started, or finished when its cancel was read
+        assert!(unknown.contains(&"d".to_string()) || finished[2].0 == "c", "{:?}", finished);
+    }
+    let ran: Vec<&str> = finished.iter().map(|(id, _)| id.as_str()).filter(|id| !dropped || *id != "c").collect();
+    assert_eq!(ran[..2], ["a", "b"], "one write at a time, in order");
+}
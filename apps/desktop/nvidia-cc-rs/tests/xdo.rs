@@ -0,0 +1,94 @@
+//! `xdo` driving the xdotool backend, here a stand-in xdotool that logs
+//! what it's asked to do.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+mod common;
+
+/// Logs its arguments, and for `type --file -` the text, one call per line
+const FAKE_XDOTOOL: &str = r#"#!/bin/sh
+log="$(dirname "$0")/xdotool.log"
+case "$1" in
+    version) ;;
+    type) echo "type $(cat)" >> "$log" ;;
+    *) echo "$*" >> "$log" ;;
+esac
+"#;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = common::scratch_dir(&format!("xdo-{}", name));
+    common::fake_tool(&dir, "xdotool", FAKE_XDOTOOL);
+    dir
+}
+
+fn xdo(dir: &Path, args: &[&str]) -> Output {
+    let path = format!("{}:{}", dir.display(), std::env::var("PATH").unwrap_or_default());
+    std::process::Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(["--inject-backend", "xdotool", "xdo"])
+        .args(args)
+        .env("PATH", path)
+        .env("DISPLAY", ":nvidia-cc-rs-test")
+        // Where key down keeps its state files
+        .env("XDG_RUNTIME_DIR", dir)
+        .output()
+        .expect("run xdo")
+}
+
+fn log(dir: &Path) -> Vec<String> {
+    fs::read_to_string(dir.join("xdotool.log")).unwrap_or_default().lines().map(String::from).collect()
+}
+
+#[test]
+fn key_and_type_take_xdotool_syntax() {
+    let dir = scratch_dir("key");
+    let key = xdo(&dir, &["key", "--clearmodifiers", "--delay", "0", "Control_L+Page_Down", "BackSpace"]);
+    let typed = xdo(&dir, &["type", "--", "--not an option"]);
+    let calls = log(&dir);
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(key.status.success(), "{}", String::from_utf8_lossy(&key.stderr));
+    assert!(typed.status.success(), "{}", String::from_utf8_lossy(&typed.stderr));
+    let expected = [
+        "keydown Control_L",
+        "keydown Next",
+        "keyup Next",
+        "keyup Control_L",
+        "keydown BackSpace",
+        "keyup BackSpace",
+        "type --not an option",
+    ];
+    assert_eq!(calls, expected);
+}
+
+#[test]
+fn keydown_holds_until_keyup() {
+    let dir = scratch_dir("hold");
+    let down = xdo(&dir, &["keydown", "shift"]);
+    let held = log(&dir);
+    let up = xdo(&dir, &["keyup", "shift"]);
+    let released = log(&dir);
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(down.status.success(), "{}", String::from_utf8_lossy(&down.stderr));
+    assert_eq!(held, ["keydown Shift_L"]);
+    assert!(up.status.success(), "{}", String::from_utf8_lossy(&up.stderr));
+    // keyup releases again itself if the holder's exit goes unseen, as in containers where nothing reaps it
+    assert_eq!(released[..2], ["keydown Shift_L", "keyup Shift_L"]);
+    assert!(released[2..].iter().all(|call| call == "keyup Shift_L"), "{:?}", released);
+}
+
+#[test]
+fn unsupported_input_is_an_error() {
+    let dir = scratch_dir("errors");
+    let command = xdo(&dir, &["mousemove", "10", "10"]);
+    let keysym = xdo(&dir, &["key", "ctrl+Hangul_Start"]);
+    let option = xdo(&dir, &["keydown", "--window", "1", "shift"]);
+    fs::remove_dir_all(&dir).ok();
+
+    assert!(String::from_utf8_lossy(&command.stderr).contains("xdo doesn't support the mousemove command"));
+    assert!(String::from_utf8_lossy(&keysym.stderr).contains(r#"Unknown keysym "Hangul_Start""#));
+    assert!(String::from_utf8_lossy(&option.stderr).contains("xdo keydown doesn't support --window"));
+}
@@ -0,0 +1,49 @@
+//! `ydotoold` and `listen --ydotool-socket`, up to the uinput device this
+//! sandbox doesn't have: the socket is only bound once the device exists.
+#![cfg(target_os = "linux")]
+
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nvidia-cc-rs"))
+        .args(args)
+        .output()
+        .expect("run nvidia-cc-rs")
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn ydotoold_takes_ydotoolds_options() {
+    let perm = run(&["ydotoold", "--socket-perm", "rw"]);
+    let missing = run(&["ydotoold", "--socket-path"]);
+    let unknown = run(&["ydotoold", "--foreground", "1"]);
+
+    assert!(!perm.status.success());
+    assert!(stderr(&perm).contains("Invalid --socket-perm value: rw"), "{}", stderr(&perm));
+    assert!(stderr(&missing).contains("--socket-path expects a value"), "{}", stderr(&missing));
+    assert!(stderr(&unknown).contains("Unknown ydotoold option: --foreground"), "{}", stderr(&unknown));
+}
+
+#[test]
+fn no_socket_without_a_virtual_keyboard() {
+    if std::path::Path::new("/dev/uinput").exists() {
+        return;
+    }
+    let socket = std::env::temp_dir().join(format!("nvidia-cc-rs-ydotool-{}.sock", std::process::id()));
+    let output = run(&["ydotoold", "--socket-path", socket.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("cannot create virtual keyboard"), "{}", stderr(&output));
+    assert!(!socket.exists());
+}
+
+#[test]
+fn listen_ydotool_socket_needs_a_path() {
+    let output = run(&["listen", "--ydotool-socket"]);
+
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("--ydotool-socket requires a path"), "{}", stderr(&output));
+}
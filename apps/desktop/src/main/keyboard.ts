@@ -37,6 +37,7 @@ type RdevEvent = {
       | "Alt"
       | "AltLeft"
       | "AltRight"
+      | "AltGr"
       | "BackSlash"
       | string
   }
@@ -47,7 +48,8 @@ type RdevEvent = {
 
 export const writeText = (text: string) => {
   return new Promise<void>((resolve, reject) => {
-    const child: ChildProcess = spawn(rdevPath, ["write", text])
+    // Passed on stdin: argv has length limits and is visible in process listings
+    const child: ChildProcess = spawn(rdevPath, ["write", "--stdin"])
 
     // Register process if agent mode is active
     if (state.isAgentModeActive) {
@@ -64,6 +66,8 @@ export const writeText = (text: string) => {
       reject(new Error(`Failed to spawn process: ${error.message}`))
     })
 
+    child.stdin?.end(text)
+
     child.on("close", (code) => {
       // writeText will trigger KeyPress event of the key A
       // I don't know why
@@ -223,6 +227,7 @@ const isModifierKey = (key: string): boolean => {
     key === "Alt" ||
     key === "AltLeft" ||
     key === "AltRight" ||
+    key === "AltGr" ||
     key === "MetaLeft" ||
     key === "MetaRight"
   )
@@ -440,7 +445,7 @@ export function listenToKeyboardEvents() {
         }
       }
 
-      if (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight") {
+      if (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight" || e.data.key === "AltGr") {
         isPressedAltKey = true
         isPressedCtrlAltKey = isPressedCtrlKey && isPressedAltKey
         tryStartMcpHoldIfEligible()
@@ -1113,7 +1118,7 @@ export function listenToKeyboardEvents() {
             }
           }, HOLD_TO_RECORD_DELAY_MS)
         } else if (
-          (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight") &&
+          (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight" || e.data.key === "AltGr") &&
           isPressedCtrlKey &&
           config.mcpToolsShortcut === "hold-ctrl-alt"
         ) {
@@ -1199,7 +1204,7 @@ export function listenToKeyboardEvents() {
         }
       }
 
-      if (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight") {
+      if (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight" || e.data.key === "AltGr") {
         isPressedAltKey = false
         isPressedCtrlAltKey = false
         if (isDebugKeybinds()) {
@@ -1298,7 +1303,7 @@ export function listenToKeyboardEvents() {
         }
       }
 
-      if (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight") {
+      if (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight" || e.data.key === "AltGr") {
         if (isHoldingCtrlAltKey) {
           const panelHandlers = getWindowRendererHandlers("panel")
           panelHandlers?.finishMcpRecording.send()
@@ -1329,7 +1334,7 @@ export function listenToKeyboardEvents() {
       }
 
       // Close panel on Alt release if not in text input mode (and not in MCP mode, which is handled above)
-      if (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight") {
+      if (e.data.key === "Alt" || e.data.key === "AltLeft" || e.data.key === "AltRight" || e.data.key === "AltGr") {
         if (!state.isTextInputActive && !state.isRecordingMcpMode) {
           // Only close panel if we're not in text input mode and not in MCP recording mode
           // (MCP toggle mode should not close panel on key release)
@@ -1339,7 +1344,7 @@ export function listenToKeyboardEvents() {
     }
   }
 
-  const child = spawn(rdevPath, ["listen"], {})
+  const child = spawn(rdevPath, ["listen", "--legacy-format"], {})
 
   if (isDebugKeybinds()) {
     logKeybinds("Starting keyboard event listener with rdev path:", rdevPath)
@@ -1385,40 +1390,63 @@ export function listenToKeyboardEvents() {
       if (stderrBuffer.includes("PermissionDenied") || stderrBuffer.includes("Permission denied")) {
         const { dialog, Notification } = require("electron")
 
+        // install-permissions asks for authentication through polkit, and needs no re-login
+        const showPermissionDialog = (detail: string) => {
+          dialog
+            .showMessageBox({
+              type: "warning",
+              title: "Global Hotkeys Permission Required",
+              message: "To use global hotkeys on Linux (especially Wayland), NVIDIA Control Center needs access to your keyboards.",
+              detail,
+              buttons: ["Grant Access", "Cancel"],
+              defaultId: 0,
+              cancelId: 1,
+            })
+            .then(({ response }) => {
+              if (response !== 0) return
+              const installer = spawn(rdevPath, ["install-permissions"])
+              let installError = ""
+              installer.stderr?.on("data", (data) => {
+                installError += data.toString()
+              })
+              installer.on("exit", (exitCode) => {
+                if (exitCode === 0) {
+                  listenToKeyboardEvents()
+                } else {
+                  dialog.showMessageBox({
+                    type: "error",
+                    title: "Global Hotkeys Permission Required",
+                    message: "Keyboard access wasn't granted.",
+                    detail: `${installError.replace(/^!error: /m, "").trim()}\n\nAlternatively, run this command in a terminal:\n\nsudo usermod -aG input $USER\n\nThen log out and log back in.`,
+                    buttons: ["OK"],
+                  })
+                }
+              })
+            })
+        }
+
         // Show a notification if supported
         if (Notification.isSupported()) {
           const notification = new Notification({
             title: "NVIDIA Control Center: Hotkeys Not Working",
-            body: "Global hotkeys require input group membership. Click for details.",
+            body: "Global hotkeys need access to your keyboards. Click to grant it.",
             urgency: "critical",
           })
           notification.on("click", () => {
-            dialog.showMessageBox({
-              type: "warning",
-              title: "Global Hotkeys Permission Required",
-              message: "To use global hotkeys on Linux (especially Wayland), you need to add your user to the 'input' group.",
-              detail: "Run this command in a terminal:\n\nsudo usermod -aG input $USER\n\nThen log out and log back in for the change to take effect.\n\nThis is required because NVIDIA Control Center needs to read keyboard events from /dev/input/ devices.",
-              buttons: ["OK"],
-            })
+            showPermissionDialog("Grant Access installs a rule giving your login session access to keyboards; it asks for your password.\n\nThis is required because NVIDIA Control Center needs to read keyboard events from /dev/input/ devices.")
           })
           notification.show()
         } else {
           // Fallback to dialog
-          dialog.showMessageBox({
-            type: "warning",
-            title: "Global Hotkeys Permission Required",
-            message: "To use global hotkeys on Linux (especially Wayland), you need to add your user to the 'input' group.",
-            detail: "Run this command in a terminal:\n\nsudo usermod -aG input $USER\n\nThen log out and log back in for the change to take effect.",
-            buttons: ["OK"],
-          })
+          showPermissionDialog("Grant Access installs a rule giving your login session access to keyboards; it asks for your password.")
         }
 
         // eslint-disable-next-line no-console
         console.error(
           "[NVIDIA-CC] Global hotkeys failed: Permission denied.\n" +
-          "To fix this on Linux, add your user to the 'input' group:\n" +
-          "  sudo usermod -aG input $USER\n" +
-          "Then log out and log back in."
+          "To fix this on Linux, run:\n" +
+          `  ${rdevPath} install-permissions\n` +
+          "or add your user to the 'input' group (sudo usermod -aG input $USER) and log back in."
         )
       }
     }